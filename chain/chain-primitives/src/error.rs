@@ -8,6 +8,7 @@ use log::error;
 use near_primitives::block::BlockValidityError;
 use near_primitives::challenge::{ChunkProofs, ChunkState};
 use near_primitives::errors::{EpochError, StorageError};
+use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::to_base;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::types::{BlockHeight, EpochId, ShardId};
@@ -52,6 +53,14 @@ pub enum QueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Function call was stopped: {error_message}")]
+    ViewLimitExceeded {
+        error_message: String,
+        gas_burnt: near_primitives::types::Gas,
+        logs: Vec<String>,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("The state of account {requested_account_id} is too large")]
     TooLargeContractState {
         requested_account_id: near_primitives::types::AccountId,
@@ -202,6 +211,9 @@ pub enum ErrorKind {
     /// A challenged block is on the chain that was attempted to become the head
     #[fail(display = "Challenged block on chain")]
     ChallengedBlockOnChain,
+    /// The block builds on a fork which doesn't include the final head.
+    #[fail(display = "Block on {} conflicts with the final block", _0)]
+    ConflictsWithFinalBlock(CryptoHash),
     /// IO Error.
     #[fail(display = "IO Error: {}", _0)]
     IOErr(String),
@@ -273,6 +285,7 @@ impl Error {
             | ErrorKind::ValidatorError(_)
             | ErrorKind::EpochOutOfBounds(_)
             | ErrorKind::ChallengedBlockOnChain
+            | ErrorKind::ConflictsWithFinalBlock(_)
             | ErrorKind::StorageError(_)
             | ErrorKind::GCError(_)
             | ErrorKind::DBNotFoundErr(_) => false,
@@ -13,9 +13,12 @@ use tracing::{debug, error, info, warn};
 
 use near_chain_primitives::error::{Error, ErrorKind, LogTransientStorageError};
 use near_primitives::block::{genesis_chunks, Tip};
+use near_primitives::block_header::{Approval, ApprovalInner};
+#[cfg(feature = "protocol_feature_slashing_escrow")]
+use near_primitives::challenge::SlashAppeal;
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
-    MaybeEncodedShardChunk, SlashedValidator,
+    ChunkStateWitness, MaybeEncodedShardChunk, SlashedValidator,
 };
 use near_primitives::checked_feature;
 use near_primitives::hash::{hash, CryptoHash};
@@ -28,12 +31,13 @@ use near_primitives::sharding::{
     ShardProof, StateSyncInfo,
 };
 use near_primitives::syncing::{
-    get_num_state_parts, ReceiptProofResponse, ReceiptResponse, RootProof,
-    ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1, ShardStateSyncResponseHeaderV2,
-    StateHeaderKey, StatePartKey,
+    get_num_state_parts, EpochSyncFinalizationResponse, ReceiptProofResponse, ReceiptResponse,
+    RootProof, ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1,
+    ShardStateSyncResponseHeaderV2, StateHeaderKey, StatePartKey, StateSyncProgress,
 };
-use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
+use near_primitives::transaction::{ExecutionOutcomeWithIdAndProof, SignedTransaction};
 use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
     AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, MerkleHash, NumBlocks,
     ShardId,
@@ -46,11 +50,17 @@ use near_primitives::views::{
     FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
     SignedTransactionView,
 };
-use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
+use near_store::{
+    ColState, ColStateHeaders, ColStateParts, ColStateSyncProgress, PartialStorage, ShardTries,
+    StoreUpdate,
+};
 
 use near_primitives::state_record::StateRecord;
 
-use crate::lightclient::get_epoch_block_producers_view;
+use crate::gas_price;
+use crate::lightclient::{
+    get_epoch_block_producers_view, light_client_block_hash, verify_approvals,
+};
 use crate::migrations::check_if_block_is_first_with_chunk_of_version;
 use crate::missing_chunks::{BlockLike, MissingChunksPool};
 use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode};
@@ -60,7 +70,7 @@ use crate::types::{
 };
 use crate::validate::{
     validate_challenge, validate_chunk_proofs, validate_chunk_with_chunk_extra,
-    validate_transactions_order,
+    validate_chunk_with_witness_result, validate_transactions_order,
 };
 use crate::{byzantine_assert, create_light_client_block_view, Doomslug};
 use crate::{metrics, DoomslugThresholdMode};
@@ -134,61 +144,93 @@ impl OrphanBlockPool {
         self.evicted
     }
 
-    fn add(&mut self, orphan: Orphan) {
-        let height_hashes =
-            self.height_idx.entry(orphan.block.header().height()).or_insert_with(|| vec![]);
-        height_hashes.push(*orphan.block.hash());
-        let prev_hash_entries =
-            self.prev_hash_idx.entry(*orphan.block.header().prev_hash()).or_insert_with(|| vec![]);
-        prev_hash_entries.push(*orphan.block.hash());
-        self.orphans.insert(*orphan.block.hash(), orphan);
+    fn add(&mut self, orphan: Orphan, now: Instant) {
+        let hash = *orphan.block.hash();
+        self.height_idx.entry(orphan.block.header().height()).or_insert_with(|| vec![]).push(hash);
+        self.prev_hash_idx
+            .entry(*orphan.block.header().prev_hash())
+            .or_insert_with(|| vec![])
+            .push(hash);
+        self.orphans.insert(hash, orphan);
 
+        self.remove_expired(now);
         if self.orphans.len() > MAX_ORPHAN_SIZE {
             let old_len = self.orphans.len();
-
-            self.orphans.retain(|_, ref mut x| {
-                x.added.elapsed() < TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS)
-            });
+            // The highest orphans are the least likely to be connected to the chain soon.
             let mut heights = self.height_idx.keys().cloned().collect::<Vec<u64>>();
             heights.sort_unstable();
-            let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
-            for h in heights.iter().rev() {
-                if let Some(hash) = self.height_idx.remove(h) {
-                    for h in hash {
-                        let _ = self.orphans.remove(&h);
-                        removed_hashes.insert(h);
-                    }
+            for height in heights.iter().rev() {
+                for hash in self.height_idx.get(height).cloned().unwrap_or_default() {
+                    self.remove(&hash);
                 }
                 if self.orphans.len() < MAX_ORPHAN_SIZE {
                     break;
                 }
             }
-            self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(&x)));
-            self.prev_hash_idx
-                .retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(&x)));
+            let evicted = old_len - self.orphans.len();
+            self.evicted += evicted;
+            near_metrics::inc_counter_by(&metrics::ORPHAN_BLOCKS_EVICTED, evicted as u64);
+        }
+        near_metrics::set_gauge(&metrics::ORPHAN_BLOCKS, self.orphans.len() as i64);
+    }
 
-            self.evicted += old_len - self.orphans.len();
+    /// Removes the orphans whose parent didn't arrive within `MAX_ORPHAN_AGE_SECS`.
+    fn remove_expired(&mut self, now: Instant) {
+        let expired = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| {
+                now.saturating_duration_since(orphan.added)
+                    >= TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS)
+            })
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        for hash in expired.iter() {
+            self.remove(hash);
         }
+        self.evicted += expired.len();
+        near_metrics::inc_counter_by(&metrics::ORPHAN_BLOCKS_EXPIRED, expired.len() as u64);
+    }
+
+    /// Removes the orphan from the pool and its indexes.
+    fn remove(&mut self, hash: &CryptoHash) -> Option<Orphan> {
+        let orphan = self.orphans.remove(hash)?;
+        remove_from_index(&mut self.height_idx, orphan.block.header().height(), hash);
+        remove_from_index(&mut self.prev_hash_idx, *orphan.block.header().prev_hash(), hash);
+        Some(orphan)
     }
 
     pub fn contains(&self, hash: &CryptoHash) -> bool {
         self.orphans.contains_key(hash)
     }
 
-    pub fn remove_by_prev_hash(&mut self, prev_hash: CryptoHash) -> Option<Vec<Orphan>> {
-        let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
-        let ret = self.prev_hash_idx.remove(&prev_hash).map(|hs| {
-            hs.iter()
-                .filter_map(|h| {
-                    removed_hashes.insert(h.clone());
-                    self.orphans.remove(h)
-                })
-                .collect()
-        });
+    /// Returns the first missing block in the chain of orphans leading to `hash`.
+    pub fn missing_ancestor(&self, hash: &CryptoHash) -> CryptoHash {
+        let mut hash = *hash;
+        while let Some(orphan) = self.orphans.get(&hash) {
+            hash = *orphan.block.header().prev_hash();
+        }
+        hash
+    }
 
-        self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(&x)));
+    pub fn remove_by_prev_hash(&mut self, prev_hash: CryptoHash) -> Option<Vec<Orphan>> {
+        let hashes = self.prev_hash_idx.get(&prev_hash).cloned()?;
+        let orphans = hashes.iter().filter_map(|hash| self.remove(hash)).collect();
+        near_metrics::set_gauge(&metrics::ORPHAN_BLOCKS, self.orphans.len() as i64);
+        Some(orphans)
+    }
+}
 
-        ret
+fn remove_from_index<K: std::hash::Hash + Eq>(
+    index: &mut HashMap<K, Vec<CryptoHash>>,
+    key: K,
+    hash: &CryptoHash,
+) {
+    if let Some(hashes) = index.get_mut(&key) {
+        hashes.retain(|h| h != hash);
+        if hashes.is_empty() {
+            index.remove(&key);
+        }
     }
 }
 
@@ -206,6 +248,8 @@ pub struct Chain {
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
     pending_states_to_patch: Option<Vec<StateRecord>>,
+    /// Whether the node syncs only the headers and never processes the blocks.
+    pub header_only: bool,
 }
 
 impl Chain {
@@ -243,6 +287,7 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            header_only: false,
         })
     }
 
@@ -358,6 +403,7 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            header_only: false,
         })
     }
 
@@ -431,6 +477,169 @@ impl Chain {
         create_light_client_block_view(&final_block_header, chain_store, Some(next_block_producers))
     }
 
+    /// Builds the epoch sync finalization response for a node which validated the light client
+    /// block of the epoch `epoch_id`, see `EpochSyncFinalizationResponse`.
+    pub fn get_epoch_sync_finalization_response(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochSyncFinalizationResponse, Error> {
+        let light_client_block = self.store.get_epoch_light_client_block(&epoch_id.0)?.clone();
+        let mut prev_epoch_headers = vec![];
+        let mut header =
+            self.get_block_header(&light_client_block_hash(&light_client_block))?.clone();
+        // The light client block is created for the last final block of the epoch, walk forward to
+        // the last block of the epoch.
+        let cur_epoch_header = loop {
+            let next_block_hash = *self.store.get_next_block_hash(header.hash())?;
+            let next_header = self.get_block_header(&next_block_hash)?.clone();
+            if next_header.epoch_id() != epoch_id {
+                break next_header;
+            }
+            prev_epoch_headers.push(std::mem::replace(&mut header, next_header));
+        };
+        let header_sync_init_header = header;
+        let cur_epoch_next_block_hash = *self.store.get_next_block_hash(cur_epoch_header.hash())?;
+        let cur_epoch_next_header = self.get_block_header(&cur_epoch_next_block_hash)?.clone();
+        let header_sync_init_header_tree =
+            self.store.get_block_merkle_tree(header_sync_init_header.hash())?.clone();
+        let (
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            cur_epoch_info,
+            next_epoch_info,
+        ) = self.runtime_adapter.get_epoch_sync_data(
+            header_sync_init_header.hash(),
+            cur_epoch_header.epoch_id(),
+            cur_epoch_header.next_epoch_id(),
+        )?;
+        Ok(EpochSyncFinalizationResponse {
+            cur_epoch_header,
+            cur_epoch_next_header,
+            prev_epoch_headers,
+            header_sync_init_header,
+            header_sync_init_header_tree,
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            cur_epoch_info,
+            next_epoch_info,
+        })
+    }
+
+    /// Validates the epoch sync finalization response against the light client block of the
+    /// previous epoch, which has to be validated with `validate_light_client_block` before.
+    pub fn validate_epoch_sync_finalization(
+        light_client_block: &LightClientBlockView,
+        response: &EpochSyncFinalizationResponse,
+    ) -> Result<(), Error> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::Other(format!("Invalid epoch sync finalization: {}", reason)).into()
+        };
+        let prev_epoch_id = EpochId(light_client_block.inner_lite.epoch_id);
+        let cur_epoch_id = EpochId(light_client_block.inner_lite.next_epoch_id);
+
+        // The headers have to link the block of the light client block to the second block of
+        // the current epoch.
+        let headers = response
+            .prev_epoch_headers
+            .iter()
+            .chain(std::iter::once(&response.header_sync_init_header))
+            .chain(std::iter::once(&response.cur_epoch_header))
+            .chain(std::iter::once(&response.cur_epoch_next_header))
+            .collect::<Vec<_>>();
+        if headers[0].hash() != &light_client_block_hash(light_client_block) {
+            return Err(invalid("headers don't start at the light client block"));
+        }
+        if headers.windows(2).any(|pair| pair[1].prev_hash() != pair[0].hash()) {
+            return Err(invalid("headers are not linked"));
+        }
+        if headers[..headers.len() - 2].iter().any(|header| header.epoch_id() != &prev_epoch_id)
+            || response.cur_epoch_header.epoch_id() != &cur_epoch_id
+            || response.cur_epoch_next_header.epoch_id() != &cur_epoch_id
+        {
+            return Err(ErrorKind::InvalidEpochHash.into());
+        }
+
+        // The approvals of the next block endorse the first block of the current epoch.
+        let block_producers = light_client_block
+            .next_bps
+            .clone()
+            .ok_or_else(|| Error::from(ErrorKind::InvalidNextBPHash))?
+            .into_iter()
+            .map(ValidatorStake::from)
+            .collect::<Vec<_>>();
+        let data = Approval::get_data_for_sig(
+            &ApprovalInner::Endorsement(*response.cur_epoch_header.hash()),
+            response.cur_epoch_next_header.height(),
+        );
+        verify_approvals(response.cur_epoch_next_header.approvals(), &block_producers, &data)?;
+
+        if response.cur_epoch_header.epoch_sync_data_hash() != Some(response.epoch_sync_data_hash())
+        {
+            return Err(invalid("epoch sync data hash mismatch"));
+        }
+        if response.prev_epoch_last_block_info.hash() != response.header_sync_init_header.hash()
+            || response.prev_epoch_prev_last_block_info.hash()
+                != response.header_sync_init_header.prev_hash()
+        {
+            return Err(invalid("block infos don't match the headers"));
+        }
+        if &response.header_sync_init_header_tree.root()
+            != response.header_sync_init_header.block_merkle_root()
+        {
+            return Err(ErrorKind::InvalidBlockMerkleRoot.into());
+        }
+        Ok(())
+    }
+
+    /// Initializes the chain from the epoch sync finalization response validated with
+    /// `validate_epoch_sync_finalization`. The epoch manager gets the data of the previous,
+    /// current and next epochs, and header sync continues from the last block of the previous
+    /// epoch.
+    pub fn init_after_epoch_sync(
+        &mut self,
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<(), Error> {
+        let EpochSyncFinalizationResponse {
+            cur_epoch_header,
+            prev_epoch_headers,
+            header_sync_init_header,
+            header_sync_init_header_tree,
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            prev_epoch_info,
+            cur_epoch_info,
+            next_epoch_info,
+            ..
+        } = response;
+        self.runtime_adapter.epoch_sync_init_epoch_manager(
+            prev_epoch_first_block_info,
+            prev_epoch_prev_last_block_info,
+            prev_epoch_last_block_info,
+            header_sync_init_header.epoch_id(),
+            prev_epoch_info,
+            cur_epoch_header.epoch_id(),
+            cur_epoch_info,
+            cur_epoch_header.next_epoch_id(),
+            next_epoch_info,
+        )?;
+
+        let mut chain_store_update = self.store.store_update();
+        for header in prev_epoch_headers {
+            chain_store_update.save_block_header_no_update_tree(header)?;
+        }
+        let header_head = Tip::from_header(&header_sync_init_header);
+        chain_store_update
+            .save_block_merkle_tree(*header_sync_init_header.hash(), header_sync_init_header_tree);
+        chain_store_update.save_block_header_no_update_tree(header_sync_init_header)?;
+        chain_store_update.force_save_header_head(&header_head)?;
+        chain_store_update.commit()
+    }
+
     pub fn save_block(&mut self, block: &Block) -> Result<(), Error> {
         if self.store.get_block(block.hash()).is_ok() {
             return Ok(());
@@ -462,11 +671,10 @@ impl Chain {
             byzantine_assert!(false);
             return Err(e.into());
         }
-        self.orphans.add(Orphan {
-            block: block.clone(),
-            provenance: Provenance::NONE,
-            added: Instant::now(),
-        });
+        self.orphans.add(
+            Orphan { block: block.clone(), provenance: Provenance::NONE, added: Instant::now() },
+            Instant::now(),
+        );
         Ok(())
     }
 
@@ -676,6 +884,18 @@ impl Chain {
         Ok(())
     }
 
+    /// Moves the data of the blocks older than the GC horizon to the cold database.
+    /// Archival nodes with a cold database call this instead of `clear_data`.
+    pub fn migrate_to_cold(&mut self, blocks_limit: NumBlocks) -> Result<(), Error> {
+        let head = self.store.head()?;
+        let gc_stop_height = self.runtime_adapter.get_gc_stop_height(&head.last_block_hash);
+        let cold_head = self.store.cold_head()?;
+        for height in (cold_head + 1..gc_stop_height).take(blocks_limit as usize) {
+            self.store.move_to_cold(height)?;
+        }
+        Ok(())
+    }
+
     /// Do Basic validation of a block upon receiving it. Check that header is valid
     /// and block is well-formed (various roots match).
     pub fn validate_block(&mut self, block: &Block) -> Result<(), Error> {
@@ -734,6 +954,35 @@ impl Chain {
         Ok(())
     }
 
+    /// Returns the witness of applying the chunk of `shard_id` included in the block, with which
+    /// nodes that don't have the state of the shard can validate the chunk.
+    pub fn create_chunk_state_witness(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<ChunkStateWitness, Error> {
+        let block = self.get_block(block_hash)?.clone();
+        let prev_block = self.get_block(block.header().prev_hash())?.clone();
+        // We create new chain update, but it's not going to be committed so it's read only.
+        let mut chain_update = self.chain_update();
+        chain_update.create_chunk_state_witness(&block, &prev_block, shard_id)
+    }
+
+    /// Validates `chunk_header`, the next chunk of the shard after the chunk of the witness, by
+    /// applying the chunk of the witness with its trie nodes only. Reads of the state are verified
+    /// against the state root of the chunk, so the node doesn't need the state of the shard.
+    pub fn validate_chunk_state_witness(
+        &mut self,
+        witness: &ChunkStateWitness,
+        chunk_header: &ShardChunkHeader,
+    ) -> Result<(), Error> {
+        let block = self.get_block(&witness.block_hash)?.clone();
+        let prev_block = self.get_block(block.header().prev_hash())?.clone();
+        // We create new chain update, but it's not going to be committed so it's read only.
+        let mut chain_update = self.chain_update();
+        chain_update.validate_chunk_state_witness(&block, &prev_block, witness, chunk_header)
+    }
+
     /// Process a received or produced block, and unroll any orphans that may depend on it.
     /// Changes current state, and calls `block_accepted` callback in case block was successfully applied.
     pub fn process_block<F, F2, F3>(
@@ -847,6 +1096,7 @@ impl Chain {
             }
         }
 
+        let header_only = self.header_only;
         let mut chain_update = self.chain_update();
 
         if let Some(header) = headers.last() {
@@ -854,6 +1104,15 @@ impl Chain {
             chain_update.update_header_head_if_not_challenged(header)?;
         }
 
+        if header_only {
+            // The blocks, which record the epoch light client blocks otherwise, are never
+            // processed. The headers need to be on the header chain first, see
+            // `create_light_client_block_view`.
+            for header in headers.iter() {
+                chain_update.save_epoch_light_client_block_for_header(header)?;
+            }
+        }
+
         chain_update.commit()
     }
 
@@ -1109,7 +1368,7 @@ impl Chain {
                             let block_hash = *block.hash();
                             let orphan = Orphan { block, provenance, added: Instant::now() };
 
-                            self.orphans.add(orphan);
+                            self.orphans.add(orphan, Instant::now());
 
                             debug!(
                                 target: "chain",
@@ -1753,14 +2012,27 @@ impl Chain {
             .into());
         }
 
-        // Saving the part data.
+        // Saving the part data together with the progress, so the download can be resumed.
+        let mut progress = self.store.get_state_sync_progress(shard_id, sync_hash)?;
+        progress.set_done(part_id, num_parts);
         let mut store_update = self.store.owned_store().store_update();
         let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
         store_update.set(ColStateParts, &key, data);
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        store_update.set_ser(ColStateSyncProgress, &key, &progress)?;
         store_update.commit()?;
         Ok(())
     }
 
+    /// Returns the parts of the shard state downloaded so far, see `set_state_part`.
+    pub fn get_state_sync_progress(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<StateSyncProgress, Error> {
+        self.store.get_state_sync_progress(shard_id, sync_hash)
+    }
+
     pub fn set_state_finalize(
         &mut self,
         shard_id: ShardId,
@@ -2435,6 +2707,12 @@ impl Chain {
         self.orphans.contains(hash)
     }
 
+    /// Returns the first unknown ancestor of an orphan, which has to be requested to connect it.
+    #[inline]
+    pub fn orphan_missing_ancestor(&self, hash: &CryptoHash) -> CryptoHash {
+        self.orphans.missing_ancestor(hash)
+    }
+
     /// Check if hash is for a known chunk orphan.
     #[inline]
     pub fn is_chunk_orphan(&self, hash: &CryptoHash) -> bool {
@@ -2671,7 +2949,17 @@ impl<'a> ChainUpdate<'a> {
                     self.chain_store_update.get_partial_chunk(&chunk_header.chunk_hash()).unwrap();
                 for receipt in partial_encoded_chunk.receipts().iter() {
                     let ReceiptProof(_, shard_proof) = receipt;
-                    let ShardProof { from_shard_id: _, to_shard_id, proof: _ } = shard_proof;
+                    let ShardProof { from_shard_id, to_shard_id, proof: _ } = shard_proof;
+                    // The proofs are checked when the chunk is received only for the shards we
+                    // care about, so a chunk producer could otherwise get unproven receipts
+                    // applied by the nodes which start caring about the shard later.
+                    if *from_shard_id != chunk_header.shard_id()
+                        || !receipt
+                            .verify_against_receipt_root(chunk_header.outgoing_receipts_root())
+                    {
+                        byzantine_assert!(false);
+                        return Err(ErrorKind::InvalidReceiptsProof.into());
+                    }
                     receipt_proofs_by_shard_id
                         .entry(*to_shard_id)
                         .or_insert_with(Vec::new)
@@ -2704,8 +2992,7 @@ impl<'a> ChainUpdate<'a> {
         let prev_chunk = self
             .chain_store_update
             .get_chain_store()
-            .get_chunk_clone_from_header(&prev_block.chunks()[chunk_shard_id as usize].clone())
-            .unwrap();
+            .get_chunk_clone_from_header(&prev_block.chunks()[chunk_shard_id as usize].clone())?;
         let receipt_proof_response: Vec<ReceiptProofResponse> =
             self.chain_store_update.get_incoming_receipts_for_shard(
                 chunk_shard_id,
@@ -2727,29 +3014,31 @@ impl<'a> ChainUpdate<'a> {
             &prev_block.hash(),
             chunk_shard_id,
         )?;
-        let apply_result = self
-            .runtime_adapter
-            .apply_transactions_with_optional_storage_proof(
-                chunk_shard_id,
-                prev_chunk_inner.prev_state_root(),
-                prev_chunk.height_included(),
-                prev_block.header().raw_timestamp(),
-                prev_chunk_inner.prev_block_hash(),
-                &prev_block.hash(),
-                &receipts,
-                prev_chunk.transactions(),
-                prev_chunk_inner.validator_proposals(),
-                prev_block.header().gas_price(),
-                prev_chunk_inner.gas_limit(),
-                &challenges_result,
-                *block.header().random_value(),
-                true,
-                true,
-                is_first_block_with_chunk_of_version,
-                None,
-            )
-            .unwrap();
-        let partial_state = apply_result.proof.unwrap().nodes;
+        // Re-execute the previous chunk recording the trie nodes it touches, so other nodes can
+        // check the state transition without having the state of the shard.
+        let apply_result = self.runtime_adapter.apply_transactions_with_optional_storage_proof(
+            chunk_shard_id,
+            prev_chunk_inner.prev_state_root(),
+            prev_chunk.height_included(),
+            prev_block.header().raw_timestamp(),
+            prev_chunk_inner.prev_block_hash(),
+            &prev_block.hash(),
+            &receipts,
+            prev_chunk.transactions(),
+            prev_chunk_inner.validator_proposals(),
+            prev_block.header().gas_price(),
+            prev_chunk_inner.gas_limit(),
+            &challenges_result,
+            *block.header().random_value(),
+            true,
+            true,
+            is_first_block_with_chunk_of_version,
+            None,
+        )?;
+        let partial_state = apply_result
+            .proof
+            .ok_or_else(|| ErrorKind::Other("storage proof of the chunk wasn't generated".into()))?
+            .nodes;
         Ok(ChunkState {
             prev_block_header: prev_block.header().try_to_vec()?,
             block_header: block.header().try_to_vec()?,
@@ -2761,6 +3050,153 @@ impl<'a> ChainUpdate<'a> {
         })
     }
 
+    /// Applies the new chunk of `shard_id` in `block` the same way `apply_chunks` does it. Without
+    /// `partial_storage` the chunk is applied with the state of the shard recording the storage
+    /// proof, otherwise with the given trie nodes only.
+    fn apply_new_chunk(
+        &mut self,
+        block: &Block,
+        prev_block: &Block,
+        shard_id: ShardId,
+        transactions: &[SignedTransaction],
+        partial_storage: Option<PartialStorage>,
+    ) -> Result<ApplyTransactionResult, Error> {
+        let chunk_header = &block.chunks()[shard_id as usize];
+        let prev_chunk_header = &prev_block.chunks()[shard_id as usize];
+        if chunk_header.height_included() != block.header().height() {
+            return Err(ErrorKind::Other(format!(
+                "block {} doesn't include a new chunk of shard {}",
+                block.hash(),
+                shard_id
+            ))
+            .into());
+        }
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
+        let receipt_proof_response = self.chain_store_update.get_incoming_receipts_for_shard(
+            shard_id,
+            *block.hash(),
+            prev_chunk_header.height_included(),
+        )?;
+        let receipts = collect_receipts_from_response(&receipt_proof_response);
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            &mut self.chain_store_update,
+            self.runtime_adapter.as_ref(),
+            &prev_block.hash(),
+            shard_id,
+        )?;
+        let gas_price =
+            gas_price::apply_gas_price(prev_block.header(), block.header(), true, protocol_version);
+        match partial_storage {
+            None => self.runtime_adapter.apply_transactions_with_optional_storage_proof(
+                shard_id,
+                &chunk_header.prev_state_root(),
+                chunk_header.height_included(),
+                block.header().raw_timestamp(),
+                &chunk_header.prev_block_hash(),
+                &block.hash(),
+                &receipts,
+                transactions,
+                chunk_header.validator_proposals(),
+                gas_price,
+                chunk_header.gas_limit(),
+                &block.header().challenges_result(),
+                *block.header().random_value(),
+                true,
+                true,
+                is_first_block_with_chunk_of_version,
+                None,
+            ),
+            Some(partial_storage) => self.runtime_adapter.check_state_transition(
+                partial_storage,
+                shard_id,
+                &chunk_header.prev_state_root(),
+                chunk_header.height_included(),
+                block.header().raw_timestamp(),
+                &chunk_header.prev_block_hash(),
+                &block.hash(),
+                &receipts,
+                transactions,
+                chunk_header.validator_proposals(),
+                gas_price,
+                chunk_header.gas_limit(),
+                &block.header().challenges_result(),
+                *block.header().random_value(),
+                true,
+                is_first_block_with_chunk_of_version,
+            ),
+        }
+    }
+
+    pub fn create_chunk_state_witness(
+        &mut self,
+        block: &Block,
+        prev_block: &Block,
+        shard_id: ShardId,
+    ) -> Result<ChunkStateWitness, Error> {
+        let chunk = self
+            .chain_store_update
+            .get_chunk_clone_from_header(&block.chunks()[shard_id as usize])?;
+        let apply_result =
+            self.apply_new_chunk(block, prev_block, shard_id, chunk.transactions(), None)?;
+        let partial_state = apply_result
+            .proof
+            .ok_or_else(|| ErrorKind::Other("storage proof of the chunk wasn't generated".into()))?
+            .nodes;
+        Ok(ChunkStateWitness {
+            block_hash: *block.hash(),
+            shard_id,
+            transactions: chunk.transactions().to_vec(),
+            partial_state,
+        })
+    }
+
+    pub fn validate_chunk_state_witness(
+        &mut self,
+        block: &Block,
+        prev_block: &Block,
+        witness: &ChunkStateWitness,
+        chunk_header: &ShardChunkHeader,
+    ) -> Result<(), Error> {
+        let shard_id = witness.shard_id;
+        if chunk_header.shard_id() != shard_id || chunk_header.prev_block_hash() != *block.hash() {
+            return Err(ErrorKind::Other(
+                "chunk isn't the next chunk after the chunk of the witness".to_string(),
+            )
+            .into());
+        }
+        let (tx_root, _) = merklize(&witness.transactions);
+        if tx_root != block.chunks()[shard_id as usize].tx_root() {
+            return Err(ErrorKind::InvalidChunkTxRoot.into());
+        }
+        if !validate_transactions_order(&witness.transactions) {
+            return Err(ErrorKind::InvalidTransactions.into());
+        }
+        let partial_storage = PartialStorage { nodes: witness.partial_state.clone() };
+        let apply_result = self.apply_new_chunk(
+            block,
+            prev_block,
+            shard_id,
+            &witness.transactions,
+            Some(partial_storage),
+        )?;
+        let (outcome_root, _) =
+            ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
+        let chunk_extra = ChunkExtra::new(
+            &apply_result.new_root,
+            outcome_root,
+            apply_result.validator_proposals,
+            apply_result.total_gas_burnt,
+            block.chunks()[shard_id as usize].gas_limit(),
+            apply_result.total_balance_burnt,
+        );
+        let outgoing_receipts =
+            apply_result.receipt_result.into_iter().flat_map(|(_, receipts)| receipts).collect();
+        let outgoing_receipts_hashes =
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+        validate_chunk_with_witness_result(&chunk_extra, &outgoing_receipts_hashes, chunk_header)
+    }
+
     fn apply_chunks(
         &mut self,
         me: &Option<AccountId>,
@@ -2900,7 +3336,12 @@ impl<'a> ChainUpdate<'a> {
                             &receipts,
                             chunk.transactions(),
                             chunk_inner.validator_proposals(),
-                            prev_block.header().gas_price(),
+                            gas_price::apply_gas_price(
+                                prev_block.header(),
+                                block.header(),
+                                true,
+                                protocol_version,
+                            ),
                             gas_limit,
                             &block.header().challenges_result(),
                             *block.header().random_value(),
@@ -2960,7 +3401,12 @@ impl<'a> ChainUpdate<'a> {
                             &[],
                             &[],
                             new_extra.validator_proposals(),
-                            block.header().gas_price(),
+                            gas_price::apply_gas_price(
+                                prev_block.header(),
+                                block.header(),
+                                false,
+                                protocol_version,
+                            ),
                             new_extra.gas_limit(),
                             &block.header().challenges_result(),
                             *block.header().random_value(),
@@ -3053,6 +3499,14 @@ impl<'a> ChainUpdate<'a> {
             return Err(ErrorKind::InvalidBlockHeight(prev_height).into());
         }
 
+        // Do not accept forks which don't include the final head, they can never become the head.
+        // Without approvals finality is not backed by stake, so forks are accepted as before.
+        if self.doomslug_threshold_mode != DoomslugThresholdMode::NoApprovals
+            && !self.is_descendant_of_final_head(&prev_hash)?
+        {
+            return Err(ErrorKind::ConflictsWithFinalBlock(prev_hash).into());
+        }
+
         let (is_caught_up, needs_to_start_fetching_state) =
             if self.runtime_adapter.is_next_block_epoch_start(&prev_hash)? {
                 if !self.prev_block_is_caught_up(&prev_prev_hash, &prev_hash)? {
@@ -3095,12 +3549,14 @@ impl<'a> ChainUpdate<'a> {
 
         let protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(&block.header().epoch_id())?;
-        if !block.verify_gas_price(
-            prev_gas_price,
-            self.block_economics_config.min_gas_price(protocol_version),
-            self.block_economics_config.max_gas_price(protocol_version),
-            self.block_economics_config.gas_price_adjustment_rate(protocol_version),
-        ) {
+        if block.header().gas_price()
+            != gas_price::expected_gas_price(
+                &self.block_economics_config,
+                protocol_version,
+                prev_gas_price,
+                block,
+            )
+        {
             byzantine_assert!(false);
             return Err(ErrorKind::InvalidGasPrice.into());
         }
@@ -3168,9 +3624,18 @@ impl<'a> ChainUpdate<'a> {
             self.chain_store_update.get_block_header(last_final_block)?.height()
         };
 
-        let epoch_manager_update = self.runtime_adapter.add_validator_proposals(
-            BlockHeaderInfo::new(&block.header(), last_finalized_height),
-        )?;
+        #[allow(unused_mut)]
+        let mut block_header_info = BlockHeaderInfo::new(&block.header(), last_finalized_height);
+        #[cfg(feature = "protocol_feature_slashing_escrow")]
+        {
+            block_header_info.appealed_validators = self.verify_slash_appeals(
+                block.challenges(),
+                block.header().epoch_id(),
+                block.header().prev_hash(),
+            )?;
+        }
+        let epoch_manager_update =
+            self.runtime_adapter.add_validator_proposals(block_header_info)?;
         self.chain_store_update.merge(epoch_manager_update);
 
         // Add validated block to the db, even if it's not the canonical fork.
@@ -3209,6 +3674,26 @@ impl<'a> ChainUpdate<'a> {
         Ok((res, needs_to_start_fetching_state))
     }
 
+    /// Records the light client block of the previous epoch if the header is the first one of
+    /// its epoch on the header chain, like `process_block` does for the blocks.
+    fn save_epoch_light_client_block_for_header(
+        &mut self,
+        header: &BlockHeader,
+    ) -> Result<(), Error> {
+        match self.chain_store_update.get_block_hash_by_height(header.height()) {
+            Ok(hash) if &hash == header.hash() => {}
+            _ => return Ok(()),
+        }
+        let prev = self.get_previous_header(header)?.clone();
+        if prev.epoch_id() != header.epoch_id() && prev.last_final_block() != &CryptoHash::default()
+        {
+            let light_client_block = self.create_light_client_block(&prev)?;
+            self.chain_store_update
+                .save_epoch_light_client_block(&prev.epoch_id().0, light_client_block);
+        }
+        Ok(())
+    }
+
     pub fn create_light_client_block(
         &mut self,
         header: &BlockHeader,
@@ -3453,6 +3938,25 @@ impl<'a> ChainUpdate<'a> {
         }
     }
 
+    /// Checks whether the final head is the block or one of its ancestors, i.e. whether the
+    /// block can become the head without reverting finalized blocks.
+    fn is_descendant_of_final_head(&mut self, hash: &CryptoHash) -> Result<bool, Error> {
+        let final_head = self.chain_store_update.final_head()?;
+        let mut header = self.chain_store_update.get_block_header(hash)?.clone();
+        // The last final block of a block is its ancestor, start from it if it's high enough.
+        if header.last_final_block() != &CryptoHash::default() {
+            let last_final_header =
+                self.chain_store_update.get_block_header(header.last_final_block())?;
+            if last_final_header.height() >= final_head.height {
+                header = last_final_header.clone();
+            }
+        }
+        while header.height() > final_head.height {
+            header = self.chain_store_update.get_block_header(header.prev_hash())?.clone();
+        }
+        Ok(header.hash() == &final_head.last_block_hash)
+    }
+
     fn update_final_head_from_block(&mut self, header: &BlockHeader) -> Result<Option<Tip>, Error> {
         let final_head = self.chain_store_update.final_head()?;
         let last_final_block_header =
@@ -3639,10 +4143,14 @@ impl<'a> ChainUpdate<'a> {
         }
         let receipts = collect_receipts_from_response(&receipt_proof_response);
         // Prev block header should be present during state sync, since headers have been synced at this point.
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(block_header.epoch_id())?;
         let gas_price = if block_header.height() == self.chain_store_update.get_genesis_height() {
             block_header.gas_price()
         } else {
-            self.chain_store_update.get_block_header(block_header.prev_hash())?.gas_price()
+            let prev_block_header =
+                self.chain_store_update.get_block_header(block_header.prev_hash())?;
+            gas_price::apply_gas_price(prev_block_header, &block_header, true, protocol_version)
         };
 
         let chunk_header = chunk.cloned_header();
@@ -3734,6 +4242,8 @@ impl<'a> ChainUpdate<'a> {
 
         let mut chunk_extra =
             self.chain_store_update.get_chunk_extra(&prev_block_header.hash(), shard_id)?.clone();
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(block_header.epoch_id())?;
 
         let apply_result = self.runtime_adapter.apply_transactions(
             shard_id,
@@ -3745,7 +4255,7 @@ impl<'a> ChainUpdate<'a> {
             &[],
             &[],
             chunk_extra.validator_proposals(),
-            prev_block_header.gas_price(),
+            gas_price::apply_gas_price(&prev_block_header, &block_header, false, protocol_version),
             chunk_extra.gas_limit(),
             &block_header.challenges_result(),
             *block_header.random_value(),
@@ -3772,12 +4282,26 @@ impl<'a> ChainUpdate<'a> {
         debug!(target: "chain", "Verifying challenges {:?}", challenges);
         let mut result = vec![];
         for challenge in challenges.iter() {
+            #[cfg(feature = "protocol_feature_slashing_escrow")]
+            {
+                if let ChallengeBody::SlashAppeal(slash_appeal) = &challenge.body {
+                    if !self.is_appealed_challenge_on_chain(slash_appeal, prev_block_hash)? {
+                        result.push(SlashedValidator::new(challenge.account_id.clone(), false));
+                        continue;
+                    }
+                }
+            }
             match validate_challenge(&*self.runtime_adapter, &epoch_id, &prev_block_hash, challenge)
             {
                 Ok((hash, account_ids)) => {
                     let is_double_sign = match challenge.body {
                         // If it's double signed block, we don't invalidate blocks just slash.
                         ChallengeBody::BlockDoubleSign(_) => true,
+                        #[cfg(feature = "protocol_feature_double_sign_slashing")]
+                        ChallengeBody::ApprovalDoubleSign(_) => true,
+                        // Correct appeals cancel slashes, see `verify_slash_appeals`.
+                        #[cfg(feature = "protocol_feature_slashing_escrow")]
+                        ChallengeBody::SlashAppeal(_) => continue,
                         _ => {
                             self.mark_block_as_challenged(&hash, block_hash)?;
                             false
@@ -3798,6 +4322,77 @@ impl<'a> ChainUpdate<'a> {
         Ok(result)
     }
 
+    /// Returns accounts whose slashes are cancelled by correct appeals among the given challenges,
+    /// with the hashes of the blocks which included the appealed challenges.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    pub fn verify_slash_appeals(
+        &mut self,
+        challenges: &Vec<Challenge>,
+        epoch_id: &EpochId,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<Vec<(AccountId, CryptoHash)>, Error> {
+        let mut result = vec![];
+        for challenge in challenges.iter() {
+            if let ChallengeBody::SlashAppeal(slash_appeal) = &challenge.body {
+                // Malicious appeals are slashed in `verify_challenges`.
+                if !self.is_appealed_challenge_on_chain(slash_appeal, prev_block_hash)? {
+                    continue;
+                }
+                if let Ok((_, account_ids)) = validate_challenge(
+                    &*self.runtime_adapter,
+                    &epoch_id,
+                    &prev_block_hash,
+                    challenge,
+                ) {
+                    result.extend(
+                        account_ids
+                            .into_iter()
+                            .map(|account_id| (account_id, slash_appeal.challenge_block_hash)),
+                    );
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Whether the appeal is against a `ChunkState` challenge of the same chunk, included in the
+    /// given block or one of its ancestors.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    fn is_appealed_challenge_on_chain(
+        &mut self,
+        slash_appeal: &SlashAppeal,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        let challenge_block =
+            match self.chain_store_update.get_block(&slash_appeal.challenge_block_hash) {
+                Ok(block) => block.clone(),
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => return Ok(false),
+                    _ => return Err(err),
+                },
+            };
+        match self
+            .chain_store_update
+            .get_header_on_chain_by_height(prev_block_hash, challenge_block.header().height())
+        {
+            Ok(header) if header.hash() == challenge_block.hash() => {}
+            Ok(_) => return Ok(false),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidBlockHeight(_) => return Ok(false),
+                _ => return Err(err),
+            },
+        }
+        let chunk_hash = slash_appeal.chunk_state.chunk_header.chunk_hash();
+        Ok(challenge_block.challenges().iter().any(|challenge| match &challenge.body {
+            ChallengeBody::ChunkState(chunk_state) => {
+                hash(&challenge.body.try_to_vec().expect("Failed to serialize"))
+                    == slash_appeal.challenge_hash
+                    && chunk_state.chunk_header.chunk_hash() == chunk_hash
+            }
+            _ => false,
+        }))
+    }
+
     /// Verify header signature when the epoch is known, but not the whole chain.
     /// Same as verify_header_signature except it does not verify that block producer hasn't been slashed
     fn partial_verify_orphan_header_signature(&self, header: &BlockHeader) -> Result<bool, Error> {
@@ -3829,3 +4424,63 @@ pub fn collect_receipts_from_response(
         receipt_proof_response.iter().flat_map(|ReceiptProofResponse(_, proofs)| proofs),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::block::Block;
+
+    use super::*;
+    use crate::test_utils::setup;
+
+    fn orphan(block: &Block, added: Instant) -> Orphan {
+        Orphan { block: block.clone(), provenance: Provenance::NONE, added }
+    }
+
+    #[test]
+    fn test_orphan_pool_expiry_and_ancestors() {
+        let (mut chain, _, signer) = setup();
+        let genesis_hash = *chain.genesis().hash();
+        let genesis = chain.get_block(&genesis_hash).unwrap().clone();
+        let b1 = Block::empty(&genesis, &*signer);
+        let b2 = Block::empty(&b1, &*signer);
+        let b3 = Block::empty(&b2, &*signer);
+
+        let now = Instant::now();
+        let mut pool = OrphanBlockPool::new();
+        pool.add(orphan(&b2, now), now);
+        pool.add(orphan(&b3, now), now);
+        assert_eq!(pool.missing_ancestor(b3.hash()), *b1.hash());
+        assert_eq!(pool.missing_ancestor(b1.hash()), *b1.hash());
+
+        let later = now + TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS);
+        pool.add(orphan(&b1, later), later);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.len_evicted(), 2);
+        assert!(pool.contains(b1.hash()));
+        assert!(pool.height_idx.len() == 1 && pool.prev_hash_idx.len() == 1);
+
+        let removed = pool.remove_by_prev_hash(*genesis.hash()).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(pool.len(), 0);
+        assert!(pool.height_idx.is_empty() && pool.prev_hash_idx.is_empty());
+    }
+
+    #[test]
+    fn test_orphan_pool_evicts_highest() {
+        let (mut chain, _, signer) = setup();
+        let genesis_hash = *chain.genesis().hash();
+        let mut prev = chain.get_block(&genesis_hash).unwrap().clone();
+        let now = Instant::now();
+        let mut pool = OrphanBlockPool::new();
+        for _ in 0..=MAX_ORPHAN_SIZE {
+            let block = Block::empty(&prev, &*signer);
+            pool.add(orphan(&block, now), now);
+            prev = block;
+        }
+        assert_eq!(pool.len(), MAX_ORPHAN_SIZE - 1);
+        assert_eq!(pool.len_evicted(), 2);
+        assert!(!pool.contains(prev.hash()));
+        assert_eq!(pool.height_idx.len(), pool.len());
+        assert_eq!(pool.prev_hash_idx.len(), pool.len());
+    }
+}
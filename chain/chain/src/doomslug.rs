@@ -187,6 +187,14 @@ impl DoomslugApprovalsTracker {
         self.approved_stake_next_epoch -= stakes.1;
     }
 
+    /// Returns whether the approved stake crosses the threshold of both epochs.
+    fn has_enough_approvals(&self) -> bool {
+        (self.approved_stake_this_epoch > self.total_stake_this_epoch * 2 / 3
+            && (self.approved_stake_next_epoch > self.total_stake_next_epoch * 2 / 3
+                || self.total_stake_next_epoch == 0))
+            || self.threshold_mode == DoomslugThresholdMode::NoApprovals
+    }
+
     /// Returns whether the block has enough approvals, and if yes, since what moment it does.
     ///
     /// # Arguments
@@ -197,11 +205,7 @@ impl DoomslugApprovalsTracker {
     /// `ReadySince` if the block has enough approvals to pass the threshold, and since when it
     ///     does
     fn get_block_production_readiness(&mut self, now: Instant) -> DoomslugBlockProductionReadiness {
-        if (self.approved_stake_this_epoch > self.total_stake_this_epoch * 2 / 3
-            && (self.approved_stake_next_epoch > self.total_stake_next_epoch * 2 / 3
-                || self.total_stake_next_epoch == 0))
-            || self.threshold_mode == DoomslugThresholdMode::NoApprovals
-        {
+        if self.has_enough_approvals() {
             if self.time_passed_threshold == None {
                 self.time_passed_threshold = Some(now);
             }
@@ -541,6 +545,18 @@ impl Doomslug {
         let _ = self.on_approval_message_internal(now, approval, stakes);
     }
 
+    /// Returns whether the approvals received for the block at `target_height` on top of the
+    /// current tip have enough stake for the block to be produced. Unlike `ready_to_produce_block`
+    /// doesn't wait for the chunks or the delay after the threshold was crossed.
+    pub fn has_enough_approvals(&self, target_height: BlockHeight) -> bool {
+        let hash_or_height =
+            ApprovalInner::new(&self.tip.block_hash, self.tip.height, target_height);
+        self.approval_tracking
+            .get(&target_height)
+            .and_then(|trackers| trackers.approval_trackers.get(&hash_or_height))
+            .map_or(false, |tracker| tracker.has_enough_approvals())
+    }
+
     /// Returns whether we can produce a block for this height. The check for whether `me` is the
     /// block producer for the height needs to be done by the caller.
     /// We can produce a block if:
@@ -869,6 +885,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_doomslug_has_enough_approvals() {
+        let accounts: Vec<(&str, u128, u128)> = vec![("test1", 2, 0), ("test2", 1, 0)];
+        let stakes = accounts
+            .iter()
+            .map(|(account_id, stake_this_epoch, stake_next_epoch)| ApprovalStake {
+                account_id: account_id.to_string(),
+                stake_this_epoch: *stake_this_epoch,
+                stake_next_epoch: *stake_next_epoch,
+                public_key: SecretKey::from_seed(KeyType::ED25519, account_id).public_key(),
+            })
+            .map(|stake| (stake, false))
+            .collect::<Vec<_>>();
+        let signers = accounts
+            .iter()
+            .map(|(account_id, _, _)| {
+                InMemoryValidatorSigner::from_seed(account_id, KeyType::ED25519, account_id)
+            })
+            .collect::<Vec<_>>();
+
+        let mut ds = Doomslug::new(
+            0,
+            Duration::from_millis(400),
+            Duration::from_millis(1000),
+            Duration::from_millis(100),
+            Duration::from_millis(3000),
+            None,
+            DoomslugThresholdMode::TwoThirds,
+        );
+        let now = Instant::now();
+        ds.set_tip(now, hash(&[1]), 1, 0);
+        assert!(!ds.has_enough_approvals(2));
+
+        // The total stake is 3, so more than 2 must approve.
+        ds.on_approval_message(now, &Approval::new(hash(&[1]), 1, 2, &signers[0]), &stakes);
+        assert!(!ds.has_enough_approvals(2));
+        ds.on_approval_message(now, &Approval::new(hash(&[1]), 1, 2, &signers[1]), &stakes);
+        assert!(ds.has_enough_approvals(2));
+
+        // Approvals for a different parent don't count for the tip.
+        ds.on_approval_message(now, &Approval::new(hash(&[2]), 2, 3, &signers[0]), &stakes);
+        ds.on_approval_message(now, &Approval::new(hash(&[2]), 2, 3, &signers[1]), &stakes);
+        assert!(!ds.has_enough_approvals(3));
+    }
+
     #[test]
     fn test_doomslug_one_approval_per_target_height() {
         let accounts = vec![("test1", 2, 0), ("test2", 1, 2), ("test3", 3, 3), ("test4", 2, 2)];
@@ -0,0 +1,101 @@
+//! Gas price of the chain.
+//!
+//! The header of every block carries the gas price charged by the chunks applied on top of it.
+//! The price follows the usage of the chunks included into the block: it stays the same when they
+//! use half of their gas limit, and moves up or down by at most `gas_price_adjustment_rate / 2`
+//! when they are full or empty. It never leaves the bounds given by the genesis config.
+use near_primitives::block::{Block, BlockHeader};
+use near_primitives::checked_feature;
+use near_primitives::types::{Balance, Gas};
+use near_primitives::version::ProtocolVersion;
+
+use crate::types::BlockEconomicsConfig;
+
+/// Returns the gas price of a block after a block with `prev_gas_price`, given the gas used by
+/// and the gas limit of the chunks included into it.
+pub fn next_gas_price(
+    config: &BlockEconomicsConfig,
+    protocol_version: ProtocolVersion,
+    prev_gas_price: Balance,
+    gas_used: Gas,
+    gas_limit: Gas,
+) -> Balance {
+    Block::compute_new_gas_price(
+        prev_gas_price,
+        gas_used,
+        gas_limit,
+        config.gas_price_adjustment_rate(protocol_version),
+        config.min_gas_price(protocol_version),
+        config.max_gas_price(protocol_version),
+    )
+}
+
+/// Returns the gas price the header of `block` must have.
+pub fn expected_gas_price(
+    config: &BlockEconomicsConfig,
+    protocol_version: ProtocolVersion,
+    prev_gas_price: Balance,
+    block: &Block,
+) -> Balance {
+    let height = block.header().height();
+    next_gas_price(
+        config,
+        protocol_version,
+        prev_gas_price,
+        Block::compute_gas_used(block.chunks().iter(), height),
+        Block::compute_gas_limit(block.chunks().iter(), height),
+    )
+}
+
+/// Returns the gas price charged when applying a shard in the block with `block_header`.
+/// New chunks are charged the price of the previous block. Shards with missing chunks used to be
+/// charged the price of the block itself, so this is kept until `ConsistentApplyGasPrice`.
+/// Both block processing and state sync must use this to get the same state.
+pub fn apply_gas_price(
+    prev_block_header: &BlockHeader,
+    block_header: &BlockHeader,
+    is_new_chunk: bool,
+    protocol_version: ProtocolVersion,
+) -> Balance {
+    if is_new_chunk
+        || checked_feature!(
+            "protocol_feature_consistent_apply_gas_price",
+            ConsistentApplyGasPrice,
+            protocol_version
+        )
+    {
+        prev_block_header.gas_price()
+    } else {
+        block_header.gas_price()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_rational::Rational;
+
+    use near_primitives::version::PROTOCOL_VERSION;
+
+    use super::*;
+    use crate::ChainGenesis;
+
+    #[test]
+    fn test_next_gas_price() {
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.min_gas_price = 100;
+        chain_genesis.max_gas_price = 1_000_000;
+        chain_genesis.gas_price_adjustment_rate = Rational::new(1, 10);
+        let config = BlockEconomicsConfig::from(&chain_genesis);
+        let next = |prev_gas_price, gas_used| {
+            next_gas_price(&config, PROTOCOL_VERSION, prev_gas_price, gas_used, 1000)
+        };
+        assert_eq!(next(1000, 500), 1000);
+        assert_eq!(next(1000, 1000), 1050);
+        assert_eq!(next(1000, 0), 950);
+        // The price stays between the minimum and the capped maximum of 20 times the minimum.
+        assert_eq!(next(100, 0), 100);
+        assert_eq!(next(2000, 1000), 2000);
+        // Blocks without new chunks keep the price.
+        assert_eq!(next_gas_price(&config, PROTOCOL_VERSION, 1000, 0, 0), 1000);
+    }
+}
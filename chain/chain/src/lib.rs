@@ -3,7 +3,10 @@ extern crate lazy_static;
 
 pub use chain::{collect_receipts, Chain, MAX_ORPHAN_SIZE};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
-pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
+pub use lightclient::{
+    create_light_client_block_view, get_epoch_block_producers_view, light_client_block_hash,
+    validate_light_client_block,
+};
 pub use near_chain_primitives::{self, Error, ErrorKind};
 pub use near_primitives::receipt::ReceiptResult;
 pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
@@ -12,6 +15,7 @@ pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance, Runti
 
 pub mod chain;
 mod doomslug;
+pub mod gas_price;
 mod lightclient;
 mod metrics;
 pub mod migrations;
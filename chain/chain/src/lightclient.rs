@@ -1,11 +1,17 @@
-use near_chain_primitives::Error;
+use borsh::BorshSerialize;
+
+use near_chain_primitives::{Error, ErrorKind};
+use near_crypto::Signature;
 use near_primitives::block::BlockHeader;
+use near_primitives::block_header::{Approval, ApprovalInner, BlockHeaderInnerLite};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::types::EpochId;
+use near_primitives::merkle::combine_hash;
+use near_primitives::types::validator_stake::ValidatorStake;
+use near_primitives::types::{Balance, EpochId};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{BlockHeaderInnerLiteView, LightClientBlockView};
 
-use crate::{ChainStoreAccess, RuntimeAdapter};
+use crate::{Chain, ChainStoreAccess, RuntimeAdapter};
 
 pub fn get_epoch_block_producers_view(
     epoch_id: &EpochId,
@@ -70,3 +76,169 @@ pub fn create_light_client_block_view(
         approvals_after_next,
     })
 }
+
+/// Computes the hash of the block the light client block is created for.
+pub fn light_client_block_hash(light_client_block: &LightClientBlockView) -> CryptoHash {
+    let inner_lite: BlockHeaderInnerLite = light_client_block.inner_lite.clone().into();
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    let inner_hash = combine_hash(inner_lite_hash, light_client_block.inner_rest_hash);
+    combine_hash(inner_hash, light_client_block.prev_block_hash)
+}
+
+/// Checks that the approvals are signed by the given block producers, in the same order, and that
+/// the signers hold more than 2/3 of the stake. Approvals past the block producers, which come
+/// from the block producers of the next epoch at the epoch boundary, are ignored.
+pub(crate) fn verify_approvals(
+    approvals: &[Option<Signature>],
+    block_producers: &[ValidatorStake],
+    data: &[u8],
+) -> Result<(), Error> {
+    let total_stake: Balance = block_producers.iter().map(|bp| bp.stake()).sum();
+    let mut approved_stake: Balance = 0;
+    for (block_producer, approval) in block_producers.iter().zip(approvals.iter()) {
+        if let Some(signature) = approval {
+            if !signature.verify(data, block_producer.public_key()) {
+                return Err(ErrorKind::InvalidSignature.into());
+            }
+            approved_stake += block_producer.stake();
+        }
+    }
+    if approved_stake * 3 <= total_stake * 2 {
+        return Err(ErrorKind::NotEnoughApprovals.into());
+    }
+    Ok(())
+}
+
+/// Validates the light client block of an epoch against the block producers of this epoch.
+///
+/// The block is final if more than 2/3 of the stake endorsed the next block, and the next block
+/// producers are trusted if their hash matches `next_bp_hash` of the block. Light client blocks
+/// used to advance to the next epoch must contain the next block producers.
+pub fn validate_light_client_block(
+    light_client_block: &LightClientBlockView,
+    block_producers: &[ValidatorStake],
+) -> Result<(), Error> {
+    let block_hash = light_client_block_hash(light_client_block);
+    let next_block_hash = combine_hash(light_client_block.next_block_inner_hash, block_hash);
+    let data = Approval::get_data_for_sig(
+        &ApprovalInner::Endorsement(next_block_hash),
+        light_client_block.inner_lite.height + 2,
+    );
+    verify_approvals(&light_client_block.approvals_after_next, block_producers, &data)?;
+
+    let next_bps = match &light_client_block.next_bps {
+        Some(next_bps) => {
+            next_bps.iter().cloned().map(ValidatorStake::from).collect::<Vec<ValidatorStake>>()
+        }
+        None => return Err(ErrorKind::InvalidNextBPHash.into()),
+    };
+    // Epochs before `BlockHeaderV3` hash the block producers in the old format.
+    let next_bp_hash = Chain::compute_collection_hash(next_bps.clone())?;
+    let next_bp_hash_v1 = Chain::compute_collection_hash(
+        next_bps.into_iter().map(|bp| bp.into_v1()).collect::<Vec<_>>(),
+    )?;
+    if light_client_block.inner_lite.next_bp_hash != next_bp_hash
+        && light_client_block.inner_lite.next_bp_hash != next_bp_hash_v1
+    {
+        return Err(ErrorKind::InvalidNextBPHash.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::KeyType;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+
+    use super::*;
+
+    fn signers() -> Vec<InMemoryValidatorSigner> {
+        ["test0", "test1", "test2"]
+            .iter()
+            .map(|account_id| {
+                InMemoryValidatorSigner::from_seed(account_id, KeyType::ED25519, account_id)
+            })
+            .collect()
+    }
+
+    fn block_producers(signers: &[InMemoryValidatorSigner]) -> Vec<ValidatorStake> {
+        signers
+            .iter()
+            .map(|signer| {
+                ValidatorStake::new(signer.validator_id().clone(), signer.public_key(), 100)
+            })
+            .collect()
+    }
+
+    /// Creates a light client block approved by the given number of signers, which are the block
+    /// producers of both the epoch and the next epoch.
+    fn light_client_block(
+        signers: &[InMemoryValidatorSigner],
+        approvals: usize,
+    ) -> LightClientBlockView {
+        let next_bps = block_producers(signers);
+        let mut light_client_block = LightClientBlockView {
+            prev_block_hash: hash(&[1]),
+            next_block_inner_hash: hash(&[2]),
+            inner_lite: BlockHeaderInnerLiteView {
+                height: 10,
+                epoch_id: hash(&[3]),
+                next_epoch_id: hash(&[4]),
+                prev_state_root: CryptoHash::default(),
+                outcome_root: CryptoHash::default(),
+                timestamp: 0,
+                timestamp_nanosec: 0,
+                next_bp_hash: Chain::compute_collection_hash(next_bps.clone()).unwrap(),
+                block_merkle_root: CryptoHash::default(),
+            },
+            inner_rest_hash: hash(&[5]),
+            next_bps: Some(next_bps.into_iter().map(Into::into).collect()),
+            approvals_after_next: vec![],
+        };
+        let next_block_hash = combine_hash(
+            light_client_block.next_block_inner_hash,
+            light_client_block_hash(&light_client_block),
+        );
+        light_client_block.approvals_after_next = signers
+            .iter()
+            .enumerate()
+            .map(|(i, signer)| {
+                if i < approvals {
+                    Some(signer.sign_approval(&ApprovalInner::Endorsement(next_block_hash), 12))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        light_client_block
+    }
+
+    #[test]
+    fn test_validate_light_client_block() {
+        let signers = signers();
+        let block_producers = block_producers(&signers);
+        assert!(
+            validate_light_client_block(&light_client_block(&signers, 3), &block_producers).is_ok()
+        );
+
+        let err = validate_light_client_block(&light_client_block(&signers, 2), &block_producers)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotEnoughApprovals);
+    }
+
+    #[test]
+    fn test_validate_light_client_block_tampered() {
+        let signers = signers();
+        let block_producers = block_producers(&signers);
+
+        let mut block = light_client_block(&signers, 3);
+        block.inner_lite.height += 1;
+        let err = validate_light_client_block(&block, &block_producers).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidSignature);
+
+        let mut block = light_client_block(&signers, 3);
+        block.next_bps.as_mut().unwrap().pop();
+        let err = validate_light_client_block(&block, &block_producers).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidNextBPHash);
+    }
+}
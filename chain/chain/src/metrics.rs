@@ -25,4 +25,14 @@ lazy_static! {
         "near_validator_active_total",
         "The total number of validators active after last block"
     );
+    pub static ref ORPHAN_BLOCKS: near_metrics::Result<IntGauge> =
+        try_create_int_gauge("near_orphan_blocks", "Number of blocks in the orphan pool");
+    pub static ref ORPHAN_BLOCKS_EVICTED: near_metrics::Result<IntCounter> = try_create_int_counter(
+        "near_orphan_blocks_evicted_total",
+        "Total number of orphans evicted because the orphan pool was full"
+    );
+    pub static ref ORPHAN_BLOCKS_EXPIRED: near_metrics::Result<IntCounter> = try_create_int_counter(
+        "near_orphan_blocks_expired_total",
+        "Total number of orphans dropped because their parent didn't arrive in time"
+    );
 }
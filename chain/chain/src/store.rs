@@ -20,7 +20,7 @@ use near_primitives::sharding::{
 };
 use near_primitives::syncing::{
     get_num_state_parts, ReceiptProofResponse, ReceiptResponse, ShardStateSyncResponseHeader,
-    StateHeaderKey, StatePartKey,
+    StateHeaderKey, StatePartKey, StateSyncProgress,
 };
 use near_primitives::transaction::{
     ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof, SignedTransaction,
@@ -28,22 +28,24 @@ use near_primitives::transaction::{
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{
-    AccountId, BlockExtra, BlockHeight, EpochId, GCCount, NumBlocks, ShardId, StateChanges,
-    StateChangesExt, StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
+    AccountId, BlockExtra, BlockHeight, EpochId, GCCount, NumBlocks, RawStateChangesWithTrieKey,
+    ShardId, StateChanges, StateChangesExt, StateChangesFilter, StateChangesKinds,
+    StateChangesKindsExt, StateChangesRequest,
 };
 use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
 use near_primitives::views::LightClientBlockView;
 use near_store::{
-    read_with_cache, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight, ColBlockInfo,
-    ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight, ColBlockRefCount,
-    ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra, ColChunkHashesByHeight,
-    ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount,
-    ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks, ColLastBlockWithNewChunk,
-    ColNextBlockHashes, ColNextBlockWithNewChunk, ColOutcomeIds, ColOutgoingReceipts,
-    ColPartialChunks, ColProcessedBlockHeights, ColReceiptIdToShardId, ColReceipts, ColState,
-    ColStateChanges, ColStateDlInfos, ColStateHeaders, ColStateParts, ColTransactionResult,
-    ColTransactions, ColTrieChanges, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate,
-    TrieChanges, WrappedTrieChanges, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
+    read_with_cache, ColBlock, ColBlockApprovals, ColBlockExtra, ColBlockHeader, ColBlockHeight,
+    ColBlockInfo, ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight,
+    ColBlockRefCount, ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra,
+    ColChunkHashesByHeight, ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks,
+    ColFilteredStateChanges, ColGCCount, ColHeaderHashesByHeight, ColIncomingReceipts,
+    ColInvalidChunks, ColLastBlockWithNewChunk, ColNextBlockHashes, ColNextBlockWithNewChunk,
+    ColOutcomeIds, ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights,
+    ColReceiptIdToShardId, ColReceipts, ColState, ColStateChanges, ColStateDlInfos,
+    ColStateHeaders, ColStateParts, ColStateSyncProgress, ColTransactionResult, ColTransactions,
+    ColTrieChanges, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges,
+    WrappedTrieChanges, CHUNK_TAIL_KEY, COLD_HEAD_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
     HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC,
     TAIL_KEY,
 };
@@ -69,6 +71,20 @@ pub enum GCMode {
     StateSync { clear_block_info: bool },
 }
 
+fn get_filtered_state_changes_key(block_hash: &CryptoHash, filter_id: &str) -> Vec<u8> {
+    let mut res = Vec::with_capacity(32 + filter_id.len());
+    res.extend_from_slice(block_hash.as_ref());
+    res.extend_from_slice(filter_id.as_bytes());
+    res
+}
+
+fn get_block_approval_key(target_height: BlockHeight, account_id: &AccountId) -> Vec<u8> {
+    let mut res = Vec::with_capacity(8 + account_id.len());
+    res.extend_from_slice(&target_height.to_le_bytes());
+    res.extend_from_slice(account_id.as_bytes());
+    res
+}
+
 fn get_height_shard_id(height: BlockHeight, shard_id: ShardId) -> Vec<u8> {
     let mut res = Vec::with_capacity(40);
     res.extend_from_slice(&height.to_le_bytes());
@@ -549,6 +565,57 @@ impl ChainStore {
         )
     }
 
+    /// The height up to which the block data was moved to the cold database.
+    pub fn cold_head(&self) -> Result<BlockHeight, Error> {
+        self.store
+            .get_ser(ColBlockMisc, COLD_HEAD_KEY)
+            .map(|option| option.unwrap_or_else(|| self.genesis_height))
+            .map_err(|e| e.into())
+    }
+
+    /// Moves the data of all the blocks at the given height which is not modified after the
+    /// block is processed to the cold database, see `SplitDB`. Reads are still served from the
+    /// cold database, so caches don't have to be invalidated.
+    pub fn move_to_cold(&mut self, height: BlockHeight) -> Result<(), Error> {
+        let block_hashes = match self.get_all_block_hashes_by_height(height) {
+            Ok(block_hashes) => block_hashes.values().flatten().cloned().collect::<Vec<_>>(),
+            Err(_) => vec![],
+        };
+        let mut keys = vec![];
+        for block_hash in block_hashes {
+            let block = self.get_block(&block_hash)?.clone();
+            let block_hash_vec: Vec<u8> = block_hash.as_ref().into();
+            keys.push((ColBlock, block_hash_vec.clone()));
+            keys.push((ColBlockExtra, block_hash_vec.clone()));
+            for shard_id in 0..block.header().chunk_mask().len() as ShardId {
+                let block_shard_id = get_block_shard_id(&block_hash, shard_id);
+                keys.push((ColChunkExtra, block_shard_id.clone()));
+                keys.push((ColIncomingReceipts, block_shard_id.clone()));
+                keys.push((ColOutgoingReceipts, block_shard_id.clone()));
+                keys.push((ColOutcomeIds, block_shard_id.clone()));
+                keys.push((ColTrieChanges, block_shard_id));
+            }
+            for chunk_header in block.chunks().iter() {
+                if chunk_header.height_included() == height {
+                    let chunk_hash: Vec<u8> = chunk_header.chunk_hash().into();
+                    keys.push((ColChunks, chunk_hash.clone()));
+                    keys.push((ColPartialChunks, chunk_hash));
+                }
+            }
+            let storage_key = KeyForStateChanges::get_prefix(&block_hash);
+            keys.extend(
+                self.store
+                    .iter_prefix(ColStateChanges, storage_key.as_ref())
+                    .map(|(key, _)| (ColStateChanges, key.into())),
+            );
+        }
+        self.store.move_to_cold(&keys)?;
+
+        let mut store_update = self.store.store_update();
+        store_update.set_ser(ColBlockMisc, COLD_HEAD_KEY, &height)?;
+        store_update.commit().map_err(|e| e.into())
+    }
+
     /// Returns a HashSet of Chunk Hashes for current Height
     pub fn get_all_chunk_hashes_by_height(
         &mut self,
@@ -580,6 +647,16 @@ impl ChainStore {
         }
     }
 
+    /// Returns the parts of the shard state downloaded so far for state sync to `sync_hash`.
+    pub fn get_state_sync_progress(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<StateSyncProgress, Error> {
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        Ok(self.store.get_ser(ColStateSyncProgress, &key)?.unwrap_or_default())
+    }
+
     /// Returns latest known height and time it was seen.
     pub fn get_latest_known(&mut self) -> Result<LatestKnown, Error> {
         if self.latest_known.is_none() {
@@ -599,6 +676,38 @@ impl ChainStore {
         store_update.commit().map_err(|err| err.into())
     }
 
+    /// Returns the approvals persisted for the blocks this node is to produce.
+    pub fn get_block_approvals(&self) -> Result<Vec<Approval>, Error> {
+        self.store
+            .iter(ColBlockApprovals)
+            .map(|(_, value)| Approval::try_from_slice(&value).map_err(|err| err.into()))
+            .collect()
+    }
+
+    /// Persists an approval for a block this node is to produce, so it's not lost on restart.
+    /// Only the last approval of each account for a target height is kept.
+    pub fn save_block_approval(&mut self, approval: &Approval) -> Result<(), Error> {
+        let mut store_update = self.store.store_update();
+        store_update.set_ser(
+            ColBlockApprovals,
+            &get_block_approval_key(approval.target_height, &approval.account_id),
+            approval,
+        )?;
+        store_update.commit().map_err(|err| err.into())
+    }
+
+    /// Removes the persisted approvals with target heights up to `height`, which can no longer
+    /// be produced on top of the head.
+    pub fn remove_block_approvals(&mut self, height: BlockHeight) -> Result<(), Error> {
+        let mut store_update = self.store.store_update();
+        for (key, value) in self.store.iter(ColBlockApprovals) {
+            if Approval::try_from_slice(&value)?.target_height <= height {
+                store_update.delete(ColBlockApprovals, &key);
+            }
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
     /// Retrieve the kinds of state changes occurred in a given block.
     ///
     /// We store different types of data, so we prefer to only expose minimal information about the
@@ -627,6 +736,51 @@ impl ChainStore {
         Ok(StateChangesKinds::from_changes(&mut block_changes)?)
     }
 
+    /// Saves the state changes of the given block matching each of the filters, so that they can
+    /// be queried later by the filter id.
+    pub fn save_filtered_state_changes(
+        &self,
+        block_hash: &CryptoHash,
+        filters: &[StateChangesFilter],
+    ) -> Result<(), Error> {
+        let storage_key = KeyForStateChanges::get_prefix(block_hash);
+        let block_changes =
+            storage_key.find_iter(&self.store).collect::<Result<Vec<_>, io::Error>>()?;
+        let mut store_update = self.store.store_update();
+        for filter in filters {
+            let filtered_changes: Vec<RawStateChangesWithTrieKey> = block_changes
+                .iter()
+                .filter(|changes| filter.matches(&changes.trie_key))
+                .cloned()
+                .collect();
+            if filtered_changes.is_empty() {
+                continue;
+            }
+            store_update.set_ser(
+                ColFilteredStateChanges,
+                &get_filtered_state_changes_key(block_hash, &filter.id),
+                &filtered_changes,
+            )?;
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
+    /// Retrieve the state changes of the given block that matched the filter with the given id.
+    pub fn get_filtered_state_changes(
+        &self,
+        block_hash: &CryptoHash,
+        filter_id: &str,
+    ) -> Result<StateChanges, Error> {
+        let raw_changes: Vec<RawStateChangesWithTrieKey> = self
+            .store
+            .get_ser(
+                ColFilteredStateChanges,
+                &get_filtered_state_changes_key(block_hash, filter_id),
+            )?
+            .unwrap_or_default();
+        Ok(StateChanges::from_changes(raw_changes.into_iter().map(Ok))?)
+    }
+
     pub fn get_state_changes_with_cause_in_block(
         &self,
         block_hash: &CryptoHash,
@@ -2105,6 +2259,15 @@ impl<'a> ChainStoreUpdate<'a> {
         for key in stored_state_changes {
             self.gc_col(ColStateChanges, &key);
         }
+        let filtered_state_changes: Vec<Vec<u8>> = self
+            .chain_store
+            .store()
+            .iter_prefix(ColFilteredStateChanges, &block_hash_vec)
+            .map(|key| key.0.into())
+            .collect();
+        for key in filtered_state_changes {
+            self.gc_col(ColFilteredStateChanges, &key);
+        }
         self.gc_col(ColBlockRefCount, &block_hash_vec);
         self.gc_outcomes(&block)?;
         match gc_mode {
@@ -2192,6 +2355,8 @@ impl<'a> ChainStoreUpdate<'a> {
             let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
             self.gc_col(ColStateParts, &key);
         }
+        let key = StateHeaderKey(shard_id, sync_hash).try_to_vec()?;
+        self.gc_col(ColStateSyncProgress, &key);
         Ok(())
     }
 
@@ -2305,6 +2470,9 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColStateChanges => {
                 store_update.delete(col, key);
             }
+            DBCol::ColFilteredStateChanges => {
+                store_update.delete(col, key);
+            }
             DBCol::ColBlockRefCount => {
                 store_update.delete(col, key);
                 self.chain_store.block_refcounts.cache_remove(key);
@@ -2342,6 +2510,9 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColStateParts => {
                 store_update.delete(col, key);
             }
+            DBCol::ColStateSyncProgress => {
+                store_update.delete(col, key);
+            }
             DBCol::ColState => {
                 panic!("Actual gc happens elsewhere, call inc_gc_col_state to increase gc count");
             }
@@ -2390,7 +2561,10 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::ColEpochValidatorInfo
             | DBCol::ColBlockOrdinal
             | DBCol::_ColTransactionRefCount
-            | DBCol::ColCachedContractCode => {
+            | DBCol::ColCachedContractCode
+            | DBCol::ColBlockApprovals
+            | DBCol::ColBlobs
+            | DBCol::ColContractExecutionStats => {
                 unreachable!();
             }
         }
@@ -2714,7 +2888,7 @@ impl<'a> ChainStoreUpdate<'a> {
             )?;
         }
         for other in self.store_updates.drain(..) {
-            store_update.merge(other);
+            store_update.try_merge(other)?;
         }
         Ok(store_update)
     }
@@ -2860,16 +3034,20 @@ mod tests {
     use strum::IntoEnumIterator;
 
     use near_crypto::KeyType;
-    use near_primitives::block::{Block, Tip};
+    use near_primitives::block::{Approval, Block, Tip};
     #[cfg(feature = "expensive_tests")]
     use near_primitives::epoch_manager::block_info::BlockInfo;
     use near_primitives::errors::InvalidTxError;
     use near_primitives::hash::hash;
-    use near_primitives::types::{BlockHeight, EpochId, GCCount, NumBlocks};
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::{
+        BlockHeight, EpochId, GCCount, NumBlocks, RawStateChange, RawStateChangesWithTrieKey,
+        StateChangeCause, StateChangeKindFilter, StateChangesFilter,
+    };
     use near_primitives::utils::index_to_bytes;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
     use near_store::test_utils::create_test_store;
-    use near_store::DBCol;
+    use near_store::{DBCol, KeyForStateChanges};
     #[cfg(feature = "expensive_tests")]
     use {crate::store_validator::StoreValidator, near_chain_configs::GenesisConfig};
 
@@ -3091,6 +3269,48 @@ mod tests {
         assert_ne!(epoch_id_to_hash, epoch_id_to_hash1);
     }
 
+    #[test]
+    fn test_filtered_state_changes() {
+        let chain = get_chain();
+        let block_hash = hash(&[1]);
+        let mut store_update = chain.store().store().store_update();
+        for account_id in &["alice.near", "bob.near", "alice2.near"] {
+            let trie_key = TrieKey::Account { account_id: account_id.to_string() };
+            let changes = RawStateChangesWithTrieKey {
+                trie_key: trie_key.clone(),
+                changes: vec![RawStateChange { cause: StateChangeCause::InitialState, data: None }],
+            };
+            store_update
+                .set_ser(
+                    DBCol::ColStateChanges,
+                    KeyForStateChanges::new_from_trie_key(&block_hash, &trie_key).as_ref(),
+                    &changes,
+                )
+                .unwrap();
+        }
+        store_update.commit().unwrap();
+
+        let filters = vec![
+            StateChangesFilter {
+                id: "alice".to_string(),
+                account_id_prefix: "alice".to_string(),
+                kinds: vec![StateChangeKindFilter::Account],
+            },
+            StateChangesFilter {
+                id: "data".to_string(),
+                account_id_prefix: "".to_string(),
+                kinds: vec![StateChangeKindFilter::Data],
+            },
+        ];
+        chain.store().save_filtered_state_changes(&block_hash, &filters).unwrap();
+        assert_eq!(
+            chain.store().get_filtered_state_changes(&block_hash, "alice").unwrap().len(),
+            2
+        );
+        assert!(chain.store().get_filtered_state_changes(&block_hash, "data").unwrap().is_empty());
+        assert!(chain.store().get_filtered_state_changes(&hash(&[2]), "alice").unwrap().is_empty());
+    }
+
     /// Test that garbage collection works properly. The blocks behind gc head should be garbage
     /// collected while the blocks that are ahead of it should not.
     #[test]
@@ -3250,6 +3470,28 @@ mod tests {
         assert!(chain.mut_store().get_next_block_hash(blocks[6].hash()).is_ok());
     }
 
+    #[test]
+    fn test_block_approvals() {
+        let mut chain = get_chain();
+        let signer = InMemoryValidatorSigner::from_seed("test1", KeyType::ED25519, "test1");
+        let genesis_hash = *chain.genesis().hash();
+        let approvals = (2..=4)
+            .map(|height| Approval::new(genesis_hash, 0, height, &signer))
+            .collect::<Vec<_>>();
+        for approval in approvals.iter().rev() {
+            chain.mut_store().save_block_approval(approval).unwrap();
+        }
+        // Only one approval per account and target height is kept.
+        chain.mut_store().save_block_approval(&approvals[1]).unwrap();
+
+        let mut stored = chain.store().get_block_approvals().unwrap();
+        stored.sort_by_key(|approval| approval.target_height);
+        assert_eq!(stored, approvals);
+
+        chain.mut_store().remove_block_approvals(3).unwrap();
+        assert_eq!(chain.store().get_block_approvals().unwrap(), vec![approvals[2].clone()]);
+    }
+
     /// Test that `gc_blocks_limit` works properly
     #[cfg(feature = "expensive_tests")]
     #[test]
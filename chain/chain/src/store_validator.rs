@@ -12,7 +12,7 @@ use near_primitives::block::{Block, BlockHeader};
 use near_primitives::borsh;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
-use near_primitives::epoch_manager::AGGREGATOR_KEY;
+use near_primitives::epoch_manager::{AGGREGATOR_KEY, VALIDATOR_SET_OVERRIDE_KEY};
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::{ChunkHash, ShardChunk, StateSyncInfo};
 use near_primitives::syncing::{ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey};
@@ -286,7 +286,7 @@ impl StoreValidator {
                     );
                 }
                 DBCol::ColEpochInfo => {
-                    if key_ref != AGGREGATOR_KEY {
+                    if key_ref != AGGREGATOR_KEY && key_ref != VALIDATOR_SET_OVERRIDE_KEY {
                         let epoch_id = EpochId::try_from_slice(key_ref)?;
                         let epoch_info = EpochInfo::try_from_slice(value_ref)?;
                         // Epoch should exist
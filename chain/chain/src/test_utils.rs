@@ -18,6 +18,7 @@ use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
+use near_primitives::runtime::contract_stats::ContractExecutionStats;
 use near_primitives::serialize::to_base;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::transaction::{
@@ -32,8 +33,9 @@ use near_primitives::types::{
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
-    AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorDiffView,
+    EpochValidatorInfo, QueryRequest, QueryResponse, QueryResponseKind, SeatPriceView,
+    ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
@@ -549,12 +551,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         &self,
         _gas_price: Balance,
         _gas_limit: Gas,
+        _transactions_size_limit: u64,
         _shard_id: ShardId,
         _state_root: StateRoot,
         _next_block_height: BlockHeight,
         transactions: &mut dyn PoolIterator,
-        _chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        _chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         _current_protocol_version: ProtocolVersion,
+        _dropped_transactions: &mut Vec<(SignedTransaction, InvalidTxError)>,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let mut res = vec![];
         while let Some(iter) = transactions.next() {
@@ -845,6 +849,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 kind: QueryResponseKind::ViewState(ViewStateResult {
                     values: Default::default(),
                     proof: vec![],
+                    next_key: None,
                 }),
                 block_height,
                 block_hash: *block_hash,
@@ -857,6 +862,11 @@ impl RuntimeAdapter for KeyValueRuntime {
                 block_height,
                 block_hash: *block_hash,
             }),
+            QueryRequest::ViewBurnLedger { .. } => Ok(QueryResponse {
+                kind: QueryResponseKind::BurnLedger(Default::default()),
+                block_height,
+                block_hash: *block_hash,
+            }),
         }
     }
 
@@ -1036,6 +1046,28 @@ impl RuntimeAdapter for KeyValueRuntime {
         })
     }
 
+    fn get_validator_diff(&self, epoch_id: &EpochId) -> Result<EpochValidatorDiffView, Error> {
+        Ok(EpochValidatorDiffView {
+            epoch_id: epoch_id.clone(),
+            prev_epoch_id: EpochId::default(),
+            epoch_height: 1,
+            joined: vec![],
+            left: vec![],
+            kicked: vec![],
+            stake_changed: vec![],
+        })
+    }
+
+    fn get_seat_prices(&self, _block_hash: &CryptoHash) -> Result<SeatPriceView, Error> {
+        Ok(SeatPriceView {
+            epoch_id: EpochId::default(),
+            seat_price: 0,
+            next_epoch_id: EpochId::default(),
+            next_seat_price: 0,
+            minimum_stake_to_join: None,
+        })
+    }
+
     fn compare_epoch_id(
         &self,
         epoch_id: &EpochId,
@@ -1103,6 +1135,37 @@ impl RuntimeAdapter for KeyValueRuntime {
         unreachable!("get_protocol_config should not be called in KeyValueRuntime");
     }
 
+    fn get_contract_execution_stats(
+        &self,
+        _code_hash: &CryptoHash,
+    ) -> Result<Option<ContractExecutionStats>, Error> {
+        Ok(None)
+    }
+
+    fn compute_total_supply(
+        &self,
+        _epoch_id: &EpochId,
+        _shard_id: ShardId,
+        state_root: &StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<Balance, Error> {
+        let state = self.state.read().unwrap();
+        let accounts_supply =
+            state.get(state_root).map_or(0, |state| state.amounts.values().sum::<Balance>());
+        let receipts_supply = receipts
+            .iter()
+            .map(|receipt| match &receipt.receipt {
+                ReceiptEnum::Action(action_receipt) => action_receipt
+                    .actions
+                    .iter()
+                    .map(|action| action.get_deposit_balance())
+                    .sum::<Balance>(),
+                ReceiptEnum::Data(_) => 0,
+            })
+            .sum::<Balance>();
+        Ok(accounts_supply + receipts_supply)
+    }
+
     fn get_prev_epoch_id_from_prev_block(
         &self,
         prev_block_hash: &CryptoHash,
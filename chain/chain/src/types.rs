@@ -9,7 +9,7 @@ use serde::Serialize;
 
 use near_chain_configs::{GenesisConfig, ProtocolConfig};
 use near_chain_primitives::Error;
-use near_crypto::Signature;
+use near_crypto::{PublicKey, Signature};
 use near_pool::types::PoolIterator;
 pub use near_primitives::block::{Block, BlockHeader, Tip};
 use near_primitives::challenge::{ChallengesResult, SlashedValidator};
@@ -20,18 +20,21 @@ use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::{Receipt, ReceiptResult};
+use near_primitives::runtime::contract_stats::ContractExecutionStats;
 use near_primitives::sharding::{ChunkHash, ReceiptList, ShardChunkHeader};
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
-    NumBlocks, ShardId, StateRoot, StateRootNode,
+    Nonce, NumBlocks, ShardId, StateRoot, StateRootNode,
 };
 use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
+use near_primitives::views::{
+    EpochValidatorDiffView, EpochValidatorInfo, QueryRequest, QueryResponse, SeatPriceView,
+};
 use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -114,6 +117,9 @@ pub struct BlockHeaderInfo {
     pub last_finalized_block_hash: CryptoHash,
     pub proposals: Vec<ValidatorStake>,
     pub slashed_validators: Vec<SlashedValidator>,
+    /// Validators whose slashes are cancelled by correct appeals, with the hashes of the blocks
+    /// which included the appealed challenges.
+    pub appealed_validators: Vec<(AccountId, CryptoHash)>,
     pub chunk_mask: Vec<bool>,
     pub total_supply: Balance,
     pub latest_protocol_version: ProtocolVersion,
@@ -131,6 +137,7 @@ impl BlockHeaderInfo {
             last_finalized_block_hash: *header.last_final_block(),
             proposals: header.validator_proposals().collect(),
             slashed_validators: vec![],
+            appealed_validators: vec![],
             chunk_mask: header.chunk_mask().to_vec(),
             total_supply: header.total_supply(),
             latest_protocol_version: header.latest_protocol_version(),
@@ -274,23 +281,41 @@ pub trait RuntimeAdapter: Send + Sync {
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error>;
 
+    /// Returns the nonce of the access key as of the given state root, or `None` if the access
+    /// key doesn't exist or the runtime doesn't keep track of nonces.
+    fn get_access_key_nonce(
+        &self,
+        _shard_id: ShardId,
+        _state_root: StateRoot,
+        _account_id: &AccountId,
+        _public_key: &PublicKey,
+    ) -> Result<Option<Nonce>, Error> {
+        Ok(None)
+    }
+
     /// Returns an ordered list of valid transactions from the pool up the given limits.
+    /// The total size of the returned transactions doesn't exceed `transactions_size_limit`,
+    /// transactions which don't fit are left in the pool for the next chunks.
     /// Pulls transactions from the given pool iterators one by one. Validates each transaction
     /// against the given `chain_validate` closure and runtime's transaction verifier.
     /// If the transaction is valid for both, it's added to the result and the temporary state
     /// update is preserved for validation of next transactions.
+    /// Transactions rejected by either are dropped from the pool and appended to
+    /// `dropped_transactions` together with the reason of the rejection.
     /// Throws an `Error` with `ErrorKind::StorageError` in case the runtime throws
     /// `RuntimeError::StorageError`.
     fn prepare_transactions(
         &self,
         gas_price: Balance,
         gas_limit: Gas,
+        transactions_size_limit: u64,
         shard_id: ShardId,
         state_root: StateRoot,
         next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
-        chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         current_protocol_version: ProtocolVersion,
+        dropped_transactions: &mut Vec<(SignedTransaction, InvalidTxError)>,
     ) -> Result<Vec<SignedTransaction>, Error>;
 
     /// Verify validator signature for the given epoch.
@@ -407,7 +432,7 @@ pub trait RuntimeAdapter: Send + Sync {
 
     fn num_data_parts(&self) -> usize;
 
-    /// Account Id to Shard Id mapping, given current number of shards.
+    /// Account Id to Shard Id mapping in the layout of the shards of the genesis.
     fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId;
 
     /// Returns `account_id` that suppose to have the `part_id` of all chunks given previous block hash.
@@ -605,6 +630,12 @@ pub trait RuntimeAdapter: Send + Sync {
         epoch_id: ValidatorInfoIdentifier,
     ) -> Result<EpochValidatorInfo, Error>;
 
+    /// Get the changes of the validator set of the given epoch relative to the previous epoch.
+    fn get_validator_diff(&self, epoch_id: &EpochId) -> Result<EpochValidatorDiffView, Error>;
+
+    /// Get the seat prices of the epoch of the given block and the following epochs.
+    fn get_seat_prices(&self, block_hash: &CryptoHash) -> Result<SeatPriceView, Error>;
+
     /// Get the part of the state from given state root.
     fn obtain_state_part(
         &self,
@@ -668,6 +699,23 @@ pub trait RuntimeAdapter: Send + Sync {
 
     fn get_protocol_config(&self, epoch_id: &EpochId) -> Result<ProtocolConfig, Error>;
 
+    /// Returns the execution statistics of the contract with the given code hash, aggregated over
+    /// the chunks applied by this node.
+    fn get_contract_execution_stats(
+        &self,
+        code_hash: &CryptoHash,
+    ) -> Result<Option<ContractExecutionStats>, Error>;
+
+    /// Returns the tokens held by the state of the shard at the given state root together with
+    /// the given receipts, which are in flight to the shards.
+    fn compute_total_supply(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<Balance, Error>;
+
     /// Get previous epoch id by hash of previous block.
     fn get_prev_epoch_id_from_prev_block(
         &self,
@@ -3,11 +3,19 @@ use std::collections::HashMap;
 use borsh::BorshDeserialize;
 
 use near_crypto::PublicKey;
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::block::Approval;
 use near_primitives::block::{Block, BlockHeader};
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::challenge::ApprovalDoubleSign;
+#[cfg(feature = "protocol_feature_slashing_escrow")]
+use near_primitives::challenge::SlashAppeal;
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChallengesResult, ChunkProofs, ChunkState,
     MaybeEncodedShardChunk,
 };
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::merklize;
 #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -122,6 +130,23 @@ pub fn validate_chunk_with_chunk_extra(
     prev_chunk_extra: &ChunkExtra,
     prev_chunk_header: &ShardChunkHeader,
     chunk_header: &ShardChunkHeader,
+) -> Result<(), Error> {
+    validate_chunk_header_with_chunk_extra(prev_chunk_extra, chunk_header)?;
+
+    let receipt_response = chain_store.get_outgoing_receipts_for_shard(
+        *prev_block_hash,
+        chunk_header.shard_id(),
+        prev_chunk_header.height_included(),
+    )?;
+    let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(&receipt_response.1);
+    validate_chunk_outgoing_receipts(&outgoing_receipts_hashes, chunk_header)
+}
+
+/// Validates that the chunk header reports the results of applying the previous chunk, except
+/// for the outgoing receipts.
+fn validate_chunk_header_with_chunk_extra(
+    prev_chunk_extra: &ChunkExtra,
+    chunk_header: &ShardChunkHeader,
 ) -> Result<(), Error> {
     if *prev_chunk_extra.state_root() != chunk_header.prev_state_root() {
         return Err(ErrorKind::InvalidStateRoot.into());
@@ -151,18 +176,6 @@ pub fn validate_chunk_with_chunk_extra(
         return Err(ErrorKind::InvalidBalanceBurnt.into());
     }
 
-    let receipt_response = chain_store.get_outgoing_receipts_for_shard(
-        *prev_block_hash,
-        chunk_header.shard_id(),
-        prev_chunk_header.height_included(),
-    )?;
-    let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(&receipt_response.1);
-    let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
-
-    if outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
-        return Err(ErrorKind::InvalidReceiptsProof.into());
-    }
-
     let prev_gas_limit = prev_chunk_extra.gas_limit();
     if chunk_header.gas_limit() < prev_gas_limit - prev_gas_limit / GAS_LIMIT_ADJUSTMENT_FACTOR
         || chunk_header.gas_limit() > prev_gas_limit + prev_gas_limit / GAS_LIMIT_ADJUSTMENT_FACTOR
@@ -173,6 +186,71 @@ pub fn validate_chunk_with_chunk_extra(
     Ok(())
 }
 
+/// Validates that the chunk header commits to the receipts sent by the previous chunk, given by
+/// the hashes of the receipts to every shard.
+fn validate_chunk_outgoing_receipts(
+    prev_outgoing_receipts_hashes: &[CryptoHash],
+    chunk_header: &ShardChunkHeader,
+) -> Result<(), Error> {
+    let (outgoing_receipts_root, _) = merklize(prev_outgoing_receipts_hashes);
+
+    if outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
+        return Err(ErrorKind::InvalidReceiptsProof.into());
+    }
+    Ok(())
+}
+
+/// Validates a chunk header against the result of applying the previous chunk of the shard with
+/// the trie nodes of a `ChunkStateWitness`, by a node which doesn't have the state of the shard.
+pub fn validate_chunk_with_witness_result(
+    prev_chunk_extra: &ChunkExtra,
+    prev_outgoing_receipts_hashes: &[CryptoHash],
+    chunk_header: &ShardChunkHeader,
+) -> Result<(), Error> {
+    validate_chunk_header_with_chunk_extra(prev_chunk_extra, chunk_header)?;
+    validate_chunk_outgoing_receipts(prev_outgoing_receipts_hashes, chunk_header)
+}
+
+/// Validates a double sign challenge of approvals. The signatures are verified with the keys of
+/// the validators of the epoch of the block with the challenge.
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+fn validate_approval_double_sign(
+    runtime_adapter: &dyn RuntimeAdapter,
+    epoch_id: &EpochId,
+    last_block_hash: &CryptoHash,
+    approval_double_sign: &ApprovalDoubleSign,
+) -> Result<(CryptoHash, Vec<AccountId>), Error> {
+    let protocol_version = runtime_adapter.get_epoch_protocol_version(epoch_id)?;
+    if !checked_feature!(
+        "protocol_feature_double_sign_slashing",
+        DoubleSignSlashing,
+        protocol_version
+    ) {
+        return Err(ErrorKind::InvalidChallenge.into());
+    }
+    let ApprovalDoubleSign { left_approval, right_approval } = approval_double_sign;
+    let verify_signature = |approval: &Approval| {
+        runtime_adapter.verify_validator_signature(
+            epoch_id,
+            last_block_hash,
+            &approval.account_id,
+            Approval::get_data_for_sig(&approval.inner, approval.target_height).as_ref(),
+            &approval.signature,
+        )
+    };
+    if left_approval.account_id == right_approval.account_id
+        && left_approval.target_height == right_approval.target_height
+        && left_approval.inner != right_approval.inner
+        && verify_signature(left_approval)?
+        && verify_signature(right_approval)?
+    {
+        // Approvals don't invalidate any block.
+        Ok((CryptoHash::default(), vec![left_approval.account_id.clone()]))
+    } else {
+        Err(ErrorKind::MaliciousChallenge.into())
+    }
+}
+
 /// Validates a double sign challenge.
 /// Only valid if ancestors of both blocks are present in the chain.
 fn validate_double_sign(
@@ -292,10 +370,13 @@ fn validate_chunk_proofs_challenge(
     return Err(ErrorKind::MaliciousChallenge.into());
 }
 
-fn validate_chunk_state_challenge(
+/// Applies the state transition from the given chunk state.
+/// Returns hash of the block with the chunk, the chunk producer and whether the result of the
+/// state transition matches the chunk header.
+fn check_chunk_state_transition(
     runtime_adapter: &dyn RuntimeAdapter,
     chunk_state: &ChunkState,
-) -> Result<(CryptoHash, Vec<AccountId>), Error> {
+) -> Result<(CryptoHash, AccountId, bool), Error> {
     let prev_block_header = BlockHeader::try_from_slice(&chunk_state.prev_block_header)?;
     let block_header = BlockHeader::try_from_slice(&chunk_state.block_header)?;
 
@@ -353,15 +434,41 @@ fn validate_chunk_state_challenge(
             .iter()
             .zip(chunk_state.chunk_header.validator_proposals())
             .all(|(x, y)| x == &y);
-    if result.new_root != chunk_state.chunk_header.prev_state_root()
-        || outcome_root != chunk_state.chunk_header.outcome_root()
-        || !proposals_match
-        || result.total_gas_burnt != chunk_state.chunk_header.gas_used()
-    {
-        Ok((*block_header.hash(), vec![chunk_producer]))
-    } else {
+    let is_valid = result.new_root == chunk_state.chunk_header.prev_state_root()
+        && outcome_root == chunk_state.chunk_header.outcome_root()
+        && proposals_match
+        && result.total_gas_burnt == chunk_state.chunk_header.gas_used();
+    Ok((*block_header.hash(), chunk_producer, is_valid))
+}
+
+fn validate_chunk_state_challenge(
+    runtime_adapter: &dyn RuntimeAdapter,
+    chunk_state: &ChunkState,
+) -> Result<(CryptoHash, Vec<AccountId>), Error> {
+    let (block_hash, chunk_producer, is_valid) =
+        check_chunk_state_transition(runtime_adapter, chunk_state)?;
+    if is_valid {
         // If all the data matches, this is actually valid chunk and challenge is malicious.
         Err(ErrorKind::MaliciousChallenge.into())
+    } else {
+        Ok((block_hash, vec![chunk_producer]))
+    }
+}
+
+/// Returns the chunk producer whose slash should be cancelled if the appeal is correct.
+/// That the appealed challenge is on chain is checked by `ChainUpdate::verify_slash_appeals`.
+#[cfg(feature = "protocol_feature_slashing_escrow")]
+fn validate_slash_appeal(
+    runtime_adapter: &dyn RuntimeAdapter,
+    slash_appeal: &SlashAppeal,
+) -> Result<(CryptoHash, Vec<AccountId>), Error> {
+    let (block_hash, chunk_producer, is_valid) =
+        check_chunk_state_transition(runtime_adapter, &slash_appeal.chunk_state)?;
+    if is_valid {
+        Ok((block_hash, vec![chunk_producer]))
+    } else {
+        // The chunk is indeed invalid, so the slash stands and the appeal is malicious.
+        Err(ErrorKind::MaliciousChallenge.into())
     }
 }
 
@@ -392,6 +499,17 @@ pub fn validate_challenge(
         ChallengeBody::ChunkState(chunk_state) => {
             validate_chunk_state_challenge(runtime_adapter, chunk_state)
         }
+        #[cfg(feature = "protocol_feature_slashing_escrow")]
+        ChallengeBody::SlashAppeal(slash_appeal) => {
+            validate_slash_appeal(runtime_adapter, slash_appeal)
+        }
+        #[cfg(feature = "protocol_feature_double_sign_slashing")]
+        ChallengeBody::ApprovalDoubleSign(approval_double_sign) => validate_approval_double_sign(
+            runtime_adapter,
+            epoch_id,
+            last_block_hash,
+            approval_double_sign,
+        ),
     }
 }
 
@@ -1,5 +1,5 @@
 use near_chain::test_utils::setup;
-use near_chain::{Block, ChainStoreAccess, ErrorKind, Provenance};
+use near_chain::{Block, ChainStoreAccess, DoomslugThresholdMode, ErrorKind, Provenance};
 use near_logger_utils::init_test_logger;
 use near_primitives::hash::CryptoHash;
 use near_primitives::version::PROTOCOL_VERSION;
@@ -39,6 +39,7 @@ fn build_chain_with_orhpans() {
     }
     let last_block = &blocks[blocks.len() - 1];
     let block = Block::produce(
+        PROTOCOL_VERSION,
         PROTOCOL_VERSION,
         &last_block.header(),
         10,
@@ -139,6 +140,34 @@ fn build_chain_with_skips_and_forks() {
     assert_eq!(chain.get_header_by_height(5).unwrap().height(), 5);
 }
 
+/// Verifies that forks which don't include the final head are rejected once finality is backed
+/// by approvals.
+#[test]
+fn reject_forks_before_final_head() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let genesis = chain.get_block(&chain.genesis().hash().clone()).unwrap().clone();
+    let b1 = Block::empty(&genesis, &*signer);
+    let b2 = Block::empty(&b1, &*signer);
+    let b3 = Block::empty(&b2, &*signer);
+    let c4 = Block::empty_with_height(&genesis, 4, &*signer);
+    let b1_hash = *b1.hash();
+    for block in vec![b1, b2, b3] {
+        chain.process_block(&None, block, Provenance::PRODUCED, |_| {}, |_| {}, |_| {}).unwrap();
+    }
+    assert_eq!(chain.final_head().unwrap().last_block_hash, b1_hash);
+
+    chain.doomslug_threshold_mode = DoomslugThresholdMode::TwoThirds;
+    assert_eq!(
+        chain
+            .process_block(&None, c4, Provenance::NONE, |_| {}, |_| {}, |_| {})
+            .unwrap_err()
+            .kind(),
+        ErrorKind::ConflictsWithFinalBlock(*genesis.hash())
+    );
+    assert_eq!(chain.head().unwrap().height, 3);
+}
+
 /// Verifies that the block at height are updated correctly when blocks from different forks are
 /// processed, especially when certain heights are skipped
 #[test]
@@ -3,7 +3,6 @@ use std::collections::{btree_map, hash_map, BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use borsh::BorshSerialize;
 use cached::{Cached, SizedCache};
 use chrono::{DateTime, Utc};
 use log::{debug, error, warn};
@@ -21,18 +20,18 @@ use near_network::types::{
 use near_network::NetworkRequests;
 use near_pool::{PoolIteratorWrapper, TransactionPool};
 use near_primitives::block::{BlockHeader, Tip};
-use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart,
-    PartialEncodedChunkV1, PartialEncodedChunkV2, ReceiptList, ReceiptProof, ReedSolomonWrapper,
+    PartialEncodedChunkV1, PartialEncodedChunkV2, ReceiptProof, ReedSolomonWrapper,
     ShardChunkHeader, ShardProof,
 };
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
-    AccountId, Balance, BlockHeight, BlockHeightDelta, Gas, MerkleHash, ShardId, StateRoot,
+    AccountId, Balance, BlockHeight, BlockHeightDelta, Gas, MerkleHash, Nonce, ShardId, StateRoot,
 };
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
@@ -772,6 +771,30 @@ impl ShardsManager {
         self.tx_pools.entry(shard_id).or_insert_with(TransactionPool::new).insert_transaction(tx)
     }
 
+    /// Same as `insert_transaction`, but parks the transaction if there is a gap between its
+    /// nonce and `on_chain_nonce`, the nonce of its access key on chain. With `replace_by_nonce`,
+    /// the transaction replaces a pending one with the same nonce if it attaches more gas.
+    pub fn insert_transaction_with_nonce(
+        &mut self,
+        shard_id: ShardId,
+        tx: SignedTransaction,
+        on_chain_nonce: Nonce,
+        replace_by_nonce: bool,
+        block_height: BlockHeight,
+    ) -> bool {
+        self.tx_pools
+            .entry(shard_id)
+            .or_insert_with(TransactionPool::new)
+            .insert_transaction_with_nonce(tx, on_chain_nonce, replace_by_nonce, block_height)
+    }
+
+    /// Drops the parked transactions of all the pools which expire at the given block height.
+    pub fn expire_parked_transactions(&mut self, block_height: BlockHeight) {
+        for pool in self.tx_pools.values_mut() {
+            pool.expire_parked_transactions(block_height);
+        }
+    }
+
     pub fn remove_transactions(
         &mut self,
         shard_id: ShardId,
@@ -1182,14 +1205,9 @@ impl ShardsManager {
                 shard_id,
                 true,
             ) {
-                let ReceiptProof(shard_receipts, receipt_proof) = proof;
-                let receipt_hash =
-                    hash(&ReceiptList(shard_id, shard_receipts).try_to_vec().unwrap());
-                if !verify_path(
-                    header.outgoing_receipts_root(),
-                    &receipt_proof.proof,
-                    &receipt_hash,
-                ) {
+                if proof.1.from_shard_id != header.shard_id()
+                    || !proof.verify_against_receipt_root(header.outgoing_receipts_root())
+                {
                     byzantine_assert!(false);
                     return Err(Error::ChainError(ErrorKind::InvalidReceiptsProof.into()));
                 }
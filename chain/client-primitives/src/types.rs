@@ -9,23 +9,24 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use near_chain_configs::ProtocolConfigView;
-use near_network::types::{AccountOrPeerIdOrHash, KnownProducer};
+use near_network::types::{AccountOrPeerIdOrHash, KnownProducer, PeerTraffic};
 use near_network::PeerInfo;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
+use near_primitives::runtime::contract_stats::ContractExecutionStats;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochReference, MaybeBlockId, ShardId,
+    AccountId, BlockHeight, BlockReference, EpochReference, MaybeBlockId, Nonce, ShardId,
     TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    BlockDetailsView, BlockView, ChunkDetailsView, ChunkView, EpochValidatorDiffView,
+    EpochValidatorInfo, ExecutionOutcomeWithIdView, FinalExecutionOutcomeViewEnum, GasPriceView,
+    LightClientBlockLiteView, LightClientBlockView, QueryRequest, QueryResponse, ReceiptView,
+    SeatPriceView, StateChangesKindsView, StateChangesRequestView, StateChangesView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -210,6 +211,13 @@ impl Message for GetBlockWithMerkleTree {
     type Result = Result<(BlockView, PartialMerkleTree), GetBlockError>;
 }
 
+/// Actor message requesting a block with the producers of its chunks.
+pub struct GetBlockDetails(pub BlockReference);
+
+impl Message for GetBlockDetails {
+    type Result = Result<BlockDetailsView, GetBlockError>;
+}
+
 /// Actor message requesting a chunk by chunk hash and block hash + shard id.
 pub enum GetChunk {
     Height(BlockHeight, ShardId),
@@ -259,6 +267,13 @@ impl From<near_chain_primitives::Error> for GetChunkError {
     }
 }
 
+/// Actor message requesting a chunk with the receipts of its shard in the block which included it.
+pub struct GetChunkDetails(pub GetChunk);
+
+impl Message for GetChunkDetails {
+    type Result = Result<ChunkDetailsView, GetChunkError>;
+}
+
 /// Queries client for given path / data.
 #[derive(Clone, Debug)]
 pub struct Query {
@@ -323,6 +338,14 @@ pub enum QueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Function call was stopped: {error_message}")]
+    ViewLimitExceeded {
+        error_message: String,
+        gas_burnt: near_primitives::types::Gas,
+        logs: Vec<String>,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
     #[error("Block either has never been observed on the node or has been garbage collected: {block_reference:?}")]
@@ -345,6 +368,8 @@ pub enum StatusError {
     NodeIsSyncing,
     #[error("No blocks for {elapsed:?}")]
     NoNewBlocks { elapsed: std::time::Duration },
+    #[error("Head stalled for {elapsed:?}")]
+    HeadStalled { elapsed: std::time::Duration },
     #[error("Epoch Out Of Bounds {epoch_id:?}")]
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
@@ -424,6 +449,74 @@ impl Message for GetNetworkInfo {
     type Result = Result<NetworkInfoResponse, String>;
 }
 
+/// Transaction which was dropped from the pool while preparing a chunk, because it's no longer
+/// valid, e.g. its nonce was already used by another transaction or its balance was spent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DroppedTransactionView {
+    pub transaction_hash: CryptoHash,
+    pub signer_id: AccountId,
+    pub nonce: Nonce,
+    pub shard_id: ShardId,
+    /// Height of the chunk from which the transaction was dropped.
+    pub height: BlockHeight,
+    pub reason: InvalidTxError,
+}
+
+/// Returns the transactions recently dropped by this node while producing chunks, the most
+/// recent last.
+pub struct GetDroppedTransactions {}
+
+impl Message for GetDroppedTransactions {
+    type Result = Result<Vec<DroppedTransactionView>, String>;
+}
+
+/// Times at which this node observed the stages of the lifecycle of a transaction, in
+/// milliseconds since the Unix epoch. Stages which weren't observed by the node are missing, e.g.
+/// nodes which don't track the shard of the transaction never see it applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionTraceView {
+    pub transaction_hash: CryptoHash,
+    /// Whether the transaction was forwarded by another node rather than submitted to this one.
+    pub is_forwarded: bool,
+    /// The transaction was received over RPC or from a peer.
+    pub received: Option<u64>,
+    /// The transaction was inserted into the transaction pool.
+    pub inserted_to_pool: Option<u64>,
+    /// The transaction was included into a chunk produced by this node, or into a chunk of a block
+    /// accepted by it.
+    pub included_in_chunk: Option<u64>,
+    /// The block with the chunk of the transaction was applied.
+    pub applied: Option<u64>,
+    /// The block with the chunk of the transaction became final.
+    pub finalized: Option<u64>,
+    pub shard_id: Option<ShardId>,
+    /// Height of the chunk which includes the transaction.
+    pub height: Option<BlockHeight>,
+    /// Block in which the chunk of the transaction was applied.
+    pub block_hash: Option<CryptoHash>,
+    /// Receipts the transaction was converted into, to follow its execution further.
+    pub receipt_ids: Vec<CryptoHash>,
+}
+
+/// Returns the lifecycle trace of a transaction recently received by this node.
+pub struct GetTransactionTrace {
+    pub transaction_hash: CryptoHash,
+}
+
+impl Message for GetTransactionTrace {
+    type Result = Result<Option<TransactionTraceView>, String>;
+}
+
+/// Returns the execution statistics of the contract with the given code hash collected by this
+/// node.
+pub struct GetContractExecutionStats {
+    pub code_hash: CryptoHash,
+}
+
+impl Message for GetContractExecutionStats {
+    type Result = Result<Option<ContractExecutionStats>, String>;
+}
+
 pub struct GetGasPrice {
     pub block_id: MaybeBlockId,
 }
@@ -469,6 +562,8 @@ pub struct NetworkInfoResponse {
     pub received_bytes_per_sec: u64,
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
+    /// Messages sent to and received from each active peer, per message type.
+    pub peer_traffic: Vec<PeerTraffic>,
     #[cfg(feature = "metric_recorder")]
     pub metric_recorder: MetricRecorder,
 }
@@ -544,6 +639,15 @@ impl From<near_chain_primitives::Error> for GetValidatorInfoError {
     }
 }
 
+/// Changes of the validator set of the epoch relative to the previous epoch.
+pub struct GetValidatorDiff {
+    pub epoch_reference: EpochReference,
+}
+
+impl Message for GetValidatorDiff {
+    type Result = Result<EpochValidatorDiffView, GetValidatorInfoError>;
+}
+
 pub struct GetValidatorOrdered {
     pub block_id: MaybeBlockId,
 }
@@ -552,6 +656,15 @@ impl Message for GetValidatorOrdered {
     type Result = Result<Vec<ValidatorStakeView>, GetValidatorInfoError>;
 }
 
+/// Seat prices of the epoch of the block and the following epochs.
+pub struct GetSeatPrice {
+    pub block_id: MaybeBlockId,
+}
+
+impl Message for GetSeatPrice {
+    type Result = Result<SeatPriceView, GetValidatorInfoError>;
+}
+
 pub struct GetStateChanges {
     pub block_hash: CryptoHash,
     pub state_changes_request: StateChangesRequestView,
@@ -607,6 +720,15 @@ impl Message for GetStateChangesWithCauseInBlock {
     type Result = Result<StateChangesView, GetStateChangesError>;
 }
 
+pub struct GetFilteredStateChanges {
+    pub block_hash: CryptoHash,
+    pub filter_id: String,
+}
+
+impl Message for GetFilteredStateChanges {
+    type Result = Result<StateChangesView, GetStateChangesError>;
+}
+
 pub struct GetExecutionOutcome {
     pub id: TransactionOrReceiptId,
 }
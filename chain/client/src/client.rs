@@ -1,7 +1,7 @@
 //! Client is responsible for tracking the chain, chunks, and producing them when needed.
 //! This client works completely synchronously and must be operated by some async actor outside.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::iter;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -13,6 +13,8 @@ use log::{debug, error, info, warn};
 use near_chain::chain::TX_ROUTING_HEIGHT_HORIZON;
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, LatestKnown};
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_chain::validate::validate_challenge;
 use near_chain::{
     BlockStatus, Chain, ChainGenesis, ChainStoreAccess, Doomslug, DoomslugThresholdMode, ErrorKind,
     Provenance, RuntimeAdapter,
@@ -25,7 +27,12 @@ use near_network::{
     EPOCH_SYNC_PEER_TIMEOUT_MS, EPOCH_SYNC_REQUEST_TIMEOUT_MS,
 };
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::challenge::ApprovalDoubleSign;
 use near_primitives::challenge::{Challenge, ChallengeBody};
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::checked_feature;
+use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
@@ -38,21 +45,33 @@ use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::chunk_extra::ChunkExtra;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_primitives::types::NumBlocks;
-use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, ShardId};
+use near_primitives::types::{AccountId, ApprovalStake, Balance, BlockHeight, EpochId, ShardId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{to_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 
 use crate::metrics;
+use crate::peer_performance::PeerPerformance;
 use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
+use crate::tx_tracing::{current_time_ms, TransactionTracer};
+use crate::upgrade_readiness::check_upgrade_readiness;
 use crate::SyncStatus;
-use near_client_primitives::types::{Error, ShardSyncDownload};
+use near_client_primitives::types::{
+    DroppedTransactionView, Error, ShardSyncDownload, TransactionTraceView,
+};
 use near_primitives::block_header::ApprovalType;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 
 use near_network::types::PartialEncodedChunkForwardMsg;
 
 const NUM_REBROADCAST_BLOCKS: usize = 30;
+/// Number of the most recently dropped transactions kept for diagnostics.
+const NUM_DROPPED_TRANSACTIONS: usize = 1000;
+/// Number of the most recently received transactions the lifecycle of which is traced.
+const NUM_TRACED_TRANSACTIONS: usize = 10000;
+/// Number of the most recently received approvals kept to detect double signing.
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+const NUM_SEEN_APPROVALS: usize = 10000;
 
 pub struct Client {
     /// Adversarial controls
@@ -73,6 +92,10 @@ pub struct Client {
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
     /// Approvals for which we do not have the block yet
     pending_approvals: SizedCache<ApprovalInner, HashMap<AccountId, (Approval, ApprovalType)>>,
+    /// Approvals received from peers by account and target height, to detect validators that
+    /// approve two different blocks at the same height.
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    seen_approvals: SizedCache<(AccountId, BlockHeight), Approval>,
     /// A mapping from a block for which a state sync is underway for the next epoch, and the object
     /// storing the current status of the state sync
     pub catchup_state_syncs: HashMap<CryptoHash, (StateSync, HashMap<u64, ShardSyncDownload>)>,
@@ -82,6 +105,8 @@ pub struct Client {
     pub header_sync: HeaderSync,
     /// Keeps track of syncing block.
     pub block_sync: BlockSync,
+    /// Latency and success rate of the peers answering the block and header requests.
+    pub peer_performance: PeerPerformance,
     /// Keeps track of syncing state.
     pub state_sync: StateSync,
     /// List of currently accumulated challenges.
@@ -93,6 +118,12 @@ pub struct Client {
     /// Last time the head was updated, or our head was rebroadcasted. Used to re-broadcast the head
     /// again to prevent network from stalling if a large percentage of the network missed a block
     last_time_head_progress_made: Instant,
+    /// Failed self-checks which keep us from voting for our latest protocol version.
+    upgrade_readiness_failures: Vec<String>,
+    /// Transactions recently dropped from the pool while preparing chunks, the most recent last.
+    dropped_transactions: VecDeque<DroppedTransactionView>,
+    /// Lifecycle traces of the transactions recently received by the node.
+    tx_tracer: TransactionTracer,
 }
 
 impl Client {
@@ -109,7 +140,9 @@ impl Client {
         } else {
             DoomslugThresholdMode::NoApprovals
         };
-        let chain = Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        let mut chain =
+            Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        chain.header_only = config.header_only;
         let shards_mgr = ShardsManager::new(
             validator_signer.as_ref().map(|x| x.validator_id().clone()),
             runtime_adapter.clone(),
@@ -156,7 +189,7 @@ impl Client {
             doomslug_threshold_mode,
         );
 
-        Ok(Self {
+        let mut client = Self {
             #[cfg(feature = "adversarial")]
             adv_produce_blocks: false,
             #[cfg(feature = "adversarial")]
@@ -170,16 +203,34 @@ impl Client {
             network_adapter,
             validator_signer,
             pending_approvals: SizedCache::with_size(num_block_producer_seats),
+            #[cfg(feature = "protocol_feature_double_sign_slashing")]
+            seen_approvals: SizedCache::with_size(NUM_SEEN_APPROVALS),
             catchup_state_syncs: HashMap::new(),
             epoch_sync,
             header_sync,
             block_sync,
+            peer_performance: PeerPerformance::default(),
             state_sync,
             challenges: Default::default(),
             rs: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: SizedCache::with_size(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: Instant::now(),
-        })
+            upgrade_readiness_failures: vec![],
+            dropped_transactions: VecDeque::with_capacity(NUM_DROPPED_TRANSACTIONS),
+            tx_tracer: TransactionTracer::new(NUM_TRACED_TRANSACTIONS),
+        };
+        client.restore_block_approvals()?;
+        Ok(client)
+    }
+
+    /// Processes the approvals persisted before the node restarted, so the block producer doesn't
+    /// stall waiting for approvals which were already received.
+    fn restore_block_approvals(&mut self) -> Result<(), Error> {
+        for approval in self.chain.store().get_block_approvals()? {
+            // The signature was verified when the approval was received.
+            self.collect_block_approval(&approval, ApprovalType::SelfApproval);
+        }
+        Ok(())
     }
 
     // Checks if it's been at least `stall_timeout` since the last time the head was updated, or
@@ -195,6 +246,27 @@ impl Client {
         Ok(())
     }
 
+    /// Drops the progress of the header and block sync, so the sync starts over with the peers
+    /// having the highest heads. State sync in progress is kept.
+    pub fn restart_sync(&mut self) {
+        self.header_sync = HeaderSync::new(
+            self.network_adapter.clone(),
+            self.config.header_sync_initial_timeout,
+            self.config.header_sync_progress_timeout,
+            self.config.header_sync_stall_ban_timeout,
+            self.config.header_sync_expected_height_per_second,
+        );
+        self.block_sync = BlockSync::new(
+            self.network_adapter.clone(),
+            self.config.block_fetch_horizon,
+            self.config.archive,
+        );
+        match self.sync_status {
+            SyncStatus::StateSync(_, _) => {}
+            _ => self.sync_status = SyncStatus::AwaitingPeers,
+        }
+    }
+
     pub fn remove_transactions_for_block(&mut self, me: AccountId, block: &Block) {
         for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
             let shard_id = shard_id as ShardId;
@@ -315,6 +387,31 @@ impl Client {
         Ok(false)
     }
 
+    /// Protocol version to vote for in the produced block. We only vote for our latest version
+    /// if we pass the self-checks, and for the version of the epoch otherwise.
+    fn protocol_version_to_vote(
+        &mut self,
+        epoch_protocol_version: ProtocolVersion,
+    ) -> ProtocolVersion {
+        if epoch_protocol_version >= PROTOCOL_VERSION {
+            return PROTOCOL_VERSION;
+        }
+        let failures = check_upgrade_readiness(self.chain.store().store(), &self.config);
+        if failures != self.upgrade_readiness_failures {
+            if failures.is_empty() {
+                info!(target: "client", "Ready to upgrade, voting for protocol version {}", PROTOCOL_VERSION);
+            } else {
+                warn!(target: "client", "Not ready to upgrade, not voting for protocol version {}: {}", PROTOCOL_VERSION, failures.join("; "));
+            }
+            self.upgrade_readiness_failures = failures;
+        }
+        if self.upgrade_readiness_failures.is_empty() {
+            PROTOCOL_VERSION
+        } else {
+            epoch_protocol_version
+        }
+    }
+
     /// Produce block if we are block producer for given `next_height` block height.
     /// Either returns produced block (not applied) or error.
     pub fn produce_block(&mut self, next_height: BlockHeight) -> Result<Option<Block>, Error> {
@@ -472,9 +569,15 @@ impl Client {
         // TODO(2445): Enable challenges when they are working correctly.
         // let challenges = self.challenges.drain().map(|(_, challenge)| challenge).collect();
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
+        #[cfg(feature = "protocol_feature_double_sign_slashing")]
+        let challenges = self.get_double_sign_challenges(&epoch_id, &prev_hash, protocol_version);
+        #[cfg(not(feature = "protocol_feature_double_sign_slashing"))]
+        let challenges = vec![];
+        let latest_protocol_version = self.protocol_version_to_vote(protocol_version);
 
         let block = Block::produce(
             protocol_version,
+            latest_protocol_version,
             &prev_header,
             next_height,
             #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -490,7 +593,7 @@ impl Client {
             max_gas_price,
             minted_amount,
             prev_block_extra.challenges_result,
-            vec![],
+            challenges,
             &*validator_signer,
             next_bp_hash,
             block_merkle_root,
@@ -507,6 +610,35 @@ impl Client {
         Ok(Some(block))
     }
 
+    /// Returns the accumulated double sign challenges which are valid in a block on top of
+    /// `prev_hash`. Other challenges are not included until they are working correctly.
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    fn get_double_sign_challenges(
+        &self,
+        epoch_id: &EpochId,
+        prev_hash: &CryptoHash,
+        protocol_version: ProtocolVersion,
+    ) -> Vec<Challenge> {
+        if !checked_feature!(
+            "protocol_feature_double_sign_slashing",
+            DoubleSignSlashing,
+            protocol_version
+        ) {
+            return vec![];
+        }
+        // A challenge which doesn't validate in the block would make the block invalid, e.g. if
+        // the challenger is no longer a validator in the epoch of the block.
+        self.challenges
+            .values()
+            .filter(|challenge| {
+                challenge.body.is_double_sign()
+                    && validate_challenge(&*self.runtime_adapter, epoch_id, prev_hash, challenge)
+                        .is_ok()
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn produce_chunk(
         &mut self,
         prev_block_hash: CryptoHash,
@@ -556,6 +688,10 @@ impl Client {
 
         let prev_block_header = self.chain.get_block_header(&prev_block_hash)?.clone();
         let transactions = self.prepare_transactions(shard_id, &chunk_extra, &prev_block_header)?;
+        let now = current_time_ms();
+        for tx in transactions.iter() {
+            self.tx_tracer.record_included_in_chunk(&tx.get_hash(), shard_id, next_height, now);
+        }
         let num_filtered_transactions = transactions.len();
         let (tx_root, _) = merklize(&transactions);
         let ReceiptResponse(_, outgoing_receipts) = self.chain.get_outgoing_receipts_for_shard(
@@ -622,17 +758,19 @@ impl Client {
         chunk_extra: &ChunkExtra,
         prev_block_header: &BlockHeader,
     ) -> Result<Vec<SignedTransaction>, Error> {
-        let Self { chain, shards_mgr, runtime_adapter, .. } = self;
+        let Self { chain, shards_mgr, runtime_adapter, dropped_transactions, config, .. } = self;
 
         let next_epoch_id =
             runtime_adapter.get_epoch_id_from_prev_block(&prev_block_header.hash())?;
         let protocol_version = runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
+        let mut dropped = vec![];
         let transactions = if let Some(mut iter) = shards_mgr.get_pool_iterator(shard_id) {
             let transaction_validity_period = chain.transaction_validity_period;
             runtime_adapter.prepare_transactions(
                 prev_block_header.gas_price(),
                 chunk_extra.gas_limit(),
+                config.max_chunk_transactions_size,
                 shard_id,
                 *chunk_extra.state_root(),
                 // while the height of the next block that includes the chunk might not be prev_height + 1,
@@ -640,17 +778,15 @@ impl Client {
                 // invalid transactions to be included.
                 prev_block_header.height() + 1,
                 &mut iter,
-                &mut |tx: &SignedTransaction| -> bool {
-                    chain
-                        .mut_store()
-                        .check_transaction_validity_period(
-                            &prev_block_header,
-                            &tx.transaction.block_hash,
-                            transaction_validity_period,
-                        )
-                        .is_ok()
+                &mut |tx: &SignedTransaction| -> Result<(), InvalidTxError> {
+                    chain.mut_store().check_transaction_validity_period(
+                        &prev_block_header,
+                        &tx.transaction.block_hash,
+                        transaction_validity_period,
+                    )
                 },
                 protocol_version,
+                &mut dropped,
             )?
         } else {
             vec![]
@@ -658,9 +794,82 @@ impl Client {
         // Reintroduce valid transactions back to the pool. They will be removed when the chunk is
         // included into the block.
         shards_mgr.reintroduce_transactions(shard_id, &transactions);
+
+        let height = prev_block_header.height() + 1;
+        for (tx, reason) in dropped {
+            debug!(target: "client", "Dropped transaction {} of {} from chunk {} of shard {}: {}", tx.get_hash(), tx.transaction.signer_id, height, shard_id, reason);
+            if dropped_transactions.len() == NUM_DROPPED_TRANSACTIONS {
+                dropped_transactions.pop_front();
+            }
+            dropped_transactions.push_back(DroppedTransactionView {
+                transaction_hash: tx.get_hash(),
+                signer_id: tx.transaction.signer_id,
+                nonce: tx.transaction.nonce,
+                shard_id,
+                height,
+                reason,
+            });
+        }
         Ok(transactions)
     }
 
+    /// Returns the transactions recently dropped from the pool while preparing chunks.
+    pub fn dropped_transactions(&self) -> impl Iterator<Item = &DroppedTransactionView> {
+        self.dropped_transactions.iter()
+    }
+
+    /// Returns the lifecycle trace of a transaction recently received by the node.
+    pub fn transaction_trace(
+        &self,
+        transaction_hash: &CryptoHash,
+    ) -> Option<&TransactionTraceView> {
+        self.tx_tracer.get(transaction_hash)
+    }
+
+    /// Records the traced transactions of the new chunks of an applied block.
+    fn trace_applied_transactions(&mut self, block: &Block) {
+        let now = current_time_ms();
+        let Self { chain, tx_tracer, .. } = self;
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            if block.header().height() != chunk_header.height_included() {
+                continue;
+            }
+            // Chunks of the shards the node doesn't track aren't available.
+            let tx_hashes = match chain.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => {
+                    chunk.transactions().iter().map(|tx| tx.get_hash()).collect::<Vec<_>>()
+                }
+                Err(_) => continue,
+            };
+            for tx_hash in tx_hashes.iter() {
+                if !tx_tracer.is_traced(tx_hash) {
+                    continue;
+                }
+                tx_tracer.record_included_in_chunk(
+                    tx_hash,
+                    shard_id as ShardId,
+                    chunk_header.height_created(),
+                    now,
+                );
+                let receipt_ids = chain
+                    .store()
+                    .get_outcomes_by_id(tx_hash)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|outcome| &outcome.block_hash == block.hash())
+                    .map(|outcome| outcome.outcome_with_id.outcome.receipt_ids)
+                    .unwrap_or_default();
+                tx_tracer.record_applied(
+                    tx_hash,
+                    *block.hash(),
+                    block.header().height(),
+                    receipt_ids,
+                    now,
+                );
+            }
+        }
+    }
+
     pub fn send_challenges(&mut self, challenges: Arc<RwLock<Vec<ChallengeBody>>>) {
         if let Some(validator_signer) = self.validator_signer.as_ref() {
             for body in challenges.write().unwrap().drain(..) {
@@ -900,6 +1109,16 @@ impl Client {
         Ok(())
     }
 
+    /// The head the node is synced to: the header head for the nodes syncing only the headers,
+    /// which never process the blocks, the head otherwise.
+    pub fn synced_head(&self) -> Result<Tip, near_chain::Error> {
+        if self.config.header_only {
+            self.chain.header_head()
+        } else {
+            self.chain.head()
+        }
+    }
+
     /// Checks if the latest hash known to Doomslug matches the current head, and updates it if not.
     pub fn check_and_update_doomslug_tip(&mut self) -> Result<(), Error> {
         let tip = self.chain.head()?;
@@ -920,6 +1139,7 @@ impl Client {
                 tip.height,
                 last_final_height,
             );
+            self.chain.mut_store().remove_block_approvals(tip.height)?;
         }
 
         Ok(())
@@ -944,6 +1164,51 @@ impl Client {
         Ok(())
     }
 
+    /// Checks at the last block of an epoch that the total supply in the block header equals the
+    /// tokens held by the states of the shards, plus the receipts produced by the block and the
+    /// tokens burnt by its chunks, which are accounted in the next block. Mismatches are logged.
+    /// The check is skipped if some chunk of the block is missing or some shard isn't tracked.
+    fn audit_total_supply(&mut self, block: &Block) -> Result<(), Error> {
+        let block_hash = *block.hash();
+        if !self.runtime_adapter.is_next_block_epoch_start(&block_hash)? {
+            return Ok(());
+        }
+        let height = block.header().height();
+        if block.chunks().iter().any(|chunk| chunk.height_included() != height) {
+            debug!(target: "client", "Skipping the total supply audit at {}: missing chunks", block_hash);
+            return Ok(());
+        }
+        let me = self.validator_signer.as_ref().map(|vs| vs.validator_id().clone());
+        let prev_hash = *block.header().prev_hash();
+        let epoch_id = block.header().epoch_id().clone();
+        let mut total_supply: Balance = 0;
+        for shard_id in 0..self.runtime_adapter.num_shards() {
+            if !self.runtime_adapter.cares_about_shard(me.as_ref(), &prev_hash, shard_id, true) {
+                debug!(target: "client", "Skipping the total supply audit at {}: shard {} isn't tracked", block_hash, shard_id);
+                return Ok(());
+            }
+            let chunk_extra = self.chain.get_chunk_extra(&block_hash, shard_id)?.clone();
+            let receipts =
+                self.chain.mut_store().get_outgoing_receipts(&block_hash, shard_id)?.clone();
+            let shard_supply = self.runtime_adapter.compute_total_supply(
+                &epoch_id,
+                shard_id,
+                chunk_extra.state_root(),
+                &receipts,
+            )?;
+            total_supply = total_supply
+                .checked_add(shard_supply)
+                .and_then(|supply| supply.checked_add(chunk_extra.balance_burnt()))
+                .ok_or_else(|| Error::Other("Total supply overflow".to_string()))?;
+        }
+        if total_supply != block.header().total_supply() {
+            error!(target: "client", "Total supply mismatch at {} (height {}): header has {}, shard states have {}", block_hash, height, block.header().total_supply(), total_supply);
+        } else {
+            info!(target: "client", "Total supply audit passed at {} (height {}): {}", block_hash, height, total_supply);
+        }
+        Ok(())
+    }
+
     /// Gets called when block got accepted.
     /// Send updates over network, update tx pool and notify ourselves if it's time to produce next block.
     /// Blocks are passed in no particular order.
@@ -982,6 +1247,24 @@ impl Client {
             }
         }
 
+        if !self.config.state_changes_filters.is_empty() {
+            if let Err(err) = self
+                .chain
+                .store()
+                .save_filtered_state_changes(&block_hash, &self.config.state_changes_filters)
+            {
+                error!(target: "client", "Failed to save filtered state changes of {}: {}", block_hash, err);
+            }
+        }
+
+        self.trace_applied_transactions(&block);
+
+        if self.config.audit_total_supply {
+            if let Err(err) = self.audit_total_supply(&block) {
+                error!(target: "client", "Failed to audit the total supply at {}: {}", block_hash, err);
+            }
+        }
+
         if status.is_new_head() {
             self.shards_mgr.update_largest_seen_height(block.header().height());
             let last_final_block = block.header().last_final_block();
@@ -991,6 +1274,18 @@ impl Client {
                 self.chain.get_block_header(last_final_block).map_or(0, |header| header.height())
             };
             self.chain.blocks_with_missing_chunks.prune_blocks_below_height(last_finalized_height);
+            let Self { chain, tx_tracer, .. } = self;
+            tx_tracer.record_final(
+                last_finalized_height,
+                |block_hash| match chain.get_block_header(block_hash) {
+                    Ok(header) => {
+                        let header = header.clone();
+                        chain.is_on_current_chain(&header).is_ok()
+                    }
+                    Err(_) => false,
+                },
+                current_time_ms(),
+            );
             if !self.config.archive {
                 let timer = near_metrics::start_timer(&metrics::GC_TIME);
                 if let Err(err) = self
@@ -1001,6 +1296,12 @@ impl Client {
                     debug_assert!(false);
                 };
                 near_metrics::stop_timer(timer);
+            } else if self.chain.store().store().has_cold_storage() {
+                let timer = near_metrics::start_timer(&metrics::COLD_MIGRATION_TIME);
+                if let Err(err) = self.chain.migrate_to_cold(self.config.gc_blocks_limit) {
+                    error!(target: "client", "Can't move old data to cold storage, {:?}", err);
+                };
+                near_metrics::stop_timer(timer);
             }
 
             if self.runtime_adapter.is_next_block_epoch_start(block.hash()).unwrap_or(false) {
@@ -1083,6 +1384,7 @@ impl Client {
                     }
                 }
             };
+            self.shards_mgr.expire_parked_transactions(block.header().height());
 
             if provenance != Provenance::SYNC && !self.sync_status.is_syncing() {
                 // Produce new chunks
@@ -1304,6 +1606,8 @@ impl Client {
                 Ok(true) => {}
                 _ => return,
             }
+            #[cfg(feature = "protocol_feature_double_sign_slashing")]
+            self.check_approval_double_sign(approval, &next_block_epoch_id);
         }
 
         let is_block_producer =
@@ -1340,9 +1644,43 @@ impl Client {
                 }
             };
 
+        if let Err(err) = self.chain.mut_store().save_block_approval(approval) {
+            error!(target: "client", "Failed to persist block approval: {}", err);
+        }
         self.doomslug.on_approval_message(Instant::now(), &approval, &block_producer_stakes);
     }
 
+    /// Remembers the approval of a validator for its target height. If the validator already
+    /// approved something else at that height, sends out a challenge with both approvals.
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    fn check_approval_double_sign(&mut self, approval: &Approval, epoch_id: &EpochId) {
+        let protocol_version =
+            unwrap_or_return!(self.runtime_adapter.get_epoch_protocol_version(epoch_id));
+        if !checked_feature!(
+            "protocol_feature_double_sign_slashing",
+            DoubleSignSlashing,
+            protocol_version
+        ) {
+            return;
+        }
+        let key = (approval.account_id.clone(), approval.target_height);
+        match self.seen_approvals.cache_get(&key).cloned() {
+            Some(seen_approval) => {
+                if seen_approval.inner != approval.inner {
+                    warn!(target: "client", "Validator {} approved two different blocks at height {}", approval.account_id, approval.target_height);
+                    let body = ChallengeBody::ApprovalDoubleSign(ApprovalDoubleSign {
+                        left_approval: seen_approval,
+                        right_approval: approval.clone(),
+                    });
+                    self.send_challenges(Arc::new(RwLock::new(vec![body])));
+                }
+            }
+            None => {
+                self.seen_approvals.cache_set(key, approval.clone());
+            }
+        }
+    }
+
     /// Forwards given transaction to upcoming validators.
     fn forward_tx(&self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
         let shard_id = self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id);
@@ -1391,6 +1729,9 @@ impl Client {
         is_forwarded: bool,
         check_only: bool,
     ) -> NetworkClientResponses {
+        if !check_only {
+            self.tx_tracer.record_received(tx.get_hash(), is_forwarded, current_time_ms());
+        }
         unwrap_or_return!(self.process_tx_internal(&tx, is_forwarded, check_only), {
             let me = self.validator_signer.as_ref().map(|vs| vs.validator_id());
             warn!(target: "client", "I'm: {:?} Dropping tx: {:?}", me, tx);
@@ -1451,6 +1792,14 @@ impl Client {
             debug!(target: "client", "Invalid tx: expired or from a different fork -- {:?}", tx);
             return Ok(NetworkClientResponses::InvalidTx(e));
         }
+        // A transaction which doesn't fit into a chunk would stay in the pool forever.
+        let limit = self.config.max_chunk_transactions_size;
+        if tx.get_size() > limit {
+            debug!(target: "client", "Invalid tx: too large to fit into a chunk -- {:?}", tx);
+            return Ok(NetworkClientResponses::InvalidTx(
+                InvalidTxError::TransactionSizeExceeded { size: tx.get_size(), limit },
+            ));
+        }
         let gas_price = cur_block_header.gas_price();
         let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
 
@@ -1489,8 +1838,28 @@ impl Client {
                 .expect("no storage errors")
             {
                 debug!(target: "client", "Invalid tx: {:?}", err);
-                Ok(NetworkClientResponses::InvalidTx(err))
-            } else if check_only {
+                return Ok(NetworkClientResponses::InvalidTx(err));
+            }
+            let on_chain_nonce = self.runtime_adapter.get_access_key_nonce(
+                shard_id,
+                state_root,
+                &tx.transaction.signer_id,
+                &tx.transaction.public_key,
+            )?;
+            // Transactions with a nonce far ahead of the access key would only take room in the
+            // pool, since all the nonces in between have to be used first.
+            let nonce_window = self.config.tx_nonce_window;
+            if let Some(on_chain_nonce) = on_chain_nonce {
+                let upper_bound = on_chain_nonce.saturating_add(nonce_window);
+                if nonce_window > 0 && tx.transaction.nonce > upper_bound {
+                    debug!(target: "client", "Invalid tx: nonce too far ahead -- {:?}", tx);
+                    return Ok(NetworkClientResponses::InvalidTx(InvalidTxError::NonceTooLarge {
+                        tx_nonce: tx.transaction.nonce,
+                        upper_bound,
+                    }));
+                }
+            }
+            if check_only {
                 Ok(NetworkClientResponses::ValidTx)
             } else {
                 let active_validator = self.active_validator(shard_id)?;
@@ -1503,7 +1872,18 @@ impl Client {
                     shard_id,
                     is_forwarded
                 );
-                self.shards_mgr.insert_transaction(shard_id, tx.clone());
+                // Transactions with a nonce gap are parked in the pool until the gap closes.
+                match on_chain_nonce {
+                    Some(on_chain_nonce) => self.shards_mgr.insert_transaction_with_nonce(
+                        shard_id,
+                        tx.clone(),
+                        on_chain_nonce,
+                        self.config.tx_replace_by_nonce,
+                        head.height,
+                    ),
+                    None => self.shards_mgr.insert_transaction(shard_id, tx.clone()),
+                };
+                self.tx_tracer.record_inserted_to_pool(&tx.get_hash(), current_time_ms());
 
                 // Active validator:
                 //   possibly forward to next epoch validators
@@ -1636,8 +2016,13 @@ impl Client {
     }
 
     /// When accepting challenge, we verify that it's valid given signature with current validators.
-    pub fn process_challenge(&mut self, _challenge: Challenge) -> Result<(), Error> {
-        // TODO(2445): Enable challenges when they are working correctly.
+    pub fn process_challenge(&mut self, challenge: Challenge) -> Result<(), Error> {
+        // Double sign challenges only slash and don't invalidate any blocks.
+        if challenge.body.is_double_sign() {
+            #[cfg(feature = "protocol_feature_double_sign_slashing")]
+            return self.process_double_sign_challenge(challenge);
+        }
+        // TODO(2445): Enable other challenges when they are working correctly.
         //        if self.challenges.contains_key(&challenge.hash) {
         //            return Ok(());
         //        }
@@ -1661,6 +2046,40 @@ impl Client {
         //        }
         Ok(())
     }
+
+    /// Keeps a valid double sign challenge to include it into the produced blocks and relays it
+    /// to the peers, so that it reaches the block producers.
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    fn process_double_sign_challenge(&mut self, challenge: Challenge) -> Result<(), Error> {
+        if self.challenges.contains_key(&challenge.hash) {
+            return Ok(());
+        }
+        let head = self.chain.head()?;
+        let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&head.epoch_id)?;
+        if !checked_feature!(
+            "protocol_feature_double_sign_slashing",
+            DoubleSignSlashing,
+            protocol_version
+        ) {
+            return Ok(());
+        }
+        debug!(target: "client", "Received double sign challenge: {:?}", challenge);
+        match validate_challenge(
+            &*self.runtime_adapter,
+            &head.epoch_id,
+            &head.last_block_hash,
+            &challenge,
+        ) {
+            Ok(_) => {
+                self.challenges.insert(challenge.hash, challenge.clone());
+                self.network_adapter.do_send(NetworkRequests::Challenge(challenge));
+            }
+            Err(err) => {
+                debug!(target: "client", "Invalid challenge {}: {}", challenge.hash, err);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -27,6 +27,7 @@ use near_chain_configs::GenesisConfig;
 use near_crypto::Signature;
 #[cfg(feature = "metric_recorder")]
 use near_network::recorder::MetricRecorder;
+use near_network::reputation::PeerMisbehavior;
 #[cfg(feature = "adversarial")]
 use near_network::types::NetworkAdversarialMessage;
 use near_network::types::{NetworkInfo, ReasonForBan};
@@ -51,13 +52,16 @@ use near_telemetry::TelemetryActor;
 
 use crate::client::Client;
 use crate::info::{InfoHelper, ValidatorInfoHelper};
+use crate::peer_performance::PeerRequest;
+use crate::stall_watchdog::HeadStallWatchdog;
 use crate::sync::{highest_height_peer, StateSync, StateSyncResult};
 #[cfg(feature = "adversarial")]
 use crate::AdversarialControls;
 use crate::StatusResponse;
 use near_client_primitives::types::{
-    Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusError, StatusSyncInfo, SyncStatus,
+    DroppedTransactionView, Error, GetDroppedTransactions, GetNetworkInfo, GetTransactionTrace,
+    NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status, StatusError, StatusSyncInfo,
+    SyncStatus, TransactionTraceView,
 };
 use near_primitives::block_header::ApprovalType;
 
@@ -68,6 +72,8 @@ const BLOCK_HORIZON: u64 = 500;
 /// `max_block_production_time` times this multiplier is how long we wait before rebroadcasting
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
+/// How often the head stall watchdog checks the progress of the heads.
+const HEAD_STALL_CHECK_PERIOD: Duration = Duration::from_secs(1);
 
 pub struct ClientActor {
     /// Adversarial controls
@@ -91,6 +97,8 @@ pub struct ClientActor {
     doomslug_timer_next_attempt: DateTime<Utc>,
     chunk_request_retry_next_attempt: DateTime<Utc>,
     sync_started: bool,
+    /// Detects when the heads stop advancing.
+    stall_watchdog: HeadStallWatchdog,
 }
 
 /// Blocks the program until given genesis time arrives.
@@ -130,6 +138,7 @@ impl ClientActor {
             info!(target: "client", "Starting validator node: {}", vs.validator_id());
         }
         let info_helper = InfoHelper::new(telemetry_actor, &config, validator_signer.clone());
+        let stall_watchdog = HeadStallWatchdog::new(config.head_stall_timeout, Instant::now());
         let client = Client::new(
             config,
             chain_genesis,
@@ -154,6 +163,7 @@ impl ClientActor {
                 received_bytes_per_sec: 0,
                 sent_bytes_per_sec: 0,
                 known_producers: vec![],
+                peer_traffic: vec![],
                 #[cfg(feature = "metric_recorder")]
                 metric_recorder: MetricRecorder::default(),
                 peer_counter: 0,
@@ -165,6 +175,7 @@ impl ClientActor {
             doomslug_timer_next_attempt: now,
             chunk_request_retry_next_attempt: now,
             sync_started: false,
+            stall_watchdog,
         })
     }
 }
@@ -189,6 +200,9 @@ impl Actor for ClientActor {
 
         // Start periodic logging of current state of the client.
         self.log_summary(ctx);
+
+        // Start watching the progress of the heads.
+        self.check_head_stall(ctx);
     }
 }
 
@@ -315,6 +329,21 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 self.client.process_tx(transaction, is_forwarded, check_only)
             }
             NetworkClientMessages::Block(block, peer_id, was_requested) => {
+                if was_requested {
+                    self.client.peer_performance.response_received(
+                        &peer_id,
+                        PeerRequest::Block(*block.hash()),
+                        Instant::now(),
+                    );
+                }
+                if self.client.config.header_only {
+                    // Only the header of the block is synced, its chunks are never requested.
+                    return if self.receive_headers(vec![block.header().clone()], peer_id) {
+                        NetworkClientResponses::NoResponse
+                    } else {
+                        NetworkClientResponses::Ban { ban_reason: ReasonForBan::BadBlockHeader }
+                    };
+                }
                 let blocks_at_height = self
                     .client
                     .chain
@@ -339,6 +368,16 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     self.receive_block(block, peer_id, was_requested);
                     NetworkClientResponses::NoResponse
                 } else {
+                    // Blocks below the final head can't be useful, announcing them is a waste
+                    // of bandwidth.
+                    if let Ok(final_head) = self.client.chain.final_head() {
+                        if block.header().height() < final_head.height {
+                            self.network_adapter.do_send(NetworkRequests::ReportPeer {
+                                peer_id: peer_id.clone(),
+                                misbehavior: PeerMisbehavior::StaleBlockAnnouncement,
+                            });
+                        }
+                    }
                     match self
                         .client
                         .runtime_adapter
@@ -357,6 +396,11 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 }
             }
             NetworkClientMessages::BlockHeaders(headers, peer_id) => {
+                self.client.peer_performance.response_received(
+                    &peer_id,
+                    PeerRequest::Headers,
+                    Instant::now(),
+                );
                 if self.receive_headers(headers, peer_id) {
                     NetworkClientResponses::NoResponse
                 } else {
@@ -487,12 +531,18 @@ impl Handler<NetworkClientMessages> for ClientActor {
 
                 NetworkClientResponses::NoResponse
             }
-            NetworkClientMessages::EpochSyncResponse(_peer_id, _response) => {
-                // TODO #3488
+            NetworkClientMessages::EpochSyncResponse(peer_id, response) => {
+                self.client.epoch_sync.process_response(peer_id, response);
                 NetworkClientResponses::NoResponse
             }
-            NetworkClientMessages::EpochSyncFinalizationResponse(_peer_id, _response) => {
-                // TODO #3488
+            NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response) => {
+                if let Err(err) = self.client.epoch_sync.process_finalization_response(
+                    &mut self.client.chain,
+                    peer_id,
+                    response,
+                ) {
+                    error!(target: "sync", "Epoch sync: failed to apply the finalization: {}", err);
+                }
                 NetworkClientResponses::NoResponse
             }
             NetworkClientMessages::PartialEncodedChunkRequest(part_request_msg, route_back) => {
@@ -503,6 +553,13 @@ impl Handler<NetworkClientMessages> for ClientActor {
                 );
                 NetworkClientResponses::NoResponse
             }
+            NetworkClientMessages::PartialEncodedChunkResponse(_)
+            | NetworkClientMessages::PartialEncodedChunk(_)
+            | NetworkClientMessages::PartialEncodedChunkForward(_)
+                if self.client.config.header_only =>
+            {
+                NetworkClientResponses::NoResponse
+            }
             NetworkClientMessages::PartialEncodedChunkResponse(response) => {
                 if let Ok(accepted_blocks) =
                     self.client.process_partial_encoded_chunk_response(response)
@@ -556,7 +613,7 @@ impl Handler<Status> for ClientActor {
         let _d = DelayDetector::new("client status".to_string().into());
         self.check_triggers(ctx);
 
-        let head = self.client.chain.head()?;
+        let head = self.client.synced_head()?;
         let head_header = self.client.chain.get_block_header(&head.last_block_hash)?;
         let latest_block_time = head_header.raw_timestamp();
         let latest_state_root = head_header.prev_state_root().clone().into();
@@ -578,6 +635,10 @@ impl Handler<Status> for ClientActor {
             if self.client.sync_status.is_syncing() {
                 return Err(StatusError::NodeIsSyncing);
             }
+
+            if let Some(elapsed) = self.stall_watchdog.stalled_for(Instant::now()) {
+                return Err(StatusError::HeadStalled { elapsed });
+            }
         }
         let validators = self
             .client
@@ -652,12 +713,39 @@ impl Handler<GetNetworkInfo> for ClientActor {
             sent_bytes_per_sec: self.network_info.sent_bytes_per_sec,
             received_bytes_per_sec: self.network_info.received_bytes_per_sec,
             known_producers: self.network_info.known_producers.clone(),
+            peer_traffic: self.network_info.peer_traffic.clone(),
             #[cfg(feature = "metric_recorder")]
             metric_recorder: self.network_info.metric_recorder.clone(),
         })
     }
 }
 
+impl Handler<GetDroppedTransactions> for ClientActor {
+    type Result = Result<Vec<DroppedTransactionView>, String>;
+
+    #[perf]
+    fn handle(&mut self, _msg: GetDroppedTransactions, ctx: &mut Context<Self>) -> Self::Result {
+        #[cfg(feature = "delay_detector")]
+        let _d = DelayDetector::new("client get dropped transactions".into());
+        self.check_triggers(ctx);
+
+        Ok(self.client.dropped_transactions().cloned().collect())
+    }
+}
+
+impl Handler<GetTransactionTrace> for ClientActor {
+    type Result = Result<Option<TransactionTraceView>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetTransactionTrace, ctx: &mut Context<Self>) -> Self::Result {
+        #[cfg(feature = "delay_detector")]
+        let _d = DelayDetector::new("client get transaction trace".into());
+        self.check_triggers(ctx);
+
+        Ok(self.client.transaction_trace(&msg.transaction_hash).cloned())
+    }
+}
+
 impl ClientActor {
     fn sign_announce_account(&self, epoch_id: &EpochId) -> Result<Signature, ()> {
         if let Some(validator_signer) = self.client.validator_signer.as_ref() {
@@ -1013,6 +1101,7 @@ impl ClientActor {
             Ok(_) => {}
             Err(ref err) if err.is_bad_data() => {
                 warn!(target: "client", "receive bad block: {}", err);
+                self.stall_watchdog.record_error(format!("receive bad block: {}", err));
             }
             Err(ref err) if err.is_error() => {
                 if let near_chain::ErrorKind::DBNotFoundErr(msg) = err.kind() {
@@ -1025,12 +1114,25 @@ impl ClientActor {
                 } else {
                     error!(target: "client", "Error on receival of block: {}", err);
                 }
+                self.stall_watchdog.record_error(format!("Error on receival of block: {}", err));
             }
             Err(e) => match e.kind() {
                 near_chain::ErrorKind::Orphan => {
-                    if !self.client.chain.is_orphan(&prev_hash) {
-                        self.request_block_by_hash(prev_hash, peer_id)
-                    }
+                    // The parent may be an orphan itself, whose ancestors the peer which sent it
+                    // didn't deliver, so ask the peer which announced this block for the first
+                    // block missing in the chain of orphans, unless it was demoted for not
+                    // answering the requests.
+                    let missing_hash = self.client.chain.orphan_missing_ancestor(&prev_hash);
+                    let now = Instant::now();
+                    let peer_performance = &mut self.client.peer_performance;
+                    let peer_id = if peer_performance.is_demoted(&peer_id, now) {
+                        peer_performance
+                            .best_peer(&self.network_info.highest_height_peers, now)
+                            .map_or(peer_id, |peer| peer.peer_info.id)
+                    } else {
+                        peer_id
+                    };
+                    self.request_block_by_hash(missing_hash, peer_id)
                 }
                 near_chain::ErrorKind::ChunksMissing(missing_chunks) => {
                     debug!(
@@ -1086,6 +1188,11 @@ impl ClientActor {
     fn request_block_by_hash(&mut self, hash: CryptoHash, peer_id: PeerId) {
         match self.client.chain.block_exists(&hash) {
             Ok(false) => {
+                self.client.peer_performance.request_sent(
+                    &peer_id,
+                    PeerRequest::Block(hash),
+                    Instant::now(),
+                );
                 self.network_adapter.do_send(NetworkRequests::BlockRequest { hash, peer_id });
             }
             Ok(true) => {
@@ -1100,7 +1207,7 @@ impl ClientActor {
     /// Check whether need to (continue) sync.
     /// Also return higher height with known peers at that height.
     fn syncing_info(&self) -> Result<(bool, u64), near_chain::Error> {
-        let head = self.client.chain.head()?;
+        let head = self.client.synced_head()?;
         let mut is_syncing = self.client.sync_status.is_syncing();
 
         let full_peer_info = if let Some(full_peer_info) =
@@ -1258,6 +1365,7 @@ impl ClientActor {
             Ok(v) => v,
             Err(err) => {
                 error!(target: "sync", "Sync: Unexpected error: {}", err);
+                self.stall_watchdog.record_error(format!("Sync: Unexpected error: {}", err));
 
                 near_performance_metrics::actix::run_later(
                     ctx,
@@ -1292,19 +1400,48 @@ impl ClientActor {
             }
             wait_period = self.client.config.sync_check_period;
         } else {
+            // Header sync only starts once epoch sync is over.
+            if self.client.config.epoch_sync_enabled
+                && !unwrap_or_run_later!(self.client.epoch_sync.run(
+                    &mut self.client.sync_status,
+                    &mut self.client.chain,
+                    &self.network_info.highest_height_peers
+                ))
+            {
+                near_performance_metrics::actix::run_later(
+                    ctx,
+                    file!(),
+                    line!(),
+                    wait_period,
+                    move |act, ctx| {
+                        act.sync(ctx);
+                    },
+                );
+                return;
+            }
+
             // Run each step of syncing separately.
             unwrap_or_run_later!(self.client.header_sync.run(
                 &mut self.client.sync_status,
                 &mut self.client.chain,
                 highest_height,
-                &self.network_info.highest_height_peers
+                &self.network_info.highest_height_peers,
+                &mut self.client.peer_performance
             ));
             // Only body / state sync if header height is close to the latest.
             let header_head = unwrap_or_run_later!(self.client.chain.header_head());
 
             // Sync state if already running sync state or if block sync is too far.
             let sync_state = match self.client.sync_status {
+                // Neither the blocks nor the state are synced by the nodes syncing the headers.
+                _ if self.client.config.header_only => false,
                 SyncStatus::StateSync(_, _) => true,
+                // The blocks before the epoch the node epoch synced to are not downloaded, so
+                // the state is synced as soon as header sync reaches the first block of the epoch.
+                _ if self.client.epoch_sync.sync_hash.is_some() => {
+                    let sync_hash = self.client.epoch_sync.sync_hash.unwrap();
+                    self.client.chain.get_block_header(&sync_hash).is_ok()
+                }
                 _ if header_head.height
                     >= highest_height
                         .saturating_sub(self.client.config.block_header_fetch_horizon) =>
@@ -1313,7 +1450,8 @@ impl ClientActor {
                         &mut self.client.sync_status,
                         &mut self.client.chain,
                         highest_height,
-                        &self.network_info.highest_height_peers
+                        &self.network_info.highest_height_peers,
+                        &mut self.client.peer_performance
                     ))
                 }
                 _ => false,
@@ -1325,7 +1463,10 @@ impl ClientActor {
                             (sync_hash.clone(), shard_sync.clone(), false)
                         }
                         _ => {
-                            let sync_hash = unwrap_or_run_later!(self.find_sync_hash());
+                            let sync_hash = match self.client.epoch_sync.sync_hash.take() {
+                                Some(sync_hash) => sync_hash,
+                                None => unwrap_or_run_later!(self.find_sync_hash()),
+                            };
                             (sync_hash, HashMap::default(), true)
                         }
                     };
@@ -1359,8 +1500,10 @@ impl ClientActor {
                     StateSyncResult::Changed(fetch_block) => {
                         self.client.sync_status = SyncStatus::StateSync(sync_hash, new_shard_sync);
                         if fetch_block {
-                            if let Some(peer_info) =
-                                highest_height_peer(&self.network_info.highest_height_peers)
+                            if let Some(peer_info) = self
+                                .client
+                                .peer_performance
+                                .best_peer(&self.network_info.highest_height_peers, Instant::now())
                             {
                                 if let Ok(header) = self.client.chain.get_block_header(&sync_hash) {
                                     for hash in
@@ -1431,6 +1574,59 @@ impl ClientActor {
         );
     }
 
+    /// Periodically checks that the head and the final head advance. If they are stalled, logs
+    /// the diagnostics, reconnects the peers with the highest heads and restarts the sync.
+    fn check_head_stall(&mut self, ctx: &mut Context<Self>) {
+        near_performance_metrics::actix::run_later(
+            ctx,
+            file!(),
+            line!(),
+            HEAD_STALL_CHECK_PERIOD,
+            move |act, ctx| {
+                #[cfg(feature = "delay_detector")]
+                let _d = DelayDetector::new("client check head stall".into());
+                let head = unwrap_or_return!(act.client.synced_head(), act.check_head_stall(ctx));
+                // The final head only advances with the processed blocks.
+                let final_head = if act.client.config.header_only {
+                    head.clone()
+                } else {
+                    unwrap_or_return!(act.client.chain.final_head(), act.check_head_stall(ctx))
+                };
+                if let Some(stalled_for) = act.stall_watchdog.check(
+                    head.height,
+                    final_head.height,
+                    act.client.sync_status.is_syncing(),
+                    Instant::now(),
+                ) {
+                    let highest_height_peers = act
+                        .network_info
+                        .highest_height_peers
+                        .iter()
+                        .map(|peer| (peer.peer_info.id.clone(), peer.chain_info.height))
+                        .collect::<Vec<_>>();
+                    warn!(
+                        target: "client",
+                        "Head stalled for {:?}: head {} at #{}, final head {} at #{}, sync status {:?}, {} active peers, highest height peers {:?}, last errors {:?}",
+                        stalled_for,
+                        head.last_block_hash,
+                        head.height,
+                        final_head.last_block_hash,
+                        final_head.height,
+                        act.client.sync_status,
+                        act.network_info.num_active_peers,
+                        highest_height_peers,
+                        act.stall_watchdog.last_errors(),
+                    );
+                    for (peer_id, _) in highest_height_peers {
+                        act.network_adapter.do_send(NetworkRequests::DisconnectPeer { peer_id });
+                    }
+                    act.client.restart_sync();
+                }
+                act.check_head_stall(ctx);
+            },
+        );
+    }
+
     /// Periodically log summary.
     fn log_summary(&self, ctx: &mut Context<Self>) {
         near_performance_metrics::actix::run_later(
@@ -1442,7 +1638,7 @@ impl ClientActor {
                 #[cfg(feature = "delay_detector")]
                 let _d = DelayDetector::new("client log summary".into());
                 let is_syncing = act.client.sync_status.is_syncing();
-                let head = unwrap_or_return!(act.client.chain.head(), act.log_summary(ctx));
+                let head = unwrap_or_return!(act.client.synced_head(), act.log_summary(ctx));
                 let validator_info = if !is_syncing {
                     let validators = unwrap_or_return!(
                         act.client.runtime_adapter.get_epoch_block_producers_ordered(
@@ -2,11 +2,14 @@
 extern crate lazy_static;
 
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetStateChangesWithCauseInBlock, GetValidatorInfo, GetValidatorOrdered,
-    Query, QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    Error, GetBlock, GetBlockDetails, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
+    GetChunk, GetChunkDetails, GetContractExecutionStats, GetDroppedTransactions,
+    GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock,
+    GetFilteredStateChanges, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock,
+    GetProtocolConfig, GetReceipt, GetSeatPrice, GetStateChanges, GetStateChangesInBlock,
+    GetStateChangesWithCauseInBlock, GetTransactionTrace, GetValidatorDiff, GetValidatorInfo,
+    GetValidatorOrdered, Query, QueryError, Status, StatusResponse, SyncStatus, TxStatus,
+    TxStatusError,
 };
 
 pub use crate::client::Client;
@@ -19,6 +22,10 @@ mod client;
 mod client_actor;
 mod info;
 mod metrics;
+pub mod peer_performance;
+mod stall_watchdog;
 pub mod sync;
 pub mod test_utils;
+mod tx_tracing;
+mod upgrade_readiness;
 mod view_client;
@@ -30,4 +30,17 @@ lazy_static! {
         try_create_int_gauge("near_memory_usage_bytes", "Amount of RAM memory usage");
     pub static ref GC_TIME: near_metrics::Result<Histogram> =
         try_create_histogram("near_gc_time", "Time taken to do garbage collection");
+    pub static ref COLD_MIGRATION_TIME: near_metrics::Result<Histogram> = try_create_histogram(
+        "near_cold_migration_time",
+        "Time taken to move old data to the cold storage"
+    );
+    pub static ref HEAD_STALLED: near_metrics::Result<IntGauge> = try_create_int_gauge(
+        "near_head_stalled",
+        "Bool to denote if the head or the final head stopped advancing"
+    );
+    pub static ref HEAD_STALL_CORRECTIVE_ACTIONS: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_head_stall_corrective_actions_total",
+            "Number of times peers were reconnected and sync restarted because the head stalled"
+        );
 }
@@ -0,0 +1,204 @@
+//! Performance of the peers answering the block and header requests of the node.
+//!
+//! The latency and the success rate of the requests sent to each peer are tracked, so the sync
+//! can spread its requests over the peers answering best instead of picking them at random. A
+//! request not answered within `RESPONSE_TIMEOUT` counts as failed, and a peer failing most of
+//! its requests is demoted: it isn't asked anymore while other peers are available, until the
+//! demotion expires and the peer gets another chance.
+//!
+//! Chunk parts are requested from the chunk producers and validators of the shard, so the peers
+//! asked for them aren't chosen by the client.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use near_network::FullPeerInfo;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+
+/// A request not answered within this time counts as failed.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Weight of the latest request in the moving averages of the latency and the success rate.
+const MOVING_AVERAGE_WEIGHT: f64 = 0.2;
+/// Number of requests sent to a peer before it can be demoted.
+const MIN_REQUESTS_TO_DEMOTE: u32 = 4;
+/// Peers answering less than this fraction of the requests are demoted.
+const MIN_SUCCESS_RATE: f64 = 0.5;
+/// How long a demoted peer isn't asked while other peers are available.
+const DEMOTION_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PeerRequest {
+    Block(CryptoHash),
+    Headers,
+}
+
+struct PeerStats {
+    /// Moving average of the response latency, in seconds.
+    latency: f64,
+    /// Moving average of the fraction of the requests answered in time.
+    success_rate: f64,
+    num_requests: u32,
+    demoted_until: Option<Instant>,
+}
+
+impl Default for PeerStats {
+    fn default() -> Self {
+        Self { latency: 0.0, success_rate: 1.0, num_requests: 0, demoted_until: None }
+    }
+}
+
+impl PeerStats {
+    fn record(&mut self, latency: Option<Duration>, now: Instant) {
+        let success = if latency.is_some() { 1.0 } else { 0.0 };
+        if self.num_requests == 0 {
+            self.success_rate = success;
+        } else {
+            self.success_rate += MOVING_AVERAGE_WEIGHT * (success - self.success_rate);
+        }
+        if let Some(latency) = latency {
+            let latency = latency.as_secs_f64();
+            if self.latency == 0.0 {
+                self.latency = latency;
+            } else {
+                self.latency += MOVING_AVERAGE_WEIGHT * (latency - self.latency);
+            }
+        }
+        self.num_requests += 1;
+        if self.num_requests >= MIN_REQUESTS_TO_DEMOTE && self.success_rate < MIN_SUCCESS_RATE {
+            self.demoted_until = Some(now + DEMOTION_DURATION);
+        }
+    }
+
+    /// Peers answering most of the requests quickly score best, untried peers score the best.
+    fn score(&self) -> f64 {
+        self.success_rate / (1.0 + self.latency)
+    }
+}
+
+#[derive(Default)]
+pub struct PeerPerformance {
+    peers: HashMap<PeerId, PeerStats>,
+    /// Requests waiting for a response, with the time they were sent.
+    pending: HashMap<(PeerId, PeerRequest), Instant>,
+}
+
+impl PeerPerformance {
+    pub fn request_sent(&mut self, peer_id: &PeerId, request: PeerRequest, now: Instant) {
+        self.expire_requests(now);
+        self.pending.insert((peer_id.clone(), request), now);
+    }
+
+    /// Records the response of a peer. Responses to requests which were not sent to the peer, or
+    /// which already timed out, are ignored.
+    pub fn response_received(&mut self, peer_id: &PeerId, request: PeerRequest, now: Instant) {
+        self.expire_requests(now);
+        if let Some(sent) = self.pending.remove(&(peer_id.clone(), request)) {
+            let latency = now.saturating_duration_since(sent);
+            self.peers.entry(peer_id.clone()).or_default().record(Some(latency), now);
+        }
+    }
+
+    /// Counts the requests pending for longer than `RESPONSE_TIMEOUT` as failed.
+    fn expire_requests(&mut self, now: Instant) {
+        let peers = &mut self.peers;
+        self.pending.retain(|(peer_id, _), sent| {
+            let expired = now.saturating_duration_since(*sent) > RESPONSE_TIMEOUT;
+            if expired {
+                peers.entry(peer_id.clone()).or_default().record(None, now);
+            }
+            !expired
+        });
+    }
+
+    /// Whether the peer is demoted. A peer whose demotion expired starts over.
+    pub fn is_demoted(&mut self, peer_id: &PeerId, now: Instant) -> bool {
+        self.expire_requests(now);
+        match self.peers.get(peer_id).and_then(|stats| stats.demoted_until) {
+            Some(demoted_until) if now < demoted_until => true,
+            Some(_) => {
+                self.peers.remove(peer_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Orders the peers best first. Demoted peers are left out, unless all the peers are demoted.
+    /// Peers scoring the same are shuffled, so the requests are spread over them.
+    pub fn rank<'a>(&mut self, peers: &'a [FullPeerInfo], now: Instant) -> Vec<&'a FullPeerInfo> {
+        let mut ranked = peers.iter().collect::<Vec<_>>();
+        ranked.shuffle(&mut thread_rng());
+        let (mut active, demoted): (Vec<_>, Vec<_>) =
+            ranked.into_iter().partition(|peer| !self.is_demoted(&peer.peer_info.id, now));
+        if active.is_empty() {
+            active = demoted;
+        }
+        let peers = &self.peers;
+        let score =
+            |peer: &FullPeerInfo| peers.get(&peer.peer_info.id).map_or(1.0, PeerStats::score);
+        active.sort_by(|a, b| score(*b).partial_cmp(&score(*a)).unwrap_or(Ordering::Equal));
+        active
+    }
+
+    /// The best peer, see `rank`.
+    pub fn best_peer(&mut self, peers: &[FullPeerInfo], now: Instant) -> Option<FullPeerInfo> {
+        self.rank(peers, now).first().map(|peer| (*peer).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_network::PeerInfo;
+
+    use super::*;
+
+    fn peer_infos(num_peers: usize) -> Vec<FullPeerInfo> {
+        (0..num_peers)
+            .map(|_| FullPeerInfo {
+                peer_info: PeerInfo::random(),
+                chain_info: Default::default(),
+                edge_info: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_by_latency() {
+        let peers = peer_infos(3);
+        let mut performance = PeerPerformance::default();
+        let now = Instant::now();
+        for (i, peer) in peers.iter().enumerate() {
+            let request = PeerRequest::Block(CryptoHash::default());
+            performance.request_sent(&peer.peer_info.id, request, now);
+            let latency = Duration::from_millis(100 * (3 - i as u64));
+            performance.response_received(&peer.peer_info.id, request, now + latency);
+        }
+        let ranked = performance.rank(&peers, now + Duration::from_secs(1));
+        assert_eq!(ranked, vec![&peers[2], &peers[1], &peers[0]]);
+    }
+
+    #[test]
+    fn test_demote_unresponsive_peer() {
+        let peers = peer_infos(2);
+        let mut performance = PeerPerformance::default();
+        let mut now = Instant::now();
+        for _ in 0..MIN_REQUESTS_TO_DEMOTE {
+            performance.request_sent(&peers[0].peer_info.id, PeerRequest::Headers, now);
+            now += RESPONSE_TIMEOUT + Duration::from_secs(1);
+        }
+        // A late response doesn't count.
+        performance.response_received(&peers[0].peer_info.id, PeerRequest::Headers, now);
+        assert!(performance.is_demoted(&peers[0].peer_info.id, now));
+        assert_eq!(performance.rank(&peers, now), vec![&peers[1]]);
+        // Demoted peers are still asked when there is no other peer.
+        assert_eq!(performance.rank(&peers[..1], now), vec![&peers[0]]);
+
+        now += DEMOTION_DURATION;
+        assert!(!performance.is_demoted(&peers[0].peer_info.id, now));
+        assert_eq!(performance.rank(&peers, now).len(), 2);
+    }
+}
@@ -0,0 +1,158 @@
+//! Watchdog of the progress of the chain head.
+//!
+//! If the head or the final head doesn't advance for `head_stall_timeout`, the client logs a
+//! diagnostic bundle and tries to recover: it drops the connections to the peers with the highest
+//! heads, which the peer manager re-establishes, and restarts the sync. The corrective actions are
+//! repeated every timeout while the stall lasts. The stall is exposed by the `near_head_stalled`
+//! metric and fails the health check until the heads advance again.
+//!
+//! The heads aren't expected to advance while the node syncs, the sync has its own timeouts.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use near_primitives::types::BlockHeight;
+
+use crate::metrics;
+
+/// Number of recent errors kept for the diagnostic bundle.
+const NUM_LAST_ERRORS: usize = 16;
+
+pub struct HeadStallWatchdog {
+    timeout: Duration,
+    head_height: BlockHeight,
+    last_head_progress: Instant,
+    final_head_height: BlockHeight,
+    last_final_head_progress: Instant,
+    /// When the corrective actions were last taken, if during the current stall.
+    last_corrective_action: Option<Instant>,
+    last_errors: VecDeque<String>,
+}
+
+impl HeadStallWatchdog {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            head_height: 0,
+            last_head_progress: now,
+            final_head_height: 0,
+            last_final_head_progress: now,
+            last_corrective_action: None,
+            last_errors: VecDeque::new(),
+        }
+    }
+
+    /// Remembers an error for the next diagnostic bundle.
+    pub fn record_error(&mut self, error: String) {
+        if self.last_errors.len() == NUM_LAST_ERRORS {
+            self.last_errors.pop_front();
+        }
+        self.last_errors.push_back(error);
+    }
+
+    pub fn last_errors(&self) -> Vec<String> {
+        self.last_errors.iter().cloned().collect()
+    }
+
+    /// Returns for how long the heads are stalled, none if they advanced within the timeout.
+    pub fn stalled_for(&self, now: Instant) -> Option<Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.last_head_progress.min(self.last_final_head_progress));
+        if elapsed > self.timeout {
+            Some(elapsed)
+        } else {
+            None
+        }
+    }
+
+    /// Records the current heads. Returns for how long the heads are stalled if the corrective
+    /// actions should be taken now.
+    pub fn check(
+        &mut self,
+        head_height: BlockHeight,
+        final_head_height: BlockHeight,
+        is_syncing: bool,
+        now: Instant,
+    ) -> Option<Duration> {
+        if head_height != self.head_height || is_syncing {
+            self.head_height = head_height;
+            self.last_head_progress = now;
+        }
+        if final_head_height != self.final_head_height || is_syncing {
+            self.final_head_height = final_head_height;
+            self.last_final_head_progress = now;
+        }
+        let stalled_for = self.stalled_for(now);
+        near_metrics::set_gauge(&metrics::HEAD_STALLED, stalled_for.is_some() as i64);
+        let stalled_for = match stalled_for {
+            Some(stalled_for) => stalled_for,
+            None => {
+                if self.last_corrective_action.take().is_some() {
+                    self.last_errors.clear();
+                }
+                return None;
+            }
+        };
+        match self.last_corrective_action {
+            Some(last_action) if now < last_action + self.timeout => None,
+            _ => {
+                self.last_corrective_action = Some(now);
+                near_metrics::inc_counter(&metrics::HEAD_STALL_CORRECTIVE_ACTIONS);
+                Some(stalled_for)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head_stall_watchdog() {
+        let timeout = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut watchdog = HeadStallWatchdog::new(timeout, start);
+        assert_eq!(watchdog.check(1, 0, false, start), None);
+
+        // The final head doesn't advance.
+        let now = start + Duration::from_secs(5);
+        assert_eq!(watchdog.check(2, 0, false, now), None);
+        let now = start + Duration::from_secs(11);
+        assert_eq!(watchdog.check(3, 0, false, now), Some(Duration::from_secs(11)));
+        assert_eq!(watchdog.stalled_for(now), Some(Duration::from_secs(11)));
+
+        // The corrective actions are repeated once per timeout.
+        let now = start + Duration::from_secs(15);
+        assert_eq!(watchdog.check(4, 0, false, now), None);
+        assert!(watchdog.stalled_for(now).is_some());
+        let now = start + Duration::from_secs(21);
+        assert_eq!(watchdog.check(5, 0, false, now), Some(Duration::from_secs(21)));
+
+        // The heads aren't expected to advance while syncing.
+        let now = start + Duration::from_secs(32);
+        assert_eq!(watchdog.check(5, 0, true, now), None);
+        assert_eq!(watchdog.stalled_for(now), None);
+        let now = start + Duration::from_secs(40);
+        assert_eq!(watchdog.check(5, 0, false, now), None);
+
+        // The stall is over once the final head advances.
+        watchdog.record_error("error".to_string());
+        let now = start + Duration::from_secs(43);
+        assert_eq!(watchdog.check(6, 0, false, now), Some(Duration::from_secs(11)));
+        let now = start + Duration::from_secs(44);
+        assert_eq!(watchdog.check(6, 4, false, now), None);
+        assert_eq!(watchdog.stalled_for(now), None);
+        assert!(watchdog.last_errors().is_empty());
+    }
+
+    #[test]
+    fn test_last_errors() {
+        let mut watchdog = HeadStallWatchdog::new(Duration::from_secs(10), Instant::now());
+        for i in 0..NUM_LAST_ERRORS + 2 {
+            watchdog.record_error(i.to_string());
+        }
+        let last_errors = watchdog.last_errors();
+        assert_eq!(last_errors.len(), NUM_LAST_ERRORS);
+        assert_eq!(last_errors[0], "2");
+    }
+}
@@ -3,6 +3,7 @@ use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{ops::Add, time::Duration as TimeDuration};
 
 use ansi_term::Color::{Purple, Yellow};
@@ -12,22 +13,28 @@ use log::{debug, error, info, warn};
 use rand::seq::{IteratorRandom, SliceRandom};
 use rand::{thread_rng, Rng};
 
-use near_chain::{Chain, RuntimeAdapter};
+use near_chain::{validate_light_client_block, Chain, RuntimeAdapter};
+use near_network::reputation::PeerMisbehavior;
 use near_network::types::{AccountOrPeerIdOrHash, NetworkResponses, ReasonForBan};
 use near_network::{FullPeerInfo, NetworkAdapter, NetworkRequests};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
-use near_primitives::syncing::get_num_state_parts;
+use near_primitives::syncing::{
+    get_num_state_parts, EpochSyncFinalizationResponse, EpochSyncResponse,
+};
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, EpochId, ShardId};
 use near_primitives::utils::to_timestamp;
+use near_primitives::views::LightClientBlockView;
 
 use cached::{Cached, SizedCache};
 use near_client_primitives::types::{
     DownloadStatus, ShardSyncDownload, ShardSyncStatus, SyncStatus,
 };
 
+use crate::peer_performance::{PeerPerformance, PeerRequest};
+
 /// Maximum number of block headers send over the network.
 pub const MAX_BLOCK_HEADERS: u64 = 512;
 
@@ -36,6 +43,10 @@ pub const MAX_BLOCK_HEADER_HASHES: usize = 20;
 
 const BLOCK_REQUEST_TIMEOUT: i64 = 2;
 
+/// Maximum number of blocks on the header chain requested at once during block sync.
+/// The requests are spread over the peers, so the blocks are downloaded in parallel.
+pub const MAX_BLOCK_REQUESTS: usize = 5;
+
 /// Maximum number of state parts to request per peer on each round when node is trying to download the state.
 pub const MAX_STATE_PART_REQUEST: u64 = 16;
 /// Number of state parts already requested stored as pending.
@@ -56,9 +67,14 @@ pub fn highest_height_peer(highest_height_peers: &Vec<FullPeerInfo>) -> Option<F
     }
 }
 
-/// Helper to keep track of the Epoch Sync
-// TODO #3488
-#[allow(dead_code)]
+/// Helper to keep track of the Epoch Sync.
+///
+/// A node starting from genesis downloads the light client block of every epoch, verifying each
+/// one with the block producers of the previous one, instead of all the block headers. Once the
+/// peers report that there are no more finished epochs, the node downloads and verifies the data
+/// the epoch manager needs to start from the current epoch, and header sync continues from the
+/// last block of the previous epoch. The blocks before the current epoch are skipped too: the
+/// state is synced at the first block of the current epoch, `sync_hash`.
 pub struct EpochSync {
     network_adapter: Arc<dyn NetworkAdapter>,
     /// Datastructure to keep track of when the last request to each peer was made.
@@ -73,6 +89,10 @@ pub struct EpochSync {
     next_epoch_id: EpochId,
     /// The block producers set to validate the light client block view for the next epoch
     next_block_producers: Vec<ValidatorStake>,
+    /// The light client block of the last epoch we are synced to
+    last_light_client_block: Option<LightClientBlockView>,
+    /// Number of epochs synced so far
+    epoch_ord: u64,
     /// The last epoch id that we have requested
     requested_epoch_id: EpochId,
     /// When and to whom was the last request made
@@ -91,9 +111,8 @@ pub struct EpochSync {
     /// Current state machine allows for only one Epoch Sync.
     pub done: bool,
 
-    pub sync_hash: CryptoHash,
-
-    received_epoch: bool,
+    /// The first block of the epoch the node was synced to, until state sync starts from it.
+    pub sync_hash: Option<CryptoHash>,
 
     is_just_started: bool,
 }
@@ -114,18 +133,201 @@ impl EpochSync {
             current_epoch_id: genesis_epoch_id.clone(),
             next_epoch_id: genesis_next_epoch_id.clone(),
             next_block_producers: first_epoch_block_producers,
+            last_light_client_block: None,
+            epoch_ord: 0,
             requested_epoch_id: genesis_epoch_id,
             last_request_time: Utc::now(),
             last_request_peer_id: None,
             request_timeout: Duration::from_std(request_timeout).unwrap(),
             peer_timeout: Duration::from_std(peer_timeout).unwrap(),
-            received_epoch: false,
             have_all_epochs: false,
             done: false,
-            sync_hash: CryptoHash::default(),
+            sync_hash: None,
             is_just_started: true,
         }
     }
+
+    /// Runs a step of epoch sync. Returns true once epoch sync is over, successfully or not, so
+    /// header sync can proceed.
+    pub fn run(
+        &mut self,
+        sync_status: &mut SyncStatus,
+        chain: &mut Chain,
+        highest_height_peers: &Vec<FullPeerInfo>,
+    ) -> Result<bool, near_chain::Error> {
+        if self.done {
+            return Ok(true);
+        }
+        if self.is_just_started {
+            self.is_just_started = false;
+            // Only a node starting from genesis can skip the headers.
+            if chain.header_head()?.height > chain.genesis().height() {
+                self.done = true;
+                return Ok(true);
+            }
+        }
+
+        let now = Utc::now();
+        if self.last_request_peer_id.is_some()
+            && now - self.last_request_time < self.request_timeout
+        {
+            return Ok(false);
+        }
+        if let Some(peer_id) = self.last_request_peer_id.take() {
+            debug!(target: "sync", "Epoch sync: request for {:?} to {} timed out", self.requested_epoch_id, peer_id);
+            self.network_adapter.do_send(NetworkRequests::ReportPeer {
+                peer_id: peer_id.clone(),
+                misbehavior: PeerMisbehavior::Timeout,
+            });
+            if self.have_all_epochs {
+                // The peer can't finalize, try the other ones.
+                self.peers_reporting_up_to_date.remove(&peer_id);
+            } else {
+                // Peers don't respond for epochs they don't know, so the peer can't help us
+                // advance either.
+                self.peers_reporting_up_to_date.insert(peer_id);
+            }
+        }
+
+        if !self.have_all_epochs
+            && !highest_height_peers.is_empty()
+            && highest_height_peers
+                .iter()
+                .all(|peer| self.peers_reporting_up_to_date.contains(&peer.peer_info.id))
+        {
+            if self.last_light_client_block.is_none() {
+                info!(target: "sync", "Epoch sync: the first epoch is not over, nothing to skip");
+                self.done = true;
+                return Ok(true);
+            }
+            info!(target: "sync", "Epoch sync: reached epoch {:?}, finalizing", self.next_epoch_id);
+            self.have_all_epochs = true;
+        }
+        if self.have_all_epochs && self.peers_reporting_up_to_date.is_empty() {
+            info!(target: "sync", "Epoch sync: no peer could finalize, falling back to header sync");
+            self.done = true;
+            return Ok(true);
+        }
+        *sync_status = SyncStatus::EpochSync { epoch_ord: self.epoch_ord };
+
+        // Finalization is requested from the peers which know the current epoch, light client
+        // blocks from the ones which may know more epochs.
+        let peer_id = highest_height_peers
+            .iter()
+            .map(|peer| &peer.peer_info.id)
+            .filter(|peer_id| {
+                self.have_all_epochs == self.peers_reporting_up_to_date.contains(peer_id)
+            })
+            .filter(|peer_id| {
+                self.peer_to_last_request_time
+                    .get(peer_id)
+                    .map_or(true, |last_request_time| now - *last_request_time >= self.peer_timeout)
+            })
+            .choose(&mut thread_rng())
+            .cloned();
+        if let Some(peer_id) = peer_id {
+            let request = if self.have_all_epochs {
+                self.requested_epoch_id = self.current_epoch_id.clone();
+                NetworkRequests::EpochSyncFinalizationRequest {
+                    epoch_id: self.requested_epoch_id.clone(),
+                    peer_id: peer_id.clone(),
+                }
+            } else {
+                self.requested_epoch_id = self.next_epoch_id.clone();
+                NetworkRequests::EpochSyncRequest {
+                    epoch_id: self.requested_epoch_id.clone(),
+                    peer_id: peer_id.clone(),
+                }
+            };
+            self.last_request_time = now;
+            self.last_request_peer_id = Some(peer_id.clone());
+            self.peer_to_last_request_time.insert(peer_id, now);
+            self.network_adapter.do_send(request);
+        }
+        Ok(false)
+    }
+
+    /// Processes the response to the request of the light client block of the next epoch.
+    pub fn process_response(&mut self, peer_id: PeerId, response: EpochSyncResponse) {
+        if self.done || self.have_all_epochs || self.last_request_peer_id.as_ref() != Some(&peer_id)
+        {
+            return;
+        }
+        self.last_request_peer_id = None;
+        let light_client_block = match response {
+            EpochSyncResponse::UpToDate => {
+                self.peers_reporting_up_to_date.insert(peer_id);
+                return;
+            }
+            EpochSyncResponse::Advance { light_client_block_view } => light_client_block_view,
+        };
+        if light_client_block.inner_lite.epoch_id != self.requested_epoch_id.0 {
+            self.ban_peer(peer_id, ReasonForBan::EpochSyncInvalidResponse);
+            return;
+        }
+        if let Err(err) =
+            validate_light_client_block(&light_client_block, &self.next_block_producers)
+        {
+            warn!(target: "sync", "Epoch sync: invalid light client block for {:?} from {}: {}", self.requested_epoch_id, peer_id, err);
+            self.ban_peer(peer_id, ReasonForBan::EpochSyncInvalidResponse);
+            return;
+        }
+
+        self.current_epoch_id = self.next_epoch_id.clone();
+        self.next_epoch_id = EpochId(light_client_block.inner_lite.next_epoch_id);
+        self.next_block_producers = light_client_block
+            .next_bps
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(ValidatorStake::from)
+            .collect();
+        self.last_light_client_block = Some(light_client_block);
+        self.epoch_ord += 1;
+        self.peers_reporting_up_to_date.clear();
+        debug!(target: "sync", "Epoch sync: synced to epoch {:?}", self.current_epoch_id);
+    }
+
+    /// Processes the response to the finalization request. Once it is validated, the chain is
+    /// initialized from the current epoch and epoch sync is done.
+    pub fn process_finalization_response(
+        &mut self,
+        chain: &mut Chain,
+        peer_id: PeerId,
+        response: EpochSyncFinalizationResponse,
+    ) -> Result<(), near_chain::Error> {
+        if self.done || self.last_request_peer_id.as_ref() != Some(&peer_id) {
+            return Ok(());
+        }
+        self.last_request_peer_id = None;
+        let light_client_block = match (&self.last_light_client_block, self.have_all_epochs) {
+            (Some(light_client_block), true) => light_client_block,
+            _ => return Ok(()),
+        };
+        if response.cur_epoch_header.epoch_sync_data_hash().is_none() {
+            // The epoch started before epoch sync was supported, so the headers can't be skipped.
+            info!(target: "sync", "Epoch sync: epoch sync data is not available, falling back to header sync");
+            self.done = true;
+            return Ok(());
+        }
+        if let Err(err) = Chain::validate_epoch_sync_finalization(light_client_block, &response) {
+            warn!(target: "sync", "Epoch sync: invalid finalization from {}: {}", peer_id, err);
+            self.ban_peer(peer_id, ReasonForBan::EpochSyncInvalidFinalizationResponse);
+            return Ok(());
+        }
+
+        let sync_hash = *response.cur_epoch_header.hash();
+        chain.init_after_epoch_sync(response)?;
+        info!(target: "sync", "Epoch sync: done, synced to epoch {:?} starting at {}", self.next_epoch_id, sync_hash);
+        self.sync_hash = Some(sync_hash);
+        self.done = true;
+        Ok(())
+    }
+
+    fn ban_peer(&mut self, peer_id: PeerId, ban_reason: ReasonForBan) {
+        self.peers_reporting_up_to_date.remove(&peer_id);
+        self.network_adapter.do_send(NetworkRequests::BanPeer { peer_id, ban_reason });
+    }
 }
 
 /// Helper to keep track of sync headers.
@@ -170,6 +372,7 @@ impl HeaderSync {
         chain: &mut Chain,
         highest_height: BlockHeight,
         highest_height_peers: &Vec<FullPeerInfo>,
+        peer_performance: &mut PeerPerformance,
     ) -> Result<(), near_chain::Error> {
         let header_head = chain.header_head()?;
         if !self.header_sync_due(sync_status, &header_head, highest_height) {
@@ -194,9 +397,9 @@ impl HeaderSync {
             *sync_status =
                 SyncStatus::HeaderSync { current_height: header_head.height, highest_height };
             self.syncing_peer = None;
-            if let Some(peer) = highest_height_peer(&highest_height_peers) {
+            if let Some(peer) = peer_performance.best_peer(highest_height_peers, Instant::now()) {
                 if peer.chain_info.height > header_head.height {
-                    self.syncing_peer = self.request_headers(chain, peer);
+                    self.syncing_peer = self.request_headers(chain, peer, peer_performance);
                 }
             }
         }
@@ -305,13 +508,19 @@ impl HeaderSync {
     }
 
     /// Request headers from a given peer to advance the chain.
-    fn request_headers(&mut self, chain: &mut Chain, peer: FullPeerInfo) -> Option<FullPeerInfo> {
+    fn request_headers(
+        &mut self,
+        chain: &mut Chain,
+        peer: FullPeerInfo,
+        peer_performance: &mut PeerPerformance,
+    ) -> Option<FullPeerInfo> {
         if let Ok(locator) = self.get_locator(chain) {
             debug!(target: "sync", "Sync: request headers: asking {} for headers, {:?}", peer.peer_info.id, locator);
             self.network_adapter.do_send(NetworkRequests::BlockHeadersRequest {
                 hashes: locator,
                 peer_id: peer.peer_info.id.clone(),
             });
+            peer_performance.request_sent(&peer.peer_info.id, PeerRequest::Headers, Instant::now());
             return Some(peer);
         }
         None
@@ -394,10 +603,17 @@ pub struct BlockSyncRequest {
     when: DateTime<Utc>,
 }
 
+impl BlockSyncRequest {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now - self.when > Duration::seconds(BLOCK_REQUEST_TIMEOUT)
+    }
+}
+
 /// Helper to track block syncing.
 pub struct BlockSync {
     network_adapter: Arc<dyn NetworkAdapter>,
-    last_request: Option<BlockSyncRequest>,
+    /// Blocks requested from peers which haven't arrived yet, by hash.
+    in_flight: HashMap<CryptoHash, BlockSyncRequest>,
     /// How far to fetch blocks vs fetch state.
     block_fetch_horizon: BlockHeightDelta,
     /// Whether to enforce block sync
@@ -410,7 +626,7 @@ impl BlockSync {
         block_fetch_horizon: BlockHeightDelta,
         archive: bool,
     ) -> Self {
-        BlockSync { network_adapter, last_request: None, block_fetch_horizon, archive }
+        BlockSync { network_adapter, in_flight: HashMap::new(), block_fetch_horizon, archive }
     }
 
     /// Runs check if block sync is needed, if it's needed and it's too far - sync state is started instead (returning true).
@@ -421,9 +637,10 @@ impl BlockSync {
         chain: &mut Chain,
         highest_height: BlockHeight,
         highest_height_peers: &[FullPeerInfo],
+        peer_performance: &mut PeerPerformance,
     ) -> Result<bool, near_chain::Error> {
         if self.block_sync_due(chain)? {
-            if self.block_sync(chain, highest_height_peers)? {
+            if self.block_sync(chain, highest_height_peers, peer_performance)? {
                 debug!(target: "sync", "Sync: transition to State Sync.");
                 return Ok(true);
             }
@@ -459,20 +676,20 @@ impl BlockSync {
     }
 
     /// Returns true if state download is required (last known block is too far).
-    /// Otherwise request recent blocks from peers round robin.
+    /// Otherwise request the next `MAX_BLOCK_REQUESTS` blocks of the header chain from the best
+    /// performing peers round robin. Blocks which are already requested are only requested again
+    /// once the request times out.
     pub fn block_sync(
         &mut self,
         chain: &mut Chain,
         highest_height_peers: &[FullPeerInfo],
+        peer_performance: &mut PeerPerformance,
     ) -> Result<bool, near_chain::Error> {
         if self.check_state_needed(chain)? {
             return Ok(true);
         }
 
-        let reference_hash = match &self.last_request {
-            Some(request) if chain.is_chunk_orphan(&request.hash) => request.hash,
-            _ => chain.head()?.last_block_hash,
-        };
+        let reference_hash = chain.head()?.last_block_hash;
 
         let reference_hash = {
             // Find the most recent block we know on the canonical chain.
@@ -515,55 +732,89 @@ impl BlockSync {
             ret_hash
         };
 
-        let next_hash = match chain.mut_store().get_next_block_hash(&reference_hash) {
-            Ok(hash) => *hash,
-            Err(e) => match e.kind() {
-                near_chain::ErrorKind::DBNotFoundErr(_) => {
-                    return Ok(false);
+        // Collect the next blocks of the header chain which haven't arrived yet. Blocks which
+        // arrived out of order wait in the orphan pool or for their chunks.
+        let now_utc = Utc::now();
+        let mut in_flight = HashMap::new();
+        let mut requests = vec![];
+        let mut hash = reference_hash;
+        while in_flight.len() + requests.len() < MAX_BLOCK_REQUESTS {
+            hash = match chain.mut_store().get_next_block_hash(&hash) {
+                Ok(hash) => *hash,
+                Err(e) => match e.kind() {
+                    near_chain::ErrorKind::DBNotFoundErr(_) => break,
+                    _ => return Err(e),
+                },
+            };
+            if chain.block_exists(&hash)? || chain.is_orphan(&hash) || chain.is_chunk_orphan(&hash)
+            {
+                continue;
+            }
+            match self.in_flight.remove(&hash) {
+                Some(request) if !request.is_expired(now_utc) => {
+                    in_flight.insert(hash, request);
                 }
-                _ => return Err(e),
-            },
-        };
-        let next_height = chain.get_block_header(&next_hash)?.height();
-
-        let request = BlockSyncRequest { height: next_height, hash: next_hash, when: Utc::now() };
+                _ => {
+                    let height = chain.get_block_header(&hash)?.height();
+                    requests.push(BlockSyncRequest { height, hash, when: now_utc });
+                }
+            }
+        }
+        // The requests for blocks which are no longer on the header chain are forgotten.
+        self.in_flight = in_flight;
+        if requests.is_empty() {
+            return Ok(false);
+        }
 
         let head = chain.head()?;
         let header_head = chain.header_head()?;
 
-        debug!(target: "sync", "Block sync: {}/{} requesting block {} from {} peers", head.height, header_head.height, next_hash, highest_height_peers.len());
+        debug!(target: "sync", "Block sync: {}/{} requesting blocks {:?} from {} peers", head.height, header_head.height, requests.iter().map(|request| request.hash).collect::<Vec<_>>(), highest_height_peers.len());
 
         let gc_stop_height = chain.runtime_adapter.get_gc_stop_height(&header_head.last_block_hash);
 
-        let request_from_archival = self.archive && request.height < gc_stop_height;
-        let peer = if request_from_archival {
-            let archival_peer_iter = highest_height_peers.iter().filter(|p| p.chain_info.archival);
-            archival_peer_iter.choose(&mut rand::thread_rng())
-        } else {
-            let peer_iter = highest_height_peers.iter();
-            peer_iter.choose(&mut rand::thread_rng())
-        };
-
-        if let Some(peer) = peer {
+        let now = Instant::now();
+        let peers = peer_performance.rank(highest_height_peers, now);
+        let archival_peers =
+            peers.iter().filter(|p| p.chain_info.archival).cloned().collect::<Vec<_>>();
+        for (i, request) in requests.into_iter().enumerate() {
+            let request_from_archival = self.archive && request.height < gc_stop_height;
+            let peers = if request_from_archival { &archival_peers } else { &peers };
+            if peers.is_empty() {
+                continue;
+            }
+            let peer_id = &peers[i % peers.len()].peer_info.id;
             self.network_adapter.do_send(NetworkRequests::BlockRequest {
                 hash: request.hash,
-                peer_id: peer.peer_info.id.clone(),
+                peer_id: peer_id.clone(),
             });
+            peer_performance.request_sent(peer_id, PeerRequest::Block(request.hash), now);
+            self.in_flight.insert(request.hash, request);
         }
 
-        self.last_request = Some(request);
-
         Ok(false)
     }
 
-    /// Check if we should run block body sync and ask for more full blocks.
+    /// Check if we should run block body sync and ask for more full blocks: once all the
+    /// requested blocks arrived, or some request timed out.
     fn block_sync_due(&mut self, chain: &Chain) -> Result<bool, near_chain::Error> {
-        match &self.last_request {
-            None => Ok(true),
-            Some(request) => Ok(chain.head()?.height >= request.height
+        let head_height = chain.head()?.height;
+        let mut arrived = vec![];
+        for request in self.in_flight.values() {
+            if head_height >= request.height
+                || chain.block_exists(&request.hash)?
+                || chain.is_orphan(&request.hash)
                 || chain.is_chunk_orphan(&request.hash)
-                || Utc::now() - request.when > Duration::seconds(BLOCK_REQUEST_TIMEOUT)),
+            {
+                arrived.push(request.hash);
+            }
+        }
+        for hash in arrived {
+            self.in_flight.remove(&hash);
         }
+        let now = Utc::now();
+        Ok(self.in_flight.is_empty()
+            || self.in_flight.values().any(|request| request.is_expired(now)))
     }
 }
 
@@ -677,6 +928,18 @@ impl StateSync {
         for shard_id in tracking_shards {
             let mut download_timeout = false;
             let mut need_shard = false;
+            if !new_shard_sync.contains_key(&shard_id)
+                && chain.get_state_header(shard_id, sync_hash).is_ok()
+            {
+                // The header was downloaded before the node restarted, resume with the parts.
+                let shard_sync_download = Self::parts_download(chain, shard_id, sync_hash, now)?;
+                info!(target: "sync", "State sync: resuming shard {} with {} of {} parts downloaded",
+                      shard_id,
+                      shard_sync_download.downloads.iter().filter(|download| download.done).count(),
+                      shard_sync_download.downloads.len());
+                new_shard_sync.insert(shard_id, shard_sync_download);
+                need_shard = true;
+            }
             let shard_sync_download = new_shard_sync.entry(shard_id).or_insert_with(|| {
                 need_shard = true;
                 init_sync_download.clone()
@@ -685,24 +948,8 @@ impl StateSync {
             match shard_sync_download.status {
                 ShardSyncStatus::StateDownloadHeader => {
                     if shard_sync_download.downloads[0].done {
-                        let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
-                        let state_num_parts =
-                            get_num_state_parts(shard_state_header.state_root_node().memory_usage);
-                        *shard_sync_download = ShardSyncDownload {
-                            downloads: vec![
-                                DownloadStatus {
-                                    start_time: now,
-                                    prev_update_time: now,
-                                    run_me: Arc::new(AtomicBool::new(true)),
-                                    error: false,
-                                    done: false,
-                                    state_requests_count: 0,
-                                    last_target: None,
-                                };
-                                state_num_parts as usize
-                            ],
-                            status: ShardSyncStatus::StateDownloadParts,
-                        };
+                        *shard_sync_download =
+                            Self::parts_download(chain, shard_id, sync_hash, now)?;
                         need_shard = true;
                     } else {
                         let prev = shard_sync_download.downloads[0].prev_update_time;
@@ -726,6 +973,16 @@ impl StateSync {
                             let prev = part_download.prev_update_time;
                             let error = part_download.error;
                             let part_timeout = now - prev > self.timeout;
+                            if part_timeout {
+                                if let Some(AccountOrPeerIdOrHash::PeerId(peer_id)) =
+                                    &part_download.last_target
+                                {
+                                    self.network_adapter.do_send(NetworkRequests::ReportPeer {
+                                        peer_id: peer_id.clone(),
+                                        misbehavior: PeerMisbehavior::Timeout,
+                                    });
+                                }
+                            }
                             if part_timeout || error {
                                 download_timeout |= part_timeout;
                                 part_download.run_me.store(true, Ordering::SeqCst);
@@ -823,6 +1080,35 @@ impl StateSync {
         Ok((update_sync_status, all_done))
     }
 
+    /// Returns the download of the state parts of the shard. Parts which were already downloaded,
+    /// possibly before a restart, are not requested again.
+    fn parts_download(
+        chain: &mut Chain,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        now: DateTime<Utc>,
+    ) -> Result<ShardSyncDownload, near_chain::Error> {
+        let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
+        let state_num_parts =
+            get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+        let progress = chain.get_state_sync_progress(shard_id, sync_hash)?;
+        let downloads = (0..state_num_parts)
+            .map(|part_id| {
+                let done = progress.is_done(part_id);
+                DownloadStatus {
+                    start_time: now,
+                    prev_update_time: now,
+                    run_me: Arc::new(AtomicBool::new(!done)),
+                    error: false,
+                    done,
+                    state_requests_count: 0,
+                    last_target: None,
+                }
+            })
+            .collect();
+        Ok(ShardSyncDownload { downloads, status: ShardSyncStatus::StateDownloadParts })
+    }
+
     /// Find the hash of the first block on the same epoch (and chain) of block with hash `sync_hash`.
     pub fn get_epoch_start_sync_hash(
         chain: &mut Chain,
@@ -1231,7 +1517,13 @@ mod test {
         };
         let head = chain.head().unwrap();
         assert!(header_sync
-            .run(&mut sync_status, &mut chain, head.height, &vec![peer1.clone()])
+            .run(
+                &mut sync_status,
+                &mut chain,
+                head.height,
+                &vec![peer1.clone()],
+                &mut PeerPerformance::default()
+            )
             .is_ok());
         assert!(sync_status.is_syncing());
         // Check that it queried last block, and then stepped down to genesis block to find common block with the peer.
@@ -1328,6 +1620,7 @@ mod test {
                     )
                 };
             let block = Block::produce(
+                PROTOCOL_VERSION,
                 PROTOCOL_VERSION,
                 &last_block.header(),
                 current_height,
@@ -1436,6 +1729,7 @@ mod test {
         let network_adapter = Arc::new(MockNetworkAdapter::default());
         let block_fetch_horizon = 10;
         let mut block_sync = BlockSync::new(network_adapter.clone(), block_fetch_horizon, false);
+        let mut peer_performance = PeerPerformance::default();
         let mut chain_genesis = ChainGenesis::test();
         chain_genesis.epoch_length = 100;
         let mut env = TestEnv::new(chain_genesis, 2, 1);
@@ -1449,35 +1743,98 @@ mod test {
         let peer_infos = create_peer_infos(2);
         env.clients[1].chain.sync_block_headers(block_headers, |_| unreachable!()).unwrap();
 
-        for block in blocks.iter().take(5) {
-            let is_state_sync =
-                block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        for batch in blocks.chunks(MAX_BLOCK_REQUESTS).take(2) {
+            let is_state_sync = block_sync
+                .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+                .unwrap();
             assert!(!is_state_sync);
 
             let requested_block_hashes =
                 collect_hashes_from_network_adapter(network_adapter.clone());
             assert_eq!(
                 requested_block_hashes,
-                [block].iter().map(|x| *x.hash()).collect::<HashSet<_>>()
+                batch.iter().map(|x| *x.hash()).collect::<HashSet<_>>()
             );
 
-            env.process_block(1, block.clone(), Provenance::NONE);
+            for block in batch.iter() {
+                env.process_block(1, block.clone(), Provenance::NONE);
+            }
         }
 
         // Receive all blocks. Should not request more.
-        for i in 5..21 {
-            env.process_block(1, blocks[i - 1].clone(), Provenance::NONE);
+        for i in 2 * MAX_BLOCK_REQUESTS..20 {
+            env.process_block(1, blocks[i].clone(), Provenance::NONE);
         }
-        block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter.clone());
         assert!(requested_block_hashes.is_empty());
     }
 
+    /// Test the blocks which don't arrive in time are requested again, and only them.
+    #[test]
+    fn test_block_sync_rerequests_expired() {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut block_sync = BlockSync::new(network_adapter.clone(), 10, false);
+        let mut peer_performance = PeerPerformance::default();
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.epoch_length = 100;
+        let mut env = TestEnv::new(chain_genesis, 2, 1);
+        let mut blocks = vec![];
+        for i in 1..11 {
+            let block = env.clients[0].produce_block(i).unwrap().unwrap();
+            blocks.push(block.clone());
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+        let block_headers = blocks.iter().map(|b| b.header().clone()).collect::<Vec<_>>();
+        let peer_infos = create_peer_infos(2);
+        env.clients[1].chain.sync_block_headers(block_headers, |_| unreachable!()).unwrap();
+
+        block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
+        let requested = collect_hashes_from_network_adapter(network_adapter.clone());
+        assert_eq!(requested.len(), MAX_BLOCK_REQUESTS);
+        assert_eq!(block_sync.in_flight.len(), MAX_BLOCK_REQUESTS);
+
+        // Only the first two blocks arrive, the others are still in flight.
+        for block in blocks[..2].iter() {
+            env.process_block(1, block.clone(), Provenance::NONE);
+        }
+        assert!(!block_sync.block_sync_due(&env.clients[1].chain).unwrap());
+        assert_eq!(block_sync.in_flight.len(), MAX_BLOCK_REQUESTS - 2);
+        block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
+        assert_eq!(
+            collect_hashes_from_network_adapter(network_adapter.clone()),
+            blocks[MAX_BLOCK_REQUESTS..MAX_BLOCK_REQUESTS + 2]
+                .iter()
+                .map(|b| *b.hash())
+                .collect::<HashSet<_>>()
+        );
+
+        // The requests time out and all the missing blocks are requested again.
+        for request in block_sync.in_flight.values_mut() {
+            request.when = request.when - Duration::seconds(BLOCK_REQUEST_TIMEOUT + 1);
+        }
+        assert!(block_sync.block_sync_due(&env.clients[1].chain).unwrap());
+        block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
+        assert_eq!(
+            collect_hashes_from_network_adapter(network_adapter.clone()),
+            blocks[2..MAX_BLOCK_REQUESTS + 2].iter().map(|b| *b.hash()).collect::<HashSet<_>>()
+        );
+    }
+
     #[test]
     fn test_block_sync_archival() {
         let network_adapter = Arc::new(MockNetworkAdapter::default());
         let block_fetch_horizon = 10;
         let mut block_sync = BlockSync::new(network_adapter.clone(), block_fetch_horizon, true);
+        let mut peer_performance = PeerPerformance::default();
         let mut chain_genesis = ChainGenesis::test();
         chain_genesis.epoch_length = 5;
         let mut env = TestEnv::new(chain_genesis, 2, 1);
@@ -1490,7 +1847,9 @@ mod test {
         let block_headers = blocks.iter().map(|b| b.header().clone()).collect::<Vec<_>>();
         let peer_infos = create_peer_infos(2);
         env.clients[1].chain.sync_block_headers(block_headers, |_| unreachable!()).unwrap();
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync = block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
         assert!(!is_state_sync);
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter.clone());
         // We don't have archival peers, and thus cannot request any blocks
@@ -1500,12 +1859,14 @@ mod test {
         for peer in peer_infos.iter_mut() {
             peer.chain_info.archival = true;
         }
-        let is_state_sync = block_sync.block_sync(&mut env.clients[1].chain, &peer_infos).unwrap();
+        let is_state_sync = block_sync
+            .block_sync(&mut env.clients[1].chain, &peer_infos, &mut peer_performance)
+            .unwrap();
         assert!(!is_state_sync);
         let requested_block_hashes = collect_hashes_from_network_adapter(network_adapter.clone());
         assert_eq!(
             requested_block_hashes,
-            blocks.iter().take(1).map(|b| *b.hash()).collect::<HashSet<_>>()
+            blocks.iter().take(MAX_BLOCK_REQUESTS).map(|b| *b.hash()).collect::<HashSet<_>>()
         );
     }
 }
@@ -480,6 +480,7 @@ pub fn setup_mock_all_validators(
                             sent_bytes_per_sec: 0,
                             received_bytes_per_sec: 0,
                             known_producers: vec![],
+                            peer_traffic: vec![],
                             #[cfg(feature = "metric_recorder")]
                             metric_recorder: MetricRecorder::default(),
                             peer_counter: 0,
@@ -872,6 +873,8 @@ pub fn setup_mock_all_validators(
                         | NetworkRequests::PingTo(_, _)
                         | NetworkRequests::FetchPingPongInfo
                         | NetworkRequests::BanPeer { .. }
+                        | NetworkRequests::ReportPeer { .. }
+                        | NetworkRequests::DisconnectPeer { .. }
                         | NetworkRequests::TxStatus(_, _, _)
                         | NetworkRequests::Query { .. }
                         | NetworkRequests::Challenge(_)
@@ -1156,7 +1159,13 @@ impl TestEnv {
                 last_block.header().prev_hash(),
                 last_block.header().hash(),
                 last_block.header().epoch_id(),
-                &QueryRequest::ViewState { account_id, prefix: vec![].into() },
+                &QueryRequest::ViewState {
+                    account_id,
+                    prefix: vec![].into(),
+                    start_key: None,
+                    limit: None,
+                    include_proof: false,
+                },
             )
             .unwrap();
         match response.kind {
@@ -1280,6 +1289,7 @@ pub fn create_chunk(
         client.chain.mut_store().get_block_merkle_tree(&last_block.hash()).unwrap().clone();
     block_merkle_tree.insert(*last_block.hash());
     let block = Block::produce(
+        PROTOCOL_VERSION,
         PROTOCOL_VERSION,
         &last_block.header(),
         next_height,
@@ -0,0 +1,190 @@
+//! Traces of the lifecycle of transactions received by the node.
+//!
+//! The client records the time at which it observes every stage of a transaction it received:
+//! the transaction arriving over RPC or from a peer, entering the pool, being included into a
+//! chunk, applied and finalized. Latency complaints can then be diagnosed with the timeline of the
+//! transaction on the node instead of guesses. Only the most recently received transactions are
+//! traced.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::Utc;
+
+use near_client_primitives::types::TransactionTraceView;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ShardId};
+
+/// Returns the current time in the format of the timestamps of the traces.
+pub(crate) fn current_time_ms() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+pub(crate) struct TransactionTracer {
+    traces: HashMap<CryptoHash, TransactionTraceView>,
+    /// Traced transactions in the order they were received, to evict the oldest ones.
+    order: VecDeque<CryptoHash>,
+    /// Applied transactions waiting for their block to become final, by the height of the block.
+    pending_final: BTreeMap<BlockHeight, Vec<CryptoHash>>,
+    capacity: usize,
+}
+
+impl TransactionTracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            traces: HashMap::new(),
+            order: VecDeque::new(),
+            pending_final: BTreeMap::new(),
+            capacity,
+        }
+    }
+
+    pub fn get(&self, transaction_hash: &CryptoHash) -> Option<&TransactionTraceView> {
+        self.traces.get(transaction_hash)
+    }
+
+    pub fn is_traced(&self, transaction_hash: &CryptoHash) -> bool {
+        self.traces.contains_key(transaction_hash)
+    }
+
+    /// Starts tracing a transaction, unless it's traced already, e.g. because it was received
+    /// from several peers.
+    pub fn record_received(
+        &mut self,
+        transaction_hash: CryptoHash,
+        is_forwarded: bool,
+        timestamp: u64,
+    ) {
+        if self.traces.contains_key(&transaction_hash) {
+            return;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.traces.remove(&evicted);
+            }
+        }
+        self.order.push_back(transaction_hash);
+        self.traces.insert(
+            transaction_hash,
+            TransactionTraceView {
+                transaction_hash,
+                is_forwarded,
+                received: Some(timestamp),
+                inserted_to_pool: None,
+                included_in_chunk: None,
+                applied: None,
+                finalized: None,
+                shard_id: None,
+                height: None,
+                block_hash: None,
+                receipt_ids: vec![],
+            },
+        );
+    }
+
+    pub fn record_inserted_to_pool(&mut self, transaction_hash: &CryptoHash, timestamp: u64) {
+        if let Some(trace) = self.traces.get_mut(transaction_hash) {
+            trace.inserted_to_pool.get_or_insert(timestamp);
+        }
+    }
+
+    pub fn record_included_in_chunk(
+        &mut self,
+        transaction_hash: &CryptoHash,
+        shard_id: ShardId,
+        height: BlockHeight,
+        timestamp: u64,
+    ) {
+        if let Some(trace) = self.traces.get_mut(transaction_hash) {
+            trace.included_in_chunk.get_or_insert(timestamp);
+            trace.shard_id = Some(shard_id);
+            trace.height = Some(height);
+        }
+    }
+
+    /// Records that the block with the chunk of the transaction was applied. A transaction
+    /// applied again in a block on another fork keeps the latest block.
+    pub fn record_applied(
+        &mut self,
+        transaction_hash: &CryptoHash,
+        block_hash: CryptoHash,
+        block_height: BlockHeight,
+        receipt_ids: Vec<CryptoHash>,
+        timestamp: u64,
+    ) {
+        if let Some(trace) = self.traces.get_mut(transaction_hash) {
+            trace.applied = Some(timestamp);
+            trace.block_hash = Some(block_hash);
+            trace.receipt_ids = receipt_ids;
+            self.pending_final.entry(block_height).or_default().push(*transaction_hash);
+        }
+    }
+
+    /// Finalizes the transactions applied in blocks up to `final_height` which are on the
+    /// canonical chain according to `is_canonical`.
+    pub fn record_final(
+        &mut self,
+        final_height: BlockHeight,
+        mut is_canonical: impl FnMut(&CryptoHash) -> bool,
+        timestamp: u64,
+    ) {
+        let pending = self.pending_final.split_off(&(final_height + 1));
+        let finalized = std::mem::replace(&mut self.pending_final, pending);
+        for transaction_hash in finalized.into_iter().flat_map(|(_, hashes)| hashes) {
+            if let Some(trace) = self.traces.get_mut(&transaction_hash) {
+                if trace.finalized.is_none()
+                    && trace.block_hash.map_or(false, |block_hash| is_canonical(&block_hash))
+                {
+                    trace.finalized = Some(timestamp);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    #[test]
+    fn test_transaction_lifecycle() {
+        let mut tracer = TransactionTracer::new(10);
+        let tx_hash = hash(&[1]);
+        let block_hash = hash(&[2]);
+        let fork_hash = hash(&[3]);
+        tracer.record_received(tx_hash, false, 1);
+        tracer.record_received(tx_hash, true, 2);
+        tracer.record_inserted_to_pool(&tx_hash, 3);
+        tracer.record_included_in_chunk(&tx_hash, 0, 5, 4);
+        tracer.record_applied(&tx_hash, fork_hash, 6, vec![], 5);
+        tracer.record_applied(&tx_hash, block_hash, 6, vec![hash(&[4])], 6);
+        tracer.record_final(5, |_| true, 7);
+        assert_eq!(tracer.get(&tx_hash).unwrap().finalized, None);
+        tracer.record_final(6, |hash| hash == &block_hash, 8);
+
+        let trace = tracer.get(&tx_hash).unwrap();
+        assert!(!trace.is_forwarded);
+        assert_eq!(
+            (trace.received, trace.inserted_to_pool, trace.included_in_chunk),
+            (Some(1), Some(3), Some(4))
+        );
+        assert_eq!((trace.applied, trace.finalized), (Some(6), Some(8)));
+        assert_eq!((trace.shard_id, trace.height), (Some(0), Some(5)));
+        assert_eq!(trace.block_hash, Some(block_hash));
+        assert_eq!(trace.receipt_ids, vec![hash(&[4])]);
+    }
+
+    #[test]
+    fn test_oldest_traces_evicted() {
+        let mut tracer = TransactionTracer::new(2);
+        for i in 0..3u8 {
+            tracer.record_received(hash(&[i]), false, i as u64);
+        }
+        assert!(!tracer.is_traced(&hash(&[0])));
+        assert!(tracer.is_traced(&hash(&[1])));
+        assert!(tracer.is_traced(&hash(&[2])));
+        // Stages of transactions which aren't traced are ignored.
+        tracer.record_inserted_to_pool(&hash(&[0]), 3);
+        assert!(tracer.get(&hash(&[0])).is_none());
+    }
+}
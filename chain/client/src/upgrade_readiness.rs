@@ -0,0 +1,79 @@
+//! Self-checks run before the node votes for a newer protocol version.
+//!
+//! Block producers vote for the latest protocol version their binary supports in the headers of
+//! the blocks they produce. A node which can't run that version yet, e.g. because migrations of
+//! its database are pending or its disk is almost full, votes for the version of the current
+//! epoch instead, so operators don't vote in upgrades their node can't execute.
+use near_chain_configs::ClientConfig;
+use near_primitives::version::{DbVersion, DB_VERSION};
+use near_store::{DBCol, Store, VERSION_KEY};
+
+/// Returns the reasons why the node isn't ready to run a newer protocol version, none if it is.
+/// The checks are skipped without a database on disk, e.g. in tests.
+pub fn check_upgrade_readiness(store: &Store, config: &ClientConfig) -> Vec<String> {
+    let store_path = match &config.store_path {
+        Some(store_path) => store_path,
+        None => return vec![],
+    };
+    let mut failures = vec![];
+    match store.get(DBCol::ColDbVersion, VERSION_KEY) {
+        Ok(Some(value)) => match serde_json::from_slice::<DbVersion>(&value) {
+            Ok(version) if version >= DB_VERSION => {}
+            Ok(version) => failures.push(format!(
+                "database version {} is older than {}, migrations are pending",
+                version, DB_VERSION
+            )),
+            Err(err) => failures.push(format!("can't parse the database version: {}", err)),
+        },
+        Ok(None) => failures.push("the database version is missing".to_string()),
+        Err(err) => failures.push(format!("can't read the database version: {}", err)),
+    }
+    match fs2::available_space(store_path) {
+        Ok(available) if available >= config.min_free_disk_space_for_upgrade => {}
+        Ok(available) => failures.push(format!(
+            "{} bytes free on the disk of the database, {} required",
+            available, config.min_free_disk_space_for_upgrade
+        )),
+        Err(err) => failures.push(format!("can't get the free disk space: {}", err)),
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use near_store::migrations::set_store_version;
+    use near_store::test_utils::create_test_store;
+
+    use super::*;
+
+    fn test_config() -> ClientConfig {
+        let mut config = ClientConfig::test(true, 10, 20, 1, false, false);
+        config.store_path = Some(std::env::temp_dir());
+        config
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let store = create_test_store();
+        let mut config = test_config();
+        assert_eq!(check_upgrade_readiness(&store, &config).len(), 1);
+        set_store_version(&store, DB_VERSION - 1);
+        assert_eq!(check_upgrade_readiness(&store, &config).len(), 1);
+        set_store_version(&store, DB_VERSION);
+        assert!(check_upgrade_readiness(&store, &config).is_empty());
+        // Nothing is checked without a database on disk.
+        set_store_version(&store, DB_VERSION - 1);
+        config.store_path = None;
+        assert!(check_upgrade_readiness(&store, &config).is_empty());
+    }
+
+    #[test]
+    fn test_disk_headroom() {
+        let store = create_test_store();
+        set_store_version(&store, DB_VERSION);
+        let mut config = test_config();
+        assert!(check_upgrade_readiness(&store, &config).is_empty());
+        config.min_free_disk_space_for_upgrade = u64::MAX;
+        assert_eq!(check_upgrade_readiness(&store, &config).len(), 1);
+    }
+}
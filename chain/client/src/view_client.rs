@@ -17,14 +17,15 @@ use near_chain::{
     get_epoch_block_producers_view, Chain, ChainGenesis, ChainStoreAccess, DoomslugThresholdMode,
     ErrorKind, RuntimeAdapter,
 };
-use near_chain_configs::{ClientConfig, ProtocolConfigView};
+use near_chain_configs::{ClientConfig, ProtocolConfigView, ShardTracking};
 use near_client_primitives::types::{
-    Error, GetBlock, GetBlockError, GetBlockProof, GetBlockProofError, GetBlockProofResponse,
-    GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome, GetExecutionOutcomeError,
-    GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError, GetNextLightClientBlockError,
-    GetProtocolConfig, GetProtocolConfigError, GetReceipt, GetReceiptError, GetStateChangesError,
-    GetStateChangesWithCauseInBlock, GetValidatorInfoError, Query, QueryError, TxStatus,
-    TxStatusError,
+    Error, GetBlock, GetBlockDetails, GetBlockError, GetBlockProof, GetBlockProofError,
+    GetBlockProofResponse, GetBlockWithMerkleTree, GetChunkDetails, GetChunkError,
+    GetContractExecutionStats, GetExecutionOutcome, GetExecutionOutcomeError,
+    GetExecutionOutcomesForBlock, GetFilteredStateChanges, GetGasPrice, GetGasPriceError,
+    GetNextLightClientBlockError, GetProtocolConfig, GetProtocolConfigError, GetReceipt,
+    GetReceiptError, GetStateChangesError, GetStateChangesWithCauseInBlock, GetValidatorInfoError,
+    Query, QueryError, TxStatus, TxStatusError,
 };
 #[cfg(feature = "adversarial")]
 use near_network::types::NetworkAdversarialMessage;
@@ -39,10 +40,11 @@ use near_primitives::block::{Block, BlockHeader, GenesisId, Tip};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, PartialMerkleTree};
 use near_primitives::network::AnnounceAccount;
-use near_primitives::sharding::ShardChunk;
+use near_primitives::runtime::contract_stats::ContractExecutionStats;
+use near_primitives::sharding::{ReceiptProof, ShardChunk};
 use near_primitives::syncing::{
-    ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
-    ShardStateSyncResponseV2,
+    EpochSyncResponse, ShardStateSyncResponse, ShardStateSyncResponseHeader,
+    ShardStateSyncResponseV1, ShardStateSyncResponseV2,
 };
 use near_primitives::types::{
     AccountId, BlockHeight, BlockId, BlockReference, EpochReference, Finality, MaybeBlockId,
@@ -50,15 +52,17 @@ use near_primitives::types::{
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
+    BlockDetailsView, BlockView, ChunkDetailsView, ChunkView, EpochValidatorDiffView,
+    EpochValidatorInfo, ExecutionOutcomeWithIdView, FinalExecutionOutcomeView,
+    FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView, LightClientBlockView,
+    QueryRequest, QueryResponse, ReceiptView, SeatPriceView, StateChangesKindsView,
     StateChangesView,
 };
 
 use crate::{
-    sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetSeatPrice,
+    GetStateChanges, GetStateChangesInBlock, GetValidatorDiff, GetValidatorInfo,
+    GetValidatorOrdered,
 };
 
 /// Max number of queries that we keep.
@@ -240,15 +244,17 @@ impl ViewClientActor {
             })?
             .clone();
 
-        let account_id = match &msg.request {
-            QueryRequest::ViewAccount { account_id, .. } => account_id,
-            QueryRequest::ViewState { account_id, .. } => account_id,
-            QueryRequest::ViewAccessKey { account_id, .. } => account_id,
-            QueryRequest::ViewAccessKeyList { account_id, .. } => account_id,
-            QueryRequest::CallFunction { account_id, .. } => account_id,
-            QueryRequest::ViewCode { account_id, .. } => account_id,
+        let shard_id = match &msg.request {
+            QueryRequest::ViewAccount { account_id, .. }
+            | QueryRequest::ViewState { account_id, .. }
+            | QueryRequest::ViewAccessKey { account_id, .. }
+            | QueryRequest::ViewAccessKeyList { account_id, .. }
+            | QueryRequest::CallFunction { account_id, .. }
+            | QueryRequest::ViewCode { account_id, .. } => {
+                self.runtime_adapter.account_id_to_shard_id(account_id)
+            }
+            QueryRequest::ViewBurnLedger { shard_id } => *shard_id,
         };
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(account_id);
 
         let chunk_extra = self.chain.get_chunk_extra(header.hash(), shard_id).map_err(|err| {
             match err.kind() {
@@ -308,6 +314,19 @@ impl ViewClientActor {
                     block_height,
                     block_hash,
                 },
+                near_chain::near_chain_primitives::error::QueryError::ViewLimitExceeded {
+                    error_message,
+                    gas_burnt,
+                    logs,
+                    block_height,
+                    block_hash,
+                } => QueryError::ViewLimitExceeded {
+                    error_message,
+                    gas_burnt,
+                    logs,
+                    block_height,
+                    block_hash,
+                },
                 near_chain::near_chain_primitives::error::QueryError::TooLargeContractState {
                     requested_account_id,
                     block_height,
@@ -551,6 +570,29 @@ impl Handler<GetBlock> for ViewClientActor {
     }
 }
 
+impl Handler<GetBlockDetails> for ViewClientActor {
+    type Result = Result<BlockDetailsView, GetBlockError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetBlockDetails, ctx: &mut Self::Context) -> Self::Result {
+        let block = self.handle(GetBlock(msg.0), ctx)?;
+        let chunk_producers = block
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let epoch_id =
+                    self.runtime_adapter.get_epoch_id_from_prev_block(&chunk.prev_block_hash)?;
+                self.runtime_adapter.get_chunk_producer(
+                    &epoch_id,
+                    chunk.height_created,
+                    chunk.shard_id,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BlockDetailsView { block, chunk_producers })
+    }
+}
+
 impl Handler<GetBlockWithMerkleTree> for ViewClientActor {
     type Result = Result<(BlockView, PartialMerkleTree), GetBlockError>;
 
@@ -615,6 +657,52 @@ impl Handler<GetChunk> for ViewClientActor {
     }
 }
 
+impl Handler<GetChunkDetails> for ViewClientActor {
+    type Result = Result<ChunkDetailsView, GetChunkError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetChunkDetails, ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = match &msg.0 {
+            GetChunk::BlockHash(block_hash, _) => Some(*block_hash),
+            GetChunk::Height(height, _) => {
+                Some(self.chain.mut_store().get_block_hash_by_height(*height)?)
+            }
+            GetChunk::ChunkHash(_) => None,
+        };
+        let chunk = self.handle(msg.0, ctx)?;
+        let block_hash = match block_hash {
+            Some(block_hash) => block_hash,
+            // A chunk can only be included in the block right after its previous block.
+            None => *self.chain.mut_store().get_next_block_hash(&chunk.header.prev_block_hash)?,
+        };
+        let shard_id = chunk.header.shard_id;
+        // The receipts are only stored by the nodes tracking the shard.
+        let incoming_receipts =
+            match self.chain.mut_store().get_incoming_receipts(&block_hash, shard_id) {
+                Ok(receipt_proofs) => Some(
+                    receipt_proofs
+                        .iter()
+                        .flat_map(|ReceiptProof(receipts, _)| receipts.iter().cloned())
+                        .map(Into::into)
+                        .collect(),
+                ),
+                Err(e) => match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => None,
+                    _ => return Err(e.into()),
+                },
+            };
+        let outgoing_receipts =
+            match self.chain.mut_store().get_outgoing_receipts(&block_hash, shard_id) {
+                Ok(receipts) => Some(receipts.iter().cloned().map(Into::into).collect()),
+                Err(e) => match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => None,
+                    _ => return Err(e.into()),
+                },
+            };
+        Ok(ChunkDetailsView { chunk, block_hash, incoming_receipts, outgoing_receipts })
+    }
+}
+
 impl Handler<TxStatus> for ViewClientActor {
     type Result = Result<Option<FinalExecutionOutcomeViewEnum>, TxStatusError>;
 
@@ -668,6 +756,23 @@ impl Handler<GetValidatorInfo> for ViewClientActor {
     }
 }
 
+impl Handler<GetValidatorDiff> for ViewClientActor {
+    type Result = Result<EpochValidatorDiffView, GetValidatorInfoError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetValidatorDiff, _: &mut Self::Context) -> Self::Result {
+        let epoch_id = match msg.epoch_reference {
+            EpochReference::EpochId(id) => id,
+            EpochReference::BlockId(block_id) => {
+                let block_hash = self.maybe_block_id_to_block_hash(Some(block_id))?;
+                self.chain.get_block_header(&block_hash)?.epoch_id().clone()
+            }
+            EpochReference::Latest => self.chain.header_head()?.epoch_id,
+        };
+        self.runtime_adapter.get_validator_diff(&epoch_id).map_err(GetValidatorInfoError::from)
+    }
+}
+
 impl Handler<GetValidatorOrdered> for ViewClientActor {
     type Result = Result<Vec<ValidatorStakeView>, GetValidatorInfoError>;
 
@@ -685,6 +790,16 @@ impl Handler<GetValidatorOrdered> for ViewClientActor {
             })?)
     }
 }
+impl Handler<GetSeatPrice> for ViewClientActor {
+    type Result = Result<SeatPriceView, GetValidatorInfoError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetSeatPrice, _: &mut Self::Context) -> Self::Result {
+        let block_hash = self.maybe_block_id_to_block_hash(msg.block_id)?;
+        self.runtime_adapter.get_seat_prices(&block_hash).map_err(GetValidatorInfoError::from)
+    }
+}
+
 /// Returns a list of change kinds per account in a store for a given block.
 impl Handler<GetStateChangesInBlock> for ViewClientActor {
     type Result = Result<StateChangesKindsView, GetStateChangesError>;
@@ -737,6 +852,22 @@ impl Handler<GetStateChangesWithCauseInBlock> for ViewClientActor {
     }
 }
 
+/// Returns a list of changes in a given block which matched the state changes filter.
+impl Handler<GetFilteredStateChanges> for ViewClientActor {
+    type Result = Result<StateChangesView, GetStateChangesError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetFilteredStateChanges, _: &mut Self::Context) -> Self::Result {
+        Ok(self
+            .chain
+            .store()
+            .get_filtered_state_changes(&msg.block_hash, &msg.filter_id)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
 /// Returns the next light client block, given the hash of the last block known to the light client.
 /// There are three cases:
 ///  1. The last block known to the light client is in the same epoch as the tip:
@@ -754,7 +885,9 @@ impl Handler<GetNextLightClientBlock> for ViewClientActor {
         let last_epoch_id = last_block_header.epoch_id().clone();
         let last_next_epoch_id = last_block_header.next_epoch_id().clone();
         let last_height = last_block_header.height();
-        let head = self.chain.head()?;
+        // The nodes syncing only the headers never process the blocks.
+        let head =
+            if self.config.header_only { self.chain.header_head()? } else { self.chain.head()? };
 
         if last_epoch_id == head.epoch_id || last_next_epoch_id == head.epoch_id {
             let head_header = self.chain.get_block_header(&head.last_block_hash)?;
@@ -1074,7 +1207,11 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                             hash: *self.chain.genesis().hash(),
                         },
                         height,
-                        tracked_shards: self.config.tracked_shards.clone(),
+                        tracked_shards: match self.config.shard_tracking {
+                            ShardTracking::All => (0..self.runtime_adapter.num_shards()).collect(),
+                            ShardTracking::Subset => self.config.tracked_shards.clone(),
+                            ShardTracking::Own => vec![],
+                        },
                         archival: self.config.archive,
                     }
                 }
@@ -1238,13 +1375,30 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
 
                 NetworkViewClientResponses::AnnounceAccount(filtered_announce_accounts)
             }
-            NetworkViewClientMessages::EpochSyncRequest { epoch_id: _epoch_id } => {
-                // TODO #3488
-                NetworkViewClientResponses::NoResponse
+            NetworkViewClientMessages::EpochSyncRequest { epoch_id } => {
+                // The light client block of an epoch is only recorded once the epoch is over.
+                match self.chain.mut_store().get_epoch_light_client_block(&epoch_id.0) {
+                    Ok(light_client_block_view) => {
+                        NetworkViewClientResponses::EpochSyncResponse(EpochSyncResponse::Advance {
+                            light_client_block_view: light_client_block_view.clone(),
+                        })
+                    }
+                    Err(_) if self.runtime_adapter.epoch_exists(&epoch_id) => {
+                        NetworkViewClientResponses::EpochSyncResponse(EpochSyncResponse::UpToDate)
+                    }
+                    Err(_) => NetworkViewClientResponses::NoResponse,
+                }
             }
-            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id: _epoch_id } => {
-                // TODO #3488
-                NetworkViewClientResponses::NoResponse
+            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id } => {
+                match self.chain.get_epoch_sync_finalization_response(&epoch_id) {
+                    Ok(response) => {
+                        NetworkViewClientResponses::EpochSyncFinalizationResponse(response)
+                    }
+                    Err(err) => {
+                        debug!(target: "sync", "Can't build epoch sync finalization for {:?}: {}", epoch_id, err);
+                        NetworkViewClientResponses::NoResponse
+                    }
+                }
             }
         }
     }
@@ -1262,6 +1416,17 @@ impl Handler<GetGasPrice> for ViewClientActor {
     }
 }
 
+impl Handler<GetContractExecutionStats> for ViewClientActor {
+    type Result = Result<Option<ContractExecutionStats>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetContractExecutionStats, _ctx: &mut Self::Context) -> Self::Result {
+        self.runtime_adapter
+            .get_contract_execution_stats(&msg.code_hash)
+            .map_err(|err| err.to_string())
+    }
+}
+
 /// Starts the View Client in a new arbiter (thread).
 pub fn start_view_client(
     validator_account_id: Option<AccountId>,
@@ -17,11 +17,19 @@ use near_crypto::{InMemorySigner, KeyType, Signer};
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::MockNetworkAdapter;
 use near_network::NetworkRequests;
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::block::Approval;
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::block_header::ApprovalType;
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::challenge::{ApprovalDoubleSign, SlashedValidator};
 use near_primitives::challenge::{
     BlockDoubleSign, Challenge, ChallengeBody, ChunkProofs, MaybeEncodedShardChunk,
 };
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, MerklePath, PartialMerkleTree};
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use near_primitives::network::PeerId;
 use near_primitives::receipt::Receipt;
 use near_primitives::serialize::BaseDecode;
 use near_primitives::sharding::{EncodedShardChunk, ReedSolomonWrapper};
@@ -48,6 +56,7 @@ fn test_verify_block_double_sign_challenge() {
     let mut block_merkle_tree = PartialMerkleTree::default();
     block_merkle_tree.insert(*genesis.hash());
     let b2 = Block::produce(
+        PROTOCOL_VERSION,
         PROTOCOL_VERSION,
         genesis.header(),
         2,
@@ -119,6 +128,72 @@ fn test_verify_block_double_sign_challenge() {
     }
 }
 
+/// A validator approving two different blocks at one height is detected by the peers, and the
+/// challenge with both approvals is included into a block to slash the validator.
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+#[test]
+fn test_approval_double_sign_challenge() {
+    init_test_logger();
+    let mut env = TestEnv::new(ChainGenesis::test(), 2, 1);
+    for i in 1..=2 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let b1 = env.clients[0].chain.get_block_by_height(1).unwrap().clone();
+    let b2 = env.clients[0].chain.get_block_by_height(2).unwrap().clone();
+
+    let signer = InMemoryValidatorSigner::from_seed("test0", KeyType::ED25519, "test0");
+    let endorsement = Approval::new(*b2.hash(), 2, 3, &signer);
+    let skip = Approval::new(*b1.hash(), 1, 3, &signer);
+    let peer_id = PeerId::random();
+    while env.network_adapters[1].pop().is_some() {}
+    env.clients[1]
+        .collect_block_approval(&endorsement, ApprovalType::PeerApproval(peer_id.clone()));
+    assert!(env.network_adapters[1].pop().is_none());
+    env.clients[1].collect_block_approval(&skip, ApprovalType::PeerApproval(peer_id));
+    let challenge = match env.network_adapters[1].pop() {
+        Some(NetworkRequests::Challenge(challenge)) => challenge,
+        request => panic!("Expected a challenge, got {:?}", request),
+    };
+    assert_eq!(
+        challenge.body,
+        ChallengeBody::ApprovalDoubleSign(ApprovalDoubleSign {
+            left_approval: endorsement.clone(),
+            right_approval: skip,
+        })
+    );
+
+    let runtime_adapter = env.clients[0].chain.runtime_adapter.clone();
+    let (_, slashed) =
+        validate_challenge(&*runtime_adapter, b2.header().epoch_id(), b2.hash(), &challenge)
+            .unwrap();
+    assert_eq!(slashed, vec!["test0".to_string()]);
+    let invalid_challenge = Challenge::produce(
+        ChallengeBody::ApprovalDoubleSign(ApprovalDoubleSign {
+            left_approval: endorsement.clone(),
+            right_approval: endorsement,
+        }),
+        &signer,
+    );
+    assert!(validate_challenge(
+        &*runtime_adapter,
+        b2.header().epoch_id(),
+        b2.hash(),
+        &invalid_challenge
+    )
+    .is_err());
+
+    env.clients[0].process_challenge(challenge.clone()).unwrap();
+    env.produce_block(0, 3);
+    assert_eq!(env.clients[0].chain.get_block_by_height(3).unwrap().challenges(), &[challenge]);
+    env.produce_block(0, 4);
+    assert_eq!(
+        env.clients[0].chain.get_block_by_height(4).unwrap().header().challenges_result(),
+        &vec![SlashedValidator::new("test0".to_string(), true)]
+    );
+}
+
 fn create_invalid_proofs_chunk(
     client: &mut Client,
 ) -> (EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>, Block) {
@@ -353,6 +428,7 @@ fn test_verify_chunk_invalid_state_challenge() {
         client.chain.mut_store().get_block_merkle_tree(&last_block.hash()).unwrap().clone();
     block_merkle_tree.insert(*last_block.hash());
     let block = Block::produce(
+        PROTOCOL_VERSION,
         PROTOCOL_VERSION,
         &last_block.header(),
         last_block.header().height() + 1,
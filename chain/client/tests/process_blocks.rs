@@ -291,6 +291,7 @@ fn receive_network_block() {
             #[cfg(feature = "protocol_feature_block_header_v3")]
             let next_block_ordinal = last_block.header.block_ordinal.unwrap() + 1;
             let block = Block::produce(
+                PROTOCOL_VERSION,
                 PROTOCOL_VERSION,
                 &last_block.header.clone().into(),
                 last_block.header.height + 1,
@@ -366,6 +367,7 @@ fn produce_block_with_approvals() {
             #[cfg(feature = "protocol_feature_block_header_v3")]
             let next_block_ordinal = last_block.header.block_ordinal.unwrap() + 1;
             let block = Block::produce(
+                PROTOCOL_VERSION,
                 PROTOCOL_VERSION,
                 &last_block.header.clone().into(),
                 last_block.header.height + 1,
@@ -533,6 +535,7 @@ fn invalid_blocks_common(is_requested: bool) {
             #[cfg(feature = "protocol_feature_block_header_v3")]
             let next_block_ordinal = last_block.header.block_ordinal.unwrap() + 1;
             let valid_block = Block::produce(
+                PROTOCOL_VERSION,
                 PROTOCOL_VERSION,
                 &last_block.header.clone().into(),
                 last_block.header.height + 1,
@@ -847,6 +850,7 @@ fn client_sync_headers() {
             sent_bytes_per_sec: 0,
             received_bytes_per_sec: 0,
             known_producers: vec![],
+            peer_traffic: vec![],
             #[cfg(feature = "metric_recorder")]
             metric_recorder: MetricRecorder::default(),
             peer_counter: 0,
@@ -1677,6 +1681,49 @@ fn test_gc_tail_update() {
     assert_eq!(env.clients[1].chain.store().tail().unwrap(), prev_sync_block.header().height());
 }
 
+/// Test that a node syncing only the headers records the same epoch light client blocks as a
+/// node processing the blocks.
+#[test]
+fn test_header_only_epoch_light_client_blocks() {
+    let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
+    let epoch_length = 5;
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 2, 1, create_nightshade_runtimes(&genesis, 2));
+    env.clients[1].chain.header_only = true;
+    let mut headers = vec![];
+    for i in 1..=epoch_length * 3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        headers.push(block.header().clone());
+    }
+    let mut epoch_ids = headers.iter().map(|header| header.epoch_id().clone()).collect::<Vec<_>>();
+    epoch_ids.dedup();
+    assert_eq!(epoch_ids.len(), 3);
+
+    env.clients[1].sync_block_headers(headers).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().height, epoch_length * 3);
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 0);
+    // The light client block of an epoch is recorded once the next epoch starts.
+    for epoch_id in &epoch_ids[..2] {
+        let expected = env.clients[0]
+            .chain
+            .mut_store()
+            .get_epoch_light_client_block(&epoch_id.0)
+            .unwrap()
+            .clone();
+        let light_client_block = env.clients[1]
+            .chain
+            .mut_store()
+            .get_epoch_light_client_block(&epoch_id.0)
+            .unwrap()
+            .clone();
+        assert_eq!(light_client_block, expected);
+    }
+}
+
 /// Test that transaction does not become invalid when there is some gas price change.
 #[test]
 fn test_gas_price_change() {
@@ -1744,6 +1791,119 @@ fn test_gas_price_change() {
     }
 }
 
+/// A transaction reusing the nonce of another transaction of the same signer is dropped when the
+/// chunk is prepared and is reported among the dropped transactions of the client.
+#[test]
+fn test_dropped_transaction_with_reused_nonce() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0", "test1"], 1);
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test1", KeyType::ED25519, "test1");
+    for amount in 1..=2 {
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".to_string(),
+            "test0".to_string(),
+            &signer,
+            amount,
+            genesis_hash,
+        );
+        assert_eq!(env.clients[0].process_tx(tx, false, false), NetworkClientResponses::ValidTx);
+    }
+    for i in 1..=4 {
+        env.produce_block(0, i);
+    }
+    let dropped = env.clients[0].dropped_transactions().collect::<Vec<_>>();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].signer_id, "test1");
+    assert_eq!(dropped[0].reason, InvalidTxError::InvalidNonce { tx_nonce: 1, ak_nonce: 1 });
+}
+
+/// Every stage of the lifecycle of a transaction submitted to a block producer is traced.
+#[test]
+fn test_transaction_trace() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0", "test1"], 1);
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test1", KeyType::ED25519, "test1");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test1".to_string(),
+        "test0".to_string(),
+        &signer,
+        1,
+        genesis_hash,
+    );
+    let tx_hash = tx.get_hash();
+    assert_eq!(env.clients[0].process_tx(tx, false, false), NetworkClientResponses::ValidTx);
+    for i in 1..=5 {
+        env.produce_block(0, i);
+    }
+    let trace = env.clients[0].transaction_trace(&tx_hash).unwrap();
+    assert!(!trace.is_forwarded);
+    assert!(trace.received.is_some());
+    assert!(trace.inserted_to_pool.is_some());
+    assert!(trace.included_in_chunk.is_some());
+    assert!(trace.applied.is_some());
+    assert!(trace.finalized.is_some());
+    assert_eq!(trace.shard_id, Some(0));
+    assert_eq!(trace.receipt_ids.len(), 1);
+    assert!(env.clients[0].transaction_trace(&CryptoHash::default()).is_none());
+}
+
+/// Chunks are validated with the trie nodes of a witness only, and witnesses with missing nodes
+/// or other transactions are rejected.
+#[test]
+fn test_chunk_state_witness() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0", "test1"], 1);
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test1", KeyType::ED25519, "test1");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test1".to_string(),
+        "test0".to_string(),
+        &signer,
+        1,
+        genesis_hash,
+    );
+    assert_eq!(env.clients[0].process_tx(tx, false, false), NetworkClientResponses::ValidTx);
+    for i in 1..=4 {
+        env.produce_block(0, i);
+    }
+    let chain = &mut env.clients[0].chain;
+    for height in 1..4 {
+        let block_hash = *chain.get_block_by_height(height).unwrap().hash();
+        let next_chunk_header = chain.get_block_by_height(height + 1).unwrap().chunks()[0].clone();
+        let witness = chain.create_chunk_state_witness(&block_hash, 0).unwrap();
+        chain.validate_chunk_state_witness(&witness, &next_chunk_header).unwrap();
+        if witness.transactions.is_empty() {
+            continue;
+        }
+
+        let mut incomplete_witness = witness.clone();
+        incomplete_witness.partial_state.0.pop();
+        assert!(chain
+            .validate_chunk_state_witness(&incomplete_witness, &next_chunk_header)
+            .is_err());
+
+        let mut other_witness = witness.clone();
+        other_witness.transactions.clear();
+        let err =
+            chain.validate_chunk_state_witness(&other_witness, &next_chunk_header).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidChunkTxRoot);
+    }
+}
+
 #[test]
 fn test_gas_price_overflow() {
     let mut genesis = Genesis::test(vec!["test0", "test1"], 1);
@@ -2204,7 +2364,13 @@ fn test_catchup_gas_price_change() {
             .set_state_part(0, sync_hash, i, num_parts, &state_sync_parts[i as usize])
             .unwrap();
     }
+    // The downloaded parts are persisted, so state sync can be resumed after a restart.
+    let progress = env.clients[1].chain.get_state_sync_progress(0, sync_hash).unwrap();
+    assert!((0..num_parts).all(|part_id| progress.is_done(part_id)));
     env.clients[1].chain.set_state_finalize(0, sync_hash, num_parts).unwrap();
+    env.clients[1].chain.clear_downloaded_parts(0, sync_hash, num_parts).unwrap();
+    let progress = env.clients[1].chain.get_state_sync_progress(0, sync_hash).unwrap();
+    assert!(!progress.is_done(0));
     let chunk_extra_after_sync =
         env.clients[1].chain.get_chunk_extra(blocks[4].hash(), 0).unwrap().clone();
     let expected_chunk_extra =
@@ -3178,7 +3344,6 @@ mod storage_usage_fix_tests {
     use near_primitives::types::AccountId;
     use near_primitives::version::ProtocolFeature;
     use near_store::TrieUpdate;
-    use std::rc::Rc;
 
     fn process_blocks_with_storage_usage_fix(
         chain_id: String,
@@ -3207,7 +3372,7 @@ mod storage_usage_fix_tests {
 
             let root =
                 env.clients[0].chain.get_chunk_extra(block.hash(), 0).unwrap().state_root().clone();
-            let trie = Rc::new(env.clients[0].runtime_adapter.get_trie_for_shard(0));
+            let trie = Arc::new(env.clients[0].runtime_adapter.get_trie_for_shard(0));
             let state_update = TrieUpdate::new(trie.clone(), root);
             use near_primitives::account::Account;
             let mut account_near_raw = state_update
@@ -3308,7 +3473,6 @@ mod contract_precompilation_tests {
     #[cfg(feature = "protocol_feature_evm")]
     use nearcore::config::TESTNET_EVM_CHAIN_ID;
     use node_runtime::state_viewer::TrieViewer;
-    use std::rc::Rc;
 
     const EPOCH_LENGTH: u64 = 5;
 
@@ -3422,7 +3586,7 @@ mod contract_precompilation_tests {
         let state_root = chunk_extra.state_root().clone();
 
         let viewer = TrieViewer::default();
-        let trie = Rc::new(env.clients[1].runtime_adapter.get_trie_for_shard(0));
+        let trie = Arc::new(env.clients[1].runtime_adapter.get_trie_for_shard(0));
         let state_update = TrieUpdate::new(trie, state_root);
 
         let mut logs = vec![];
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
@@ -6,26 +7,36 @@ use cached::{Cached, SizedCache};
 use log::{debug, warn};
 use primitive_types::U256;
 
+use near_primitives::checked_feature;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::{EpochInfo, EpochSummary};
-use near_primitives::epoch_manager::{EpochConfig, SlashState, AGGREGATOR_KEY};
+use near_primitives::epoch_manager::{
+    EpochConfig, SlashState, AGGREGATOR_KEY, VALIDATOR_SET_OVERRIDE_KEY,
+};
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
-    AccountId, ApprovalStake, Balance, BlockChunkValidatorStats, BlockHeight, EpochId, ShardId,
-    ValidatorId, ValidatorKickoutReason, ValidatorStats,
+    AccountId, ApprovalStake, Balance, BlockChunkValidatorStats, BlockHeight, EpochId,
+    ShardChunkValidatorStats, ShardId, ValidatorId, ValidatorKickoutReason, ValidatorStats,
 };
 use near_primitives::version::{ProtocolVersion, UPGRADABILITY_FIX_PROTOCOL_VERSION};
 use near_primitives::views::{
-    CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
+    CurrentEpochValidatorInfo, EpochValidatorDiffView, EpochValidatorInfo, NextEpochValidatorInfo,
+    ValidatorKickoutView, ValidatorStakeChangeView,
 };
 use near_store::{ColBlockInfo, ColEpochInfo, ColEpochStart, Store, StoreUpdate};
 
-use crate::proposals::proposals_to_epoch_info;
+use crate::proposals::{
+    check_genesis_epoch_info, override_validator_set, proposals_to_epoch_info,
+    proposals_to_seat_price,
+};
 pub use crate::reward_calculator::RewardCalculator;
+use crate::seed::derive_epoch_seed;
+pub use crate::seed::{verify_epoch_seed, NUM_SEED_BLOCKS};
 use crate::types::EpochInfoAggregator;
-pub use crate::types::RngSeed;
+pub use crate::types::{RngSeed, ValidatorSetOverride};
 
 pub use crate::reward_calculator::NUM_SECONDS_IN_A_YEAR;
 use near_chain::types::{BlockHeaderInfo, ValidatorInfoIdentifier};
@@ -33,6 +44,7 @@ use near_store::db::DBCol::ColEpochValidatorInfo;
 
 mod proposals;
 mod reward_calculator;
+mod seed;
 pub mod test_utils;
 mod types;
 
@@ -65,6 +77,11 @@ pub struct EpochManager {
     epoch_info_aggregator: Option<EpochInfoAggregator>,
     /// Largest final height. Monotonically increasing.
     largest_final_height: BlockHeight,
+    /// Validator set forced on the network at a given height, if any.
+    validator_set_override: Option<ValidatorSetOverride>,
+    /// Validator set override applied to the chain, persisted in the store. Its seats are used
+    /// for every epoch from its height on.
+    applied_validator_set_override: Option<ValidatorSetOverride>,
 }
 
 impl EpochManager {
@@ -75,8 +92,9 @@ impl EpochManager {
         reward_calculator: RewardCalculator,
         validators: Vec<ValidatorStake>,
     ) -> Result<Self, EpochError> {
-        let validator_reward = vec![(reward_calculator.protocol_treasury_account.clone(), 0u128)]
-            .into_iter()
+        let validator_reward = std::iter::once(&reward_calculator.protocol_treasury_account)
+            .chain(reward_calculator.protocol_treasury_split.iter().map(|share| &share.account_id))
+            .map(|account_id| (account_id.clone(), 0u128))
             .collect();
         let mut epoch_manager = EpochManager {
             store,
@@ -90,7 +108,13 @@ impl EpochManager {
             epoch_validators_ordered_unique: SizedCache::with_size(EPOCH_CACHE_SIZE),
             epoch_info_aggregator: None,
             largest_final_height: 0,
+            validator_set_override: None,
+            applied_validator_set_override: None,
         };
+        epoch_manager.applied_validator_set_override = epoch_manager
+            .store
+            .get_ser(ColEpochInfo, VALIDATOR_SET_OVERRIDE_KEY)
+            .map_err(EpochError::from)?;
         let genesis_epoch_id = EpochId::default();
         if !epoch_manager.has_epoch_info(&genesis_epoch_id)? {
             // Missing genesis epoch, means that there is no validator initialize yet.
@@ -98,12 +122,13 @@ impl EpochManager {
                 &epoch_manager.config,
                 [0; 32],
                 &EpochInfo::default(),
-                validators,
+                validators.clone(),
                 HashMap::default(),
                 validator_reward,
                 0,
                 genesis_protocol_version,
             )?;
+            check_genesis_epoch_info(&epoch_manager.config, &validators, &epoch_info)?;
             // Dummy block info.
             // Artificial block we add to simplify implementation: dummy block is the
             // parent of genesis block that points to itself.
@@ -137,10 +162,24 @@ impl EpochManager {
         self.save_epoch_info(&mut store_update, &prev_epoch_id, prev_epoch_info)?;
         self.save_epoch_info(&mut store_update, &epoch_id, epoch_info)?;
         self.save_epoch_info(&mut store_update, &next_epoch_id, next_epoch_info)?;
-        // TODO #3488
-        // put unreachable! here to avoid warnings
-        unreachable!();
-        // Ok(store_update)
+        Ok(store_update)
+    }
+
+    /// Sets the validator set to force on the network, see `ValidatorSetOverride`. It must have
+    /// been checked with `ValidatorSetOverride::validate`.
+    pub fn set_validator_set_override(&mut self, validator_set_override: ValidatorSetOverride) {
+        self.validator_set_override = Some(validator_set_override);
+    }
+
+    /// Epoch config to assign the seats of the epoch finalized at `height`, which is overridden
+    /// from the height of the applied validator set override on.
+    fn epoch_config_at(&self, height: BlockHeight) -> Cow<EpochConfig> {
+        match &self.applied_validator_set_override {
+            Some(validator_set_override) if validator_set_override.height <= height => {
+                Cow::Owned(validator_set_override.epoch_config(&self.config))
+            }
+            _ => Cow::Borrowed(&self.config),
+        }
     }
 
     /// # Parameters
@@ -328,6 +367,19 @@ impl EpochManager {
             prev_validator_kickout,
         );
         validator_kickout.extend(kickout);
+        let mut validator_shard_chunk_stats: HashMap<AccountId, ShardChunkValidatorStats> =
+            HashMap::new();
+        for (shard_id, tracker) in chunk_validator_tracker.iter() {
+            for (validator_id, stats) in tracker.iter() {
+                let account_id = epoch_info.validator_account_id(*validator_id);
+                if validator_block_chunk_stats.contains_key(account_id) {
+                    validator_shard_chunk_stats
+                        .entry(account_id.clone())
+                        .or_default()
+                        .insert(*shard_id, stats.clone());
+                }
+            }
+        }
         debug!(
             target: "epoch_manager",
             "All proposals: {:?}, Kickouts: {:?}, Block Tracker: {:?}, Shard Tracker: {:?}",
@@ -340,19 +392,22 @@ impl EpochManager {
             validator_kickout,
             validator_block_chunk_stats,
             next_version,
+            validator_shard_chunk_stats,
+            uptime_curve: Some(self.reward_calculator.uptime_curve()),
         })
     }
 
     /// Finalizes epoch (T), where given last block hash is given, and returns next next epoch id (T + 2).
+    /// `random_values` are the random values of the last blocks of the epoch, oldest first.
     fn finalize_epoch(
         &mut self,
         store_update: &mut StoreUpdate,
         block_info: &BlockInfo,
         last_block_hash: &CryptoHash,
-        rng_seed: RngSeed,
+        random_values: &[CryptoHash],
     ) -> Result<EpochId, EpochError> {
         let epoch_summary = self.collect_blocks_info(&block_info, last_block_hash)?;
-        let epoch_info = self.get_epoch_info(&block_info.epoch_id())?;
+        let epoch_info = self.get_epoch_info(&block_info.epoch_id())?.clone();
         let epoch_protocol_version = epoch_info.protocol_version();
         let validator_stake =
             epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
@@ -366,17 +421,22 @@ impl EpochManager {
             validator_kickout,
             validator_block_chunk_stats,
             next_version,
+            validator_shard_chunk_stats,
+            ..
         } = epoch_summary;
 
+        let last_epoch_last_block_hash =
+            *self.get_block_info(block_info.epoch_first_block())?.prev_hash();
+        let last_epoch_last_block_height =
+            *self.get_block_info(&last_epoch_last_block_hash)?.height();
         let (validator_reward, minted_amount) = {
-            let last_epoch_last_block_hash =
-                *self.get_block_info(block_info.epoch_first_block())?.prev_hash();
             let last_block_in_last_epoch = self.get_block_info(&last_epoch_last_block_hash)?;
             assert!(block_info.timestamp_nanosec() > last_block_in_last_epoch.timestamp_nanosec());
             let epoch_duration =
                 block_info.timestamp_nanosec() - last_block_in_last_epoch.timestamp_nanosec();
             self.reward_calculator.calculate_reward(
                 validator_block_chunk_stats,
+                &validator_shard_chunk_stats,
                 &validator_stake,
                 *block_info.total_supply(),
                 epoch_protocol_version,
@@ -384,8 +444,42 @@ impl EpochManager {
                 epoch_duration,
             )
         };
+        let seed_from_vrf = checked_feature!(
+            "protocol_feature_epoch_seed_from_vrf",
+            EpochSeedFromVrf,
+            next_version
+        );
+        let rng_seed = if seed_from_vrf {
+            derive_epoch_seed(random_values)
+        } else {
+            // The seed used to be the random value of the last block of the epoch.
+            random_values.last().map_or_else(RngSeed::default, |random_value| random_value.0)
+        };
+        let (all_proposals, validator_kickout) = match &self.validator_set_override {
+            Some(validator_set_override)
+                if last_epoch_last_block_height < validator_set_override.height
+                    && validator_set_override.height <= *block_info.height() =>
+            {
+                warn!(target: "epoch_manager", "Overriding the validator set of epoch {:?} with {:?}", last_block_hash, validator_set_override);
+                let validator_set_override = validator_set_override.clone();
+                let overridden = override_validator_set(
+                    &validator_set_override,
+                    &epoch_info,
+                    &next_epoch_info,
+                    all_proposals,
+                )?;
+                store_update.set_ser(
+                    ColEpochInfo,
+                    VALIDATOR_SET_OVERRIDE_KEY,
+                    &validator_set_override,
+                )?;
+                self.applied_validator_set_override = Some(validator_set_override);
+                overridden
+            }
+            _ => (all_proposals, validator_kickout),
+        };
         let next_next_epoch_info = match proposals_to_epoch_info(
-            &self.config,
+            &self.epoch_config_at(*block_info.height()),
             rng_seed,
             &next_epoch_info,
             all_proposals,
@@ -394,6 +488,10 @@ impl EpochManager {
             minted_amount,
             next_version,
         ) {
+            #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+            Ok(next_next_epoch_info) if seed_from_vrf => {
+                next_next_epoch_info.with_rng_seed_inputs(random_values.to_vec())
+            }
             Ok(next_next_epoch_info) => next_next_epoch_info,
             Err(EpochError::ThresholdError { stake_sum, num_seats }) => {
                 warn!(target: "epoch_manager", "Not enough stake for required number of seats (all validators tried to unstake?): amount = {} for {}", stake_sum, num_seats);
@@ -410,10 +508,12 @@ impl EpochManager {
         Ok(EpochId(prev_epoch_last_block_hash))
     }
 
+    /// Records the block and finalizes the epoch if it's the last block of it. `random_values` are
+    /// the random values of up to `NUM_SEED_BLOCKS` last blocks ending with this one, oldest first.
     pub fn record_block_info(
         &mut self,
         mut block_info: BlockInfo,
-        rng_seed: RngSeed,
+        random_values: &[CryptoHash],
     ) -> Result<StoreUpdate, EpochError> {
         let current_hash = *block_info.hash();
         let mut store_update = self.store.store_update();
@@ -459,6 +559,7 @@ impl EpochManager {
                     if is_epoch_start {
                         if slash_state == &SlashState::DoubleSign
                             || slash_state == &SlashState::Other
+                            || slash_state.is_appealed()
                         {
                             block_info
                                 .slashed_mut()
@@ -476,12 +577,19 @@ impl EpochManager {
                             .entry(account_id.clone())
                             .and_modify(|e| {
                                 if let SlashState::Other = slash_state {
-                                    *e = SlashState::Other;
+                                    if !e.is_appealed() {
+                                        *e = SlashState::Other;
+                                    }
                                 }
                             })
                             .or_insert_with(|| slash_state.clone());
                     }
                 }
+                // Appeals only matter for validators that were slashed before.
+                #[cfg(feature = "protocol_feature_slashing_escrow")]
+                block_info.slashed_mut().retain(|account_id, slash_state| {
+                    !slash_state.is_appealed() || prev_block_info.slashed().contains_key(account_id)
+                });
 
                 if is_epoch_start {
                     self.save_epoch_start(
@@ -541,7 +649,12 @@ impl EpochManager {
 
                 // If this is the last block in the epoch, finalize this epoch.
                 if self.is_next_block_in_next_epoch(&block_info)? {
-                    self.finalize_epoch(&mut store_update, &block_info, &current_hash, rng_seed)?;
+                    self.finalize_epoch(
+                        &mut store_update,
+                        &block_info,
+                        &current_hash,
+                        random_values,
+                    )?;
                 }
             }
         }
@@ -858,6 +971,24 @@ impl EpochManager {
         Ok((stake_info, validator_reward, slashing_info))
     }
 
+    /// Returns accounts whose slashes were cancelled by correct appeals in the epoch of the given
+    /// block, with the hashes of the blocks which caused the cancelled slashes. The stake escrowed
+    /// by these slashes should be returned.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    pub fn compute_slash_appeals(
+        &mut self,
+        last_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, CryptoHash>, EpochError> {
+        Ok(self
+            .get_slashed_validators(last_block_hash)?
+            .iter()
+            .filter_map(|(account_id, slash_state)| match slash_state {
+                SlashState::Appealed(block_hash) => Some((account_id.clone(), *block_hash)),
+                _ => None,
+            })
+            .collect())
+    }
+
     /// Compute slashing information. Returns a hashmap of account id to slashed amount for double sign
     /// slashing.
     fn compute_double_sign_slashing_info(
@@ -1044,6 +1175,59 @@ impl EpochManager {
         })
     }
 
+    /// Get the changes of the validator set of the given epoch relative to the previous epoch.
+    /// The genesis epoch has no previous epoch and is compared with itself.
+    pub fn get_validator_diff(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochValidatorDiffView, EpochError> {
+        // Epoch T is identified by the last block of epoch T - 2, which belongs to the epoch
+        // preceding epoch T - 1.
+        let prev_epoch_id = self.get_next_epoch_id(&epoch_id.0)?;
+        let prev_epoch_info = self.get_epoch_info(&prev_epoch_id)?.clone();
+        let epoch_info = self.get_epoch_info(epoch_id)?.clone();
+
+        let mut joined = vec![];
+        let mut stake_changed = vec![];
+        for validator in epoch_info.validators_iter() {
+            match prev_epoch_info.get_validator_by_account(validator.account_id()) {
+                None => joined.push(validator.into()),
+                Some(prev_validator) if prev_validator.stake() != validator.stake() => {
+                    stake_changed.push(ValidatorStakeChangeView {
+                        account_id: validator.account_id().clone(),
+                        prev_stake: prev_validator.stake(),
+                        stake: validator.stake(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        let mut left = vec![];
+        let mut kicked = vec![];
+        for validator in prev_epoch_info.validators_iter() {
+            if epoch_info.get_validator_id(validator.account_id()).is_some() {
+                continue;
+            }
+            match epoch_info.validator_kickout().get(validator.account_id()) {
+                Some(reason) => kicked.push(ValidatorKickoutView {
+                    account_id: validator.account_id().clone(),
+                    reason: reason.clone(),
+                }),
+                None => left.push(validator.into()),
+            }
+        }
+
+        Ok(EpochValidatorDiffView {
+            epoch_id: epoch_id.clone(),
+            prev_epoch_id,
+            epoch_height: epoch_info.epoch_height(),
+            joined,
+            left,
+            kicked,
+            stake_changed,
+        })
+    }
+
     /// Compare two epoch ids based on their start height. This works because finality gadget
     /// guarantees that we cannot have two different epochs on two forks
     pub fn compare_epoch_id(
@@ -1073,6 +1257,27 @@ impl EpochManager {
         Ok(self.get_epoch_info(&next_epoch_id)?.seat_price() / stake_divisor)
     }
 
+    /// Returns the seat price of the epoch: the minimum stake of a validator with a seat in it.
+    pub fn get_seat_price(&mut self, epoch_id: &EpochId) -> Result<Balance, EpochError> {
+        Ok(self.get_epoch_info(epoch_id)?.seat_price())
+    }
+
+    /// Estimates the minimum stake which gets a seat in the epoch assigned from the proposals of
+    /// the epoch of `block_hash`, i.e. the epoch after the next one, given the proposals made up
+    /// to `block_hash`. Returns `None` if the stake isn't enough to fill all the seats.
+    pub fn minimum_stake_to_join(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<Option<Balance>, EpochError> {
+        let epoch_id = self.get_block_info(block_hash)?.epoch_id().clone();
+        let aggregator = self.get_and_update_epoch_info_aggregator(&epoch_id, block_hash, true)?;
+        let next_epoch_id = self.get_next_epoch_id(block_hash)?;
+        let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
+        let proposals = aggregator.all_proposals.into_iter().map(|(_, p)| p).collect();
+        let height = *self.get_block_info(block_hash)?.height();
+        proposals_to_seat_price(&self.epoch_config_at(height), &next_epoch_info, proposals)
+    }
+
     // Note: this function should only be used in 18 -> 19 migration and should be removed in the
     // next release
     /// `block_header_info` must be the header info of the last block of an epoch.
@@ -1138,6 +1343,10 @@ impl EpochManager {
             *self.get_block_info(block_info.epoch_first_block())?.height()
                 + self.config.epoch_length;
 
+        if self.is_epoch_shorter_than_min_duration(block_info)? {
+            return Ok(false);
+        }
+
         if self.config.epoch_length <= 3 {
             // This is here to make epoch_manager tests pass. Needs to be removed, tracked in
             // https://github.com/nearprotocol/nearcore/issues/2522
@@ -1159,8 +1368,25 @@ impl EpochManager {
         let estimated_next_epoch_start =
             *self.get_block_info(block_info.epoch_first_block())?.height()
                 + self.config.epoch_length;
-        Ok(*block_info.last_finalized_height() + 3 < estimated_next_epoch_start
-            && *block_info.height() + 3 >= estimated_next_epoch_start)
+        Ok(*block_info.height() + 3 >= estimated_next_epoch_start
+            && (*block_info.last_finalized_height() + 3 < estimated_next_epoch_start
+                // Any block of an epoch extended to its minimum duration may be the last one.
+                || self.is_epoch_shorter_than_min_duration(block_info)?))
+    }
+
+    /// Returns true if the epoch of the given block has lasted less than the minimum epoch
+    /// duration, in which case the epoch doesn't end even if it reached its length in heights.
+    fn is_epoch_shorter_than_min_duration(
+        &mut self,
+        block_info: &BlockInfo,
+    ) -> Result<bool, EpochError> {
+        if self.config.min_epoch_duration_nanosec == 0 {
+            return Ok(false);
+        }
+        let epoch_start_timestamp =
+            *self.get_block_info(block_info.epoch_first_block())?.timestamp_nanosec();
+        Ok(block_info.timestamp_nanosec().saturating_sub(epoch_start_timestamp)
+            < self.config.min_epoch_duration_nanosec)
     }
 
     /// Returns epoch id for the next epoch (T+1), given an block info in current epoch (T).
@@ -1186,6 +1412,17 @@ impl EpochManager {
         self.epochs_info.cache_get(epoch_id).ok_or(EpochError::EpochOutOfBounds(epoch_id.clone()))
     }
 
+    /// Returns the layout of the shards in the given epoch. The chain keeps the layout of the
+    /// genesis until its protocol version has resharding.
+    pub fn get_shard_layout(&mut self, epoch_id: &EpochId) -> Result<ShardLayout, EpochError> {
+        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
+        Ok(ShardLayout::for_protocol_version(
+            self.config.num_shards,
+            self.config.resharding_shard_layout.as_ref(),
+            protocol_version,
+        ))
+    }
+
     fn has_epoch_info(&mut self, epoch_id: &EpochId) -> Result<bool, EpochError> {
         match self.get_epoch_info(epoch_id) {
             Ok(_) => Ok(true),
@@ -1513,6 +1750,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_validator_diff() {
+        let amount_staked = 1_000_000;
+        let validators = vec![("test1", amount_staked), ("test2", amount_staked)];
+        let mut epoch_manager = setup_default_epoch_manager(validators, 1, 1, 2, 0, 90, 60);
+        epoch_manager.config.fishermen_threshold = 100;
+
+        let h = hash_range(5);
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        record_block(
+            &mut epoch_manager,
+            h[0],
+            h[1],
+            1,
+            vec![
+                stake("test1", 10),
+                stake("test2", 2 * amount_staked),
+                stake("test3", amount_staked),
+            ],
+        );
+        record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+        record_block(&mut epoch_manager, h[2], h[3], 3, vec![]);
+        record_block(&mut epoch_manager, h[3], h[4], 4, vec![]);
+
+        let prev_epoch_id = epoch_manager.get_epoch_id(&h[2]).unwrap();
+        let epoch_id = epoch_manager.get_epoch_id(&h[3]).unwrap();
+        let diff = epoch_manager.get_validator_diff(&epoch_id).unwrap();
+        assert_eq!(diff.prev_epoch_id, prev_epoch_id);
+        assert_eq!(
+            diff.joined.into_iter().map(|v| v.take_account_id()).collect::<Vec<_>>(),
+            vec!["test3".to_string()]
+        );
+        assert_eq!(diff.left, vec![]);
+        assert_eq!(diff.kicked.len(), 1);
+        assert_eq!(diff.kicked[0].account_id, "test1".to_string());
+        assert!(matches!(
+            diff.kicked[0].reason,
+            ValidatorKickoutReason::NotEnoughStake { stake: 10, .. }
+        ));
+        assert_eq!(
+            diff.stake_changed,
+            vec![ValidatorStakeChangeView {
+                account_id: "test2".to_string(),
+                prev_stake: amount_staked,
+                stake: 2 * amount_staked,
+            }]
+        );
+
+        // Nothing changes in the following epoch.
+        let epoch_id = epoch_manager.get_epoch_id(&h[4]).unwrap();
+        let diff = epoch_manager.get_validator_diff(&epoch_id).unwrap();
+        assert_eq!(diff.prev_epoch_id, epoch_manager.get_epoch_id(&h[3]).unwrap());
+        assert!(diff.joined.is_empty() && diff.left.is_empty() && diff.kicked.is_empty());
+        assert!(diff.stake_changed.is_empty());
+    }
+
+    #[test]
+    fn test_seat_price() {
+        let amount_staked = 1_000_000;
+        let validators = vec![("test1", amount_staked), ("test2", amount_staked)];
+        let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 2, 0, 90, 60);
+
+        let h = hash_range(2);
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        record_block(&mut epoch_manager, h[0], h[1], 1, vec![stake("test3", 4 * amount_staked)]);
+        let epoch_id = epoch_manager.get_epoch_id(&h[1]).unwrap();
+        assert_eq!(epoch_manager.get_seat_price(&epoch_id).unwrap(), amount_staked);
+        // The new proposal would take both seats with any seat price above half of its stake.
+        assert_eq!(epoch_manager.minimum_stake_to_join(&h[1]).unwrap(), Some(2 * amount_staked));
+    }
+
     /// Test handling forks across the epoch finalization.
     /// Fork with where one BP produces blocks in one chain and 2 BPs are in another chain.
     ///     |   | /--1---4------|--7---10------|---13---
@@ -1986,6 +2294,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(1, 10),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(90, 100),
             online_max_threshold: Rational::new(99, 100),
             num_seconds_per_year: 50,
@@ -2001,7 +2311,6 @@ mod tests {
             100,
             reward_calculator.clone(),
         );
-        let rng_seed = [0; 32];
         let h = hash_range(5);
 
         epoch_manager
@@ -2016,19 +2325,19 @@ mod tests {
                     vec![true],
                     total_supply,
                 ),
-                rng_seed,
+                &[],
             )
             .unwrap();
         epoch_manager
             .record_block_info(
                 block_info(h[1], 1, 1, h[0], h[0], h[1], vec![true], total_supply),
-                rng_seed,
+                &[],
             )
             .unwrap();
         epoch_manager
             .record_block_info(
                 block_info(h[2], 2, 2, h[1], h[1], h[1], vec![true], total_supply),
-                rng_seed,
+                &[],
             )
             .unwrap();
         let mut validator_online_ratio = HashMap::new();
@@ -2043,6 +2352,7 @@ mod tests {
         validator_stakes.insert("test2".to_string(), stake_amount);
         let (validator_reward, inflation) = reward_calculator.calculate_reward(
             validator_online_ratio,
+            &HashMap::new(),
             &validator_stakes,
             total_supply,
             PROTOCOL_VERSION,
@@ -2082,6 +2392,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(1, 10),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(90, 100),
             online_max_threshold: Rational::new(99, 100),
             num_seconds_per_year: 50,
@@ -2139,6 +2451,7 @@ mod tests {
         validators_stakes.insert("test2".to_string(), stake_amount2);
         let (validator_reward, inflation) = reward_calculator.calculate_reward(
             validator_online_ratio,
+            &HashMap::new(),
             &validators_stakes,
             total_supply,
             PROTOCOL_VERSION,
@@ -2189,6 +2502,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(1, 10),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(90, 100),
             online_max_threshold: Rational::new(99, 100),
             num_seconds_per_year: 1_000_000,
@@ -2239,6 +2554,7 @@ mod tests {
         validators_stakes.insert("test2".to_string(), stake_amount);
         let (validator_reward, inflation) = reward_calculator.calculate_reward(
             validator_online_ratio,
+            &HashMap::new(),
             &validators_stakes,
             total_supply,
             PROTOCOL_VERSION,
@@ -2317,7 +2633,6 @@ mod tests {
             0,
             default_reward_calculator(),
         );
-        let rng_seed = [0; 32];
         let h = hash_range(5);
         epoch_manager
             .record_block_info(
@@ -2331,19 +2646,19 @@ mod tests {
                     vec![],
                     total_supply,
                 ),
-                rng_seed,
+                &[],
             )
             .unwrap();
         epoch_manager
             .record_block_info(
                 block_info(h[1], 1, 1, h[0], h[0], h[1], vec![true, true, true], total_supply),
-                rng_seed,
+                &[],
             )
             .unwrap();
         epoch_manager
             .record_block_info(
                 block_info(h[3], 3, 3, h[1], h[1], h[2], vec![true, true, true], total_supply),
-                rng_seed,
+                &[],
             )
             .unwrap();
         assert_eq!(
@@ -2384,7 +2699,6 @@ mod tests {
             0,
             default_reward_calculator(),
         );
-        let rng_seed = [0; 32];
         let h = hash_range(5);
         record_block(&mut epoch_manager, Default::default(), h[0], 0, vec![]);
         record_block(&mut epoch_manager, h[0], h[1], 1, vec![]);
@@ -2392,7 +2706,7 @@ mod tests {
         epoch_manager
             .record_block_info(
                 block_info(h[3], 3, 1, h[1], h[1], h[1], vec![false], total_supply),
-                rng_seed,
+                &[],
             )
             .unwrap();
         assert_eq!(
@@ -2461,6 +2775,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_min_epoch_duration() {
+        let validators = vec![("test1", 1_000_000)];
+        let epoch_length = 5;
+        let epoch_starts = |min_epoch_duration_nanosec: u64| {
+            let mut em =
+                setup_default_epoch_manager(validators.clone(), epoch_length, 1, 1, 0, 0, 0);
+            em.config.min_epoch_duration_nanosec = min_epoch_duration_nanosec;
+            let h = hash_range(21);
+            record_block(&mut em, Default::default(), h[0], 0, vec![]);
+            for height in 1..h.len() {
+                record_block(&mut em, h[height - 1], h[height], height as u64, vec![]);
+            }
+            (0..h.len() - 1)
+                .filter(|height| em.is_next_block_epoch_start(&h[*height]).unwrap())
+                .map(|height| height + 1)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(epoch_starts(0), vec![1, 6, 11, 16]);
+        // Blocks are produced every second, so epochs last at least 10 blocks.
+        assert_eq!(epoch_starts(10 * NUM_NS_IN_SECOND), vec![1, 12]);
+        // The minimum duration never shortens an epoch.
+        assert_eq!(epoch_starts(NUM_NS_IN_SECOND), vec![1, 6, 11, 16]);
+    }
+
     /// If the node stops and restarts, the aggregator should be able to recover
     #[test]
     fn test_epoch_info_aggregator_data_loss() {
@@ -2666,22 +3005,21 @@ mod tests {
             0,
             default_reward_calculator(),
         );
-        let rng_seed = [0; 32];
         let h = hash_range(5);
         record_block(&mut em, Default::default(), h[0], 0, vec![]);
         em.record_block_info(
             block_info(h[1], 1, 1, h[0], h[0], h[1], vec![true, true, true, false], total_supply),
-            rng_seed,
+            &[],
         )
         .unwrap();
         em.record_block_info(
             block_info(h[2], 2, 2, h[1], h[1], h[1], vec![true, true, true, false], total_supply),
-            rng_seed,
+            &[],
         )
         .unwrap();
         em.record_block_info(
             block_info(h[3], 3, 3, h[2], h[2], h[3], vec![true, true, true, true], total_supply),
-            rng_seed,
+            &[],
         )
         .unwrap();
         assert_eq!(
@@ -2769,6 +3107,8 @@ mod tests {
         match &mut epoch_info {
             EpochInfo::V1(info) => info.validator_kickout = HashMap::default(),
             EpochInfo::V2(info) => info.validator_kickout = HashMap::default(),
+            #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+            EpochInfo::V3(info) => info.validator_kickout = HashMap::default(),
         }
         #[cfg(not(feature = "protocol_feature_block_header_v3"))]
         {
@@ -3221,7 +3561,7 @@ mod tests {
         let mut block_info1 =
             block_info(h[1], 1, 1, h[0], h[0], h[0], vec![], DEFAULT_TOTAL_SUPPLY);
         set_block_info_protocol_version(&mut block_info1, 0);
-        epoch_manager.record_block_info(block_info1, [0; 32]).unwrap();
+        epoch_manager.record_block_info(block_info1, &[]).unwrap();
         for i in 2..6 {
             record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
         }
@@ -3252,7 +3592,7 @@ mod tests {
         let mut block_info1 =
             block_info(h[1], 1, 1, h[0], h[0], h[0], vec![], DEFAULT_TOTAL_SUPPLY);
         set_block_info_protocol_version(&mut block_info1, 0);
-        epoch_manager.record_block_info(block_info1, [0; 32]).unwrap();
+        epoch_manager.record_block_info(block_info1, &[]).unwrap();
         for i in 2..32 {
             record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
         }
@@ -3304,7 +3644,7 @@ mod tests {
                     UPGRADABILITY_FIX_PROTOCOL_VERSION,
                 );
             }
-            epoch_manager.record_block_info(block_info, [0; 32]).unwrap();
+            epoch_manager.record_block_info(block_info, &[]).unwrap();
         }
 
         assert_eq!(
@@ -3330,7 +3670,7 @@ mod tests {
                 DEFAULT_TOTAL_SUPPLY,
             );
             set_block_info_protocol_version(&mut block_info, UPGRADABILITY_FIX_PROTOCOL_VERSION);
-            epoch_manager.record_block_info(block_info, [0; 32]).unwrap();
+            epoch_manager.record_block_info(block_info, &[]).unwrap();
         }
         assert_eq!(
             epoch_manager.get_epoch_info(&EpochId(h[6])).unwrap().protocol_version(),
@@ -3368,7 +3708,7 @@ mod tests {
         epoch_manager
             .record_block_info(
                 block_info(h[5], 5, 1, h[1], h[2], h[1], vec![], DEFAULT_TOTAL_SUPPLY),
-                [0; 32],
+                &[],
             )
             .unwrap()
             .commit()
@@ -3405,4 +3745,80 @@ mod tests {
             epoch_manager.epoch_validators_ordered_unique.cache_get(&epoch_id).unwrap().clone();
         assert_eq!(epoch_validators_unique, epoch_validators_unique_in_cache);
     }
+
+    #[test]
+    fn test_validator_set_override() {
+        let amount_staked = 1_000_000;
+        let validators = vec![("test1", amount_staked), ("test2", amount_staked)];
+        let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 2, 0, 90, 60);
+        let new_validator = stake("test3", amount_staked);
+        let validator_set_override = ValidatorSetOverride {
+            height: 3,
+            validators: vec![near_primitives::types::AccountInfo {
+                account_id: new_validator.account_id().clone(),
+                public_key: new_validator.public_key().clone(),
+                amount: new_validator.stake(),
+            }],
+            num_block_producer_seats: Some(1),
+            num_block_producer_seats_per_shard: Some(vec![1]),
+            avg_hidden_validator_seats_per_shard: None,
+        };
+        validator_set_override.validate(&epoch_manager.config).unwrap();
+        epoch_manager.set_validator_set_override(validator_set_override.clone());
+
+        let h = hash_range(10);
+        record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+        for i in 1..10 {
+            // The stake of the new validator is locked by its proposal.
+            let proposals = if i == 3 { vec![new_validator.clone()] } else { vec![] };
+            record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, proposals);
+        }
+        // The override is applied once, by the epoch containing its height, and kept afterwards.
+        let mut recorded_overrides = vec![];
+        let mut last_validators = vec![];
+        for i in 0..10 {
+            let epoch_id = epoch_manager.get_epoch_id(&h[i]).unwrap();
+            let epoch_info = epoch_manager.get_epoch_info(&epoch_id).unwrap();
+            last_validators = epoch_info.validators_iter().map(|v| v.take_account_id()).collect();
+            if epoch_info
+                .validator_kickout()
+                .values()
+                .any(|reason| reason == &ValidatorKickoutReason::ValidatorSetOverride)
+                && !recorded_overrides.contains(&epoch_id)
+            {
+                recorded_overrides.push(epoch_id);
+            }
+        }
+        assert_eq!(last_validators, vec!["test3".to_string()]);
+        assert_eq!(recorded_overrides.len(), 1);
+        let epoch_info = epoch_manager.get_epoch_info(&recorded_overrides[0]).unwrap();
+        assert_eq!(
+            epoch_info.validators_iter().map(|v| v.take_account_id()).collect::<Vec<_>>(),
+            vec!["test3".to_string()]
+        );
+        assert_eq!(epoch_info.block_producers_settlement(), &[0]);
+        assert_eq!(
+            epoch_info.validator_kickout(),
+            &vec![
+                ("test1".to_string(), ValidatorKickoutReason::ValidatorSetOverride),
+                ("test2".to_string(), ValidatorKickoutReason::ValidatorSetOverride),
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        // The applied override is read back from the store, without the override file.
+        let epoch_manager = EpochManager::new(
+            epoch_manager.store.clone(),
+            epoch_manager.config.clone(),
+            PROTOCOL_VERSION,
+            default_reward_calculator(),
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(epoch_manager.validator_set_override, None);
+        assert_eq!(epoch_manager.applied_validator_set_override, Some(validator_set_override));
+        assert_eq!(epoch_manager.epoch_config_at(2).num_block_producer_seats, 2);
+        assert_eq!(epoch_manager.epoch_config_at(3).num_block_producer_seats, 1);
+    }
 }
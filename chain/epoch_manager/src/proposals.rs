@@ -1,14 +1,18 @@
+use std::cmp::max;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::iter;
 
+use log::warn;
+
+use near_primitives::checked_feature;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
-use near_primitives::epoch_manager::EpochConfig;
+use near_primitives::epoch_manager::{EpochConfig, StakeDenomination};
 use near_primitives::errors::EpochError;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, Balance, NumSeats, ValidatorId, ValidatorKickoutReason};
 use near_primitives::version::ProtocolVersion;
 
-use crate::types::RngSeed;
+use crate::types::{RngSeed, ValidatorSetOverride};
 
 /// Find threshold of stake per seat, given provided stakes and required number of seats.
 pub(crate) fn find_threshold(
@@ -37,7 +41,58 @@ pub(crate) fn find_threshold(
     }
 }
 
+/// Returns the denomination the stakes are compared in to assign the seats of an epoch running
+/// the given protocol version.
+fn stake_denomination(
+    epoch_config: &EpochConfig,
+    protocol_version: ProtocolVersion,
+) -> &StakeDenomination {
+    static NATIVE: StakeDenomination = StakeDenomination::Native;
+    if checked_feature!("protocol_feature_stake_denomination", StakeDenomination, protocol_version)
+    {
+        &epoch_config.stake_denomination
+    } else {
+        &NATIVE
+    }
+}
+
+fn num_total_seats(epoch_config: &EpochConfig) -> NumSeats {
+    let num_hidden_validator_seats: NumSeats =
+        epoch_config.avg_hidden_validator_seats_per_shard.iter().sum();
+    epoch_config.num_block_producer_seats + num_hidden_validator_seats
+}
+
+/// Estimates the seat price of the epoch which is assigned from `proposals`, assuming that the
+/// validators of `prev_epoch_info` which didn't propose roll over with their current stake.
+/// Kickouts and rewards are only known at the end of the epoch, so they are not accounted for.
+/// The seat price is in the stake denomination of the epoch config.
+/// Returns `None` if the stake isn't enough to fill all the seats.
+pub(crate) fn proposals_to_seat_price(
+    epoch_config: &EpochConfig,
+    prev_epoch_info: &EpochInfo,
+    proposals: Vec<ValidatorStake>,
+) -> Result<Option<Balance>, EpochError> {
+    let denomination = stake_denomination(epoch_config, prev_epoch_info.protocol_version());
+    let mut stakes = BTreeMap::new();
+    for p in proposals {
+        let stake = denomination.denominate(p.account_id(), p.stake());
+        stakes.insert(p.take_account_id(), stake);
+    }
+    for r in prev_epoch_info.validators_iter() {
+        let stake = denomination.denominate(r.account_id(), r.stake());
+        stakes.entry(r.take_account_id()).or_insert(stake);
+    }
+    let stakes = stakes.values().cloned().collect::<Vec<_>>();
+    match find_threshold(&stakes, num_total_seats(epoch_config)) {
+        Ok(threshold) => Ok(Some(threshold)),
+        Err(EpochError::ThresholdError { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 /// Calculates new seat assignments based on current seat assignments and proposals.
+/// The seats are assigned by the stakes in the denomination of the epoch config, which is also
+/// the denomination of the seat price. The stake changes stay in native tokens.
 pub fn proposals_to_epoch_info(
     epoch_config: &EpochConfig,
     rng_seed: RngSeed,
@@ -95,19 +150,18 @@ pub fn proposals_to_epoch_info(
     }
 
     // Get the threshold given current number of seats and stakes.
-    let num_hidden_validator_seats: NumSeats =
-        epoch_config.avg_hidden_validator_seats_per_shard.iter().sum();
-    let num_total_seats = epoch_config.num_block_producer_seats + num_hidden_validator_seats;
-    let stakes = ordered_proposals.iter().map(|(_, p)| p.stake()).collect::<Vec<_>>();
-    let threshold = find_threshold(&stakes, num_total_seats)?;
+    let denomination = stake_denomination(epoch_config, next_version);
+    let denominated_stake = |p: &ValidatorStake| denomination.denominate(p.account_id(), p.stake());
+    let stakes = ordered_proposals.iter().map(|(_, p)| denominated_stake(p)).collect::<Vec<_>>();
+    let threshold = find_threshold(&stakes, num_total_seats(epoch_config))?;
     // Remove proposals under threshold.
     let mut final_proposals = vec![];
 
     for (account_id, p) in ordered_proposals {
-        let stake = p.stake();
+        let stake = denominated_stake(&p);
         if stake >= threshold {
             final_proposals.push(p);
-        } else if stake >= epoch_config.fishermen_threshold {
+        } else if p.stake() >= epoch_config.fishermen_threshold {
             // Do not return stake back since they will become fishermen
             fishermen.push(p);
         } else {
@@ -127,7 +181,7 @@ pub fn proposals_to_epoch_info(
     let mut dup_proposals = final_proposals
         .iter()
         .enumerate()
-        .flat_map(|(i, p)| iter::repeat(i as u64).take((p.stake() / threshold) as usize))
+        .flat_map(|(i, p)| iter::repeat(i as u64).take((denominated_stake(p) / threshold) as usize))
         .collect::<Vec<_>>();
 
     assert!(dup_proposals.len() >= num_total_seats as usize, "bug in find_threshold");
@@ -156,7 +210,7 @@ pub fn proposals_to_epoch_info(
         },
     );
     for p in proposals_to_remove {
-        debug_assert!(p.stake() >= threshold);
+        debug_assert!(denominated_stake(&p) >= threshold);
         if p.stake() >= epoch_config.fishermen_threshold {
             fishermen.push(p);
         } else {
@@ -218,6 +272,94 @@ pub fn proposals_to_epoch_info(
     ))
 }
 
+/// Replaces the proposals and kickouts of an epoch by the validators of the override, which then
+/// make up the whole validator set assigned from them. The validators of `next_epoch_info` and the
+/// proposals which are not part of the override are kicked out, so their stake is returned.
+///
+/// The stake of every validator of the override must be locked on its account, which is only
+/// known for the validators of `epoch_info` and `next_epoch_info` and the proposals of the epoch.
+pub(crate) fn override_validator_set(
+    validator_set_override: &ValidatorSetOverride,
+    epoch_info: &EpochInfo,
+    next_epoch_info: &EpochInfo,
+    proposals: Vec<ValidatorStake>,
+) -> Result<(Vec<ValidatorStake>, HashMap<AccountId, ValidatorKickoutReason>), EpochError> {
+    let mut locked_stakes: HashMap<AccountId, Balance> = HashMap::new();
+    let known_stakes = epoch_info
+        .validators_iter()
+        .chain(epoch_info.fishermen_iter())
+        .chain(next_epoch_info.validators_iter())
+        .chain(next_epoch_info.fishermen_iter())
+        .chain(proposals.iter().cloned())
+        .map(|r| r.account_and_stake());
+    for (account_id, stake) in known_stakes {
+        let locked_stake = locked_stakes.entry(account_id).or_default();
+        *locked_stake = max(*locked_stake, stake);
+    }
+    let mut override_proposals = validator_set_override.proposals();
+    for proposal in override_proposals.iter() {
+        let locked_stake = locked_stakes.get(proposal.account_id()).cloned().unwrap_or_default();
+        if proposal.stake() > locked_stake {
+            return Err(EpochError::InvalidValidatorSetOverride(format!(
+                "stake {} of {} is more than its locked stake {}",
+                proposal.stake(),
+                proposal.account_id(),
+                locked_stake
+            )));
+        }
+    }
+    let override_accounts =
+        override_proposals.iter().map(|p| p.account_id().clone()).collect::<HashSet<_>>();
+    let mut validator_kickout = HashMap::new();
+    let previous_accounts = next_epoch_info
+        .validators_iter()
+        .chain(next_epoch_info.fishermen_iter())
+        .map(|r| r.take_account_id());
+    for account_id in previous_accounts.chain(proposals.iter().map(|p| p.account_id().clone())) {
+        if !override_accounts.contains(&account_id) {
+            validator_kickout.insert(account_id, ValidatorKickoutReason::ValidatorSetOverride);
+        }
+    }
+    override_proposals
+        .extend(proposals.into_iter().filter(|p| !override_accounts.contains(p.account_id())));
+    Ok((override_proposals, validator_kickout))
+}
+
+/// Checks that the genesis validators produce a working genesis epoch: every shard must have
+/// chunk producers. Genesis validators which didn't get a seat are reported, since they are
+/// usually a mistake in the genesis config.
+pub(crate) fn check_genesis_epoch_info(
+    epoch_config: &EpochConfig,
+    validators: &[ValidatorStake],
+    epoch_info: &EpochInfo,
+) -> Result<(), EpochError> {
+    for shard_id in 0..epoch_config.num_shards {
+        match epoch_info.chunk_producers_settlement().get(shard_id as usize) {
+            Some(chunk_producers) if !chunk_producers.is_empty() => {}
+            _ => return Err(EpochError::ShardWithoutChunkProducers(shard_id)),
+        }
+    }
+    for validator in validators {
+        if epoch_info.account_is_validator(validator.account_id()) {
+            continue;
+        }
+        let role = if epoch_info.account_is_fisherman(validator.account_id()) {
+            "a fisherman"
+        } else {
+            "nothing"
+        };
+        warn!(
+            target: "epoch_manager",
+            "Genesis validator {} with stake {} is below the seat price {} and became {}",
+            validator.account_id(),
+            validator.stake(),
+            epoch_info.seat_price(),
+            role
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use num_rational::Rational;
@@ -230,6 +372,134 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_check_genesis_epoch_info() {
+        let validators = vec![stake("test1", 1_000_000), stake("test2", 10)];
+        let config = epoch_config(2, 2, 2, 0, 90, 60, 0);
+        let epoch_info = proposals_to_epoch_info(
+            &config,
+            [0; 32],
+            &EpochInfo::default(),
+            validators.clone(),
+            HashMap::default(),
+            HashMap::default(),
+            0,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert!(!epoch_info.account_is_validator("test2"));
+        assert!(check_genesis_epoch_info(&config, &validators, &epoch_info).is_ok());
+
+        let mut config = config;
+        config.num_block_producer_seats_per_shard = vec![2, 0];
+        let epoch_info = proposals_to_epoch_info(
+            &config,
+            [0; 32],
+            &EpochInfo::default(),
+            validators.clone(),
+            HashMap::default(),
+            HashMap::default(),
+            0,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert_eq!(
+            check_genesis_epoch_info(&config, &validators, &epoch_info),
+            Err(EpochError::ShardWithoutChunkProducers(1))
+        );
+    }
+
+    #[test]
+    fn test_override_validator_set_locked_stake() {
+        let epoch_info = epoch_info(
+            1,
+            vec![("test1", 1_000)],
+            vec![0],
+            vec![vec![0]],
+            vec![],
+            vec![],
+            BTreeMap::new(),
+            vec![],
+            HashMap::default(),
+            0,
+        );
+        let override_with = |account_id: &str, amount: Balance| {
+            let validator = stake(account_id, amount);
+            ValidatorSetOverride {
+                height: 1,
+                validators: vec![near_primitives::types::AccountInfo {
+                    account_id: validator.account_id().clone(),
+                    public_key: validator.public_key().clone(),
+                    amount,
+                }],
+                num_block_producer_seats: None,
+                num_block_producer_seats_per_shard: None,
+                avg_hidden_validator_seats_per_shard: None,
+            }
+        };
+        // The stakes of the validators and of the proposals are locked.
+        assert!(override_validator_set(
+            &override_with("test1", 1_000),
+            &epoch_info,
+            &EpochInfo::default(),
+            vec![]
+        )
+        .is_ok());
+        assert!(override_validator_set(
+            &override_with("test2", 500),
+            &EpochInfo::default(),
+            &epoch_info,
+            vec![stake("test2", 500)]
+        )
+        .is_ok());
+        for (account_id, amount) in vec![("test1", 1_001), ("test2", 1)] {
+            assert!(matches!(
+                override_validator_set(
+                    &override_with(account_id, amount),
+                    &epoch_info,
+                    &epoch_info,
+                    vec![]
+                ),
+                Err(EpochError::InvalidValidatorSetOverride(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_proposals_to_seat_price() {
+        let config = epoch_config(2, 1, 2, 0, 90, 60, 0);
+        let prev_epoch_info = epoch_info(
+            1,
+            vec![("test1", 1000), ("test2", 1000)],
+            vec![0, 1],
+            vec![vec![0, 1]],
+            vec![],
+            vec![],
+            BTreeMap::new(),
+            vec![],
+            HashMap::default(),
+            0,
+        );
+        assert_eq!(proposals_to_seat_price(&config, &prev_epoch_info, vec![]), Ok(Some(1000)));
+        // Proposals override the stake of the validators which roll over.
+        assert_eq!(
+            proposals_to_seat_price(
+                &config,
+                &prev_epoch_info,
+                vec![stake("test2", 0), stake("test3", 500)]
+            ),
+            Ok(Some(500))
+        );
+        assert_eq!(
+            proposals_to_seat_price(
+                &config,
+                &EpochInfo::default(),
+                vec![stake("test1", 1), stake("test2", 0)]
+            ),
+            Ok(None)
+        );
+    }
+
     #[test]
     fn test_find_threshold() {
         assert_eq!(find_threshold(&[1_000_000, 1_000_000, 10], 10).unwrap(), 200_000);
@@ -283,6 +553,9 @@ mod tests {
                     minimum_stake_divisor: 1,
                     protocol_upgrade_stake_threshold: Rational::new(80, 100),
                     protocol_upgrade_num_epochs: 2,
+                    min_epoch_duration_nanosec: 0,
+                    resharding_shard_layout: None,
+                    stake_denomination: StakeDenomination::Native,
                 },
                 [0; 32],
                 &EpochInfo::default(),
@@ -377,6 +650,8 @@ mod tests {
         match &mut epoch_info {
             EpochInfo::V1(info) => info.validator_kickout = HashMap::default(),
             EpochInfo::V2(info) => info.validator_kickout = HashMap::default(),
+            #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+            EpochInfo::V3(info) => info.validator_kickout = HashMap::default(),
         }
         #[cfg(not(feature = "protocol_feature_block_header_v3"))]
         {
@@ -397,4 +672,36 @@ mod tests {
             epoch_info
         );
     }
+
+    /// Test that the seats are assigned by the stake multiplied by the account multipliers, while
+    /// the stakes of the validators stay in native tokens.
+    #[test]
+    #[cfg(feature = "protocol_feature_stake_denomination")]
+    fn test_stake_multipliers() {
+        let mut config = epoch_config(2, 1, 2, 0, 90, 60, 1_000);
+        config.stake_denomination = StakeDenomination::Multipliers(
+            vec![("test3".to_string(), Rational::new(3, 1))].into_iter().collect(),
+        );
+        let epoch_info = proposals_to_epoch_info(
+            &config,
+            [0; 32],
+            &EpochInfo::default(),
+            vec![stake("test1", 100), stake("test2", 100), stake("test3", 100)],
+            HashMap::default(),
+            HashMap::default(),
+            0,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+        assert!(!epoch_info.account_is_validator("test1"));
+        assert!(!epoch_info.account_is_validator("test2"));
+        assert!(epoch_info.account_is_validator("test3"));
+        assert_eq!(epoch_info.block_producers_settlement(), &[0, 0]);
+        assert_eq!(epoch_info.seat_price(), 150);
+        assert_eq!(epoch_info.validator_stake(0), 100);
+        assert_eq!(
+            epoch_info.stake_change(),
+            &change_stake(vec![("test1", 0), ("test2", 0), ("test3", 100)])
+        );
+    }
 }
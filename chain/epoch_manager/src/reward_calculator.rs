@@ -3,13 +3,19 @@ use std::collections::HashMap;
 use num_rational::Rational;
 use primitive_types::U256;
 
-use near_chain_configs::GenesisConfig;
+use near_chain_configs::{GenesisConfig, ProtocolTreasuryShare};
 use near_primitives::checked_feature;
-use near_primitives::types::{AccountId, Balance, BlockChunkValidatorStats};
+use near_primitives::types::{
+    AccountId, Balance, BlockChunkValidatorStats, ShardChunkValidatorStats, UptimeCurve,
+    ValidatorStats,
+};
+use near_primitives::utils::system_account;
 use near_primitives::version::{ProtocolVersion, ENABLE_INFLATION_PROTOCOL_VERSION};
 
 pub(crate) const NUM_NS_IN_SECOND: u64 = 1_000_000_000;
 pub const NUM_SECONDS_IN_A_YEAR: u64 = 24 * 60 * 60 * 365;
+/// Denominator of the uptimes of validators on shards, which are averaged over shards.
+const SHARD_UPTIME_PRECISION: u64 = 1_000_000_000;
 
 #[derive(Clone, Debug)]
 pub struct RewardCalculator {
@@ -18,6 +24,10 @@ pub struct RewardCalculator {
     pub epoch_length: u64,
     pub protocol_reward_rate: Rational,
     pub protocol_treasury_account: AccountId,
+    /// Shares of the protocol treasury reward paid to other accounts than the treasury account.
+    pub protocol_treasury_split: Vec<ProtocolTreasuryShare>,
+    /// Share of the protocol treasury reward paid to the contracts by their gas usage.
+    pub developer_reward_share: Option<Rational>,
     pub online_min_threshold: Rational,
     pub online_max_threshold: Rational,
     pub num_seconds_per_year: u64,
@@ -31,17 +41,56 @@ impl RewardCalculator {
             epoch_length: config.epoch_length,
             protocol_reward_rate: config.protocol_reward_rate,
             protocol_treasury_account: config.protocol_treasury_account.to_string(),
+            protocol_treasury_split: config.protocol_treasury_split.clone(),
+            developer_reward_share: config.developer_reward_share,
             online_max_threshold: config.online_max_threshold,
             online_min_threshold: config.online_min_threshold,
             num_seconds_per_year: NUM_SECONDS_IN_A_YEAR,
         }
     }
+    pub fn uptime_curve(&self) -> UptimeCurve {
+        UptimeCurve {
+            zero_reward_uptime: (
+                *self.online_min_threshold.numer() as u64,
+                *self.online_min_threshold.denom() as u64,
+            ),
+            full_reward_uptime: (
+                *self.online_max_threshold.numer() as u64,
+                *self.online_max_threshold.denom() as u64,
+            ),
+        }
+    }
+
+    /// Uptime of a validator as the average of its block uptime and its chunk uptime, which is
+    /// the average of its uptimes on the shards it was expected to produce chunks for. Returns
+    /// the numerator and the denominator.
+    fn per_shard_uptime(
+        block_stats: &ValidatorStats,
+        shard_chunk_stats: &ShardChunkValidatorStats,
+    ) -> (U256, U256) {
+        let precision = U256::from(SHARD_UPTIME_PRECISION);
+        let shard_uptimes = shard_chunk_stats
+            .values()
+            .filter(|stats| stats.expected > 0)
+            .map(|stats| U256::from(stats.produced) * precision / U256::from(stats.expected))
+            .collect::<Vec<_>>();
+        let num_shards = U256::from(shard_uptimes.len().max(1));
+        let chunk_uptime =
+            shard_uptimes.into_iter().fold(U256::zero(), |sum, uptime| sum + uptime) / num_shards;
+        (
+            U256::from(block_stats.produced) * precision
+                + chunk_uptime * U256::from(block_stats.expected),
+            U256::from(2 * block_stats.expected) * precision,
+        )
+    }
+
     /// Calculate validator reward for an epoch based on their block and chunk production stats.
     /// Returns map of validators with their rewards and amount of newly minted tokens including to protocol's treasury.
     /// See spec https://nomicon.io/Economics/README.html#rewards-calculation
     pub fn calculate_reward(
         &self,
         validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
+        validator_shard_chunk_stats: &HashMap<AccountId, ShardChunkValidatorStats>,
         validator_stake: &HashMap<AccountId, Balance>,
         total_supply: Balance,
         protocol_version: ProtocolVersion,
@@ -77,21 +126,55 @@ impl RewardCalculator {
             * U256::from(*protocol_reward_rate.numer() as u64)
             / U256::from(*protocol_reward_rate.denom() as u64))
         .as_u128();
-        res.insert(self.protocol_treasury_account.clone(), epoch_protocol_treasury);
+        // The treasury account gets what is left after the shares, including the rounding.
+        let mut treasury_reward = epoch_protocol_treasury;
+        for ProtocolTreasuryShare { account_id, share } in self.protocol_treasury_split.iter() {
+            let reward = (U256::from(epoch_protocol_treasury) * U256::from(*share.numer() as u64)
+                / U256::from(*share.denom() as u64))
+            .as_u128();
+            res.insert(account_id.clone(), reward);
+            treasury_reward -= reward;
+        }
+        // The developer reward is kept under the system account, which never gets rewards
+        // otherwise. The runtime splits it between the contracts.
+        if let Some(share) = self.developer_reward_share {
+            if checked_feature!(
+                "protocol_feature_developer_reward",
+                DeveloperReward,
+                protocol_version
+            ) {
+                let reward = (U256::from(epoch_protocol_treasury)
+                    * U256::from(*share.numer() as u64)
+                    / U256::from(*share.denom() as u64))
+                .as_u128();
+                res.insert(system_account(), reward);
+                treasury_reward -= reward;
+            }
+        }
+        res.insert(self.protocol_treasury_account.clone(), treasury_reward);
         if num_validators == 0 {
             return (res, 0);
         }
         let epoch_validator_reward = epoch_total_reward - epoch_protocol_treasury;
         let mut epoch_actual_reward = epoch_protocol_treasury;
         let total_stake: Balance = validator_stake.values().sum();
+        let per_shard_uptime =
+            checked_feature!("protocol_feature_per_shard_uptime", PerShardUptime, protocol_version);
         for (account_id, stats) in validator_block_chunk_stats {
-            // Uptime is an average of block produced / expected and chunk produced / expected.
-            let average_produced_numer = U256::from(
-                stats.block_stats.produced * stats.chunk_stats.expected
-                    + stats.chunk_stats.produced * stats.block_stats.expected,
-            );
-            let average_produced_denom =
-                U256::from(2 * stats.chunk_stats.expected * stats.block_stats.expected);
+            let (average_produced_numer, average_produced_denom) =
+                match validator_shard_chunk_stats.get(&account_id) {
+                    Some(shard_chunk_stats) if per_shard_uptime => {
+                        Self::per_shard_uptime(&stats.block_stats, shard_chunk_stats)
+                    }
+                    // Uptime is an average of block produced / expected and chunk produced / expected.
+                    _ => (
+                        U256::from(
+                            stats.block_stats.produced * stats.chunk_stats.expected
+                                + stats.chunk_stats.produced * stats.block_stats.expected,
+                        ),
+                        U256::from(2 * stats.chunk_stats.expected * stats.block_stats.expected),
+                    ),
+                };
             let online_min_numer = U256::from(*self.online_min_threshold.numer() as u64);
             let online_min_denom = U256::from(*self.online_min_threshold.denom() as u64);
             // If average of produced blocks below online min threshold, validator gets 0 reward.
@@ -133,6 +216,7 @@ impl RewardCalculator {
 mod tests {
     use crate::reward_calculator::NUM_NS_IN_SECOND;
     use crate::RewardCalculator;
+    use near_chain_configs::ProtocolTreasuryShare;
     use near_primitives::types::{BlockChunkValidatorStats, ValidatorStats};
     use near_primitives::version::PROTOCOL_VERSION;
     use num_rational::Rational;
@@ -147,6 +231,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(0, 1),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(9, 10),
             online_max_threshold: Rational::new(1, 1),
             num_seconds_per_year: 1000000,
@@ -175,6 +261,7 @@ mod tests {
         let total_supply = 1_000_000_000_000;
         let result = reward_calculator.calculate_reward(
             validator_block_chunk_stats,
+            &HashMap::new(),
             &validator_stake,
             total_supply,
             PROTOCOL_VERSION,
@@ -203,6 +290,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(0, 10),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(9, 10),
             online_max_threshold: Rational::new(99, 100),
             num_seconds_per_year: 1000,
@@ -242,6 +331,7 @@ mod tests {
         let total_supply = 1_000_000_000;
         let result = reward_calculator.calculate_reward(
             validator_block_chunk_stats,
+            &HashMap::new(),
             &validator_stake,
             total_supply,
             PROTOCOL_VERSION,
@@ -264,6 +354,158 @@ mod tests {
         assert_eq!(result.1, 4_999_999u128);
     }
 
+    /// Test that the protocol treasury reward is split between the configured accounts and the
+    /// treasury account gets the rest.
+    #[test]
+    fn test_protocol_treasury_split() {
+        let epoch_length = 1000;
+        let reward_calculator = RewardCalculator {
+            max_inflation_rate: Rational::new(1, 100),
+            num_blocks_per_year: 1000,
+            epoch_length,
+            protocol_reward_rate: Rational::new(1, 10),
+            protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![
+                ProtocolTreasuryShare {
+                    account_id: "grants".to_string(),
+                    share: Rational::new(1, 3),
+                },
+                ProtocolTreasuryShare {
+                    account_id: "security".to_string(),
+                    share: Rational::new(1, 2),
+                },
+            ],
+            developer_reward_share: None,
+            online_min_threshold: Rational::new(9, 10),
+            online_max_threshold: Rational::new(99, 100),
+            num_seconds_per_year: 1000,
+        };
+        let result = reward_calculator.calculate_reward(
+            HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            1_000_000_000,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+            epoch_length * NUM_NS_IN_SECOND,
+        );
+        // The protocol treasury reward is 1_000_000.
+        assert_eq!(
+            result.0,
+            vec![
+                ("near".to_string(), 166_667u128),
+                ("grants".to_string(), 333_333u128),
+                ("security".to_string(), 500_000u128),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    /// Test that the developer reward is carved out of the treasury account's remainder and kept
+    /// under the system account.
+    #[test]
+    #[cfg(feature = "protocol_feature_developer_reward")]
+    fn test_developer_reward_share() {
+        let epoch_length = 1000;
+        let reward_calculator = RewardCalculator {
+            max_inflation_rate: Rational::new(1, 100),
+            num_blocks_per_year: 1000,
+            epoch_length,
+            protocol_reward_rate: Rational::new(1, 10),
+            protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![ProtocolTreasuryShare {
+                account_id: "grants".to_string(),
+                share: Rational::new(1, 2),
+            }],
+            developer_reward_share: Some(Rational::new(1, 4)),
+            online_min_threshold: Rational::new(9, 10),
+            online_max_threshold: Rational::new(99, 100),
+            num_seconds_per_year: 1000,
+        };
+        let result = reward_calculator.calculate_reward(
+            HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            1_000_000_000,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+            epoch_length * NUM_NS_IN_SECOND,
+        );
+        // The protocol treasury reward is 1_000_000.
+        assert_eq!(
+            result.0,
+            vec![
+                ("near".to_string(), 250_000u128),
+                ("grants".to_string(), 500_000u128),
+                (near_primitives::utils::system_account(), 250_000u128),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    /// Test that with per-shard uptime a validator offline on one of its shards isn't covered by
+    /// the chunks it produced on another shard.
+    #[test]
+    fn test_reward_per_shard_uptime() {
+        let epoch_length = 1000;
+        let reward_calculator = RewardCalculator {
+            max_inflation_rate: Rational::new(1, 100),
+            num_blocks_per_year: 1000,
+            epoch_length,
+            protocol_reward_rate: Rational::new(0, 10),
+            protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
+            online_min_threshold: Rational::new(9, 10),
+            online_max_threshold: Rational::new(99, 100),
+            num_seconds_per_year: 1000,
+        };
+        let validator_shard_chunk_stats: HashMap<_, HashMap<_, _>> = vec![(
+            "test1".to_string(),
+            vec![
+                (0, ValidatorStats { produced: 100, expected: 100 }),
+                (1, ValidatorStats { produced: 0, expected: 10 }),
+            ]
+            .into_iter()
+            .collect(),
+        )]
+        .into_iter()
+        .collect();
+        let validator_stake = vec![("test1".to_string(), 500_000)].into_iter().collect();
+        let reward = |protocol_version| {
+            let validator_block_chunk_stats = vec![(
+                "test1".to_string(),
+                BlockChunkValidatorStats {
+                    block_stats: ValidatorStats { produced: 100, expected: 100 },
+                    chunk_stats: ValidatorStats { produced: 100, expected: 110 },
+                },
+            )]
+            .into_iter()
+            .collect();
+            reward_calculator
+                .calculate_reward(
+                    validator_block_chunk_stats,
+                    &validator_shard_chunk_stats,
+                    &validator_stake,
+                    1_000_000_000,
+                    protocol_version,
+                    protocol_version,
+                    epoch_length * NUM_NS_IN_SECOND,
+                )
+                .0["test1"]
+        };
+        // Chunks of all shards together make an uptime of 95.4%.
+        assert_eq!(reward(PROTOCOL_VERSION - 1), 6_060_606);
+        // The average of the uptimes on the shards makes an uptime of 75%.
+        #[cfg(feature = "protocol_feature_per_shard_uptime")]
+        assert_eq!(
+            reward(near_primitives::version::ProtocolFeature::PerShardUptime.protocol_version()),
+            0
+        );
+    }
+
     /// Test that under an extreme setting (total supply 100b, epoch length half a day),
     /// reward calculation will not overflow.
     #[test]
@@ -276,6 +518,8 @@ mod tests {
             epoch_length,
             protocol_reward_rate: Rational::new(1, 10),
             protocol_treasury_account: "near".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_min_threshold: Rational::new(9, 10),
             online_max_threshold: Rational::new(1, 1),
             num_seconds_per_year: 60 * 60 * 24 * 365,
@@ -296,6 +540,7 @@ mod tests {
         let total_supply = 100_000_000_000 * 10_u128.pow(24);
         reward_calculator.calculate_reward(
             validator_block_chunk_stats,
+            &HashMap::new(),
             &validator_stake,
             total_supply,
             PROTOCOL_VERSION,
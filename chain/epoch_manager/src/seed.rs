@@ -0,0 +1,77 @@
+//! Seed of the validator shuffling.
+//!
+//! The validators of epoch T + 2 are shuffled at the end of epoch T. The seed of the shuffling is
+//! derived from the random values of the last blocks of epoch T. The random value of a block is
+//! the hash of the VRF output of its producer over the random value of the previous block, so
+//! anyone with the blocks can check that the seed wasn't chosen by the node computing the epoch.
+use near_crypto::vrf::Value;
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::hash::{hash, CryptoHash};
+
+use crate::types::RngSeed;
+
+/// Number of the last blocks of an epoch the shuffling seed is derived from.
+pub const NUM_SEED_BLOCKS: usize = 3;
+
+/// Derives the shuffling seed from the random values of the last blocks of an epoch, oldest first.
+pub fn derive_epoch_seed(random_values: &[CryptoHash]) -> RngSeed {
+    let bytes: Vec<u8> =
+        random_values.iter().flat_map(|random_value| random_value.as_ref().to_vec()).collect();
+    hash(&bytes).0
+}
+
+/// Checks that the random values the shuffling seed of `epoch_info` was derived from are the
+/// hashes of the given VRF outputs of the last blocks of the epoch, oldest first. Returns the
+/// seed, which can be used to reproduce the settlements, or `None` if the check fails or the epoch
+/// info doesn't store the inputs of its seed.
+pub fn verify_epoch_seed(epoch_info: &EpochInfo, vrf_values: &[Value]) -> Option<RngSeed> {
+    let random_values = epoch_info.rng_seed_inputs()?;
+    if random_values.len() != vrf_values.len()
+        || random_values
+            .iter()
+            .zip(vrf_values)
+            .any(|(random_value, vrf_value)| random_value != &hash(vrf_value.0.as_ref()))
+    {
+        return None;
+    }
+    Some(derive_epoch_seed(random_values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_epoch_seed() {
+        let random_values = vec![hash(&[1]), hash(&[2])];
+        let seed = derive_epoch_seed(&random_values);
+        assert_eq!(seed, derive_epoch_seed(&random_values));
+        assert_ne!(seed, derive_epoch_seed(&[hash(&[2]), hash(&[1])]));
+        assert_ne!(seed, derive_epoch_seed(&random_values[1..]));
+    }
+
+    #[test]
+    fn test_verify_epoch_seed_without_inputs() {
+        assert_eq!(verify_epoch_seed(&EpochInfo::default(), &[]), None);
+    }
+
+    #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+    #[test]
+    fn test_verify_epoch_seed() {
+        let vrf_values = vec![Value([1; 32]), Value([2; 32])];
+        let random_values =
+            vrf_values.iter().map(|vrf_value| hash(vrf_value.0.as_ref())).collect::<Vec<_>>();
+        let epoch_info = EpochInfo::default().with_rng_seed_inputs(random_values.clone());
+        assert_eq!(
+            verify_epoch_seed(&epoch_info, &vrf_values),
+            Some(derive_epoch_seed(&random_values))
+        );
+        assert_eq!(verify_epoch_seed(&epoch_info, &vrf_values[..1]), None);
+        assert_eq!(verify_epoch_seed(&epoch_info, &[Value([1; 32]), Value([3; 32])]), None);
+
+        // The oldest epoch info version is upgraded to store the seed inputs.
+        let epoch_info = EpochInfo::V1(near_primitives::epoch_manager::EpochInfoV1::default())
+            .with_rng_seed_inputs(random_values.clone());
+        assert_eq!(epoch_info.rng_seed_inputs(), Some(random_values.as_slice()));
+    }
+}
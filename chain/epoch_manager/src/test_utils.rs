@@ -7,7 +7,7 @@ use near_primitives::challenge::SlashedValidator;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_primitives::epoch_manager::block_info::BlockInfoV2;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
-use near_primitives::epoch_manager::{EpochConfig, ValidatorWeight};
+use near_primitives::epoch_manager::{EpochConfig, StakeDenomination, ValidatorWeight};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
@@ -152,6 +152,9 @@ pub fn epoch_config(
         protocol_upgrade_stake_threshold: Rational::new(80, 100),
         protocol_upgrade_num_epochs: 2,
         minimum_stake_divisor: 1,
+        min_epoch_duration_nanosec: 0,
+        resharding_shard_layout: None,
+        stake_denomination: StakeDenomination::Native,
     }
 }
 
@@ -168,6 +171,8 @@ pub fn default_reward_calculator() -> RewardCalculator {
         epoch_length: 1,
         protocol_reward_rate: Rational::from_integer(0),
         protocol_treasury_account: "near".to_string(),
+        protocol_treasury_split: vec![],
+        developer_reward_share: None,
         online_min_threshold: Rational::new(90, 100),
         online_max_threshold: Rational::new(99, 100),
         num_seconds_per_year: NUM_SECONDS_IN_A_YEAR,
@@ -254,7 +259,7 @@ pub fn record_block_with_final_block_hash(
                 PROTOCOL_VERSION,
                 height * NUM_NS_IN_SECOND,
             ),
-            [0; 32],
+            &[],
         )
         .unwrap()
         .commit()
@@ -284,7 +289,7 @@ pub fn record_block_with_slashes(
                 PROTOCOL_VERSION,
                 height * NUM_NS_IN_SECOND,
             ),
-            [0; 32],
+            &[],
         )
         .unwrap()
         .commit()
@@ -358,5 +363,5 @@ pub fn block_info(
 }
 
 pub fn record_with_block_info(epoch_manager: &mut EpochManager, block_info: BlockInfo) {
-    epoch_manager.record_block_info(block_info, [0; 32]).unwrap().commit().unwrap();
+    epoch_manager.record_block_info(block_info, &[]).unwrap().commit().unwrap();
 }
@@ -1,14 +1,20 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use log::error;
+use serde::{Deserialize, Serialize};
 
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::epoch_manager::EpochConfig;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
-    AccountId, BlockHeight, EpochId, ShardId, ValidatorId, ValidatorStats,
+    AccountId, AccountInfo, BlockHeight, EpochId, NumSeats, NumShards, ShardId, ValidatorId,
+    ValidatorStats,
 };
 use near_primitives::version::ProtocolVersion;
 
@@ -16,6 +22,114 @@ use crate::EpochManager;
 
 pub type RngSeed = [u8; 32];
 
+/// Validator set forced on the network from a given height on, to recover a network whose
+/// validators became unable to produce blocks. Every node of the network must start with the same
+/// override, read from `validator_set_override.json` in the home directory.
+///
+/// The override is applied once, when finalizing the epoch which contains `height`: the validator
+/// set assigned then (the one of the epoch after next) is made of `validators` only, and every
+/// other validator is kicked out with `ValidatorKickoutReason::ValidatorSetOverride`, which
+/// records the override in the `EpochInfo`. The applied override is persisted in the store, and
+/// the seats given here replace the ones of the epoch config for that epoch and the following
+/// ones, whether the file is still there or not.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorSetOverride {
+    pub height: BlockHeight,
+    /// The stakes must be locked on the accounts, as they are returned when the validators leave.
+    pub validators: Vec<AccountInfo>,
+    #[serde(default)]
+    pub num_block_producer_seats: Option<NumSeats>,
+    #[serde(default)]
+    pub num_block_producer_seats_per_shard: Option<Vec<NumSeats>>,
+    #[serde(default)]
+    pub avg_hidden_validator_seats_per_shard: Option<Vec<NumSeats>>,
+}
+
+impl ValidatorSetOverride {
+    /// Reads the override from the file and checks it against the epoch config.
+    pub fn from_file<P: AsRef<Path>>(path: P, config: &EpochConfig) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            format!("Could not open validator set override file {:?}: {}", path, err)
+        })?;
+        let validator_set_override: Self =
+            serde_json::from_reader(BufReader::new(file)).map_err(|err| {
+                format!("Failed to deserialize the validator set override {:?}: {}", path, err)
+            })?;
+        validator_set_override.validate(config)?;
+        Ok(validator_set_override)
+    }
+
+    /// Checks that the override has validators with some stake and as many seats per shard as
+    /// there are shards.
+    pub fn validate(&self, config: &EpochConfig) -> Result<(), String> {
+        if self.validators.is_empty() {
+            return Err("Validator set override has no validators".to_string());
+        }
+        let mut account_ids = HashSet::new();
+        for account_info in self.validators.iter() {
+            if account_info.amount == 0 {
+                return Err(format!(
+                    "Validator {} of the override has no stake",
+                    account_info.account_id
+                ));
+            }
+            if !account_ids.insert(&account_info.account_id) {
+                return Err(format!(
+                    "Validator {} is repeated in the override",
+                    account_info.account_id
+                ));
+            }
+        }
+        if self.num_block_producer_seats == Some(0) {
+            return Err("Validator set override has no block producer seats".to_string());
+        }
+        for seats in
+            [&self.num_block_producer_seats_per_shard, &self.avg_hidden_validator_seats_per_shard]
+                .iter()
+        {
+            if let Some(seats) = seats {
+                if seats.len() as NumShards != config.num_shards {
+                    return Err(format!(
+                        "Validator set override has seats for {} shards instead of {}",
+                        seats.len(),
+                        config.num_shards
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn proposals(&self) -> Vec<ValidatorStake> {
+        self.validators
+            .iter()
+            .map(|account_info| {
+                ValidatorStake::new(
+                    account_info.account_id.clone(),
+                    account_info.public_key.clone(),
+                    account_info.amount,
+                )
+            })
+            .collect()
+    }
+
+    /// Epoch config with the seats of the override.
+    pub fn epoch_config(&self, config: &EpochConfig) -> EpochConfig {
+        let mut config = config.clone();
+        if let Some(num_seats) = self.num_block_producer_seats {
+            config.num_block_producer_seats = num_seats;
+        }
+        if let Some(num_seats) = &self.num_block_producer_seats_per_shard {
+            config.num_block_producer_seats_per_shard = num_seats.clone();
+        }
+        if let Some(num_seats) = &self.avg_hidden_validator_seats_per_shard {
+            config.avg_hidden_validator_seats_per_shard = num_seats.clone();
+        }
+        config
+    }
+}
+
 /// Aggregator of information needed for validator computation at the end of the epoch.
 #[derive(Clone, BorshSerialize, BorshDeserialize, Debug)]
 pub struct EpochInfoAggregator {
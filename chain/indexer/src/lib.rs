@@ -17,6 +17,7 @@ pub use self::streamer::{
     IndexerExecutionOutcomeWithReceipt, IndexerShard, IndexerTransactionWithOutcome,
     StreamerMessage,
 };
+use near_chain_configs::ShardTracking;
 pub use near_primitives;
 use near_primitives::types::Gas;
 
@@ -95,7 +96,8 @@ impl Indexer {
         let near_config = nearcore::load_config(&indexer_config.home_dir);
         nearcore::genesis_validate::validate_genesis(&near_config.genesis);
         assert!(
-            !&near_config.client_config.tracked_shards.is_empty(),
+            near_config.client_config.shard_tracking == ShardTracking::All
+                || !&near_config.client_config.tracked_shards.is_empty(),
             "Indexer should track at least one shard. \n\
             Tip: You may want to update {} with `\"tracked_shards\": [0]`
             ",
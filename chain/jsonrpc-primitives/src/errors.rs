@@ -5,6 +5,11 @@ use serde_json::{to_value, Value};
 
 use near_primitives::errors::{InvalidTxError, TxExecutionError};
 
+/// Version of the errors schema in `chain/jsonrpc/res/rpc_errors_schema.json`, so clients
+/// matching on the error names can tell which errors to expect. It is bumped in
+/// `near-rpc-error-core`, which generates the schema.
+pub const RPC_ERRORS_SCHEMA_VERSION: u32 = near_rpc_error_core::SCHEMA_VERSION;
+
 #[derive(Serialize)]
 pub struct RpcParseError(pub String);
 
@@ -154,7 +159,7 @@ impl std::string::ToString for RpcError {
 
 impl From<actix::MailboxError> for RpcError {
     fn from(error: actix::MailboxError) -> Self {
-        Self::new(-32_000, "Server error".to_string(), Some(Value::String(error.to_string())))
+        Self::new_internal_error(Some(Value::String(error.to_string())), error.to_string())
     }
 }
 
@@ -204,3 +209,22 @@ impl From<ServerError> for RpcError {
         RpcError::new_handler_error(Some(error_data.clone()), error_data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_schema_version() {
+        let schema: Value =
+            serde_json::from_str(include_str!("../../jsonrpc/res/rpc_errors_schema.json")).unwrap();
+        assert_eq!(schema["version"], Value::from(RPC_ERRORS_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn test_mailbox_error_is_internal_error() {
+        let error: RpcError = actix::MailboxError::Timeout.into();
+        assert!(matches!(error.error_struct, Some(RpcErrorKind::InternalError(_))));
+        assert_eq!(error.code, -32_000);
+    }
+}
@@ -147,6 +147,13 @@ pub struct Notification {
     pub params: Option<Value>,
 }
 
+impl Notification {
+    /// A request to process the notification with. It has no ID, as nobody expects the reply.
+    pub fn into_request(self) -> Request {
+        Request { jsonrpc: Version, method: self.method, params: self.params, id: Value::Null }
+    }
+}
+
 /// One message of the JSON RPC protocol.
 ///
 /// One message, directly mapped from the structures of the protocol. See the
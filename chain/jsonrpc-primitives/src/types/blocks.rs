@@ -38,6 +38,12 @@ pub struct RpcBlockResponse {
     pub block_view: near_primitives::views::BlockView,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcBlockDetailsResponse {
+    #[serde(flatten)]
+    pub block_details_view: near_primitives::views::BlockDetailsView,
+}
+
 // near_client_primitives::types::GetBlock wants BlockReference from near_primitives
 // that's why this impl exists
 impl From<BlockReference> for near_primitives::types::BlockReference {
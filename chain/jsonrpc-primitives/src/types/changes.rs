@@ -27,6 +27,13 @@ pub struct RpcStateChangesInBlockResponse {
     pub changes: near_primitives::views::StateChangesKindsView,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcFilteredStateChangesRequest {
+    #[serde(flatten)]
+    pub block_reference: crate::types::blocks::BlockReference,
+    pub filter_id: String,
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcStateChangesError {
@@ -53,6 +60,12 @@ impl RpcStateChangesInBlockRequest {
     }
 }
 
+impl RpcFilteredStateChangesRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
 impl From<near_client_primitives::types::GetBlockError> for RpcStateChangesError {
     fn from(error: near_client_primitives::types::GetBlockError) -> Self {
         match error {
@@ -25,6 +25,12 @@ pub struct RpcChunkResponse {
     pub chunk_view: near_primitives::views::ChunkView,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcChunkDetailsResponse {
+    #[serde(flatten)]
+    pub chunk_details_view: near_primitives::views::ChunkDetailsView,
+}
+
 #[derive(thiserror::Error, Debug, Serialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcChunkError {
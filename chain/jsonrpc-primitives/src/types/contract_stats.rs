@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcContractExecutionStatsRequest {
+    pub code_hash: near_primitives::hash::CryptoHash,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RpcContractExecutionStatsResponse {
+    #[serde(flatten)]
+    pub contract_stats: near_primitives::runtime::contract_stats::ContractExecutionStats,
+    pub average_gas_burnt: near_primitives::types::Gas,
+    pub average_wall_time_nanos: u64,
+}
+
+#[derive(thiserror::Error, Debug, Serialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcContractExecutionStatsError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Contract with code hash {code_hash} wasn't called on this node")]
+    UnknownContract { code_hash: near_primitives::hash::CryptoHash },
+}
+
+impl RpcContractExecutionStatsRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        crate::utils::parse_params::<Self>(value)
+    }
+}
+
+impl From<near_primitives::runtime::contract_stats::ContractExecutionStats>
+    for RpcContractExecutionStatsResponse
+{
+    fn from(
+        contract_stats: near_primitives::runtime::contract_stats::ContractExecutionStats,
+    ) -> Self {
+        Self {
+            average_gas_burnt: contract_stats.average_gas_burnt(),
+            average_wall_time_nanos: contract_stats.average_wall_time_nanos(),
+            contract_stats,
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcContractExecutionStatsError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcContractExecutionStatsError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcContractExecutionStatsError> for crate::errors::RpcError {
+    fn from(error: RpcContractExecutionStatsError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcContractExecutionStatsError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
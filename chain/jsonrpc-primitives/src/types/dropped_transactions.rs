@@ -0,0 +1,50 @@
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct RpcDroppedTransactionsResponse {
+    pub dropped_transactions: Vec<near_client_primitives::types::DroppedTransactionView>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcDroppedTransactionsError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<Vec<near_client_primitives::types::DroppedTransactionView>>
+    for RpcDroppedTransactionsResponse
+{
+    fn from(
+        dropped_transactions: Vec<near_client_primitives::types::DroppedTransactionView>,
+    ) -> Self {
+        Self { dropped_transactions }
+    }
+}
+
+impl From<actix::MailboxError> for RpcDroppedTransactionsError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcDroppedTransactionsError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcDroppedTransactionsError> for crate::errors::RpcError {
+    fn from(error: RpcDroppedTransactionsError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcDroppedTransactionsError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -2,6 +2,8 @@ pub mod blocks;
 pub mod changes;
 pub mod chunks;
 pub mod config;
+pub mod contract_stats;
+pub mod dropped_transactions;
 pub mod gas_price;
 pub mod light_client;
 pub mod network_info;
@@ -9,5 +11,7 @@ pub mod query;
 pub mod receipts;
 pub mod sandbox;
 pub mod status;
+pub mod subscriptions;
+pub mod transaction_trace;
 pub mod transactions;
 pub mod validator;
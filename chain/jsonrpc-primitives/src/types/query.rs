@@ -59,6 +59,14 @@ pub enum RpcQueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Function call was stopped: {error_message}")]
+    ViewLimitExceeded {
+        error_message: String,
+        gas_burnt: near_primitives::types::Gas,
+        logs: Vec<String>,
+        block_height: near_primitives::types::BlockHeight,
+        block_hash: near_primitives::hash::CryptoHash,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
@@ -80,6 +88,7 @@ pub enum QueryResponseKind {
     CallResult(near_primitives::views::CallResult),
     AccessKey(near_primitives::views::AccessKeyView),
     AccessKeyList(near_primitives::views::AccessKeyList),
+    BurnLedger(near_primitives::views::BurnLedgerView),
 }
 
 impl RpcQueryRequest {
@@ -124,6 +133,9 @@ impl RpcQueryRequest {
                 "contract" => near_primitives::views::QueryRequest::ViewState {
                     account_id,
                     prefix: data.into(),
+                    start_key: None,
+                    limit: None,
+                    include_proof: false,
                 },
                 "call" => match maybe_extra_arg {
                     Some(method_name) => near_primitives::views::QueryRequest::CallFunction {
@@ -194,6 +206,15 @@ impl From<near_client_primitives::types::QueryError> for RpcQueryError {
                 block_height,
                 block_hash,
             } => Self::ContractExecutionError { vm_error, block_height, block_hash },
+            near_client_primitives::types::QueryError::ViewLimitExceeded {
+                error_message,
+                gas_burnt,
+                logs,
+                block_height,
+                block_hash,
+            } => {
+                Self::ViewLimitExceeded { error_message, gas_burnt, logs, block_height, block_hash }
+            }
             near_client_primitives::types::QueryError::Unreachable { ref error_message } => {
                 tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
                 near_metrics::inc_counter_vec(
@@ -242,6 +263,9 @@ impl From<near_primitives::views::QueryResponseKind> for QueryResponseKind {
             near_primitives::views::QueryResponseKind::AccessKeyList(access_key_list) => {
                 Self::AccessKeyList(access_key_list)
             }
+            near_primitives::views::QueryResponseKind::BurnLedger(burn_ledger) => {
+                Self::BurnLedger(burn_ledger)
+            }
         }
     }
 }
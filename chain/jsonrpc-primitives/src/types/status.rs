@@ -16,6 +16,8 @@ pub enum RpcStatusError {
     NodeIsSyncing,
     #[error("No blocks for {elapsed:?}")]
     NoNewBlocks { elapsed: std::time::Duration },
+    #[error("Head stalled for {elapsed:?}")]
+    HeadStalled { elapsed: std::time::Duration },
     #[error("Epoch Out Of Bounds {epoch_id:?}")]
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
@@ -44,6 +46,9 @@ impl From<near_client_primitives::types::StatusError> for RpcStatusError {
             near_client_primitives::types::StatusError::NoNewBlocks { elapsed } => {
                 Self::NoNewBlocks { elapsed }
             }
+            near_client_primitives::types::StatusError::HeadStalled { elapsed } => {
+                Self::HeadStalled { elapsed }
+            }
             near_client_primitives::types::StatusError::EpochOutOfBounds { epoch_id } => {
                 Self::EpochOutOfBounds { epoch_id }
             }
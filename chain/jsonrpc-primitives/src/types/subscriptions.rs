@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Events a WebSocket client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RpcSubscriptionKind {
+    /// Every new head of the chain.
+    NewHeads,
+    /// Every new final block.
+    FinalizedHeads,
+    /// Execution outcomes of transactions and receipts executed by the given account.
+    ExecutionOutcomes { account_id: near_primitives::types::AccountId },
+    /// State changes matching a filter from the node config.
+    StateChanges { filter_id: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcSubscribeRequest {
+    #[serde(flatten)]
+    pub kind: RpcSubscriptionKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcSubscribeResponse {
+    pub subscription_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcUnsubscribeRequest {
+    pub subscription_id: u64,
+}
+
+/// Params of the `subscription` notification sent to the subscriber.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcSubscriptionNotification {
+    pub subscription_id: u64,
+    pub result: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcExecutionOutcomesNotification {
+    pub block_hash: near_primitives::hash::CryptoHash,
+    pub outcomes: Vec<near_primitives::views::ExecutionOutcomeWithIdView>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcSubscriptionError {
+    #[error("The connection reached the limit of {limit} subscriptions")]
+    TooManySubscriptions { limit: usize },
+    #[error("The node reached the limit of {limit} subscriptions")]
+    TooManySubscriptionsOnNode { limit: usize },
+    #[error("Subscription {subscription_id} does not exist")]
+    UnknownSubscription { subscription_id: u64 },
+}
+
+impl RpcSubscribeRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl RpcUnsubscribeRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<RpcSubscriptionError> for crate::errors::RpcError {
+    fn from(error: RpcSubscriptionError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcSubscriptionError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
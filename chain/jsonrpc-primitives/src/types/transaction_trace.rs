@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcTransactionTraceRequest {
+    pub transaction_hash: near_primitives::hash::CryptoHash,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RpcTransactionTraceResponse {
+    #[serde(flatten)]
+    pub transaction_trace: near_client_primitives::types::TransactionTraceView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcTransactionTraceError {
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Transaction {transaction_hash} wasn't received by this node recently")]
+    UnknownTransaction { transaction_hash: near_primitives::hash::CryptoHash },
+}
+
+impl RpcTransactionTraceRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        crate::utils::parse_params::<Self>(value)
+    }
+}
+
+impl From<near_client_primitives::types::TransactionTraceView> for RpcTransactionTraceResponse {
+    fn from(transaction_trace: near_client_primitives::types::TransactionTraceView) -> Self {
+        Self { transaction_trace }
+    }
+}
+
+impl From<actix::MailboxError> for RpcTransactionTraceError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<String> for RpcTransactionTraceError {
+    fn from(error_message: String) -> Self {
+        Self::InternalError { error_message }
+    }
+}
+
+impl From<RpcTransactionTraceError> for crate::errors::RpcError {
+    fn from(error: RpcTransactionTraceError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcTransactionTraceError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
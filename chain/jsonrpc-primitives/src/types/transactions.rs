@@ -20,14 +20,11 @@ pub enum TransactionInfo {
     },
 }
 
-#[derive(thiserror::Error, Debug, Serialize)]
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcTransactionError {
     #[error("An error happened during transaction execution: {context:?}")]
-    InvalidTransaction {
-        #[serde(skip)]
-        context: near_primitives::errors::InvalidTxError,
-    },
+    InvalidTransaction { context: near_primitives::errors::InvalidTxError },
     #[error("Node doesn't track this shard. Cannot determine whether the transaction is valid")]
     DoesNotTrackShard,
     #[error("Transaction with hash {transaction_hash} was routed")]
@@ -143,3 +140,37 @@ impl From<actix::MailboxError> for RpcTransactionError {
         Self::InternalError { debug_info: error.to_string() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_transaction_error_is_structured() {
+        let error = RpcTransactionError::InvalidTransaction {
+            context: near_primitives::errors::InvalidTxError::InvalidNonce {
+                tx_nonce: 5,
+                ak_nonce: 10,
+            },
+        };
+        let rpc_error: crate::errors::RpcError = error.into();
+        assert_eq!(
+            rpc_error.error_struct,
+            Some(crate::errors::RpcErrorKind::HandlerError(serde_json::json!({
+                "name": "INVALID_TRANSACTION",
+                "info": {"context": {"InvalidNonce": {"tx_nonce": 5, "ak_nonce": 10}}}
+            })))
+        );
+    }
+
+    #[test]
+    fn test_timeout_error_is_structured() {
+        let rpc_error: crate::errors::RpcError = RpcTransactionError::TimeoutError.into();
+        assert_eq!(
+            rpc_error.error_struct,
+            Some(crate::errors::RpcErrorKind::HandlerError(
+                serde_json::json!({"name": "TIMEOUT_ERROR"})
+            ))
+        );
+    }
+}
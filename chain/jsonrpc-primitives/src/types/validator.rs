@@ -26,6 +26,29 @@ pub struct RpcValidatorsOrderedRequest {
     pub block_id: near_primitives::types::MaybeBlockId,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorsDiffRequest {
+    #[serde(flatten)]
+    pub epoch_reference: near_primitives::types::EpochReference,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorsDiffResponse {
+    #[serde(flatten)]
+    pub validators_diff: near_primitives::views::EpochValidatorDiffView,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcSeatPriceRequest {
+    pub block_id: near_primitives::types::MaybeBlockId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcSeatPriceResponse {
+    #[serde(flatten)]
+    pub seat_prices: near_primitives::views::SeatPriceView,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RpcValidatorResponse {
     #[serde(flatten)]
@@ -86,6 +109,19 @@ impl RpcValidatorsOrderedRequest {
     }
 }
 
+impl RpcSeatPriceRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcSeatPriceRequest>(value)?)
+    }
+}
+
+impl RpcValidatorsDiffRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        let RpcValidatorRequest { epoch_reference } = RpcValidatorRequest::parse(value)?;
+        Ok(Self { epoch_reference })
+    }
+}
+
 impl From<RpcValidatorError> for crate::errors::RpcError {
     fn from(error: RpcValidatorError) -> Self {
         let error_data = match &error {
@@ -8,7 +8,9 @@ use serde::Serialize;
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{from_slice, Message};
 use near_jsonrpc_primitives::types::changes::{RpcStateChangesRequest, RpcStateChangesResponse};
-use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
+use near_jsonrpc_primitives::types::validator::{
+    RpcValidatorsDiffRequest, RpcValidatorsDiffResponse, RpcValidatorsOrderedRequest,
+};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{BlockId, BlockReference, MaybeBlockId, ShardId};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
@@ -241,6 +243,14 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_validators_diff(
+        &self,
+        request: RpcValidatorsDiffRequest,
+    ) -> RpcRequest<RpcValidatorsDiffResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_diff", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_receipt(
         &self,
@@ -256,6 +266,31 @@ impl JsonRpcClient {
     ) -> RpcRequest<near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse> {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_protocol_config", request)
     }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_block_details(
+        &self,
+        request: BlockReference,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::blocks::RpcBlockDetailsResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_block_details", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_chunk_details(
+        &self,
+        id: ChunkId,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::chunks::RpcChunkDetailsResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_chunk_details", [id])
+    }
+
+    pub fn light_client_proof(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofRequest,
+    ) -> RpcRequest<
+        near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofResponse,
+    > {
+        call_method(&self.client, &self.server_addr, "light_client_proof", request)
+    }
 }
 
 fn create_client() -> Client {
@@ -13,16 +13,19 @@ use tracing::info;
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    ClientActor, GetBlock, GetBlockDetails, GetBlockProof, GetChunk, GetChunkDetails,
+    GetContractExecutionStats, GetDroppedTransactions, GetExecutionOutcome,
+    GetFilteredStateChanges, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock,
+    GetProtocolConfig, GetReceipt, GetSeatPrice, GetStateChanges, GetStateChangesInBlock,
+    GetTransactionTrace, GetValidatorDiff, GetValidatorInfo, GetValidatorOrdered, Query, Status,
+    TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{Message, Request};
 use near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse;
 use near_metrics::{Encoder, TextEncoder};
+use near_network::event_log::NetworkEventLog;
 #[cfg(feature = "adversarial")]
 use near_network::types::{NetworkAdversarialMessage, NetworkViewClientMessages};
 #[cfg(feature = "sandbox")]
@@ -35,6 +38,10 @@ use near_primitives::types::AccountId;
 use near_primitives::views::FinalExecutionOutcomeViewEnum;
 
 mod metrics;
+mod subscriptions;
+
+pub use subscriptions::RpcSubscriptionsConfig;
+use subscriptions::SubscriptionsHub;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct RpcPollingConfig {
@@ -55,11 +62,18 @@ impl Default for RpcPollingConfig {
 pub struct RpcLimitsConfig {
     /// Maximum byte size of the json payload.
     pub json_payload_max_size: usize,
+    /// Maximum number of requests in a single JSON RPC batch.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+}
+
+fn default_max_batch_size() -> usize {
+    100
 }
 
 impl Default for RpcLimitsConfig {
     fn default() -> Self {
-        Self { json_payload_max_size: 10 * 1024 * 1024 }
+        Self { json_payload_max_size: 10 * 1024 * 1024, max_batch_size: default_max_batch_size() }
     }
 }
 
@@ -72,6 +86,8 @@ pub struct RpcConfig {
     pub polling_config: RpcPollingConfig,
     #[serde(default)]
     pub limits_config: RpcLimitsConfig,
+    #[serde(default)]
+    pub subscriptions_config: RpcSubscriptionsConfig,
 }
 
 impl Default for RpcConfig {
@@ -82,6 +98,7 @@ impl Default for RpcConfig {
             cors_allowed_origins: vec!["*".to_owned()],
             polling_config: Default::default(),
             limits_config: Default::default(),
+            subscriptions_config: Default::default(),
         }
     }
 }
@@ -201,18 +218,67 @@ struct JsonRpcHandler {
     view_client_addr: Addr<ViewClientActor>,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
+    max_batch_size: usize,
+    network_event_log: NetworkEventLog,
 }
 
 impl JsonRpcHandler {
-    pub async fn process(&self, message: Message) -> Result<Message, HttpError> {
+    /// Returns the reply to the message, if any.
+    pub async fn process(&self, message: Message) -> Result<Option<Message>, HttpError> {
+        match message {
+            Message::Batch(messages) => Ok(self.process_batch(messages).await),
+            message => Ok(Some(self.process_single(message).await)),
+        }
+    }
+
+    async fn process_single(&self, message: Message) -> Message {
         let id = message.id();
         match message {
-            Message::Request(request) => {
-                Ok(Message::response(id, self.process_request(request).await))
-            }
-            _ => Ok(Message::error(RpcError::parse_error(
+            Message::Request(request) => Message::response(id, self.process_request(request).await),
+            _ => Message::error(RpcError::parse_error(
                 "JSON RPC Request format was expected".to_owned(),
-            ))),
+            )),
+        }
+    }
+
+    /// Processes the requests of a batch concurrently. Every request gets its own response in
+    /// the batch reply, so a failing request doesn't affect the others. Notifications are
+    /// processed too, but get no response. A batch of notifications only gets no reply at all.
+    async fn process_batch(&self, messages: Vec<Message>) -> Option<Message> {
+        if messages.is_empty() {
+            return Some(Message::error(RpcError::parse_error(
+                "JSON RPC batch is empty".to_owned(),
+            )));
+        }
+        if messages.len() > self.max_batch_size {
+            return Some(Message::error(RpcError::parse_error(format!(
+                "JSON RPC batch of {} requests exceeds the limit of {}",
+                messages.len(),
+                self.max_batch_size
+            ))));
+        }
+        near_metrics::observe(&metrics::RPC_BATCH_SIZE, messages.len() as f64);
+        let responses = futures::future::join_all(messages.into_iter().map(|message| {
+            async move {
+                match message {
+                    Message::Notification(notification) => {
+                        let _ = self.process_request(notification.into_request()).await;
+                        None
+                    }
+                    // Nested batches are not allowed by the specification.
+                    Message::Batch(_) => Some(Message::error(RpcError::parse_error(
+                        "JSON RPC Request format was expected".to_owned(),
+                    ))),
+                    message => Some(self.process_single(message).await),
+                }
+            }
+        }))
+        .await;
+        let responses: Vec<Message> = responses.into_iter().flatten().collect();
+        if responses.is_empty() {
+            None
+        } else {
+            Some(Message::Batch(responses))
         }
     }
 
@@ -340,6 +406,13 @@ impl JsonRpcHandler {
                 serde_json::to_value(validator_info)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_block_details" => {
+                let rpc_block_request =
+                    near_jsonrpc_primitives::types::blocks::RpcBlockRequest::parse(request.params)?;
+                let block_details = self.block_details(rpc_block_request).await?;
+                serde_json::to_value(block_details)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_broadcast_tx_sync" => {
                 let rpc_transaction_request =
                     near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
@@ -368,6 +441,16 @@ impl JsonRpcHandler {
                 serde_json::to_value(state_changes)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_filtered_changes" => {
+                let rpc_filtered_state_changes_request =
+                    near_jsonrpc_primitives::types::changes::RpcFilteredStateChangesRequest::parse(
+                        request.params,
+                    )?;
+                let state_changes =
+                    self.filtered_changes_in_block(rpc_filtered_state_changes_request).await?;
+                serde_json::to_value(state_changes)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_check_tx" => {
                 let rpc_transaction_request =
                     near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
@@ -377,6 +460,27 @@ impl JsonRpcHandler {
                 serde_json::to_value(broadcast_tx_sync_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_chunk_details" => {
+                let rpc_chunk_request =
+                    near_jsonrpc_primitives::types::chunks::RpcChunkRequest::parse(request.params)?;
+                let chunk_details = self.chunk_details(rpc_chunk_request).await?;
+                serde_json::to_value(chunk_details)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_contract_stats" => {
+                let rpc_contract_stats_request =
+                    near_jsonrpc_primitives::types::contract_stats::RpcContractExecutionStatsRequest::parse(
+                        request.params,
+                    )?;
+                let contract_stats = self.contract_stats(rpc_contract_stats_request).await?;
+                serde_json::to_value(contract_stats)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_dropped_transactions" => {
+                let dropped_transactions_response = self.dropped_transactions().await?;
+                serde_json::to_value(dropped_transactions_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_genesis_config" => {
                 let genesis_config = self.genesis_config().await;
                 serde_json::to_value(genesis_config)
@@ -415,6 +519,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_transaction_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_tx_trace" => {
+                let rpc_transaction_trace_request =
+                    near_jsonrpc_primitives::types::transaction_trace::RpcTransactionTraceRequest::parse(
+                        request.params,
+                    )?;
+                let transaction_trace = self.tx_trace(rpc_transaction_trace_request).await?;
+                serde_json::to_value(transaction_trace)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_validators_ordered" => {
                 let rpc_validators_ordered_request =
                     near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest::parse(
@@ -424,6 +537,24 @@ impl JsonRpcHandler {
                 serde_json::to_value(validators)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_validators_diff" => {
+                let rpc_validators_diff_request =
+                    near_jsonrpc_primitives::types::validator::RpcValidatorsDiffRequest::parse(
+                        request.params,
+                    )?;
+                let validators_diff = self.validators_diff(rpc_validators_diff_request).await?;
+                serde_json::to_value(validators_diff)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_seat_price" => {
+                let rpc_seat_price_request =
+                    near_jsonrpc_primitives::types::validator::RpcSeatPriceRequest::parse(
+                        request.params,
+                    )?;
+                let seat_price = self.seat_price(rpc_seat_price_request).await?;
+                serde_json::to_value(seat_price)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             #[cfg(feature = "sandbox")]
             "sandbox_patch_state" => {
                 let sandbox_patch_state_request =
@@ -815,6 +946,34 @@ impl JsonRpcHandler {
         Ok(near_jsonrpc_primitives::types::chunks::RpcChunkResponse { chunk_view })
     }
 
+    async fn block_details(
+        &self,
+        request_data: near_jsonrpc_primitives::types::blocks::RpcBlockRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::blocks::RpcBlockDetailsResponse,
+        near_jsonrpc_primitives::types::blocks::RpcBlockError,
+    > {
+        let block_details_view = self
+            .view_client_addr
+            .send(GetBlockDetails(request_data.block_reference.into()))
+            .await??;
+        Ok(near_jsonrpc_primitives::types::blocks::RpcBlockDetailsResponse { block_details_view })
+    }
+
+    async fn chunk_details(
+        &self,
+        request_data: near_jsonrpc_primitives::types::chunks::RpcChunkRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::chunks::RpcChunkDetailsResponse,
+        near_jsonrpc_primitives::types::chunks::RpcChunkError,
+    > {
+        let chunk_details_view = self
+            .view_client_addr
+            .send(GetChunkDetails(GetChunk::from(request_data.chunk_reference)))
+            .await??;
+        Ok(near_jsonrpc_primitives::types::chunks::RpcChunkDetailsResponse { chunk_details_view })
+    }
+
     async fn receipt(
         &self,
         request_data: near_jsonrpc_primitives::types::receipts::RpcReceiptRequest,
@@ -880,6 +1039,27 @@ impl JsonRpcHandler {
         })
     }
 
+    async fn filtered_changes_in_block(
+        &self,
+        request: near_jsonrpc_primitives::types::changes::RpcFilteredStateChangesRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::changes::RpcStateChangesResponse,
+        near_jsonrpc_primitives::types::changes::RpcStateChangesError,
+    > {
+        let block = self.view_client_addr.send(GetBlock(request.block_reference.into())).await??;
+
+        let block_hash = block.header.hash.clone();
+        let changes = self
+            .view_client_addr
+            .send(GetFilteredStateChanges { block_hash, filter_id: request.filter_id })
+            .await??;
+
+        Ok(near_jsonrpc_primitives::types::changes::RpcStateChangesResponse {
+            block_hash: block.header.hash,
+            changes,
+        })
+    }
+
     async fn next_light_client_block(
         &self,
         request: near_jsonrpc_primitives::types::light_client::RpcLightClientNextBlockRequest,
@@ -934,6 +1114,51 @@ impl JsonRpcHandler {
         Ok(self.client_addr.send(GetNetworkInfo {}).await??.into())
     }
 
+    async fn dropped_transactions(
+        &self,
+    ) -> Result<
+        near_jsonrpc_primitives::types::dropped_transactions::RpcDroppedTransactionsResponse,
+        near_jsonrpc_primitives::types::dropped_transactions::RpcDroppedTransactionsError,
+    > {
+        Ok(self.client_addr.send(GetDroppedTransactions {}).await??.into())
+    }
+
+    async fn tx_trace(
+        &self,
+        request_data: near_jsonrpc_primitives::types::transaction_trace::RpcTransactionTraceRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::transaction_trace::RpcTransactionTraceResponse,
+        near_jsonrpc_primitives::types::transaction_trace::RpcTransactionTraceError,
+    > {
+        let transaction_hash = request_data.transaction_hash;
+        match self.client_addr.send(GetTransactionTrace { transaction_hash }).await?? {
+            Some(transaction_trace) => Ok(transaction_trace.into()),
+            None => Err(
+                near_jsonrpc_primitives::types::transaction_trace::RpcTransactionTraceError::UnknownTransaction {
+                    transaction_hash,
+                },
+            ),
+        }
+    }
+
+    async fn contract_stats(
+        &self,
+        request_data: near_jsonrpc_primitives::types::contract_stats::RpcContractExecutionStatsRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::contract_stats::RpcContractExecutionStatsResponse,
+        near_jsonrpc_primitives::types::contract_stats::RpcContractExecutionStatsError,
+    > {
+        let code_hash = request_data.code_hash;
+        match self.view_client_addr.send(GetContractExecutionStats { code_hash }).await?? {
+            Some(contract_stats) => Ok(contract_stats.into()),
+            None => Err(
+                near_jsonrpc_primitives::types::contract_stats::RpcContractExecutionStatsError::UnknownContract {
+                    code_hash,
+                },
+            ),
+        }
+    }
+
     async fn gas_price(
         &self,
         request_data: near_jsonrpc_primitives::types::gas_price::RpcGasPriceRequest,
@@ -974,6 +1199,36 @@ impl JsonRpcHandler {
             request;
         Ok(self.view_client_addr.send(GetValidatorOrdered { block_id }).await??.into())
     }
+
+    /// Returns the validators which joined, left or were kicked out in the epoch, and the ones
+    /// whose stake changed, relative to the previous epoch.
+    async fn validators_diff(
+        &self,
+        request_data: near_jsonrpc_primitives::types::validator::RpcValidatorsDiffRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcValidatorsDiffResponse,
+        near_jsonrpc_primitives::types::validator::RpcValidatorError,
+    > {
+        let validators_diff = self
+            .view_client_addr
+            .send(GetValidatorDiff { epoch_reference: request_data.epoch_reference })
+            .await??;
+        Ok(near_jsonrpc_primitives::types::validator::RpcValidatorsDiffResponse { validators_diff })
+    }
+
+    /// Returns the seat prices of the epoch of the block and the next epoch, and the minimum
+    /// stake which gets a seat in the epoch after, given the proposals made so far.
+    async fn seat_price(
+        &self,
+        request_data: near_jsonrpc_primitives::types::validator::RpcSeatPriceRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcSeatPriceResponse,
+        near_jsonrpc_primitives::types::validator::RpcValidatorError,
+    > {
+        let seat_prices =
+            self.view_client_addr.send(GetSeatPrice { block_id: request_data.block_id }).await??;
+        Ok(near_jsonrpc_primitives::types::validator::RpcSeatPriceResponse { seat_prices })
+    }
 }
 
 #[cfg(feature = "sandbox")]
@@ -1132,8 +1387,10 @@ fn rpc_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
     let response = async move {
-        let message = handler.process(message.0).await?;
-        Ok(HttpResponse::Ok().json(&message))
+        match handler.process(message.0).await? {
+            Some(message) => Ok(HttpResponse::Ok().json(&message)),
+            None => Ok(HttpResponse::NoContent().finish()),
+        }
     };
     response.boxed()
 }
@@ -1176,6 +1433,13 @@ fn network_info_handler(
     response.boxed()
 }
 
+fn network_events_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move { Ok(HttpResponse::Ok().json(&handler.network_event_log.events())) };
+    response.boxed()
+}
+
 pub async fn prometheus_handler() -> Result<HttpResponse, HttpError> {
     near_metrics::inc_counter(&metrics::PROMETHEUS_REQUEST_COUNT);
 
@@ -1207,11 +1471,21 @@ pub fn start_http(
     genesis_config: GenesisConfig,
     client_addr: Addr<ClientActor>,
     view_client_addr: Addr<ViewClientActor>,
+    network_event_log: NetworkEventLog,
 ) {
-    let RpcConfig { addr, prometheus_addr, cors_allowed_origins, polling_config, limits_config } =
-        config;
+    let RpcConfig {
+        addr,
+        prometheus_addr,
+        cors_allowed_origins,
+        polling_config,
+        limits_config,
+        subscriptions_config,
+    } = config;
     let prometheus_addr = prometheus_addr.filter(|it| it != &addr);
     let cors_allowed_origins_clone = cors_allowed_origins.clone();
+    let subscriptions_hub =
+        web::Data::new(SubscriptionsHub::new(view_client_addr.clone(), subscriptions_config));
+    subscriptions_hub.start(polling_config);
     info!(target:"network", "Starting http server at {}", addr);
     HttpServer::new(move || {
         App::new()
@@ -1221,10 +1495,14 @@ pub fn start_http(
                 view_client_addr: view_client_addr.clone(),
                 polling_config,
                 genesis_config: genesis_config.clone(),
+                max_batch_size: limits_config.max_batch_size,
+                network_event_log: network_event_log.clone(),
             })
+            .app_data(subscriptions_hub.clone())
             .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
             .wrap(middleware::Logger::default())
             .service(web::resource("/").route(web::post().to(rpc_handler)))
+            .service(web::resource("/ws").route(web::get().to(subscriptions::ws_handler)))
             .service(
                 web::resource("/status")
                     .route(web::get().to(status_handler))
@@ -1236,6 +1514,9 @@ pub fn start_http(
                     .route(web::head().to(health_handler)),
             )
             .service(web::resource("/network_info").route(web::get().to(network_info_handler)))
+            .service(
+                web::resource("/debug/network_events").route(web::get().to(network_events_handler)),
+            )
             .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
     })
     .bind(addr)
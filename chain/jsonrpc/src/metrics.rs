@@ -1,5 +1,5 @@
 use lazy_static::lazy_static;
-use near_metrics::{HistogramVec, IntCounter, IntCounterVec};
+use near_metrics::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge};
 
 lazy_static! {
     pub static ref RPC_PROCESSING_TIME: near_metrics::Result<HistogramVec> =
@@ -36,4 +36,19 @@ lazy_static! {
             "Total count of errors by method and message",
             &["method", "err_code"]
         );
+    pub static ref RPC_BATCH_SIZE: near_metrics::Result<Histogram> =
+        near_metrics::try_create_histogram(
+            "near_rpc_batch_size",
+            "Number of requests in JSON RPC batches"
+        );
+    pub static ref WS_ACTIVE_CONNECTIONS: near_metrics::Result<IntGauge> =
+        near_metrics::try_create_int_gauge(
+            "near_rpc_ws_active_connections",
+            "Number of open WebSocket subscription connections"
+        );
+    pub static ref WS_SLOW_SUBSCRIBERS_DROPPED: near_metrics::Result<IntCounter> =
+        near_metrics::try_create_int_counter(
+            "near_rpc_ws_slow_subscribers_dropped",
+            "Number of WebSocket connections closed because they didn't keep up with notifications"
+        );
 }
@@ -0,0 +1,547 @@
+//! WebSocket endpoint with JSON RPC subscriptions.
+//!
+//! A single poller follows the head and the final block of the chain through the view client and
+//! broadcasts every new block to all open connections. Each connection keeps its own set of
+//! subscriptions and turns the broadcasted blocks into `subscription` notifications.
+//!
+//! The number of subscriptions is limited per connection and over the whole node. A connection
+//! which doesn't keep up with its notifications, so that its outgoing queue fills up or it misses
+//! chain events, is closed.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix::Addr;
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws;
+use actix_web::{http::header, web, Error as HttpError, HttpRequest, HttpResponse};
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+use near_client::{
+    GetBlock, GetExecutionOutcomesForBlock, GetFilteredStateChanges, ViewClientActor,
+};
+use near_jsonrpc_primitives::errors::RpcError;
+use near_jsonrpc_primitives::message::{self, Message, Request};
+use near_jsonrpc_primitives::types::changes::RpcStateChangesResponse;
+use near_jsonrpc_primitives::types::subscriptions::{
+    RpcExecutionOutcomesNotification, RpcSubscribeRequest, RpcSubscribeResponse,
+    RpcSubscriptionError, RpcSubscriptionKind, RpcSubscriptionNotification, RpcUnsubscribeRequest,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, BlockId, BlockReference, Finality};
+use near_primitives::views::BlockView;
+
+use crate::metrics;
+use crate::RpcPollingConfig;
+
+/// Maximum number of blocks the poller walks back to catch up with the chain.
+/// Older blocks are not reported to the subscribers.
+const MAX_MISSED_BLOCKS: usize = 16;
+/// Number of chain events buffered for a slow connection before it misses them and is closed.
+const EVENTS_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RpcSubscriptionsConfig {
+    /// Maximum number of active subscriptions on a single WebSocket connection.
+    pub max_subscriptions_per_connection: usize,
+    /// Maximum number of active subscriptions over all the WebSocket connections.
+    pub max_subscriptions: usize,
+    /// Maximum number of messages queued for a single WebSocket connection. The connection is
+    /// closed once its queue is full.
+    pub max_queued_messages_per_connection: usize,
+}
+
+impl Default for RpcSubscriptionsConfig {
+    fn default() -> Self {
+        Self {
+            max_subscriptions_per_connection: 16,
+            max_subscriptions: 1024,
+            max_queued_messages_per_connection: 256,
+        }
+    }
+}
+
+/// Number of active subscriptions over all the connections.
+struct SubscriptionsCounter {
+    active: AtomicUsize,
+    limit: usize,
+}
+
+impl SubscriptionsCounter {
+    fn new(limit: usize) -> Self {
+        Self { active: AtomicUsize::new(0), limit }
+    }
+
+    /// Reserves one of the subscriptions available on the node.
+    fn reserve(&self) -> Result<(), RpcSubscriptionError> {
+        let limit = self.limit;
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                if active < limit {
+                    Some(active + 1)
+                } else {
+                    None
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| RpcSubscriptionError::TooManySubscriptionsOnNode { limit })
+    }
+
+    fn release(&self, count: usize) {
+        self.active.fetch_sub(count, Ordering::SeqCst);
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ChainEvent {
+    NewHead(Arc<BlockView>),
+    FinalizedHead(Arc<BlockView>),
+}
+
+pub(crate) struct SubscriptionsHub {
+    view_client_addr: Addr<ViewClientActor>,
+    events: broadcast::Sender<ChainEvent>,
+    subscriptions: SubscriptionsCounter,
+    config: RpcSubscriptionsConfig,
+}
+
+impl SubscriptionsHub {
+    pub(crate) fn new(
+        view_client_addr: Addr<ViewClientActor>,
+        config: RpcSubscriptionsConfig,
+    ) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CHANNEL_CAPACITY);
+        let subscriptions = SubscriptionsCounter::new(config.max_subscriptions);
+        Self { view_client_addr, events, subscriptions, config }
+    }
+
+    /// Starts the chain poller feeding all the connections.
+    pub(crate) fn start(&self, polling_config: RpcPollingConfig) {
+        let view_client_addr = self.view_client_addr.clone();
+        let events = self.events.clone();
+        actix::spawn(async move {
+            let mut last_head = None;
+            let mut last_final = None;
+            loop {
+                tokio::time::sleep(polling_config.polling_interval).await;
+                if events.receiver_count() == 0 {
+                    // Nobody listens, start from the current head once somebody connects.
+                    last_head = None;
+                    last_final = None;
+                    continue;
+                }
+                for block in new_blocks(&view_client_addr, Finality::None, &mut last_head).await {
+                    let _ = events.send(ChainEvent::NewHead(block));
+                }
+                for block in new_blocks(&view_client_addr, Finality::Final, &mut last_final).await {
+                    let _ = events.send(ChainEvent::FinalizedHead(block));
+                }
+            }
+        });
+    }
+}
+
+async fn get_block(
+    view_client_addr: &Addr<ViewClientActor>,
+    block_reference: BlockReference,
+) -> Option<BlockView> {
+    match view_client_addr.send(GetBlock(block_reference)).await {
+        Ok(Ok(block)) => Some(block),
+        Ok(Err(err)) => {
+            debug!(target: "jsonrpc", "Subscriptions poller failed to fetch a block: {}", err);
+            None
+        }
+        Err(err) => {
+            debug!(target: "jsonrpc", "Subscriptions poller failed to reach view client: {}", err);
+            None
+        }
+    }
+}
+
+/// Returns the blocks built on top of `last_seen` up to the latest block with the given finality,
+/// oldest first, and moves `last_seen` forward.
+async fn new_blocks(
+    view_client_addr: &Addr<ViewClientActor>,
+    finality: Finality,
+    last_seen: &mut Option<(CryptoHash, BlockHeight)>,
+) -> Vec<Arc<BlockView>> {
+    let latest = match get_block(view_client_addr, BlockReference::Finality(finality)).await {
+        Some(block) => block,
+        None => return vec![],
+    };
+    let (last_hash, last_height) =
+        match last_seen.replace((latest.header.hash, latest.header.height)) {
+            Some(last) => last,
+            // Nothing to catch up with on the first poll.
+            None => return vec![],
+        };
+    if latest.header.hash == last_hash || latest.header.height <= last_height {
+        *last_seen = Some((last_hash, last_height));
+        return vec![];
+    }
+
+    let mut blocks = vec![Arc::new(latest)];
+    while blocks.len() < MAX_MISSED_BLOCKS {
+        let prev_hash = blocks.last().unwrap().header.prev_hash;
+        if prev_hash == last_hash {
+            break;
+        }
+        match get_block(view_client_addr, BlockId::Hash(prev_hash).into()).await {
+            Some(block) if block.header.height > last_height => blocks.push(Arc::new(block)),
+            _ => break,
+        }
+    }
+    blocks.reverse();
+    blocks
+}
+
+/// Subscriptions of a single connection.
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    active: HashMap<u64, RpcSubscriptionKind>,
+}
+
+impl Subscriptions {
+    fn subscribe(
+        &mut self,
+        kind: RpcSubscriptionKind,
+        limit: usize,
+    ) -> Result<u64, RpcSubscriptionError> {
+        if self.active.len() >= limit {
+            return Err(RpcSubscriptionError::TooManySubscriptions { limit });
+        }
+        let subscription_id = self.next_id;
+        self.next_id += 1;
+        self.active.insert(subscription_id, kind);
+        Ok(subscription_id)
+    }
+
+    fn unsubscribe(&mut self, subscription_id: u64) -> Result<(), RpcSubscriptionError> {
+        self.active
+            .remove(&subscription_id)
+            .map(|_| ())
+            .ok_or(RpcSubscriptionError::UnknownSubscription { subscription_id })
+    }
+}
+
+struct Session {
+    hub: web::Data<SubscriptionsHub>,
+    subscriptions: Subscriptions,
+    codec: ws::Codec,
+    sender: mpsc::Sender<Bytes>,
+}
+
+impl Session {
+    /// Queues the message for the client, returns false once the session is over.
+    fn send(&mut self, message: ws::Message) -> bool {
+        let mut buf = BytesMut::new();
+        if let Err(err) = self.codec.encode(message, &mut buf) {
+            warn!(target: "jsonrpc", "Failed to encode WebSocket frame: {}", err);
+            return false;
+        }
+        match self.sender.try_send(buf.freeze()) {
+            Ok(()) => true,
+            Err(err) => {
+                if err.is_full() {
+                    debug!(target: "jsonrpc", "Closing WebSocket subscriber which doesn't read its messages");
+                    near_metrics::inc_counter(&metrics::WS_SLOW_SUBSCRIBERS_DROPPED);
+                }
+                false
+            }
+        }
+    }
+
+    fn send_json(&mut self, message: Message) -> bool {
+        let text: String = message.into();
+        self.send(ws::Message::Text(text.into()))
+    }
+
+    fn notify(&mut self, subscription_id: u64, result: Value) -> bool {
+        let params = serde_json::to_value(RpcSubscriptionNotification { subscription_id, result })
+            .expect("Notification is always serializable");
+        self.send_json(Message::notification("subscription".to_owned(), Some(params)))
+    }
+
+    fn process_request(&mut self, request: Request) -> Result<Value, RpcError> {
+        match request.method.as_ref() {
+            "subscribe" => {
+                let request = RpcSubscribeRequest::parse(request.params)?;
+                self.hub.subscriptions.reserve()?;
+                let subscription_id = match self
+                    .subscriptions
+                    .subscribe(request.kind, self.hub.config.max_subscriptions_per_connection)
+                {
+                    Ok(subscription_id) => subscription_id,
+                    Err(err) => {
+                        self.hub.subscriptions.release(1);
+                        return Err(err.into());
+                    }
+                };
+                serde_json::to_value(RpcSubscribeResponse { subscription_id })
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "unsubscribe" => {
+                let request = RpcUnsubscribeRequest::parse(request.params)?;
+                self.subscriptions.unsubscribe(request.subscription_id)?;
+                self.hub.subscriptions.release(1);
+                Ok(Value::Bool(true))
+            }
+            _ => Err(RpcError::method_not_found(request.method)),
+        }
+    }
+
+    fn process_text(&mut self, text: &[u8]) -> bool {
+        let reply = match message::from_slice(text) {
+            Ok(Message::Request(request)) => {
+                let id = request.id.clone();
+                Message::response(id, self.process_request(request))
+            }
+            Ok(_) => Message::error(RpcError::parse_error(
+                "JSON RPC Request format was expected".to_owned(),
+            )),
+            Err(broken) => broken.reply(),
+        };
+        self.send_json(reply)
+    }
+
+    /// Handles a frame received from the client, returns false once the session is over.
+    fn process_frame(&mut self, frame: ws::Frame) -> bool {
+        match frame {
+            ws::Frame::Text(text) => self.process_text(&text),
+            ws::Frame::Binary(_) => self.send_json(Message::error(RpcError::parse_error(
+                "Binary messages are not supported".to_owned(),
+            ))),
+            ws::Frame::Ping(payload) => self.send(ws::Message::Pong(payload)),
+            ws::Frame::Pong(_) => true,
+            ws::Frame::Close(reason) => {
+                self.send(ws::Message::Close(reason));
+                false
+            }
+            ws::Frame::Continuation(_) => {
+                // Requests are small enough to never be fragmented.
+                self.send(ws::Message::Close(Some(ws::CloseCode::Unsupported.into())));
+                false
+            }
+        }
+    }
+
+    async fn process_event(&mut self, event: ChainEvent) -> bool {
+        match event {
+            ChainEvent::NewHead(block) => self.process_new_head(block).await,
+            ChainEvent::FinalizedHead(block) => {
+                self.notify_all(|kind| kind == &RpcSubscriptionKind::FinalizedHeads, &block)
+            }
+        }
+    }
+
+    fn notify_all(
+        &mut self,
+        matches: impl Fn(&RpcSubscriptionKind) -> bool,
+        block: &BlockView,
+    ) -> bool {
+        let ids: Vec<u64> = self
+            .subscriptions
+            .active
+            .iter()
+            .filter(|(_, kind)| matches(kind))
+            .map(|(id, _)| *id)
+            .collect();
+        if ids.is_empty() {
+            return true;
+        }
+        let result = serde_json::to_value(block).expect("BlockView is always serializable");
+        ids.into_iter().all(|id| self.notify(id, result.clone()))
+    }
+
+    async fn process_new_head(&mut self, block: Arc<BlockView>) -> bool {
+        if !self.notify_all(|kind| kind == &RpcSubscriptionKind::NewHeads, &block) {
+            return false;
+        }
+        let block_hash = block.header.hash;
+        let subscriptions: Vec<(u64, RpcSubscriptionKind)> = self
+            .subscriptions
+            .active
+            .iter()
+            .filter(|(_, kind)| {
+                matches!(
+                    kind,
+                    RpcSubscriptionKind::ExecutionOutcomes { .. }
+                        | RpcSubscriptionKind::StateChanges { .. }
+                )
+            })
+            .map(|(id, kind)| (*id, kind.clone()))
+            .collect();
+        for (subscription_id, kind) in subscriptions {
+            let result = match kind {
+                RpcSubscriptionKind::ExecutionOutcomes { account_id } => {
+                    self.execution_outcomes(block_hash, &account_id).await
+                }
+                RpcSubscriptionKind::StateChanges { filter_id } => {
+                    self.state_changes(block_hash, filter_id).await
+                }
+                RpcSubscriptionKind::NewHeads | RpcSubscriptionKind::FinalizedHeads => None,
+            };
+            if let Some(result) = result {
+                if !self.notify(subscription_id, result) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    async fn execution_outcomes(&self, block_hash: CryptoHash, account_id: &str) -> Option<Value> {
+        let outcomes =
+            match self.hub.view_client_addr.send(GetExecutionOutcomesForBlock { block_hash }).await
+            {
+                Ok(Ok(outcomes)) => outcomes,
+                _ => return None,
+            };
+        let outcomes: Vec<_> = outcomes
+            .into_iter()
+            .flat_map(|(_, outcomes)| outcomes)
+            .filter(|outcome| outcome.outcome.executor_id == account_id)
+            .collect();
+        if outcomes.is_empty() {
+            return None;
+        }
+        serde_json::to_value(RpcExecutionOutcomesNotification { block_hash, outcomes }).ok()
+    }
+
+    async fn state_changes(&self, block_hash: CryptoHash, filter_id: String) -> Option<Value> {
+        let changes = match self
+            .hub
+            .view_client_addr
+            .send(GetFilteredStateChanges { block_hash, filter_id })
+            .await
+        {
+            Ok(Ok(changes)) => changes,
+            _ => return None,
+        };
+        if changes.is_empty() {
+            return None;
+        }
+        serde_json::to_value(RpcStateChangesResponse { block_hash, changes }).ok()
+    }
+}
+
+async fn run_session(mut session: Session, mut payload: web::Payload) {
+    near_metrics::inc_gauge(&metrics::WS_ACTIVE_CONNECTIONS);
+    let mut events = session.hub.events.subscribe();
+    let mut buf = BytesMut::new();
+    let mut decoder = ws::Codec::new();
+    'session: loop {
+        tokio::select! {
+            chunk = payload.next() => {
+                let chunk = match chunk {
+                    Some(Ok(chunk)) => chunk,
+                    _ => break 'session,
+                };
+                buf.extend_from_slice(&chunk);
+                loop {
+                    match decoder.decode(&mut buf) {
+                        Ok(Some(frame)) => {
+                            if !session.process_frame(frame) {
+                                break 'session;
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(err) => {
+                            debug!(target: "jsonrpc", "Invalid WebSocket frame: {}", err);
+                            break 'session;
+                        }
+                    }
+                }
+            }
+            event = events.recv() => {
+                match event {
+                    Ok(event) => {
+                        if !session.process_event(event).await {
+                            break 'session;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        debug!(
+                            target: "jsonrpc",
+                            "Closing WebSocket subscriber which lagged {} chain events behind",
+                            skipped
+                        );
+                        near_metrics::inc_counter(&metrics::WS_SLOW_SUBSCRIBERS_DROPPED);
+                        break 'session;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break 'session,
+                }
+            }
+        }
+    }
+    session.hub.subscriptions.release(session.subscriptions.active.len());
+    near_metrics::dec_gauge(&metrics::WS_ACTIVE_CONNECTIONS);
+}
+
+pub(crate) async fn ws_handler(
+    req: HttpRequest,
+    payload: web::Payload,
+    hub: web::Data<SubscriptionsHub>,
+) -> Result<HttpResponse, HttpError> {
+    ws::verify_handshake(req.head())?;
+    let accept = match req.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => ws::hash_key(key.as_ref()),
+        None => return Ok(HttpResponse::BadRequest().finish()),
+    };
+    let accept = header::HeaderValue::from_bytes(accept.as_ref())
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Invalid WebSocket accept key"))?;
+
+    let (sender, receiver) = mpsc::channel(hub.config.max_queued_messages_per_connection);
+    let session =
+        Session { hub, subscriptions: Subscriptions::default(), codec: ws::Codec::new(), sender };
+    actix::spawn(run_session(session, payload));
+
+    Ok(HttpResponse::SwitchingProtocols()
+        .upgrade("websocket")
+        .insert_header((header::SEC_WEBSOCKET_ACCEPT, accept))
+        .streaming(receiver.map(Ok::<_, HttpError>)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscriptions_limit() {
+        let mut subscriptions = Subscriptions::default();
+        assert_eq!(subscriptions.subscribe(RpcSubscriptionKind::NewHeads, 2).unwrap(), 0);
+        assert_eq!(subscriptions.subscribe(RpcSubscriptionKind::FinalizedHeads, 2).unwrap(), 1);
+        assert!(matches!(
+            subscriptions.subscribe(RpcSubscriptionKind::NewHeads, 2),
+            Err(RpcSubscriptionError::TooManySubscriptions { limit: 2 })
+        ));
+        subscriptions.unsubscribe(0).unwrap();
+        assert!(matches!(
+            subscriptions.unsubscribe(0),
+            Err(RpcSubscriptionError::UnknownSubscription { subscription_id: 0 })
+        ));
+        // Ids are never reused within a connection.
+        assert_eq!(subscriptions.subscribe(RpcSubscriptionKind::NewHeads, 2).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_node_subscriptions_limit() {
+        let counter = SubscriptionsCounter::new(2);
+        counter.reserve().unwrap();
+        counter.reserve().unwrap();
+        assert!(matches!(
+            counter.reserve(),
+            Err(RpcSubscriptionError::TooManySubscriptionsOnNode { limit: 2 })
+        ));
+        counter.release(2);
+        counter.reserve().unwrap();
+    }
+}
@@ -9,12 +9,16 @@ use near_crypto::{KeyType, PublicKey, Signature};
 use near_jsonrpc::client::new_client;
 use near_jsonrpc_client::ChunkId;
 use near_jsonrpc_primitives::types::query::QueryResponseKind;
-use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
+use near_jsonrpc_primitives::types::validator::{
+    RpcValidatorsDiffRequest, RpcValidatorsOrderedRequest,
+};
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::WaitOrTimeout;
 use near_primitives::account::{AccessKey, AccessKeyPermission};
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::{BlockId, BlockReference, ShardId, SyncCheckpoint};
+use near_primitives::types::{
+    BlockId, BlockReference, EpochId, EpochReference, ShardId, SyncCheckpoint,
+};
 use near_primitives::views::QueryRequest;
 
 #[macro_use]
@@ -116,6 +120,30 @@ fn test_chunk_by_hash() {
     });
 }
 
+/// Retrieve block and chunk details via json rpc
+#[test]
+fn test_block_and_chunk_details() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let block_details = client
+            .EXPERIMENTAL_block_details(BlockReference::BlockId(BlockId::Height(0)))
+            .await
+            .unwrap();
+        let block = block_details.block_details_view.block;
+        assert_eq!(block.header.height, 0);
+        assert_eq!(block_details.block_details_view.chunk_producers.len(), block.chunks.len());
+        assert_eq!(block_details.block_details_view.chunk_producers[0], "test2");
+
+        let chunk_details = client
+            .EXPERIMENTAL_chunk_details(ChunkId::BlockShardId(BlockId::Height(0), 0))
+            .await
+            .unwrap();
+        let chunk_details = chunk_details.chunk_details_view;
+        assert_eq!(chunk_details.block_hash, block.header.hash);
+        assert_eq!(chunk_details.chunk.author, "test2");
+        assert_eq!(chunk_details.chunk.header.chunk_hash, block.chunks[0].chunk_hash);
+    });
+}
+
 /// Retrieve chunk via json rpc
 #[test]
 fn test_chunk_invalid_shard_id() {
@@ -302,6 +330,9 @@ fn test_query_state() {
                 request: QueryRequest::ViewState {
                     account_id: "test".to_string(),
                     prefix: vec![].into(),
+                    start_key: None,
+                    limit: None,
+                    include_proof: false,
                 },
             })
             .await
@@ -466,6 +497,19 @@ fn test_validators_ordered() {
     });
 }
 
+#[test]
+fn test_validators_diff() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let response = client
+            .EXPERIMENTAL_validators_diff(RpcValidatorsDiffRequest {
+                epoch_reference: EpochReference::BlockId(BlockId::Height(0)),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.validators_diff.epoch_id, EpochId::default());
+    });
+}
+
 /// Retrieve genesis config via JSON RPC.
 /// WARNING: Be mindful about changing genesis structure as it is part of the public protocol!
 #[test]
@@ -554,6 +598,95 @@ fn test_invalid_methods() {
     });
 }
 
+#[test]
+fn test_batch_request() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let json = serde_json::json!([
+            {
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "block",
+                "params": {"block_id": 0},
+            },
+            {
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "no_such_method",
+                "params": [],
+            },
+            {"not": "a request"},
+            {
+                "jsonrpc": "2.0",
+                "method": "status",
+                "params": [],
+            },
+        ]);
+        let response = &mut client
+            .client
+            .post(&client.server_addr)
+            .insert_header(("Content-Type", "application/json"))
+            .send_json(&json)
+            .await
+            .unwrap();
+
+        let response =
+            serde_json::from_value::<serde_json::Value>(response.json().await.unwrap()).unwrap();
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], json!(1));
+        assert_eq!(responses[0]["result"]["header"]["height"], json!(0));
+        assert_eq!(responses[1]["id"], json!(2));
+        assert_ne!(responses[1]["error"], json!(null));
+        assert_ne!(responses[2]["error"], json!(null));
+    });
+}
+
+#[test]
+fn test_batch_of_notifications() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "status",
+            "params": [],
+        });
+        let json = serde_json::Value::Array(vec![notification; 2]);
+        let response = client
+            .client
+            .post(&client.server_addr)
+            .insert_header(("Content-Type", "application/json"))
+            .send_json(&json)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), actix_web::http::StatusCode::NO_CONTENT);
+    });
+}
+
+#[test]
+fn test_batch_request_too_large() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": "dontcare",
+            "method": "status",
+            "params": [],
+        });
+        let json = serde_json::Value::Array(vec![request; 101]);
+        let response = &mut client
+            .client
+            .post(&client.server_addr)
+            .insert_header(("Content-Type", "application/json"))
+            .send_json(&json)
+            .await
+            .unwrap();
+
+        let response =
+            serde_json::from_value::<serde_json::Value>(response.json().await.unwrap()).unwrap();
+        assert!(!response.is_array());
+        assert_ne!(response["error"], json!(null));
+    });
+}
+
 #[test]
 #[ignore] // https://github.com/nearprotocol/nearcore/issues/2789
 fn test_query_view_account_non_existing_account_must_return_error() {
@@ -651,6 +784,9 @@ fn test_view_state_non_existing_account_invalid_prefix() {
                 request: QueryRequest::ViewState {
                     account_id: "\u{0}\u{0}\u{0}\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{8}\u{0}\u{0}\u{0}\u{0}\u{0}eeeeeeeeeeeeeeeeeeeeeeeeeeeee".to_string(),
                     prefix: "eeeeeeeeeeee".as_bytes().to_vec().into(),
+                    start_key: None,
+                    limit: None,
+                    include_proof: false,
                 },
             })
             .await
@@ -6,6 +6,7 @@ use near_chain_configs::GenesisConfig;
 use near_client::test_utils::setup_no_network_with_validity_period_and_no_epoch_sync;
 use near_client::ViewClientActor;
 use near_jsonrpc::{start_http, RpcConfig};
+use near_network::event_log::NetworkEventLog;
 use near_network::test_utils::open_port;
 use near_primitives::types::NumBlocks;
 
@@ -45,6 +46,7 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
         TEST_GENESIS_CONFIG.clone(),
         client_addr.clone(),
         view_client_addr.clone(),
+        NetworkEventLog::default(),
     );
     (view_client_addr, addr)
 }
@@ -0,0 +1,95 @@
+//! Selection of the peers a broadcast message is sent to.
+//!
+//! Blocks are of interest to every node, so they are sent to all the active peers. Other messages
+//! only matter to the validators, e.g. challenges, and are sent only to the active peers which
+//! announced a validator account, plus the active peers on the shortest routes to the validators
+//! which aren't directly connected. If no validator can be reached this way the message is sent to
+//! all the active peers, so it still spreads through the network.
+//!
+//! Chunk parts, forwarded chunk parts and transactions are not broadcast at all: they are routed
+//! to the accounts of the chunk producers and validators of the shard which need them.
+use std::collections::{HashMap, HashSet};
+
+use near_primitives::network::PeerId;
+
+/// Which of the active peers a broadcast message is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastFanout {
+    /// All the active peers.
+    All,
+    /// The active peers which are validators or on the shortest route to one.
+    Validators,
+}
+
+/// Selects the active peers a message with the given fanout is sent to.
+///
+/// `validators` are the peers which announced a validator account and `peer_forwarding` the
+/// active peers on the shortest routes to each reachable peer, as kept by the routing table.
+pub fn select_fanout<'a>(
+    fanout: BroadcastFanout,
+    active_peers: impl Iterator<Item = &'a PeerId>,
+    validators: &HashSet<PeerId>,
+    peer_forwarding: &HashMap<PeerId, Vec<PeerId>>,
+) -> Vec<PeerId> {
+    let active_peers: Vec<_> = active_peers.cloned().collect();
+    if fanout == BroadcastFanout::All {
+        return active_peers;
+    }
+    let mut targets = HashSet::new();
+    for validator in validators.iter() {
+        targets.insert(validator);
+        if let Some(next_hops) = peer_forwarding.get(validator) {
+            targets.extend(next_hops.iter());
+        }
+    }
+    let selected: Vec<_> =
+        active_peers.iter().filter(|peer_id| targets.contains(peer_id)).cloned().collect();
+    if selected.is_empty() {
+        active_peers
+    } else {
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::random_peer_id;
+
+    use super::*;
+
+    #[test]
+    fn test_validators_fanout() {
+        let active: Vec<_> = (0..4).map(|_| random_peer_id()).collect();
+        let remote_validator = random_peer_id();
+        // The first active peer is a validator, the second one is the next hop towards a validator
+        // which isn't directly connected.
+        let validators: HashSet<_> =
+            vec![active[0].clone(), remote_validator.clone()].into_iter().collect();
+        let mut peer_forwarding = HashMap::new();
+        peer_forwarding.insert(active[0].clone(), vec![active[0].clone()]);
+        peer_forwarding.insert(remote_validator, vec![active[1].clone()]);
+        peer_forwarding.insert(active[2].clone(), vec![active[2].clone()]);
+
+        let selected: HashSet<_> = select_fanout(
+            BroadcastFanout::Validators,
+            active.iter(),
+            &validators,
+            &peer_forwarding,
+        )
+        .into_iter()
+        .collect();
+        assert_eq!(selected, active[..2].iter().cloned().collect());
+
+        let all = select_fanout(BroadcastFanout::All, active.iter(), &validators, &peer_forwarding);
+        assert_eq!(all, active);
+    }
+
+    #[test]
+    fn test_no_reachable_validator_falls_back_to_all() {
+        let active: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+        let validators: HashSet<_> = vec![random_peer_id()].into_iter().collect();
+        let selected =
+            select_fanout(BroadcastFanout::Validators, active.iter(), &validators, &HashMap::new());
+        assert_eq!(selected, active);
+    }
+}
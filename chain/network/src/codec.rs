@@ -1,4 +1,5 @@
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytes::{Buf, BufMut, BytesMut};
@@ -7,23 +8,39 @@ use tokio_util::codec::{Decoder, Encoder};
 use tracing::error;
 
 use crate::metrics;
+use crate::noise::{FrameCipher, NoiseTransport};
 use crate::types::{PeerMessage, ReasonForBan};
 use near_performance_metrics::framed_write::EncoderCallBack;
 #[cfg(feature = "performance_stats")]
 use near_performance_metrics::stats_enabled::get_thread_stats_logger;
 use near_rust_allocator_proxy::allocator::get_tid;
 
-const NETWORK_MESSAGE_MAX_SIZE: u32 = 512 * MIB as u32;
+pub(crate) const NETWORK_MESSAGE_MAX_SIZE: u32 = 512 * MIB as u32;
 const MAX_CAPACITY: u64 = GIB;
 
 pub struct Codec {
     max_length: u32,
+    /// Cipher of the frames, if the connection is encrypted.
+    cipher: Option<FrameCipher>,
 }
 
 #[allow(clippy::new_without_default)]
 impl Codec {
     pub fn new() -> Self {
-        Codec { max_length: NETWORK_MESSAGE_MAX_SIZE as u32 }
+        Codec { max_length: NETWORK_MESSAGE_MAX_SIZE as u32, cipher: None }
+    }
+
+    /// Codec of one direction of an encrypted connection.
+    pub fn encrypted(transport: Arc<NoiseTransport>) -> Self {
+        Codec { cipher: Some(FrameCipher::new(transport)), ..Codec::new() }
+    }
+
+    /// Size of the frame carrying a message of the given size, without the length prefix.
+    fn frame_len(&self, len: usize) -> usize {
+        match self.cipher {
+            Some(_) => FrameCipher::encrypted_len(len),
+            None => len,
+        }
     }
 }
 
@@ -38,24 +55,21 @@ impl EncoderCallBack for Codec {
     }
 }
 
-impl Encoder<Vec<u8>> for Codec {
+impl<T: AsRef<[u8]>> Encoder<T> for Codec {
     type Error = Error;
 
-    fn encode(&mut self, item: Vec<u8>, buf: &mut BytesMut) -> Result<(), Error> {
+    fn encode(&mut self, item: T, buf: &mut BytesMut) -> Result<(), Error> {
+        let item = item.as_ref();
         if item.len() > self.max_length as usize {
             Err(Error::new(ErrorKind::InvalidInput, "Input is too long"))
         } else {
+            let frame_len = self.frame_len(item.len());
             #[cfg(feature = "performance_stats")]
             {
                 let stat = get_thread_stats_logger();
-                stat.lock().unwrap().log_add_write_buffer(
-                    item.len() + 4,
-                    buf.len(),
-                    buf.capacity(),
-                );
+                stat.lock().unwrap().log_add_write_buffer(frame_len + 4, buf.len(), buf.capacity());
             }
-            if buf.capacity() >= MAX_CAPACITY as usize
-                && item.len() + 4 + buf.len() > buf.capacity()
+            if buf.capacity() >= MAX_CAPACITY as usize && frame_len + 4 + buf.len() > buf.capacity()
             {
                 error!(target: "network", "{} throwing away message, because buffer is full item.len(): {} buf.capacity: {}", get_tid(), item.len(), buf.capacity());
 
@@ -63,9 +77,12 @@ impl Encoder<Vec<u8>> for Codec {
                 return Err(Error::new(ErrorKind::Other, "Buf max capacity exceeded"));
             }
             // First four bytes is the length of the buffer.
-            buf.reserve(item.len() + 4);
-            buf.put_u32_le(item.len() as u32);
-            buf.put(&item[..]);
+            buf.reserve(frame_len + 4);
+            buf.put_u32_le(frame_len as u32);
+            match &mut self.cipher {
+                Some(cipher) => cipher.encrypt(item, buf)?,
+                None => buf.put(item),
+            }
             Ok(())
         }
     }
@@ -85,7 +102,7 @@ impl Decoder for Codec {
         len_bytes.copy_from_slice(&buf[0..4]);
         let len = u32::from_le_bytes(len_bytes);
 
-        if len > self.max_length {
+        if len as usize > self.frame_len(self.max_length as usize) {
             // If this point is reached, abusive peer is banned.
             return Ok(Some(Err(ReasonForBan::Abusive)));
         }
@@ -94,7 +111,12 @@ impl Decoder for Codec {
             // not enough bytes, keep waiting
             Ok(None)
         } else {
-            let res = Some(Ok(buf[4..4 + len as usize].to_vec()));
+            let frame = &buf[4..4 + len as usize];
+            // A frame which fails to decrypt is an error of the stream, which closes the connection.
+            let res = match &mut self.cipher {
+                Some(cipher) => Some(Ok(cipher.decrypt(frame)?)),
+                None => Some(Ok(frame.to_vec())),
+            };
             buf.advance(4 + len as usize);
             Ok(res)
         }
@@ -405,6 +427,31 @@ mod test {
         assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Err(ReasonForBan::Abusive)));
     }
 
+    #[test]
+    fn test_encrypted_codec() {
+        let (initiator, responder) = crate::noise::transport_pair();
+        let (initiator, responder) = (Arc::new(initiator), Arc::new(responder));
+        let mut encoder = Codec::encrypted(initiator.clone());
+        let mut decoder = Codec::encrypted(responder.clone());
+        let mut buffer = BytesMut::new();
+        // Messages larger than a Noise message are split into several chunks.
+        let messages = vec![vec![], vec![1; 10], vec![2; 200_000]];
+        for message in messages.iter() {
+            encoder.encode(message.clone(), &mut buffer).unwrap();
+        }
+        for message in messages.iter() {
+            assert_eq!(decoder.decode(&mut buffer).unwrap().unwrap().unwrap(), *message);
+        }
+        assert_eq!(buffer.len(), 0);
+
+        // Frames are authenticated, and each direction has its own key.
+        Codec::encrypted(initiator.clone()).encode(vec![3; 10], &mut buffer).unwrap();
+        assert!(Codec::encrypted(initiator).decode(&mut buffer.clone()).is_err());
+        let last = buffer.len() - 1;
+        buffer[last] ^= 1;
+        assert!(Codec::encrypted(responder).decode(&mut buffer).is_err());
+    }
+
     #[test]
     fn test_not_abusive() {
         let mut codec = Codec::new();
@@ -0,0 +1,175 @@
+//! Log of the significant network events, kept in memory for postmortems.
+//!
+//! Connections, disconnections, bans, failed handshakes and changes of the routing table are
+//! recorded together with their time in a bounded ring buffer, dropping the oldest events first.
+//! The log is served by the `/debug/network_events` endpoint and dumped when the node panics, so
+//! intermittent connectivity issues can be reconstructed after the fact.
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::panic;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::error;
+
+use near_primitives::network::PeerId;
+
+use crate::types::{PeerType, ReasonForBan};
+
+/// Number of the most recent events kept by default.
+pub const NETWORK_EVENT_LOG_SIZE: usize = 1000;
+
+/// Why a connection to a peer was closed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The connection was closed by the peer or failed.
+    ConnectionClosed,
+    /// The peer didn't complete the handshake in time.
+    HandshakeTimeout,
+    /// The peer rejected our handshake.
+    HandshakeRejected,
+    /// The handshake of the peer was invalid, e.g. sent by ourselves or with an invalid nonce.
+    InvalidHandshake,
+    /// The peer manager refused the connection, the reason is recorded by the peer manager.
+    NotConsolidated,
+    /// The peer asked to disconnect.
+    PeerRequested,
+    /// The peer manager closed the connection, e.g. to make room for other peers or because it
+    /// was replaced by a newer one.
+    Unregistered,
+    /// The peer didn't read the messages sent to it fast enough, so a send queue overflowed.
+    SendQueueFull,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum NetworkEvent {
+    Connected {
+        peer_id: PeerId,
+        addr: Option<SocketAddr>,
+        peer_type: PeerType,
+    },
+    Disconnected {
+        peer_id: PeerId,
+        reason: DisconnectReason,
+    },
+    Banned {
+        peer_id: PeerId,
+        reason: ReasonForBan,
+    },
+    HandshakeFailed {
+        peer_id: PeerId,
+        reason: String,
+    },
+    /// Number of peers reachable through the routing table changed.
+    RoutingTableChanged {
+        reachable_peers: usize,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkEventRecord {
+    pub time: DateTime<Utc>,
+    pub event: NetworkEvent,
+}
+
+/// Ring buffer of the most recent network events. Clones share the same buffer.
+#[derive(Clone)]
+pub struct NetworkEventLog {
+    events: Arc<Mutex<VecDeque<NetworkEventRecord>>>,
+    capacity: usize,
+}
+
+impl Default for NetworkEventLog {
+    fn default() -> Self {
+        Self::new(NETWORK_EVENT_LOG_SIZE)
+    }
+}
+
+impl NetworkEventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    fn lock(&self) -> MutexGuard<VecDeque<NetworkEventRecord>> {
+        // The log is still worth reading if some thread panicked while holding the lock.
+        self.events.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn record(&self, event: NetworkEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.lock();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(NetworkEventRecord { time: Utc::now(), event });
+    }
+
+    /// Recorded events, the most recent last.
+    pub fn events(&self) -> Vec<NetworkEventRecord> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Human readable dump of the recorded events, one per line.
+    pub fn report(&self) -> String {
+        let events = self.lock();
+        let mut report = format!("Last {} network events:\n", events.len());
+        for record in events.iter() {
+            report.push_str(&format!("{} {:?}\n", record.time.to_rfc3339(), record.event));
+        }
+        report
+    }
+
+    /// Dumps the log when the process panics, before running the previously installed hook.
+    pub fn dump_on_panic(&self) {
+        let event_log = self.clone();
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            error!(target: "network", "{}", event_log.report());
+            previous_hook(info);
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::random_peer_id;
+
+    use super::*;
+
+    fn disconnected(peer_id: &PeerId) -> NetworkEvent {
+        NetworkEvent::Disconnected {
+            peer_id: peer_id.clone(),
+            reason: DisconnectReason::HandshakeTimeout,
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest_events() {
+        let event_log = NetworkEventLog::new(2);
+        let peers = vec![random_peer_id(), random_peer_id(), random_peer_id()];
+        for peer_id in peers.iter() {
+            event_log.record(disconnected(peer_id));
+        }
+        let events: Vec<_> = event_log.events().into_iter().map(|record| record.event).collect();
+        assert_eq!(events, vec![disconnected(&peers[1]), disconnected(&peers[2])]);
+
+        let report = event_log.report();
+        assert!(report.starts_with("Last 2 network events:\n"));
+        assert_eq!(report.lines().count(), 3);
+        assert!(report.contains("HandshakeTimeout"));
+    }
+
+    #[test]
+    fn test_clones_share_events() {
+        let event_log = NetworkEventLog::new(10);
+        event_log.clone().record(NetworkEvent::RoutingTableChanged { reachable_peers: 3 });
+        assert_eq!(event_log.events().len(), 1);
+
+        let disabled = NetworkEventLog::new(0);
+        disabled.record(NetworkEvent::RoutingTableChanged { reachable_peers: 3 });
+        assert!(disabled.events().is_empty());
+    }
+}
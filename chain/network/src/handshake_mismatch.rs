@@ -0,0 +1,179 @@
+//! Detection of a node configured for another network.
+//!
+//! A node started with the genesis of another network, or with a binary too old to talk to its
+//! peers, fails every handshake. Instead of retrying forever, the mismatches reported by the peers
+//! rejecting us are collected, and once enough distinct peers rejected the node before it ever
+//! connected to any peer, the node stops with a report of the fields which differ.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use near_primitives::block::GenesisId;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::version::ProtocolVersion;
+
+use crate::types::PeerInfo;
+
+/// Field of the handshake which differs between this node and a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeMismatch {
+    ChainId {
+        ours: String,
+        theirs: String,
+    },
+    GenesisHash {
+        ours: CryptoHash,
+        theirs: CryptoHash,
+    },
+    /// Network protocol versions, each with the oldest version the node can still talk to.
+    ProtocolVersion {
+        ours: (ProtocolVersion, ProtocolVersion),
+        theirs: (ProtocolVersion, ProtocolVersion),
+    },
+}
+
+impl HandshakeMismatch {
+    /// Fields of the genesis ids which differ, empty if they are equal.
+    pub fn genesis_mismatches(ours: &GenesisId, theirs: &GenesisId) -> Vec<HandshakeMismatch> {
+        let mut mismatches = vec![];
+        if ours.chain_id != theirs.chain_id {
+            mismatches.push(HandshakeMismatch::ChainId {
+                ours: ours.chain_id.clone(),
+                theirs: theirs.chain_id.clone(),
+            });
+        }
+        if ours.hash != theirs.hash {
+            mismatches
+                .push(HandshakeMismatch::GenesisHash { ours: ours.hash, theirs: theirs.hash });
+        }
+        mismatches
+    }
+}
+
+impl fmt::Display for HandshakeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeMismatch::ChainId { ours, theirs } => {
+                write!(f, "chain id: ours `{}`, theirs `{}`", ours, theirs)
+            }
+            HandshakeMismatch::GenesisHash { ours, theirs } => {
+                write!(f, "genesis hash: ours {}, theirs {}", ours, theirs)
+            }
+            HandshakeMismatch::ProtocolVersion { ours, theirs } => write!(
+                f,
+                "protocol version: ours {} (oldest supported {}), theirs {} (oldest supported {})",
+                ours.0, ours.1, theirs.0, theirs.1
+            ),
+        }
+    }
+}
+
+/// Peers which rejected the handshake of this node.
+pub struct HandshakeMismatches {
+    peers: BTreeMap<PeerId, (PeerInfo, Vec<HandshakeMismatch>)>,
+    /// Number of distinct peers which have to reject the node for it to give up, 0 to never
+    /// give up.
+    threshold: usize,
+    /// Set once the node connected to a peer, after which mismatching peers are just skipped.
+    connected: bool,
+}
+
+impl HandshakeMismatches {
+    pub fn new(threshold: usize) -> Self {
+        Self { peers: BTreeMap::new(), threshold, connected: false }
+    }
+
+    /// Records the mismatches reported by a peer.
+    /// Returns true if the node has to give up joining the network.
+    pub fn record(&mut self, peer_info: PeerInfo, mismatches: Vec<HandshakeMismatch>) -> bool {
+        if self.connected || self.threshold == 0 || mismatches.is_empty() {
+            return false;
+        }
+        self.peers.insert(peer_info.id.clone(), (peer_info, mismatches));
+        self.peers.len() >= self.threshold
+    }
+
+    /// Called when a connection to a peer is established, which proves the node is on the
+    /// right network.
+    pub fn set_connected(&mut self) {
+        self.connected = true;
+        self.peers.clear();
+    }
+
+    /// Human readable report of the fields which differ, per peer.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "Unable to join the network: {} peers rejected the handshake.\n",
+            self.peers.len()
+        );
+        for (peer_info, mismatches) in self.peers.values() {
+            report.push_str(&format!("Peer {}:\n", peer_info));
+            for mismatch in mismatches {
+                report.push_str(&format!("    {}\n", mismatch));
+            }
+        }
+        report.push_str(
+            "Check that genesis.json is the one of the network to join \
+             (`neard init --download-genesis` fetches the verified one for mainnet and testnet) \
+             and that neard is up to date.",
+        );
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use crate::test_utils::random_peer_id;
+
+    use super::*;
+
+    fn genesis_id(chain_id: &str, data: &[u8]) -> GenesisId {
+        GenesisId { chain_id: chain_id.to_string(), hash: hash(data) }
+    }
+
+    fn peer_info() -> PeerInfo {
+        PeerInfo::new(random_peer_id(), "127.0.0.1:24567".parse().unwrap())
+    }
+
+    #[test]
+    fn test_genesis_mismatches() {
+        let ours = genesis_id("testnet", b"a");
+        assert_eq!(HandshakeMismatch::genesis_mismatches(&ours, &ours), vec![]);
+        assert_eq!(
+            HandshakeMismatch::genesis_mismatches(&ours, &genesis_id("testnet", b"b")),
+            vec![HandshakeMismatch::GenesisHash { ours: ours.hash, theirs: hash(b"b") }]
+        );
+        let mismatches = HandshakeMismatch::genesis_mismatches(&ours, &genesis_id("mainnet", b"a"));
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].to_string(), "chain id: ours `testnet`, theirs `mainnet`");
+    }
+
+    #[test]
+    fn test_give_up_after_threshold() {
+        let ours = genesis_id("testnet", b"a");
+        let mismatches = HandshakeMismatch::genesis_mismatches(&ours, &genesis_id("mainnet", b"b"));
+        let mut handshake_mismatches = HandshakeMismatches::new(2);
+        let peer = peer_info();
+        assert!(!handshake_mismatches.record(peer.clone(), mismatches.clone()));
+        // The same peer rejecting the node again doesn't count twice.
+        assert!(!handshake_mismatches.record(peer, mismatches.clone()));
+        assert!(handshake_mismatches.record(peer_info(), mismatches));
+        let report = handshake_mismatches.report();
+        assert!(report.starts_with("Unable to join the network: 2 peers rejected the handshake."));
+        assert!(report.contains("chain id: ours `testnet`, theirs `mainnet`"));
+    }
+
+    #[test]
+    fn test_never_give_up_once_connected() {
+        let ours = genesis_id("testnet", b"a");
+        let mismatches = HandshakeMismatch::genesis_mismatches(&ours, &genesis_id("testnet", b"b"));
+        let mut handshake_mismatches = HandshakeMismatches::new(1);
+        handshake_mismatches.set_connected();
+        assert!(!handshake_mismatches.record(peer_info(), mismatches.clone()));
+
+        let mut handshake_mismatches = HandshakeMismatches::new(0);
+        assert!(!handshake_mismatches.record(peer_info(), mismatches));
+    }
+}
@@ -8,16 +8,26 @@ pub use types::{
     NetworkRecipient, NetworkRequests, NetworkResponses, PeerInfo,
 };
 
+pub mod broadcast;
 mod cache;
 mod codec;
+pub mod event_log;
+pub mod handshake_mismatch;
 pub mod metrics;
+pub mod noise;
 mod peer;
 mod peer_manager;
 pub mod peer_store;
 mod rate_counter;
 #[cfg(feature = "metric_recorder")]
 pub mod recorder;
+#[cfg(feature = "protocol_feature_relay_peers")]
+pub mod relay;
+pub mod reputation;
 pub mod routing;
+pub mod send_queue;
+#[cfg(feature = "protocol_feature_stream_multiplexing")]
+mod stream;
 pub mod types;
 pub mod utils;
 
@@ -31,6 +31,16 @@ lazy_static! {
             "near_peer_transaction_received_total",
             "Number of transactions received by peers"
         );
+    pub static ref BROADCAST_MESSAGE_SERIALIZATIONS: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_broadcast_message_serializations_total",
+            "Number of broadcast messages serialized, once per message regardless of the number of peers"
+        );
+    pub static ref BROADCAST_MESSAGE_SERIALIZATIONS_REUSED: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_broadcast_message_serializations_reused_total",
+            "Number of times a peer sent a broadcast message serialized for another peer"
+        );
 
     // Routing table metrics
     pub static ref ROUTING_TABLE_RECALCULATIONS: near_metrics::Result<IntCounter> =
@@ -72,6 +82,16 @@ lazy_static! {
             "near_dropped_messages_count",
             "Total count of messages which were dropped, because write buffer was full"
         );
+    pub static ref PEER_MISBEHAVIOR_REPORTED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_peer_misbehavior_reported_total",
+            "Total number of misbehaviors of peers which lowered their reputation"
+        );
+    pub static ref PEER_LOW_REPUTATION_BANS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_peer_low_reputation_bans_total",
+            "Total number of peers banned because their reputation got too low"
+        );
 }
 
 #[derive(Clone)]
@@ -101,6 +121,18 @@ impl NetworkMetrics {
                 try_create_int_counter(counter_name.as_ref(), counter_name.as_ref()).ok(),
             );
 
+            let counter_name = NetworkMetrics::peer_message_total_tx(name.as_ref());
+            peer_messages.insert(
+                counter_name.clone(),
+                try_create_int_counter(counter_name.as_ref(), counter_name.as_ref()).ok(),
+            );
+
+            let counter_name = NetworkMetrics::peer_message_bytes_tx(name.as_ref());
+            peer_messages.insert(
+                counter_name.clone(),
+                try_create_int_counter(counter_name.as_ref(), counter_name.as_ref()).ok(),
+            );
+
             let counter_name = NetworkMetrics::peer_message_dropped(name.as_ref());
             peer_messages.insert(
                 counter_name.clone(),
@@ -119,6 +151,14 @@ impl NetworkMetrics {
         format!("near_{}_bytes", message_name.to_lowercase())
     }
 
+    pub fn peer_message_total_tx(message_name: &str) -> String {
+        format!("near_{}_sent_total", message_name.to_lowercase())
+    }
+
+    pub fn peer_message_bytes_tx(message_name: &str) -> String {
+        format!("near_{}_sent_bytes", message_name.to_lowercase())
+    }
+
     pub fn peer_message_dropped(message_name: &str) -> String {
         format!("near_{}_dropped", message_name.to_lowercase())
     }
@@ -0,0 +1,390 @@
+//! Encryption of the connections between peers with the Noise protocol.
+//!
+//! Before any framed message is sent, the initiator of an encrypted connection writes a preamble
+//! with the highest version of the encryption it supports, followed by a Noise IK handshake with
+//! the static keys derived from the ed25519 keys of the nodes. The handshake authenticates both
+//! sides, so the peer id claimed in the `Handshake` message is checked against the key the
+//! connection was established with. The first four bytes of the preamble read as a length above
+//! `NETWORK_MESSAGE_MAX_SIZE`, so they never start a valid plaintext frame and a node can tell
+//! the two kinds of connections apart.
+//!
+//! Nodes which don't know about encryption treat the preamble as an abusive frame, so encryption
+//! is rolled out in two steps: first every node runs with `ConnectionEncryption::Accept`, which
+//! accepts both kinds of connections but opens plaintext ones, and only then nodes switch to
+//! `ConnectionEncryption::Required`.
+use std::cmp::min;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use snow::{Builder, HandshakeState, StatelessTransportState};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use near_crypto::{PublicKey, SecretKey};
+use near_primitives::network::PeerId;
+
+const NOISE_PARAMS: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+/// First bytes of an encrypted connection.
+const MAGIC: [u8; 7] = [0xff, b'N', b'E', b'A', b'R', b'N', b'X'];
+/// Latest version of the encryption.
+const NOISE_VERSION: u8 = 1;
+/// Oldest version of the encryption we can talk with.
+const OLDEST_NOISE_VERSION: u8 = 1;
+/// Maximum size of a Noise message.
+const MAX_MESSAGE_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+/// Maximum size of the plaintext encrypted into a single Noise message.
+const MAX_CHUNK_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+/// How long to wait between checks of an incomplete preamble.
+const PREAMBLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionEncryption {
+    /// Only plaintext connections, as nodes without encryption support.
+    Disabled,
+    /// Accept both encrypted and plaintext inbound connections, open plaintext ones.
+    Accept,
+    /// Open encrypted connections and drop plaintext inbound ones.
+    Required,
+}
+
+impl Default for ConnectionEncryption {
+    fn default() -> Self {
+        ConnectionEncryption::Accept
+    }
+}
+
+/// Keys of an established encrypted connection.
+pub struct NoiseTransport {
+    state: StatelessTransportState,
+    remote_static_key: [u8; 32],
+}
+
+impl NoiseTransport {
+    fn new(handshake: HandshakeState) -> Result<Self, Error> {
+        let mut remote_static_key = [0; 32];
+        remote_static_key.copy_from_slice(
+            handshake
+                .get_remote_static()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Missing remote static key"))?,
+        );
+        let state = handshake.into_stateless_transport_mode().map_err(noise_error)?;
+        Ok(NoiseTransport { state, remote_static_key })
+    }
+
+    /// Whether the other side of the connection holds the key of the peer.
+    pub fn is_authenticated_as(&self, peer_id: &PeerId) -> bool {
+        static_public_key(peer_id).map_or(false, |key| key == self.remote_static_key)
+    }
+}
+
+/// Encrypts or decrypts the frames sent in one direction of a connection.
+pub(crate) struct FrameCipher {
+    transport: Arc<NoiseTransport>,
+    nonce: u64,
+}
+
+impl FrameCipher {
+    pub fn new(transport: Arc<NoiseTransport>) -> Self {
+        FrameCipher { transport, nonce: 0 }
+    }
+
+    /// Size of the encrypted frame of a plaintext of the given size.
+    pub fn encrypted_len(len: usize) -> usize {
+        let chunks = std::cmp::max(1, (len + MAX_CHUNK_LEN - 1) / MAX_CHUNK_LEN);
+        len + chunks * TAG_LEN
+    }
+
+    /// Encrypts the plaintext into `buf`, split into chunks which fit into a Noise message.
+    pub fn encrypt(&mut self, plaintext: &[u8], buf: &mut BytesMut) -> Result<(), Error> {
+        let mut start = 0;
+        loop {
+            let chunk = &plaintext[start..min(plaintext.len(), start + MAX_CHUNK_LEN)];
+            let offset = buf.len();
+            buf.resize(offset + chunk.len() + TAG_LEN, 0);
+            self.transport
+                .state
+                .write_message(self.nonce, chunk, &mut buf[offset..])
+                .map_err(noise_error)?;
+            self.nonce += 1;
+            start += chunk.len();
+            if start >= plaintext.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn decrypt(&mut self, frame: &[u8]) -> Result<Vec<u8>, Error> {
+        if frame.len() < TAG_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "Encrypted frame is too short"));
+        }
+        let mut plaintext = vec![0; frame.len()];
+        let mut len = 0;
+        for chunk in frame.chunks(MAX_MESSAGE_LEN) {
+            len += self
+                .transport
+                .state
+                .read_message(self.nonce, chunk, &mut plaintext[len..])
+                .map_err(noise_error)?;
+            self.nonce += 1;
+        }
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+}
+
+fn noise_error(err: snow::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, err.to_string())
+}
+
+/// Noise private key of the node, derived from its ed25519 key the same way as the ed25519 scalar.
+fn static_private_key(secret_key: &SecretKey) -> Result<[u8; 32], Error> {
+    match secret_key {
+        SecretKey::ED25519(secret_key) => {
+            let hash = Sha512::digest(&secret_key.0[..32]);
+            let mut key = [0; 32];
+            key.copy_from_slice(&hash[..32]);
+            Ok(key)
+        }
+        _ => Err(Error::new(ErrorKind::InvalidInput, "Encryption requires an ED25519 node key")),
+    }
+}
+
+/// Noise public key of the peer, the Montgomery form of its ed25519 key.
+fn static_public_key(peer_id: &PeerId) -> Result<[u8; 32], Error> {
+    match peer_id.public_key() {
+        PublicKey::ED25519(public_key) => CompressedEdwardsY(public_key.0)
+            .decompress()
+            .map(|point| point.to_montgomery().to_bytes())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid ED25519 peer key")),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "Encryption requires an ED25519 peer key")),
+    }
+}
+
+async fn write_handshake_message(stream: &mut TcpStream, message: &[u8]) -> Result<(), Error> {
+    stream.write_all(&(message.len() as u16).to_le_bytes()).await?;
+    stream.write_all(message).await
+}
+
+async fn read_handshake_message(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).await?;
+    let mut message = vec![0; u16::from_le_bytes(len) as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// Opens an encrypted connection to the peer.
+pub async fn initiate(
+    mut stream: TcpStream,
+    secret_key: SecretKey,
+    peer_id: PeerId,
+) -> Result<(TcpStream, NoiseTransport), Error> {
+    let mut handshake = Builder::new(NOISE_PARAMS.parse().unwrap())
+        .local_private_key(&static_private_key(&secret_key)?)
+        .remote_public_key(&static_public_key(&peer_id)?)
+        .build_initiator()
+        .map_err(noise_error)?;
+    stream.write_all(&MAGIC).await?;
+    stream.write_all(&[NOISE_VERSION]).await?;
+
+    let mut message = vec![0; MAX_MESSAGE_LEN];
+    let len = handshake.write_message(&[], &mut message).map_err(noise_error)?;
+    write_handshake_message(&mut stream, &message[..len]).await?;
+
+    // The response carries the version chosen by the responder.
+    let response = read_handshake_message(&mut stream).await?;
+    let len = handshake.read_message(&response, &mut message).map_err(noise_error)?;
+    match message[..len] {
+        [version] if OLDEST_NOISE_VERSION <= version && version <= NOISE_VERSION => {}
+        _ => return Err(Error::new(ErrorKind::InvalidData, "Unsupported encryption version")),
+    }
+    Ok((stream, NoiseTransport::new(handshake)?))
+}
+
+/// Completes the handshake of an inbound connection which starts with the preamble.
+async fn respond(
+    mut stream: TcpStream,
+    secret_key: SecretKey,
+) -> Result<(TcpStream, NoiseTransport), Error> {
+    let mut preamble = [0; MAGIC.len() + 1];
+    stream.read_exact(&mut preamble).await?;
+    let version = min(preamble[MAGIC.len()], NOISE_VERSION);
+    if version < OLDEST_NOISE_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, "Unsupported encryption version"));
+    }
+    let mut handshake = Builder::new(NOISE_PARAMS.parse().unwrap())
+        .local_private_key(&static_private_key(&secret_key)?)
+        .build_responder()
+        .map_err(noise_error)?;
+
+    let request = read_handshake_message(&mut stream).await?;
+    let mut message = vec![0; MAX_MESSAGE_LEN];
+    handshake.read_message(&request, &mut message).map_err(noise_error)?;
+    let len = handshake.write_message(&[version], &mut message).map_err(noise_error)?;
+    write_handshake_message(&mut stream, &message[..len]).await?;
+    Ok((stream, NoiseTransport::new(handshake)?))
+}
+
+/// Whether the inbound connection starts with the preamble of an encrypted connection.
+async fn starts_with_preamble(stream: &TcpStream) -> Result<bool, Error> {
+    let mut buf = [0; MAGIC.len()];
+    loop {
+        let len = stream.peek(&mut buf).await?;
+        if len == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed"));
+        }
+        if buf[..len] != MAGIC[..len] {
+            return Ok(false);
+        }
+        if len == MAGIC.len() {
+            return Ok(true);
+        }
+        // `peek` returns right away while the data it already saw is not consumed.
+        tokio::time::sleep(PREAMBLE_POLL_INTERVAL).await;
+    }
+}
+
+/// Sets up an inbound connection according to the encryption mode. Returns the keys of the
+/// connection if it's encrypted, and an error if it has to be dropped.
+pub async fn accept(
+    stream: TcpStream,
+    secret_key: SecretKey,
+    encryption: ConnectionEncryption,
+) -> Result<(TcpStream, Option<NoiseTransport>), Error> {
+    if encryption == ConnectionEncryption::Disabled {
+        return Ok((stream, None));
+    }
+    if starts_with_preamble(&stream).await? {
+        let (stream, transport) = respond(stream, secret_key).await?;
+        Ok((stream, Some(transport)))
+    } else if encryption == ConnectionEncryption::Required {
+        Err(Error::new(ErrorKind::PermissionDenied, "Plaintext connection"))
+    } else {
+        Ok((stream, None))
+    }
+}
+
+/// Keys of both sides of a connection, established in memory.
+#[cfg(test)]
+pub(crate) fn transport_pair() -> (NoiseTransport, NoiseTransport) {
+    let initiator_key = SecretKey::from_seed(near_crypto::KeyType::ED25519, "initiator");
+    let responder_key = SecretKey::from_seed(near_crypto::KeyType::ED25519, "responder");
+    let mut initiator = Builder::new(NOISE_PARAMS.parse().unwrap())
+        .local_private_key(&static_private_key(&initiator_key).unwrap())
+        .remote_public_key(&static_public_key(&PeerId::new(responder_key.public_key())).unwrap())
+        .build_initiator()
+        .unwrap();
+    let mut responder = Builder::new(NOISE_PARAMS.parse().unwrap())
+        .local_private_key(&static_private_key(&responder_key).unwrap())
+        .build_responder()
+        .unwrap();
+    let (mut message, mut payload) = (vec![0; MAX_MESSAGE_LEN], vec![0; MAX_MESSAGE_LEN]);
+    let len = initiator.write_message(&[], &mut message).unwrap();
+    responder.read_message(&message[..len], &mut payload).unwrap();
+    let len = responder.write_message(&[], &mut message).unwrap();
+    initiator.read_message(&message[..len], &mut payload).unwrap();
+    (NoiseTransport::new(initiator).unwrap(), NoiseTransport::new(responder).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::KeyType;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn node_key(seed: &str) -> (SecretKey, PeerId) {
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, seed);
+        let peer_id = PeerId::new(secret_key.public_key());
+        (secret_key, peer_id)
+    }
+
+    async fn connect(
+        initiator_key: SecretKey,
+        target: PeerId,
+        responder_key: SecretKey,
+        encryption: ConnectionEncryption,
+        encrypt: bool,
+    ) -> (Result<NoiseTransport, Error>, Result<Option<NoiseTransport>, Error>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responder = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            accept(stream, responder_key, encryption).await.map(|(_, transport)| transport)
+        });
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let initiator = if encrypt {
+            initiate(stream, initiator_key, target).await.map(|(_, transport)| transport)
+        } else {
+            stream.write_all(&[1, 0, 0, 0, 0]).await.unwrap();
+            Err(Error::new(ErrorKind::Other, "Plaintext"))
+        };
+        (initiator, responder.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_connection() {
+        let (initiator_key, initiator_id) = node_key("initiator");
+        let (responder_key, responder_id) = node_key("responder");
+        let (initiator, responder) = connect(
+            initiator_key,
+            responder_id.clone(),
+            responder_key,
+            ConnectionEncryption::Accept,
+            true,
+        )
+        .await;
+        let initiator = Arc::new(initiator.unwrap());
+        let responder = Arc::new(responder.unwrap().unwrap());
+        assert!(initiator.is_authenticated_as(&responder_id));
+        assert!(responder.is_authenticated_as(&initiator_id));
+        assert!(!responder.is_authenticated_as(&responder_id));
+
+        let mut buf = BytesMut::new();
+        FrameCipher::new(initiator).encrypt(b"hello", &mut buf).unwrap();
+        assert_eq!(buf.len(), FrameCipher::encrypted_len(5));
+        assert_eq!(FrameCipher::new(responder).decrypt(&buf).unwrap(), b"hello".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_peer_key() {
+        let (initiator_key, _) = node_key("initiator");
+        let (responder_key, _) = node_key("responder");
+        let (_, other_id) = node_key("other");
+        let (initiator, responder) =
+            connect(initiator_key, other_id, responder_key, ConnectionEncryption::Required, true)
+                .await;
+        assert!(initiator.is_err());
+        assert!(responder.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_connection() {
+        let (initiator_key, _) = node_key("initiator");
+        let (responder_key, responder_id) = node_key("responder");
+        let (_, responder) = connect(
+            initiator_key.clone(),
+            responder_id.clone(),
+            responder_key.clone(),
+            ConnectionEncryption::Accept,
+            false,
+        )
+        .await;
+        assert!(responder.unwrap().is_none());
+        let (_, responder) = connect(
+            initiator_key,
+            responder_id,
+            responder_key,
+            ConnectionEncryption::Required,
+            false,
+        )
+        .await;
+        assert!(responder.is_err());
+    }
+}
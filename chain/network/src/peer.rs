@@ -17,6 +17,8 @@ use tracing::{debug, error, info, trace, warn};
 use near_metrics;
 use near_performance_metrics;
 use near_primitives::block::GenesisId;
+#[cfg(feature = "protocol_feature_stream_multiplexing")]
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::logging;
 use near_primitives::network::PeerId;
@@ -27,18 +29,25 @@ use near_primitives::version::{
 };
 
 use crate::codec::{self, bytes_to_peer_message, peer_message_to_bytes, Codec};
+use crate::event_log::DisconnectReason;
+use crate::handshake_mismatch::HandshakeMismatch;
+use crate::noise::NoiseTransport;
 use crate::rate_counter::RateCounter;
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::{PeerMessageMetadata, Status};
+use crate::reputation::PeerMisbehavior;
 use crate::routing::{Edge, EdgeInfo};
+use crate::send_queue::{MessageClass, QueuedMessage, SendQueueConfig, SendQueues};
+#[cfg(feature = "protocol_feature_stream_multiplexing")]
+use crate::stream::{IncomingStreams, OutgoingStreams, StreamFrame, STREAM_THRESHOLD_SIZE};
 use crate::types::{
-    Ban, Consolidate, ConsolidateResponse, Handshake, HandshakeFailureReason, HandshakeV2,
-    NetworkClientMessages, NetworkClientResponses, NetworkRequests, NetworkViewClientMessages,
-    NetworkViewClientResponses, PeerChainInfo, PeerChainInfoV2, PeerInfo, PeerManagerRequest,
-    PeerMessage, PeerRequest, PeerResponse, PeerStatsResult, PeerStatus, PeerType, PeersRequest,
-    PeersResponse, QueryPeerStats, ReasonForBan, RoutedMessage, RoutedMessageBody,
-    RoutedMessageFrom, SendMessage, StateResponseInfo, Unregister,
-    UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE,
+    Ban, BroadcastMessage, ConnectionAddrs, Consolidate, ConsolidateResponse, Handshake,
+    HandshakeFailureReason, HandshakeV2, NetworkClientMessages, NetworkClientResponses,
+    NetworkRequests, NetworkViewClientMessages, NetworkViewClientResponses, PeerChainInfo,
+    PeerChainInfoV2, PeerInfo, PeerManagerRequest, PeerMessage, PeerRequest, PeerResponse,
+    PeerStatsResult, PeerStatus, PeerTrafficStats, PeerType, PeersRequest, PeersResponse,
+    QueryPeerStats, ReasonForBan, ReportPeer, RoutedMessage, RoutedMessageBody, RoutedMessageFrom,
+    SendMessage, StateResponseInfo, Unregister, UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE,
 };
 use crate::PeerManagerActor;
 use crate::{metrics, NetworkResponses};
@@ -62,6 +71,11 @@ const MAX_PEER_MSG_PER_MIN: u64 = std::u64::MAX;
 /// dispatching transactions when we should be focusing on consensus-related messages.
 const MAX_TXNS_PER_BLOCK_MESSAGE: usize = 1000;
 
+/// Queued messages are released to the connection while its write buffer is smaller than this.
+const SEND_BUFFER_TARGET_SIZE: usize = 64 * 1024;
+/// How often the queued messages are released if the connection is slow or the budget is over.
+const SEND_QUEUE_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
 /// The time we wait for the response to a Epoch Sync request before retrying
 // TODO #3488 set 30_000
 pub const EPOCH_SYNC_REQUEST_TIMEOUT_MS: u64 = 1_000;
@@ -113,6 +127,8 @@ pub struct Tracker {
     requested: CircularUniqueQueue,
     /// Received elements.
     received: CircularUniqueQueue,
+    /// Messages sent and received per message type.
+    traffic: PeerTrafficStats,
 }
 
 impl Default for Tracker {
@@ -122,6 +138,7 @@ impl Default for Tracker {
             received_bytes: RateCounter::new(),
             requested: CircularUniqueQueue::new(MAX_TRACK_SIZE),
             received: CircularUniqueQueue::new(MAX_TRACK_SIZE),
+            traffic: Default::default(),
         }
     }
 }
@@ -157,16 +174,24 @@ pub struct Peer {
     pub node_info: PeerInfo,
     /// Peer address from connection.
     pub peer_addr: SocketAddr,
+    /// Local address of the connection.
+    local_addr: SocketAddr,
     /// Peer id and info. Present if outbound or ready.
     pub peer_info: DisplayOption<PeerInfo>,
     /// Peer type.
     pub peer_type: PeerType,
     /// Peer status.
     pub peer_status: PeerStatus,
+    /// Why the connection is closed, reported to the peer manager when the actor stops.
+    disconnect_reason: DisconnectReason,
     /// Protocol version to communicate with this peer.
     pub protocol_version: ProtocolVersion,
     /// Framed wrapper to send messages through the TCP connection.
-    framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+    framed: FramedWrite<Arc<[u8]>, WriteHalf, Codec, Codec>,
+    /// Keys of the connection if it's encrypted, authenticating the peer.
+    transport: Option<Arc<NoiseTransport>>,
+    /// Messages waiting for room in the write buffer of `framed`.
+    send_queue: SendQueues,
     /// Handshake timeout.
     handshake_timeout: Duration,
     /// Peer manager recipient to break the dependency loop.
@@ -193,15 +218,23 @@ pub struct Peer {
     peer_counter: Arc<AtomicUsize>,
     /// The last time a Epoch Sync request was received from this peer
     last_time_received_epoch_sync_request: Instant,
+    /// Streams sending large messages to this peer.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    outgoing_streams: OutgoingStreams,
+    /// Streams receiving large messages from this peer.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    incoming_streams: IncomingStreams,
 }
 
 impl Peer {
     pub fn new(
         node_info: PeerInfo,
         peer_addr: SocketAddr,
+        local_addr: SocketAddr,
         peer_info: Option<PeerInfo>,
         peer_type: PeerType,
-        framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+        framed: FramedWrite<Arc<[u8]>, WriteHalf, Codec, Codec>,
+        transport: Option<Arc<NoiseTransport>>,
         handshake_timeout: Duration,
         peer_manager_addr: Addr<PeerManagerActor>,
         client_addr: Recipient<NetworkClientMessages>,
@@ -210,15 +243,20 @@ impl Peer {
         network_metrics: NetworkMetrics,
         txns_since_last_block: Arc<AtomicUsize>,
         peer_counter: Arc<AtomicUsize>,
+        send_queue_config: &SendQueueConfig,
     ) -> Self {
         Peer {
             node_info,
             peer_addr,
+            local_addr,
             peer_info: peer_info.into(),
             peer_type,
             peer_status: PeerStatus::Connecting,
+            disconnect_reason: DisconnectReason::ConnectionClosed,
             protocol_version: PROTOCOL_VERSION,
             framed,
+            transport,
+            send_queue: SendQueues::new(send_queue_config, Instant::now()),
             handshake_timeout,
             peer_manager_addr,
             client_addr,
@@ -233,6 +271,10 @@ impl Peer {
             peer_counter,
             last_time_received_epoch_sync_request: Instant::now()
                 - Duration::from_millis(EPOCH_SYNC_PEER_TIMEOUT_MS),
+            #[cfg(feature = "protocol_feature_stream_multiplexing")]
+            outgoing_streams: Default::default(),
+            #[cfg(feature = "protocol_feature_stream_multiplexing")]
+            incoming_streams: Default::default(),
         }
     }
 
@@ -246,6 +288,16 @@ impl Peer {
     }
 
     fn send_message(&mut self, msg: &PeerMessage) {
+        self.send_serialized_message(msg, || peer_message_to_bytes(msg).map(Arc::from))
+    }
+
+    /// Queues the message with the bytes returned by `serialize`, which is only called if the
+    /// message is actually sent to the peer.
+    fn send_serialized_message(
+        &mut self,
+        msg: &PeerMessage,
+        serialize: impl FnOnce() -> io::Result<Arc<[u8]>>,
+    ) {
         // Skip sending block and headers if we received it or header from this peer.
         // Record block requests in tracker.
         match msg {
@@ -263,25 +315,122 @@ impl Peer {
             metadata
         };
 
-        match peer_message_to_bytes(msg) {
+        match serialize() {
             Ok(bytes) => {
                 #[cfg(feature = "metric_recorder")]
                 self.peer_manager_addr.do_send(metadata.set_size(bytes.len()));
                 self.tracker.increment_sent(bytes.len() as u64);
-                let bytes_len = bytes.len();
-                if !self.framed.write(bytes) {
-                    error!(
-                        "{} Failed to send message {} of size {}",
-                        get_tid(),
-                        strum::AsStaticRef::as_static(msg),
-                        bytes_len,
-                    )
+                let message = QueuedMessage { name: msg.msg_variant(), bytes };
+                for dropped in self.send_queue.push(MessageClass::of(msg), message) {
+                    debug!(target: "network", "Dropping message {} of size {} to {}, send queue is full", dropped.name, dropped.bytes.len(), self.peer_info);
+                    self.network_metrics
+                        .inc(NetworkMetrics::peer_message_dropped(dropped.name).as_str());
                 }
+                self.flush_send_queue();
             }
             Err(err) => error!(target: "network", "Error converting message to bytes: {}", err),
         };
     }
 
+    /// Moves queued messages to the write buffer of the connection as long as it has room.
+    fn flush_send_queue(&mut self) {
+        let now = Instant::now();
+        while self.framed.buffer_len() < SEND_BUFFER_TARGET_SIZE {
+            match self.send_queue.pop(now) {
+                Some(message) => self.write_message(message),
+                None => break,
+            }
+        }
+    }
+
+    /// Releases the queued messages which didn't fit into the write buffer when they were sent.
+    /// Disconnects from the peer if it doesn't read fast enough for the queues to keep up.
+    fn periodically_flush_send_queue(&mut self, ctx: &mut Context<Peer>) {
+        if self.send_queue.is_overflowed() {
+            warn!(target: "network", "Disconnecting from {}, the send queue is full", self.peer_info);
+            self.disconnect(ctx, DisconnectReason::SendQueueFull);
+            return;
+        }
+        if !self.send_queue.is_empty() {
+            self.flush_send_queue();
+        }
+        near_performance_metrics::actix::run_later(
+            ctx,
+            file!(),
+            line!(),
+            SEND_QUEUE_FLUSH_INTERVAL,
+            move |act, ctx| act.periodically_flush_send_queue(ctx),
+        );
+    }
+
+    fn write_message(&mut self, message: QueuedMessage) {
+        #[cfg(feature = "protocol_feature_stream_multiplexing")]
+        {
+            if message.bytes.len() > STREAM_THRESHOLD_SIZE && self.streams_enabled() {
+                self.outgoing_streams.open(message.bytes);
+                self.send_stream_frames();
+                return;
+            }
+        }
+        let bytes_len = message.bytes.len();
+        if !self.framed.write(message.bytes) {
+            error!("{} Failed to send message {} of size {}", get_tid(), message.name, bytes_len);
+            return;
+        }
+        self.tracker.traffic.record_sent(message.name, bytes_len);
+        self.network_metrics.inc(NetworkMetrics::peer_message_total_tx(message.name).as_ref());
+        self.network_metrics
+            .inc_by(NetworkMetrics::peer_message_bytes_tx(message.name).as_ref(), bytes_len as u64);
+    }
+
+    /// Whether large messages to this peer are split into streams.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    fn streams_enabled(&self) -> bool {
+        self.peer_status == PeerStatus::Ready
+            && checked_feature!(
+                "protocol_feature_stream_multiplexing",
+                StreamMultiplexing,
+                self.protocol_version
+            )
+    }
+
+    /// Sends frames of the outgoing streams as long as their windows allow.
+    /// Frames bypass the send queue: the message they belong to was already queued, and the
+    /// windows limit how much of it is in flight.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    fn send_stream_frames(&mut self) {
+        while let Some(frame) = self.outgoing_streams.next_frame() {
+            let msg = PeerMessage::StreamFrame(frame);
+            match peer_message_to_bytes(&msg) {
+                Ok(bytes) => {
+                    self.tracker.increment_sent(bytes.len() as u64);
+                    self.write_message(QueuedMessage {
+                        name: msg.msg_variant(),
+                        bytes: bytes.into(),
+                    });
+                }
+                Err(err) => error!(target: "network", "Error converting message to bytes: {}", err),
+            }
+        }
+    }
+
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    fn receive_stream_frame(&mut self, ctx: &mut Context<Peer>, frame: StreamFrame) {
+        let stream_id = frame.stream_id;
+        match self.incoming_streams.receive(frame) {
+            Ok(received) => {
+                if let Some(increment) = received.window_update {
+                    self.send_message(&PeerMessage::StreamWindowUpdate(stream_id, increment));
+                }
+                if let Some(message) = received.message {
+                    // Process the reassembled message as if it was received in a single piece.
+                    StreamHandler::handle(self, Ok(message), ctx);
+                }
+            }
+            Err(ban_reason) => self.ban_peer(ctx, ban_reason),
+        }
+    }
+
     fn fetch_client_chain_info(&mut self, ctx: &mut Context<Peer>) {
         ctx.wait(
             self.view_client_addr
@@ -352,6 +501,11 @@ impl Peer {
             .spawn(ctx);
     }
 
+    fn disconnect(&mut self, ctx: &mut Context<Peer>, reason: DisconnectReason) {
+        self.disconnect_reason = reason;
+        ctx.stop();
+    }
+
     fn ban_peer(&mut self, ctx: &mut Context<Peer>, ban_reason: ReasonForBan) {
         warn!(target: "network", "Banning peer {} for {:?}", self.peer_info, ban_reason);
         self.peer_status = PeerStatus::Banned(ban_reason);
@@ -558,6 +712,11 @@ impl Peer {
                         error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
                         return;
                     }
+                    #[cfg(feature = "protocol_feature_relay_peers")]
+                    RoutedMessageBody::RelayConnect(_) => {
+                        error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
+                        return;
+                    }
                 }
             }
             PeerMessage::Challenge(challenge) => NetworkClientMessages::Challenge(challenge),
@@ -584,6 +743,16 @@ impl Peer {
                 error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
                 return;
             }
+            #[cfg(feature = "protocol_feature_stream_multiplexing")]
+            PeerMessage::StreamFrame(_) | PeerMessage::StreamWindowUpdate(_, _) => {
+                error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
+                return;
+            }
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            PeerMessage::RelayRegister => {
+                error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
+                return;
+            }
         };
 
         self.client_addr
@@ -649,7 +818,7 @@ impl Actor for Peer {
             move |act, ctx| {
                 if act.peer_status != PeerStatus::Ready {
                     info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
-                    ctx.stop();
+                    act.disconnect(ctx, DisconnectReason::HandshakeTimeout);
                 }
             },
         );
@@ -658,9 +827,16 @@ impl Actor for Peer {
         if self.peer_type == PeerType::Outbound {
             self.send_handshake(ctx);
         }
+
+        self.periodically_flush_send_queue(ctx);
     }
 
-    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        // The write buffer is flushed when the connection is dropped, so the queued messages,
+        // e.g. a final `Disconnect`, are moved there regardless of the budgets.
+        for message in self.send_queue.drain() {
+            self.write_message(message);
+        }
         self.peer_counter.fetch_sub(1, Ordering::SeqCst);
         near_metrics::dec_gauge(&metrics::PEER_CONNECTIONS_TOTAL);
         debug!(target: "network", "{:?}: Peer {} disconnected. {:?}", self.node_info.id, self.peer_info, self.peer_status);
@@ -669,6 +845,7 @@ impl Actor for Peer {
                 self.peer_manager_addr.do_send(Ban { peer_id: peer_info.id.clone(), ban_reason });
             } else {
                 self.peer_manager_addr.do_send(Unregister {
+                    actor: ctx.address(),
                     peer_id: peer_info.id.clone(),
                     peer_type: self.peer_type,
                     // If the PeerActor is no longer in the Connecting state this means
@@ -678,6 +855,7 @@ impl Actor for Peer {
                     // each other, and after resolving the tie, a peer tries to remove the other
                     // peer from the active connection if it was added in the parallel connection.
                     remove_from_peer_store: self.peer_status != PeerStatus::Connecting,
+                    reason: self.disconnect_reason,
                 })
             }
         }
@@ -741,6 +919,12 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                     ));
                 } else {
                     info!(target: "network", "Received invalid data {:?} from {}: {}", logging::pretty_vec(&msg), self.peer_info, err);
+                    if let Some(peer_id) = self.peer_id() {
+                        self.peer_manager_addr.do_send(ReportPeer {
+                            peer_id,
+                            misbehavior: PeerMisbehavior::InvalidMessage,
+                        });
+                    }
                 }
                 return;
             }
@@ -778,16 +962,35 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
             NetworkMetrics::peer_message_bytes_rx(&peer_msg.msg_variant()).as_ref(),
             msg.len() as u64,
         );
+        self.tracker.traffic.record_received(peer_msg.msg_variant(), msg.len());
 
         if let PeerMessage::HandshakeV2(handshake) = peer_msg {
             peer_msg = PeerMessage::Handshake(handshake.into());
         }
 
+        #[cfg(feature = "protocol_feature_stream_multiplexing")]
+        let peer_msg = match (self.peer_status, peer_msg) {
+            (PeerStatus::Ready, PeerMessage::StreamFrame(frame)) => {
+                self.receive_stream_frame(ctx, frame);
+                return;
+            }
+            (PeerStatus::Ready, PeerMessage::StreamWindowUpdate(stream_id, increment)) => {
+                self.outgoing_streams.update_window(stream_id, increment);
+                self.send_stream_frames();
+                return;
+            }
+            (_, peer_msg) => peer_msg,
+        };
+
         match (self.peer_type, self.peer_status, peer_msg) {
             (_, _, PeerMessage::HandshakeFailure(peer_info, reason)) => {
                 match reason {
                     HandshakeFailureReason::GenesisMismatch(genesis) => {
                         warn!(target: "network", "Attempting to connect to a node ({}) with a different genesis block. Our genesis: {:?}, their genesis: {:?}", peer_info, self.genesis_id, genesis);
+                        self.peer_manager_addr.do_send(PeerRequest::HandshakeMismatch(
+                            peer_info,
+                            HandshakeMismatch::genesis_mismatches(&self.genesis_id, &genesis),
+                        ));
                     }
                     HandshakeFailureReason::ProtocolVersionMismatch {
                         version,
@@ -807,6 +1010,16 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                             return;
                         } else {
                             warn!(target: "network", "Unable to connect to a node ({}) due to a network protocol version mismatch. Our version: {:?}, their: {:?}", peer_info, (PROTOCOL_VERSION, OLDEST_BACKWARD_COMPATIBLE_PROTOCOL_VERSION), (version, oldest_supported_version));
+                            self.peer_manager_addr.do_send(PeerRequest::HandshakeMismatch(
+                                peer_info,
+                                vec![HandshakeMismatch::ProtocolVersion {
+                                    ours: (
+                                        PROTOCOL_VERSION,
+                                        OLDEST_BACKWARD_COMPATIBLE_PROTOCOL_VERSION,
+                                    ),
+                                    theirs: (version, oldest_supported_version),
+                                }],
+                            ));
                         }
                     }
                     HandshakeFailureReason::InvalidTarget => {
@@ -814,7 +1027,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                         self.peer_manager_addr.do_send(PeerRequest::UpdatePeerInfo(peer_info));
                     }
                 }
-                ctx.stop();
+                self.disconnect(ctx, DisconnectReason::HandshakeRejected);
             }
             (_, PeerStatus::Connecting, PeerMessage::Handshake(handshake)) => {
                 debug!(target: "network", "{:?}: Received handshake {:?}", self.node_info.id, handshake);
@@ -842,10 +1055,19 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                 if handshake.peer_id == self.node_info.id {
                     near_metrics::inc_counter(&metrics::RECEIVED_INFO_ABOUT_ITSELF);
                     debug!(target: "network", "Received info about itself. Disconnecting this peer.");
-                    ctx.stop();
+                    self.disconnect(ctx, DisconnectReason::InvalidHandshake);
                     return;
                 }
 
+                // The peer of an encrypted connection has to hold the key it claims.
+                if let Some(transport) = &self.transport {
+                    if !transport.is_authenticated_as(&handshake.peer_id) {
+                        warn!(target: "network", "Received handshake from {} over a connection encrypted with another key. Disconnecting peer.", handshake.peer_id);
+                        self.disconnect(ctx, DisconnectReason::InvalidHandshake);
+                        return;
+                    }
+                }
+
                 if handshake.target_peer_id != self.node_info.id {
                     debug!(target: "network", "Received handshake from {:?} to {:?} but I am {:?}", handshake.peer_id, handshake.target_peer_id, self.node_info.id);
                     self.send_message(&PeerMessage::HandshakeFailure(
@@ -873,7 +1095,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                         != self.edge_info.as_ref().map(|edge_info| edge_info.nonce).unwrap()
                     {
                         warn!(target: "network", "Received invalid nonce on handshake. Disconnecting peer {}", handshake.peer_id);
-                        ctx.stop();
+                        self.disconnect(ctx, DisconnectReason::InvalidHandshake);
                         return;
                     }
                 }
@@ -889,9 +1111,14 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                 self.peer_manager_addr
                     .send(Consolidate {
                         actor: ctx.address(),
+                        connection: ConnectionAddrs {
+                            local: self.local_addr,
+                            remote: self.peer_addr,
+                        },
                         peer_info: peer_info.clone(),
                         peer_type: self.peer_type,
                         chain_info: handshake.chain_info.clone(),
+                        protocol_version: self.protocol_version,
                         this_edge_info: self.edge_info.clone(),
                         other_edge_info: handshake.edge_info.clone(),
                     })
@@ -915,7 +1142,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                             }
                             _ => {
                                 info!(target: "network", "{:?}: Peer with handshake {:?} wasn't consolidated, disconnecting.", act.node_id(), handshake);
-                                ctx.stop();
+                                act.disconnect(ctx, DisconnectReason::NotConsolidated);
                                 actix::fut::ready(())
                             }
                         }
@@ -926,14 +1153,14 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                 // This message will be received only if we started the connection.
                 if self.peer_type == PeerType::Inbound {
                     info!(target: "network", "{:?}: Inbound peer {:?} sent invalid message. Disconnect.", self.node_id(), self.peer_addr);
-                    ctx.stop();
+                    self.disconnect(ctx, DisconnectReason::InvalidHandshake);
                     return ();
                 }
 
                 // Disconnect if neighbor propose invalid edge.
                 if !edge.verify() {
                     info!(target: "network", "{:?}: Peer {:?} sent invalid edge. Disconnect.", self.node_id(), self.peer_addr);
-                    ctx.stop();
+                    self.disconnect(ctx, DisconnectReason::InvalidHandshake);
                     return ();
                 }
 
@@ -954,7 +1181,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
             }
             (_, PeerStatus::Ready, PeerMessage::Disconnect) => {
                 debug!(target: "network", "Disconnect signal. Me: {:?} Peer: {:?}", self.node_info.id, self.peer_id());
-                ctx.stop();
+                self.disconnect(ctx, DisconnectReason::PeerRequested);
             }
             (_, PeerStatus::Ready, PeerMessage::Handshake(_)) => {
                 // Received handshake after already have seen handshake from this peer.
@@ -1006,6 +1233,14 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                     actix::fut::ready(())
                 })
                 .spawn(ctx),
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            (_, PeerStatus::Ready, PeerMessage::RelayRegister) => {
+                // Only peers which connected to us need a relay.
+                if self.peer_type == PeerType::Inbound {
+                    self.peer_manager_addr
+                        .do_send(PeerRequest::RelayRegister(self.peer_id().unwrap()));
+                }
+            }
             (_, PeerStatus::Ready, PeerMessage::RoutingTableSync(sync_data)) => {
                 self.peer_manager_addr
                     .do_send(NetworkRequests::Sync { peer_id: self.peer_id().unwrap(), sync_data });
@@ -1053,14 +1288,14 @@ impl Handler<SendMessage> for Peer {
     }
 }
 
-impl Handler<Arc<SendMessage>> for Peer {
+impl Handler<Arc<BroadcastMessage>> for Peer {
     type Result = ();
 
     #[perf]
-    fn handle(&mut self, msg: Arc<SendMessage>, _: &mut Self::Context) {
+    fn handle(&mut self, msg: Arc<BroadcastMessage>, _: &mut Self::Context) {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("send message".into());
-        self.send_message(&msg.as_ref().message);
+        self.send_serialized_message(&msg.message, || msg.bytes());
     }
 }
 
@@ -1080,6 +1315,7 @@ impl Handler<QueryPeerStats> for Peer {
                 self.tracker.sent_bytes.count_per_min(),
                 self.tracker.received_bytes.count_per_min(),
             ),
+            traffic: self.tracker.traffic.clone(),
         }
     }
 }
@@ -1096,7 +1332,7 @@ impl Handler<PeerManagerRequest> for Peer {
                 self.ban_peer(ctx, ban_reason);
             }
             PeerManagerRequest::UnregisterPeer => {
-                ctx.stop();
+                self.disconnect(ctx, DisconnectReason::Unregistered);
             }
         }
     }
@@ -9,7 +9,7 @@ use std::time::{Duration, Instant};
 
 use actix::{
     Actor, ActorFuture, Addr, Arbiter, AsyncContext, Context, ContextFutureSpawner, Handler,
-    Recipient, Running, StreamHandler, SyncArbiter, SyncContext, WrapFuture,
+    Recipient, Running, StreamHandler, SyncArbiter, SyncContext, System, WrapFuture,
 };
 use chrono::Utc;
 use futures::task::Poll;
@@ -18,32 +18,43 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio_util::codec::FramedRead;
 use tracing::{debug, error, info, trace, warn};
 
+#[cfg(feature = "protocol_feature_relay_peers")]
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::AccountId;
 use near_primitives::utils::from_timestamp;
+use near_primitives::version::ProtocolVersion;
 use near_store::Store;
 
+use crate::broadcast::{select_fanout, BroadcastFanout};
 use crate::codec::Codec;
+use crate::event_log::{DisconnectReason, NetworkEvent, NetworkEventLog};
+use crate::handshake_mismatch::HandshakeMismatches;
 use crate::metrics;
+use crate::noise::{self, ConnectionEncryption, NoiseTransport};
 use crate::peer::Peer;
 use crate::peer_store::{PeerStore, TrustLevel};
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::{MetricRecorder, PeerMessageMetadata};
+#[cfg(feature = "protocol_feature_relay_peers")]
+use crate::relay::RelayState;
+use crate::reputation::{PeerMisbehavior, PeerReputation};
 use crate::routing::{
     Edge, EdgeInfo, EdgeType, EdgeVerifierHelper, ProcessEdgeResult, RoutingTable, MAX_NUM_PEERS,
 };
 use crate::types::{
-    AccountOrPeerIdOrHash, Ban, BlockedPorts, Consolidate, ConsolidateResponse, FullPeerInfo,
-    InboundTcpConnect, KnownPeerStatus, KnownProducer, NetworkInfo, NetworkViewClientMessages,
-    NetworkViewClientResponses, OutboundTcpConnect, PeerIdOrHash, PeerList, PeerManagerRequest,
-    PeerMessage, PeerRequest, PeerResponse, PeerType, PeersRequest, PeersResponse, Ping, Pong,
-    QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
-    RoutedMessageFrom, SendMessage, StateResponseInfo, SyncData, Unregister,
+    AccountOrPeerIdOrHash, Ban, BlockedPorts, BroadcastMessage, ConnectionAddrs, Consolidate,
+    ConsolidateResponse, FullPeerInfo, InboundTcpConnect, KnownPeerStatus, KnownProducer,
+    NetworkInfo, NetworkViewClientMessages, NetworkViewClientResponses, OutboundTcpConnect,
+    PeerIdOrHash, PeerList, PeerManagerRequest, PeerMessage, PeerRequest, PeerResponse, PeerType,
+    PeersRequest, PeersResponse, Ping, Pong, QueryPeerStats, RawRoutedMessage, ReasonForBan,
+    ReportPeer, RoutedMessage, RoutedMessageBody, RoutedMessageFrom, SendMessage,
+    StateResponseInfo, SyncData, Unregister,
 };
 use crate::types::{
     EdgeList, KnownPeerState, NetworkClientMessages, NetworkConfig, NetworkRequests,
-    NetworkResponses, PeerInfo,
+    NetworkResponses, PeerInfo, PeerTraffic, PeerTrafficStats,
 };
 #[cfg(feature = "delay_detector")]
 use delay_detector::DelayDetector;
@@ -95,11 +106,15 @@ macro_rules! unwrap_or_error(($obj: expr, $error: expr) => (match $obj {
 /// Contains information relevant to an active peer.
 struct ActivePeer {
     addr: Addr<Peer>,
+    /// Addresses of the connection, to tell a peer which moved from a duplicate connection.
+    connection: ConnectionAddrs,
     full_peer_info: FullPeerInfo,
     /// Number of bytes we've received from the peer.
     received_bytes_per_sec: u64,
     /// Number of bytes we've sent to the peer.
     sent_bytes_per_sec: u64,
+    /// Messages exchanged with the peer per message type, as of the last stats query.
+    traffic: PeerTrafficStats,
     /// Last time requested peers.
     last_time_peer_requested: Instant,
     /// Last time we received a message from this peer.
@@ -108,6 +123,9 @@ struct ActivePeer {
     connection_established_time: Instant,
     /// Who started connection. Inbound (other) or Outbound (us).
     peer_type: PeerType,
+    /// Protocol version agreed with the peer.
+    #[cfg_attr(not(feature = "protocol_feature_relay_peers"), allow(dead_code))]
+    protocol_version: ProtocolVersion,
 }
 
 struct EdgeVerifier {}
@@ -181,6 +199,17 @@ pub struct PeerManagerActor {
     peer_counter: Arc<AtomicUsize>,
     scheduled_routing_table_update: bool,
     edge_verifier_requests_in_progress: u64,
+    /// Reputation of the peers, used to ban peers which keep misbehaving.
+    reputation: PeerReputation,
+    /// Peers which rejected us for being on another network.
+    handshake_mismatches: HandshakeMismatches,
+    /// Recent connections, disconnections, bans and routing changes, for postmortems.
+    event_log: NetworkEventLog,
+    /// Number of reachable peers after the last routing table update.
+    num_reachable_peers: usize,
+    /// Peers we relay for, and our relay if we can't accept inbound connections.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    relay: RelayState,
 }
 
 impl PeerManagerActor {
@@ -207,6 +236,17 @@ impl PeerManagerActor {
         let metric_recorder = MetricRecorder::default().set_me(me.clone());
 
         let txns_since_last_block = Arc::new(AtomicUsize::new(0));
+        let reputation =
+            PeerReputation::new(config.reputation_ban_threshold, config.reputation_half_life);
+        #[cfg(feature = "protocol_feature_relay_peers")]
+        let relay = RelayState::new(config.max_relayed_peers);
+        // A node with a single boot node has to give up once that one rejected it.
+        let max_mismatching_peers = if config.boot_nodes.is_empty() {
+            config.max_mismatching_peers as usize
+        } else {
+            cmp::min(config.max_mismatching_peers as usize, config.boot_nodes.len())
+        };
+        let handshake_mismatches = HandshakeMismatches::new(max_mismatching_peers);
 
         Ok(PeerManagerActor {
             peer_id: me,
@@ -230,9 +270,21 @@ impl PeerManagerActor {
             peer_counter: Arc::new(AtomicUsize::new(0)),
             scheduled_routing_table_update: false,
             edge_verifier_requests_in_progress: 0,
+            reputation,
+            handshake_mismatches,
+            event_log: NetworkEventLog::default(),
+            num_reachable_peers: 0,
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            relay,
         })
     }
 
+    /// Records the network events into `event_log`, which may be shared with other components.
+    pub fn with_event_log(mut self, event_log: NetworkEventLog) -> Self {
+        self.event_log = event_log;
+        self
+    }
+
     fn broadcast_accounts(
         &mut self,
         ctx: &mut Context<PeerManagerActor>,
@@ -345,10 +397,18 @@ impl PeerManagerActor {
         full_peer_info: FullPeerInfo,
         edge_info: EdgeInfo,
         peer_type: PeerType,
+        protocol_version: ProtocolVersion,
         addr: Addr<Peer>,
+        connection: ConnectionAddrs,
         ctx: &mut Context<Self>,
     ) {
         debug!(target: "network", "Consolidated connection with {:?}", full_peer_info);
+        self.handshake_mismatches.set_connected();
+        self.event_log.record(NetworkEvent::Connected {
+            peer_id: full_peer_info.peer_info.id.clone(),
+            addr: full_peer_info.peer_info.addr,
+            peer_type,
+        });
 
         if self.outgoing_peers.contains(&full_peer_info.peer_info.id) {
             self.outgoing_peers.remove(&full_peer_info.peer_info.id);
@@ -372,13 +432,16 @@ impl PeerManagerActor {
             target_peer_id.clone(),
             ActivePeer {
                 addr: addr.clone(),
+                connection,
                 full_peer_info,
                 sent_bytes_per_sec: 0,
                 received_bytes_per_sec: 0,
+                traffic: Default::default(),
                 last_time_peer_requested: Instant::now(),
                 last_time_received_message: Instant::now(),
                 connection_established_time: Instant::now(),
                 peer_type,
+                protocol_version,
             },
         );
 
@@ -426,6 +489,15 @@ impl PeerManagerActor {
         );
     }
 
+    /// Refuses a connection whose handshake was received, recording why.
+    fn reject_handshake(&self, peer_id: &PeerId, reason: &str) -> ConsolidateResponse {
+        self.event_log.record(NetworkEvent::HandshakeFailed {
+            peer_id: peer_id.clone(),
+            reason: reason.to_string(),
+        });
+        ConsolidateResponse::Reject
+    }
+
     /// Remove peer from active set.
     /// Check it match peer_type to avoid removing a peer that both started connection to each other.
     /// If peer_type is None, remove anyway disregarding who started the connection.
@@ -447,6 +519,8 @@ impl PeerManagerActor {
         // If the last edge we have with this peer represent a connection addition, create the edge
         // update that represents the connection removal.
         self.active_peers.remove(&peer_id);
+        #[cfg(feature = "protocol_feature_relay_peers")]
+        self.relay.peer_disconnected(peer_id);
 
         if let Some(edge) = self.routing_table.get_edge(self.peer_id.clone(), peer_id.clone()) {
             if edge.edge_type() == EdgeType::Added {
@@ -492,6 +566,8 @@ impl PeerManagerActor {
     /// Note: Use `try_ban_peer` if there might be a Peer instance still active.
     fn ban_peer(&mut self, ctx: &mut Context<Self>, peer_id: &PeerId, ban_reason: ReasonForBan) {
         warn!(target: "network", "Banning peer {:?} for {:?}", peer_id, ban_reason);
+        self.event_log
+            .record(NetworkEvent::Banned { peer_id: peer_id.clone(), reason: ban_reason });
         self.remove_active_peer(ctx, peer_id, None);
         unwrap_or_error!(self.peer_store.peer_ban(peer_id, ban_reason), "Failed to save peer data");
     }
@@ -514,8 +590,24 @@ impl PeerManagerActor {
         }
     }
 
+    /// Lowers the reputation of the peer, and bans it once the reputation gets too low.
+    fn report_peer(
+        &mut self,
+        ctx: &mut Context<Self>,
+        peer_id: &PeerId,
+        misbehavior: PeerMisbehavior,
+    ) {
+        debug!(target: "network", "Peer {:?} misbehaved: {:?}", peer_id, misbehavior);
+        near_metrics::inc_counter(&metrics::PEER_MISBEHAVIOR_REPORTED_TOTAL);
+        if self.reputation.report(peer_id, misbehavior, Instant::now()) {
+            near_metrics::inc_counter(&metrics::PEER_LOW_REPUTATION_BANS_TOTAL);
+            self.try_ban_peer(ctx, peer_id, ReasonForBan::LowReputation);
+        }
+    }
+
     /// Connects peer with given TcpStream and optional information if it's outbound.
     /// This might fail if the other peers drop listener at its endpoint while establishing connection.
+    /// `transport` holds the keys of the connection if it's encrypted.
     fn try_connect_peer(
         &mut self,
         recipient: Addr<Self>,
@@ -523,11 +615,13 @@ impl PeerManagerActor {
         peer_type: PeerType,
         peer_info: Option<PeerInfo>,
         edge_info: Option<EdgeInfo>,
+        transport: Option<NoiseTransport>,
     ) {
         let peer_id = self.peer_id.clone();
         let account_id = self.config.account_id.clone();
         let server_addr = self.config.addr;
         let handshake_timeout = self.config.handshake_timeout;
+        let send_queue_config = self.config.send_queue.clone();
         let client_addr = self.client_addr.clone();
         let view_client_addr = self.view_client_addr.clone();
 
@@ -550,6 +644,14 @@ impl PeerManagerActor {
             }
         };
 
+        let local_addr = match stream.local_addr() {
+            Ok(local_addr) => local_addr,
+            _ => {
+                warn!(target: "network", "Failed establishing connection with {:?}", peer_info);
+                return;
+            }
+        };
+
         let network_metrics = self.network_metrics.clone();
         let txns_since_last_block = Arc::clone(&self.txns_since_last_block);
 
@@ -560,10 +662,17 @@ impl PeerManagerActor {
 
         Peer::start_in_arbiter(&arbiter.handle(), move |ctx| {
             let (read, write) = tokio::io::split(stream);
+            let transport = transport.map(Arc::new);
+            let (read_codec, write_codec) = match &transport {
+                Some(transport) => {
+                    (Codec::encrypted(transport.clone()), Codec::encrypted(transport.clone()))
+                }
+                None => (Codec::new(), Codec::new()),
+            };
 
             // TODO: check if peer is banned or known based on IP address and port.
             Peer::add_stream(
-                FramedRead::new(read, Codec::new())
+                FramedRead::new(read, read_codec)
                     .take_while(|x| match x {
                         Ok(_) => future::ready(true),
                         Err(e) => {
@@ -578,9 +687,11 @@ impl PeerManagerActor {
             Peer::new(
                 PeerInfo { id: peer_id, addr: Some(server_addr), account_id },
                 remote_addr,
+                local_addr,
                 peer_info,
                 peer_type,
-                FramedWrite::new(write, Codec::new(), Codec::new(), ctx),
+                FramedWrite::new(write, write_codec, Codec::new(), ctx),
+                transport,
                 handshake_timeout,
                 recipient,
                 client_addr,
@@ -589,10 +700,49 @@ impl PeerManagerActor {
                 network_metrics,
                 txns_since_last_block,
                 peer_counter,
+                &send_queue_config,
             )
         });
     }
 
+    /// Runs the encryption handshake of an outbound connection, without blocking the actor.
+    fn encrypt_outbound_connection(
+        &mut self,
+        ctx: &mut Context<Self>,
+        stream: TcpStream,
+        peer_info: PeerInfo,
+    ) {
+        let handshake =
+            noise::initiate(stream, self.config.secret_key.clone(), peer_info.id.clone());
+        tokio::time::timeout(self.config.handshake_timeout, handshake)
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok((stream, transport))) => {
+                        let edge_info = act.propose_edge(peer_info.id.clone(), None);
+                        act.try_connect_peer(
+                            ctx.address(),
+                            stream,
+                            PeerType::Outbound,
+                            Some(peer_info),
+                            Some(edge_info),
+                            Some(transport),
+                        );
+                    }
+                    Ok(Err(err)) => {
+                        info!(target: "network", "Error encrypting connection to {}: {}", peer_info, err);
+                        act.outgoing_peers.remove(&peer_info.id);
+                    }
+                    Err(err) => {
+                        info!(target: "network", "Error encrypting connection to {}: {}", peer_info, err);
+                        act.outgoing_peers.remove(&peer_info.id);
+                    }
+                }
+                actix::fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
     fn num_active_outgoing_peers(&self) -> usize {
         self.active_peers
             .values()
@@ -700,6 +850,13 @@ impl PeerManagerActor {
                     act.scheduled_routing_table_update = false;
                     // We only want to save prune edges if there are no pending requests to EdgeVerifier
                     act.routing_table.update(act.edge_verifier_requests_in_progress == 0);
+                    let num_reachable_peers = act.routing_table.reachable_peers().count();
+                    if num_reachable_peers != act.num_reachable_peers {
+                        act.num_reachable_peers = num_reachable_peers;
+                        act.event_log.record(NetworkEvent::RoutingTableChanged {
+                            reachable_peers: num_reachable_peers,
+                        });
+                    }
                     #[cfg(feature = "metric_recorder")]
                     act.metric_recorder.set_graph(act.routing_table.get_raw_graph())
                 },
@@ -803,20 +960,18 @@ impl PeerManagerActor {
                 .send(QueryPeerStats {})
                 .into_actor(self)
                 .map(|result, _, _| result.map_err(|err| error!(target: "network", "Failed sending message(monitor_peer_stats): {}", err)))
-                .map(move |res, act, _| {
+                .map(move |res, act, ctx| {
                     let _ignore = res.map(|res| {
                         if res.is_abusive {
-                            trace!(target: "network", "Banning peer {} for abuse ({} sent, {} recv)", peer_id1, res.message_counts.0, res.message_counts.1);
-                            // TODO(MarX, #1586): Ban peer if we found them abusive. Fix issue with heavy
-                            //  network traffic that flags honest peers.
-                            // Send ban signal to peer instance. It should send ban signal back and stop the instance.
-                            // if let Some(active_peer) = act.active_peers.get(&peer_id1) {
-                            //     active_peer.addr.do_send(PeerManagerRequest::BanPeer(ReasonForBan::Abusive));
-                            // }
+                            trace!(target: "network", "Peer {} is abusive ({} sent, {} recv)", peer_id1, res.message_counts.0, res.message_counts.1);
+                            // Heavy network traffic can flag honest peers as well, so this only
+                            // lowers the reputation of the peer instead of banning it right away.
+                            act.report_peer(ctx, &peer_id1, PeerMisbehavior::BandwidthAbuse);
                         } else if let Some(active_peer) = act.active_peers.get_mut(&peer_id1) {
                             active_peer.full_peer_info.chain_info = res.chain_info;
                             active_peer.sent_bytes_per_sec = res.sent_bytes_per_sec;
                             active_peer.received_bytes_per_sec = res.received_bytes_per_sec;
+                            active_peer.traffic = res.traffic;
                         }
                     });
                 })
@@ -870,6 +1025,14 @@ impl PeerManagerActor {
             }
         }
 
+        // Peers we relay for can't be reached once disconnected, and neither can we without our
+        // relay if we run in relay mode.
+        #[cfg(feature = "protocol_feature_relay_peers")]
+        {
+            safe_set.extend(self.relay.relayed_peers().cloned());
+            safe_set.extend(self.relay.relay().cloned());
+        }
+
         // Find all recent connections
         let mut recent_connections = self
             .active_peers
@@ -924,8 +1087,11 @@ impl PeerManagerActor {
     ///  - request new peers from connected peers,
     ///  - bootstrap outbound connections from known peers,
     ///  - unban peers that have been banned for awhile,
+    ///  - forget the misbehavior of peers which behave since awhile,
     ///  - remove expired peers,
     fn monitor_peers(&mut self, ctx: &mut Context<Self>) {
+        self.reputation.prune(Instant::now());
+
         let mut to_unban = vec![];
         for (peer_id, peer_state) in self.peer_store.iter() {
             if let KnownPeerStatus::Banned(_, last_banned) = peer_state.status {
@@ -963,8 +1129,13 @@ impl PeerManagerActor {
             } else {
                 self.query_active_peers_for_more_peers(ctx);
             }
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            self.request_relayed_connection(ctx);
         }
 
+        #[cfg(feature = "protocol_feature_relay_peers")]
+        self.maybe_register_at_relay();
+
         // If there are too many active connections try to remove some connections
         if self.active_peers.len() > self.config.ideal_connections_hi as usize {
             self.try_stop_active_connection();
@@ -997,15 +1168,161 @@ impl PeerManagerActor {
         );
     }
 
+    /// Registers at one of our outbound peers if we run in relay mode and have no relay yet.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    fn maybe_register_at_relay(&mut self) {
+        if !self.config.use_relay || self.relay.relay().is_some() {
+            return;
+        }
+        let relay = self
+            .active_peers
+            .iter()
+            .filter(|(_, active_peer)| {
+                active_peer.peer_type == PeerType::Outbound
+                    && checked_feature!(
+                        "protocol_feature_relay_peers",
+                        RelayPeers,
+                        active_peer.protocol_version
+                    )
+            })
+            .choose(&mut rand::thread_rng());
+        if let Some((peer_id, active_peer)) = relay {
+            debug!(target: "network", "Registering at relay {}", peer_id);
+            active_peer.addr.do_send(SendMessage { message: PeerMessage::RelayRegister });
+            self.relay.set_relay(peer_id.clone());
+        }
+    }
+
+    /// Asks a peer we can reach through the network, but aren't connected to, to connect to us.
+    /// Only peers in relay mode answer, since the others can be connected to directly.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    fn request_relayed_connection(&mut self, ctx: &mut Context<Self>) {
+        // We can't accept the connection ourselves.
+        if self.config.use_relay {
+            return;
+        }
+        let mut candidates = self
+            .routing_table
+            .reachable_peers()
+            .filter(|peer_id| {
+                **peer_id != self.peer_id
+                    && !self.active_peers.contains_key(peer_id)
+                    && !self.outgoing_peers.contains(peer_id)
+                    && !self.peer_store.is_banned(peer_id)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        candidates.shuffle(&mut thread_rng());
+        let now = Instant::now();
+        if let Some(target) =
+            candidates.into_iter().find(|peer_id| self.relay.try_request_connection(peer_id, now))
+        {
+            debug!(target: "network", "Asking {} to connect to us", target);
+            let peer_info = PeerInfo {
+                id: self.peer_id.clone(),
+                addr: self.config.addr,
+                account_id: self.config.account_id.clone(),
+            };
+            self.send_message_to_peer(
+                ctx,
+                RawRoutedMessage {
+                    target: AccountOrPeerIdOrHash::PeerId(target),
+                    body: RoutedMessageBody::RelayConnect(peer_info),
+                },
+            );
+        }
+    }
+
+    /// Connects to the author of a `RelayConnect` request if we run in relay mode.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    fn handle_relay_connect(
+        &mut self,
+        ctx: &mut Context<Self>,
+        author: PeerId,
+        peer_info: PeerInfo,
+    ) {
+        if !self.config.use_relay {
+            return;
+        }
+        // Don't let peers make us connect to somebody else.
+        if peer_info.id != author {
+            warn!(target: "network", "Received RelayConnect from {} for {}", author, peer_info.id);
+            return;
+        }
+        if self.active_peers.contains_key(&author)
+            || self.outgoing_peers.contains(&author)
+            || self.peer_store.is_banned(&author)
+            || !self.is_inbound_allowed()
+        {
+            return;
+        }
+        // The address observed by other peers is more reliable than the configured one, which
+        // may be the unspecified address the peer listens on.
+        let addr = self
+            .peer_store
+            .peer_addr(&author)
+            .or_else(|| peer_info.addr.filter(|addr| !addr.ip().is_unspecified()));
+        match addr {
+            Some(addr) if !self.is_blacklisted(&addr) => {
+                debug!(target: "network", "Connecting to {} at {} on its request", author, addr);
+                self.outgoing_peers.insert(author);
+                ctx.notify(OutboundTcpConnect {
+                    peer_info: PeerInfo { addr: Some(addr), ..peer_info },
+                });
+            }
+            _ => {
+                debug!(target: "network", "Can't connect to {} on its request: unknown address", author);
+            }
+        }
+    }
+
     /// Broadcast message to all active peers.
     fn broadcast_message(&self, ctx: &mut Context<Self>, msg: SendMessage) {
         // TODO(MarX, #1363): Implement smart broadcasting. (MST)
+        self.send_to_peers(ctx, msg, self.active_peers.values());
+    }
+
+    /// Broadcast a message to the active peers selected by `fanout`.
+    fn broadcast_message_with_fanout(
+        &mut self,
+        ctx: &mut Context<Self>,
+        msg: SendMessage,
+        fanout: BroadcastFanout,
+    ) {
+        if fanout == BroadcastFanout::All {
+            return self.broadcast_message(ctx, msg);
+        }
+        let validators = self
+            .routing_table
+            .get_announce_accounts()
+            .into_iter()
+            .map(|announce_account| announce_account.peer_id)
+            .collect::<HashSet<_>>();
+        let peer_ids = select_fanout(
+            fanout,
+            self.active_peers.keys(),
+            &validators,
+            &self.routing_table.peer_forwarding,
+        );
+        trace!(target: "network", "Broadcast with fanout {:?} to {} of {} peers", fanout, peer_ids.len(), self.active_peers.len());
+        self.send_to_peers(
+            ctx,
+            msg,
+            peer_ids.iter().filter_map(|peer_id| self.active_peers.get(peer_id)),
+        );
+    }
 
+    fn send_to_peers<'a>(
+        &self,
+        ctx: &mut Context<Self>,
+        msg: SendMessage,
+        peers: impl Iterator<Item = &'a ActivePeer>,
+    ) {
         // Change message to reference counted to allow sharing with all actors
-        // without cloning.
-        let msg = Arc::new(msg);
+        // without cloning, and serializing it only once.
+        let msg = Arc::new(BroadcastMessage::new(msg.message));
         let mut requests: futures::stream::FuturesUnordered<_> =
-            self.active_peers.values().map(|peer| peer.addr.send(Arc::clone(&msg))).collect();
+            peers.map(|peer| peer.addr.send(Arc::clone(&msg))).collect();
 
         ctx.spawn(async move {
             while let Some(response) = requests.next().await {
@@ -1246,6 +1563,14 @@ impl PeerManagerActor {
                     addr: None,
                 })
                 .collect(),
+            peer_traffic: self
+                .active_peers
+                .iter()
+                .map(|(peer_id, active_peer)| PeerTraffic {
+                    peer_id: peer_id.clone(),
+                    stats: active_peer.traffic.clone(),
+                })
+                .collect(),
             #[cfg(feature = "metric_recorder")]
             metric_recorder: self.metric_recorder.clone(),
             peer_counter: self.peer_counter.load(Ordering::SeqCst),
@@ -1361,7 +1686,11 @@ impl Handler<NetworkRequests> for PeerManagerActor {
         let _d = DelayDetector::new(format!("network request {}", msg.as_ref()).into());
         match msg {
             NetworkRequests::Block { block } => {
-                self.broadcast_message(ctx, SendMessage { message: PeerMessage::Block(block) });
+                self.broadcast_message_with_fanout(
+                    ctx,
+                    SendMessage { message: PeerMessage::Block(block) },
+                    BroadcastFanout::All,
+                );
                 NetworkResponses::NoResponse
             }
             NetworkRequests::Approval { approval_message } => {
@@ -1442,10 +1771,21 @@ impl Handler<NetworkRequests> for PeerManagerActor {
                     NetworkResponses::RouteNotFound
                 }
             }
+            NetworkRequests::ReportPeer { peer_id, misbehavior } => {
+                self.report_peer(ctx, &peer_id, misbehavior);
+                NetworkResponses::NoResponse
+            }
             NetworkRequests::BanPeer { peer_id, ban_reason } => {
                 self.try_ban_peer(ctx, &peer_id, ban_reason);
                 NetworkResponses::NoResponse
             }
+            NetworkRequests::DisconnectPeer { peer_id } => {
+                if let Some(active_peer) = self.active_peers.get(&peer_id) {
+                    debug!(target: "network", "Disconnect peer: {:?}", peer_id);
+                    active_peer.addr.do_send(PeerManagerRequest::UnregisterPeer);
+                }
+                NetworkResponses::NoResponse
+            }
             NetworkRequests::AnnounceAccount(announce_account) => {
                 self.announce_account(ctx, announce_account);
                 NetworkResponses::NoResponse
@@ -1649,10 +1989,11 @@ impl Handler<NetworkRequests> for PeerManagerActor {
                 NetworkResponses::NoResponse
             }
             NetworkRequests::Challenge(challenge) => {
-                // TODO(illia): smarter routing?
-                self.broadcast_message(
+                // Challenges are only acted upon by the validators.
+                self.broadcast_message_with_fanout(
                     ctx,
                     SendMessage { message: PeerMessage::Challenge(challenge) },
+                    BroadcastFanout::Validators,
                 );
                 NetworkResponses::NoResponse
             }
@@ -1717,7 +2058,30 @@ impl Handler<InboundTcpConnect> for PeerManagerActor {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("inbound tcp connect".into());
         if self.is_inbound_allowed() {
-            self.try_connect_peer(ctx.address(), msg.stream, PeerType::Inbound, None, None);
+            let handshake =
+                noise::accept(msg.stream, self.config.secret_key.clone(), self.config.encryption);
+            tokio::time::timeout(self.config.handshake_timeout, handshake)
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(Ok((stream, transport))) => act.try_connect_peer(
+                            ctx.address(),
+                            stream,
+                            PeerType::Inbound,
+                            None,
+                            None,
+                            transport,
+                        ),
+                        Ok(Err(err)) => {
+                            debug!(target: "network", "Inbound connection dropped: {}", err)
+                        }
+                        Err(err) => {
+                            debug!(target: "network", "Inbound connection dropped: {}", err)
+                        }
+                    }
+                    actix::fut::ready(())
+                })
+                .spawn(ctx);
         } else {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network", "Inbound connection dropped (network at max capacity).");
@@ -1749,6 +2113,10 @@ impl Handler<OutboundTcpConnect> for PeerManagerActor {
                     Ok(res) => match res {
                         Ok(stream) => {
                             debug!(target: "network", "Connecting to {}", msg.peer_info);
+                            if act.config.encryption == ConnectionEncryption::Required {
+                                act.encrypt_outbound_connection(ctx, stream, msg.peer_info);
+                                return actix::fut::ready(());
+                            }
                             let edge_info = act.propose_edge(msg.peer_info.id.clone(), None);
 
                             act.try_connect_peer(
@@ -1757,6 +2125,7 @@ impl Handler<OutboundTcpConnect> for PeerManagerActor {
                                 PeerType::Outbound,
                                 Some(msg.peer_info),
                                 Some(edge_info),
+                                None,
                             );
                             actix::fut::ready(())
                         }
@@ -1789,19 +2158,24 @@ impl Handler<Consolidate> for PeerManagerActor {
         // Check if this is a blacklisted peer.
         if msg.peer_info.addr.as_ref().map_or(true, |addr| self.is_blacklisted(addr)) {
             debug!(target: "network", "Dropping connection from blacklisted peer or unknown address: {:?}", msg.peer_info);
-            return ConsolidateResponse::Reject;
+            return self.reject_handshake(&msg.peer_info.id, "blacklisted or unknown address");
         }
 
         if self.peer_store.is_banned(&msg.peer_info.id) {
             debug!(target: "network", "Dropping connection from banned peer: {:?}", msg.peer_info.id);
-            return ConsolidateResponse::Reject;
+            return self.reject_handshake(&msg.peer_info.id, "banned");
         }
 
-        // We already connected to this peer.
-        if self.active_peers.contains_key(&msg.peer_info.id) {
-            debug!(target: "network", "Dropping handshake (Active Peer). {:?} {:?}", self.peer_id, msg.peer_info.id);
-            return ConsolidateResponse::Reject;
-        }
+        // We already connected to this peer. If the new connection runs between other hosts, one
+        // of us changed its address and the new connection replaces the stale one.
+        let migrated = match self.active_peers.get(&msg.peer_info.id) {
+            Some(active_peer) if active_peer.connection.is_migrated_to(&msg.connection) => true,
+            Some(_) => {
+                debug!(target: "network", "Dropping handshake (Active Peer). {:?} {:?}", self.peer_id, msg.peer_info.id);
+                return self.reject_handshake(&msg.peer_info.id, "already connected");
+            }
+            None => false,
+        };
 
         // This is incoming connection but we have this peer already in outgoing.
         // This only happens when both of us connect at the same time, break tie using higher peer id.
@@ -1809,19 +2183,22 @@ impl Handler<Consolidate> for PeerManagerActor {
             // We pick connection that has lower id.
             if msg.peer_info.id > self.peer_id {
                 debug!(target: "network", "Dropping handshake (Tied). {:?} {:?}", self.peer_id, msg.peer_info.id);
-                return ConsolidateResponse::Reject;
+                return self.reject_handshake(
+                    &msg.peer_info.id,
+                    "simultaneous connection from both sides",
+                );
             }
         }
 
-        if msg.peer_type == PeerType::Inbound && !self.is_inbound_allowed() {
+        if msg.peer_type == PeerType::Inbound && !migrated && !self.is_inbound_allowed() {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network", "Inbound connection dropped (network at max capacity).");
-            return ConsolidateResponse::Reject;
+            return self.reject_handshake(&msg.peer_info.id, "too many peers");
         }
 
         if msg.other_edge_info.nonce == 0 {
             debug!(target: "network", "Invalid nonce. It must be greater than 0. nonce={}", msg.other_edge_info.nonce);
-            return ConsolidateResponse::Reject;
+            return self.reject_handshake(&msg.peer_info.id, "zero edge nonce");
         }
 
         let last_edge = self.routing_table.get_edge(self.peer_id.clone(), msg.peer_info.id.clone());
@@ -1836,7 +2213,7 @@ impl Handler<Consolidate> for PeerManagerActor {
 
         if msg.other_edge_info.nonce >= Edge::next_nonce(last_nonce) + EDGE_NONCE_BUMP_ALLOWED {
             debug!(target: "network", "Too large nonce. ({} >= {} + {}) {:?} {:?}", msg.other_edge_info.nonce, last_nonce, EDGE_NONCE_BUMP_ALLOWED, self.peer_id, msg.peer_info.id);
-            return ConsolidateResponse::Reject;
+            return self.reject_handshake(&msg.peer_info.id, "edge nonce too large");
         }
 
         let require_response = msg.this_edge_info.is_none();
@@ -1847,6 +2224,11 @@ impl Handler<Consolidate> for PeerManagerActor {
 
         let edge_info_response = if require_response { Some(edge_info.clone()) } else { None };
 
+        // The edge of the new connection has a higher nonce and replaces the edge of the stale
+        // one, so the peer stays in the routing table and keeps its reputation.
+        let stale_peer = if migrated { self.active_peers.remove(&msg.peer_info.id) } else { None };
+        let peer_id = msg.peer_info.id.clone();
+
         // TODO: double check that address is connectable and add account id.
         self.register_peer(
             FullPeerInfo {
@@ -1856,10 +2238,22 @@ impl Handler<Consolidate> for PeerManagerActor {
             },
             edge_info,
             msg.peer_type,
+            msg.protocol_version,
             msg.actor,
+            msg.connection,
             ctx,
         );
 
+        if let Some(stale_peer) = stale_peer {
+            info!(target: "network", "Peer {:?} moved from {:?} to {:?}", peer_id, stale_peer.connection, msg.connection);
+            if let Some(active_peer) = self.active_peers.get_mut(&peer_id) {
+                active_peer.connection_established_time = stale_peer.connection_established_time;
+                active_peer.last_time_peer_requested = stale_peer.last_time_peer_requested;
+            }
+            stale_peer.addr.do_send(SendMessage { message: PeerMessage::Disconnect });
+            stale_peer.addr.do_send(PeerManagerRequest::UnregisterPeer);
+        }
+
         return ConsolidateResponse::Accept(edge_info_response);
     }
 }
@@ -1871,6 +2265,24 @@ impl Handler<Unregister> for PeerManagerActor {
     fn handle(&mut self, msg: Unregister, ctx: &mut Self::Context) {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("unregister".into());
+        // The connection was replaced after the peer changed its address.
+        if msg.remove_from_peer_store
+            && self.active_peers.get(&msg.peer_id).map_or(false, |peer| peer.addr != msg.actor)
+        {
+            return;
+        }
+        if msg.remove_from_peer_store {
+            self.event_log.record(NetworkEvent::Disconnected {
+                peer_id: msg.peer_id.clone(),
+                reason: msg.reason,
+            });
+        } else if msg.reason != DisconnectReason::NotConsolidated {
+            // Connections refused by the peer manager were recorded when refusing them.
+            self.event_log.record(NetworkEvent::HandshakeFailed {
+                peer_id: msg.peer_id.clone(),
+                reason: format!("{:?}", msg.reason),
+            });
+        }
         self.unregister_peer(ctx, msg.peer_id, msg.peer_type, msg.remove_from_peer_store);
     }
 }
@@ -1886,6 +2298,17 @@ impl Handler<Ban> for PeerManagerActor {
     }
 }
 
+impl Handler<ReportPeer> for PeerManagerActor {
+    type Result = ();
+
+    #[perf]
+    fn handle(&mut self, msg: ReportPeer, ctx: &mut Self::Context) {
+        #[cfg(feature = "delay_detector")]
+        let _d = DelayDetector::new("report peer".into());
+        self.report_peer(ctx, &msg.peer_id, msg.misbehavior);
+    }
+}
+
 impl Handler<PeersRequest> for PeerManagerActor {
     type Result = PeerList;
 
@@ -1904,9 +2327,14 @@ impl Handler<PeersResponse> for PeerManagerActor {
     fn handle(&mut self, msg: PeersResponse, _ctx: &mut Self::Context) {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("peers response".into());
+        // A peer sends at most `max_send_peers` peers, ignore the excess of misbehaving peers.
         unwrap_or_error!(
             self.peer_store.add_indirect_peers(
-                msg.peers.into_iter().filter(|peer_info| peer_info.id != self.peer_id).collect()
+                msg.peers
+                    .into_iter()
+                    .take(self.config.max_send_peers as usize)
+                    .filter(|peer_info| peer_info.id != self.peer_id)
+                    .collect()
             ),
             "Fail to update peer store"
         );
@@ -1937,6 +2365,10 @@ impl Handler<RoutedMessageFrom> for PeerManagerActor {
             match &msg.body {
                 RoutedMessageBody::Ping(ping) => self.handle_ping(ctx, ping.clone(), msg.hash()),
                 RoutedMessageBody::Pong(pong) => self.handle_pong(ctx, pong.clone()),
+                #[cfg(feature = "protocol_feature_relay_peers")]
+                RoutedMessageBody::RelayConnect(peer_info) => {
+                    self.handle_relay_connect(ctx, msg.author.clone(), peer_info.clone())
+                }
                 _ => return true,
             }
 
@@ -2000,6 +2432,34 @@ impl Handler<PeerRequest> for PeerManagerActor {
                 }
                 PeerResponse::NoResponse
             }
+            PeerRequest::HandshakeMismatch(peer_info, mismatches) => {
+                self.event_log.record(NetworkEvent::HandshakeFailed {
+                    peer_id: peer_info.id.clone(),
+                    reason: mismatches
+                        .iter()
+                        .map(|mismatch| mismatch.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                });
+                if self.handshake_mismatches.record(peer_info, mismatches) {
+                    error!(target: "network", "{}", self.handshake_mismatches.report());
+                    error!(target: "network", "{}", self.event_log.report());
+                    System::current().stop_with_code(1);
+                }
+                PeerResponse::NoResponse
+            }
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            PeerRequest::RelayRegister(peer_id) => {
+                // Ignore the request if the connection was closed in the meantime.
+                if self.active_peers.contains_key(&peer_id) {
+                    if self.relay.register(peer_id.clone()) {
+                        debug!(target: "network", "Relaying for {}", peer_id);
+                    } else {
+                        debug!(target: "network", "Not relaying for {}: too many relayed peers", peer_id);
+                    }
+                }
+                PeerResponse::NoResponse
+            }
         }
     }
 }
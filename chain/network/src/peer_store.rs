@@ -3,7 +3,7 @@ use std::collections::{
     HashMap,
 };
 use std::convert::TryInto;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use borsh::BorshSerialize;
@@ -126,6 +126,11 @@ impl PeerStore {
             .map_or(false, |known_peer_state| known_peer_state.status.is_banned())
     }
 
+    /// Return the known address of the peer.
+    pub fn peer_addr(&self, peer_id: &PeerId) -> Option<SocketAddr> {
+        self.peer_states.get(peer_id).and_then(|known_peer_state| known_peer_state.peer_info.addr)
+    }
+
     pub fn peer_connected(
         &mut self,
         peer_info: &PeerInfo,
@@ -352,11 +357,19 @@ impl PeerStore {
         Ok(())
     }
 
+    /// Adds the peers learned from other peers. Peers with an address which can't be dialed are
+    /// dropped, so a peer can't fill the store with garbage.
     pub fn add_indirect_peers(
         &mut self,
         peers: Vec<PeerInfo>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         for peer_info in peers {
+            if let Some(addr) = peer_info.addr {
+                if !is_valid_peer_addr(&addr) {
+                    debug!(target: "network", "Ignoring peer {} with invalid address {}", peer_info.id, addr);
+                    continue;
+                }
+            }
             self.add_peer(peer_info, TrustLevel::Indirect)?;
         }
         Ok(())
@@ -371,6 +384,16 @@ impl PeerStore {
     }
 }
 
+/// Whether a peer can be dialed at the address.
+fn is_valid_peer_addr(addr: &SocketAddr) -> bool {
+    let ip = addr.ip();
+    let is_broadcast = match ip {
+        IpAddr::V4(ip) => ip.is_broadcast(),
+        IpAddr::V6(_) => false,
+    };
+    addr.port() != 0 && !ip.is_unspecified() && !ip.is_multicast() && !is_broadcast
+}
+
 #[cfg(test)]
 mod test {
     use near_crypto::{KeyType, SecretKey};
@@ -419,6 +442,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_ignore_invalid_indirect_peers() {
+        let store = create_test_store();
+        let mut peer_store = PeerStore::new(store, &[]).unwrap();
+        let valid = gen_peer_info(1);
+        let no_addr = get_peer_info(get_peer_id("no_addr".to_string()), None);
+        let invalid_addrs =
+            ["127.0.0.1:0", "0.0.0.0:24567", "224.0.0.1:24567", "255.255.255.255:24567"];
+        let mut peers = vec![valid.clone(), no_addr.clone()];
+        for (i, addr) in invalid_addrs.iter().enumerate() {
+            peers.push(get_peer_info(
+                get_peer_id(format!("invalid{}", i)),
+                Some(addr.parse().unwrap()),
+            ));
+        }
+        peer_store.add_indirect_peers(peers).unwrap();
+        assert_eq!(peer_store.len(), 2);
+        assert!(check_exist(
+            &peer_store,
+            &valid.id,
+            Some((valid.addr.unwrap(), TrustLevel::Indirect))
+        ));
+        assert!(check_exist(&peer_store, &no_addr.id, None));
+    }
+
     fn check_exist(
         peer_store: &PeerStore,
         peer_id: &PeerId,
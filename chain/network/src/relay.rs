@@ -0,0 +1,127 @@
+//! Connections to nodes which can't accept inbound connections, e.g. because they are behind NAT.
+//!
+//! Such a node runs with `use_relay` set. It registers at one of its outbound peers, the relay,
+//! which keeps the connection to it even when it has too many peers, so the node stays reachable
+//! through the routing table. Nodes which need more connections ask unconnected peers reachable
+//! through the network to connect to them with a routed `RelayConnect` message, and a node in
+//! relay mode answers by dialing the author of the request.
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use near_primitives::network::PeerId;
+
+/// Minimum time between two connection requests to the same peer.
+const CONNECT_REQUEST_INTERVAL: Duration = Duration::from_secs(600);
+
+pub struct RelayState {
+    /// Peers we relay for.
+    relayed_peers: HashSet<PeerId>,
+    max_relayed_peers: usize,
+    /// Peer relaying for us, if we run in relay mode.
+    relay: Option<PeerId>,
+    /// Last time we asked each peer to connect to us.
+    connect_requests: HashMap<PeerId, Instant>,
+}
+
+impl RelayState {
+    pub fn new(max_relayed_peers: u32) -> Self {
+        Self {
+            relayed_peers: HashSet::new(),
+            max_relayed_peers: max_relayed_peers as usize,
+            relay: None,
+            connect_requests: HashMap::new(),
+        }
+    }
+
+    /// Starts relaying for the peer. Returns false if we already relay for too many peers.
+    pub fn register(&mut self, peer_id: PeerId) -> bool {
+        if self.relayed_peers.contains(&peer_id) {
+            return true;
+        }
+        if self.relayed_peers.len() >= self.max_relayed_peers {
+            return false;
+        }
+        self.relayed_peers.insert(peer_id);
+        true
+    }
+
+    pub fn is_relayed(&self, peer_id: &PeerId) -> bool {
+        self.relayed_peers.contains(peer_id)
+    }
+
+    pub fn relayed_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.relayed_peers.iter()
+    }
+
+    pub fn relay(&self) -> Option<&PeerId> {
+        self.relay.as_ref()
+    }
+
+    pub fn set_relay(&mut self, peer_id: PeerId) {
+        self.relay = Some(peer_id);
+    }
+
+    /// Forgets the peer once the connection to it is closed.
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.relayed_peers.remove(peer_id);
+        if self.relay.as_ref() == Some(peer_id) {
+            self.relay = None;
+        }
+    }
+
+    /// Whether we can ask the peer to connect to us, in which case the request is recorded.
+    pub fn try_request_connection(&mut self, peer_id: &PeerId, now: Instant) -> bool {
+        self.connect_requests.retain(|_, requested| {
+            now.saturating_duration_since(*requested) < CONNECT_REQUEST_INTERVAL
+        });
+        if self.connect_requests.contains_key(peer_id) {
+            return false;
+        }
+        self.connect_requests.insert(peer_id.clone(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::random_peer_id;
+
+    use super::*;
+
+    #[test]
+    fn test_max_relayed_peers() {
+        let mut state = RelayState::new(2);
+        let peers = (0..3).map(|_| random_peer_id()).collect::<Vec<_>>();
+        assert!(state.register(peers[0].clone()));
+        assert!(state.register(peers[1].clone()));
+        // Registering again is a no-op.
+        assert!(state.register(peers[1].clone()));
+        assert!(!state.register(peers[2].clone()));
+        state.peer_disconnected(&peers[0]);
+        assert!(!state.is_relayed(&peers[0]));
+        assert!(state.register(peers[2].clone()));
+        assert!(state.is_relayed(&peers[2]));
+    }
+
+    #[test]
+    fn test_relay_disconnected() {
+        let mut state = RelayState::new(0);
+        let relay = random_peer_id();
+        state.set_relay(relay.clone());
+        state.peer_disconnected(&random_peer_id());
+        assert_eq!(state.relay(), Some(&relay));
+        state.peer_disconnected(&relay);
+        assert_eq!(state.relay(), None);
+    }
+
+    #[test]
+    fn test_connect_requests_rate_limit() {
+        let mut state = RelayState::new(0);
+        let peer_id = random_peer_id();
+        let now = Instant::now();
+        assert!(state.try_request_connection(&peer_id, now));
+        assert!(!state.try_request_connection(&peer_id, now + Duration::from_secs(1)));
+        assert!(state.try_request_connection(&random_peer_id(), now));
+        assert!(state.try_request_connection(&peer_id, now + CONNECT_REQUEST_INTERVAL));
+    }
+}
@@ -0,0 +1,150 @@
+//! Reputation of the connected peers.
+//!
+//! Misbehavior which is not bad enough to ban a peer right away, e.g. an undecodable message or
+//! a request which timed out, adds a penalty to the score of the peer. Scores decay exponentially
+//! over time, so honest peers which occasionally misbehave are never banned, while a peer which
+//! keeps misbehaving crosses the threshold and is banned with `ReasonForBan::LowReputation`.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use near_primitives::network::PeerId;
+
+/// Score below which the score of a peer is forgotten.
+const MIN_TRACKED_SCORE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerMisbehavior {
+    /// The peer sent data which can't be decoded.
+    InvalidMessage,
+    /// The peer announced a block we didn't request and which is too old to be useful.
+    StaleBlockAnnouncement,
+    /// The peer didn't answer a request in time.
+    Timeout,
+    /// The peer sends or requests much more data than it should.
+    BandwidthAbuse,
+}
+
+impl PeerMisbehavior {
+    pub fn penalty(&self) -> f64 {
+        match self {
+            PeerMisbehavior::InvalidMessage => 10.0,
+            PeerMisbehavior::StaleBlockAnnouncement => 2.0,
+            PeerMisbehavior::Timeout => 5.0,
+            PeerMisbehavior::BandwidthAbuse => 25.0,
+        }
+    }
+}
+
+struct PeerScore {
+    score: f64,
+    updated: Instant,
+}
+
+impl PeerScore {
+    fn decay(&mut self, half_life: Duration, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.score *= 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64());
+        self.updated = now;
+    }
+}
+
+pub struct PeerReputation {
+    scores: HashMap<PeerId, PeerScore>,
+    /// Peers whose score reaches the threshold are banned.
+    ban_threshold: f64,
+    /// Time after which a penalty counts half.
+    half_life: Duration,
+}
+
+impl PeerReputation {
+    pub fn new(ban_threshold: u32, half_life: Duration) -> Self {
+        Self { scores: HashMap::new(), ban_threshold: ban_threshold as f64, half_life }
+    }
+
+    /// Adds the penalty of the misbehavior to the score of the peer.
+    /// Returns true if the peer has to be banned, in which case its score is reset.
+    pub fn report(&mut self, peer_id: &PeerId, misbehavior: PeerMisbehavior, now: Instant) -> bool {
+        let half_life = self.half_life;
+        let entry = self
+            .scores
+            .entry(peer_id.clone())
+            .or_insert_with(|| PeerScore { score: 0.0, updated: now });
+        entry.decay(half_life, now);
+        entry.score += misbehavior.penalty();
+        if entry.score >= self.ban_threshold {
+            self.scores.remove(peer_id);
+            return true;
+        }
+        false
+    }
+
+    /// Current score of the peer, 0 for peers which behave.
+    pub fn score(&mut self, peer_id: &PeerId, now: Instant) -> f64 {
+        let half_life = self.half_life;
+        self.scores.get_mut(peer_id).map_or(0.0, |entry| {
+            entry.decay(half_life, now);
+            entry.score
+        })
+    }
+
+    /// Forgets the peers whose score decayed close to zero.
+    pub fn prune(&mut self, now: Instant) {
+        let half_life = self.half_life;
+        self.scores.retain(|_, entry| {
+            entry.decay(half_life, now);
+            entry.score >= MIN_TRACKED_SCORE
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::random_peer_id;
+
+    use super::*;
+
+    const HALF_LIFE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_ban_after_repeated_misbehavior() {
+        let mut reputation = PeerReputation::new(30, HALF_LIFE);
+        let peer_id = random_peer_id();
+        let now = Instant::now();
+        assert!(!reputation.report(&peer_id, PeerMisbehavior::InvalidMessage, now));
+        assert!(!reputation.report(&peer_id, PeerMisbehavior::InvalidMessage, now));
+        assert_eq!(reputation.score(&peer_id, now), 20.0);
+        assert!(reputation.report(&peer_id, PeerMisbehavior::InvalidMessage, now));
+        // The score is reset once the peer is banned.
+        assert_eq!(reputation.score(&peer_id, now), 0.0);
+        assert_eq!(reputation.len(), 0);
+    }
+
+    #[test]
+    fn test_score_decay() {
+        let mut reputation = PeerReputation::new(30, HALF_LIFE);
+        let peer_id = random_peer_id();
+        let now = Instant::now();
+        assert!(!reputation.report(&peer_id, PeerMisbehavior::BandwidthAbuse, now));
+        assert!((reputation.score(&peer_id, now + HALF_LIFE) - 12.5).abs() < 1e-6);
+        // The decayed score is not enough to cross the threshold anymore.
+        assert!(!reputation.report(&peer_id, PeerMisbehavior::InvalidMessage, now + HALF_LIFE));
+        reputation.prune(now + 10 * HALF_LIFE);
+        assert_eq!(reputation.len(), 0);
+    }
+
+    #[test]
+    fn test_peers_are_scored_separately() {
+        let mut reputation = PeerReputation::new(10, HALF_LIFE);
+        let now = Instant::now();
+        let peer1 = random_peer_id();
+        let peer2 = random_peer_id();
+        assert!(!reputation.report(&peer1, PeerMisbehavior::Timeout, now));
+        assert!(!reputation.report(&peer2, PeerMisbehavior::Timeout, now));
+        assert!(reputation.report(&peer1, PeerMisbehavior::Timeout, now));
+        assert_eq!(reputation.score(&peer2, now), 5.0);
+    }
+}
@@ -0,0 +1,355 @@
+//! Prioritized queues of the messages sent to a peer.
+//!
+//! Messages are not written to the connection in the order they are sent. Each one is put into
+//! the queue of its `MessageClass` and the queues are served in priority order whenever the
+//! write buffer of the connection has room, so consensus messages overtake state parts and
+//! forwarded transactions waiting behind a slow connection. Each class can be limited to a
+//! bandwidth budget, and the queued bytes of every class are capped, in which case messages are
+//! dropped or the peer is disconnected according to the `DropPolicy` of the class.
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{PeerMessage, RoutedMessageBody};
+
+/// Messages classes, from the highest priority to the lowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageClass {
+    /// Messages consensus depends on: blocks, approvals, chunk parts and connection control.
+    Consensus,
+    /// All the other messages.
+    Normal,
+    /// Traffic which can wait: state sync parts and transactions.
+    Bulk,
+}
+
+const MESSAGE_CLASSES: [MessageClass; 3] =
+    [MessageClass::Consensus, MessageClass::Normal, MessageClass::Bulk];
+
+impl MessageClass {
+    pub fn of(msg: &PeerMessage) -> Self {
+        match msg {
+            PeerMessage::Handshake(_)
+            | PeerMessage::HandshakeV2(_)
+            | PeerMessage::HandshakeFailure(_, _)
+            | PeerMessage::LastEdge(_)
+            | PeerMessage::Disconnect
+            | PeerMessage::Block(_) => MessageClass::Consensus,
+            #[cfg(feature = "protocol_feature_stream_multiplexing")]
+            PeerMessage::StreamWindowUpdate(_, _) => MessageClass::Consensus,
+            PeerMessage::Transaction(_) => MessageClass::Bulk,
+            PeerMessage::Routed(routed_message) => match routed_message.body {
+                RoutedMessageBody::BlockApproval(_)
+                | RoutedMessageBody::PartialEncodedChunk(_)
+                | RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                | RoutedMessageBody::PartialEncodedChunkForward(_)
+                | RoutedMessageBody::PartialEncodedChunkRequest(_)
+                | RoutedMessageBody::PartialEncodedChunkResponse(_) => MessageClass::Consensus,
+                RoutedMessageBody::ForwardTx(_)
+                | RoutedMessageBody::StateResponse(_)
+                | RoutedMessageBody::VersionedStateResponse(_) => MessageClass::Bulk,
+                _ => MessageClass::Normal,
+            },
+            _ => MessageClass::Normal,
+        }
+    }
+}
+
+/// Which messages to drop once the queue of a class is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DropPolicy {
+    /// Drop the oldest queued messages to make room for the new one.
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+    /// Drop the new message and disconnect from the peer, for the messages which can't be lost.
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageClassConfig {
+    /// Maximum number of bytes per second sent in messages of the class, unlimited if not set.
+    pub bandwidth_budget: Option<u64>,
+    /// Maximum number of bytes of the queued messages of the class, unlimited if not set.
+    pub max_queued_bytes: Option<u64>,
+    pub drop_policy: DropPolicy,
+}
+
+impl MessageClassConfig {
+    pub fn unlimited() -> Self {
+        MessageClassConfig {
+            bandwidth_budget: None,
+            max_queued_bytes: None,
+            drop_policy: DropPolicy::DropNewest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendQueueConfig {
+    pub consensus: MessageClassConfig,
+    pub normal: MessageClassConfig,
+    pub bulk: MessageClassConfig,
+}
+
+impl Default for SendQueueConfig {
+    fn default() -> Self {
+        SendQueueConfig {
+            consensus: MessageClassConfig {
+                bandwidth_budget: None,
+                max_queued_bytes: Some(64 * 1024 * 1024),
+                drop_policy: DropPolicy::Disconnect,
+            },
+            normal: MessageClassConfig {
+                bandwidth_budget: None,
+                max_queued_bytes: Some(64 * 1024 * 1024),
+                drop_policy: DropPolicy::DropNewest,
+            },
+            bulk: MessageClassConfig {
+                bandwidth_budget: None,
+                max_queued_bytes: Some(256 * 1024 * 1024),
+                drop_policy: DropPolicy::DropOldest,
+            },
+        }
+    }
+}
+
+impl SendQueueConfig {
+    fn class(&self, class: MessageClass) -> &MessageClassConfig {
+        match class {
+            MessageClass::Consensus => &self.consensus,
+            MessageClass::Normal => &self.normal,
+            MessageClass::Bulk => &self.bulk,
+        }
+    }
+}
+
+/// Encoded message waiting to be written to the connection.
+pub struct QueuedMessage {
+    /// Name of the message variant, for logging and metrics.
+    pub name: &'static str,
+    /// Serialized message, shared with the queues of other peers if the message is broadcast.
+    pub bytes: Arc<[u8]>,
+}
+
+struct ClassQueue {
+    config: MessageClassConfig,
+    messages: VecDeque<QueuedMessage>,
+    queued_bytes: u64,
+    /// Bytes which can be sent before the budget runs out. Can go negative, since a message is
+    /// sent as long as some budget is left.
+    allowance: f64,
+    last_refill: Instant,
+}
+
+impl ClassQueue {
+    fn refill(&mut self, now: Instant) {
+        if let Some(budget) = self.config.bandwidth_budget {
+            let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+            // At most one second worth of budget is accumulated.
+            self.allowance = (self.allowance + elapsed * budget as f64).min(budget as f64);
+        }
+        self.last_refill = now;
+    }
+
+    fn can_send(&self) -> bool {
+        !self.messages.is_empty()
+            && (self.config.bandwidth_budget.is_none() || self.allowance > 0.0)
+    }
+
+    fn pop_front(&mut self) -> Option<QueuedMessage> {
+        let message = self.messages.pop_front()?;
+        self.queued_bytes -= message.bytes.len() as u64;
+        Some(message)
+    }
+}
+
+/// Queues of the messages sent to a single peer.
+pub struct SendQueues {
+    queues: Vec<ClassQueue>,
+    /// Whether a message of a class with the `Disconnect` policy didn't fit into its queue.
+    overflowed: bool,
+}
+
+impl SendQueues {
+    pub fn new(config: &SendQueueConfig, now: Instant) -> Self {
+        let queues = MESSAGE_CLASSES
+            .iter()
+            .map(|class| {
+                let config = config.class(*class).clone();
+                let allowance = config.bandwidth_budget.unwrap_or(0) as f64;
+                ClassQueue {
+                    config,
+                    messages: VecDeque::new(),
+                    queued_bytes: 0,
+                    allowance,
+                    last_refill: now,
+                }
+            })
+            .collect();
+        SendQueues { queues, overflowed: false }
+    }
+
+    /// Queues the message. Returns the messages dropped to respect the queue size limit. If the
+    /// class disconnects once its queue is full, the new message is dropped and the queues are
+    /// marked as overflowed.
+    pub fn push(&mut self, class: MessageClass, message: QueuedMessage) -> Vec<QueuedMessage> {
+        let queue = &mut self.queues[class as usize];
+        let size = message.bytes.len() as u64;
+        let mut dropped = vec![];
+        if let Some(max_queued_bytes) = queue.config.max_queued_bytes {
+            if queue.queued_bytes + size > max_queued_bytes {
+                match queue.config.drop_policy {
+                    DropPolicy::DropNewest => return vec![message],
+                    DropPolicy::Disconnect => {
+                        self.overflowed = true;
+                        return vec![message];
+                    }
+                    DropPolicy::DropOldest => {
+                        while queue.queued_bytes + size > max_queued_bytes {
+                            match queue.pop_front() {
+                                Some(message) => dropped.push(message),
+                                None => break,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        queue.queued_bytes += size;
+        queue.messages.push_back(message);
+        dropped
+    }
+
+    /// Takes the next message to send: the oldest message of the highest priority class which
+    /// has budget left.
+    pub fn pop(&mut self, now: Instant) -> Option<QueuedMessage> {
+        let queue = self.queues.iter_mut().find(|queue| {
+            queue.refill(now);
+            queue.can_send()
+        })?;
+        let message = queue.pop_front()?;
+        queue.allowance -= message.bytes.len() as f64;
+        Some(message)
+    }
+
+    /// Takes all the queued messages in priority order, regardless of the budgets.
+    pub fn drain(&mut self) -> Vec<QueuedMessage> {
+        let mut messages = vec![];
+        for queue in self.queues.iter_mut() {
+            while let Some(message) = queue.pop_front() {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|queue| queue.messages.is_empty())
+    }
+
+    /// Whether the peer has to be disconnected, since a queue of a class with the `Disconnect`
+    /// policy overflowed.
+    pub fn is_overflowed(&self) -> bool {
+        self.overflowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn message(name: &'static str, size: usize) -> QueuedMessage {
+        QueuedMessage { name, bytes: vec![0; size].into() }
+    }
+
+    fn pop_names(queues: &mut SendQueues, now: Instant) -> Vec<&'static str> {
+        std::iter::from_fn(|| queues.pop(now)).map(|message| message.name).collect()
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let now = Instant::now();
+        let mut queues = SendQueues::new(&SendQueueConfig::default(), now);
+        queues.push(MessageClass::Bulk, message("state1", 10));
+        queues.push(MessageClass::Normal, message("headers", 10));
+        queues.push(MessageClass::Bulk, message("state2", 10));
+        queues.push(MessageClass::Consensus, message("approval", 10));
+        assert_eq!(pop_names(&mut queues, now), vec!["approval", "headers", "state1", "state2"]);
+        assert!(queues.is_empty());
+    }
+
+    #[test]
+    fn test_bandwidth_budget() {
+        let now = Instant::now();
+        let mut config = SendQueueConfig::default();
+        config.bulk.bandwidth_budget = Some(100);
+        let mut queues = SendQueues::new(&config, now);
+        for name in &["state1", "state2", "state3"] {
+            queues.push(MessageClass::Bulk, message(name, 60));
+        }
+        // The budget allows going over it with a single message.
+        assert_eq!(pop_names(&mut queues, now), vec!["state1", "state2"]);
+        queues.push(MessageClass::Normal, message("headers", 10));
+        assert_eq!(pop_names(&mut queues, now), vec!["headers"]);
+        assert_eq!(pop_names(&mut queues, now + Duration::from_millis(100)), Vec::<&str>::new());
+        assert_eq!(pop_names(&mut queues, now + Duration::from_millis(300)), vec!["state3"]);
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        let now = Instant::now();
+        let mut config = SendQueueConfig::default();
+        config.normal =
+            MessageClassConfig { max_queued_bytes: Some(25), ..MessageClassConfig::unlimited() };
+        config.bulk = MessageClassConfig {
+            max_queued_bytes: Some(25),
+            drop_policy: DropPolicy::DropOldest,
+            ..MessageClassConfig::unlimited()
+        };
+        let mut queues = SendQueues::new(&config, now);
+        let mut dropped = vec![];
+        for name in &["a", "b", "c"] {
+            dropped.extend(queues.push(MessageClass::Normal, message(name, 10)));
+            dropped.extend(queues.push(MessageClass::Bulk, message(name, 10)));
+        }
+        // The normal class drops the new message, the bulk one the oldest.
+        assert_eq!(dropped.iter().map(|message| message.name).collect::<Vec<_>>(), vec!["c", "a"]);
+        assert_eq!(pop_names(&mut queues, now), vec!["a", "b", "b", "c"]);
+    }
+
+    #[test]
+    fn test_default_limits() {
+        let config = SendQueueConfig::default();
+        for class in MESSAGE_CLASSES.iter() {
+            assert!(config.class(*class).max_queued_bytes.is_some());
+        }
+
+        let now = Instant::now();
+        let mut config = SendQueueConfig::default();
+        config.consensus.max_queued_bytes = Some(15);
+        let mut queues = SendQueues::new(&config, now);
+        assert!(queues.push(MessageClass::Consensus, message("block1", 10)).is_empty());
+        assert!(!queues.is_overflowed());
+        let dropped = queues.push(MessageClass::Consensus, message("block2", 10));
+        assert_eq!(dropped.iter().map(|message| message.name).collect::<Vec<_>>(), vec!["block2"]);
+        assert!(queues.is_overflowed());
+    }
+
+    #[test]
+    fn test_drain_ignores_budget() {
+        let now = Instant::now();
+        let mut config = SendQueueConfig::default();
+        config.bulk.bandwidth_budget = Some(1);
+        let mut queues = SendQueues::new(&config, now);
+        queues.push(MessageClass::Bulk, message("state1", 10));
+        queues.push(MessageClass::Bulk, message("state2", 10));
+        queues.push(MessageClass::Consensus, message("disconnect", 1));
+        let names = queues.drain().into_iter().map(|message| message.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["disconnect", "state1", "state2"]);
+    }
+}
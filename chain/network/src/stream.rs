@@ -0,0 +1,249 @@
+//! Multiplexing of large messages over a single peer connection.
+//!
+//! Messages larger than `STREAM_THRESHOLD_SIZE` are not written to the connection at once.
+//! Instead they are opened as a logical stream and sent as `PeerMessage::StreamFrame` chunks.
+//! Each stream has its own flow control window: the sender never has more than the window
+//! of unacknowledged bytes in flight and the receiver extends the window with
+//! `PeerMessage::StreamWindowUpdate` as it consumes frames. Frames of different streams are sent
+//! round-robin, and any other message is written right away, so a large state part transfer no
+//! longer blocks consensus messages behind it.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::codec::NETWORK_MESSAGE_MAX_SIZE;
+use crate::types::ReasonForBan;
+
+pub type StreamId = u32;
+
+/// Messages larger than this are sent over a stream.
+pub const STREAM_THRESHOLD_SIZE: usize = 1024 * 1024;
+/// Maximum size of the data in a single frame.
+pub const STREAM_FRAME_SIZE: usize = 64 * 1024;
+/// Number of bytes the sender can send on a new stream before receiving a window update.
+pub const INITIAL_STREAM_WINDOW: u32 = 1024 * 1024;
+/// Maximum number of streams a peer can have open towards us at the same time.
+pub const MAX_INCOMING_STREAMS: usize = 8;
+
+/// A chunk of a message sent over a stream.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct StreamFrame {
+    pub stream_id: StreamId,
+    /// Length of the whole message sent over the stream.
+    pub total_len: u32,
+    /// Position of `data` in the message.
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+struct OutgoingStream {
+    id: StreamId,
+    data: Arc<[u8]>,
+    offset: usize,
+    /// Number of bytes which can still be sent before the receiver extends the window.
+    window: usize,
+}
+
+/// Streams opened by this node towards the peer.
+#[derive(Default)]
+pub struct OutgoingStreams {
+    next_stream_id: StreamId,
+    /// Streams in the order they are served in.
+    streams: VecDeque<OutgoingStream>,
+}
+
+impl OutgoingStreams {
+    /// Opens a new stream which will send the given message.
+    pub fn open(&mut self, data: Arc<[u8]>) -> StreamId {
+        let id = self.next_stream_id;
+        self.next_stream_id = self.next_stream_id.wrapping_add(1);
+        self.streams.push_back(OutgoingStream {
+            id,
+            data,
+            offset: 0,
+            window: INITIAL_STREAM_WINDOW as usize,
+        });
+        id
+    }
+
+    /// Extends the window of the stream. Updates for finished streams are ignored.
+    pub fn update_window(&mut self, stream_id: StreamId, increment: u32) {
+        if let Some(stream) = self.streams.iter_mut().find(|stream| stream.id == stream_id) {
+            stream.window = stream.window.saturating_add(increment as usize);
+        }
+    }
+
+    /// Returns the next frame to send, taking streams round-robin.
+    /// Returns `None` if there is nothing to send or all the windows are exhausted.
+    pub fn next_frame(&mut self) -> Option<StreamFrame> {
+        for _ in 0..self.streams.len() {
+            let mut stream = self.streams.pop_front()?;
+            if stream.window == 0 {
+                self.streams.push_back(stream);
+                continue;
+            }
+            let len = std::cmp::min(
+                std::cmp::min(STREAM_FRAME_SIZE, stream.window),
+                stream.data.len() - stream.offset,
+            );
+            let frame = StreamFrame {
+                stream_id: stream.id,
+                total_len: stream.data.len() as u32,
+                offset: stream.offset as u32,
+                data: stream.data[stream.offset..stream.offset + len].to_vec(),
+            };
+            stream.offset += len;
+            stream.window -= len;
+            if stream.offset < stream.data.len() {
+                self.streams.push_back(stream);
+            }
+            return Some(frame);
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+}
+
+struct IncomingStream {
+    data: Vec<u8>,
+    total_len: usize,
+    /// Number of bytes the sender can still send.
+    window: usize,
+    /// Number of bytes received since the last window update.
+    unacknowledged: usize,
+}
+
+/// Result of receiving a frame.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReceivedFrame {
+    /// Window increment to send back to the peer.
+    pub window_update: Option<u32>,
+    /// The whole message, once the last frame of the stream was received.
+    pub message: Option<Vec<u8>>,
+}
+
+/// Streams opened by the peer towards this node.
+#[derive(Default)]
+pub struct IncomingStreams {
+    streams: HashMap<StreamId, IncomingStream>,
+}
+
+impl IncomingStreams {
+    /// Processes a frame received from the peer.
+    /// A peer which doesn't follow the protocol is considered abusive.
+    pub fn receive(&mut self, frame: StreamFrame) -> Result<ReceivedFrame, ReasonForBan> {
+        let total_len = frame.total_len as usize;
+        if total_len > NETWORK_MESSAGE_MAX_SIZE as usize || frame.data.is_empty() {
+            return Err(ReasonForBan::Abusive);
+        }
+        if !self.streams.contains_key(&frame.stream_id) {
+            if frame.offset != 0 || self.streams.len() >= MAX_INCOMING_STREAMS {
+                return Err(ReasonForBan::Abusive);
+            }
+            self.streams.insert(
+                frame.stream_id,
+                IncomingStream {
+                    data: Vec::with_capacity(std::cmp::min(total_len, STREAM_THRESHOLD_SIZE)),
+                    total_len,
+                    window: INITIAL_STREAM_WINDOW as usize,
+                    unacknowledged: 0,
+                },
+            );
+        }
+        let stream = self.streams.get_mut(&frame.stream_id).unwrap();
+        if stream.total_len != total_len
+            || stream.data.len() != frame.offset as usize
+            || frame.data.len() > stream.window
+            || stream.data.len() + frame.data.len() > stream.total_len
+        {
+            return Err(ReasonForBan::Abusive);
+        }
+        stream.window -= frame.data.len();
+        stream.unacknowledged += frame.data.len();
+        stream.data.extend_from_slice(&frame.data);
+
+        if stream.data.len() == stream.total_len {
+            let stream = self.streams.remove(&frame.stream_id).unwrap();
+            return Ok(ReceivedFrame { window_update: None, message: Some(stream.data) });
+        }
+        // Extend the window once half of it was consumed, so the sender is never stalled
+        // waiting for the update.
+        let mut window_update = None;
+        if stream.unacknowledged >= INITIAL_STREAM_WINDOW as usize / 2 {
+            window_update = Some(stream.unacknowledged as u32);
+            stream.window += stream.unacknowledged;
+            stream.unacknowledged = 0;
+        }
+        Ok(ReceivedFrame { window_update, message: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(outgoing: &mut OutgoingStreams, incoming: &mut IncomingStreams) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        while let Some(frame) = outgoing.next_frame() {
+            let stream_id = frame.stream_id;
+            let received = incoming.receive(frame).unwrap();
+            if let Some(increment) = received.window_update {
+                outgoing.update_window(stream_id, increment);
+            }
+            messages.extend(received.message);
+        }
+        messages
+    }
+
+    #[test]
+    fn test_streams_roundtrip() {
+        let mut outgoing = OutgoingStreams::default();
+        let mut incoming = IncomingStreams::default();
+        let first: Vec<u8> = (0..3 * STREAM_THRESHOLD_SIZE).map(|i| i as u8).collect();
+        let second = vec![7; STREAM_THRESHOLD_SIZE + 1];
+        outgoing.open(first.clone().into());
+        outgoing.open(second.clone().into());
+        let messages = transfer(&mut outgoing, &mut incoming);
+        // Frames are interleaved, so the smaller message is completed first.
+        assert_eq!(messages, vec![second, first]);
+        assert!(outgoing.is_empty());
+    }
+
+    #[test]
+    fn test_stream_window_exhausted() {
+        let mut outgoing = OutgoingStreams::default();
+        let mut incoming = IncomingStreams::default();
+        outgoing.open(vec![0; 2 * INITIAL_STREAM_WINDOW as usize].into());
+        let mut sent = 0;
+        while let Some(frame) = outgoing.next_frame() {
+            sent += frame.data.len();
+            assert!(incoming.receive(frame).unwrap().message.is_none());
+        }
+        // Without window updates the sender stops after the initial window.
+        assert_eq!(sent, INITIAL_STREAM_WINDOW as usize);
+        assert!(!outgoing.is_empty());
+    }
+
+    #[test]
+    fn test_stream_frame_over_window_is_abusive() {
+        let mut incoming = IncomingStreams::default();
+        let frame = StreamFrame {
+            stream_id: 0,
+            total_len: 2 * INITIAL_STREAM_WINDOW,
+            offset: 0,
+            data: vec![0; INITIAL_STREAM_WINDOW as usize + 1],
+        };
+        assert_eq!(incoming.receive(frame), Err(ReasonForBan::Abusive));
+    }
+
+    #[test]
+    fn test_stream_out_of_order_frame_is_abusive() {
+        let mut incoming = IncomingStreams::default();
+        let frame = StreamFrame { stream_id: 0, total_len: 10, offset: 5, data: vec![0; 5] };
+        assert_eq!(incoming.receive(frame), Err(ReasonForBan::Abusive));
+    }
+}
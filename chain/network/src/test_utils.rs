@@ -68,6 +68,9 @@ impl NetworkConfig {
             safe_set_size: 20,
             archival_peer_connections_lower_bound: 10,
             ban_window: Duration::from_secs(1),
+            reputation_ban_threshold: 100,
+            reputation_half_life: Duration::from_secs(60),
+            send_queue: Default::default(),
             peer_expiration_duration: Duration::from_secs(60 * 60),
             max_send_peers: 512,
             peer_stats_period: Duration::from_secs(5),
@@ -79,6 +82,10 @@ impl NetworkConfig {
             blacklist: HashMap::new(),
             outbound_disabled: false,
             archive: false,
+            use_relay: false,
+            max_relayed_peers: 0,
+            encryption: Default::default(),
+            max_mismatching_peers: 0,
         }
     }
 }
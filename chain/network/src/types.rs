@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{Into, TryFrom, TryInto};
 use std::fmt;
 use std::net::{AddrParseError, IpAddr, SocketAddr};
@@ -11,6 +11,7 @@ use actix::{Actor, Addr, MailboxError, Message, Recipient};
 use borsh::{BorshDeserialize, BorshSerialize};
 use chrono::{DateTime, Utc};
 use futures::{future::BoxFuture, FutureExt};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use strum::AsStaticStr;
 use tokio::net::TcpStream;
@@ -41,10 +42,19 @@ use near_primitives::version::{
 };
 use near_primitives::views::{FinalExecutionOutcomeView, QueryRequest, QueryResponse};
 
+use crate::codec::peer_message_to_bytes;
+use crate::event_log::DisconnectReason;
+use crate::handshake_mismatch::HandshakeMismatch;
+use crate::metrics;
+use crate::noise::ConnectionEncryption;
 use crate::peer::Peer;
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::MetricRecorder;
+use crate::reputation::PeerMisbehavior;
 use crate::routing::{Edge, EdgeInfo, RoutingTableInfo};
+use crate::send_queue::SendQueueConfig;
+#[cfg(feature = "protocol_feature_stream_multiplexing")]
+use crate::stream::{StreamFrame, StreamId};
 use std::fmt::{Debug, Error, Formatter};
 use std::io;
 
@@ -171,7 +181,7 @@ impl From<PeerChainInfo> for PeerChainInfoV2 {
 }
 
 /// Peer type.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum PeerType {
     /// Inbound session
     Inbound,
@@ -475,6 +485,9 @@ pub enum RoutedMessageBody {
     VersionedPartialEncodedChunk(PartialEncodedChunk),
     VersionedStateResponse(StateResponseInfo),
     PartialEncodedChunkForward(PartialEncodedChunkForwardMsg),
+    /// Asks a node in relay mode to connect to the author of the message.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    RelayConnect(PeerInfo),
 }
 
 impl From<PartialEncodedChunkWithArcReceipts> for RoutedMessageBody {
@@ -548,6 +561,8 @@ impl Debug for RoutedMessageBody {
             RoutedMessageBody::Ping(_) => write!(f, "Ping"),
             RoutedMessageBody::Pong(_) => write!(f, "Pong"),
             RoutedMessageBody::Unused => write!(f, "Unused"),
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            RoutedMessageBody::RelayConnect(peer_info) => write!(f, "RelayConnect({})", peer_info),
         }
     }
 }
@@ -768,6 +783,16 @@ pub enum PeerMessage {
     EpochSyncResponse(EpochSyncResponse),
     EpochSyncFinalizationRequest(EpochId),
     EpochSyncFinalizationResponse(EpochSyncFinalizationResponse),
+
+    /// A chunk of a large message sent over a logical stream of the connection.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    StreamFrame(StreamFrame),
+    /// Allows the peer to send more bytes on the given stream.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    StreamWindowUpdate(StreamId, u32),
+    /// Asks the peer to relay for us, sent by nodes which can't accept inbound connections.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    RelayRegister,
 }
 
 impl fmt::Display for PeerMessage {
@@ -777,7 +802,7 @@ impl fmt::Display for PeerMessage {
 }
 
 impl PeerMessage {
-    pub fn msg_variant(&self) -> &str {
+    pub fn msg_variant(&self) -> &'static str {
         match self {
             PeerMessage::Routed(routed_message) => {
                 strum::AsStaticRef::as_static(&routed_message.body)
@@ -866,6 +891,12 @@ pub struct NetworkConfig {
     pub archival_peer_connections_lower_bound: u32,
     /// Duration of the ban for misbehaving peers.
     pub ban_window: Duration,
+    /// Peers whose reputation score reaches this threshold are banned.
+    pub reputation_ban_threshold: u32,
+    /// Time after which the penalty for a misbehavior counts half.
+    pub reputation_half_life: Duration,
+    /// Budgets and limits of the per-peer queues of sent messages.
+    pub send_queue: SendQueueConfig,
     /// Remove expired peers.
     pub peer_expiration_duration: Duration,
     /// Maximum number of peer addresses we should ever send on PeersRequest.
@@ -896,6 +927,17 @@ pub struct NetworkConfig {
     pub outbound_disabled: bool,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Set for nodes which can't accept inbound connections, e.g. because they are behind NAT.
+    /// Such nodes register at a relay peer and connect to the peers asking them to.
+    pub use_relay: bool,
+    /// Maximum number of peers we relay for.
+    pub max_relayed_peers: u32,
+    /// Whether connections to peers are encrypted.
+    pub encryption: ConnectionEncryption,
+    /// Number of peers rejecting our handshake because of a different genesis or protocol version
+    /// after which the node stops, unless it connected to some peer before. Capped by the number
+    /// of boot nodes. 0 to never stop.
+    pub max_mismatching_peers: u32,
 }
 
 impl NetworkConfig {
@@ -1046,13 +1088,60 @@ pub struct SendMessage {
     pub message: PeerMessage,
 }
 
+/// Message sent to all the active peers. It's serialized once, by the first peer which actually
+/// sends it, and the bytes are shared by the send queues of all the peers.
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct BroadcastMessage {
+    pub message: PeerMessage,
+    bytes: OnceCell<Arc<[u8]>>,
+}
+
+impl BroadcastMessage {
+    pub fn new(message: PeerMessage) -> Self {
+        Self { message, bytes: OnceCell::new() }
+    }
+
+    /// Returns the serialized message, serializing it on the first call.
+    pub fn bytes(&self) -> Result<Arc<[u8]>, std::io::Error> {
+        let mut serialized = false;
+        let bytes = self.bytes.get_or_try_init(|| {
+            serialized = true;
+            peer_message_to_bytes(&self.message).map(Arc::from)
+        })?;
+        if serialized {
+            near_metrics::inc_counter(&metrics::BROADCAST_MESSAGE_SERIALIZATIONS);
+        } else {
+            near_metrics::inc_counter(&metrics::BROADCAST_MESSAGE_SERIALIZATIONS_REUSED);
+        }
+        Ok(Arc::clone(bytes))
+    }
+}
+
+/// Socket addresses of both ends of a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionAddrs {
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+}
+
+impl ConnectionAddrs {
+    /// Whether the other connection runs between different hosts, which happens when one of the
+    /// nodes changed its IP, while a connection between the same hosts is a duplicate.
+    pub fn is_migrated_to(&self, other: &ConnectionAddrs) -> bool {
+        self.local.ip() != other.local.ip() || self.remote.ip() != other.remote.ip()
+    }
+}
+
 /// Actor message to consolidate potential new peer.
 /// Returns if connection should be kept or dropped.
 pub struct Consolidate {
     pub actor: Addr<Peer>,
+    pub connection: ConnectionAddrs,
     pub peer_info: PeerInfo,
     pub peer_type: PeerType,
     pub chain_info: PeerChainInfoV2,
+    pub protocol_version: ProtocolVersion,
     // Edge information from this node.
     // If this is None it implies we are outbound connection, so we need to create our
     // EdgeInfo part and send it to the other peer.
@@ -1076,9 +1165,12 @@ pub enum ConsolidateResponse {
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Unregister {
+    /// Actor of the closed connection, which may have been replaced in the meantime.
+    pub actor: Addr<Peer>,
     pub peer_id: PeerId,
     pub peer_type: PeerType,
     pub remove_from_peer_store: bool,
+    pub reason: DisconnectReason,
 }
 
 pub struct PeerList {
@@ -1092,6 +1184,11 @@ pub enum PeerRequest {
     RouteBack(Box<RoutedMessageBody>, CryptoHash),
     UpdatePeerInfo(PeerInfo),
     ReceivedMessage(PeerId, Instant),
+    /// The peer rejected our handshake because it is on another network.
+    HandshakeMismatch(PeerInfo, Vec<HandshakeMismatch>),
+    /// The peer asks us to relay for it.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    RelayRegister(PeerId),
 }
 
 impl Message for PeerRequest {
@@ -1147,6 +1244,7 @@ pub enum ReasonForBan {
     EpochSyncNoResponse = 11,
     EpochSyncInvalidResponse = 12,
     EpochSyncInvalidFinalizationResponse = 13,
+    LowReputation = 14,
 }
 
 /// Banning signal sent from Peer instance to PeerManager
@@ -1158,6 +1256,15 @@ pub struct Ban {
     pub ban_reason: ReasonForBan,
 }
 
+/// Misbehavior of a peer, sent from Peer instance to PeerManager, which bans the peer once its
+/// reputation gets too low.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReportPeer {
+    pub peer_id: PeerId,
+    pub misbehavior: PeerMisbehavior,
+}
+
 // TODO(#1313): Use Box
 #[derive(Debug, Clone, PartialEq, strum::AsRefStr)]
 #[allow(clippy::large_enum_variant)]
@@ -1211,6 +1318,15 @@ pub enum NetworkRequests {
         peer_id: PeerId,
         ban_reason: ReasonForBan,
     },
+    /// Report misbehavior of the peer, which lowers its reputation.
+    ReportPeer {
+        peer_id: PeerId,
+        misbehavior: PeerMisbehavior,
+    },
+    /// Drop the connection to given peer, it is re-established when peers are monitored.
+    DisconnectPeer {
+        peer_id: PeerId,
+    },
     /// Announce account
     AnnounceAccount(AnnounceAccount),
 
@@ -1296,6 +1412,54 @@ pub struct FullPeerInfo {
     pub edge_info: EdgeInfo,
 }
 
+/// Number and total size of the messages of one type.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageStats {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Messages exchanged with a peer since the connection was established, per message type.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerTrafficStats {
+    pub sent: BTreeMap<String, MessageStats>,
+    pub received: BTreeMap<String, MessageStats>,
+}
+
+impl PeerTrafficStats {
+    pub fn record_sent(&mut self, message_type: &str, bytes: usize) {
+        Self::record(&mut self.sent, message_type, bytes);
+    }
+
+    pub fn record_received(&mut self, message_type: &str, bytes: usize) {
+        Self::record(&mut self.received, message_type, bytes);
+    }
+
+    fn record(stats: &mut BTreeMap<String, MessageStats>, message_type: &str, bytes: usize) {
+        let entry = match stats.get_mut(message_type) {
+            Some(entry) => entry,
+            None => stats.entry(message_type.to_string()).or_default(),
+        };
+        entry.messages += 1;
+        entry.bytes += bytes as u64;
+    }
+
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent.values().map(|stats| stats.bytes).sum()
+    }
+
+    pub fn received_bytes(&self) -> u64 {
+        self.received.values().map(|stats| stats.bytes).sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeerTraffic {
+    pub peer_id: PeerId,
+    #[serde(flatten)]
+    pub stats: PeerTrafficStats,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct KnownProducer {
     pub account_id: AccountId,
@@ -1313,6 +1477,8 @@ pub struct NetworkInfo {
     pub received_bytes_per_sec: u64,
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
+    /// Messages exchanged with each of the active peers.
+    pub peer_traffic: Vec<PeerTraffic>,
     #[cfg(feature = "metric_recorder")]
     pub metric_recorder: MetricRecorder,
     pub peer_counter: usize,
@@ -1611,6 +1777,8 @@ pub struct PeerStatsResult {
     pub is_abusive: bool,
     /// Counts of incoming/outgoing messages from given peer.
     pub message_counts: (u64, u64),
+    /// Messages exchanged with the peer per message type.
+    pub traffic: PeerTrafficStats,
 }
 
 impl<A, M> MessageResponse<A, M> for PeerStatsResult
@@ -1739,6 +1907,46 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_peer_traffic_stats() {
+        let mut traffic = PeerTrafficStats::default();
+        traffic.record_sent("Block", 100);
+        traffic.record_sent("Block", 50);
+        traffic.record_sent("BlockApproval", 10);
+        traffic.record_received("Block", 20);
+        assert_eq!(traffic.sent["Block"], MessageStats { messages: 2, bytes: 150 });
+        assert_eq!(traffic.sent_bytes(), 160);
+        assert_eq!(traffic.received_bytes(), 20);
+        assert!(!traffic.received.contains_key("BlockApproval"));
+    }
+
+    #[test]
+    fn test_broadcast_message_serialized_once() {
+        let message = BroadcastMessage::new(PeerMessage::PeersRequest);
+        let bytes = message.bytes().unwrap();
+        assert_eq!(bytes.as_ref(), &peer_message_to_bytes(&message.message).unwrap()[..]);
+        // Every peer gets the same bytes.
+        assert!(Arc::ptr_eq(&bytes, &message.bytes().unwrap()));
+    }
+
+    #[test]
+    fn test_connection_migration() {
+        let connection = ConnectionAddrs {
+            local: "10.0.0.1:24567".parse().unwrap(),
+            remote: "10.0.0.2:40000".parse().unwrap(),
+        };
+        // Reconnecting from another port of the same host is a duplicate connection.
+        let reconnected =
+            ConnectionAddrs { remote: "10.0.0.2:40001".parse().unwrap(), ..connection };
+        assert!(!connection.is_migrated_to(&reconnected));
+        let remote_moved =
+            ConnectionAddrs { remote: "10.0.0.3:40000".parse().unwrap(), ..connection };
+        assert!(connection.is_migrated_to(&remote_moved));
+        let local_moved =
+            ConnectionAddrs { local: "10.0.0.4:24567".parse().unwrap(), ..connection };
+        assert!(connection.is_migrated_to(&local_moved));
+    }
+
     const ALLOWED_SIZE: usize = 1 << 20;
     const NOTIFY_SIZE: usize = 1024;
 
@@ -1,16 +1,31 @@
+#[macro_use]
+extern crate lazy_static;
+
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
+use crate::parked::ParkedTransactions;
 use crate::types::{PoolIterator, PoolKey, TransactionGroup};
 use borsh::BorshSerialize;
 use near_crypto::PublicKey;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, BlockHeight, Gas, Nonce};
 use rand::RngCore;
 use std::ops::Bound;
 
+mod metrics;
+pub mod parked;
 pub mod types;
 
+/// Whether `new` may replace `old`, a pending transaction with the same access key and nonce,
+/// which is the case if it attaches more gas.
+pub(crate) fn is_better_replacement(new: &SignedTransaction, old: &SignedTransaction) -> bool {
+    fn prepaid_gas(tx: &SignedTransaction) -> Gas {
+        tx.transaction.actions.iter().map(|action| action.get_prepaid_gas()).sum()
+    }
+    prepaid_gas(new) > prepaid_gas(old)
+}
+
 /// Transaction pool: keeps track of transactions that were not yet accepted into the block chain.
 pub struct TransactionPool {
     /// Transactions are grouped by a pair of (account ID, signer public key).
@@ -19,10 +34,14 @@ pub struct TransactionPool {
     pub transactions: BTreeMap<PoolKey, Vec<SignedTransaction>>,
     /// Set of all hashes to quickly check if the given transaction is in the pool.
     pub unique_transactions: HashSet<CryptoHash>,
+    /// Total serialized size of the transactions in the pool, not counting the parked ones.
+    transactions_size: u64,
     /// A uniquely generated key seed to randomize PoolKey order.
     key_seed: Vec<u8>,
     /// The key after which the pool iterator starts. Doesn't have to be present in the pool.
     last_used_key: PoolKey,
+    /// Transactions waiting for a nonce gap to close. They are not visible to the pool iterator.
+    parked: ParkedTransactions,
 }
 
 impl TransactionPool {
@@ -31,7 +50,9 @@ impl TransactionPool {
             key_seed: rand::thread_rng().next_u64().to_le_bytes().to_vec(),
             transactions: BTreeMap::new(),
             unique_transactions: HashSet::new(),
+            transactions_size: 0,
             last_used_key: CryptoHash::default(),
+            parked: ParkedTransactions::default(),
         }
     }
 
@@ -47,6 +68,7 @@ impl TransactionPool {
         if !self.unique_transactions.insert(signed_transaction.get_hash()) {
             return false;
         }
+        self.transactions_size += signed_transaction.get_size();
         let signer_id = &signed_transaction.transaction.signer_id;
         let signer_public_key = &signed_transaction.transaction.public_key;
         self.transactions
@@ -56,6 +78,86 @@ impl TransactionPool {
         true
     }
 
+    /// Insert a signed transaction that passed validation, given the nonce of its access key on
+    /// chain. If there is a gap between the nonce of the transaction and the next nonce of the
+    /// access key, which the transactions in the pool don't fill, the transaction is parked until
+    /// the gap closes.
+    /// With `replace_by_nonce`, a pending transaction with the same access key and nonce is
+    /// replaced if the new one attaches more gas, and the new one is rejected otherwise. Without
+    /// it, both are kept and at most one of them gets included.
+    /// `block_height` is the height of the head, parked transactions expire some blocks later.
+    pub fn insert_transaction_with_nonce(
+        &mut self,
+        signed_transaction: SignedTransaction,
+        on_chain_nonce: Nonce,
+        replace_by_nonce: bool,
+        block_height: BlockHeight,
+    ) -> bool {
+        let tx_hash = signed_transaction.get_hash();
+        if self.unique_transactions.contains(&tx_hash) || self.parked.contains(&tx_hash) {
+            return false;
+        }
+        let key = self.key(
+            &signed_transaction.transaction.signer_id,
+            &signed_transaction.transaction.public_key,
+        );
+        self.parked.observe_nonce(&key, on_chain_nonce);
+        if replace_by_nonce {
+            let nonce = signed_transaction.transaction.nonce;
+            let pending = self.transactions.get_mut(&key).and_then(|transactions| {
+                transactions.iter_mut().find(|tx| tx.transaction.nonce == nonce)
+            });
+            if let Some(pending) = pending {
+                if !is_better_replacement(&signed_transaction, pending) {
+                    return false;
+                }
+                let size = signed_transaction.get_size();
+                let replaced = std::mem::replace(pending, signed_transaction);
+                self.unique_transactions.remove(&replaced.get_hash());
+                self.unique_transactions.insert(tx_hash);
+                self.transactions_size = self.transactions_size - replaced.get_size() + size;
+                near_metrics::inc_counter(&metrics::TRANSACTIONS_REPLACED_TOTAL);
+                return true;
+            }
+        }
+        let pending_nonces = self.pending_nonces(&key);
+        let mut next_nonce = on_chain_nonce.saturating_add(1);
+        while pending_nonces.contains(&next_nonce) {
+            next_nonce = match next_nonce.checked_add(1) {
+                Some(nonce) => nonce,
+                None => break,
+            };
+        }
+        if signed_transaction.transaction.nonce > next_nonce {
+            return self.parked.park(
+                key,
+                signed_transaction,
+                on_chain_nonce,
+                replace_by_nonce,
+                block_height,
+            );
+        }
+        self.insert_transaction(signed_transaction);
+        self.promote_parked_transactions(&key);
+        true
+    }
+
+    /// Nonces of the transactions in the pool with the given key.
+    fn pending_nonces(&self, key: &PoolKey) -> HashSet<Nonce> {
+        self.transactions
+            .get(key)
+            .map(|transactions| transactions.iter().map(|tx| tx.transaction.nonce).collect())
+            .unwrap_or_default()
+    }
+
+    /// Moves parked transactions with the given key, whose nonce gap is closed, into the pool.
+    fn promote_parked_transactions(&mut self, key: &PoolKey) {
+        let pending_nonces = self.pending_nonces(key);
+        for tx in self.parked.promote(key, &pending_nonces) {
+            self.insert_transaction(tx);
+        }
+    }
+
     /// Returns a pool iterator wrapper that implements an iterator like trait to iterate over
     /// transaction groups in the proper order defined by the protocol.
     /// When the iterator is dropped, all remaining groups are inserted back into the pool.
@@ -65,22 +167,31 @@ impl TransactionPool {
 
     /// Quick reconciliation step - evict all transactions that already in the block
     /// or became invalid after it.
+    /// The included transactions advance the nonces of their access keys, which may close the
+    /// gap for parked transactions.
     pub fn remove_transactions(&mut self, transactions: &[SignedTransaction]) {
         let mut grouped_transactions = HashMap::new();
+        let mut included_nonces = HashMap::new();
         for tx in transactions {
+            let key = self.key(&tx.transaction.signer_id, &tx.transaction.public_key);
+            let nonce = included_nonces.entry(key).or_insert(tx.transaction.nonce);
+            *nonce = std::cmp::max(*nonce, tx.transaction.nonce);
             if self.unique_transactions.contains(&tx.get_hash()) {
-                let signer_id = &tx.transaction.signer_id;
-                let signer_public_key = &tx.transaction.public_key;
-                grouped_transactions
-                    .entry(self.key(signer_id, signer_public_key))
-                    .or_insert_with(HashSet::new)
-                    .insert(tx.get_hash());
+                grouped_transactions.entry(key).or_insert_with(HashSet::new).insert(tx.get_hash());
             }
         }
         for (key, hashes) in grouped_transactions {
             let mut remove_entry = false;
             if let Some(v) = self.transactions.get_mut(&key) {
-                v.retain(|tx| !hashes.contains(&tx.get_hash()));
+                let mut removed_size = 0;
+                v.retain(|tx| {
+                    let retain = !hashes.contains(&tx.get_hash());
+                    if !retain {
+                        removed_size += tx.get_size();
+                    }
+                    retain
+                });
+                self.transactions_size -= removed_size;
                 remove_entry = v.is_empty();
             }
             if remove_entry {
@@ -90,13 +201,33 @@ impl TransactionPool {
                 self.unique_transactions.remove(&hash);
             }
         }
+        for (key, nonce) in included_nonces {
+            self.parked.observe_nonce(&key, nonce);
+            self.promote_parked_transactions(&key);
+        }
     }
 
     /// Reintroduce transactions back during the chain reorg
+    /// The nonces of their access keys on chain go back to before them, and the parked
+    /// transactions of these access keys are checked again against the pool.
     pub fn reintroduce_transactions(&mut self, transactions: Vec<SignedTransaction>) {
+        let mut reverted_nonces = HashMap::new();
         for tx in transactions {
+            let key = self.key(&tx.transaction.signer_id, &tx.transaction.public_key);
+            let nonce = reverted_nonces.entry(key).or_insert(tx.transaction.nonce);
+            *nonce = std::cmp::min(*nonce, tx.transaction.nonce);
             self.insert_transaction(tx);
         }
+        for (key, nonce) in reverted_nonces {
+            self.parked.rewind_nonce(&key, nonce.saturating_sub(1));
+            self.promote_parked_transactions(&key);
+        }
+    }
+
+    /// Drops the parked transactions which expire at the given block height, the height of the
+    /// new head.
+    pub fn expire_parked_transactions(&mut self, block_height: BlockHeight) {
+        self.parked.expire(block_height);
     }
 
     pub fn len(&self) -> usize {
@@ -106,6 +237,16 @@ impl TransactionPool {
     pub fn is_empty(&self) -> bool {
         self.unique_transactions.is_empty()
     }
+
+    /// Total serialized size of the transactions in the pool, in bytes.
+    pub fn transactions_size(&self) -> u64 {
+        self.transactions_size
+    }
+
+    /// Number of transactions waiting for a nonce gap to close.
+    pub fn parked_len(&self) -> usize {
+        self.parked.len()
+    }
 }
 
 /// PoolIterator is a structure to pull transactions from the pool.
@@ -133,7 +274,7 @@ impl<'a> PoolIteratorWrapper<'a> {
 /// If the pool is empty, the iterator gets the group from the front of the sorted groups queue.
 ///
 /// If this group is empty (no transactions left inside), then the iterator discards it and
-/// updates `unique_transactions` in the pool. Then gets the next one.
+/// updates `unique_transactions` and the size of the transactions in the pool. Then gets the next one.
 ///
 /// Once a non-empty group is found, this group is pushed to the back of the sorted groups queue
 /// and the iterator returns a mutable reference to this group.
@@ -166,6 +307,7 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
                 key,
                 transactions,
                 removed_transaction_hashes: vec![],
+                removed_transaction_size: 0,
             });
             Some(self.sorted_groups.back_mut().expect("just pushed"))
         } else {
@@ -174,6 +316,7 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
                     for hash in sorted_group.removed_transaction_hashes {
                         self.pool.unique_transactions.remove(&hash);
                     }
+                    self.pool.transactions_size -= sorted_group.removed_transaction_size;
                 } else {
                     self.sorted_groups.push_back(sorted_group);
                     return Some(self.sorted_groups.back_mut().expect("just pushed"));
@@ -193,6 +336,7 @@ impl<'a> Drop for PoolIteratorWrapper<'a> {
             for hash in group.removed_transaction_hashes {
                 self.pool.unique_transactions.remove(&hash);
             }
+            self.pool.transactions_size -= group.removed_transaction_size;
             if !group.transactions.is_empty() {
                 self.pool.transactions.insert(group.key, group.transactions);
             }
@@ -450,4 +594,224 @@ mod tests {
         new_nonces.sort();
         assert_ne!(nonces, new_nonces);
     }
+
+    /// Transactions with a nonce gap are parked until the gap is filled by the pool.
+    #[test]
+    fn test_parked_transaction_promoted_when_gap_filled() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(transactions[2].clone(), 0, false, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (0, 1));
+        assert!(pool.insert_transaction_with_nonce(transactions[0].clone(), 0, false, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (1, 1));
+        assert!(pool.insert_transaction_with_nonce(transactions[1].clone(), 0, false, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (3, 0));
+        let nonces: Vec<u64> =
+            prepare_transactions(&mut pool, 10).iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces, vec![1, 2, 3]);
+    }
+
+    /// Parked transactions are promoted once the nonce advances on chain, and dropped once
+    /// the nonce on chain passes them.
+    #[test]
+    fn test_parked_transaction_promoted_by_on_chain_nonce() {
+        let included = generate_transactions("alice.near", "alice.near", 1, 4);
+        let parked = generate_transactions("alice.near", "alice.near", 5, 7);
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(parked[0].clone(), 0, false, 0));
+        assert!(pool.insert_transaction_with_nonce(parked[2].clone(), 0, false, 0));
+        assert_eq!(pool.parked_len(), 2);
+
+        pool.remove_transactions(&included[..2]);
+        assert_eq!((pool.len(), pool.parked_len()), (0, 2));
+        pool.remove_transactions(&included[2..]);
+        assert_eq!((pool.len(), pool.parked_len()), (1, 1));
+
+        pool.remove_transactions(&parked[..2]);
+        assert_eq!((pool.len(), pool.parked_len()), (1, 0));
+
+        let stale = generate_transactions("alice.near", "alice.near", 10, 11);
+        assert!(pool.insert_transaction_with_nonce(stale[0].clone(), 6, false, 0));
+        assert_eq!(pool.parked_len(), 1);
+        pool.remove_transactions(&stale[1..]);
+        assert_eq!(pool.parked_len(), 0);
+    }
+
+    #[test]
+    fn test_parked_transactions_limit() {
+        let transactions = generate_transactions(
+            "alice.near",
+            "alice.near",
+            2,
+            2 + parked::MAX_PARKED_TRANSACTIONS_PER_KEY as u64,
+        );
+        let mut pool = TransactionPool::new();
+        for tx in transactions[..parked::MAX_PARKED_TRANSACTIONS_PER_KEY].iter() {
+            assert!(pool.insert_transaction_with_nonce(tx.clone(), 0, false, 0));
+        }
+        assert!(!pool.insert_transaction_with_nonce(
+            transactions[parked::MAX_PARKED_TRANSACTIONS_PER_KEY].clone(),
+            0,
+            false
+        ));
+        assert_eq!(pool.parked_len(), parked::MAX_PARKED_TRANSACTIONS_PER_KEY);
+        // Transactions without a gap are not limited.
+        let next = generate_transactions("alice.near", "alice.near", 1, 1);
+        assert!(pool.insert_transaction_with_nonce(next[0].clone(), 0, false, 0));
+        assert_eq!(pool.parked_len(), 0);
+        assert_eq!(pool.len(), parked::MAX_PARKED_TRANSACTIONS_PER_KEY + 1);
+    }
+
+    /// Parked transactions are dropped once they stay parked for too long.
+    #[test]
+    fn test_parked_transactions_expire() {
+        let transactions = generate_transactions("alice.near", "alice.near", 3, 4);
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(transactions[0].clone(), 0, false, 10));
+        assert!(pool.insert_transaction_with_nonce(transactions[1].clone(), 0, false, 20));
+        pool.expire_parked_transactions(10 + parked::PARKED_TRANSACTION_TTL - 1);
+        assert_eq!(pool.parked_len(), 2);
+        pool.expire_parked_transactions(10 + parked::PARKED_TRANSACTION_TTL);
+        assert_eq!(pool.parked_len(), 1);
+        pool.expire_parked_transactions(20 + parked::PARKED_TRANSACTION_TTL);
+        assert_eq!((pool.len(), pool.parked_len()), (0, 0));
+        // The transaction can be parked again afterwards.
+        assert!(pool.insert_transaction_with_nonce(transactions[0].clone(), 0, false, 1000));
+        assert_eq!(pool.parked_len(), 1);
+    }
+
+    /// Parked transactions are checked again when a reorg reintroduces transactions of their
+    /// access key.
+    #[test]
+    fn test_parked_transactions_after_reorg() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 4);
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(transactions[3].clone(), 0, false, 0));
+        // A fork includes the first transaction, then a reorg reverts it.
+        pool.remove_transactions(&transactions[..1]);
+        pool.reintroduce_transactions(transactions[..1].to_vec());
+        assert_eq!((pool.len(), pool.parked_len()), (1, 1));
+        assert!(pool.insert_transaction_with_nonce(transactions[2].clone(), 0, false, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (1, 2));
+        // The reintroduced transaction and the new one close the gap.
+        assert!(pool.insert_transaction_with_nonce(transactions[1].clone(), 0, false, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (4, 0));
+    }
+
+    fn total_size(transactions: &[SignedTransaction]) -> u64 {
+        transactions.iter().map(|tx| tx.get_size()).sum()
+    }
+
+    /// Test the size of the transactions in the pool follows insertions, removals and the
+    /// transactions pulled by the pool iterator.
+    #[test]
+    fn test_transactions_size() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 10);
+        let mut pool = TransactionPool::new();
+        for tx in transactions.iter() {
+            pool.insert_transaction(tx.clone());
+        }
+        // Duplicates are not counted.
+        pool.insert_transaction(transactions[0].clone());
+        assert_eq!(pool.transactions_size(), total_size(&transactions));
+
+        pool.remove_transactions(&transactions[..2]);
+        assert_eq!(pool.transactions_size(), total_size(&transactions[2..]));
+
+        let pulled = prepare_transactions(&mut pool, 3);
+        assert_eq!(pulled, transactions[2..5].to_vec());
+        assert_eq!(pool.transactions_size(), total_size(&transactions[5..]));
+
+        prepare_transactions(&mut pool, 10);
+        assert_eq!(pool.transactions_size(), 0);
+        assert!(pool.is_empty());
+    }
+
+    /// Test a peeked transaction stays in the pool.
+    #[test]
+    fn test_pool_iterator_peek() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 2);
+        let (_, mut pool) = process_txs_to_nonces(transactions.clone(), 0);
+        {
+            let mut pool_iter = pool.pool_iterator();
+            let group = pool_iter.next().unwrap();
+            assert_eq!(group.peek(), Some(&transactions[0]));
+            assert_eq!(group.next(), Some(transactions[0].clone()));
+            assert_eq!(group.peek(), Some(&transactions[1]));
+        }
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.transactions_size(), transactions[1].get_size());
+    }
+
+    fn function_call(nonce: Nonce, gas: Gas) -> SignedTransaction {
+        let signer = InMemorySigner::from_seed("alice.near", KeyType::ED25519, "alice.near");
+        SignedTransaction::call(
+            nonce,
+            "alice.near".to_string(),
+            "bob.near".to_string(),
+            &signer,
+            0,
+            "method".to_string(),
+            vec![],
+            gas,
+            CryptoHash::default(),
+        )
+    }
+
+    /// Test a pending transaction is replaced by one with the same nonce attaching more gas.
+    #[test]
+    fn test_replace_by_nonce() {
+        let mut pool = TransactionPool::new();
+        let (cheap, expensive) = (function_call(1, 100), function_call(1, 200));
+        assert!(pool.insert_transaction_with_nonce(expensive.clone(), 0, true, 0));
+        assert!(!pool.insert_transaction_with_nonce(cheap.clone(), 0, true, 0));
+        assert_eq!(pool.len(), 1);
+
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(cheap.clone(), 0, true, 0));
+        assert!(pool.insert_transaction_with_nonce(expensive.clone(), 0, true, 0));
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.transactions_size(), expensive.get_size());
+        assert_eq!(prepare_transactions(&mut pool, 10), vec![expensive.clone()]);
+
+        // Without replacement both transactions are kept.
+        let mut pool = TransactionPool::new();
+        assert!(pool.insert_transaction_with_nonce(cheap.clone(), 0, false, 0));
+        assert!(pool.insert_transaction_with_nonce(expensive, 0, false, 0));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_replace_parked_by_nonce() {
+        let mut pool = TransactionPool::new();
+        let (cheap, expensive) = (function_call(3, 100), function_call(3, 200));
+        assert!(pool.insert_transaction_with_nonce(cheap.clone(), 0, true, 0));
+        assert!(!pool.insert_transaction_with_nonce(cheap, 0, true, 0));
+        assert!(pool.insert_transaction_with_nonce(expensive.clone(), 0, true, 0));
+        assert_eq!(pool.parked_len(), 1);
+        assert!(pool.insert_transaction_with_nonce(function_call(1, 0), 0, true, 0));
+        assert!(pool.insert_transaction_with_nonce(function_call(2, 0), 0, true, 0));
+        assert_eq!((pool.len(), pool.parked_len()), (3, 0));
+        assert_eq!(prepare_transactions(&mut pool, 10)[2], expensive);
+    }
+
+    /// The search for the next nonce stops at the largest one.
+    #[test]
+    fn test_pending_max_nonce() {
+        let mut pool = TransactionPool::new();
+        let on_chain_nonce = Nonce::MAX - 1;
+        assert!(pool.insert_transaction_with_nonce(
+            function_call(Nonce::MAX, 100),
+            on_chain_nonce,
+            false,
+            0
+        ));
+        assert!(pool.insert_transaction_with_nonce(
+            function_call(Nonce::MAX, 200),
+            on_chain_nonce,
+            false,
+            0
+        ));
+        assert_eq!((pool.len(), pool.parked_len()), (2, 0));
+    }
 }
@@ -0,0 +1,33 @@
+use near_metrics::{try_create_int_counter, try_create_int_gauge, IntCounter, IntGauge};
+
+lazy_static! {
+    pub static ref PARKED_TRANSACTIONS: near_metrics::Result<IntGauge> = try_create_int_gauge(
+        "near_pool_parked_transactions",
+        "Number of transactions parked in the pools because of a nonce gap"
+    );
+    pub static ref PARKED_TRANSACTIONS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_pool_parked_transactions_total",
+            "Total number of transactions parked because of a nonce gap"
+        );
+    pub static ref PARKED_TRANSACTIONS_PROMOTED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_pool_parked_transactions_promoted_total",
+            "Total number of parked transactions moved to the pool once the nonce gap closed"
+        );
+    pub static ref PARKED_TRANSACTIONS_REJECTED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_pool_parked_transactions_rejected_total",
+            "Total number of transactions with a nonce gap rejected because of the parking limits"
+        );
+    pub static ref PARKED_TRANSACTIONS_EXPIRED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_pool_parked_transactions_expired_total",
+            "Total number of parked transactions dropped because the nonce gap didn't close in time"
+        );
+    pub static ref TRANSACTIONS_REPLACED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_pool_transactions_replaced_total",
+            "Total number of pending transactions replaced by a transaction with the same nonce"
+        );
+}
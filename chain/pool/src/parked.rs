@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{BlockHeight, BlockHeightDelta, Nonce};
+
+use crate::types::PoolKey;
+use crate::{is_better_replacement, metrics};
+
+/// Maximum number of transactions parked for a single access key.
+pub const MAX_PARKED_TRANSACTIONS_PER_KEY: usize = 16;
+/// Maximum number of transactions parked in a pool.
+pub const MAX_PARKED_TRANSACTIONS: usize = 10_000;
+/// Number of blocks a transaction stays parked at most, unless its `ValidUntil` height comes
+/// earlier.
+pub const PARKED_TRANSACTION_TTL: BlockHeightDelta = 600;
+
+struct ParkedTransaction {
+    transaction: SignedTransaction,
+    /// The transaction is dropped once a block at this height or above is accepted.
+    expires_at: BlockHeight,
+}
+
+struct ParkedGroup {
+    /// The largest nonce of the access key observed on chain.
+    on_chain_nonce: Nonce,
+    transactions: BTreeMap<Nonce, ParkedTransaction>,
+}
+
+/// Transactions with a nonce gap: their nonce is larger than the next nonce of the access key,
+/// and the transactions in between are neither on chain nor in the pool. Parked transactions
+/// are not offered to chunk producers until the gap closes.
+#[derive(Default)]
+pub(crate) struct ParkedTransactions {
+    groups: HashMap<PoolKey, ParkedGroup>,
+    unique_transactions: HashSet<CryptoHash>,
+}
+
+impl ParkedTransactions {
+    pub fn contains(&self, hash: &CryptoHash) -> bool {
+        self.unique_transactions.contains(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.unique_transactions.len()
+    }
+
+    /// Parks the transaction. Returns false if the transaction was not parked, because it is
+    /// already parked, there is another parked transaction with the same nonce which it doesn't
+    /// replace, or the limits are reached. With `replace_by_nonce`, a parked transaction with the
+    /// same nonce is replaced if the new one attaches more gas. `block_height` is the height of
+    /// the head, the transaction expires `PARKED_TRANSACTION_TTL` blocks later.
+    pub fn park(
+        &mut self,
+        key: PoolKey,
+        signed_transaction: SignedTransaction,
+        on_chain_nonce: Nonce,
+        replace_by_nonce: bool,
+        block_height: BlockHeight,
+    ) -> bool {
+        let nonce = signed_transaction.transaction.nonce;
+        let hash = signed_transaction.get_hash();
+        let group = self.groups.get(&key);
+        let parked = group.and_then(|group| group.transactions.get(&nonce));
+        let replaced_hash = match parked {
+            Some(parked)
+                if replace_by_nonce
+                    && is_better_replacement(&signed_transaction, &parked.transaction) =>
+            {
+                Some(parked.transaction.get_hash())
+            }
+            _ => None,
+        };
+        let rejected = match parked {
+            Some(_) => replaced_hash.is_none(),
+            None => {
+                group.map_or(false, |group| {
+                    group.transactions.len() >= MAX_PARKED_TRANSACTIONS_PER_KEY
+                }) || self.unique_transactions.len() >= MAX_PARKED_TRANSACTIONS
+            }
+        };
+        if rejected || self.unique_transactions.contains(&hash) {
+            near_metrics::inc_counter(&metrics::PARKED_TRANSACTIONS_REJECTED_TOTAL);
+            return false;
+        }
+        if let Some(replaced_hash) = replaced_hash {
+            self.unique_transactions.remove(&replaced_hash);
+            near_metrics::dec_gauge(&metrics::PARKED_TRANSACTIONS);
+            near_metrics::inc_counter(&metrics::TRANSACTIONS_REPLACED_TOTAL);
+        }
+        let group = self
+            .groups
+            .entry(key)
+            .or_insert_with(|| ParkedGroup { on_chain_nonce, transactions: BTreeMap::new() });
+        group.on_chain_nonce = std::cmp::max(group.on_chain_nonce, on_chain_nonce);
+        let mut expires_at = block_height.saturating_add(PARKED_TRANSACTION_TTL);
+        if let Some(valid_until) = signed_transaction.transaction.valid_until() {
+            expires_at = std::cmp::min(expires_at, valid_until.saturating_add(1));
+        }
+        group
+            .transactions
+            .insert(nonce, ParkedTransaction { transaction: signed_transaction, expires_at });
+        self.unique_transactions.insert(hash);
+        near_metrics::inc_counter(&metrics::PARKED_TRANSACTIONS_TOTAL);
+        near_metrics::inc_gauge(&metrics::PARKED_TRANSACTIONS);
+        true
+    }
+
+    /// Records that the nonce of the access key reached `nonce` on chain. Parked transactions
+    /// which can no longer be included are dropped.
+    pub fn observe_nonce(&mut self, key: &PoolKey, nonce: Nonce) {
+        let group = match self.groups.get_mut(key) {
+            Some(group) => group,
+            None => return,
+        };
+        group.on_chain_nonce = std::cmp::max(group.on_chain_nonce, nonce);
+        let remaining = group.transactions.split_off(&group.on_chain_nonce.saturating_add(1));
+        for (_, parked) in std::mem::replace(&mut group.transactions, remaining) {
+            self.unique_transactions.remove(&parked.transaction.get_hash());
+            near_metrics::dec_gauge(&metrics::PARKED_TRANSACTIONS);
+        }
+        if group.transactions.is_empty() {
+            self.groups.remove(key);
+        }
+    }
+
+    /// Records that the nonce of the access key on chain is back to `nonce`, after a reorg
+    /// removed the transactions which advanced it from the canonical chain.
+    pub fn rewind_nonce(&mut self, key: &PoolKey, nonce: Nonce) {
+        if let Some(group) = self.groups.get_mut(key) {
+            group.on_chain_nonce = std::cmp::min(group.on_chain_nonce, nonce);
+        }
+    }
+
+    /// Drops the parked transactions which expire at the given block height.
+    pub fn expire(&mut self, block_height: BlockHeight) {
+        let unique_transactions = &mut self.unique_transactions;
+        let mut expired = 0;
+        self.groups.retain(|_, group| {
+            let expired_nonces: Vec<_> = group
+                .transactions
+                .iter()
+                .filter(|(_, parked)| parked.expires_at <= block_height)
+                .map(|(nonce, _)| *nonce)
+                .collect();
+            for nonce in expired_nonces {
+                if let Some(parked) = group.transactions.remove(&nonce) {
+                    unique_transactions.remove(&parked.transaction.get_hash());
+                    near_metrics::dec_gauge(&metrics::PARKED_TRANSACTIONS);
+                    expired += 1;
+                }
+            }
+            !group.transactions.is_empty()
+        });
+        near_metrics::inc_counter_by(&metrics::PARKED_TRANSACTIONS_EXPIRED_TOTAL, expired);
+    }
+
+    /// Removes and returns the parked transactions of the access key whose gap is closed, given
+    /// the nonces of the transactions of this access key in the pool.
+    pub fn promote(
+        &mut self,
+        key: &PoolKey,
+        pending_nonces: &HashSet<Nonce>,
+    ) -> Vec<SignedTransaction> {
+        let group = match self.groups.get_mut(key) {
+            Some(group) => group,
+            None => return vec![],
+        };
+        let mut promoted = vec![];
+        let mut next_nonce = group.on_chain_nonce.saturating_add(1);
+        loop {
+            if let Some(parked) = group.transactions.remove(&next_nonce) {
+                promoted.push(parked.transaction);
+            } else if !pending_nonces.contains(&next_nonce) {
+                break;
+            }
+            next_nonce = match next_nonce.checked_add(1) {
+                Some(nonce) => nonce,
+                None => break,
+            };
+        }
+        if group.transactions.is_empty() {
+            self.groups.remove(key);
+        }
+        for tx in promoted.iter() {
+            self.unique_transactions.remove(&tx.get_hash());
+            near_metrics::dec_gauge(&metrics::PARKED_TRANSACTIONS);
+        }
+        near_metrics::inc_counter_by(
+            &metrics::PARKED_TRANSACTIONS_PROMOTED_TOTAL,
+            promoted.len() as u64,
+        );
+        promoted
+    }
+}
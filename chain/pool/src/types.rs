@@ -21,6 +21,8 @@ pub struct TransactionGroup {
     pub(crate) transactions: Vec<SignedTransaction>,
     /// Hashes of the transactions that were pulled from the group using `.next()`.
     pub(crate) removed_transaction_hashes: Vec<CryptoHash>,
+    /// Total size of the transactions that were pulled from the group using `.next()`.
+    pub(crate) removed_transaction_size: u64,
 }
 
 impl TransactionGroup {
@@ -29,9 +31,15 @@ impl TransactionGroup {
     pub fn next(&mut self) -> Option<SignedTransaction> {
         if let Some(tx) = self.transactions.pop() {
             self.removed_transaction_hashes.push(tx.get_hash());
+            self.removed_transaction_size += tx.get_size();
             Some(tx)
         } else {
             None
         }
     }
+
+    /// Returns the next transaction with the smallest nonce without removing it from the group.
+    pub fn peek(&self) -> Option<&SignedTransaction> {
+        self.transactions.last()
+    }
 }
@@ -170,8 +170,10 @@ fn convert_block_changes_to_transactions(
             }
             StateChangeCauseView::ActionReceiptProcessingStarted { receipt_hash }
             | StateChangeCauseView::ActionReceiptGasReward { receipt_hash }
+            | StateChangeCauseView::ActionReceiptGasRefund { receipt_hash }
             | StateChangeCauseView::ReceiptProcessing { receipt_hash }
-            | StateChangeCauseView::PostponedReceipt { receipt_hash } => {
+            | StateChangeCauseView::PostponedReceipt { receipt_hash }
+            | StateChangeCauseView::PostponedReceiptExpired { receipt_hash } => {
                 format!("receipt:{}", receipt_hash.to_base())
             }
             StateChangeCauseView::InitialState => format!("block:{}", block_hash),
@@ -181,6 +183,9 @@ fn convert_block_changes_to_transactions(
             StateChangeCauseView::UpdatedDelayedReceipts => {
                 format!("block-delayed-receipts:{}", block_hash)
             }
+            StateChangeCauseView::UpdatedBurnLedger => {
+                format!("block-burn-ledger:{}", block_hash)
+            }
             StateChangeCauseView::NotWritableToDisk => {
                 return Err(crate::errors::ErrorKind::InternalInvariantError(
                     "State Change 'NotWritableToDisk' should never be observed".to_string(),
@@ -573,7 +578,8 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                 }
 
-                near_primitives::transaction::Action::FunctionCall(action) => {
+                near_primitives::transaction::Action::FunctionCall(action)
+                | near_primitives::transaction::Action::OptionalFunctionCall(action) => {
                     let attached_amount = crate::models::Amount::from_yoctonear(action.deposit);
 
                     let mut related_operations = vec![];
@@ -613,6 +619,12 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                     operations.push(deploy_contract_operation);
                 }
+
+                // Don't move any funds.
+                near_primitives::transaction::Action::MaxStorageDelta(_)
+                | near_primitives::transaction::Action::DeployGlobalContract(_)
+                | near_primitives::transaction::Action::UseGlobalContract(_)
+                | near_primitives::transaction::Action::ValidUntil(_) => {}
             }
         }
         operations
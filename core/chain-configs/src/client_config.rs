@@ -1,10 +1,13 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use near_primitives::types::{AccountId, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId};
+use near_primitives::types::{
+    AccountId, BlockHeightDelta, Gas, Nonce, NumBlocks, NumSeats, ShardId, StateChangesFilter,
+};
 use near_primitives::version::Version;
 
 pub const TEST_STATE_SYNC_TIMEOUT: u64 = 5;
@@ -17,6 +20,26 @@ pub enum LogSummaryStyle {
     Colored,
 }
 
+/// Which shards the node tracks, i.e. downloads the chunks of and applies.
+/// Validators always track the shards they are assigned to in the current and the next epoch.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardTracking {
+    /// All shards, e.g. for RPC and archival nodes.
+    All,
+    /// The shards of `tracked_shards` and `tracked_accounts`.
+    Subset,
+    /// Only the assigned shards, ignoring `tracked_shards` and `tracked_accounts`, for
+    /// lightweight validators.
+    Own,
+}
+
+impl Default for ShardTracking {
+    fn default() -> Self {
+        ShardTracking::Subset
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// Version of the binary.
@@ -87,6 +110,8 @@ pub struct ClientConfig {
     pub tracked_accounts: Vec<AccountId>,
     /// Shards that this client tracks
     pub tracked_shards: Vec<ShardId>,
+    /// Whether this client tracks all shards, the tracked ones or only the assigned ones.
+    pub shard_tracking: ShardTracking,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
     /// Number of threads for ViewClientActor pool.
@@ -101,6 +126,33 @@ pub struct ClientConfig {
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
     pub max_gas_burnt_view: Option<Gas>,
+    /// Max wall-clock time of a single view method call.  Like `max_gas_burnt_view`, it only
+    /// affects the RPCs.  None is no limit.
+    pub max_view_call_time: Option<Duration>,
+    /// Filters for which matching state changes of every block are stored separately.
+    pub state_changes_filters: Vec<StateChangesFilter>,
+    /// Directory of the database, checked before voting for a newer protocol version. Nothing
+    /// is checked if it's not set.
+    pub store_path: Option<PathBuf>,
+    /// Minimum free disk space of the database, in bytes, to vote for a newer protocol version.
+    pub min_free_disk_space_for_upgrade: u64,
+    /// How long the head or the final head may not advance before the node tries to recover.
+    pub head_stall_timeout: Duration,
+    /// Check at the end of every epoch that the total supply in the block header matches the
+    /// tokens held by the states of the shards.
+    pub audit_total_supply: bool,
+    /// Maximum total size of the transactions in a produced chunk, in bytes. Larger transactions
+    /// are rejected when submitted.
+    pub max_chunk_transactions_size: u64,
+    /// Transactions whose nonce is more than this ahead of the nonce of their access key are
+    /// rejected. 0 disables the limit.
+    pub tx_nonce_window: Nonce,
+    /// Whether a transaction replaces a pending transaction with the same access key and nonce
+    /// if it attaches more gas. Otherwise both are kept and at most one of them is included.
+    pub tx_replace_by_nonce: bool,
+    /// Sync and validate only the block headers and the epoch data, without the chunks and the
+    /// state, for nodes serving the light client RPC. Such a node never validates.
+    pub header_only: bool,
 }
 
 impl ClientConfig {
@@ -152,6 +204,7 @@ impl ClientConfig {
             gc_blocks_limit: 100,
             tracked_accounts: vec![],
             tracked_shards: vec![],
+            shard_tracking: ShardTracking::Subset,
             archive,
             log_summary_style: LogSummaryStyle::Colored,
             view_client_threads: 1,
@@ -159,6 +212,16 @@ impl ClientConfig {
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
             max_gas_burnt_view: None,
+            max_view_call_time: None,
+            state_changes_filters: vec![],
+            store_path: None,
+            min_free_disk_space_for_upgrade: 0,
+            head_stall_timeout: Duration::from_secs(60),
+            audit_total_supply: false,
+            max_chunk_transactions_size: 4 * 1024 * 1024,
+            tx_nonce_window: 0,
+            tx_replace_by_nonce: false,
+            header_only: false,
         }
     }
 }
@@ -16,7 +16,8 @@ use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Serializer;
 use smart_default::SmartDefault;
 
-use near_primitives::epoch_manager::EpochConfig;
+use near_primitives::epoch_manager::{EpochConfig, StakeDenomination};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::NumShards;
 use near_primitives::{
@@ -63,6 +64,10 @@ fn default_protocol_upgrade_stake_threshold() -> Rational {
     Rational::new(8, 10)
 }
 
+fn default_slashing_escrow_epochs() -> EpochHeight {
+    3
+}
+
 #[derive(Debug, Clone, SmartDefault, Serialize, Deserialize)]
 pub struct GenesisConfig {
     /// Protocol version that this genesis works with.
@@ -133,6 +138,14 @@ pub struct GenesisConfig {
     pub num_blocks_per_year: NumBlocks,
     /// Protocol treasury account
     pub protocol_treasury_account: AccountId,
+    /// Accounts getting a share of the protocol treasury reward, e.g. a grants pool or a security
+    /// fund. The protocol treasury account gets the rest of the reward.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protocol_treasury_split: Vec<ProtocolTreasuryShare>,
+    /// Share of the protocol treasury reward paid to the contracts by their share of the gas
+    /// burnt by function calls in the epoch. The protocol treasury account gets the rest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub developer_reward_share: Option<Rational>,
     /// Fishermen stake threshold.
     #[serde(with = "u128_dec_format")]
     pub fishermen_threshold: Balance,
@@ -140,6 +153,30 @@ pub struct GenesisConfig {
     #[serde(default = "default_minimum_stake_divisor")]
     #[default(10)]
     pub minimum_stake_divisor: u64,
+    /// Number of epochs slashed stake is kept in escrow before it is burnt. During this time
+    /// the slash can be cancelled by counter-evidence.
+    #[serde(default = "default_slashing_escrow_epochs")]
+    #[default(3)]
+    pub slashing_escrow_epochs: EpochHeight,
+    /// Minimum duration of an epoch in nanoseconds according to block timestamps, on top of
+    /// its length in heights. Meant for test networks producing blocks very fast. Disabled if 0.
+    #[serde(default)]
+    pub min_epoch_duration_nanosec: u64,
+    /// Layout of the shards the state is split or merged into once the chain runs a protocol
+    /// version with resharding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resharding_shard_layout: Option<ShardLayout>,
+    /// Denomination of the stake the validator seats are assigned by, for experiments on test
+    /// networks.
+    #[serde(default, skip_serializing_if = "StakeDenomination::is_native")]
+    pub stake_denomination: StakeDenomination,
+}
+
+/// Share of the protocol treasury reward of an epoch paid to an account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolTreasuryShare {
+    pub account_id: AccountId,
+    pub share: Rational,
 }
 
 impl From<&GenesisConfig> for EpochConfig {
@@ -160,6 +197,9 @@ impl From<&GenesisConfig> for EpochConfig {
             protocol_upgrade_num_epochs: config.protocol_upgrade_num_epochs,
             protocol_upgrade_stake_threshold: config.protocol_upgrade_stake_threshold,
             minimum_stake_divisor: config.minimum_stake_divisor,
+            min_epoch_duration_nanosec: config.min_epoch_duration_nanosec,
+            resharding_shard_layout: config.resharding_shard_layout.clone(),
+            stake_denomination: config.stake_denomination.clone(),
         }
     }
 }
@@ -228,6 +268,13 @@ impl GenesisConfig {
         .expect("Failed to create / write a genesis config file.");
     }
 
+    /// Accounts receiving the protocol treasury reward, the protocol treasury account first.
+    pub fn protocol_treasury_accounts(&self) -> Vec<AccountId> {
+        std::iter::once(self.protocol_treasury_account.clone())
+            .chain(self.protocol_treasury_split.iter().map(|share| share.account_id.clone()))
+            .collect()
+    }
+
     /// Get validators from genesis config
     pub fn validators(&self) -> Vec<ValidatorStake> {
         self.validators
@@ -507,11 +554,22 @@ pub struct ProtocolConfigView {
     pub num_blocks_per_year: NumBlocks,
     /// Protocol treasury account
     pub protocol_treasury_account: AccountId,
+    /// Accounts getting a share of the protocol treasury reward.
+    #[serde(default)]
+    pub protocol_treasury_split: Vec<ProtocolTreasuryShare>,
+    /// Share of the protocol treasury reward paid to the contracts by their gas usage.
+    #[serde(default)]
+    pub developer_reward_share: Option<Rational>,
     /// Fishermen stake threshold.
     #[serde(with = "u128_dec_format")]
     pub fishermen_threshold: Balance,
     /// The minimum stake required for staking is last seat price divided by this number.
     pub minimum_stake_divisor: u64,
+    /// Layout of the shards at the protocol version, i.e. how accounts are assigned to shards.
+    pub shard_layout: ShardLayout,
+    /// Denomination of the stake the validator seats are assigned by.
+    #[serde(default)]
+    pub stake_denomination: StakeDenomination,
 }
 
 // This may be subject to change
@@ -519,6 +577,11 @@ pub type ProtocolConfig = GenesisConfig;
 
 impl From<ProtocolConfig> for ProtocolConfigView {
     fn from(config: ProtocolConfig) -> Self {
+        let shard_layout = ShardLayout::for_protocol_version(
+            config.num_block_producer_seats_per_shard.len() as NumShards,
+            config.resharding_shard_layout.as_ref(),
+            config.protocol_version,
+        );
         ProtocolConfigView {
             protocol_version: config.protocol_version,
             genesis_time: config.genesis_time,
@@ -544,8 +607,12 @@ impl From<ProtocolConfig> for ProtocolConfigView {
             max_inflation_rate: config.max_inflation_rate,
             num_blocks_per_year: config.num_blocks_per_year,
             protocol_treasury_account: config.protocol_treasury_account,
+            protocol_treasury_split: config.protocol_treasury_split,
+            developer_reward_share: config.developer_reward_share,
             fishermen_threshold: config.fishermen_threshold,
             minimum_stake_divisor: config.minimum_stake_divisor,
+            shard_layout,
+            stake_denomination: config.stake_denomination,
         }
     }
 }
@@ -1,9 +1,10 @@
 mod client_config;
 mod genesis_config;
 
-pub use client_config::{ClientConfig, LogSummaryStyle, TEST_STATE_SYNC_TIMEOUT};
+pub use client_config::{ClientConfig, LogSummaryStyle, ShardTracking, TEST_STATE_SYNC_TIMEOUT};
 pub use genesis_config::{
     Genesis, GenesisConfig, GenesisRecords, ProtocolConfig, ProtocolConfigView,
+    ProtocolTreasuryShare,
 };
 #[cfg(feature = "protocol_feature_evm")]
 pub use genesis_config::{BETANET_EVM_CHAIN_ID, MAINNET_EVM_CHAIN_ID, TESTNET_EVM_CHAIN_ID};
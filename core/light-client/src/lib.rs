@@ -0,0 +1,236 @@
+//! Verification of the proofs returned by the `light_client_proof` RPC method.
+//!
+//! A light client only tracks the `block_merkle_root` of its head. Given the response for a
+//! transaction or a receipt, `verify_execution_outcome_proof` checks that the execution outcome
+//! is included in the chunk outcome root, that the chunk outcome root is included in the
+//! `outcome_root` of the block, and that the block is included in the block merkle tree of the
+//! light client head. That is enough to trust e.g. a deposit without running a full node.
+use borsh::BorshSerialize;
+
+use near_primitives::block_header::BlockHeaderInnerLite;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::{
+    combine_hash, compute_root_from_path_and_item, verify_hash, verify_path, MerklePath,
+};
+use near_primitives::serialize::from_base64;
+use near_primitives::transaction::{PartialExecutionOutcome, PartialExecutionStatus};
+use near_primitives::views::{
+    ExecutionOutcomeWithIdView, ExecutionStatusView, LightClientBlockLiteView,
+};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    #[error("Execution outcome has a malformed result: {0}")]
+    MalformedOutcome(String),
+    #[error("Execution outcome is not included in the outcome root of the block")]
+    OutcomeNotIncluded,
+    #[error(
+        "Execution outcome was proven for block {expected} but the proof is for block {actual}"
+    )]
+    BlockHashMismatch { expected: CryptoHash, actual: CryptoHash },
+    #[error(
+        "Block {block_hash} is not included in the block merkle root of the light client head"
+    )]
+    BlockNotIncluded { block_hash: CryptoHash },
+}
+
+/// Computes the hash of the block from its lite header.
+pub fn block_header_lite_hash(block_header_lite: &LightClientBlockLiteView) -> CryptoHash {
+    let inner_lite: BlockHeaderInnerLite = block_header_lite.inner_lite.clone().into();
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    let inner_hash = combine_hash(inner_lite_hash, block_header_lite.inner_rest_hash);
+    combine_hash(inner_hash, block_header_lite.prev_block_hash)
+}
+
+/// Computes the hashes the execution outcome is merklized from, see
+/// `ExecutionOutcomeWithId::to_hashes`.
+pub fn execution_outcome_hashes(
+    outcome_with_id: &ExecutionOutcomeWithIdView,
+) -> Result<Vec<CryptoHash>, ProofError> {
+    let outcome = &outcome_with_id.outcome;
+    let status = match &outcome.status {
+        ExecutionStatusView::Unknown => PartialExecutionStatus::Unknown,
+        ExecutionStatusView::Failure(_) => PartialExecutionStatus::Failure,
+        ExecutionStatusView::SuccessValue(value) => PartialExecutionStatus::SuccessValue(
+            from_base64(value).map_err(|err| ProofError::MalformedOutcome(err.to_string()))?,
+        ),
+        ExecutionStatusView::SuccessReceiptId(receipt_id) => {
+            PartialExecutionStatus::SuccessReceiptId(*receipt_id)
+        }
+    };
+    let partial_outcome = PartialExecutionOutcome {
+        receipt_ids: outcome.receipt_ids.clone(),
+        gas_burnt: outcome.gas_burnt,
+        tokens_burnt: outcome.tokens_burnt,
+        executor_id: outcome.executor_id.clone(),
+        status,
+    };
+    let mut result =
+        vec![outcome_with_id.id, hash(&partial_outcome.try_to_vec().expect("Failed to serialize"))];
+    result.extend(outcome.logs.iter().map(|log| hash(log.as_bytes())));
+    Ok(result)
+}
+
+/// Verifies the execution outcome proof against the `block_merkle_root` of the light client head.
+///
+/// The arguments are the fields of the `light_client_proof` response. The block of the outcome
+/// has to precede the light client head: a block is not part of its own block merkle tree.
+pub fn verify_execution_outcome_proof(
+    outcome_proof: &ExecutionOutcomeWithIdView,
+    outcome_root_proof: &MerklePath,
+    block_header_lite: &LightClientBlockLiteView,
+    block_proof: &MerklePath,
+    block_merkle_root: &CryptoHash,
+) -> Result<(), ProofError> {
+    let chunk_outcome_root = compute_root_from_path_and_item(
+        &outcome_proof.proof,
+        &execution_outcome_hashes(outcome_proof)?,
+    );
+    if !verify_path(
+        block_header_lite.inner_lite.outcome_root,
+        outcome_root_proof,
+        &chunk_outcome_root,
+    ) {
+        return Err(ProofError::OutcomeNotIncluded);
+    }
+
+    let block_hash = block_header_lite_hash(block_header_lite);
+    if block_hash != outcome_proof.block_hash {
+        return Err(ProofError::BlockHashMismatch {
+            expected: outcome_proof.block_hash,
+            actual: block_hash,
+        });
+    }
+    if !verify_hash(*block_merkle_root, block_proof, block_hash) {
+        return Err(ProofError::BlockNotIncluded { block_hash });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::block::{genesis_chunks, Block};
+    use near_primitives::merkle::{merklize, Direction, MerklePathItem};
+    use near_primitives::types::StateRoot;
+    use near_primitives::utils::from_timestamp;
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_primitives::views::ExecutionOutcomeView;
+
+    use super::*;
+
+    struct Proof {
+        outcome_proof: ExecutionOutcomeWithIdView,
+        outcome_root_proof: MerklePath,
+        block_header_lite: LightClientBlockLiteView,
+        block_proof: MerklePath,
+        block_merkle_root: CryptoHash,
+    }
+
+    impl Proof {
+        fn verify(&self) -> Result<(), ProofError> {
+            verify_execution_outcome_proof(
+                &self.outcome_proof,
+                &self.outcome_root_proof,
+                &self.block_header_lite,
+                &self.block_proof,
+                &self.block_merkle_root,
+            )
+        }
+    }
+
+    fn genesis_block() -> Block {
+        let chunks = genesis_chunks(vec![StateRoot::default()], 1, 1_000, 0, PROTOCOL_VERSION);
+        Block::genesis(
+            PROTOCOL_VERSION,
+            chunks.into_iter().map(|chunk| chunk.take_header()).collect(),
+            from_timestamp(0),
+            0,
+            1_000,
+            1_000,
+            CryptoHash::default(),
+        )
+    }
+
+    fn outcome(id: CryptoHash) -> ExecutionOutcomeWithIdView {
+        ExecutionOutcomeWithIdView {
+            proof: vec![],
+            block_hash: CryptoHash::default(),
+            id,
+            outcome: ExecutionOutcomeView {
+                logs: vec!["deposit".to_string()],
+                receipt_ids: vec![hash(&[1])],
+                gas_burnt: 100,
+                tokens_burnt: 1_000,
+                executor_id: "alice.near".to_string(),
+                status: ExecutionStatusView::SuccessValue(String::new()),
+                metadata: Default::default(),
+            },
+        }
+    }
+
+    /// Builds a proof of the first of two outcomes in the first of two chunks, for a light
+    /// client head whose block merkle tree has one more block.
+    fn proof() -> Proof {
+        let mut outcomes = vec![outcome(hash(&[1])), outcome(hash(&[2]))];
+        let outcome_hashes = outcomes
+            .iter()
+            .map(|outcome| execution_outcome_hashes(outcome).unwrap())
+            .collect::<Vec<_>>();
+        let (chunk_outcome_root, outcome_paths) = merklize(&outcome_hashes);
+        let (outcome_root, outcome_root_paths) = merklize(&[chunk_outcome_root, hash(&[3])]);
+
+        let mut block_header_lite: LightClientBlockLiteView =
+            genesis_block().header().clone().into();
+        block_header_lite.inner_lite.outcome_root = outcome_root;
+        let block_hash = block_header_lite_hash(&block_header_lite);
+
+        let mut outcome_proof = outcomes.swap_remove(0);
+        outcome_proof.proof = outcome_paths[0].clone();
+        outcome_proof.block_hash = block_hash;
+
+        let next_block_hash = hash(&[4]);
+        Proof {
+            outcome_proof,
+            outcome_root_proof: outcome_root_paths[0].clone(),
+            block_header_lite,
+            block_proof: vec![MerklePathItem {
+                hash: next_block_hash,
+                direction: Direction::Right,
+            }],
+            block_merkle_root: combine_hash(block_hash, next_block_hash),
+        }
+    }
+
+    #[test]
+    fn test_block_header_lite_hash() {
+        let block = genesis_block();
+        let block_header_lite: LightClientBlockLiteView = block.header().clone().into();
+        assert_eq!(block_header_lite_hash(&block_header_lite), *block.hash());
+    }
+
+    #[test]
+    fn test_verify_execution_outcome_proof() {
+        assert_eq!(proof().verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_tampered_outcome() {
+        let mut proof = proof();
+        proof.outcome_proof.outcome.tokens_burnt += 1;
+        assert_eq!(proof.verify(), Err(ProofError::OutcomeNotIncluded));
+    }
+
+    #[test]
+    fn test_outcome_for_other_block() {
+        let mut proof = proof();
+        proof.outcome_proof.block_hash = hash(&[5]);
+        assert!(matches!(proof.verify(), Err(ProofError::BlockHashMismatch { .. })));
+    }
+
+    #[test]
+    fn test_block_not_in_block_merkle_root() {
+        let mut proof = proof();
+        proof.block_merkle_root = hash(&[6]);
+        assert!(matches!(proof.verify(), Err(ProofError::BlockNotIncluded { .. })));
+    }
+}
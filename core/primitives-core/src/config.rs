@@ -59,6 +59,8 @@ pub struct VMLimitConfig {
 
     /// Max number of actions per receipt.
     pub max_actions_per_receipt: u64,
+    /// Max number of actions per transaction.
+    pub max_actions_per_transaction: u64,
     /// Max total length of all method names (including terminating character) for a function call
     /// permission access key.
     pub max_number_bytes_method_names: u64,
@@ -80,6 +82,10 @@ pub struct VMLimitConfig {
     pub max_promises_per_function_call_action: u64,
     /// Max number of input data dependencies
     pub max_number_input_data_dependencies: u64,
+    /// Max length of the id of an account extension record
+    pub max_length_account_extension_id: u64,
+    /// Max size of the value of an account extension record
+    pub max_length_account_extension_value: u64,
 }
 
 impl Default for VMConfig {
@@ -147,6 +153,7 @@ impl Default for VMLimitConfig {
 
             // Safety limit. Unlikely to hit it for most common transactions and receipts.
             max_actions_per_receipt: 100,
+            max_actions_per_transaction: 100,
             // Should be low enough to deserialize an access key without paying.
             max_number_bytes_method_names: 2000,
             max_length_method_name: 256,            // basic safety limit
@@ -161,6 +168,9 @@ impl Default for VMLimitConfig {
             max_promises_per_function_call_action: 1024,
             // Unlikely to hit it for normal development.
             max_number_input_data_dependencies: 128,
+            // Extension records are meant to be small typed records, not general storage.
+            max_length_account_extension_id: 64,
+            max_length_account_extension_value: 1024,
         }
     }
 }
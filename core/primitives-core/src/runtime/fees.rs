@@ -31,6 +31,10 @@ pub struct Fee {
 }
 
 impl Fee {
+    pub fn free() -> Self {
+        Fee { send_sir: 0, send_not_sir: 0, execution: 0 }
+    }
+
     #[inline]
     pub fn send_fee(&self, sir: bool) -> Gas {
         if sir {
@@ -72,6 +76,11 @@ pub struct RuntimeFeesConfig {
     /// Pessimistic gas price inflation ratio.
     pub pessimistic_gas_price_inflation_ratio: Rational,
 
+    /// Amount of tokens refunded to the signer per byte of storage freed by a receipt, at most
+    /// the fees burnt for its execution. Zero disables the rebate.
+    #[serde(with = "u128_dec_format")]
+    pub storage_rebate_per_byte: crate::types::Balance,
+
     /// Describes cost of running method of evm, include deploy code and call contract function
     #[cfg(feature = "protocol_feature_evm")]
     pub evm_config: EvmCostConfig,
@@ -131,6 +140,11 @@ pub struct ActionCreationConfig {
 
     /// Base cost of deleting an account.
     pub delete_account_cost: Fee,
+
+    /// Overhead of any action, charged on top of its base cost. It accounts for the work done per
+    /// action of a receipt regardless of its kind, so large batches of cheap actions pay for it.
+    #[serde(default = "Fee::free")]
+    pub action_overhead_cost: Fee,
 }
 
 /// Describes the cost of creating an access key.
@@ -318,6 +332,7 @@ impl Default for RuntimeFeesConfig {
                     send_not_sir: 147489000000,
                     execution: 147489000000,
                 },
+                action_overhead_cost: Fee::free(),
             },
             storage_usage_config: StorageUsageConfig {
                 // See Account in core/primitives/src/account.rs for the data structure.
@@ -327,6 +342,7 @@ impl Default for RuntimeFeesConfig {
             },
             burnt_gas_reward: Rational::new(3, 10),
             pessimistic_gas_price_inflation_ratio: Rational::new(103, 100),
+            storage_rebate_per_byte: 0,
             #[cfg(feature = "protocol_feature_evm")]
             evm_config: EvmCostConfig::default(),
             #[cfg(feature = "protocol_feature_evm")]
@@ -337,7 +353,7 @@ impl Default for RuntimeFeesConfig {
 
 impl RuntimeFeesConfig {
     pub fn free() -> Self {
-        let free = Fee { send_sir: 0, send_not_sir: 0, execution: 0 };
+        let free = Fee::free();
         RuntimeFeesConfig {
             action_receipt_creation_config: free.clone(),
             data_receipt_creation_config: DataReceiptCreationConfig {
@@ -358,7 +374,8 @@ impl RuntimeFeesConfig {
                     function_call_cost_per_byte: free.clone(),
                 },
                 delete_key_cost: free.clone(),
-                delete_account_cost: free,
+                delete_account_cost: free.clone(),
+                action_overhead_cost: free,
             },
             storage_usage_config: StorageUsageConfig {
                 num_bytes_account: 0,
@@ -366,6 +383,7 @@ impl RuntimeFeesConfig {
             },
             burnt_gas_reward: Rational::from_integer(0),
             pessimistic_gas_price_inflation_ratio: Rational::from_integer(0),
+            storage_rebate_per_byte: 0,
             #[cfg(feature = "protocol_feature_evm")]
             evm_config: EvmCostConfig {
                 bootstrap_cost: 0,
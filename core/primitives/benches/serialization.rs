@@ -47,6 +47,7 @@ fn create_block() -> Block {
     );
     let signer = InMemoryValidatorSigner::from_random("".to_string(), KeyType::ED25519);
     Block::produce(
+        PROTOCOL_VERSION,
         PROTOCOL_VERSION,
         genesis.header(),
         10,
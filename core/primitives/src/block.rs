@@ -192,6 +192,7 @@ impl Block {
     /// Produces new block from header of previous block, current state root and set of transactions.
     pub fn produce(
         protocol_version: ProtocolVersion,
+        latest_protocol_version: ProtocolVersion,
         prev: &BlockHeader,
         height: BlockHeight,
         #[cfg(feature = "protocol_feature_block_header_v3")] block_ordinal: NumBlocks,
@@ -268,6 +269,7 @@ impl Block {
 
         let header = BlockHeader::new(
             protocol_version,
+            latest_protocol_version,
             height,
             prev.hash().clone(),
             Block::compute_state_root(&chunks),
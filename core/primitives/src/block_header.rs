@@ -12,7 +12,7 @@ use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, Validato
 use crate::types::{AccountId, Balance, BlockHeight, EpochId, MerkleHash, NumBlocks};
 use crate::utils::{from_timestamp, to_timestamp};
 use crate::validator_signer::ValidatorSigner;
-use crate::version::{ProtocolVersion, PROTOCOL_VERSION};
+use crate::version::ProtocolVersion;
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
 pub struct BlockHeaderInnerLite {
@@ -334,6 +334,7 @@ impl BlockHeader {
 
     pub fn new(
         protocol_version: ProtocolVersion,
+        latest_protocol_version: ProtocolVersion,
         height: BlockHeight,
         prev_hash: CryptoHash,
         prev_state_root: MerkleHash,
@@ -398,7 +399,7 @@ impl BlockHeader {
                 last_final_block,
                 last_ds_final_block,
                 approvals,
-                latest_protocol_version: PROTOCOL_VERSION,
+                latest_protocol_version,
             };
             let (hash, signature) = signer.sign_block_header_parts(
                 prev_hash,
@@ -432,7 +433,7 @@ impl BlockHeader {
                 last_final_block,
                 last_ds_final_block,
                 approvals,
-                latest_protocol_version: PROTOCOL_VERSION,
+                latest_protocol_version,
             };
             let (hash, signature) = signer.sign_block_header_parts(
                 prev_hash,
@@ -468,7 +469,7 @@ impl BlockHeader {
                     prev_height,
                     epoch_sync_data_hash,
                     approvals,
-                    latest_protocol_version: PROTOCOL_VERSION,
+                    latest_protocol_version,
                 };
                 let (hash, signature) = signer.sign_block_header_parts(
                     prev_hash,
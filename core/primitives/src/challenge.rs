@@ -3,10 +3,13 @@ use serde::{Deserialize, Serialize};
 
 use near_crypto::Signature;
 
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+use crate::block_header::Approval;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::MerklePath;
 use crate::sharding::{EncodedShardChunk, ShardChunk, ShardChunkHeader};
-use crate::types::AccountId;
+use crate::transaction::SignedTransaction;
+use crate::types::{AccountId, Balance, EpochHeight, ShardId};
 use crate::validator_signer::ValidatorSigner;
 
 /// Serialized TrieNodeWithSize
@@ -28,6 +31,14 @@ impl std::fmt::Display for BlockDoubleSign {
     }
 }
 
+/// Two different approvals of a validator for the same target height.
+#[cfg(feature = "protocol_feature_double_sign_slashing")]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ApprovalDoubleSign {
+    pub left_approval: Approval,
+    pub right_approval: Approval,
+}
+
 /// Invalid chunk (body of the chunk doesn't match proofs or invalid encoding).
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
 pub struct ChunkProofs {
@@ -68,6 +79,33 @@ pub struct ChunkState {
     pub partial_state: PartialState,
 }
 
+/// Appeal against the slash caused by a `ChunkState` challenge. Only cancels the slash of the
+/// referenced challenge.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SlashAppeal {
+    /// Hash of the block which included the appealed challenge.
+    pub challenge_block_hash: CryptoHash,
+    /// Hash of the appealed `ChunkState` challenge.
+    pub challenge_hash: CryptoHash,
+    /// State transition of the challenged chunk, which must match the chunk header for the appeal
+    /// to be accepted.
+    pub chunk_state: ChunkState,
+}
+
+/// Everything a node without the state of a shard needs to apply a chunk of the shard: the
+/// transactions of the chunk and the trie nodes touched by applying it. Incoming receipts aren't
+/// part of it, since every node stores them together with their proofs.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ChunkStateWitness {
+    /// Block in which the chunk is included.
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+    /// Transactions of the chunk, checked against the transactions root of its header.
+    pub transactions: Vec<SignedTransaction>,
+    /// Trie nodes touched by applying the chunk, checked against its previous state root.
+    pub partial_state: PartialState,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
 // TODO(#1313): Use Box
 #[allow(clippy::large_enum_variant)]
@@ -75,6 +113,24 @@ pub enum ChallengeBody {
     BlockDoubleSign(BlockDoubleSign),
     ChunkProofs(ChunkProofs),
     ChunkState(ChunkState),
+    /// Counter-evidence for a slash caused by a `ChunkState` challenge.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    SlashAppeal(SlashAppeal),
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    ApprovalDoubleSign(ApprovalDoubleSign),
+}
+
+impl ChallengeBody {
+    /// Whether the challenge proves that a validator signed two conflicting messages. Such
+    /// challenges only slash the validator and don't invalidate any block.
+    pub fn is_double_sign(&self) -> bool {
+        match self {
+            ChallengeBody::BlockDoubleSign(_) => true,
+            #[cfg(feature = "protocol_feature_double_sign_slashing")]
+            ChallengeBody::ApprovalDoubleSign(_) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
@@ -113,6 +169,25 @@ impl SlashedValidator {
     }
 }
 
+/// Slashed stake of an account that is kept aside until the appeal windows are over.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug, Default)]
+pub struct SlashEscrow {
+    /// Total amount slashed from the account and not burnt yet.
+    pub amount: Balance,
+    /// Slashes making up the amount, each of them is returned or burnt on its own.
+    pub slashes: Vec<EscrowedSlash>,
+}
+
+/// Single slash kept in escrow.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct EscrowedSlash {
+    /// Hash of the block whose challenges caused the slash.
+    pub block_hash: CryptoHash,
+    pub amount: Balance,
+    /// Epoch height starting from which the slashed amount is burnt.
+    pub release_epoch_height: EpochHeight,
+}
+
 /// Result of checking challenge, contains which accounts to slash.
 /// If challenge is invalid this is sender, otherwise author of chunk (and possibly other participants that signed invalid blocks).
 pub type ChallengesResult = Vec<SlashedValidator>;
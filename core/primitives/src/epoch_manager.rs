@@ -1,8 +1,11 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use num_rational::Rational;
-use serde::Serialize;
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
 
 use crate::challenge::SlashedValidator;
+use crate::serialize::u128_dec_format;
+use crate::shard_layout::ShardLayout;
 use crate::types::validator_stake::ValidatorStakeV1;
 use crate::types::{
     AccountId, Balance, BlockHeightDelta, EpochHeight, EpochId, NumSeats, NumShards,
@@ -17,6 +20,7 @@ use std::collections::{BTreeMap, HashMap};
 pub type RngSeed = [u8; 32];
 
 pub const AGGREGATOR_KEY: &[u8] = b"AGGREGATOR";
+pub const VALIDATOR_SET_OVERRIDE_KEY: &[u8] = b"VALIDATOR_SET_OVERRIDE";
 
 /// Epoch config, determines validator assignment for given epoch.
 /// Can change from epoch to epoch depending on the sharding and other parameters, etc.
@@ -48,6 +52,78 @@ pub struct EpochConfig {
     pub protocol_upgrade_stake_threshold: Rational,
     /// Number of epochs after stake threshold was achieved to start next prtocol version.
     pub protocol_upgrade_num_epochs: EpochHeight,
+    /// Minimum duration of an epoch according to block timestamps. An epoch which reached its
+    /// length in heights is extended until it lasts that long. Disabled if 0.
+    pub min_epoch_duration_nanosec: u64,
+    /// Layout of the shards the chain switches to with resharding. The shards keep the layout of
+    /// the genesis, with accounts assigned by their hash, until then.
+    pub resharding_shard_layout: Option<ShardLayout>,
+    /// Denomination of the stake the seats are assigned by.
+    pub stake_denomination: StakeDenomination,
+}
+
+/// Denomination of the stake the validator seats are assigned by, meant for experiments on test
+/// networks. The seat price is expressed in it, while the stakes locked on the accounts, the
+/// rewards and the fishermen threshold stay in native tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeDenomination {
+    /// The stake in native tokens.
+    Native,
+    /// The stake of the listed accounts is multiplied by their multipliers, e.g. to weight the
+    /// stake by its lockup. The stake of the other accounts counts as is.
+    Multipliers(BTreeMap<AccountId, Rational>),
+    /// The stake in native tokens plus the stake of the account in a secondary staking token,
+    /// converted to native tokens at a fixed rate.
+    SecondaryToken { stakes: Vec<SecondaryStake>, rate: Rational },
+}
+
+impl Default for StakeDenomination {
+    fn default() -> Self {
+        StakeDenomination::Native
+    }
+}
+
+/// Stake of an account in the secondary staking token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SecondaryStake {
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+}
+
+impl StakeDenomination {
+    pub fn is_native(&self) -> bool {
+        *self == StakeDenomination::Native
+    }
+
+    /// Returns the native stake of the account in this denomination.
+    pub fn denominate(&self, account_id: &AccountId, stake: Balance) -> Balance {
+        match self {
+            StakeDenomination::Native => stake,
+            StakeDenomination::Multipliers(multipliers) => match multipliers.get(account_id) {
+                Some(multiplier) => mul_ratio(stake, multiplier),
+                None => stake,
+            },
+            StakeDenomination::SecondaryToken { stakes, rate } => {
+                let secondary_stake = stakes
+                    .iter()
+                    .find(|secondary_stake| &secondary_stake.account_id == account_id)
+                    .map_or(0, |secondary_stake| mul_ratio(secondary_stake.amount, rate));
+                stake.saturating_add(secondary_stake)
+            }
+        }
+    }
+}
+
+fn mul_ratio(value: Balance, ratio: &Rational) -> Balance {
+    let result =
+        U256::from(value) * U256::from(*ratio.numer() as u64) / U256::from(*ratio.denom() as u64);
+    if result > U256::from(Balance::MAX) {
+        Balance::MAX
+    } else {
+        result.as_u128()
+    }
 }
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -429,7 +505,9 @@ pub struct ValidatorWeight(ValidatorId, u64);
 pub mod epoch_info {
     use crate::epoch_manager::ValidatorWeight;
     use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
-    use crate::types::{BlockChunkValidatorStats, ValidatorKickoutReason};
+    use crate::types::{
+        BlockChunkValidatorStats, ShardChunkValidatorStats, UptimeCurve, ValidatorKickoutReason,
+    };
     use crate::version::PROTOCOL_VERSION;
     use borsh::{BorshDeserialize, BorshSerialize};
     use near_primitives_core::hash::CryptoHash;
@@ -446,6 +524,8 @@ pub mod epoch_info {
     pub enum EpochInfo {
         V1(EpochInfoV1),
         V2(EpochInfoV2),
+        #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+        V3(EpochInfoV3),
     }
 
     impl Default for EpochInfo {
@@ -489,6 +569,45 @@ pub mod epoch_info {
         pub protocol_version: ProtocolVersion,
     }
 
+    // V2 -> V3: Store the random values the seed of validator shuffling was derived from
+    #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+    #[derive(SmartDefault, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct EpochInfoV3 {
+        /// Ordinal of given epoch from genesis.
+        /// There can be multiple epochs with the same ordinal in case of long forks.
+        pub epoch_height: EpochHeight,
+        /// List of current validators.
+        pub validators: Vec<ValidatorStake>,
+        /// Validator account id to index in proposals.
+        pub validator_to_index: HashMap<AccountId, ValidatorId>,
+        /// Settlement of validators responsible for block production.
+        pub block_producers_settlement: Vec<ValidatorId>,
+        /// Per each shard, settlement validators that are responsible.
+        pub chunk_producers_settlement: Vec<Vec<ValidatorId>>,
+        /// Settlement of hidden validators with weights used to determine how many shards they will validate.
+        pub hidden_validators_settlement: Vec<ValidatorWeight>,
+        /// List of current fishermen.
+        pub fishermen: Vec<ValidatorStake>,
+        /// Fisherman account id to index of proposal.
+        pub fishermen_to_index: HashMap<AccountId, ValidatorId>,
+        /// New stake for validators.
+        pub stake_change: BTreeMap<AccountId, Balance>,
+        /// Validator reward for the epoch.
+        pub validator_reward: HashMap<AccountId, Balance>,
+        /// Validators who are kicked out in this epoch.
+        pub validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
+        /// Total minted tokens in the epoch.
+        pub minted_amount: Balance,
+        /// Seat price of this epoch.
+        pub seat_price: Balance,
+        /// Current protocol version during this epoch.
+        #[default(PROTOCOL_VERSION)]
+        pub protocol_version: ProtocolVersion,
+        /// Random values (hashes of the VRF outputs) of the last blocks of the epoch the
+        /// settlement was computed in, oldest first. The shuffling seed is derived from them.
+        pub rng_seed_inputs: Vec<CryptoHash>,
+    }
+
     impl EpochInfo {
         pub fn new(
             epoch_height: EpochHeight,
@@ -529,6 +648,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &mut v1.epoch_height,
                 Self::V2(v2) => &mut v2.epoch_height,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &mut v3.epoch_height,
             }
         }
 
@@ -537,6 +658,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.epoch_height,
                 Self::V2(v2) => v2.epoch_height,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.epoch_height,
             }
         }
 
@@ -545,6 +668,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.seat_price,
                 Self::V2(v2) => v2.seat_price,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.seat_price,
             }
         }
 
@@ -553,6 +678,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.minted_amount,
                 Self::V2(v2) => v2.minted_amount,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.minted_amount,
             }
         }
 
@@ -561,6 +688,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.block_producers_settlement,
                 Self::V2(v2) => &v2.block_producers_settlement,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &v3.block_producers_settlement,
             }
         }
 
@@ -569,6 +698,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.chunk_producers_settlement,
                 Self::V2(v2) => &v2.chunk_producers_settlement,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &v3.chunk_producers_settlement,
             }
         }
 
@@ -577,6 +708,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.validator_kickout,
                 Self::V2(v2) => &v2.validator_kickout,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &v3.validator_kickout,
             }
         }
 
@@ -585,6 +718,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.protocol_version,
                 Self::V2(v2) => v2.protocol_version,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.protocol_version,
             }
         }
 
@@ -593,6 +728,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.stake_change,
                 Self::V2(v2) => &v2.stake_change,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &v3.stake_change,
             }
         }
 
@@ -601,6 +738,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.validator_reward,
                 Self::V2(v2) => &v2.validator_reward,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => &v3.validator_reward,
             }
         }
 
@@ -609,6 +748,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => ValidatorStakeIter::v1(&v1.validators),
                 Self::V2(v2) => ValidatorStakeIter::new(&v2.validators),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => ValidatorStakeIter::new(&v3.validators),
             }
         }
 
@@ -617,6 +758,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => ValidatorStakeIter::v1(&v1.fishermen),
                 Self::V2(v2) => ValidatorStakeIter::new(&v2.fishermen),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => ValidatorStakeIter::new(&v3.fishermen),
             }
         }
 
@@ -625,6 +768,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.validators[validator_id as usize].stake,
                 Self::V2(v2) => v2.validators[validator_id as usize].stake(),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validators[validator_id as usize].stake(),
             }
         }
 
@@ -633,6 +778,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => &v1.validators[validator_id as usize].account_id,
                 Self::V2(v2) => v2.validators[validator_id as usize].account_id(),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validators[validator_id as usize].account_id(),
             }
         }
 
@@ -641,6 +788,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.validator_to_index.contains_key(account_id),
                 Self::V2(v2) => v2.validator_to_index.contains_key(account_id),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validator_to_index.contains_key(account_id),
             }
         }
 
@@ -648,6 +797,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.validator_to_index.get(account_id),
                 Self::V2(v2) => v2.validator_to_index.get(account_id),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validator_to_index.get(account_id),
             }
         }
 
@@ -660,6 +811,11 @@ pub mod epoch_info {
                     .validator_to_index
                     .get(account_id)
                     .map(|validator_id| v2.validators[*validator_id as usize].clone()),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3
+                    .validator_to_index
+                    .get(account_id)
+                    .map(|validator_id| v3.validators[*validator_id as usize].clone()),
             }
         }
 
@@ -668,6 +824,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => ValidatorStake::V1(v1.validators[validator_id as usize].clone()),
                 Self::V2(v2) => v2.validators[validator_id as usize].clone(),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validators[validator_id as usize].clone(),
             }
         }
 
@@ -676,6 +834,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.fishermen_to_index.contains_key(account_id),
                 Self::V2(v2) => v2.fishermen_to_index.contains_key(account_id),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.fishermen_to_index.contains_key(account_id),
             }
         }
 
@@ -688,6 +848,11 @@ pub mod epoch_info {
                     .fishermen_to_index
                     .get(account_id)
                     .map(|validator_id| v2.fishermen[*validator_id as usize].clone()),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3
+                    .fishermen_to_index
+                    .get(account_id)
+                    .map(|validator_id| v3.fishermen[*validator_id as usize].clone()),
             }
         }
 
@@ -696,6 +861,8 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => ValidatorStake::V1(v1.fishermen[fisherman_id as usize].clone()),
                 Self::V2(v2) => v2.fishermen[fisherman_id as usize].clone(),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.fishermen[fisherman_id as usize].clone(),
             }
         }
 
@@ -704,8 +871,63 @@ pub mod epoch_info {
             match self {
                 Self::V1(v1) => v1.validators.len(),
                 Self::V2(v2) => v2.validators.len(),
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => v3.validators.len(),
             }
         }
+
+        /// Random values the shuffling seed of this epoch was derived from, if they were stored.
+        #[inline]
+        pub fn rng_seed_inputs(&self) -> Option<&[CryptoHash]> {
+            match self {
+                Self::V1(_) | Self::V2(_) => None,
+                #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+                Self::V3(v3) => Some(&v3.rng_seed_inputs),
+            }
+        }
+
+        /// Converts the epoch info into the version which stores the inputs of the shuffling seed.
+        /// `EpochInfoV1` is upgraded too, its validators get the versioned stake structure.
+        #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+        pub fn with_rng_seed_inputs(self, rng_seed_inputs: Vec<CryptoHash>) -> Self {
+            let v2 = match self {
+                Self::V1(v1) => EpochInfoV2 {
+                    epoch_height: v1.epoch_height,
+                    validators: v1.validators.into_iter().map(ValidatorStake::V1).collect(),
+                    validator_to_index: v1.validator_to_index,
+                    block_producers_settlement: v1.block_producers_settlement,
+                    chunk_producers_settlement: v1.chunk_producers_settlement,
+                    hidden_validators_settlement: v1.hidden_validators_settlement,
+                    fishermen: v1.fishermen.into_iter().map(ValidatorStake::V1).collect(),
+                    fishermen_to_index: v1.fishermen_to_index,
+                    stake_change: v1.stake_change,
+                    validator_reward: v1.validator_reward,
+                    validator_kickout: v1.validator_kickout,
+                    minted_amount: v1.minted_amount,
+                    seat_price: v1.seat_price,
+                    protocol_version: v1.protocol_version,
+                },
+                Self::V2(v2) => v2,
+                Self::V3(v3) => return Self::V3(EpochInfoV3 { rng_seed_inputs, ..v3 }),
+            };
+            Self::V3(EpochInfoV3 {
+                epoch_height: v2.epoch_height,
+                validators: v2.validators,
+                validator_to_index: v2.validator_to_index,
+                block_producers_settlement: v2.block_producers_settlement,
+                chunk_producers_settlement: v2.chunk_producers_settlement,
+                hidden_validators_settlement: v2.hidden_validators_settlement,
+                fishermen: v2.fishermen,
+                fishermen_to_index: v2.fishermen_to_index,
+                stake_change: v2.stake_change,
+                validator_reward: v2.validator_reward,
+                validator_kickout: v2.validator_kickout,
+                minted_amount: v2.minted_amount,
+                seat_price: v2.seat_price,
+                protocol_version: v2.protocol_version,
+                rng_seed_inputs,
+            })
+        }
     }
 
     #[derive(BorshSerialize, BorshDeserialize)]
@@ -719,6 +941,11 @@ pub mod epoch_info {
         pub validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
         /// Protocol version for next epoch.
         pub next_version: ProtocolVersion,
+        /// Chunk production stats by shard of the validators in `validator_block_chunk_stats`.
+        pub validator_shard_chunk_stats: HashMap<AccountId, ShardChunkValidatorStats>,
+        /// Curve the rewards of the validators were computed with, `None` for the epochs
+        /// summarized before it was recorded.
+        pub uptime_curve: Option<UptimeCurve>,
     }
 }
 
@@ -726,7 +953,9 @@ pub mod epoch_info {
 pub mod epoch_info {
     use crate::epoch_manager::ValidatorWeight;
     use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
-    use crate::types::{BlockChunkValidatorStats, ValidatorKickoutReason};
+    use crate::types::{
+        BlockChunkValidatorStats, ShardChunkValidatorStats, UptimeCurve, ValidatorKickoutReason,
+    };
     use borsh::{BorshDeserialize, BorshSerialize};
     use near_primitives_core::hash::CryptoHash;
     use near_primitives_core::types::{
@@ -883,6 +1112,12 @@ pub mod epoch_info {
         pub fn validators_len(&self) -> usize {
             self.validators.len()
         }
+
+        /// Random values the shuffling seed of this epoch was derived from, if they were stored.
+        #[inline]
+        pub fn rng_seed_inputs(&self) -> Option<&[CryptoHash]> {
+            None
+        }
     }
 
     #[derive(BorshSerialize, BorshDeserialize)]
@@ -896,6 +1131,11 @@ pub mod epoch_info {
         pub validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
         /// Protocol version for next epoch.
         pub next_version: ProtocolVersion,
+        /// Chunk production stats by shard of the validators in `validator_block_chunk_stats`.
+        pub validator_shard_chunk_stats: HashMap<AccountId, ShardChunkValidatorStats>,
+        /// Curve the rewards of the validators were computed with, `None` for the epochs
+        /// summarized before it was recorded.
+        pub uptime_curve: Option<UptimeCurve>,
     }
 }
 
@@ -943,4 +1183,20 @@ pub enum SlashState {
     AlreadySlashed,
     /// All other cases (tokens should be entirely slashed),
     Other,
+    /// Slash was cancelled by counter-evidence, the tokens escrowed by the slash in the block
+    /// with the given hash should be returned.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    Appealed(CryptoHash),
+}
+
+impl SlashState {
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    pub fn is_appealed(&self) -> bool {
+        matches!(self, SlashState::Appealed(_))
+    }
+
+    #[cfg(not(feature = "protocol_feature_slashing_escrow"))]
+    pub fn is_appealed(&self) -> bool {
+        false
+    }
 }
@@ -1,5 +1,6 @@
 use crate::serialize::u128_dec_format;
-use crate::types::{AccountId, Balance, EpochId, Gas, Nonce};
+use crate::types::{AccountId, Balance, BlockHeight, EpochId, Gas, Nonce, ShardId, StorageUsage};
+use crate::version::ProtocolVersion;
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::PublicKey;
 use serde::{Deserialize, Serialize};
@@ -145,6 +146,13 @@ pub enum InvalidTxError {
     ActionsValidation(ActionsValidationError),
     /// The size of serialized transaction exceeded the limit.
     TransactionSizeExceeded { size: u64, limit: u64 },
+    /// The number of actions of the transaction exceeded the limit.
+    NumberOfActionsExceeded { number_of_actions: u64, limit: u64 },
+    /// The receiver of the transaction is in the shard of the signer and has too many delayed
+    /// receipts.
+    ReceiverCongested { receiver_id: AccountId, delayed_receipts: u64, limit: u64 },
+    /// The transaction can't be included after the height of its `ValidUntil` action.
+    ValidUntilExceeded { valid_until: BlockHeight, block_height: BlockHeight },
 }
 
 #[derive(
@@ -201,6 +209,18 @@ pub enum ActionsValidationError {
     UnsuitableStakingKey { public_key: PublicKey },
     /// The attached amount of gas in a FunctionCall action has to be a positive number.
     FunctionCallZeroAttachedGas,
+    /// More than one DeployContract action in the list of actions.
+    DuplicateDeployContract { index: u64 },
+    /// The same access key is added more than once in the list of actions.
+    DuplicateAddKey { public_key: PublicKey, index: u64 },
+    /// The same access key is deleted more than once in the list of actions.
+    DuplicateDeleteKey { public_key: PublicKey, index: u64 },
+    /// The action isn't supported by the current protocol version.
+    UnsupportedProtocolFeature { protocol_feature: String, version: ProtocolVersion },
+    /// The MaxStorageDelta action isn't the first action, or there are several of them.
+    MaxStorageDeltaMustBeFirst { index: u64 },
+    /// A method name of an Add Key action has a `*` which isn't its last character.
+    AddKeyInvalidMethodNamePattern { method_name: String },
 }
 
 /// Describes the error for validating a receipt.
@@ -315,6 +335,38 @@ impl Display for ActionsValidationError {
                 f,
                 "The attached amount of gas in a FunctionCall action has to be a positive number",
             ),
+            ActionsValidationError::DuplicateDeployContract { index } => write!(
+                f,
+                "The DeployContract action at index {} deploys a contract for the second time",
+                index,
+            ),
+            ActionsValidationError::DuplicateAddKey { public_key, index } => write!(
+                f,
+                "The AddKey action at index {} adds the key {} for the second time",
+                index, public_key,
+            ),
+            ActionsValidationError::DuplicateDeleteKey { public_key, index } => write!(
+                f,
+                "The DeleteKey action at index {} deletes the key {} for the second time",
+                index, public_key,
+            ),
+            ActionsValidationError::UnsupportedProtocolFeature { protocol_feature, version } => {
+                write!(
+                    f,
+                    "Protocol feature {} is unsupported in version {}",
+                    protocol_feature, version
+                )
+            }
+            ActionsValidationError::MaxStorageDeltaMustBeFirst { index } => write!(
+                f,
+                "The MaxStorageDelta action at index {} must be the first action",
+                index
+            ),
+            ActionsValidationError::AddKeyInvalidMethodNamePattern { method_name } => write!(
+                f,
+                "The method name pattern {:?} of an Add Key action may only end with `*`",
+                method_name
+            ),
         }
     }
 }
@@ -433,6 +485,26 @@ pub enum ActionErrorKind {
     OnlyImplicitAccountCreationAllowed { account_id: AccountId },
     /// Delete account whose state is large is temporarily banned.
     DeleteAccountWithLargeState { account_id: AccountId },
+    /// The postponed receipt didn't receive all its input data within the timeout.
+    PostponedReceiptExpired { account_id: AccountId },
+    /// The storage usage of the account grew by more than the MaxStorageDelta action allows.
+    MaxStorageDeltaExceeded {
+        account_id: AccountId,
+        storage_delta: StorageUsage,
+        max_storage_delta: StorageUsage,
+    },
+    /// No code is published in the shard of the account under the hash given to the
+    /// UseGlobalContract action.
+    GlobalContractDoesNotExist { code_hash: CryptoHash },
+    /// The deployed contract imports a host function which the current protocol version doesn't
+    /// provide, `allowed_since` is the first protocol version providing it, if any.
+    ContractImportNotAllowed {
+        account_id: AccountId,
+        import: String,
+        allowed_since: Option<ProtocolVersion>,
+    },
+    /// The receipt of a transaction reached a receiver with too many delayed receipts.
+    ReceiverCongested { receiver_id: AccountId, delayed_receipts: u64, limit: u64 },
 }
 
 impl From<ActionErrorKind> for ActionError {
@@ -494,6 +566,21 @@ impl Display for InvalidTxError {
             InvalidTxError::TransactionSizeExceeded { size, limit } => {
                 write!(f, "Size of serialized transaction {} exceeded the limit {}", size, limit)
             }
+            InvalidTxError::NumberOfActionsExceeded { number_of_actions, limit } => write!(
+                f,
+                "Number of actions of the transaction {} exceeded the limit {}",
+                number_of_actions, limit
+            ),
+            InvalidTxError::ReceiverCongested { receiver_id, delayed_receipts, limit } => write!(
+                f,
+                "Receiver {} has {} delayed receipts, which reached the limit {}",
+                receiver_id, delayed_receipts, limit
+            ),
+            InvalidTxError::ValidUntilExceeded { valid_until, block_height } => write!(
+                f,
+                "Transaction is valid until height {}, but would be included at height {}",
+                valid_until, block_height
+            ),
         }
     }
 }
@@ -576,6 +663,29 @@ pub struct BalanceMismatchError {
     pub slashed_burnt_amount: Balance,
     #[serde(with = "u128_dec_format")]
     pub other_burnt_amount: Balance,
+    /// The accounts whose balance changed. Only reported with the `balance_checker_debug`
+    /// feature of the runtime.
+    pub account_diffs: Vec<AccountBalanceDiff>,
+}
+
+/// The balance of an account before and after applying a chunk, including its locked balance and
+/// its escrowed slashed stake.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccountBalanceDiff {
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub initial_balance: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub final_balance: Balance,
+}
+
+/// Formats the signed difference `to - from` of two balances.
+fn balance_delta(from: Balance, to: Balance) -> String {
+    if to >= from {
+        format!("+{}", to - from)
+    } else {
+        format!("-{}", from - to)
+    }
 }
 
 impl Display for BalanceMismatchError {
@@ -597,7 +707,7 @@ impl Display for BalanceMismatchError {
             .saturating_add(self.other_burnt_amount);
         write!(
             f,
-            "Balance Mismatch Error. The input balance {} doesn't match output balance {}\n\
+            "Balance Mismatch Error. The input balance {} doesn't match output balance {} ({})\n\
              Inputs:\n\
              \tIncoming validator rewards sum: {}\n\
              \tInitial accounts balance sum: {}\n\
@@ -611,9 +721,11 @@ impl Display for BalanceMismatchError {
              \tFinal postponed receipts balance sum: {}\n\
              \tTx fees burnt amount: {}\n\
              \tSlashed amount: {}\n\
-             \tOther burnt amount: {}",
+             \tOther burnt amount: {}\n\
+             Accounts balance delta: {}",
             initial_balance,
             final_balance,
+            balance_delta(initial_balance, final_balance),
             self.incoming_validator_rewards,
             self.initial_accounts_balance,
             self.incoming_receipts_balance,
@@ -626,7 +738,19 @@ impl Display for BalanceMismatchError {
             self.tx_burnt_amount,
             self.slashed_burnt_amount,
             self.other_burnt_amount,
-        )
+            balance_delta(self.initial_accounts_balance, self.final_accounts_balance),
+        )?;
+        for diff in self.account_diffs.iter() {
+            write!(
+                f,
+                "\n\t{}: {} -> {} ({})",
+                diff.account_id,
+                diff.initial_balance,
+                diff.final_balance,
+                balance_delta(diff.initial_balance, diff.final_balance),
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -734,6 +858,12 @@ impl Display for ActionErrorKind {
             ActionErrorKind::InsufficientStake { account_id, stake, minimum_stake } => write!(f, "Account {} tries to stake {} but minimum required stake is {}", account_id, stake, minimum_stake),
             ActionErrorKind::OnlyImplicitAccountCreationAllowed { account_id } => write!(f, "CreateAccount action is called on hex-characters account of length 64 {}", account_id),
             ActionErrorKind::DeleteAccountWithLargeState { account_id } => write!(f, "The state of account {} is too large and therefore cannot be deleted", account_id),
+            ActionErrorKind::PostponedReceiptExpired { account_id } => write!(f, "The receipt to account {} didn't receive its input data in time", account_id),
+            ActionErrorKind::MaxStorageDeltaExceeded { account_id, storage_delta, max_storage_delta } => write!(f, "The storage usage of account {} grew by {} bytes, but at most {} bytes are allowed", account_id, storage_delta, max_storage_delta),
+            ActionErrorKind::GlobalContractDoesNotExist { code_hash } => write!(f, "No global contract code with hash {} is published in the shard", code_hash),
+            ActionErrorKind::ContractImportNotAllowed { account_id, import, allowed_since: Some(version) } => write!(f, "The contract deployed to account {} imports the host function {}, which is only provided since protocol version {}", account_id, import, version),
+            ActionErrorKind::ContractImportNotAllowed { account_id, import, allowed_since: None } => write!(f, "The contract deployed to account {} imports the host function {}, which isn't provided by any protocol version", account_id, import),
+            ActionErrorKind::ReceiverCongested { receiver_id, delayed_receipts, limit } => write!(f, "Receiver {} has {} delayed receipts, which reached the limit {}", receiver_id, delayed_receipts, limit),
         }
     }
 }
@@ -751,6 +881,10 @@ pub enum EpochError {
     IOErr(String),
     /// Given account ID is not a validator in the given epoch ID.
     NotAValidator(AccountId, EpochId),
+    /// The genesis validators and config don't assign any chunk producer to the shard.
+    ShardWithoutChunkProducers(ShardId),
+    /// The validator set override can't be applied, e.g. a stake of it isn't locked.
+    InvalidValidatorSetOverride(String),
 }
 
 impl std::error::Error for EpochError {}
@@ -771,6 +905,12 @@ impl Display for EpochError {
             EpochError::NotAValidator(account_id, epoch_id) => {
                 write!(f, "{} is not a validator in epoch {:?}", account_id, epoch_id)
             }
+            EpochError::ShardWithoutChunkProducers(shard_id) => {
+                write!(f, "Genesis epoch has no chunk producers for shard {}", shard_id)
+            }
+            EpochError::InvalidValidatorSetOverride(err) => {
+                write!(f, "Invalid validator set override: {}", err)
+            }
         }
     }
 }
@@ -787,6 +927,12 @@ impl Debug for EpochError {
             EpochError::NotAValidator(account_id, epoch_id) => {
                 write!(f, "NotAValidator({}, {:?})", account_id, epoch_id)
             }
+            EpochError::ShardWithoutChunkProducers(shard_id) => {
+                write!(f, "ShardWithoutChunkProducers({})", shard_id)
+            }
+            EpochError::InvalidValidatorSetOverride(err) => {
+                write!(f, "InvalidValidatorSetOverride({})", err)
+            }
         }
     }
 }
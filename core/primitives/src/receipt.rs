@@ -11,7 +11,7 @@ use crate::hash::CryptoHash;
 use crate::logging;
 use crate::serialize::{option_base64_format, u128_dec_format_compatible};
 use crate::transaction::{Action, TransferAction};
-use crate::types::{AccountId, Balance, ShardId};
+use crate::types::{AccountId, Balance, BlockHeight, EpochHeight, ShardId};
 use crate::utils::system_account;
 
 /// Receipts are used for a cross-shard communication.
@@ -87,6 +87,26 @@ impl Receipt {
             }),
         }
     }
+
+    /// Returns `true` for receipts that are issued on behalf of the protocol rather than a user:
+    /// balance and gas refunds, and action receipts that only change the stake of the receiver.
+    /// Such receipts are processed ahead of the user receipts once
+    /// `ProtocolFeature::PriorityReceipts` is enabled.
+    pub fn is_priority(&self) -> bool {
+        if self.predecessor_id == system_account() {
+            return true;
+        }
+        match &self.receipt {
+            ReceiptEnum::Action(action_receipt) => {
+                !action_receipt.actions.is_empty()
+                    && action_receipt
+                        .actions
+                        .iter()
+                        .all(|action| matches!(action, Action::Stake(_)))
+            }
+            ReceiptEnum::Data(_) => false,
+        }
+    }
 }
 
 /// Receipt could be either ActionReceipt or DataReceipt
@@ -172,5 +192,17 @@ pub struct DelayedReceiptIndices {
     pub next_available_index: u64,
 }
 
+/// Entry of the persistent queue of postponed receipts, which fails the receipts waiting for
+/// their input data for too long.
+#[derive(Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq)]
+pub struct PostponedReceiptExpiry {
+    pub receiver_id: AccountId,
+    pub receipt_id: CryptoHash,
+    /// Height of the block the receipt was postponed at.
+    pub postponed_height: BlockHeight,
+    /// Height of the epoch the receipt was postponed at, the timeout is counted from it.
+    pub postponed_epoch_height: EpochHeight,
+}
+
 /// Map of shard to list of receipts to send to it.
 pub type ReceiptResult = HashMap<ShardId, Vec<Receipt>>;
@@ -1,13 +1,54 @@
+use crate::errors::ActionError;
+use crate::receipt::Receipt;
 use crate::runtime::migration_data::{MigrationData, MigrationFlags};
+use crate::transaction::{Action, ExecutionOutcomeWithId, SignedTransaction};
 use crate::{
     hash::CryptoHash,
     runtime::config::RuntimeConfig,
     types::{Balance, BlockHeight, CompiledContractCache, EpochHeight, EpochId, Gas},
     version::ProtocolVersion,
 };
+use std::fmt;
 use std::sync::Arc;
 
-#[derive(Debug)]
+/// Receives the execution events of a state transition, so indexers, debuggers or simulators
+/// can follow the execution without patching the runtime. The events of receipts which are
+/// delayed or postponed are emitted when the receipts are executed.
+pub trait ApplyObserver: Send + Sync {
+    /// The transaction was verified and converted into a receipt.
+    fn on_transaction_processed(
+        &self,
+        _transaction: &SignedTransaction,
+        _outcome: &ExecutionOutcomeWithId,
+    ) {
+    }
+
+    /// Execution of the action receipt started.
+    fn on_receipt_started(&self, _receipt: &Receipt) {}
+
+    /// The action of the receipt was executed. Actions after the first failed one are not
+    /// executed.
+    fn on_action_executed(
+        &self,
+        _receipt: &Receipt,
+        _action_index: usize,
+        _action: &Action,
+        _gas_burnt: Gas,
+        _error: Option<&ActionError>,
+    ) {
+    }
+
+    /// Execution of the action receipt finished.
+    fn on_receipt_finished(&self, _receipt: &Receipt, _outcome: &ExecutionOutcomeWithId) {}
+}
+
+impl fmt::Debug for dyn ApplyObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Apply observer")
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct ApplyState {
     /// Currently building block height.
     // TODO #1903 pub block_height: BlockHeight,
@@ -29,6 +70,8 @@ pub struct ApplyState {
     pub gas_limit: Option<Gas>,
     /// Current random seed (from current block vrf output).
     pub random_seed: CryptoHash,
+    /// Random seed of the current epoch: the vrf output of the last block of the previous epoch.
+    pub epoch_random_seed: CryptoHash,
     /// Current Protocol version when we apply the state transition
     pub current_protocol_version: ProtocolVersion,
     /// The Runtime config to use for the current transition.
@@ -47,4 +90,6 @@ pub struct ApplyState {
     pub migration_data: Arc<MigrationData>,
     /// Flags for migrations indicating whether they can be applied at this block
     pub migration_flags: MigrationFlags,
+    /// Receives the execution events of the state transition.
+    pub observer: Option<Arc<dyn ApplyObserver>>,
 }
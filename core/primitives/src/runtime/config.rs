@@ -5,7 +5,7 @@ use crate::checked_feature;
 use crate::config::VMConfig;
 use crate::runtime::fees::RuntimeFeesConfig;
 use crate::serialize::u128_dec_format;
-use crate::types::{AccountId, Balance, Gas};
+use crate::types::{AccountId, Balance, EpochHeight, Gas};
 use crate::version::ProtocolVersion;
 use std::sync::Arc;
 
@@ -24,6 +24,13 @@ pub struct RuntimeConfig {
     pub wasm_config: VMConfig,
     /// Config that defines rules for account creation.
     pub account_creation_config: AccountCreationConfig,
+    /// Number of epochs a postponed receipt waits for its input data before it's failed and
+    /// refunded. Disabled if 0.
+    pub postponed_receipt_timeout_epochs: EpochHeight,
+    /// Maximum number of delayed receipts to a single receiver. The new transactions to a receiver
+    /// over this backlog in the shard of the signer are rejected, the receipts of the ones from
+    /// the other shards are failed and refunded. Disabled if 0.
+    pub max_delayed_receipts_per_account: u64,
 }
 
 impl Default for RuntimeConfig {
@@ -34,6 +41,8 @@ impl Default for RuntimeConfig {
             transaction_costs: RuntimeFeesConfig::default(),
             wasm_config: VMConfig::default(),
             account_creation_config: AccountCreationConfig::default(),
+            postponed_receipt_timeout_epochs: 100,
+            max_delayed_receipts_per_account: 1_000,
         }
     }
 }
@@ -45,6 +54,8 @@ impl RuntimeConfig {
             transaction_costs: RuntimeFeesConfig::free(),
             wasm_config: VMConfig::free(),
             account_creation_config: AccountCreationConfig::default(),
+            postponed_receipt_timeout_epochs: 100,
+            max_delayed_receipts_per_account: 1_000,
         }
     }
 }
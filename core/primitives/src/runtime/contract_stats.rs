@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::hash::CryptoHash;
+use crate::types::Gas;
+
+/// Execution statistics of the function calls of a contract. They are collected by the node for
+/// diagnostics only and are not part of the state, so they may include wall-clock time.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq,
+)]
+pub struct ContractExecutionStats {
+    pub calls: u64,
+    pub total_gas_burnt: Gas,
+    pub total_wall_time_nanos: u64,
+    /// Number of failed calls by the kind of the failure, e.g. `WasmTrap` or `HostError`.
+    pub failures: BTreeMap<String, u64>,
+}
+
+impl ContractExecutionStats {
+    pub fn record_call(&mut self, gas_burnt: Gas, wall_time: Duration, failure: Option<&str>) {
+        self.calls = self.calls.saturating_add(1);
+        self.total_gas_burnt = self.total_gas_burnt.saturating_add(gas_burnt);
+        self.total_wall_time_nanos =
+            self.total_wall_time_nanos.saturating_add(wall_time.as_nanos() as u64);
+        if let Some(failure) = failure {
+            let failures = self.failures.entry(failure.to_string()).or_default();
+            *failures = failures.saturating_add(1);
+        }
+    }
+
+    pub fn merge(&mut self, other: &ContractExecutionStats) {
+        self.calls = self.calls.saturating_add(other.calls);
+        self.total_gas_burnt = self.total_gas_burnt.saturating_add(other.total_gas_burnt);
+        self.total_wall_time_nanos =
+            self.total_wall_time_nanos.saturating_add(other.total_wall_time_nanos);
+        for (failure, count) in other.failures.iter() {
+            let failures = self.failures.entry(failure.clone()).or_default();
+            *failures = failures.saturating_add(*count);
+        }
+    }
+
+    pub fn average_gas_burnt(&self) -> Gas {
+        if self.calls == 0 {
+            0
+        } else {
+            self.total_gas_burnt / self.calls
+        }
+    }
+
+    pub fn average_wall_time_nanos(&self) -> u64 {
+        if self.calls == 0 {
+            0
+        } else {
+            self.total_wall_time_nanos / self.calls
+        }
+    }
+}
+
+/// Execution statistics of contracts by the hash of their code.
+pub type ContractStats = HashMap<CryptoHash, ContractExecutionStats>;
+
+/// Adds the statistics of `other` to `stats`.
+pub fn merge_contract_stats(stats: &mut ContractStats, other: &ContractStats) {
+    for (code_hash, other_stats) in other.iter() {
+        stats.entry(*code_hash).or_default().merge(other_stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contract_execution_stats() {
+        let mut stats = ContractExecutionStats::default();
+        assert_eq!((stats.average_gas_burnt(), stats.average_wall_time_nanos()), (0, 0));
+        stats.record_call(100, Duration::from_nanos(10), None);
+        stats.record_call(300, Duration::from_nanos(30), Some("WasmTrap"));
+
+        let mut other = ContractExecutionStats::default();
+        other.record_call(200, Duration::from_nanos(20), Some("WasmTrap"));
+        other.record_call(200, Duration::from_nanos(20), Some("HostError"));
+        stats.merge(&other);
+
+        assert_eq!(stats.calls, 4);
+        assert_eq!(stats.average_gas_burnt(), 200);
+        assert_eq!(stats.average_wall_time_nanos(), 20);
+        assert_eq!(stats.failures.get("WasmTrap"), Some(&2));
+        assert_eq!(stats.failures.get("HostError"), Some(&1));
+    }
+}
@@ -1,7 +1,21 @@
+#[cfg(feature = "protocol_feature_system_calls")]
+use crate::hash::hash;
 #[cfg(feature = "protocol_feature_restore_receipts_after_fix")]
 use crate::receipt::ReceiptResult;
+#[cfg(feature = "protocol_feature_system_calls")]
+use crate::receipt::{ActionReceipt, Receipt, ReceiptEnum};
+#[cfg(feature = "protocol_feature_system_calls")]
+use crate::transaction::{Action, FunctionCallAction};
 use crate::types::AccountId;
+#[cfg(feature = "protocol_feature_system_calls")]
+use crate::types::BlockHeight;
 use crate::types::Gas;
+#[cfg(feature = "protocol_feature_system_calls")]
+use crate::utils::system_account;
+#[cfg(feature = "protocol_feature_system_calls")]
+use near_crypto::{KeyType, PublicKey};
+#[cfg(feature = "protocol_feature_system_calls")]
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 
@@ -11,6 +25,9 @@ pub struct MigrationData {
     pub storage_usage_fix_gas: Gas,
     #[cfg(feature = "protocol_feature_restore_receipts_after_fix")]
     pub restored_receipts: ReceiptResult,
+    /// Maintenance calls executed at the start of the chunk at the given heights.
+    #[cfg(feature = "protocol_feature_system_calls")]
+    pub system_calls: BTreeMap<BlockHeight, Vec<SystemCall>>,
 }
 
 impl Debug for MigrationData {
@@ -19,7 +36,48 @@ impl Debug for MigrationData {
     }
 }
 
-#[derive(Debug, Default)]
+/// Call of a whitelisted maintenance method, e.g. cleaning up the state left by a deleted
+/// contract, executed by the protocol without anyone paying for its gas.
+#[cfg(feature = "protocol_feature_system_calls")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemCall {
+    pub receiver_id: AccountId,
+    pub method_name: String,
+    pub args: Vec<u8>,
+    pub gas: Gas,
+}
+
+#[cfg(feature = "protocol_feature_system_calls")]
+impl SystemCall {
+    /// Receipt executing the call, where `index` is the position of the call among the calls
+    /// scheduled at `block_height`. Like refunds, it comes from the system account at zero gas
+    /// price, so its gas is free and it doesn't generate refunds.
+    pub fn to_receipt(&self, block_height: BlockHeight, index: usize) -> Receipt {
+        let mut receipt_id_seed = b"system_call".to_vec();
+        receipt_id_seed.extend_from_slice(&block_height.to_le_bytes());
+        receipt_id_seed.extend_from_slice(&(index as u64).to_le_bytes());
+        Receipt {
+            predecessor_id: system_account(),
+            receiver_id: self.receiver_id.clone(),
+            receipt_id: hash(&receipt_id_seed),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: system_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(FunctionCallAction {
+                    method_name: self.method_name.clone(),
+                    args: self.args.clone(),
+                    gas: self.gas,
+                    deposit: 0,
+                })],
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct MigrationFlags {
     // True iff the current block is the first one in the chain with current protocol version
     pub is_first_block_of_version: bool,
@@ -5,6 +5,7 @@ use crate::types::Balance;
 pub use near_primitives_core::runtime::*;
 pub mod apply_state;
 pub mod config;
+pub mod contract_stats;
 pub use near_primitives_core::runtime::fees;
 pub mod migration_data;
 
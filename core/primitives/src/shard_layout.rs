@@ -0,0 +1,216 @@
+//! Layouts of the shards, i.e. how accounts are assigned to shards.
+//!
+//! The layout of the genesis is `V0`, which assigns accounts to shards by the hash of the account
+//! id. Resharding switches the chain to a layout with ranges of account ids at an epoch boundary:
+//! the state of the shards of the previous layout is split or merged into the shards of the new
+//! one. Every layout has a version, so the shards of different layouts are never confused.
+use std::cmp::Ordering;
+use std::convert::TryInto;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::checked_feature;
+use crate::hash::hash;
+use crate::types::{AccountId, NumShards, ShardId};
+use crate::version::ProtocolVersion;
+
+pub type ShardVersion = u32;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ShardLayout {
+    V0(ShardLayoutV0),
+    V1(ShardLayoutV1),
+}
+
+/// Accounts are assigned to shards by the hash of the account id.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShardLayoutV0 {
+    pub num_shards: NumShards,
+    pub version: ShardVersion,
+}
+
+/// Accounts are assigned to shards by ranges of account ids. Shard `i` has the accounts from
+/// `boundary_accounts[i - 1]` inclusive to `boundary_accounts[i]` exclusive, so there is one
+/// shard more than there are boundary accounts.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShardLayoutV1 {
+    pub boundary_accounts: Vec<AccountId>,
+    pub version: ShardVersion,
+}
+
+impl ShardLayout {
+    pub fn v0(num_shards: NumShards, version: ShardVersion) -> Self {
+        ShardLayout::V0(ShardLayoutV0 { num_shards, version })
+    }
+
+    /// `boundary_accounts` must be sorted.
+    pub fn v1(boundary_accounts: Vec<AccountId>, version: ShardVersion) -> Self {
+        debug_assert!(boundary_accounts.windows(2).all(|pair| pair[0] < pair[1]));
+        ShardLayout::V1(ShardLayoutV1 { boundary_accounts, version })
+    }
+
+    /// Returns the layout of the shards of a chain with `num_shards` shards at genesis at the
+    /// given protocol version. The layout of the genesis is kept until the protocol version has
+    /// resharding.
+    pub fn for_protocol_version(
+        num_shards: NumShards,
+        resharding_shard_layout: Option<&ShardLayout>,
+        protocol_version: ProtocolVersion,
+    ) -> Self {
+        match resharding_shard_layout {
+            Some(shard_layout)
+                if checked_feature!(
+                    "protocol_feature_resharding",
+                    Resharding,
+                    protocol_version
+                ) =>
+            {
+                shard_layout.clone()
+            }
+            _ => ShardLayout::v0(num_shards, 0),
+        }
+    }
+
+    pub fn num_shards(&self) -> NumShards {
+        match self {
+            ShardLayout::V0(layout) => layout.num_shards,
+            ShardLayout::V1(layout) => layout.boundary_accounts.len() as NumShards + 1,
+        }
+    }
+
+    pub fn version(&self) -> ShardVersion {
+        match self {
+            ShardLayout::V0(layout) => layout.version,
+            ShardLayout::V1(layout) => layout.version,
+        }
+    }
+
+    /// Returns the shards of `next_layout` which get accounts of the shard `shard_id` of this
+    /// layout. A shard with several children is split, a shard which is a child of several shards
+    /// is merged from them.
+    pub fn get_children_shards(
+        &self,
+        shard_id: ShardId,
+        next_layout: &ShardLayout,
+    ) -> Vec<ShardId> {
+        match (self, next_layout) {
+            (ShardLayout::V1(layout), ShardLayout::V1(next_layout)) => {
+                let (start, end) = layout.account_range(shard_id);
+                (0..next_layout.boundary_accounts.len() as ShardId + 1)
+                    .filter(|child_shard_id| {
+                        let (child_start, child_end) = next_layout.account_range(*child_shard_id);
+                        range_start_before_end(start, child_end)
+                            && range_start_before_end(child_start, end)
+                    })
+                    .collect()
+            }
+            // Accounts of a shard with hashed account ids may go to any shard.
+            _ => (0..next_layout.num_shards()).collect(),
+        }
+    }
+
+    /// Returns the shards of this layout which have accounts of the shard `shard_id` of
+    /// `next_layout`.
+    pub fn get_parent_shards(&self, shard_id: ShardId, next_layout: &ShardLayout) -> Vec<ShardId> {
+        (0..self.num_shards())
+            .filter(|parent_shard_id| {
+                self.get_children_shards(*parent_shard_id, next_layout).contains(&shard_id)
+            })
+            .collect()
+    }
+}
+
+impl ShardLayoutV1 {
+    /// Returns the first account of the shard and the first account after it, `None` if the shard
+    /// is unbounded on that side.
+    fn account_range(&self, shard_id: ShardId) -> (Option<&AccountId>, Option<&AccountId>) {
+        let shard_id = shard_id as usize;
+        let start = if shard_id == 0 { None } else { self.boundary_accounts.get(shard_id - 1) };
+        (start, self.boundary_accounts.get(shard_id))
+    }
+}
+
+/// Whether the range starting at `start` and the range ending at `end` (exclusive) intersect.
+fn range_start_before_end(start: Option<&AccountId>, end: Option<&AccountId>) -> bool {
+    match (start, end) {
+        (Some(start), Some(end)) => start.cmp(end) == Ordering::Less,
+        _ => true,
+    }
+}
+
+pub fn account_id_to_shard_id(account_id: &AccountId, shard_layout: &ShardLayout) -> ShardId {
+    match shard_layout {
+        ShardLayout::V0(ShardLayoutV0 { num_shards, .. }) => {
+            let hash = hash(account_id.as_bytes());
+            u64::from_le_bytes(hash.0[..8].try_into().unwrap()) % num_shards
+        }
+        ShardLayout::V1(ShardLayoutV1 { boundary_accounts, .. }) => {
+            boundary_accounts.iter().take_while(|boundary| *boundary <= account_id).count()
+                as ShardId
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts(accounts: &[&str]) -> Vec<AccountId> {
+        accounts.iter().map(|account| account.to_string()).collect()
+    }
+
+    #[test]
+    fn test_account_id_to_shard_id_v1() {
+        let layout = ShardLayout::v1(accounts(&["h", "p"]), 1);
+        assert_eq!(layout.num_shards(), 3);
+        let shards = accounts(&["alice", "h", "harry", "p", "zoe"])
+            .iter()
+            .map(|account_id| account_id_to_shard_id(account_id, &layout))
+            .collect::<Vec<_>>();
+        assert_eq!(shards, vec![0, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn test_account_id_to_shard_id_v0() {
+        let layout = ShardLayout::v0(4, 0);
+        for account_id in accounts(&["alice", "bob", "test0", "test1"]) {
+            let shard_id = account_id_to_shard_id(&account_id, &layout);
+            assert!(shard_id < 4);
+            assert_eq!(account_id_to_shard_id(&account_id, &ShardLayout::v0(1, 0)), 0);
+        }
+    }
+
+    #[test]
+    fn test_children_shards() {
+        let layout = ShardLayout::v1(accounts(&["h", "p"]), 1);
+        // The first shard is split in two.
+        let split_layout = ShardLayout::v1(accounts(&["d", "h", "p"]), 2);
+        assert_eq!(layout.get_children_shards(0, &split_layout), vec![0, 1]);
+        assert_eq!(layout.get_children_shards(1, &split_layout), vec![2]);
+        assert_eq!(layout.get_children_shards(2, &split_layout), vec![3]);
+        assert_eq!(layout.get_parent_shards(1, &split_layout), vec![0]);
+        // The last two shards are merged.
+        let merged_layout = ShardLayout::v1(accounts(&["h"]), 2);
+        assert_eq!(layout.get_children_shards(2, &merged_layout), vec![1]);
+        assert_eq!(layout.get_parent_shards(1, &merged_layout), vec![1, 2]);
+        // Hashed account ids may go anywhere.
+        assert_eq!(ShardLayout::v0(2, 0).get_children_shards(1, &layout), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_shard_layout_for_protocol_version() {
+        let layout = ShardLayout::v1(accounts(&["h", "p"]), 1);
+        assert_eq!(ShardLayout::for_protocol_version(4, None, 0), ShardLayout::v0(4, 0));
+        assert_eq!(ShardLayout::for_protocol_version(4, Some(&layout), 0), ShardLayout::v0(4, 0));
+        #[cfg(feature = "protocol_feature_resharding")]
+        assert_eq!(
+            ShardLayout::for_protocol_version(
+                4,
+                Some(&layout),
+                crate::version::ProtocolFeature::Resharding.protocol_version()
+            ),
+            layout
+        );
+    }
+}
@@ -5,7 +5,7 @@ use serde::Serialize;
 use near_crypto::Signature;
 
 use crate::hash::{hash, CryptoHash};
-use crate::merkle::{combine_hash, merklize, MerklePath};
+use crate::merkle::{combine_hash, merklize, verify_path, MerklePath};
 use crate::receipt::Receipt;
 use crate::transaction::SignedTransaction;
 use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, ValidatorStakeV1};
@@ -739,6 +739,17 @@ pub struct ShardProof {
 /// For each Merkle proof there is a subset of receipts which may be proven.
 pub struct ReceiptProof(pub Vec<Receipt>, pub ShardProof);
 
+impl ReceiptProof {
+    /// Checks that the receipts are all the outgoing receipts to `to_shard_id` of a chunk with the
+    /// given `outgoing_receipts_root`.
+    pub fn verify_against_receipt_root(&self, outgoing_receipts_root: CryptoHash) -> bool {
+        let ReceiptProof(receipts, shard_proof) = self;
+        let receipts_hash =
+            hash(&ReceiptList(shard_proof.to_shard_id, receipts).try_to_vec().unwrap());
+        verify_path(outgoing_receipts_root, &shard_proof.proof, &receipts_hash)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Eq, PartialEq)]
 pub struct PartialEncodedChunkPart {
     pub part_ord: u64,
@@ -1310,3 +1321,39 @@ impl ReedSolomonWrapper {
         self.rs.total_shard_count()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_receipt_proof() {
+        let receipts = vec![
+            vec![Receipt::new_balance_refund(&"alice".to_string(), 1)],
+            vec![],
+            vec![Receipt::new_balance_refund(&"bob".to_string(), 2)],
+        ];
+        let hashes = receipts
+            .iter()
+            .enumerate()
+            .map(|(shard_id, receipts)| {
+                hash(&ReceiptList(shard_id as ShardId, receipts).try_to_vec().unwrap())
+            })
+            .collect::<Vec<_>>();
+        let (root, proofs) = merklize(&hashes);
+        let receipt_proof = |to_shard_id: usize, receipts: Vec<Receipt>| {
+            let shard_proof = ShardProof {
+                from_shard_id: 0,
+                to_shard_id: to_shard_id as ShardId,
+                proof: proofs[to_shard_id].clone(),
+            };
+            ReceiptProof(receipts, shard_proof)
+        };
+        for (to_shard_id, receipts) in receipts.iter().enumerate() {
+            assert!(receipt_proof(to_shard_id, receipts.clone()).verify_against_receipt_root(root));
+        }
+        // Receipts can be neither added nor hidden.
+        assert!(!receipt_proof(1, receipts[0].clone()).verify_against_receipt_root(root));
+        assert!(!receipt_proof(2, vec![]).verify_against_receipt_root(root));
+    }
+}
@@ -96,6 +96,16 @@ impl StateRecord {
                 Some(StateRecord::DelayedReceipt(Box::new(receipt)))
             }
             col::DELAYED_RECEIPT_INDICES => None,
+            // The delayed priority receipts are imported into the delayed receipts queue.
+            col::DELAYED_PRIORITY_RECEIPT => {
+                let receipt = Receipt::try_from_slice(&value).unwrap();
+                Some(StateRecord::DelayedReceipt(Box::new(receipt)))
+            }
+            col::DELAYED_PRIORITY_RECEIPT_INDICES => None,
+            // The global contract code isn't exported to the state records yet.
+            col::GLOBAL_CONTRACT_CODE => None,
+            // The burn ledger starts over with the new genesis.
+            col::BURN_LEDGER => None,
             _ => unreachable!(),
         }
     }
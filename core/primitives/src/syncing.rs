@@ -3,7 +3,7 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use crate::block_header::BlockHeader;
 use crate::epoch_manager::block_info::BlockInfo;
 use crate::epoch_manager::epoch_info::EpochInfo;
-use crate::hash::CryptoHash;
+use crate::hash::{hash, CryptoHash};
 use crate::merkle::{MerklePath, PartialMerkleTree};
 use crate::receipt::Receipt;
 use crate::sharding::{
@@ -27,6 +27,27 @@ pub struct StateHeaderKey(pub ShardId, pub CryptoHash);
 #[derive(PartialEq, Eq, Clone, Debug, BorshSerialize, BorshDeserialize)]
 pub struct StatePartKey(pub CryptoHash, pub ShardId, pub u64 /* PartId */);
 
+/// Parts of the shard state which were downloaded and validated against the state root.
+/// Persisted so that state sync can be resumed after a restart.
+#[derive(PartialEq, Eq, Clone, Debug, Default, BorshSerialize, BorshDeserialize)]
+pub struct StateSyncProgress {
+    /// Whether the part with the given id was downloaded.
+    pub parts_done: Vec<bool>,
+}
+
+impl StateSyncProgress {
+    pub fn is_done(&self, part_id: u64) -> bool {
+        self.parts_done.get(part_id as usize).cloned().unwrap_or(false)
+    }
+
+    pub fn set_done(&mut self, part_id: u64, num_parts: u64) {
+        self.parts_done.resize(num_parts as usize, false);
+        if let Some(done) = self.parts_done.get_mut(part_id as usize) {
+            *done = true;
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct ShardStateSyncResponseHeaderV1 {
     pub chunk: ShardChunkV1,
@@ -193,8 +214,15 @@ impl ShardStateSyncResponseV1 {
 
 #[derive(BorshSerialize, BorshDeserialize, Eq, PartialEq, Debug, Clone)]
 pub struct EpochSyncFinalizationResponse {
+    // The first block of the epoch the node starts from.
     pub cur_epoch_header: BlockHeader,
+    // The block following `cur_epoch_header`. Its approvals endorse `cur_epoch_header`, so the
+    // `epoch_sync_data_hash` in it can be trusted.
+    pub cur_epoch_next_header: BlockHeader,
+    // Headers from the block of the light client block of the previous epoch up to
+    // `header_sync_init_header`, exclusive.
     pub prev_epoch_headers: Vec<BlockHeader>,
+    // The last block of the previous epoch, header sync continues from it.
     pub header_sync_init_header: BlockHeader,
     pub header_sync_init_header_tree: PartialMerkleTree,
     // This Block Info is required by Epoch Manager when it checks if it's a good time to start a new Epoch.
@@ -213,6 +241,40 @@ pub struct EpochSyncFinalizationResponse {
     pub next_epoch_info: EpochInfo,
 }
 
+impl EpochSyncFinalizationResponse {
+    /// Hash of the epoch manager data in the response, which has to match the
+    /// `epoch_sync_data_hash` of `cur_epoch_header`.
+    pub fn epoch_sync_data_hash(&self) -> CryptoHash {
+        compute_epoch_sync_data_hash(
+            &self.prev_epoch_first_block_info,
+            &self.prev_epoch_prev_last_block_info,
+            &self.prev_epoch_last_block_info,
+            &self.prev_epoch_info,
+            &self.cur_epoch_info,
+            &self.next_epoch_info,
+        )
+    }
+}
+
+/// Computes the hash of the epoch manager data a node needs to start from an epoch, which is
+/// recorded in the header of the first block of the epoch.
+pub fn compute_epoch_sync_data_hash(
+    prev_epoch_first_block_info: &BlockInfo,
+    prev_epoch_prev_last_block_info: &BlockInfo,
+    prev_epoch_last_block_info: &BlockInfo,
+    prev_epoch_info: &EpochInfo,
+    cur_epoch_info: &EpochInfo,
+    next_epoch_info: &EpochInfo,
+) -> CryptoHash {
+    let mut data = prev_epoch_first_block_info.try_to_vec().unwrap();
+    data.extend(prev_epoch_prev_last_block_info.try_to_vec().unwrap());
+    data.extend(prev_epoch_last_block_info.try_to_vec().unwrap());
+    data.extend(prev_epoch_info.try_to_vec().unwrap());
+    data.extend(cur_epoch_info.try_to_vec().unwrap());
+    data.extend(next_epoch_info.try_to_vec().unwrap());
+    hash(data.as_slice())
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Eq, PartialEq, Debug, Clone)]
 pub enum EpochSyncResponse {
     UpToDate,
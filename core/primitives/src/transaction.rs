@@ -13,7 +13,7 @@ use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
 use crate::serialize::{base64_format, u128_dec_format_compatible};
-use crate::types::{AccountId, Balance, Gas, Nonce};
+use crate::types::{AccountId, Balance, BlockHeight, Gas, Nonce, StorageUsage};
 
 pub type LogEntry = String;
 
@@ -41,6 +41,18 @@ impl Transaction {
         let bytes = self.try_to_vec().expect("Failed to deserialize");
         (hash(&bytes), bytes.len() as u64)
     }
+
+    /// The last block height the transaction can be included at, set by its `ValidUntil`
+    /// actions.
+    pub fn valid_until(&self) -> Option<BlockHeight> {
+        self.actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::ValidUntil(action) => Some(action.valid_until),
+                _ => None,
+            })
+            .min()
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -56,18 +68,31 @@ pub enum Action {
     AddKey(AddKeyAction),
     DeleteKey(DeleteKeyAction),
     DeleteAccount(DeleteAccountAction),
+    /// A function call whose failure doesn't fail the receipt unless it's the last action.
+    /// The effects of the failed call are reverted, its deposit and unused gas are refunded, and
+    /// the following actions are executed as if it wasn't there.
+    OptionalFunctionCall(FunctionCallAction),
+    /// Caps the growth of the storage usage of the receiver account while the receipt is
+    /// executed. Has to be the first action.
+    MaxStorageDelta(MaxStorageDeltaAction),
+    /// Publishes a Wasm code in the state under its hash, for any account of the shard to use.
+    DeployGlobalContract(DeployGlobalContractAction),
+    /// Sets the code of the receiver_id to a globally published code.
+    UseGlobalContract(UseGlobalContractAction),
+    /// Makes the transaction invalid after the given block height.
+    ValidUntil(ValidUntilAction),
 }
 
 impl Action {
     pub fn get_prepaid_gas(&self) -> Gas {
         match self {
-            Action::FunctionCall(a) => a.gas,
+            Action::FunctionCall(a) | Action::OptionalFunctionCall(a) => a.gas,
             _ => 0,
         }
     }
     pub fn get_deposit_balance(&self) -> Balance {
         match self {
-            Action::FunctionCall(a) => a.deposit,
+            Action::FunctionCall(a) | Action::OptionalFunctionCall(a) => a.deposit,
             Action::Transfer(a) => a.deposit,
             _ => 0,
         }
@@ -198,6 +223,72 @@ impl From<DeleteAccountAction> for Action {
     }
 }
 
+/// Fails the receipt if the storage usage of the receiver account grows by more than
+/// `max_storage_delta` bytes while the receipt is executed, which protects the signer from
+/// contracts consuming the storage stake of the account. It only applies to the receipt of the
+/// transaction, not to the receipts created by the contracts it calls.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct MaxStorageDeltaAction {
+    pub max_storage_delta: StorageUsage,
+}
+
+impl From<MaxStorageDeltaAction> for Action {
+    fn from(max_storage_delta_action: MaxStorageDeltaAction) -> Self {
+        Self::MaxStorageDelta(max_storage_delta_action)
+    }
+}
+
+/// Publishes the code under its hash, so that many accounts can run it without storing a copy
+/// of it each. The signer pays for the storage of the code, which can't be removed. Publishing
+/// a code which is already published does nothing.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeployGlobalContractAction {
+    /// WebAssembly binary
+    #[serde(with = "base64_format")]
+    pub code: Vec<u8>,
+}
+
+impl From<DeployGlobalContractAction> for Action {
+    fn from(deploy_global_contract_action: DeployGlobalContractAction) -> Self {
+        Self::DeployGlobalContract(deploy_global_contract_action)
+    }
+}
+
+impl fmt::Debug for DeployGlobalContractAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeployGlobalContractAction")
+            .field("code", &format_args!("{}", logging::pretty_utf8(&self.code)))
+            .finish()
+    }
+}
+
+/// Makes the account run the global code with the given hash, removing the code deployed on it
+/// before. The code has to be published in the shard of the account.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct UseGlobalContractAction {
+    pub code_hash: CryptoHash,
+}
+
+impl From<UseGlobalContractAction> for Action {
+    fn from(use_global_contract_action: UseGlobalContractAction) -> Self {
+        Self::UseGlobalContract(use_global_contract_action)
+    }
+}
+
+/// The transaction can't be included in a chunk after the block height `valid_until`, so a
+/// transaction which wasn't included in time can be safely replaced by another one. The
+/// receipt created from the transaction isn't affected.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ValidUntilAction {
+    pub valid_until: BlockHeight,
+}
+
+impl From<ValidUntilAction> for Action {
+    fn from(valid_until_action: ValidUntilAction) -> Self {
+        Self::ValidUntil(valid_until_action)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Eq, Debug, Clone)]
 #[borsh_init(init)]
 pub struct SignedTransaction {
@@ -287,7 +378,7 @@ impl Default for ExecutionStatus {
 
 /// ExecutionOutcome for proof. Excludes logs and metadata
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Clone)]
-struct PartialExecutionOutcome {
+pub struct PartialExecutionOutcome {
     pub receipt_ids: Vec<CryptoHash>,
     pub gas_burnt: Gas,
     pub tokens_burnt: Balance,
@@ -35,6 +35,34 @@ pub(crate) mod col {
     pub const DELAYED_RECEIPT: &[u8] = &[8];
     /// This column id is used when storing Key-Value data from a contract on an `account_id`.
     pub const CONTRACT_DATA: &[u8] = &[9];
+    /// This column id is used when storing `primitives::challenge::SlashEscrow` type for a given
+    /// slashed `account_id`.
+    pub const SLASH_ESCROW: &[u8] = &[10];
+    /// This column id is used when storing the gas burnt in the current epoch by the function calls
+    /// of the contract deployed on a given `account_id`.
+    pub const CONTRACT_GAS_BURNT: &[u8] = &[11];
+    /// This column id is used when storing the extension records attached to an `account_id`.
+    pub const ACCOUNT_EXTENSION: &[u8] = &[12];
+    /// This column id is used when storing the indices of the postponed receipts expiry queue.
+    /// NOTE: It is a singleton per shard.
+    pub const POSTPONED_RECEIPT_EXPIRY_INDICES: &[u8] = &[13];
+    /// This column id is used when storing the entries of the postponed receipts expiry queue
+    /// (`primitives::receipt::PostponedReceiptExpiry`).
+    pub const POSTPONED_RECEIPT_EXPIRY: &[u8] = &[14];
+    /// This column id is used when storing the number of delayed receipts of a receiver.
+    pub const DELAYED_RECEIPTS_COUNT: &[u8] = &[15];
+    /// This column id is used when storing contract blobs published globally under their hash.
+    pub const GLOBAL_CONTRACT_CODE: &[u8] = &[16];
+    /// This column id is used when storing the amounts burnt in the shard
+    /// (`primitives::types::BurnLedger`).
+    /// NOTE: It is a singleton per shard.
+    pub const BURN_LEDGER: &[u8] = &[17];
+    /// This column id is used when storing the indices of the delayed priority receipts queue.
+    /// NOTE: It is a singleton per shard.
+    pub const DELAYED_PRIORITY_RECEIPT_INDICES: &[u8] = &[18];
+    /// This column id is used when storing refunds and stake receipts delayed because the shard
+    /// used up the gas available to the priority receipts.
+    pub const DELAYED_PRIORITY_RECEIPT: &[u8] = &[19];
 }
 
 /// Describes the key of a specific key-value record in a state trie.
@@ -72,6 +100,39 @@ pub enum TrieKey {
     /// Used to store a key-value record `Vec<u8>` within a contract deployed on a given `AccountId`
     /// and a given key.
     ContractData { account_id: AccountId, key: Vec<u8> },
+    /// Used to store `primitives::challenge::SlashEscrow` struct with the slashed stake of a given
+    /// `AccountId` that is not burnt yet.
+    SlashEscrow { account_id: AccountId },
+    /// Used to store the `Gas` burnt in the current epoch by the function calls of the contract
+    /// deployed on a given `AccountId`.
+    ContractGasBurnt { account_id: AccountId },
+    /// Used to store a bounded `Vec<u8>` record attached to a given `AccountId` under a given
+    /// `extension_id`.
+    AccountExtension { account_id: AccountId, extension_id: Vec<u8> },
+    /// Used to store indices of the postponed receipts expiry queue
+    /// (`primitives::receipt::DelayedReceiptIndices`).
+    /// NOTE: It is a singleton per shard.
+    PostponedReceiptExpiryIndices,
+    /// Used to store a `primitives::receipt::PostponedReceiptExpiry` entry for a given index `u64`
+    /// in the postponed receipts expiry queue. The queue is unique per shard.
+    PostponedReceiptExpiry { index: u64 },
+    /// Used to store the number `u64` of receipts in the delayed receipts queue for a given
+    /// receiver's `AccountId`.
+    DelayedReceiptsCount { receiver_id: AccountId },
+    /// Used to store `Vec<u8>` contract code published globally under its `CryptoHash`.
+    /// NOTE: It belongs to the whole shard.
+    GlobalContractCode { code_hash: CryptoHash },
+    /// Used to store the amounts burnt and the gas deficits of the shard
+    /// (`primitives::types::BurnLedger`).
+    /// NOTE: It is a singleton per shard.
+    BurnLedger,
+    /// Used to store indices of the delayed priority receipts queue
+    /// (`primitives::receipt::DelayedReceiptIndices`).
+    /// NOTE: It is a singleton per shard.
+    DelayedPriorityReceiptIndices,
+    /// Used to store a delayed priority receipt `primitives::receipt::Receipt` for a given index
+    /// `u64` in the delayed priority receipts queue. The queue is unique per shard.
+    DelayedPriorityReceipt { index: u64 },
 }
 
 impl TrieKey {
@@ -114,6 +175,31 @@ impl TrieKey {
                     + ACCOUNT_DATA_SEPARATOR.len()
                     + key.len()
             }
+            TrieKey::SlashEscrow { account_id } => col::SLASH_ESCROW.len() + account_id.len(),
+            TrieKey::ContractGasBurnt { account_id } => {
+                col::CONTRACT_GAS_BURNT.len() + account_id.len()
+            }
+            TrieKey::AccountExtension { account_id, extension_id } => {
+                col::ACCOUNT_EXTENSION.len()
+                    + account_id.len()
+                    + ACCOUNT_DATA_SEPARATOR.len()
+                    + extension_id.len()
+            }
+            TrieKey::PostponedReceiptExpiryIndices => col::POSTPONED_RECEIPT_EXPIRY_INDICES.len(),
+            TrieKey::PostponedReceiptExpiry { .. } => {
+                col::POSTPONED_RECEIPT_EXPIRY.len() + size_of::<u64>()
+            }
+            TrieKey::DelayedReceiptsCount { receiver_id } => {
+                col::DELAYED_RECEIPTS_COUNT.len() + receiver_id.len()
+            }
+            TrieKey::GlobalContractCode { code_hash } => {
+                col::GLOBAL_CONTRACT_CODE.len() + code_hash.as_ref().len()
+            }
+            TrieKey::BurnLedger => col::BURN_LEDGER.len(),
+            TrieKey::DelayedPriorityReceiptIndices => col::DELAYED_PRIORITY_RECEIPT_INDICES.len(),
+            TrieKey::DelayedPriorityReceipt { .. } => {
+                col::DELAYED_PRIORITY_RECEIPT.len() + size_of::<u64>()
+            }
         }
     }
 
@@ -172,6 +258,45 @@ impl TrieKey {
                 res.extend(ACCOUNT_DATA_SEPARATOR);
                 res.extend(key);
             }
+            TrieKey::SlashEscrow { account_id } => {
+                res.extend(col::SLASH_ESCROW);
+                res.extend(account_id.as_bytes());
+            }
+            TrieKey::ContractGasBurnt { account_id } => {
+                res.extend(col::CONTRACT_GAS_BURNT);
+                res.extend(account_id.as_bytes());
+            }
+            TrieKey::AccountExtension { account_id, extension_id } => {
+                res.extend(col::ACCOUNT_EXTENSION);
+                res.extend(account_id.as_bytes());
+                res.extend(ACCOUNT_DATA_SEPARATOR);
+                res.extend(extension_id);
+            }
+            TrieKey::PostponedReceiptExpiryIndices => {
+                res.extend(col::POSTPONED_RECEIPT_EXPIRY_INDICES);
+            }
+            TrieKey::PostponedReceiptExpiry { index } => {
+                res.extend(col::POSTPONED_RECEIPT_EXPIRY);
+                res.extend(&index.to_le_bytes());
+            }
+            TrieKey::DelayedReceiptsCount { receiver_id } => {
+                res.extend(col::DELAYED_RECEIPTS_COUNT);
+                res.extend(receiver_id.as_bytes());
+            }
+            TrieKey::GlobalContractCode { code_hash } => {
+                res.extend(col::GLOBAL_CONTRACT_CODE);
+                res.extend(code_hash.as_ref());
+            }
+            TrieKey::BurnLedger => {
+                res.extend(col::BURN_LEDGER);
+            }
+            TrieKey::DelayedPriorityReceiptIndices => {
+                res.extend(col::DELAYED_PRIORITY_RECEIPT_INDICES);
+            }
+            TrieKey::DelayedPriorityReceipt { index } => {
+                res.extend(col::DELAYED_PRIORITY_RECEIPT);
+                res.extend(&index.to_le_bytes());
+            }
         };
         debug_assert_eq!(res.len(), expected_len);
         res
@@ -211,6 +336,21 @@ pub mod trie_key_parsers {
         Ok(&raw_key[prefix_len..])
     }
 
+    pub fn parse_extension_id_from_account_extension_key<'a>(
+        raw_key: &'a [u8],
+        account_id: &AccountId,
+    ) -> Result<&'a [u8], std::io::Error> {
+        let prefix_len =
+            col::ACCOUNT_EXTENSION.len() + account_id.len() + ACCOUNT_DATA_SEPARATOR.len();
+        if raw_key.len() < prefix_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key is too short for TrieKey::AccountExtension",
+            ));
+        }
+        Ok(&raw_key[prefix_len..])
+    }
+
     pub fn parse_account_id_prefix<'a>(
         column: &[u8],
         raw_key: &'a [u8],
@@ -357,6 +497,116 @@ pub mod trie_key_parsers {
         })
     }
 
+    pub fn parse_account_id_from_slash_escrow_key(
+        raw_key: &[u8],
+    ) -> Result<AccountId, std::io::Error> {
+        let account_id = parse_account_id_prefix(col::SLASH_ESCROW, raw_key)?;
+        Ok(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId to be TrieKey::SlashEscrow",
+            )
+        })?))
+    }
+
+    pub fn parse_account_id_from_contract_gas_burnt_key(
+        raw_key: &[u8],
+    ) -> Result<AccountId, std::io::Error> {
+        let account_id = parse_account_id_prefix(col::CONTRACT_GAS_BURNT, raw_key)?;
+        Ok(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId to be TrieKey::ContractGasBurnt",
+            )
+        })?))
+    }
+
+    /// Returns the account a raw key of any kind belongs to, `None` for the keys of the delayed
+    /// receipts queues, of the postponed receipts expiry queue, of the global contract code and of
+    /// the burn ledger, which belong to the whole shard.
+    pub fn parse_account_id_from_raw_key(
+        raw_key: &[u8],
+    ) -> Result<Option<AccountId>, std::io::Error> {
+        let column = match raw_key.first() {
+            Some(column) => *column,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "raw key is empty",
+                ))
+            }
+        };
+        // Keys of all kinds with an account have a single byte column id.
+        debug_assert_eq!(col::ACCOUNT.len(), 1);
+        let separator = match &raw_key[..1] {
+            col::DELAYED_RECEIPT_INDICES
+            | col::DELAYED_RECEIPT
+            | col::POSTPONED_RECEIPT_EXPIRY_INDICES
+            | col::POSTPONED_RECEIPT_EXPIRY
+            | col::GLOBAL_CONTRACT_CODE
+            | col::BURN_LEDGER
+            | col::DELAYED_PRIORITY_RECEIPT_INDICES
+            | col::DELAYED_PRIORITY_RECEIPT => return Ok(None),
+            col::ACCOUNT
+            | col::CONTRACT_CODE
+            | col::SLASH_ESCROW
+            | col::CONTRACT_GAS_BURNT
+            | col::DELAYED_RECEIPTS_COUNT => None,
+            col::ACCESS_KEY => Some(col::ACCESS_KEY[0]),
+            col::RECEIVED_DATA
+            | col::POSTPONED_RECEIPT_ID
+            | col::PENDING_DATA_COUNT
+            | col::POSTPONED_RECEIPT
+            | col::CONTRACT_DATA
+            | col::ACCOUNT_EXTENSION => Some(ACCOUNT_DATA_SEPARATOR[0]),
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("raw key has an unknown column marker {}", column),
+                ))
+            }
+        };
+        let account_id = &raw_key[1..];
+        let account_id = match separator {
+            Some(separator) => match account_id.iter().position(|c| *c == separator) {
+                Some(position) => &account_id[..position],
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "raw key does not have a separator after the AccountId",
+                    ))
+                }
+            },
+            None => account_id,
+        };
+        Ok(Some(AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId",
+            )
+        })?)))
+    }
+
+    pub fn get_raw_prefix_for_accounts() -> Vec<u8> {
+        col::ACCOUNT.to_vec()
+    }
+
+    pub fn get_raw_prefix_for_postponed_receipts() -> Vec<u8> {
+        col::POSTPONED_RECEIPT.to_vec()
+    }
+
+    pub fn get_raw_prefix_for_slash_escrows() -> Vec<u8> {
+        col::SLASH_ESCROW.to_vec()
+    }
+
+    pub fn get_raw_prefix_for_contract_gas_burnt() -> Vec<u8> {
+        col::CONTRACT_GAS_BURNT.to_vec()
+    }
+
+    pub fn get_raw_prefix_for_global_contract_code() -> Vec<u8> {
+        col::GLOBAL_CONTRACT_CODE.to_vec()
+    }
+
     pub fn get_raw_prefix_for_access_keys(account_id: &AccountId) -> Vec<u8> {
         let mut res = Vec::with_capacity(col::ACCESS_KEY.len() * 2 + account_id.len());
         res.extend(col::ACCESS_KEY);
@@ -378,6 +628,16 @@ pub mod trie_key_parsers {
         res.extend(prefix);
         res
     }
+
+    pub fn get_raw_prefix_for_account_extensions(account_id: &AccountId) -> Vec<u8> {
+        let mut res = Vec::with_capacity(
+            col::ACCOUNT_EXTENSION.len() + account_id.len() + ACCOUNT_DATA_SEPARATOR.len(),
+        );
+        res.extend(col::ACCOUNT_EXTENSION);
+        res.extend(account_id.as_bytes());
+        res.extend(ACCOUNT_DATA_SEPARATOR);
+        res
+    }
 }
 
 #[cfg(test)]
@@ -449,6 +709,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_for_slash_escrow_consistency() {
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::SlashEscrow { account_id: account_id.clone() };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert!(raw_key.starts_with(&trie_key_parsers::get_raw_prefix_for_slash_escrows()));
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_slash_escrow_key(&raw_key).unwrap(),
+                account_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_for_contract_gas_burnt_consistency() {
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::ContractGasBurnt { account_id: account_id.clone() };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert!(raw_key.starts_with(&trie_key_parsers::get_raw_prefix_for_contract_gas_burnt()));
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_contract_gas_burnt_key(&raw_key).unwrap(),
+                account_id
+            );
+        }
+    }
+
     #[test]
     fn test_key_for_data_consistency() {
         let data_key = b"0123456789" as &[u8];
@@ -469,6 +757,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_for_account_extension_consistency() {
+        let extension_id = b"recovery" as &[u8];
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let key = TrieKey::AccountExtension {
+                account_id: account_id.clone(),
+                extension_id: extension_id.to_vec(),
+            };
+            let raw_key = key.to_vec();
+            assert_eq!(raw_key.len(), key.len());
+            assert!(raw_key.starts_with(&trie_key_parsers::get_raw_prefix_for_account_extensions(
+                &account_id
+            )));
+            assert_eq!(
+                trie_key_parsers::parse_extension_id_from_account_extension_key(
+                    &raw_key,
+                    &account_id
+                )
+                .unwrap(),
+                extension_id
+            );
+        }
+    }
+
     #[test]
     fn test_key_for_code_consistency() {
         for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
@@ -481,4 +793,53 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_account_id_from_raw_key() {
+        let hash = CryptoHash::default();
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let keys = vec![
+                TrieKey::Account { account_id: account_id.clone() },
+                TrieKey::ContractCode { account_id: account_id.clone() },
+                TrieKey::AccessKey {
+                    account_id: account_id.clone(),
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                },
+                TrieKey::ReceivedData { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PostponedReceiptId { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PendingDataCount { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::PostponedReceipt { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::ContractData { account_id: account_id.clone(), key: b"key".to_vec() },
+                TrieKey::SlashEscrow { account_id: account_id.clone() },
+                TrieKey::ContractGasBurnt { account_id: account_id.clone() },
+                TrieKey::AccountExtension {
+                    account_id: account_id.clone(),
+                    extension_id: b"recovery".to_vec(),
+                },
+                TrieKey::DelayedReceiptsCount { receiver_id: account_id.clone() },
+            ];
+            for key in keys {
+                assert_eq!(
+                    trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                    Some(account_id.clone())
+                );
+            }
+        }
+        for key in vec![
+            TrieKey::DelayedReceiptIndices,
+            TrieKey::DelayedReceipt { index: 1 },
+            TrieKey::PostponedReceiptExpiryIndices,
+            TrieKey::PostponedReceiptExpiry { index: 1 },
+            TrieKey::GlobalContractCode { code_hash: CryptoHash::default() },
+            TrieKey::BurnLedger,
+            TrieKey::DelayedPriorityReceiptIndices,
+            TrieKey::DelayedPriorityReceipt { index: 1 },
+        ] {
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                None
+            );
+        }
+        assert!(trie_key_parsers::parse_account_id_from_raw_key(&[]).is_err());
+    }
 }
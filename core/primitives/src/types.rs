@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use borsh::{BorshDeserialize, BorshSerialize};
 use derive_more::{AsRef as DeriveAsRef, From as DeriveFrom};
 use serde::{Deserialize, Serialize};
@@ -41,7 +43,7 @@ pub struct AccountWithPublicKey {
 }
 
 /// Account info for validators
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct AccountInfo {
     pub account_id: AccountId,
     pub public_key: PublicKey,
@@ -155,6 +157,12 @@ pub enum StateChangeCause {
     /// State change that is happens due to migration that happens in first block of an epoch
     /// after protocol upgrade
     Migration,
+    /// Gas refund of a receipt applied directly to its signer account, which executed it.
+    ActionReceiptGasRefund { receipt_hash: CryptoHash },
+    /// The given postponed receipt didn't receive its input data in time and was failed.
+    PostponedReceiptExpired { receipt_hash: CryptoHash },
+    /// Updated burn ledger of the shard with the amounts burnt by the chunk.
+    UpdatedBurnLedger,
 }
 
 /// This represents the committed changes in the Trie with a change cause.
@@ -183,6 +191,48 @@ pub enum StateChangesRequest {
     DataChanges { account_ids: Vec<AccountId>, key_prefix: StoreKey },
 }
 
+/// Kind of the state changes matched by a [`StateChangesFilter`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StateChangeKindFilter {
+    Account,
+    AccessKey,
+    ContractCode,
+    Data,
+}
+
+/// A persistent filter over the state changes of every processed block. Changes matching the
+/// filter are stored separately, so that they can be queried without scanning all the changes of
+/// the block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+pub struct StateChangesFilter {
+    /// Unique name of the filter which is used to query the stored changes.
+    pub id: String,
+    /// Only changes of accounts with ids starting with this prefix are matched.
+    pub account_id_prefix: String,
+    /// Kinds of changes to match. All kinds are matched if empty.
+    #[serde(default)]
+    pub kinds: Vec<StateChangeKindFilter>,
+}
+
+impl StateChangesFilter {
+    pub fn matches(&self, trie_key: &TrieKey) -> bool {
+        let (kind, account_id) = match trie_key {
+            TrieKey::Account { account_id } => (StateChangeKindFilter::Account, account_id),
+            TrieKey::AccessKey { account_id, .. } => (StateChangeKindFilter::AccessKey, account_id),
+            TrieKey::ContractCode { account_id } => {
+                (StateChangeKindFilter::ContractCode, account_id)
+            }
+            TrieKey::ContractData { account_id, .. } => (StateChangeKindFilter::Data, account_id),
+            _ => return false,
+        };
+        account_id.starts_with(&self.account_id_prefix)
+            && (self.kinds.is_empty() || self.kinds.contains(&kind))
+    }
+}
+
 #[derive(Debug)]
 pub enum StateChangeValue {
     AccountUpdate { account_id: AccountId, account: Account },
@@ -292,6 +342,17 @@ impl StateChanges {
                 TrieKey::PostponedReceipt { .. } => {}
                 TrieKey::DelayedReceiptIndices => {}
                 TrieKey::DelayedReceipt { .. } => {}
+                TrieKey::SlashEscrow { .. } => {}
+                TrieKey::ContractGasBurnt { .. } => {}
+                // Account extensions aren't exposed in the views yet.
+                TrieKey::AccountExtension { .. } => {}
+                TrieKey::PostponedReceiptExpiryIndices => {}
+                TrieKey::PostponedReceiptExpiry { .. } => {}
+                TrieKey::DelayedReceiptsCount { .. } => {}
+                TrieKey::GlobalContractCode { .. } => {}
+                TrieKey::BurnLedger => {}
+                TrieKey::DelayedPriorityReceiptIndices => {}
+                TrieKey::DelayedPriorityReceipt { .. } => {}
             }
         }
 
@@ -918,6 +979,23 @@ impl From<Finality> for BlockReference {
     }
 }
 
+/// Amounts burnt and gas deficits of a shard, kept in its state under `TrieKey::BurnLedger`, so
+/// the burnt tokens can be audited on chain.
+#[derive(Default, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BurnLedger {
+    /// Height of the last block whose chunk burnt tokens or had a gas deficit. The amounts below
+    /// are the ones of this chunk.
+    pub block_height: BlockHeight,
+    pub tx_burnt_amount: Balance,
+    pub slashed_burnt_amount: Balance,
+    pub other_burnt_amount: Balance,
+    pub gas_deficit_amount: Balance,
+    /// Sum of the amounts burnt by all the chunks recorded in the ledger.
+    pub total_burnt_amount: Balance,
+    /// Sum of the gas deficits of all the chunks recorded in the ledger.
+    pub total_gas_deficit_amount: Balance,
+}
+
 #[derive(Default, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub struct ValidatorStats {
     pub produced: NumBlocks,
@@ -930,6 +1008,18 @@ pub struct BlockChunkValidatorStats {
     pub chunk_stats: ValidatorStats,
 }
 
+/// Chunk production stats of a validator by shard.
+pub type ShardChunkValidatorStats = HashMap<ShardId, ValidatorStats>;
+
+/// Piecewise linear curve of the share of its reward a validator gets by its uptime: nothing
+/// below `zero_reward_uptime`, linearly more above it up to the full reward at
+/// `full_reward_uptime`. Uptimes are fractions given by their numerator and denominator.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UptimeCurve {
+    pub zero_reward_uptime: (u64, u64),
+    pub full_reward_uptime: (u64, u64),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum EpochReference {
@@ -958,6 +1048,8 @@ pub enum ValidatorKickoutReason {
     },
     /// Enough stake but is not chosen because of seat limits.
     DidNotGetASeat,
+    /// Validator was removed by an override of the validator set, see `ValidatorSetOverride`.
+    ValidatorSetOverride,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -169,6 +169,11 @@ pub fn create_data_id(
 /// Creates a unique random seed to be provided to `VMContext` from a give `action_hash` and
 /// a given `random_seed`.
 /// This method is backward compatible, so it takes the current protocol version.
+///
+/// Since `CREATE_HASH_PROTOCOL_VERSION` the seed is `sha256(action_hash || random_seed)`, where
+/// `random_seed` is the random value of the block applying the receipt, so anyone can verify it
+/// from the block and the receipt. The `next_random_seed` host function derives its values from
+/// this seed, see `VMLogic::next_random_seed`.
 pub fn create_random_seed(
     protocol_version: ProtocolVersion,
     action_hash: CryptoHash,
@@ -13,7 +13,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 25;
+pub const DB_VERSION: DbVersion = 26;
 
 /// Protocol version type.
 pub use near_primitives_core::types::ProtocolVersion;
@@ -107,6 +107,104 @@ pub enum ProtocolFeature {
     AltBn128,
     #[cfg(feature = "protocol_feature_restore_receipts_after_fix")]
     RestoreReceiptsAfterFix,
+    /// Process refunds and stake receipts before user receipts.
+    #[cfg(feature = "protocol_feature_priority_receipts")]
+    PriorityReceipts,
+    /// Keep slashed stake in escrow for a number of epochs before burning it.
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
+    SlashingEscrow,
+    /// Send large network messages over multiplexed streams.
+    #[cfg(feature = "protocol_feature_stream_multiplexing")]
+    StreamMultiplexing,
+    /// Reject receipts which deploy a contract, add a key or delete a key more than once.
+    #[cfg(feature = "protocol_feature_duplicate_actions_validation")]
+    DuplicateActionsValidation,
+    /// Let nodes behind NAT accept connections through a relay peer.
+    #[cfg(feature = "protocol_feature_relay_peers")]
+    RelayPeers,
+    /// Add `epoch_random_seed` host function.
+    #[cfg(feature = "protocol_feature_epoch_random_seed")]
+    EpochRandomSeed,
+    /// Refund part of the fees of receipts which free storage.
+    #[cfg(feature = "protocol_feature_storage_rebate")]
+    StorageRebate,
+    /// Charge the gas price of the previous block when applying shards with missing chunks, like
+    /// it's done for new chunks.
+    #[cfg(feature = "protocol_feature_consistent_apply_gas_price")]
+    ConsistentApplyGasPrice,
+    /// Add `OptionalFunctionCall` action, which doesn't fail the receipt when it fails.
+    #[cfg(feature = "protocol_feature_optional_function_calls")]
+    OptionalFunctionCalls,
+    /// Derive the seed of validator shuffling from the random values of the last blocks of the
+    /// epoch and store them in the epoch info, so the shuffling can be audited.
+    #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+    EpochSeedFromVrf,
+    /// Switch to the resharding shard layout of the genesis config, splitting and merging the
+    /// state of the shards at the epoch boundary.
+    #[cfg(feature = "protocol_feature_resharding")]
+    Resharding,
+    /// Compute the chunk production uptime of validators as the average of their uptimes on the
+    /// shards they produce chunks for, instead of from their chunks on all shards together.
+    #[cfg(feature = "protocol_feature_per_shard_uptime")]
+    PerShardUptime,
+    /// Slash validators which approved two different blocks at one height, and include the
+    /// double sign challenges gossiped by validators into the blocks.
+    #[cfg(feature = "protocol_feature_double_sign_slashing")]
+    DoubleSignSlashing,
+    /// Pay a share of the protocol treasury reward to the contracts by their share of the gas
+    /// burnt by function calls in the epoch.
+    #[cfg(feature = "protocol_feature_developer_reward")]
+    DeveloperReward,
+    /// Small records attached to an account next to the contract storage, readable and writable
+    /// by the contract with the `account_extension_*` host functions.
+    #[cfg(feature = "protocol_feature_account_extensions")]
+    AccountExtensions,
+    /// Apply the gas refunds of the receipts executed by their signer accounts directly instead
+    /// of sending refund receipts.
+    #[cfg(feature = "protocol_feature_local_gas_refund")]
+    LocalGasRefund,
+    /// Assign the validator seats by the stake in the denomination of the epoch config, e.g. with
+    /// stake multipliers or a secondary staking token.
+    #[cfg(feature = "protocol_feature_stake_denomination")]
+    StakeDenomination,
+    /// Fail the postponed receipts which don't receive their input data within
+    /// `postponed_receipt_timeout_epochs` and refund them.
+    #[cfg(feature = "protocol_feature_postponed_receipt_timeout")]
+    PostponedReceiptTimeout,
+    /// Count the delayed receipts of every receiver and reject the transactions to a receiver with
+    /// `max_delayed_receipts_per_account` or more of them, or fail their receipts once they reach
+    /// the shard of the receiver.
+    #[cfg(feature = "protocol_feature_receipt_congestion_control")]
+    ReceiptCongestionControl,
+    /// Add `next_random_seed` host function returning a new random value on every call.
+    #[cfg(feature = "protocol_feature_next_random_seed")]
+    NextRandomSeed,
+    /// Add `MaxStorageDelta` action capping the storage growth of the receiver of a transaction.
+    #[cfg(feature = "protocol_feature_max_storage_delta")]
+    MaxStorageDelta,
+    /// Execute the maintenance calls scheduled in the migration data as system receipts which
+    /// don't pay for gas.
+    #[cfg(feature = "protocol_feature_system_calls")]
+    SystemCalls,
+    /// Add `DeployGlobalContract` and `UseGlobalContract` actions, so accounts can run a code
+    /// published once in the state instead of storing a copy of it each.
+    #[cfg(feature = "protocol_feature_global_contracts")]
+    GlobalContracts,
+    /// Method names of function call access keys ending with `*` allow all the methods starting
+    /// with the rest of the name.
+    #[cfg(feature = "protocol_feature_method_name_patterns")]
+    MethodNamePatterns,
+    /// Add `ValidUntil` action, so a transaction can't be included after the given height.
+    #[cfg(feature = "protocol_feature_transaction_valid_until")]
+    TransactionValidUntil,
+    /// Record the amounts burnt and the gas deficits of every chunk under the `BurnLedger` trie
+    /// key of the shard.
+    #[cfg(feature = "protocol_feature_burn_ledger")]
+    BurnLedger,
+    /// Reject deploying contracts importing host functions which the current protocol version
+    /// doesn't provide.
+    #[cfg(feature = "protocol_feature_contract_import_versions")]
+    ContractImportVersions,
 }
 
 /// Current latest stable version of the protocol.
@@ -117,7 +215,7 @@ pub const PROTOCOL_VERSION: ProtocolVersion = 46;
 
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 114;
+pub const PROTOCOL_VERSION: ProtocolVersion = 141;
 
 impl ProtocolFeature {
     pub const fn protocol_version(self) -> ProtocolVersion {
@@ -145,6 +243,60 @@ impl ProtocolFeature {
             ProtocolFeature::BlockHeaderV3 => 109,
             #[cfg(feature = "protocol_feature_restore_receipts_after_fix")]
             ProtocolFeature::RestoreReceiptsAfterFix => 112,
+            #[cfg(feature = "protocol_feature_priority_receipts")]
+            ProtocolFeature::PriorityReceipts => 115,
+            #[cfg(feature = "protocol_feature_slashing_escrow")]
+            ProtocolFeature::SlashingEscrow => 116,
+            #[cfg(feature = "protocol_feature_stream_multiplexing")]
+            ProtocolFeature::StreamMultiplexing => 117,
+            #[cfg(feature = "protocol_feature_duplicate_actions_validation")]
+            ProtocolFeature::DuplicateActionsValidation => 118,
+            #[cfg(feature = "protocol_feature_relay_peers")]
+            ProtocolFeature::RelayPeers => 119,
+            #[cfg(feature = "protocol_feature_epoch_random_seed")]
+            ProtocolFeature::EpochRandomSeed => 120,
+            #[cfg(feature = "protocol_feature_storage_rebate")]
+            ProtocolFeature::StorageRebate => 121,
+            #[cfg(feature = "protocol_feature_consistent_apply_gas_price")]
+            ProtocolFeature::ConsistentApplyGasPrice => 122,
+            #[cfg(feature = "protocol_feature_optional_function_calls")]
+            ProtocolFeature::OptionalFunctionCalls => 123,
+            #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+            ProtocolFeature::EpochSeedFromVrf => 124,
+            #[cfg(feature = "protocol_feature_resharding")]
+            ProtocolFeature::Resharding => 125,
+            #[cfg(feature = "protocol_feature_per_shard_uptime")]
+            ProtocolFeature::PerShardUptime => 126,
+            #[cfg(feature = "protocol_feature_double_sign_slashing")]
+            ProtocolFeature::DoubleSignSlashing => 127,
+            #[cfg(feature = "protocol_feature_developer_reward")]
+            ProtocolFeature::DeveloperReward => 128,
+            #[cfg(feature = "protocol_feature_account_extensions")]
+            ProtocolFeature::AccountExtensions => 129,
+            #[cfg(feature = "protocol_feature_local_gas_refund")]
+            ProtocolFeature::LocalGasRefund => 130,
+            #[cfg(feature = "protocol_feature_stake_denomination")]
+            ProtocolFeature::StakeDenomination => 131,
+            #[cfg(feature = "protocol_feature_postponed_receipt_timeout")]
+            ProtocolFeature::PostponedReceiptTimeout => 132,
+            #[cfg(feature = "protocol_feature_receipt_congestion_control")]
+            ProtocolFeature::ReceiptCongestionControl => 133,
+            #[cfg(feature = "protocol_feature_next_random_seed")]
+            ProtocolFeature::NextRandomSeed => 134,
+            #[cfg(feature = "protocol_feature_max_storage_delta")]
+            ProtocolFeature::MaxStorageDelta => 135,
+            #[cfg(feature = "protocol_feature_system_calls")]
+            ProtocolFeature::SystemCalls => 136,
+            #[cfg(feature = "protocol_feature_global_contracts")]
+            ProtocolFeature::GlobalContracts => 137,
+            #[cfg(feature = "protocol_feature_method_name_patterns")]
+            ProtocolFeature::MethodNamePatterns => 138,
+            #[cfg(feature = "protocol_feature_transaction_valid_until")]
+            ProtocolFeature::TransactionValidUntil => 139,
+            #[cfg(feature = "protocol_feature_burn_ledger")]
+            ProtocolFeature::BurnLedger => 140,
+            #[cfg(feature = "protocol_feature_contract_import_versions")]
+            ProtocolFeature::ContractImportVersions => 141,
         }
     }
 }
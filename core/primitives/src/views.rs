@@ -39,14 +39,15 @@ use crate::sharding::{ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderI
 use crate::sharding::{ShardChunkHeaderInnerV2, ShardChunkHeaderV3};
 use crate::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
-    DeployContractAction, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithIdAndProof,
-    ExecutionStatus, FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
+    DeployContractAction, DeployGlobalContractAction, ExecutionMetadata, ExecutionOutcome,
+    ExecutionOutcomeWithIdAndProof, ExecutionStatus, FunctionCallAction, MaxStorageDeltaAction,
+    SignedTransaction, StakeAction, TransferAction, UseGlobalContractAction, ValidUntilAction,
 };
 use crate::types::{
-    AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
-    EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
-    StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
-    StoreValue, ValidatorKickoutReason,
+    AccountId, AccountWithPublicKey, Balance, BlockHeight, BurnLedger, CompiledContractCache,
+    EpochHeight, EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause,
+    StateChangeKind, StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot,
+    StorageUsage, StoreKey, StoreValue, ValidatorKickoutReason,
 };
 use crate::version::{ProtocolVersion, Version};
 use validator_stake_view::ValidatorStakeView;
@@ -210,7 +211,49 @@ pub struct StateItem {
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct ViewStateResult {
     pub values: Vec<StateItem>,
+    /// Trie nodes read to look up the returned values, when a proof was requested. Iterating the
+    /// same key range of the trie built from these nodes gives the same values, so they can be
+    /// checked against the state root.
     pub proof: TrieProofPath,
+    /// Key of the first value left out of a paginated response, to be used as the start key of
+    /// the next page. `None` when there are no more values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_key: Option<String>,
+}
+
+/// Amounts burnt by the last chunk of the shard which burnt tokens or had a gas deficit, and the
+/// totals of the shard.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default,
+)]
+pub struct BurnLedgerView {
+    pub block_height: BlockHeight,
+    #[serde(with = "u128_dec_format")]
+    pub tx_burnt_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub slashed_burnt_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub other_burnt_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub gas_deficit_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub total_burnt_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub total_gas_deficit_amount: Balance,
+}
+
+impl From<BurnLedger> for BurnLedgerView {
+    fn from(burn_ledger: BurnLedger) -> Self {
+        Self {
+            block_height: burn_ledger.block_height,
+            tx_burnt_amount: burn_ledger.tx_burnt_amount,
+            slashed_burnt_amount: burn_ledger.slashed_burnt_amount,
+            other_burnt_amount: burn_ledger.other_burnt_amount,
+            gas_deficit_amount: burn_ledger.gas_deficit_amount,
+            total_burnt_amount: burn_ledger.total_burnt_amount,
+            total_gas_deficit_amount: burn_ledger.total_gas_deficit_amount,
+        }
+    }
 }
 
 #[derive(
@@ -252,6 +295,7 @@ pub enum QueryResponseKind {
     CallResult(CallResult),
     AccessKey(AccessKeyView),
     AccessKeyList(AccessKeyList),
+    BurnLedger(BurnLedgerView),
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -267,6 +311,14 @@ pub enum QueryRequest {
         account_id: AccountId,
         #[serde(rename = "prefix_base64", with = "base64_format")]
         prefix: StoreKey,
+        /// Key to start the page at, taken from the `next_key` of the previous page.
+        #[serde(rename = "start_key_base64", default, with = "option_base64_format")]
+        start_key: Option<Vec<u8>>,
+        /// Maximum number of values to return. All the values are returned if not set.
+        #[serde(default)]
+        limit: Option<u64>,
+        #[serde(default)]
+        include_proof: bool,
     },
     ViewAccessKey {
         account_id: AccountId,
@@ -281,6 +333,10 @@ pub enum QueryRequest {
         #[serde(rename = "args_base64", with = "base64_format")]
         args: FunctionArgs,
     },
+    /// The burn ledger of a shard, see `BurnLedgerView`.
+    ViewBurnLedger {
+        shard_id: ShardId,
+    },
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq, Clone)]
@@ -794,6 +850,28 @@ impl ChunkView {
     }
 }
 
+/// A block with the producers of its chunks.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockDetailsView {
+    #[serde(flatten)]
+    pub block: BlockView,
+    /// Producers of the chunks of the block, ordered by shard id.
+    pub chunk_producers: Vec<AccountId>,
+}
+
+/// A chunk with the receipts of its shard in the block which included it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChunkDetailsView {
+    #[serde(flatten)]
+    pub chunk: ChunkView,
+    /// Hash of the block the receipts belong to.
+    pub block_hash: CryptoHash,
+    /// Receipts applied to the shard in the block. `None` if the node doesn't track the shard.
+    pub incoming_receipts: Option<Vec<ReceiptView>>,
+    /// Receipts produced by the shard in the block. `None` if the node doesn't track the shard.
+    pub outgoing_receipts: Option<Vec<ReceiptView>>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
 pub enum ActionView {
     CreateAccount,
@@ -826,6 +904,25 @@ pub enum ActionView {
     DeleteAccount {
         beneficiary_id: AccountId,
     },
+    OptionalFunctionCall {
+        method_name: String,
+        args: String,
+        gas: Gas,
+        #[serde(with = "u128_dec_format")]
+        deposit: Balance,
+    },
+    MaxStorageDelta {
+        max_storage_delta: StorageUsage,
+    },
+    DeployGlobalContract {
+        code: String,
+    },
+    UseGlobalContract {
+        code_hash: CryptoHash,
+    },
+    ValidUntil {
+        valid_until: BlockHeight,
+    },
 }
 
 impl From<Action> for ActionView {
@@ -853,6 +950,24 @@ impl From<Action> for ActionView {
             Action::DeleteAccount(action) => {
                 ActionView::DeleteAccount { beneficiary_id: action.beneficiary_id }
             }
+            Action::OptionalFunctionCall(action) => ActionView::OptionalFunctionCall {
+                method_name: action.method_name,
+                args: to_base64(&action.args),
+                gas: action.gas,
+                deposit: action.deposit,
+            },
+            Action::MaxStorageDelta(action) => {
+                ActionView::MaxStorageDelta { max_storage_delta: action.max_storage_delta }
+            }
+            Action::DeployGlobalContract(action) => {
+                ActionView::DeployGlobalContract { code: to_base64(&hash(&action.code)) }
+            }
+            Action::UseGlobalContract(action) => {
+                ActionView::UseGlobalContract { code_hash: action.code_hash }
+            }
+            Action::ValidUntil(action) => {
+                ActionView::ValidUntil { valid_until: action.valid_until }
+            }
         }
     }
 }
@@ -887,6 +1002,28 @@ impl TryFrom<ActionView> for Action {
             ActionView::DeleteAccount { beneficiary_id } => {
                 Action::DeleteAccount(DeleteAccountAction { beneficiary_id })
             }
+            ActionView::OptionalFunctionCall { method_name, args, gas, deposit } => {
+                Action::OptionalFunctionCall(FunctionCallAction {
+                    method_name,
+                    args: from_base64(&args)?,
+                    gas,
+                    deposit,
+                })
+            }
+            ActionView::MaxStorageDelta { max_storage_delta } => {
+                Action::MaxStorageDelta(MaxStorageDeltaAction { max_storage_delta })
+            }
+            ActionView::DeployGlobalContract { code } => {
+                Action::DeployGlobalContract(DeployGlobalContractAction {
+                    code: from_base64(&code)?,
+                })
+            }
+            ActionView::UseGlobalContract { code_hash } => {
+                Action::UseGlobalContract(UseGlobalContractAction { code_hash })
+            }
+            ActionView::ValidUntil { valid_until } => {
+                Action::ValidUntil(ValidUntilAction { valid_until })
+            }
         })
     }
 }
@@ -1365,6 +1502,47 @@ pub struct NextEpochValidatorInfo {
     pub shards: Vec<ShardId>,
 }
 
+/// Changes of the validator set of an epoch relative to the previous epoch.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EpochValidatorDiffView {
+    pub epoch_id: EpochId,
+    pub prev_epoch_id: EpochId,
+    pub epoch_height: EpochHeight,
+    /// Validators of this epoch which were not validators in the previous epoch
+    pub joined: Vec<ValidatorStakeView>,
+    /// Validators of the previous epoch which left the validator set without being kicked out
+    pub left: Vec<ValidatorStakeView>,
+    /// Validators of the previous epoch which were kicked out
+    pub kicked: Vec<ValidatorKickoutView>,
+    /// Validators of both epochs whose stake changed
+    pub stake_changed: Vec<ValidatorStakeChangeView>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ValidatorStakeChangeView {
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub prev_stake: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub stake: Balance,
+}
+
+/// Seat prices of the epoch of a block and the next epoch, and the expected seat price of the
+/// epoch after, which is assigned from the proposals made in the epoch of the block.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct SeatPriceView {
+    pub epoch_id: EpochId,
+    #[serde(with = "u128_dec_format")]
+    pub seat_price: Balance,
+    pub next_epoch_id: EpochId,
+    #[serde(with = "u128_dec_format")]
+    pub next_seat_price: Balance,
+    /// Minimum stake to get a seat in the epoch after the next one, given the proposals made so
+    /// far. `None` if the stake proposed so far isn't enough to fill all the seats.
+    #[serde(with = "option_u128_dec_format")]
+    pub minimum_stake_to_join: Option<Balance>,
+}
+
 #[derive(Serialize, PartialEq, Eq, Debug, Clone, BorshDeserialize, BorshSerialize)]
 pub struct LightClientBlockView {
     pub prev_block_hash: CryptoHash,
@@ -1490,6 +1668,9 @@ pub enum StateChangeCauseView {
     UpdatedDelayedReceipts,
     ValidatorAccountsUpdate,
     Migration,
+    ActionReceiptGasRefund { receipt_hash: CryptoHash },
+    PostponedReceiptExpired { receipt_hash: CryptoHash },
+    UpdatedBurnLedger,
 }
 
 impl From<StateChangeCause> for StateChangeCauseView {
@@ -1515,6 +1696,13 @@ impl From<StateChangeCause> for StateChangeCauseView {
             StateChangeCause::UpdatedDelayedReceipts => Self::UpdatedDelayedReceipts,
             StateChangeCause::ValidatorAccountsUpdate => Self::ValidatorAccountsUpdate,
             StateChangeCause::Migration => Self::Migration,
+            StateChangeCause::ActionReceiptGasRefund { receipt_hash } => {
+                Self::ActionReceiptGasRefund { receipt_hash }
+            }
+            StateChangeCause::PostponedReceiptExpired { receipt_hash } => {
+                Self::PostponedReceiptExpired { receipt_hash }
+            }
+            StateChangeCause::UpdatedBurnLedger => Self::UpdatedBurnLedger,
         }
     }
 }
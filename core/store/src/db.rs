@@ -13,6 +13,7 @@ use rocksdb::{
 use strum::EnumIter;
 use tracing::warn;
 
+use near_primitives::hash::CryptoHash;
 use near_primitives::version::DbVersion;
 
 use crate::db::refcount::merge_refcounted_records;
@@ -20,15 +21,29 @@ use crate::db::refcount::merge_refcounted_records;
 use std::path::Path;
 use std::sync::atomic::Ordering;
 
+pub(crate) mod async_write;
+pub(crate) mod blob;
 pub(crate) mod refcount;
+pub(crate) mod split;
 pub(crate) mod v6_to_v7;
 
+pub use crate::db::async_write::AsyncWriteDB;
+pub use crate::db::blob::{BlobDB, DEFAULT_BLOB_THRESHOLD};
+pub use crate::db::split::SplitDB;
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct DBError(rocksdb::Error);
+pub enum DBError {
+    RocksDB(rocksdb::Error),
+    /// A value of a blob column refers to a blob which is missing from `ColBlobs`.
+    MissingBlob(CryptoHash),
+}
 
 impl std::fmt::Display for DBError {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        self.0.fmt(formatter)
+        match self {
+            DBError::RocksDB(err) => err.fmt(formatter),
+            DBError::MissingBlob(blob_hash) => write!(formatter, "Missing blob {}", blob_hash),
+        }
     }
 }
 
@@ -36,7 +51,7 @@ impl std::error::Error for DBError {}
 
 impl From<rocksdb::Error> for DBError {
     fn from(err: rocksdb::Error) -> Self {
-        DBError(err)
+        DBError::RocksDB(err)
     }
 }
 
@@ -119,10 +134,21 @@ pub enum DBCol {
     ColEpochValidatorInfo = 47,
     /// Header Hashes indexed by Height
     ColHeaderHashesByHeight = 48,
+    /// State changes matching the configured state changes filters, indexed by block hash and
+    /// filter id
+    ColFilteredStateChanges = 49,
+    /// Parts of the shard state downloaded during state sync, indexed by shard id and sync hash
+    ColStateSyncProgress = 50,
+    /// Approvals for the blocks this node is to produce, indexed by target height and account id
+    ColBlockApprovals = 51,
+    /// Large values of the blob columns, indexed by their hash, see `BlobDB`
+    ColBlobs = 52,
+    /// Execution statistics of contracts, indexed by the hash of their code
+    ColContractExecutionStats = 53,
 }
 
 // Do not move this line from enum DBCol
-pub const NUM_COLS: usize = 49;
+pub const NUM_COLS: usize = 54;
 
 impl std::fmt::Display for DBCol {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -176,6 +202,11 @@ impl std::fmt::Display for DBCol {
             Self::ColCachedContractCode => "cached code",
             Self::ColEpochValidatorInfo => "epoch validator info",
             Self::ColHeaderHashesByHeight => "header hashes indexed by their height",
+            Self::ColFilteredStateChanges => "state changes matching filters",
+            Self::ColStateSyncProgress => "progress of the state sync download",
+            Self::ColBlockApprovals => "approvals for blocks to produce",
+            Self::ColBlobs => "large values of blob columns",
+            Self::ColContractExecutionStats => "execution statistics of contracts",
         };
         write!(formatter, "{}", desc)
     }
@@ -185,6 +216,21 @@ impl DBCol {
     pub fn is_rc(&self) -> bool {
         IS_COL_RC[*self as usize]
     }
+
+    /// Whether the data of the column can be moved to the cold database, see `SplitDB`.
+    pub fn is_cold(&self) -> bool {
+        IS_COL_COLD[*self as usize]
+    }
+
+    /// Whether the large values of the column are stored in `ColBlobs`, see `BlobDB`.
+    pub fn is_blob(&self) -> bool {
+        IS_COL_BLOB[*self as usize]
+    }
+
+    /// Whether the column is no longer written to and its content can be dropped safely.
+    pub fn is_deprecated(&self) -> bool {
+        matches!(self, DBCol::_ColTransactionRefCount)
+    }
 }
 
 // List of columns for which GC should be implemented
@@ -209,6 +255,12 @@ lazy_static! {
         col_gc[DBCol::ColEpochValidatorInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColCachedContractCode as usize] = false;
+        // Removed by the client once the head passes the target height of the approvals
+        col_gc[DBCol::ColBlockApprovals as usize] = false;
+        // Blobs are removed with the last value of a blob column referring to them
+        col_gc[DBCol::ColBlobs as usize] = false;
+        // Statistics are aggregated over the whole history of the node
+        col_gc[DBCol::ColContractExecutionStats as usize] = false;
         col_gc
     };
 }
@@ -222,6 +274,10 @@ lazy_static! {
         col_gc[DBCol::ColStateHeaders as usize] = true;
         // True until #2515
         col_gc[DBCol::ColStateParts as usize] = true;
+        // Only populated if state changes filters are configured
+        col_gc[DBCol::ColFilteredStateChanges as usize] = true;
+        // Only populated while a node is state syncing
+        col_gc[DBCol::ColStateSyncProgress as usize] = true;
         col_gc
     };
 }
@@ -234,10 +290,43 @@ lazy_static! {
         col_rc[DBCol::ColTransactions as usize] = true;
         col_rc[DBCol::ColReceipts as usize] = true;
         col_rc[DBCol::ColReceiptIdToShardId as usize] = true;
+        col_rc[DBCol::ColBlobs as usize] = true;
         col_rc
     };
 }
 
+// List of columns whose large values are stored separately in `ColBlobs`. Values of these columns
+// have to be borsh-serialized enums, so they can be told apart from the references to blobs.
+lazy_static! {
+    pub static ref IS_COL_BLOB: Vec<bool> = {
+        let mut col_blob = vec![false; NUM_COLS];
+        col_blob[DBCol::ColChunks as usize] = true;
+        col_blob[DBCol::ColPartialChunks as usize] = true;
+        col_blob[DBCol::ColCachedContractCode as usize] = true;
+        col_blob
+    };
+}
+
+// List of columns whose data is moved to the cold database once it is older than the GC horizon.
+// Only block-indexed data which is never modified after the block is processed can be moved,
+// and reference counted columns can't be moved at all.
+lazy_static! {
+    pub static ref IS_COL_COLD: Vec<bool> = {
+        let mut col_cold = vec![false; NUM_COLS];
+        col_cold[DBCol::ColBlock as usize] = true;
+        col_cold[DBCol::ColBlockExtra as usize] = true;
+        col_cold[DBCol::ColChunks as usize] = true;
+        col_cold[DBCol::ColPartialChunks as usize] = true;
+        col_cold[DBCol::ColChunkExtra as usize] = true;
+        col_cold[DBCol::ColIncomingReceipts as usize] = true;
+        col_cold[DBCol::ColOutgoingReceipts as usize] = true;
+        col_cold[DBCol::ColOutcomeIds as usize] = true;
+        col_cold[DBCol::ColStateChanges as usize] = true;
+        col_cold[DBCol::ColTrieChanges as usize] = true;
+        col_cold
+    };
+}
+
 pub const HEAD_KEY: &[u8; 4] = b"HEAD";
 pub const TAIL_KEY: &[u8; 4] = b"TAIL";
 pub const CHUNK_TAIL_KEY: &[u8; 10] = b"CHUNK_TAIL";
@@ -246,6 +335,7 @@ pub const HEADER_HEAD_KEY: &[u8; 11] = b"HEADER_HEAD";
 pub const FINAL_HEAD_KEY: &[u8; 10] = b"FINAL_HEAD";
 pub const LATEST_KNOWN_KEY: &[u8; 12] = b"LATEST_KNOWN";
 pub const LARGEST_TARGET_HEIGHT_KEY: &[u8; 21] = b"LARGEST_TARGET_HEIGHT";
+pub const COLD_HEAD_KEY: &[u8; 9] = b"COLD_HEAD";
 pub const VERSION_KEY: &[u8; 7] = b"VERSION";
 pub const GENESIS_JSON_HASH_KEY: &[u8; 17] = b"GENESIS_JSON_HASH";
 pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
@@ -261,6 +351,21 @@ pub enum DBOp {
     DeleteAll { col: DBCol },
 }
 
+/// Size statistics of a single column as estimated by RocksDB.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnStats {
+    /// Estimated number of keys in the column.
+    pub estimate_num_keys: u64,
+    /// Total size of all SST files of the column, including obsolete ones.
+    pub total_sst_files_size: u64,
+    /// Size of SST files which belong to the latest version of the column.
+    pub live_sst_files_size: u64,
+    /// Estimated size of live data in the column.
+    pub estimate_live_data_size: u64,
+    /// Number of SST files at level 0.
+    pub num_files_at_level0: u64,
+}
+
 impl DBTransaction {
     pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, col: DBCol, key: K, value: V) {
         self.ops.push(DBOp::Insert {
@@ -459,6 +564,9 @@ pub trait Database: Sync + Send {
     fn as_rocksdb(&self) -> Option<&RocksDB> {
         None
     }
+    fn as_split_db(&self) -> Option<&SplitDB> {
+        None
+    }
 }
 
 impl Database for RocksDB {
@@ -696,10 +804,56 @@ impl RocksDB {
         RocksDBOptions::default().read_only(path)
     }
 
+    /// Opens all columns of the database in read only mode.
+    pub fn new_read_only_all_columns<P: AsRef<std::path::Path>>(path: P) -> Result<Self, DBError> {
+        use strum::IntoEnumIterator;
+        RocksDBOptions::default()
+            .cf_names(DBCol::iter().map(|col| format!("col{}", col as usize)).collect())
+            .read_only(path)
+    }
+
     pub fn new<P: AsRef<std::path::Path>>(path: P) -> Result<Self, DBError> {
         RocksDBOptions::default().read_write(path)
     }
 
+    /// Returns size statistics of the given column.
+    pub fn column_stats(&self, col: DBCol) -> Result<ColumnStats, DBError> {
+        let cf_handle = unsafe { &*self.cfs[col as usize] };
+        let property = |name: &str| -> Result<u64, DBError> {
+            Ok(self.db.property_int_value_cf(cf_handle, name)?.unwrap_or_default())
+        };
+        Ok(ColumnStats {
+            estimate_num_keys: property("rocksdb.estimate-num-keys")?,
+            total_sst_files_size: property("rocksdb.total-sst-files-size")?,
+            live_sst_files_size: property("rocksdb.live-sst-files-size")?,
+            estimate_live_data_size: property("rocksdb.estimate-live-data-size")?,
+            num_files_at_level0: property("rocksdb.num-files-at-level0")?,
+        })
+    }
+
+    /// Whether manual compaction can be run, with `single_thread_rocksdb` it hangs forever.
+    pub const fn can_compact() -> bool {
+        cfg!(not(feature = "single_thread_rocksdb"))
+    }
+
+    /// Runs manual compaction over the whole key range of the given column.
+    /// Panics if manual compaction isn't supported, see `can_compact`.
+    pub fn compact(&self, col: DBCol) {
+        assert!(Self::can_compact(), "Manual compaction hangs with single_thread_rocksdb");
+        self.db.compact_range_cf(
+            unsafe { &*self.cfs[col as usize] },
+            Option::<&[u8]>::None,
+            Option::<&[u8]>::None,
+        );
+    }
+
+    /// Creates a consistent point-in-time copy of the database in `path`.
+    /// `path` must not exist yet. SST files are hard-linked if `path` is on the same filesystem.
+    pub fn create_checkpoint<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), DBError> {
+        let checkpoint = rocksdb::checkpoint::Checkpoint::new(&self.db)?;
+        Ok(checkpoint.create_checkpoint(path)?)
+    }
+
     /// Checks if there is enough memory left to perform a write. Not having enough memory left can
     /// lead to difficult to recover from state, thus a PreWriteCheckErr is pretty much
     /// unrecoverable in most cases.
@@ -770,20 +924,11 @@ impl TestDB {
 
 #[cfg(test)]
 mod tests {
-    use crate::db::DBCol::ColState;
+    use crate::db::DBCol::{ColBlockMisc, ColState};
     use crate::db::{rocksdb_read_options, DBError, Database, RocksDB};
     use crate::{create_store, DBCol};
 
     impl RocksDB {
-        #[cfg(not(feature = "single_thread_rocksdb"))]
-        fn compact(&self, col: DBCol) {
-            self.db.compact_range_cf(
-                unsafe { &*self.cfs[col as usize] },
-                Option::<&[u8]>::None,
-                Option::<&[u8]>::None,
-            );
-        }
-
         fn get_no_empty_filtering(
             &self,
             col: DBCol,
@@ -824,6 +969,27 @@ mod tests {
         assert_eq!(store.get(ColState, &[1]).unwrap(), None);
     }
 
+    #[test]
+    fn test_checkpoint() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_checkpoint").tempdir().unwrap();
+        let store = create_store(tmp_dir.path().join("data").to_str().unwrap());
+        {
+            let mut store_update = store.store_update();
+            store_update.set(ColBlockMisc, b"key", b"value");
+            store_update.commit().unwrap();
+        }
+        let checkpoint_path = tmp_dir.path().join("checkpoint");
+        store.get_rocksdb().unwrap().create_checkpoint(&checkpoint_path).unwrap();
+        {
+            let mut store_update = store.store_update();
+            store_update.set(ColBlockMisc, b"key", b"new value");
+            store_update.commit().unwrap();
+        }
+        let checkpoint = create_store(checkpoint_path.to_str().unwrap());
+        assert_eq!(checkpoint.get(ColBlockMisc, b"key").unwrap(), Some(b"value".to_vec()));
+        assert!(checkpoint.get_rocksdb().unwrap().column_stats(ColBlockMisc).is_ok());
+    }
+
     #[test]
     fn rocksdb_merge_sanity() {
         let tmp_dir = tempfile::Builder::new().prefix("_test_snapshot_sanity").tempdir().unwrap();
@@ -0,0 +1,483 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use tracing::error;
+
+use crate::db::refcount::decode_value_with_rc;
+use crate::db::{
+    DBCol, DBError, DBOp, DBTransaction, Database, RocksDB, SplitDB, LARGEST_TARGET_HEIGHT_KEY,
+};
+
+/// Maximum number of transactions waiting to be committed. Writes block once it's reached, so
+/// a slow disk throttles block processing instead of growing the queue without bounds.
+const MAX_PENDING_TRANSACTIONS: usize = 16;
+
+/// Database which commits transactions on a dedicated writer thread.
+///
+/// `write` only queues the transaction, so the caller doesn't wait for RocksDB to persist it.
+/// Transactions are committed one by one in the order they were written, and the number of
+/// committed transactions is the watermark readers wait on. Until a transaction is committed,
+/// reads of the keys it touches are answered from the queue, so readers observe all the writes
+/// made so far exactly as with a synchronous database. Reads which can't be answered from the
+/// queue, i.e. iterators and refcounted values which lost references, wait for the watermark.
+///
+/// If the node stops before the queue is committed the latest transactions are lost, but the
+/// database stays consistent since every transaction is committed atomically and in order. Losing
+/// the Doomslug largest target height or the block approvals could make a validator sign twice
+/// after a restart though, so transactions writing them are committed before `write` returns.
+///
+/// A failed commit is returned by the following reads and writes. Iterators have no way to return
+/// it, so they panic rather than silently skip the writes which were never committed.
+pub struct AsyncWriteDB {
+    inner: Pin<Arc<dyn Database>>,
+    queue: Arc<(Mutex<WriteQueue>, Condvar)>,
+    sender: Mutex<Option<Sender<DBTransaction>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+struct WriteQueue {
+    /// Transactions which are written but not committed yet, oldest first.
+    pending: VecDeque<PendingTransaction>,
+    /// Number of transactions written so far.
+    written: u64,
+    /// Number of transactions committed to the inner database so far.
+    committed: u64,
+    /// Error of the failed commit. Transactions after it are never committed.
+    error: Option<DBError>,
+}
+
+struct PendingTransaction {
+    /// Value of `WriteQueue::written` once the transaction is written.
+    seq: u64,
+    values: HashMap<(DBCol, Vec<u8>), PendingValue>,
+    /// Columns which are cleared by the transaction before writing the keys in `values`.
+    deleted_cols: Vec<DBCol>,
+}
+
+enum PendingValue {
+    /// Value of a regular column, `None` if the key is deleted.
+    Value(Option<Vec<u8>>),
+    /// Value of a refcounted column which only got references added. Refcounts are never
+    /// negative in the database, so the value is present once the transaction is committed.
+    Referenced(Vec<u8>),
+    /// The value is only known once the transaction is merged into the database.
+    Unknown,
+}
+
+/// Result of looking up a key in the pending transactions.
+enum Lookup {
+    Found(Option<Vec<u8>>),
+    NotFound,
+    /// The transaction with the given sequence number has to be committed first.
+    WaitFor(u64),
+}
+
+/// Whether the transaction writes data a validator relies on not to sign conflicting messages.
+fn must_be_durable(transaction: &DBTransaction) -> bool {
+    transaction.ops.iter().any(|op| match op {
+        DBOp::Insert { col: DBCol::ColBlockMisc, key, .. }
+        | DBOp::Delete { col: DBCol::ColBlockMisc, key } => key == LARGEST_TARGET_HEIGHT_KEY,
+        DBOp::Insert { col: DBCol::ColBlockApprovals, .. }
+        | DBOp::DeleteAll { col: DBCol::ColBlockMisc }
+        | DBOp::DeleteAll { col: DBCol::ColBlockApprovals } => true,
+        _ => false,
+    })
+}
+
+impl PendingTransaction {
+    fn new(seq: u64, transaction: &DBTransaction) -> Self {
+        let mut values = HashMap::new();
+        let mut deleted_cols = vec![];
+        for op in transaction.ops.iter() {
+            match op {
+                DBOp::Insert { col, key, value } => {
+                    let pending = if col.is_rc() {
+                        PendingValue::Unknown
+                    } else {
+                        PendingValue::Value(Some(value.clone()))
+                    };
+                    values.insert((*col, key.clone()), pending);
+                }
+                DBOp::UpdateRefcount { col, key, value } => {
+                    let added = match decode_value_with_rc(value) {
+                        (Some(value), rc) if rc > 0 => Some(value.to_vec()),
+                        _ => None,
+                    };
+                    let key = (*col, key.clone());
+                    let pending = match (values.remove(&key), added) {
+                        (None, Some(value)) | (Some(PendingValue::Referenced(_)), Some(value)) => {
+                            PendingValue::Referenced(value)
+                        }
+                        _ => PendingValue::Unknown,
+                    };
+                    values.insert(key, pending);
+                }
+                DBOp::Delete { col, key } => {
+                    let pending =
+                        if col.is_rc() { PendingValue::Unknown } else { PendingValue::Value(None) };
+                    values.insert((*col, key.clone()), pending);
+                }
+                DBOp::DeleteAll { col } => {
+                    values.retain(|(value_col, _), _| value_col != col);
+                    deleted_cols.push(*col);
+                }
+            }
+        }
+        PendingTransaction { seq, values, deleted_cols }
+    }
+
+    fn lookup(&self, col: DBCol, key: &[u8]) -> Lookup {
+        match self.values.get(&(col, key.to_vec())) {
+            Some(PendingValue::Value(value)) => Lookup::Found(value.clone()),
+            Some(PendingValue::Referenced(value)) => Lookup::Found(Some(value.clone())),
+            Some(PendingValue::Unknown) => Lookup::WaitFor(self.seq),
+            None if self.deleted_cols.contains(&col) => {
+                if col.is_rc() {
+                    Lookup::WaitFor(self.seq)
+                } else {
+                    Lookup::Found(None)
+                }
+            }
+            None => Lookup::NotFound,
+        }
+    }
+}
+
+impl WriteQueue {
+    fn lookup(&self, col: DBCol, key: &[u8]) -> Lookup {
+        for transaction in self.pending.iter().rev() {
+            match transaction.lookup(col, key) {
+                Lookup::NotFound => continue,
+                result => return result,
+            }
+        }
+        Lookup::NotFound
+    }
+
+    fn check_error(&self) -> Result<(), DBError> {
+        match &self.error {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AsyncWriteDB {
+    pub fn new(inner: Pin<Arc<dyn Database>>) -> Self {
+        let queue = Arc::new((
+            Mutex::new(WriteQueue {
+                pending: VecDeque::new(),
+                written: 0,
+                committed: 0,
+                error: None,
+            }),
+            Condvar::new(),
+        ));
+        let (sender, receiver) = channel();
+        let writer = {
+            let inner = inner.clone();
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name("store-writer".to_string())
+                .spawn(move || run_writer(inner, queue, receiver))
+                .expect("Failed to start the store writer thread")
+        };
+        AsyncWriteDB { inner, queue, sender: Mutex::new(Some(sender)), writer: Some(writer) }
+    }
+
+    /// Number of transactions committed to the inner database so far.
+    pub fn committed_up_to(&self) -> u64 {
+        self.lock_queue().committed
+    }
+
+    /// Waits until all the transactions written so far are committed.
+    pub fn flush(&self) -> Result<(), DBError> {
+        let queue = self.lock_queue();
+        let written = queue.written;
+        self.wait_for(queue, written).map(|_| ())
+    }
+
+    /// Commits the pending writes before reading the inner database with no way to return an
+    /// error.
+    fn flush_or_panic(&self) {
+        if let Err(err) = self.flush() {
+            panic!("Failed to commit the pending writes: {:?}", err);
+        }
+    }
+
+    fn lock_queue(&self) -> MutexGuard<WriteQueue> {
+        self.queue.0.lock().expect("Store write queue lock is poisoned")
+    }
+
+    /// Waits until the transaction with the given sequence number is committed.
+    fn wait_for<'a>(
+        &'a self,
+        mut queue: MutexGuard<'a, WriteQueue>,
+        seq: u64,
+    ) -> Result<MutexGuard<'a, WriteQueue>, DBError> {
+        while queue.committed < seq {
+            queue.check_error()?;
+            queue = self.queue.1.wait(queue).expect("Store write queue lock is poisoned");
+        }
+        Ok(queue)
+    }
+}
+
+fn run_writer(
+    inner: Pin<Arc<dyn Database>>,
+    queue: Arc<(Mutex<WriteQueue>, Condvar)>,
+    receiver: Receiver<DBTransaction>,
+) {
+    let (lock, condvar) = &*queue;
+    for transaction in receiver {
+        let result = inner.write(transaction);
+        let mut queue = lock.lock().expect("Store write queue lock is poisoned");
+        match result {
+            Ok(()) => {
+                queue.pending.pop_front();
+                queue.committed += 1;
+            }
+            Err(err) => {
+                error!(target: "store", "Failed to commit transaction #{}: {:?}", queue.committed + 1, err);
+                queue.error = Some(err);
+            }
+        }
+        condvar.notify_all();
+        if queue.error.is_some() {
+            break;
+        }
+    }
+}
+
+impl Database for AsyncWriteDB {
+    fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+        let queue = self.lock_queue();
+        queue.check_error()?;
+        let queue = match queue.lookup(col, key) {
+            Lookup::Found(value) => return Ok(value),
+            Lookup::NotFound => queue,
+            Lookup::WaitFor(seq) => self.wait_for(queue, seq)?,
+        };
+        drop(queue);
+        self.inner.get(col, key)
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.flush_or_panic();
+        self.inner.iter(col)
+    }
+
+    fn iter_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.flush_or_panic();
+        self.inner.iter_without_rc_logic(col)
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.flush_or_panic();
+        self.inner.iter_prefix(col, key_prefix)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        let mut queue = self.lock_queue();
+        while queue.pending.len() >= MAX_PENDING_TRANSACTIONS && queue.error.is_none() {
+            queue = self.queue.1.wait(queue).expect("Store write queue lock is poisoned");
+        }
+        queue.check_error()?;
+        let seq = queue.written + 1;
+        let durable = must_be_durable(&transaction);
+        queue.pending.push_back(PendingTransaction::new(seq, &transaction));
+        queue.written = seq;
+        // Sending while holding the lock keeps the commit order the same as `seq`.
+        self.sender
+            .lock()
+            .expect("Store writer sender lock is poisoned")
+            .as_ref()
+            .expect("Store writer is stopped")
+            .send(transaction)
+            .expect("Store writer thread is gone");
+        if durable {
+            self.wait_for(queue, seq)?;
+        }
+        Ok(())
+    }
+
+    /// Checkpoints and maintenance have to see all the writes, so the queue is committed first.
+    fn as_rocksdb(&self) -> Option<&RocksDB> {
+        self.flush_or_panic();
+        self.inner.as_rocksdb()
+    }
+
+    fn as_split_db(&self) -> Option<&SplitDB> {
+        self.flush_or_panic();
+        self.inner.as_split_db()
+    }
+}
+
+impl Drop for AsyncWriteDB {
+    /// Commits the queue before the database is closed.
+    fn drop(&mut self) {
+        self.sender.lock().expect("Store writer sender lock is poisoned").take();
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!(target: "store", "Store writer thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use crate::db::refcount::encode_value_with_rc;
+    use crate::db::DBCol::{ColBlock, ColBlockMisc, ColState};
+    use crate::db::{
+        AsyncWriteDB, DBCol, DBError, DBTransaction, Database, TestDB, LARGEST_TARGET_HEIGHT_KEY,
+    };
+
+    /// Database whose writes wait until the gate is opened.
+    struct GatedDB {
+        inner: TestDB,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Database for GatedDB {
+        fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+            self.inner.get(col, key)
+        }
+
+        fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+            self.inner.iter(col)
+        }
+
+        fn iter_without_rc_logic<'a>(
+            &'a self,
+            col: DBCol,
+        ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+            self.inner.iter_without_rc_logic(col)
+        }
+
+        fn iter_prefix<'a>(
+            &'a self,
+            col: DBCol,
+            key_prefix: &'a [u8],
+        ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+            self.inner.iter_prefix(col, key_prefix)
+        }
+
+        fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+            let _gate = self.gate.lock().unwrap();
+            self.inner.write(transaction)
+        }
+    }
+
+    fn gated_db() -> (AsyncWriteDB, Pin<Arc<dyn Database>>, Arc<Mutex<()>>) {
+        let gate = Arc::new(Mutex::new(()));
+        let inner: Pin<Arc<dyn Database>> =
+            Arc::pin(GatedDB { inner: TestDB::new(), gate: gate.clone() });
+        (AsyncWriteDB::new(inner.clone()), inner, gate)
+    }
+
+    #[test]
+    fn test_reads_see_pending_writes() {
+        let (db, inner, gate) = gated_db();
+        let mut transaction = db.transaction();
+        transaction.put(ColBlock, &[1], &[1]);
+        transaction.put(ColBlock, &[2], &[2]);
+        db.write(transaction).unwrap();
+        db.flush().unwrap();
+
+        let closed_gate = gate.lock().unwrap();
+        let mut transaction = db.transaction();
+        transaction.put(ColBlock, &[1], &[3]);
+        transaction.delete(ColBlock, &[2]);
+        transaction.delete_all(ColBlockMisc);
+        transaction.update_refcount(ColState, &[1], &encode_value_with_rc(&[4], 1));
+        db.write(transaction).unwrap();
+
+        assert_eq!(db.get(ColBlock, &[1]).unwrap(), Some(vec![3]));
+        assert_eq!(db.get(ColBlock, &[2]).unwrap(), None);
+        assert_eq!(db.get(ColBlockMisc, &[1]).unwrap(), None);
+        assert_eq!(db.get(ColState, &[1]).unwrap(), Some(vec![4]));
+        assert_eq!(inner.get(ColState, &[1]).unwrap(), None);
+        assert_eq!(db.committed_up_to(), 1);
+
+        drop(closed_gate);
+        db.flush().unwrap();
+        assert_eq!(db.committed_up_to(), 2);
+        assert_eq!(inner.get(ColBlock, &[1]).unwrap(), Some(vec![3]));
+        assert_eq!(inner.get(ColState, &[1]).unwrap(), Some(vec![4]));
+        assert_eq!(db.iter(ColBlock).count(), 1);
+    }
+
+    #[test]
+    fn test_removed_references_wait_for_commit() {
+        let (db, _inner, gate) = gated_db();
+        let mut transaction = db.transaction();
+        transaction.update_refcount(ColState, &[1], &encode_value_with_rc(&[1], 2));
+        db.write(transaction).unwrap();
+        db.flush().unwrap();
+
+        let closed_gate = gate.lock().unwrap();
+        let mut transaction = db.transaction();
+        transaction.update_refcount(ColState, &[1], &encode_value_with_rc(&[1], -2));
+        db.write(transaction).unwrap();
+
+        let reader = std::thread::spawn({
+            let gate = gate.clone();
+            move || {
+                // The read can only complete once the gate is open and the removal committed.
+                let value = db.get(ColState, &[1]).unwrap();
+                assert!(gate.try_lock().is_ok());
+                value
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(closed_gate);
+        assert_eq!(reader.join().unwrap(), None);
+    }
+
+    #[test]
+    fn test_doomslug_writes_are_durable() {
+        let (db, inner, gate) = gated_db();
+        let closed_gate = gate.lock().unwrap();
+        let writer = std::thread::spawn({
+            let gate = gate.clone();
+            move || {
+                let mut transaction = db.transaction();
+                transaction.put(ColBlockMisc, LARGEST_TARGET_HEIGHT_KEY, &[1]);
+                db.write(transaction).unwrap();
+                // The write only returns once the gate is open and the transaction committed.
+                assert!(gate.try_lock().is_ok());
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(closed_gate);
+        writer.join().unwrap();
+        assert_eq!(inner.get(ColBlockMisc, LARGEST_TARGET_HEIGHT_KEY).unwrap(), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_drop_commits_pending_writes() {
+        let inner: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        let db = AsyncWriteDB::new(inner.clone());
+        for i in 0..100u8 {
+            let mut transaction = db.transaction();
+            transaction.put(ColBlock, &[i], &[i]);
+            db.write(transaction).unwrap();
+        }
+        drop(db);
+        assert_eq!(inner.iter(ColBlock).count(), 100);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use tracing::error;
+
+use near_primitives::hash::{hash, CryptoHash};
+
+use crate::db::refcount::encode_value_with_rc;
+use crate::db::{DBCol, DBError, DBOp, DBTransaction, Database, RocksDB, SplitDB};
+
+/// Values of blob columns which are at least this large are moved to `ColBlobs`.
+pub const DEFAULT_BLOB_THRESHOLD: usize = 64 * 1024;
+
+/// First byte of the references to `ColBlobs` stored in blob columns. Values of blob columns
+/// are borsh-serialized enums, so they never start with it.
+const BLOB_REF_TAG: u8 = 0xff;
+
+const BLOB_REF_LEN: usize = 1 + 32;
+
+/// Returns the hash of the blob a stored value of a blob column refers to, if it is a reference.
+pub(crate) fn decode_blob_ref(value: &[u8]) -> Option<CryptoHash> {
+    if value.len() == BLOB_REF_LEN && value[0] == BLOB_REF_TAG {
+        CryptoHash::try_from(&value[1..]).ok()
+    } else {
+        None
+    }
+}
+
+/// Database which keeps the large values of the columns marked in `IS_COL_BLOB` separately.
+///
+/// Values of these columns (chunks, compiled contracts) above the threshold are stored in
+/// `ColBlobs`, keyed by the hash of the value, and the blob column only keeps a reference to
+/// it. This keeps the blob columns small, so compactions of them don't rewrite megabytes of
+/// values over and over again. Blobs are reference counted, so equal values are stored once
+/// and removed with their last reference. References are resolved on reads transparently.
+pub struct BlobDB {
+    inner: Pin<Arc<dyn Database>>,
+    threshold: usize,
+    /// Held by writes from reading the values they overwrite until they are written, so the
+    /// blobs referred to by these values are released once.
+    write_lock: Mutex<()>,
+}
+
+impl BlobDB {
+    pub fn new(inner: Pin<Arc<dyn Database>>, threshold: usize) -> Self {
+        BlobDB { inner, threshold, write_lock: Mutex::new(()) }
+    }
+
+    fn encode_ref(blob_hash: &CryptoHash) -> Vec<u8> {
+        let mut value = Vec::with_capacity(BLOB_REF_LEN);
+        value.push(BLOB_REF_TAG);
+        value.extend_from_slice(blob_hash.as_ref());
+        value
+    }
+
+    /// Returns the value a stored value of a blob column stands for.
+    fn resolve(&self, value: Vec<u8>) -> Result<Vec<u8>, DBError> {
+        match decode_blob_ref(&value) {
+            Some(blob_hash) => self
+                .inner
+                .get(DBCol::ColBlobs, blob_hash.as_ref())?
+                .ok_or(DBError::MissingBlob(blob_hash)),
+            None => Ok(value),
+        }
+    }
+
+    /// Resolves the values of the iterator. Since iterators can't return errors, the values
+    /// which can't be resolved are logged and skipped.
+    fn resolve_iter<'a>(
+        &'a self,
+        col: DBCol,
+        iterator: Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        if !col.is_blob() {
+            return iterator;
+        }
+        Box::new(iterator.filter_map(move |(key, value)| match self.resolve(value.into_vec()) {
+            Ok(value) => Some((key, value.into_boxed_slice())),
+            Err(err) => {
+                error!(target: "store", "Failed to read the value of {:?} in {}: {}", key, col, err);
+                None
+            }
+        }))
+    }
+}
+
+impl Database for BlobDB {
+    fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+        match self.inner.get(col, key)? {
+            Some(value) if col.is_blob() => Ok(Some(self.resolve(value)?)),
+            result => Ok(result),
+        }
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.resolve_iter(col, self.inner.iter(col))
+    }
+
+    fn iter_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.resolve_iter(col, self.inner.iter_without_rc_logic(col))
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.resolve_iter(col, self.inner.iter_prefix(col, key_prefix))
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        let _write_guard = self.write_lock.lock().expect("Blob write lock is poisoned");
+        let mut result = self.inner.transaction();
+        // Stored values of the keys of blob columns written by this transaction so far.
+        let mut written: HashMap<(DBCol, Vec<u8>), Option<Vec<u8>>> = HashMap::new();
+        // Blob columns cleared by this transaction, the database values of which are released.
+        let mut cleared: HashSet<DBCol> = HashSet::new();
+        for op in transaction.ops {
+            let (col, key) = match &op {
+                DBOp::Insert { col, key, .. } | DBOp::Delete { col, key } if col.is_blob() => {
+                    (*col, key.clone())
+                }
+                DBOp::DeleteAll { col } if col.is_blob() => {
+                    if cleared.insert(*col) {
+                        for (key, value) in self.inner.iter_without_rc_logic(*col) {
+                            // Values overwritten by this transaction are already released.
+                            if written.contains_key(&(*col, key.to_vec())) {
+                                continue;
+                            }
+                            if let Some(blob_hash) = decode_blob_ref(&value) {
+                                result.update_refcount(
+                                    DBCol::ColBlobs,
+                                    &blob_hash,
+                                    encode_value_with_rc(&[], -1),
+                                );
+                            }
+                        }
+                    }
+                    for ((written_col, _), value) in written.iter_mut() {
+                        if written_col != col {
+                            continue;
+                        }
+                        if let Some(blob_hash) = value.as_deref().and_then(decode_blob_ref) {
+                            result.update_refcount(
+                                DBCol::ColBlobs,
+                                &blob_hash,
+                                encode_value_with_rc(&[], -1),
+                            );
+                        }
+                        *value = None;
+                    }
+                    result.ops.push(op);
+                    continue;
+                }
+                _ => {
+                    result.ops.push(op);
+                    continue;
+                }
+            };
+            // Release the blob the previous value of the key refers to.
+            let prev_value = match written.get(&(col, key.clone())) {
+                Some(value) => value.clone(),
+                None if cleared.contains(&col) => None,
+                None => self.inner.get(col, &key)?,
+            };
+            if let Some(blob_hash) = prev_value.as_deref().and_then(decode_blob_ref) {
+                result.update_refcount(DBCol::ColBlobs, &blob_hash, encode_value_with_rc(&[], -1));
+            }
+            let value = match op {
+                DBOp::Insert { value, .. } if value.len() >= self.threshold => {
+                    let blob_hash = hash(&value);
+                    result.update_refcount(
+                        DBCol::ColBlobs,
+                        &blob_hash,
+                        encode_value_with_rc(&value, 1),
+                    );
+                    let value = Self::encode_ref(&blob_hash);
+                    result.put(col, &key, &value);
+                    Some(value)
+                }
+                DBOp::Insert { value, .. } => {
+                    result.put(col, &key, &value);
+                    Some(value)
+                }
+                _ => {
+                    result.delete(col, &key);
+                    None
+                }
+            };
+            written.insert((col, key), value);
+        }
+        self.inner.write(result)
+    }
+
+    fn as_rocksdb(&self) -> Option<&RocksDB> {
+        self.inner.as_rocksdb()
+    }
+
+    fn as_split_db(&self) -> Option<&SplitDB> {
+        self.inner.as_split_db()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::Arc;
+
+    use crate::db::DBCol::{ColBlobs, ColBlockMisc, ColChunks};
+    use crate::db::{BlobDB, Database, TestDB};
+    use crate::Store;
+
+    fn blob_store() -> (Store, Pin<Arc<dyn Database>>) {
+        let inner: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        (Store::new(Arc::pin(BlobDB::new(inner.clone(), 4))), inner)
+    }
+
+    #[test]
+    fn test_blob_separation() {
+        let (store, inner) = blob_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColChunks, &[1], &[1, 2, 3, 4]);
+        store_update.set(ColChunks, &[2], &[1, 2, 3, 4]);
+        store_update.set(ColChunks, &[3], &[1]);
+        store_update.set(ColBlockMisc, &[1], &[1, 2, 3, 4]);
+        store_update.commit().unwrap();
+        // Large values of blob columns are stored once and referenced.
+        assert_eq!(inner.iter(ColBlobs).count(), 1);
+        assert_eq!(inner.get(ColChunks, &[1]).unwrap().unwrap().len(), 33);
+        assert_eq!(inner.get(ColChunks, &[3]).unwrap(), Some(vec![1]));
+        assert_eq!(inner.get(ColBlockMisc, &[1]).unwrap(), Some(vec![1, 2, 3, 4]));
+        // References are resolved on reads.
+        assert_eq!(store.get(ColChunks, &[1]).unwrap(), Some(vec![1, 2, 3, 4]));
+        let mut values = store.iter(ColChunks).map(|(_, value)| value.to_vec()).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![vec![1], vec![1, 2, 3, 4], vec![1, 2, 3, 4]]);
+
+        // The blob is removed with its last reference.
+        let mut store_update = store.store_update();
+        store_update.delete(ColChunks, &[1]);
+        store_update.set(ColChunks, &[2], &[2]);
+        store_update.commit().unwrap();
+        assert_eq!(inner.iter(ColBlobs).count(), 0);
+        assert_eq!(store.get(ColChunks, &[2]).unwrap(), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_blob_overwritten_in_transaction() {
+        let (store, inner) = blob_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColChunks, &[1], &[1, 2, 3, 4]);
+        store_update.set(ColChunks, &[1], &[5, 6, 7, 8]);
+        store_update.commit().unwrap();
+        assert_eq!(inner.iter(ColBlobs).count(), 1);
+        assert_eq!(store.get(ColChunks, &[1]).unwrap(), Some(vec![5, 6, 7, 8]));
+
+        let mut store_update = store.store_update();
+        store_update.delete_all(ColChunks);
+        store_update.commit().unwrap();
+        assert_eq!(inner.iter(ColBlobs).count(), 0);
+    }
+
+    #[test]
+    fn test_missing_blob() {
+        let (store, inner) = blob_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColChunks, &[1], &[1, 2, 3, 4]);
+        store_update.set(ColChunks, &[2], &[1]);
+        store_update.commit().unwrap();
+        let mut transaction = inner.transaction();
+        transaction.delete_all(ColBlobs);
+        inner.write(transaction).unwrap();
+
+        assert!(store.get(ColChunks, &[1]).is_err());
+        // The values which can't be resolved are skipped by iterators.
+        let values = store.iter(ColChunks).map(|(_, value)| value.to_vec()).collect::<Vec<_>>();
+        assert_eq!(values, vec![vec![1]]);
+    }
+}
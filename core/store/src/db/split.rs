@@ -0,0 +1,212 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::db::blob::decode_blob_ref;
+use crate::db::refcount::encode_value_with_rc;
+use crate::db::{DBCol, DBError, DBOp, DBTransaction, Database, RocksDB};
+
+/// Database of an archival node split into a hot and a cold part.
+///
+/// All the writes go to the hot database. Data of the columns marked in `IS_COL_COLD` which
+/// is older than the GC horizon is moved to the cold database with `move_to_cold`, so the
+/// cold database can live on cheaper and slower disks. Reads of these columns fall back to
+/// the cold database transparently; all the other columns only live in the hot database.
+pub struct SplitDB {
+    hot: Pin<Arc<dyn Database>>,
+    cold: Pin<Arc<dyn Database>>,
+}
+
+impl SplitDB {
+    pub fn new(hot: Pin<Arc<dyn Database>>, cold: Pin<Arc<dyn Database>>) -> Self {
+        SplitDB { hot, cold }
+    }
+
+    /// Moves the values of the given keys from the hot database to the cold one. Keys missing
+    /// from the hot database are skipped. Values of blob columns referring to `ColBlobs`, see
+    /// `BlobDB`, are moved resolved, and the reference to the blob in the hot database is
+    /// released.
+    ///
+    /// Values are written to the cold database before they are deleted from the hot one, so
+    /// a crash in between only leaves a duplicate behind.
+    pub fn move_to_cold(&self, keys: &[(DBCol, Vec<u8>)]) -> Result<(), DBError> {
+        let mut cold_transaction = self.cold.transaction();
+        let mut hot_transaction = self.hot.transaction();
+        for (col, key) in keys {
+            assert!(col.is_cold(), "Column {} can't be moved to the cold database", col);
+            if let Some(value) = self.hot.get(*col, key)? {
+                let value = match decode_blob_ref(&value).filter(|_| col.is_blob()) {
+                    Some(blob_hash) => {
+                        hot_transaction.update_refcount(
+                            DBCol::ColBlobs,
+                            &blob_hash,
+                            encode_value_with_rc(&[], -1),
+                        );
+                        self.hot
+                            .get(DBCol::ColBlobs, blob_hash.as_ref())?
+                            .ok_or(DBError::MissingBlob(blob_hash))?
+                    }
+                    None => value,
+                };
+                cold_transaction.put(*col, key, value);
+                hot_transaction.delete(*col, key);
+            }
+        }
+        self.cold.write(cold_transaction)?;
+        self.hot.write(hot_transaction)
+    }
+}
+
+impl Database for SplitDB {
+    fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+        let result = self.hot.get(col, key)?;
+        if result.is_none() && col.is_cold() {
+            return self.cold.get(col, key);
+        }
+        Ok(result)
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        if col.is_cold() {
+            Box::new(self.hot.iter(col).chain(self.cold.iter(col)))
+        } else {
+            self.hot.iter(col)
+        }
+    }
+
+    fn iter_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        if col.is_cold() {
+            Box::new(
+                self.hot.iter_without_rc_logic(col).chain(self.cold.iter_without_rc_logic(col)),
+            )
+        } else {
+            self.hot.iter_without_rc_logic(col)
+        }
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        if col.is_cold() {
+            Box::new(
+                self.hot.iter_prefix(col, key_prefix).chain(self.cold.iter_prefix(col, key_prefix)),
+            )
+        } else {
+            self.hot.iter_prefix(col, key_prefix)
+        }
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        // Deletions of cold columns have to reach the data which was already moved.
+        let mut cold_transaction = self.cold.transaction();
+        for op in transaction.ops.iter() {
+            match op {
+                DBOp::Delete { col, key } if col.is_cold() => cold_transaction.delete(*col, key),
+                DBOp::DeleteAll { col } if col.is_cold() => cold_transaction.delete_all(*col),
+                _ => {}
+            }
+        }
+        if !cold_transaction.ops.is_empty() {
+            self.cold.write(cold_transaction)?;
+        }
+        self.hot.write(transaction)
+    }
+
+    /// Returns the hot database, which is the one that has to be checkpointed or inspected.
+    fn as_rocksdb(&self) -> Option<&RocksDB> {
+        self.hot.as_rocksdb()
+    }
+
+    fn as_split_db(&self) -> Option<&SplitDB> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::Arc;
+
+    use crate::db::DBCol::{ColBlobs, ColBlock, ColBlockMisc, ColChunks};
+    use crate::db::{BlobDB, Database, SplitDB, TestDB};
+    use crate::Store;
+
+    fn split_store() -> (Store, Pin<Arc<dyn Database>>, Pin<Arc<dyn Database>>) {
+        let hot: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        let cold: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        let split_db = SplitDB::new(hot.clone(), cold.clone());
+        (Store::new(Arc::pin(split_db)), hot, cold)
+    }
+
+    #[test]
+    fn test_move_to_cold() {
+        let (store, hot, cold) = split_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColBlock, &[1], &[1]);
+        store_update.set(ColBlock, &[2], &[2]);
+        store_update.commit().unwrap();
+        assert_eq!(cold.get(ColBlock, &[1]).unwrap(), None);
+
+        store.move_to_cold(&[(ColBlock, vec![1]), (ColBlock, vec![3])]).unwrap();
+        assert_eq!(hot.get(ColBlock, &[1]).unwrap(), None);
+        assert_eq!(cold.get(ColBlock, &[1]).unwrap(), Some(vec![1]));
+        assert_eq!(cold.get(ColBlock, &[3]).unwrap(), None);
+        // Reads are routed to the cold database transparently.
+        assert_eq!(store.get(ColBlock, &[1]).unwrap(), Some(vec![1]));
+        assert_eq!(store.get(ColBlock, &[2]).unwrap(), Some(vec![2]));
+        let mut keys = store.iter(ColBlock).map(|(key, _)| key.to_vec()).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec![vec![1], vec![2]]);
+
+        let mut store_update = store.store_update();
+        store_update.delete(ColBlock, &[1]);
+        store_update.commit().unwrap();
+        assert_eq!(store.get(ColBlock, &[1]).unwrap(), None);
+    }
+
+    /// Test the blobs referred to by the moved values are moved along with them.
+    #[test]
+    fn test_move_blobs_to_cold() {
+        let hot: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        let cold: Pin<Arc<dyn Database>> = Arc::pin(TestDB::new());
+        let split_db: Pin<Arc<dyn Database>> = Arc::pin(SplitDB::new(hot.clone(), cold.clone()));
+        let store = Store::new(Arc::pin(BlobDB::new(split_db, 4)));
+        let mut store_update = store.store_update();
+        store_update.set(ColChunks, &[1], &[1, 2, 3, 4]);
+        store_update.set(ColChunks, &[2], &[1, 2, 3, 4]);
+        store_update.commit().unwrap();
+
+        store.move_to_cold(&[(ColChunks, vec![1])]).unwrap();
+        assert_eq!(cold.get(ColChunks, &[1]).unwrap(), Some(vec![1, 2, 3, 4]));
+        assert_eq!(store.get(ColChunks, &[1]).unwrap(), Some(vec![1, 2, 3, 4]));
+        // The blob is still referred to by the value in the hot database.
+        assert_eq!(hot.iter(ColBlobs).count(), 1);
+
+        store.move_to_cold(&[(ColChunks, vec![2])]).unwrap();
+        assert_eq!(hot.iter(ColBlobs).count(), 0);
+        assert_eq!(cold.iter(ColBlobs).count(), 0);
+        assert_eq!(store.get(ColChunks, &[2]).unwrap(), Some(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_hot_only_column() {
+        let (store, _hot, cold) = split_store();
+        let mut cold_transaction = cold.transaction();
+        cold_transaction.put(ColBlockMisc, &[1], &[1]);
+        cold.write(cold_transaction).unwrap();
+        assert_eq!(store.get(ColBlockMisc, &[1]).unwrap(), None);
+        assert!(store.move_to_cold(&[]).is_ok());
+        assert!(store.has_cold_storage());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_move_hot_only_column() {
+        let (store, _hot, _cold) = split_store();
+        let _ = store.move_to_cold(&[(ColBlockMisc, vec![1])]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Export of database columns and state records into CSV, so the data can be analyzed with
+//! generic tools instead of custom decoders of the storage format.
+use std::io;
+
+use near_primitives::serialize::to_base64;
+use near_primitives::state_record::StateRecord;
+use near_primitives::types::StateRoot;
+
+use crate::{DBCol, Store, Trie};
+
+/// Writes all key-value pairs of the column as `key,value` rows, both encoded in base64.
+/// Returns the number of exported rows.
+pub fn export_column_csv<W: io::Write>(store: &Store, column: DBCol, writer: W) -> io::Result<u64> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&["key", "value"])?;
+    let mut rows = 0;
+    for (key, value) in store.iter(column) {
+        writer.write_record(&[to_base64(&key), to_base64(&value)])?;
+        rows += 1;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+/// Writes the records of the state as `kind,account_id,record` rows, where `record` is the
+/// record in the JSON format of genesis records. Returns the number of exported rows.
+pub fn export_state_records_csv<W: io::Write>(
+    trie: &Trie,
+    state_root: &StateRoot,
+    writer: W,
+) -> io::Result<u64> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&["kind", "account_id", "record"])?;
+    let mut rows = 0;
+    let iter = trie.iter(state_root).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    for item in iter {
+        let (key, value) = item.map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let record = match StateRecord::from_raw_key_value(key, value) {
+            Some(record) => record,
+            None => continue,
+        };
+        let (kind, account_id) = match &record {
+            StateRecord::Account { account_id, .. } => ("account", account_id),
+            StateRecord::Data { account_id, .. } => ("data", account_id),
+            StateRecord::Contract { account_id, .. } => ("contract", account_id),
+            StateRecord::AccessKey { account_id, .. } => ("access_key", account_id),
+            StateRecord::PostponedReceipt(receipt) => ("postponed_receipt", &receipt.receiver_id),
+            StateRecord::ReceivedData { account_id, .. } => ("received_data", account_id),
+            StateRecord::DelayedReceipt(receipt) => ("delayed_receipt", &receipt.receiver_id),
+        };
+        let record = serde_json::to_string(&record)?;
+        writer.write_record(&[kind, account_id.as_str(), record.as_str()])?;
+        rows += 1;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::account::Account;
+    use near_primitives::trie_key::TrieKey;
+
+    use super::*;
+    use crate::test_utils::{create_test_store, create_tries};
+    use crate::ColBlockMisc;
+
+    #[test]
+    fn test_export_column() {
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColBlockMisc, b"key", b"value");
+        store_update.commit().unwrap();
+
+        let mut output = vec![];
+        assert_eq!(export_column_csv(&store, ColBlockMisc, &mut output).unwrap(), 1);
+        assert_eq!(String::from_utf8(output).unwrap(), "key,value\na2V5,dmFsdWU=\n");
+    }
+
+    #[test]
+    fn test_export_state_records() {
+        let tries = create_tries();
+        let account = Account::new(1, 0, Default::default(), 0);
+        let changes = vec![
+            (
+                TrieKey::Account { account_id: "alice".to_string() }.to_vec(),
+                Some(borsh::BorshSerialize::try_to_vec(&account).unwrap()),
+            ),
+            (
+                TrieKey::ContractData { account_id: "alice".to_string(), key: b"k".to_vec() }
+                    .to_vec(),
+                Some(b"v".to_vec()),
+            ),
+        ];
+        let state_root =
+            crate::test_utils::test_populate_trie(&tries, &Trie::empty_root(), 0, changes);
+
+        let mut output = vec![];
+        let trie = tries.get_trie_for_shard(0);
+        assert_eq!(export_state_records_csv(&trie, &state_root, &mut output).unwrap(), 2);
+        let output = String::from_utf8(output).unwrap();
+        let rows = output.lines().collect::<Vec<_>>();
+        assert_eq!(rows[0], "kind,account_id,record");
+        assert!(rows[1].starts_with("account,alice,"));
+        assert!(rows[2].starts_with("data,alice,"));
+    }
+}
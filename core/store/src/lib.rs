@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::ops::Deref;
@@ -15,18 +16,21 @@ use cached::{Cached, SizedCache};
 
 pub use db::DBCol::{self, *};
 pub use db::{
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    AsyncWriteDB, BlobDB, SplitDB, CHUNK_TAIL_KEY, COLD_HEAD_KEY, DEFAULT_BLOB_THRESHOLD,
+    FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, IS_COL_COLD,
     LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
+    VERSION_KEY,
 };
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, Account};
+use near_primitives::challenge::SlashEscrow;
 use near_primitives::contract::ContractCode;
 pub use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{Receipt, ReceivedData};
 use near_primitives::serialize::to_base;
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
-use near_primitives::types::{AccountId, CompiledContractCache, StateRoot};
+use near_primitives::types::{AccountId, BurnLedger, CompiledContractCache, Gas, StateRoot};
 
 pub use crate::db::refcount::decode_value_with_rc;
 use crate::db::refcount::encode_value_with_rc;
@@ -34,12 +38,14 @@ use crate::db::{
     DBOp, DBTransaction, Database, RocksDB, GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY,
 };
 pub use crate::trie::{
-    iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateIterator,
-    update::TrieUpdateValuePtr, ApplyStatePartResult, KeyForStateChanges, PartialStorage,
-    ShardTries, Trie, TrieChanges, WrappedTrieChanges,
+    iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateCheckpoint,
+    update::TrieUpdateIterator, update::TrieUpdateValuePtr, ApplyStatePartResult,
+    KeyForStateChanges, PartialStorage, ShardTries, SplitStateStatus, StateSplitter, Trie,
+    TrieChanges, WrappedTrieChanges,
 };
 
 pub mod db;
+pub mod export;
 pub mod migrations;
 pub mod test_utils;
 mod trie;
@@ -148,6 +154,19 @@ impl Store {
     pub fn get_rocksdb(&self) -> Option<&RocksDB> {
         self.storage.as_rocksdb()
     }
+
+    /// Whether the store is split into a hot and a cold database, see `SplitDB`.
+    pub fn has_cold_storage(&self) -> bool {
+        self.storage.as_split_db().is_some()
+    }
+
+    /// Moves the values of the given keys to the cold database.
+    pub fn move_to_cold(&self, keys: &[(DBCol, Vec<u8>)]) -> Result<(), io::Error> {
+        match self.storage.as_split_db() {
+            Some(split_db) => split_db.move_to_cold(keys).map_err(|e| e.into()),
+            None => Err(io::Error::new(io::ErrorKind::Other, "Store has no cold storage")),
+        }
+    }
 }
 
 /// Keeps track of current changes to the database and can commit all of them to the database.
@@ -200,6 +219,127 @@ impl StoreUpdate {
         self.transaction.delete_all(column);
     }
 
+    /// Reads the value of the key as it will be once the update is committed: pending writes and
+    /// deletions take precedence over the value in the database.
+    /// Refcounted columns are not supported, because their refcount in the database is not known.
+    pub fn get(&self, column: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, io::Error> {
+        assert!(!column.is_rc(), "Can't read refcounted column {} from StoreUpdate", column);
+        for op in self.transaction.ops.iter().rev() {
+            match op {
+                DBOp::Insert { col, key: op_key, value }
+                    if *col == column && op_key.as_slice() == key =>
+                {
+                    return Ok(Some(value.clone()));
+                }
+                DBOp::Delete { col, key: op_key } if *col == column && op_key.as_slice() == key => {
+                    return Ok(None);
+                }
+                DBOp::DeleteAll { col } if *col == column => return Ok(None),
+                _ => {}
+            }
+        }
+        self.storage.get(column, key).map_err(|e| e.into())
+    }
+
+    pub fn get_ser<T: BorshDeserialize>(
+        &self,
+        column: DBCol,
+        key: &[u8],
+    ) -> Result<Option<T>, io::Error> {
+        match self.get(column, key)? {
+            Some(bytes) => Ok(Some(T::try_from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Keys deleted by this update.
+    fn deleted_keys(&self) -> HashSet<(DBCol, Vec<u8>)> {
+        self.transaction
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                DBOp::Delete { col, key } => Some((*col, key.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns a key written by both updates, if any. Refcount updates commute, so they only
+    /// conflict with deleting the whole column. Deleting a key twice isn't a conflict either.
+    fn find_conflict(&self, other: &StoreUpdate) -> Option<(DBCol, Vec<u8>)> {
+        let mut keys = HashSet::new();
+        let mut deleted_keys = HashSet::new();
+        let mut columns = HashSet::new();
+        let mut cleared_columns = HashSet::new();
+        for op in self.transaction.ops.iter() {
+            match op {
+                DBOp::Insert { col, key, .. } => {
+                    keys.insert((*col, key.as_slice()));
+                    columns.insert(*col);
+                }
+                DBOp::Delete { col, key } => {
+                    keys.insert((*col, key.as_slice()));
+                    deleted_keys.insert((*col, key.as_slice()));
+                    columns.insert(*col);
+                }
+                DBOp::UpdateRefcount { col, .. } => {
+                    columns.insert(*col);
+                }
+                DBOp::DeleteAll { col } => {
+                    columns.insert(*col);
+                    cleared_columns.insert(*col);
+                }
+            }
+        }
+        for op in other.transaction.ops.iter() {
+            match op {
+                DBOp::Insert { col, key, .. } => {
+                    if keys.contains(&(*col, key.as_slice())) || cleared_columns.contains(col) {
+                        return Some((*col, key.clone()));
+                    }
+                }
+                DBOp::Delete { col, key } => {
+                    let key_ref = (*col, key.as_slice());
+                    if keys.contains(&key_ref) && !deleted_keys.contains(&key_ref)
+                        || cleared_columns.contains(col)
+                    {
+                        return Some((*col, key.clone()));
+                    }
+                }
+                DBOp::UpdateRefcount { col, key, .. } => {
+                    if cleared_columns.contains(col) {
+                        return Some((*col, key.clone()));
+                    }
+                }
+                DBOp::DeleteAll { col } => {
+                    if columns.contains(col) {
+                        return Some((*col, vec![]));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Merges another store update into this one, unless both of them write to the same key.
+    /// Unlike `merge`, the result doesn't depend on the order the updates are merged in.
+    /// A key deleted by both updates is deleted once.
+    pub fn try_merge(&mut self, mut other: StoreUpdate) -> Result<(), io::Error> {
+        if let Some((col, key)) = self.find_conflict(&other) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Store updates conflict on {:?} {}", col, to_base(&key)),
+            ));
+        }
+        let deleted_keys = self.deleted_keys();
+        other.transaction.ops.retain(|op| match op {
+            DBOp::Delete { col, key } => !deleted_keys.contains(&(*col, key.clone())),
+            _ => true,
+        });
+        self.merge(other);
+        Ok(())
+    }
+
     /// Merge another store update into this one.
     pub fn merge(&mut self, other: StoreUpdate) {
         if let Some(tries) = other.tries {
@@ -295,9 +435,24 @@ pub fn read_with_cache<'a, T: BorshDeserialize + 'a>(
     Ok(None)
 }
 
+/// Opens the store at the given path. Large values of blob columns are kept separately, see
+/// `BlobDB`.
 pub fn create_store(path: &str) -> Arc<Store> {
     let db = Arc::pin(RocksDB::new(path).expect("Failed to open the database"));
-    Arc::new(Store::new(db))
+    Arc::new(Store::new(Arc::pin(BlobDB::new(db, DEFAULT_BLOB_THRESHOLD))))
+}
+
+/// Opens the store of an archival node whose old data lives in a separate cold database.
+pub fn create_split_store(path: &str, cold_path: &str) -> Arc<Store> {
+    let hot = Arc::pin(RocksDB::new(path).expect("Failed to open the database"));
+    let cold = Arc::pin(RocksDB::new(cold_path).expect("Failed to open the cold database"));
+    let split_db = Arc::pin(SplitDB::new(hot, cold));
+    Arc::new(Store::new(Arc::pin(BlobDB::new(split_db, DEFAULT_BLOB_THRESHOLD))))
+}
+
+/// Makes the store commit its writes on a dedicated writer thread, see `AsyncWriteDB`.
+pub fn with_async_commits(store: Arc<Store>) -> Arc<Store> {
+    Arc::new(Store::new(Arc::pin(AsyncWriteDB::new(store.storage.clone()))))
 }
 
 /// Reads an object from Trie.
@@ -379,6 +534,121 @@ pub fn get_postponed_receipt(
     get(state_update, &TrieKey::PostponedReceipt { receiver_id: receiver_id.clone(), receipt_id })
 }
 
+pub fn set_slash_escrow(
+    state_update: &mut TrieUpdate,
+    account_id: AccountId,
+    slash_escrow: &SlashEscrow,
+) {
+    set(state_update, TrieKey::SlashEscrow { account_id }, slash_escrow);
+}
+
+pub fn remove_slash_escrow(state_update: &mut TrieUpdate, account_id: AccountId) {
+    state_update.remove(TrieKey::SlashEscrow { account_id });
+}
+
+pub fn get_slash_escrow(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<SlashEscrow>, StorageError> {
+    get(state_update, &TrieKey::SlashEscrow { account_id: account_id.clone() })
+}
+
+/// Returns all slash escrows stored in the state together with the slashed account ids.
+pub fn get_slash_escrows(
+    state_update: &TrieUpdate,
+) -> Result<Vec<(AccountId, SlashEscrow)>, StorageError> {
+    let account_ids = state_update
+        .iter(&trie_key_parsers::get_raw_prefix_for_slash_escrows())?
+        .map(|raw_key| {
+            trie_key_parsers::parse_account_id_from_slash_escrow_key(&raw_key?).map_err(|_e| {
+                StorageError::StorageInconsistentState(
+                    "Can't parse account id from raw key for SlashEscrow".to_string(),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    account_ids
+        .into_iter()
+        .map(|account_id| {
+            let slash_escrow = get_slash_escrow(state_update, &account_id)?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Slash escrow of {} should be in the state",
+                    account_id
+                ))
+            })?;
+            Ok((account_id, slash_escrow))
+        })
+        .collect()
+}
+
+pub fn set_contract_gas_burnt(state_update: &mut TrieUpdate, account_id: AccountId, gas: Gas) {
+    set(state_update, TrieKey::ContractGasBurnt { account_id }, &gas);
+}
+
+pub fn remove_contract_gas_burnt(state_update: &mut TrieUpdate, account_id: AccountId) {
+    state_update.remove(TrieKey::ContractGasBurnt { account_id });
+}
+
+pub fn get_contract_gas_burnt(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<Gas>, StorageError> {
+    get(state_update, &TrieKey::ContractGasBurnt { account_id: account_id.clone() })
+}
+
+/// Returns the gas burnt in the current epoch by the contracts of the shard, by account id.
+pub fn get_all_contract_gas_burnt(
+    state_update: &TrieUpdate,
+) -> Result<Vec<(AccountId, Gas)>, StorageError> {
+    let account_ids = state_update
+        .iter(&trie_key_parsers::get_raw_prefix_for_contract_gas_burnt())?
+        .map(|raw_key| {
+            trie_key_parsers::parse_account_id_from_contract_gas_burnt_key(&raw_key?).map_err(
+                |_e| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse account id from raw key for ContractGasBurnt".to_string(),
+                    )
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    account_ids
+        .into_iter()
+        .map(|account_id| {
+            let gas = get_contract_gas_burnt(state_update, &account_id)?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Gas burnt by the contract of {} should be in the state",
+                    account_id
+                ))
+            })?;
+            Ok((account_id, gas))
+        })
+        .collect()
+}
+
+/// Returns the number of receipts to the given receiver in the delayed receipts queue.
+pub fn get_delayed_receipts_count(
+    state_update: &TrieUpdate,
+    receiver_id: &AccountId,
+) -> Result<u64, StorageError> {
+    Ok(get(state_update, &TrieKey::DelayedReceiptsCount { receiver_id: receiver_id.clone() })?
+        .unwrap_or_default())
+}
+
+/// Stores the number of receipts to the given receiver in the delayed receipts queue, removing the
+/// record once there are none left.
+pub fn set_delayed_receipts_count(
+    state_update: &mut TrieUpdate,
+    receiver_id: AccountId,
+    count: u64,
+) {
+    if count == 0 {
+        state_update.remove(TrieKey::DelayedReceiptsCount { receiver_id });
+    } else {
+        set(state_update, TrieKey::DelayedReceiptsCount { receiver_id }, &count);
+    }
+}
+
 pub fn set_access_key(
     state_update: &mut TrieUpdate,
     account_id: AccountId,
@@ -432,6 +702,30 @@ pub fn get_code(
         .map(|opt| opt.map(|code| ContractCode::new(code, code_hash)))
 }
 
+/// Publishes the code in the shard under its hash.
+pub fn set_global_code(state_update: &mut TrieUpdate, code: &ContractCode) {
+    state_update.set(TrieKey::GlobalContractCode { code_hash: code.get_hash() }, code.code.clone());
+}
+
+/// Returns the code published in the shard under the given hash.
+pub fn get_global_code(
+    state_update: &TrieUpdate,
+    code_hash: &CryptoHash,
+) -> Result<Option<ContractCode>, StorageError> {
+    state_update
+        .get(&TrieKey::GlobalContractCode { code_hash: *code_hash })
+        .map(|opt| opt.map(|code| ContractCode::new(code, Some(*code_hash))))
+}
+
+/// Returns the burn ledger of the shard, empty if nothing was recorded yet.
+pub fn get_burn_ledger(state_update: &TrieUpdate) -> Result<BurnLedger, StorageError> {
+    Ok(get(state_update, &TrieKey::BurnLedger)?.unwrap_or_default())
+}
+
+pub fn set_burn_ledger(state_update: &mut TrieUpdate, burn_ledger: &BurnLedger) {
+    set(state_update, TrieKey::BurnLedger, burn_ledger);
+}
+
 /// Removes account, code and all access keys associated to it.
 pub fn remove_account(
     state_update: &mut TrieUpdate,
@@ -473,6 +767,24 @@ pub fn remove_account(
     for key in data_keys {
         state_update.remove(TrieKey::ContractData { account_id: account_id.clone(), key });
     }
+
+    // Removing account extensions
+    let extension_ids = state_update
+        .iter(&trie_key_parsers::get_raw_prefix_for_account_extensions(&account_id))?
+        .map(|raw_key| {
+            trie_key_parsers::parse_extension_id_from_account_extension_key(&raw_key?, account_id)
+                .map_err(|_e| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse extension id from raw key for AccountExtension".to_string(),
+                    )
+                })
+                .map(Vec::from)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for extension_id in extension_ids {
+        state_update
+            .remove(TrieKey::AccountExtension { account_id: account_id.clone(), extension_id });
+    }
     Ok(())
 }
 
@@ -518,9 +830,113 @@ impl CompiledContractCache for StoreCompiledContractCache {
 
 #[cfg(test)]
 mod tests {
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::account::{AccessKey, Account};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::StateChangeCause;
+
+    use crate::test_utils::{create_test_store, create_tries};
+    use crate::{set_access_key, set_account, ColBlockMisc, ColState, Trie};
+
     #[test]
     fn test_no_cache_disabled() {
         #[cfg(feature = "no_cache")]
         panic!("no cache is enabled");
     }
+
+    #[test]
+    fn test_store_update_read_your_writes() {
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColBlockMisc, b"a", b"1");
+        store_update.set(ColBlockMisc, b"b", b"2");
+        store_update.commit().unwrap();
+
+        let mut store_update = store.store_update();
+        assert_eq!(store_update.get(ColBlockMisc, b"a").unwrap(), Some(b"1".to_vec()));
+        store_update.set(ColBlockMisc, b"a", b"3");
+        store_update.delete(ColBlockMisc, b"b");
+        store_update.set(ColBlockMisc, b"c", b"4");
+        assert_eq!(store_update.get(ColBlockMisc, b"a").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(store_update.get(ColBlockMisc, b"b").unwrap(), None);
+        assert_eq!(store_update.get(ColBlockMisc, b"c").unwrap(), Some(b"4".to_vec()));
+        // Nothing is visible in the store before the commit.
+        assert_eq!(store.get(ColBlockMisc, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.get(ColBlockMisc, b"c").unwrap(), None);
+
+        store_update.delete_all(ColBlockMisc);
+        assert_eq!(store_update.get(ColBlockMisc, b"c").unwrap(), None);
+    }
+
+    #[test]
+    fn test_store_update_try_merge() {
+        let store = create_test_store();
+        let mut store_update = store.store_update();
+        store_update.set(ColBlockMisc, b"a", b"1");
+        store_update.update_refcount(ColState, b"x", b"1", 1);
+
+        let mut other = store.store_update();
+        other.set(ColBlockMisc, b"b", b"2");
+        other.update_refcount(ColState, b"x", b"1", 1);
+        store_update.try_merge(other).unwrap();
+
+        let mut other = store.store_update();
+        other.delete(ColBlockMisc, b"a");
+        assert!(store_update.try_merge(other).is_err());
+
+        let mut other = store.store_update();
+        other.delete_all(ColState);
+        assert!(store_update.try_merge(other).is_err());
+
+        // Deleting the same key twice is not a conflict, but deleting a key written by the other
+        // update is, in either order.
+        store_update.delete(ColBlockMisc, b"c");
+        let mut other = store.store_update();
+        other.delete(ColBlockMisc, b"c");
+        store_update.try_merge(other).unwrap();
+        let mut other = store.store_update();
+        other.set(ColBlockMisc, b"c", b"3");
+        assert!(store_update.try_merge(other).is_err());
+
+        store_update.commit().unwrap();
+        assert_eq!(store.get(ColBlockMisc, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.get(ColBlockMisc, b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(store.get(ColState, b"x").unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_iterate_accounts() {
+        let tries = create_tries();
+        let mut state_update = tries.new_trie_update(0, Trie::empty_root());
+        let account_ids: Vec<String> =
+            vec!["alice.near", "bob.near", "carol.near"].into_iter().map(String::from).collect();
+        for (i, account_id) in account_ids.iter().enumerate() {
+            let account = Account::new(i as u128, 0, CryptoHash::default(), 100);
+            set_account(&mut state_update, account_id.clone(), &account);
+            set_access_key(
+                &mut state_update,
+                account_id.clone(),
+                PublicKey::empty(KeyType::ED25519),
+                &AccessKey::full_access(),
+            );
+        }
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let accounts = tries.iterate_accounts(0, &root, None, 10).unwrap();
+        assert_eq!(
+            accounts.iter().map(|(account_id, _)| account_id.clone()).collect::<Vec<_>>(),
+            account_ids
+        );
+        assert_eq!(accounts[2].1.amount(), 2);
+
+        let accounts = tries.iterate_accounts(0, &root, Some(&account_ids[1]), 1).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].0, account_ids[1]);
+        let accounts = tries.iterate_accounts(0, &root, Some(&"bz".to_string()), 10).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].0, account_ids[2]);
+    }
 }
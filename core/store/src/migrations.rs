@@ -38,7 +38,6 @@ use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, Balance};
 use near_primitives::utils::{create_receipt_id_from_transaction, get_block_shard_id};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
-use std::rc::Rc;
 
 pub mod v6_to_v7;
 pub mod v8_to_v9;
@@ -410,7 +409,7 @@ pub fn migrate_13_to_14(path: &String) {
 pub fn migrate_14_to_15(path: &String) {
     let store = create_store(path);
     let trie_store = Box::new(TrieCachingStorage::new(store.clone(), TrieCache::new(), 0));
-    let trie = Rc::new(Trie::new(trie_store, 0));
+    let trie = Arc::new(Trie::new(trie_store, 0));
 
     let mut store_update = store.store_update();
     let batch_size_limit = 10_000_000;
@@ -707,6 +706,28 @@ pub fn migrate_21_to_22(path: &String) {
     set_store_version(&store, 22);
 }
 
+/// Adds the chunk stats by shard and the uptime curve, which weren't recorded before, to the
+/// epoch summaries. They are the last fields of `EpochSummary`, so the serialized summaries are
+/// extended with the serialized empty values.
+pub fn migrate_25_to_26(path: &String) {
+    use near_primitives::types::{ShardChunkValidatorStats, UptimeCurve};
+
+    let store = create_store(path);
+    let empty_fields =
+        (HashMap::<AccountId, ShardChunkValidatorStats>::new(), Option::<UptimeCurve>::None)
+            .try_to_vec()
+            .expect("Borsh cannot fail");
+    let mut store_update = store.store_update();
+    for (key, value) in store.iter(DBCol::ColEpochValidatorInfo) {
+        let mut value = value.into_vec();
+        value.extend_from_slice(&empty_fields);
+        store_update.set(DBCol::ColEpochValidatorInfo, &key, &value);
+    }
+    store_update.commit().unwrap();
+
+    set_store_version(&store, 26);
+}
+
 #[cfg(feature = "protocol_feature_block_header_v3")]
 pub fn migrate_18_to_new_validator_stake(store: &Store) {
     use near_primitives::epoch_manager::block_info::{BlockInfo, BlockInfoV1};
@@ -715,8 +736,8 @@ pub fn migrate_18_to_new_validator_stake(store: &Store) {
     use near_primitives::types::chunk_extra::{ChunkExtra, ChunkExtraV1};
     use near_primitives::types::validator_stake::ValidatorStakeV1;
     use near_primitives::types::{
-        BlockChunkValidatorStats, EpochId, ProtocolVersion, ShardId, ValidatorId,
-        ValidatorKickoutReason, ValidatorStats,
+        BlockChunkValidatorStats, EpochId, ProtocolVersion, ShardChunkValidatorStats, ShardId,
+        UptimeCurve, ValidatorId, ValidatorKickoutReason, ValidatorStats,
     };
     use std::collections::BTreeMap;
 
@@ -727,6 +748,8 @@ pub fn migrate_18_to_new_validator_stake(store: &Store) {
         pub validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
         pub validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
         pub next_version: ProtocolVersion,
+        pub validator_shard_chunk_stats: HashMap<AccountId, ShardChunkValidatorStats>,
+        pub uptime_curve: Option<UptimeCurve>,
     }
 
     #[derive(BorshDeserialize)]
@@ -758,6 +781,8 @@ pub fn migrate_18_to_new_validator_stake(store: &Store) {
         validator_kickout: info.validator_kickout,
         validator_block_chunk_stats: info.validator_block_chunk_stats,
         next_version: info.next_version,
+        validator_shard_chunk_stats: info.validator_shard_chunk_stats,
+        uptime_curve: info.uptime_curve,
     })
     .unwrap();
 
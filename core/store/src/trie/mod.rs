@@ -1,10 +1,9 @@
-use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::io::{Cursor, Read, Write};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -18,6 +17,7 @@ use crate::trie::insert_delete::NodesStorage;
 use crate::trie::iterator::TrieIterator;
 use crate::trie::nibble_slice::NibbleSlice;
 pub use crate::trie::shard_tries::{KeyForStateChanges, ShardTries, WrappedTrieChanges};
+pub use crate::trie::split_state::{SplitStateStatus, StateSplitter};
 use crate::trie::trie_storage::{
     TouchedNodesCounter, TrieMemoryPartialStorage, TrieRecordingStorage, TrieStorage,
 };
@@ -28,6 +28,7 @@ mod insert_delete;
 pub mod iterator;
 mod nibble_slice;
 mod shard_tries;
+mod split_state;
 mod state_parts;
 mod trie_storage;
 pub mod update;
@@ -478,7 +479,7 @@ impl Trie {
         let storage = TrieRecordingStorage {
             store: Arc::clone(&storage.store),
             shard_id: storage.shard_id,
-            recorded: RefCell::new(Default::default()),
+            recorded: Mutex::new(Default::default()),
         };
         Trie { storage: Box::new(storage), counter: TouchedNodesCounter::default() }
     }
@@ -489,8 +490,13 @@ impl Trie {
 
     pub fn recorded_storage(&self) -> Option<PartialStorage> {
         let storage = self.storage.as_recording_storage()?;
-        let mut nodes: Vec<_> =
-            storage.recorded.borrow_mut().drain().map(|(_key, value)| value).collect();
+        let mut nodes: Vec<_> = storage
+            .recorded
+            .lock()
+            .expect(POISONED_LOCK_ERR)
+            .drain()
+            .map(|(_key, value)| value)
+            .collect();
         nodes.sort();
         Some(PartialStorage { nodes: PartialState(nodes) })
     }
@@ -2,14 +2,15 @@ use crate::db::{DBCol, DBOp, DBTransaction};
 use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::trie::TrieRefcountChange;
 use crate::{StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
-use borsh::BorshSerialize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::account::Account;
 use near_primitives::hash::CryptoHash;
-use near_primitives::trie_key::TrieKey;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{
-    NumShards, RawStateChange, RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+    AccountId, NumShards, RawStateChange, RawStateChangesWithTrieKey, ShardId, StateChangeCause,
+    StateRoot,
 };
 use near_primitives::utils::get_block_shard_id;
-use std::rc::Rc;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -36,11 +37,14 @@ impl ShardTries {
     }
 
     pub fn new_trie_update(&self, shard_id: ShardId, state_root: CryptoHash) -> TrieUpdate {
-        TrieUpdate::new(Rc::new(self.get_trie_for_shard(shard_id)), state_root)
+        TrieUpdate::new(Arc::new(self.get_trie_for_shard(shard_id)), state_root)
     }
 
+    /// Trie update for view calls, with its own cache. The trie nodes are immutable and addressed
+    /// by hash, so the update reads the state at `state_root` regardless of the changes committed
+    /// meanwhile, and can be sent to another thread to serve views while chunks are applied.
     pub fn new_trie_update_view(&self, shard_id: ShardId, state_root: CryptoHash) -> TrieUpdate {
-        TrieUpdate::new(Rc::new(self.get_view_trie_for_shard(shard_id)), state_root)
+        TrieUpdate::new(Arc::new(self.get_view_trie_for_shard(shard_id)), state_root)
     }
 
     fn get_trie_for_shard_internal(&self, shard_id: ShardId, is_view: bool) -> Trie {
@@ -65,6 +69,51 @@ impl ShardTries {
         self.store.clone()
     }
 
+    /// Returns up to `limit` accounts of the shard at the given state root in the order of their
+    /// ids, starting from `from_account` inclusive, or from the first account if none is given.
+    /// Only the account records are visited, the iteration seeks to the account column of the
+    /// trie and stops at its end.
+    pub fn iterate_accounts(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        from_account: Option<&AccountId>,
+        limit: usize,
+    ) -> Result<Vec<(AccountId, Account)>, StorageError> {
+        let prefix = trie_key_parsers::get_raw_prefix_for_accounts();
+        let start = match from_account {
+            Some(account_id) => TrieKey::Account { account_id: account_id.clone() }.to_vec(),
+            None => prefix.clone(),
+        };
+        let trie = self.get_view_trie_for_shard(shard_id);
+        let mut iter = trie.iter(state_root)?;
+        iter.seek(&start)?;
+        let mut accounts = vec![];
+        for item in iter {
+            if accounts.len() >= limit {
+                break;
+            }
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let account_id =
+                trie_key_parsers::parse_account_id_from_account_key(&key).map_err(|_e| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse account id from raw key for Account".to_string(),
+                    )
+                })?;
+            let account = Account::try_from_slice(&value).map_err(|_e| {
+                StorageError::StorageInconsistentState(format!(
+                    "Can't deserialize the account of {}",
+                    account_id
+                ))
+            })?;
+            accounts.push((account_id, account));
+        }
+        Ok(accounts)
+    }
+
     pub fn update_cache(&self, transaction: &DBTransaction) -> std::io::Result<()> {
         let mut shards = vec![Vec::new(); self.caches.len()];
         for op in &transaction.ops {
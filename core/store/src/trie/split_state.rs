@@ -0,0 +1,578 @@
+//! Building the state of the shards of a new layout from the state of the shards of the previous
+//! one.
+//!
+//! The state of a parent shard is streamed in batches, so resharding can run as a background job
+//! next to block processing and resume after a restart: every batch is a separate store update,
+//! and the splitter only keeps the key to continue from and the roots of the children so far.
+//! Every key goes to the child of the account it belongs to. Delayed receipts belong to the whole
+//! shard, so they are moved last, in the order of the queue of the parent, to the end of the queue
+//! of the child with their receiver. The delayed priority receipts and the postponed receipts
+//! expiry queue are moved the same way. Merging runs the splitters of all the parents one after
+//! another, each starting from the children roots the previous one ended with.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_primitives::errors::StorageError;
+use near_primitives::receipt::{DelayedReceiptIndices, PostponedReceiptExpiry, Receipt};
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::types::{AccountId, ShardId, StateRoot};
+
+use crate::{ShardTries, StoreUpdate, Trie};
+
+/// Changes of the states of the children made by a batch.
+type ChildrenChanges = HashMap<ShardId, Vec<(Vec<u8>, Option<Vec<u8>>)>>;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SplitStateStatus {
+    /// Streaming the keys of the parent starting from the given one.
+    Keys(Option<Vec<u8>>),
+    /// Moving the delayed receipts of the parent starting from the given index of its queue.
+    DelayedReceipts(Option<u64>),
+    /// Moving the delayed priority receipts of the parent starting from the given index of its
+    /// queue.
+    DelayedPriorityReceipts(Option<u64>),
+    /// Moving the postponed receipts expiry queue of the parent starting from the given index.
+    PostponedReceiptExpiry(Option<u64>),
+    Done,
+}
+
+/// Resumable job splitting the state of one shard of the previous layout into the shards of the
+/// next one.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateSplitter {
+    parent_shard_id: ShardId,
+    parent_root: StateRoot,
+    next_layout: ShardLayout,
+    /// State roots of the children of the parent in the next layout.
+    child_roots: HashMap<ShardId, StateRoot>,
+    status: SplitStateStatus,
+}
+
+impl StateSplitter {
+    /// Starts splitting the parent into the given states of the children. The children which
+    /// aren't given start empty.
+    pub fn new(
+        parent_shard_id: ShardId,
+        parent_root: StateRoot,
+        prev_layout: &ShardLayout,
+        next_layout: ShardLayout,
+        mut child_roots: HashMap<ShardId, StateRoot>,
+    ) -> Self {
+        for child_shard_id in prev_layout.get_children_shards(parent_shard_id, &next_layout) {
+            child_roots.entry(child_shard_id).or_insert_with(Trie::empty_root);
+        }
+        Self {
+            parent_shard_id,
+            parent_root,
+            next_layout,
+            child_roots,
+            status: SplitStateStatus::Keys(None),
+        }
+    }
+
+    pub fn child_roots(&self) -> &HashMap<ShardId, StateRoot> {
+        &self.child_roots
+    }
+
+    pub fn status(&self) -> &SplitStateStatus {
+        &self.status
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status == SplitStateStatus::Done
+    }
+
+    /// Moves the next `batch_size` records of the parent into the children. The store update
+    /// must be committed before the next batch, which reads the states of the children.
+    /// `child_tries` are the tries of the shards of the next layout.
+    pub fn apply_next_batch(
+        &mut self,
+        parent_tries: &ShardTries,
+        child_tries: &ShardTries,
+        batch_size: usize,
+    ) -> Result<StoreUpdate, StorageError> {
+        let parent_trie = parent_tries.get_trie_for_shard(self.parent_shard_id);
+        let mut changes = ChildrenChanges::new();
+        self.status = match &self.status {
+            SplitStateStatus::Keys(start_key) => {
+                self.next_keys_batch(&parent_trie, start_key.clone(), batch_size, &mut changes)?
+            }
+            SplitStateStatus::DelayedReceipts(start_index) => {
+                match self.next_queue_batch(
+                    &parent_trie,
+                    child_tries,
+                    ReceiptQueue::Delayed,
+                    *start_index,
+                    batch_size,
+                    &mut changes,
+                )? {
+                    Some(index) => SplitStateStatus::DelayedReceipts(Some(index)),
+                    None => SplitStateStatus::DelayedPriorityReceipts(None),
+                }
+            }
+            SplitStateStatus::DelayedPriorityReceipts(start_index) => {
+                match self.next_queue_batch(
+                    &parent_trie,
+                    child_tries,
+                    ReceiptQueue::DelayedPriority,
+                    *start_index,
+                    batch_size,
+                    &mut changes,
+                )? {
+                    Some(index) => SplitStateStatus::DelayedPriorityReceipts(Some(index)),
+                    None => SplitStateStatus::PostponedReceiptExpiry(None),
+                }
+            }
+            SplitStateStatus::PostponedReceiptExpiry(start_index) => {
+                match self.next_queue_batch(
+                    &parent_trie,
+                    child_tries,
+                    ReceiptQueue::PostponedReceiptExpiry,
+                    *start_index,
+                    batch_size,
+                    &mut changes,
+                )? {
+                    Some(index) => SplitStateStatus::PostponedReceiptExpiry(Some(index)),
+                    None => SplitStateStatus::Done,
+                }
+            }
+            SplitStateStatus::Done => SplitStateStatus::Done,
+        };
+
+        let mut store_update = child_tries.get_store().store_update();
+        for (child_shard_id, child_changes) in changes {
+            let child_root = self.child_roots.get_mut(&child_shard_id).ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "account of shard {} moves to shard {}, which isn't its child",
+                    self.parent_shard_id, child_shard_id
+                ))
+            })?;
+            let trie_changes = child_tries
+                .get_trie_for_shard(child_shard_id)
+                .update(child_root, child_changes.into_iter())?;
+            let (child_store_update, new_root) =
+                child_tries.apply_all(&trie_changes, child_shard_id)?;
+            store_update.merge(child_store_update);
+            *child_root = new_root;
+        }
+        Ok(store_update)
+    }
+
+    fn next_keys_batch(
+        &self,
+        parent_trie: &Trie,
+        start_key: Option<Vec<u8>>,
+        batch_size: usize,
+        changes: &mut ChildrenChanges,
+    ) -> Result<SplitStateStatus, StorageError> {
+        let mut iter = parent_trie.iter(&self.parent_root)?;
+        if let Some(start_key) = start_key {
+            iter.seek(start_key)?;
+        }
+        for (num_keys, item) in iter.enumerate() {
+            let (key, value) = item?;
+            if num_keys == batch_size {
+                return Ok(SplitStateStatus::Keys(Some(key)));
+            }
+            let account_id = trie_key_parsers::parse_account_id_from_raw_key(&key)
+                .map_err(|err| StorageError::StorageInconsistentState(err.to_string()))?;
+            // Delayed receipts and the postponed receipts expiry queue are moved separately. The
+            // burn ledger isn't moved, the children start ledgers of their own. The global
+            // contract code is copied to all the children, as the accounts using it may end up in
+            // any of them.
+            if let Some(account_id) = account_id {
+                let child_shard_id = account_id_to_shard_id(&account_id, &self.next_layout);
+                changes.entry(child_shard_id).or_default().push((key, Some(value)));
+            } else if key.starts_with(&trie_key_parsers::get_raw_prefix_for_global_contract_code())
+            {
+                for child_shard_id in self.child_roots.keys() {
+                    changes
+                        .entry(*child_shard_id)
+                        .or_default()
+                        .push((key.clone(), Some(value.clone())));
+                }
+            }
+        }
+        Ok(SplitStateStatus::DelayedReceipts(None))
+    }
+
+    /// Moves the next batch of entries of the given queue. Returns the index to continue from, or
+    /// `None` once the queue is moved.
+    fn next_queue_batch(
+        &self,
+        parent_trie: &Trie,
+        child_tries: &ShardTries,
+        queue: ReceiptQueue,
+        start_index: Option<u64>,
+        batch_size: usize,
+        changes: &mut ChildrenChanges,
+    ) -> Result<Option<u64>, StorageError> {
+        let parent_indices = get_queue_indices(parent_trie, &self.parent_root, queue)?;
+        let start_index = start_index.unwrap_or(parent_indices.first_index);
+        let end_index =
+            parent_indices.next_available_index.min(start_index.saturating_add(batch_size as u64));
+        let mut child_indices = HashMap::new();
+        for index in start_index..end_index {
+            let value = parent_trie
+                .get(&self.parent_root, &queue.entry_key(index).to_vec())?
+                .ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "{} #{} of shard {} is missing",
+                        queue.entry_name(),
+                        index,
+                        self.parent_shard_id
+                    ))
+                })?;
+            let receiver_id = queue.receiver_id(&value)?;
+            let child_shard_id = account_id_to_shard_id(&receiver_id, &self.next_layout);
+            let indices: &mut DelayedReceiptIndices = match child_indices.entry(child_shard_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let child_root = self.child_roots.get(&child_shard_id).ok_or_else(|| {
+                        StorageError::StorageInconsistentState(format!(
+                            "{} of shard {} moves to shard {}, which isn't its child",
+                            queue.entry_name(),
+                            self.parent_shard_id,
+                            child_shard_id
+                        ))
+                    })?;
+                    let child_trie = child_tries.get_trie_for_shard(child_shard_id);
+                    entry.insert(get_queue_indices(&child_trie, child_root, queue)?)
+                }
+            };
+            changes
+                .entry(child_shard_id)
+                .or_default()
+                .push((queue.entry_key(indices.next_available_index).to_vec(), Some(value)));
+            indices.next_available_index += 1;
+        }
+        for (child_shard_id, indices) in child_indices {
+            changes.entry(child_shard_id).or_default().push((
+                queue.indices_key().to_vec(),
+                Some(indices.try_to_vec().expect("Borsh cannot fail")),
+            ));
+        }
+        if end_index == parent_indices.next_available_index {
+            Ok(None)
+        } else {
+            Ok(Some(end_index))
+        }
+    }
+}
+
+/// The queues of a shard, whose entries are moved to the child shard of their receiver.
+#[derive(Clone, Copy)]
+enum ReceiptQueue {
+    Delayed,
+    DelayedPriority,
+    PostponedReceiptExpiry,
+}
+
+impl ReceiptQueue {
+    fn indices_key(self) -> TrieKey {
+        match self {
+            ReceiptQueue::Delayed => TrieKey::DelayedReceiptIndices,
+            ReceiptQueue::DelayedPriority => TrieKey::DelayedPriorityReceiptIndices,
+            ReceiptQueue::PostponedReceiptExpiry => TrieKey::PostponedReceiptExpiryIndices,
+        }
+    }
+
+    fn entry_key(self, index: u64) -> TrieKey {
+        match self {
+            ReceiptQueue::Delayed => TrieKey::DelayedReceipt { index },
+            ReceiptQueue::DelayedPriority => TrieKey::DelayedPriorityReceipt { index },
+            ReceiptQueue::PostponedReceiptExpiry => TrieKey::PostponedReceiptExpiry { index },
+        }
+    }
+
+    fn entry_name(self) -> &'static str {
+        match self {
+            ReceiptQueue::Delayed | ReceiptQueue::DelayedPriority => "delayed receipt",
+            ReceiptQueue::PostponedReceiptExpiry => "postponed receipt expiry",
+        }
+    }
+
+    fn receiver_id(self, value: &[u8]) -> Result<AccountId, StorageError> {
+        let receiver_id = match self {
+            ReceiptQueue::Delayed | ReceiptQueue::DelayedPriority => {
+                Receipt::try_from_slice(value).map(|receipt| receipt.receiver_id)
+            }
+            ReceiptQueue::PostponedReceiptExpiry => {
+                PostponedReceiptExpiry::try_from_slice(value).map(|expiry| expiry.receiver_id)
+            }
+        };
+        receiver_id.map_err(|_| {
+            StorageError::StorageInconsistentState(format!(
+                "failed to deserialize the {}",
+                self.entry_name()
+            ))
+        })
+    }
+}
+
+fn get_queue_indices(
+    trie: &Trie,
+    root: &StateRoot,
+    queue: ReceiptQueue,
+) -> Result<DelayedReceiptIndices, StorageError> {
+    match trie.get(root, &queue.indices_key().to_vec())? {
+        Some(value) => DelayedReceiptIndices::try_from_slice(&value).map_err(|_| {
+            StorageError::StorageInconsistentState(format!(
+                "failed to deserialize the {} indices",
+                queue.entry_name()
+            ))
+        }),
+        None => Ok(DelayedReceiptIndices::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::account::AccessKey;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::state_record::StateRecord;
+
+    use super::*;
+    use crate::test_utils::create_test_store;
+
+    fn state_of(tries: &ShardTries, shard_id: ShardId, root: &StateRoot) -> Vec<StateRecord> {
+        let trie = tries.get_trie_for_shard(shard_id);
+        trie.iter(root)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .filter_map(|(key, value)| StateRecord::from_raw_key_value(key, value))
+            .collect()
+    }
+
+    fn delayed_receivers(
+        tries: &ShardTries,
+        shard_id: ShardId,
+        root: &StateRoot,
+    ) -> Vec<AccountId> {
+        let trie = tries.get_trie_for_shard(shard_id);
+        let indices = get_queue_indices(&trie, root, ReceiptQueue::Delayed).unwrap();
+        (indices.first_index..indices.next_available_index)
+            .map(|index| {
+                let value =
+                    trie.get(root, &TrieKey::DelayedReceipt { index }.to_vec()).unwrap().unwrap();
+                Receipt::try_from_slice(&value).unwrap().receiver_id
+            })
+            .collect()
+    }
+
+    fn populate(tries: &ShardTries, shard_id: ShardId, accounts: &[&str]) -> StateRoot {
+        let mut changes = vec![];
+        for account_id in accounts {
+            changes.push((
+                TrieKey::ContractData { account_id: account_id.to_string(), key: b"k".to_vec() }
+                    .to_vec(),
+                Some(b"v".to_vec()),
+            ));
+            changes.push((
+                TrieKey::AccessKey {
+                    account_id: account_id.to_string(),
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                }
+                .to_vec(),
+                Some(AccessKey::full_access().try_to_vec().unwrap()),
+            ));
+        }
+        // The queue starts at a later index, like the queues of shards which processed receipts.
+        let first_index = 2;
+        for (index, account_id) in accounts.iter().enumerate() {
+            changes.push((
+                TrieKey::DelayedReceipt { index: first_index + index as u64 }.to_vec(),
+                Some(Receipt::new_balance_refund(&account_id.to_string(), 1).try_to_vec().unwrap()),
+            ));
+        }
+        let indices = DelayedReceiptIndices {
+            first_index,
+            next_available_index: first_index + accounts.len() as u64,
+        };
+        changes
+            .push((TrieKey::DelayedReceiptIndices.to_vec(), Some(indices.try_to_vec().unwrap())));
+        let trie = tries.get_trie_for_shard(shard_id);
+        let trie_changes = trie.update(&Trie::empty_root(), changes.into_iter()).unwrap();
+        let (store_update, root) = tries.apply_all(&trie_changes, shard_id).unwrap();
+        store_update.commit().unwrap();
+        root
+    }
+
+    fn run(splitter: &mut StateSplitter, parent_tries: &ShardTries, child_tries: &ShardTries) {
+        while !splitter.is_done() {
+            splitter.apply_next_batch(parent_tries, child_tries, 3).unwrap().commit().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_split_state() {
+        let store = create_test_store();
+        let parent_tries = ShardTries::new(Arc::clone(&store), 1);
+        let child_tries = ShardTries::new(store, 2);
+        let accounts = ["zoe", "alice", "bob", "yara", "carol"];
+        let parent_root = populate(&parent_tries, 0, &accounts);
+        let prev_layout = ShardLayout::v0(1, 0);
+        let next_layout = ShardLayout::v1(vec!["m".to_string()], 1);
+
+        let mut splitter =
+            StateSplitter::new(0, parent_root, &prev_layout, next_layout, HashMap::new());
+        run(&mut splitter, &parent_tries, &child_tries);
+
+        let child_roots = splitter.child_roots().clone();
+        let records = |shard_id| state_of(&child_tries, shard_id, &child_roots[&shard_id]);
+        // Every account has its access key and data in its shard.
+        assert_eq!(records(0).len(), 6);
+        assert_eq!(records(1).len(), 4);
+        for record in records(1) {
+            if let StateRecord::AccessKey { account_id, .. } = record {
+                assert!(account_id == "zoe" || account_id == "yara");
+            }
+        }
+        // Delayed receipts keep their order in the queues of the children.
+        assert_eq!(
+            delayed_receivers(&child_tries, 0, &child_roots[&0]),
+            vec!["alice", "bob", "carol"]
+        );
+        assert_eq!(delayed_receivers(&child_tries, 1, &child_roots[&1]), vec!["zoe", "yara"]);
+    }
+
+    #[test]
+    fn test_split_state_copies_global_contract_code() {
+        let store = create_test_store();
+        let parent_tries = ShardTries::new(Arc::clone(&store), 1);
+        let child_tries = ShardTries::new(store, 2);
+        let parent_root = populate(&parent_tries, 0, &["alice", "zoe"]);
+        let code = b"code".to_vec();
+        let key = TrieKey::GlobalContractCode { code_hash: CryptoHash::default() }.to_vec();
+        let trie_changes = parent_tries
+            .get_trie_for_shard(0)
+            .update(&parent_root, vec![(key.clone(), Some(code.clone()))].into_iter())
+            .unwrap();
+        let (store_update, parent_root) = parent_tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let prev_layout = ShardLayout::v0(1, 0);
+        let next_layout = ShardLayout::v1(vec!["m".to_string()], 1);
+
+        let mut splitter =
+            StateSplitter::new(0, parent_root, &prev_layout, next_layout, HashMap::new());
+        run(&mut splitter, &parent_tries, &child_tries);
+
+        for (child_shard_id, child_root) in splitter.child_roots() {
+            let child_trie = child_tries.get_trie_for_shard(*child_shard_id);
+            assert_eq!(child_trie.get(child_root, &key).unwrap(), Some(code.clone()));
+        }
+    }
+
+    #[test]
+    fn test_split_state_moves_postponed_receipt_expiry() {
+        let store = create_test_store();
+        let parent_tries = ShardTries::new(Arc::clone(&store), 1);
+        let child_tries = ShardTries::new(store, 2);
+        let parent_root = populate(&parent_tries, 0, &["alice", "zoe"]);
+        let receivers = ["zoe", "alice", "yara", "bob"];
+        let first_index = 5;
+        let mut changes = vec![];
+        for (index, receiver_id) in receivers.iter().enumerate() {
+            let expiry = PostponedReceiptExpiry {
+                receiver_id: receiver_id.to_string(),
+                receipt_id: CryptoHash::default(),
+                postponed_height: index as u64,
+                postponed_epoch_height: 1,
+            };
+            changes.push((
+                TrieKey::PostponedReceiptExpiry { index: first_index + index as u64 }.to_vec(),
+                Some(expiry.try_to_vec().unwrap()),
+            ));
+        }
+        let indices = DelayedReceiptIndices {
+            first_index,
+            next_available_index: first_index + receivers.len() as u64,
+        };
+        changes.push((
+            TrieKey::PostponedReceiptExpiryIndices.to_vec(),
+            Some(indices.try_to_vec().unwrap()),
+        ));
+        let trie_changes =
+            parent_tries.get_trie_for_shard(0).update(&parent_root, changes.into_iter()).unwrap();
+        let (store_update, parent_root) = parent_tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let prev_layout = ShardLayout::v0(1, 0);
+        let next_layout = ShardLayout::v1(vec!["m".to_string()], 1);
+
+        let mut splitter =
+            StateSplitter::new(0, parent_root, &prev_layout, next_layout, HashMap::new());
+        run(&mut splitter, &parent_tries, &child_tries);
+
+        let expiries = |shard_id: ShardId| {
+            let root = &splitter.child_roots()[&shard_id];
+            let trie = child_tries.get_trie_for_shard(shard_id);
+            let indices =
+                get_queue_indices(&trie, root, ReceiptQueue::PostponedReceiptExpiry).unwrap();
+            (indices.first_index..indices.next_available_index)
+                .map(|index| {
+                    let value = trie
+                        .get(root, &TrieKey::PostponedReceiptExpiry { index }.to_vec())
+                        .unwrap()
+                        .unwrap();
+                    let expiry = PostponedReceiptExpiry::try_from_slice(&value).unwrap();
+                    (expiry.receiver_id, expiry.postponed_height)
+                })
+                .collect::<Vec<_>>()
+        };
+        // The entries keep their order in the queues of the children, which start from zero.
+        assert_eq!(expiries(0), vec![("alice".to_string(), 1), ("bob".to_string(), 3)]);
+        assert_eq!(expiries(1), vec![("zoe".to_string(), 0), ("yara".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_merge_state() {
+        let store = create_test_store();
+        let parent_tries = ShardTries::new(Arc::clone(&store), 2);
+        let child_tries = ShardTries::new(store, 1);
+        let prev_layout = ShardLayout::v1(vec!["m".to_string()], 1);
+        let next_layout = ShardLayout::v1(vec![], 2);
+        let parent_roots = vec![
+            populate(&parent_tries, 0, &["bob", "alice"]),
+            populate(&parent_tries, 1, &["zoe"]),
+        ];
+
+        let mut child_roots = HashMap::new();
+        for (parent_shard_id, parent_root) in parent_roots.into_iter().enumerate() {
+            let mut splitter = StateSplitter::new(
+                parent_shard_id as ShardId,
+                parent_root,
+                &prev_layout,
+                next_layout.clone(),
+                child_roots,
+            );
+            run(&mut splitter, &parent_tries, &child_tries);
+            child_roots = splitter.child_roots().clone();
+        }
+
+        assert_eq!(state_of(&child_tries, 0, &child_roots[&0]).len(), 6);
+        assert_eq!(
+            delayed_receivers(&child_tries, 0, &child_roots[&0]),
+            vec!["bob", "alice", "zoe"]
+        );
+    }
+
+    #[test]
+    fn test_split_state_of_foreign_account() {
+        let store = create_test_store();
+        let parent_tries = ShardTries::new(Arc::clone(&store), 1);
+        let child_tries = ShardTries::new(store, 3);
+        // The first shard of the layout can't have accounts after the boundary.
+        let parent_root = populate(&parent_tries, 0, &["zoe"]);
+        let prev_layout = ShardLayout::v1(vec!["m".to_string()], 1);
+        let next_layout = ShardLayout::v1(vec!["m".to_string(), "t".to_string()], 2);
+        let mut splitter =
+            StateSplitter::new(0, parent_root, &prev_layout, next_layout, HashMap::new());
+        assert!(splitter.apply_next_batch(&parent_tries, &child_tries, 10).is_err());
+    }
+}
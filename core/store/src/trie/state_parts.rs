@@ -8,6 +8,7 @@ use crate::trie::iterator::TrieTraversalItem;
 use crate::trie::nibble_slice::NibbleSlice;
 use crate::trie::{
     ApplyStatePartResult, NodeHandle, RawTrieNodeWithSize, TrieNode, TrieNodeWithSize,
+    POISONED_LOCK_ERR,
 };
 use crate::{PartialStorage, StorageError, Trie, TrieChanges, TrieIterator};
 use near_primitives::contract::ContractCode;
@@ -172,7 +173,7 @@ impl Trie {
         trie.visit_nodes_for_state_part(&state_root, part_id, num_parts)?;
         let storage = trie.storage.as_partial_storage().unwrap();
 
-        if storage.visited_nodes.borrow().len() != num_nodes {
+        if storage.visited_nodes.lock().expect(POISONED_LOCK_ERR).len() != num_nodes {
             // TODO #1603 not actually TrieNodeMissing.
             // The error is that the proof has more nodes than needed.
             return Err(StorageError::TrieNodeMissing);
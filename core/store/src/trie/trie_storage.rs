@@ -10,7 +10,6 @@ use crate::db::refcount::decode_value_with_rc;
 use crate::trie::POISONED_LOCK_ERR;
 use crate::{ColState, StorageError, Store};
 use near_primitives::types::ShardId;
-use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
 use std::io::ErrorKind;
 
@@ -44,7 +43,9 @@ impl TrieCache {
     }
 }
 
-pub trait TrieStorage {
+/// Storage of the trie nodes. It's shared between threads, e.g. by view calls reading the last
+/// committed state while a chunk is applied.
+pub trait TrieStorage: Send + Sync {
     /// Get bytes of a serialized TrieNode.
     /// # Errors
     /// StorageError if the storage fails internally or the hash is not present.
@@ -68,12 +69,12 @@ pub trait TrieStorage {
 pub struct TrieRecordingStorage {
     pub(crate) store: Arc<Store>,
     pub(crate) shard_id: ShardId,
-    pub(crate) recorded: RefCell<HashMap<CryptoHash, Vec<u8>>>,
+    pub(crate) recorded: Mutex<HashMap<CryptoHash, Vec<u8>>>,
 }
 
 impl TrieStorage for TrieRecordingStorage {
     fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
-        if let Some(val) = self.recorded.borrow().get(hash) {
+        if let Some(val) = self.recorded.lock().expect(POISONED_LOCK_ERR).get(hash) {
             return Ok(val.clone());
         }
         let key = TrieCachingStorage::get_key_from_shard_id_and_hash(self.shard_id, hash);
@@ -82,7 +83,7 @@ impl TrieStorage for TrieRecordingStorage {
             .get(ColState, key.as_ref())
             .map_err(|_| StorageError::StorageInternalError)?;
         if let Some(val) = val {
-            self.recorded.borrow_mut().insert(*hash, val.clone());
+            self.recorded.lock().expect(POISONED_LOCK_ERR).insert(*hash, val.clone());
             Ok(val)
         } else {
             Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
@@ -98,7 +99,7 @@ impl TrieStorage for TrieRecordingStorage {
 /// visited_nodes are to validate that partial storage doesn't contain unnecessary nodes.
 pub struct TrieMemoryPartialStorage {
     pub(crate) recorded_storage: HashMap<CryptoHash, Vec<u8>>,
-    pub(crate) visited_nodes: RefCell<HashSet<CryptoHash>>,
+    pub(crate) visited_nodes: Mutex<HashSet<CryptoHash>>,
 }
 
 impl TrieStorage for TrieMemoryPartialStorage {
@@ -108,7 +109,7 @@ impl TrieStorage for TrieMemoryPartialStorage {
             .get(hash)
             .map_or_else(|| Err(StorageError::TrieNodeMissing), |val| Ok(val.clone()));
         if result.is_ok() {
-            self.visited_nodes.borrow_mut().insert(*hash);
+            self.visited_nodes.lock().expect(POISONED_LOCK_ERR).insert(*hash);
         }
         result
     }
@@ -161,24 +162,25 @@ impl TrieCachingStorage {
 
 impl TrieStorage for TrieCachingStorage {
     fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
-        let mut guard = self.cache.0.lock().expect(POISONED_LOCK_ERR);
-        if let Some(val) = guard.cache_get(hash) {
-            Ok(val.clone())
-        } else {
-            let key = Self::get_key_from_shard_id_and_hash(self.shard_id, hash);
-            let val = self
-                .store
-                .get(ColState, key.as_ref())
-                .map_err(|_| StorageError::StorageInternalError)?;
-            if let Some(val) = val {
-                if val.len() < TRIE_LIMIT_CACHED_VALUE_SIZE {
-                    guard.cache_set(*hash, val.clone());
-                }
-                Ok(val)
-            } else {
-                // not StorageError::TrieNodeMissing because it's only for TrieMemoryPartialStorage
-                Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
+        if let Some(val) = self.cache.0.lock().expect(POISONED_LOCK_ERR).cache_get(hash) {
+            return Ok(val.clone());
+        }
+        // The lock isn't held while reading from the database, so concurrent readers of the
+        // trie, like view calls, don't wait for each other's reads. The nodes are immutable, a
+        // value read by another thread meanwhile is the same.
+        let key = Self::get_key_from_shard_id_and_hash(self.shard_id, hash);
+        let val = self
+            .store
+            .get(ColState, key.as_ref())
+            .map_err(|_| StorageError::StorageInternalError)?;
+        if let Some(val) = val {
+            if val.len() < TRIE_LIMIT_CACHED_VALUE_SIZE {
+                self.cache.0.lock().expect(POISONED_LOCK_ERR).cache_set(*hash, val.clone());
             }
+            Ok(val)
+        } else {
+            // not StorageError::TrieNodeMissing because it's only for TrieMemoryPartialStorage
+            Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
         }
     }
 
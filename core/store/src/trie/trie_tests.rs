@@ -5,15 +5,14 @@ use near_primitives::errors::StorageError;
 use near_primitives::hash::{hash, CryptoHash};
 use rand::seq::SliceRandom;
 use rand::Rng;
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 /// TrieMemoryPartialStorage, but contains only the first n requested nodes.
 pub struct IncompletePartialStorage {
     pub(crate) recorded_storage: HashMap<CryptoHash, Vec<u8>>,
-    pub(crate) visited_nodes: RefCell<HashSet<CryptoHash>>,
+    pub(crate) visited_nodes: Mutex<HashSet<CryptoHash>>,
     pub node_count_to_fail_after: usize,
 }
 
@@ -37,10 +36,10 @@ impl TrieStorage for IncompletePartialStorage {
             .map_or_else(|| Err(StorageError::TrieNodeMissing), |val| Ok(val.clone()));
 
         if result.is_ok() {
-            self.visited_nodes.borrow_mut().insert(*hash);
+            self.visited_nodes.lock().unwrap().insert(*hash);
         }
 
-        if self.visited_nodes.borrow().len() > self.node_count_to_fail_after {
+        if self.visited_nodes.lock().unwrap().len() > self.node_count_to_fail_after {
             Err(StorageError::TrieNodeMissing)
         } else {
             result
@@ -53,22 +52,22 @@ impl TrieStorage for IncompletePartialStorage {
     }
 }
 
-fn setup_storage<F, Out>(trie: Rc<Trie>, test: &mut F) -> (PartialStorage, Out)
+fn setup_storage<F, Out>(trie: Arc<Trie>, test: &mut F) -> (PartialStorage, Out)
 where
-    F: FnMut(Rc<Trie>) -> Result<Out, StorageError>,
+    F: FnMut(Arc<Trie>) -> Result<Out, StorageError>,
     Out: PartialEq + Debug,
 {
-    let recording_trie = Rc::new(trie.recording_reads());
-    let output = test(Rc::clone(&recording_trie)).expect("should not fail");
+    let recording_trie = Arc::new(trie.recording_reads());
+    let output = test(Arc::clone(&recording_trie)).expect("should not fail");
     (recording_trie.recorded_storage().unwrap(), output)
 }
 
-fn test_incomplete_storage<F, Out>(trie: Rc<Trie>, mut test: F)
+fn test_incomplete_storage<F, Out>(trie: Arc<Trie>, mut test: F)
 where
-    F: FnMut(Rc<Trie>) -> Result<Out, StorageError>,
+    F: FnMut(Arc<Trie>) -> Result<Out, StorageError>,
     Out: PartialEq + Debug,
 {
-    let (storage, expected) = setup_storage(Rc::clone(&trie), &mut test);
+    let (storage, expected) = setup_storage(Arc::clone(&trie), &mut test);
     let size = storage.nodes.0.len();
     print!("Test touches {} nodes, expected result {:?}...", size, expected);
     for i in 0..(size + 1) {
@@ -76,7 +75,7 @@ where
         let trie = Trie { storage: Box::new(storage), counter: Default::default() };
         let expected_result =
             if i < size { Err(&StorageError::TrieNodeMissing) } else { Ok(&expected) };
-        assert_eq!(test(Rc::new(trie)).as_ref(), expected_result);
+        assert_eq!(test(Arc::new(trie)).as_ref(), expected_result);
     }
     println!("Success");
 }
@@ -87,7 +86,7 @@ fn test_reads_with_incomplete_storage() {
     for _ in 0..50 {
         let tries = create_tries();
         let trie = tries.get_trie_for_shard(0);
-        let trie = Rc::new(trie);
+        let trie = Arc::new(trie);
         let mut state_root = Trie::empty_root();
         let trie_changes = gen_changes(&mut rng, 20);
         let trie_changes = simplify_changes(&trie_changes);
@@ -100,27 +99,27 @@ fn test_reads_with_incomplete_storage() {
             let (key, _) = trie_changes.choose(&mut rng).unwrap();
             println!("Testing lookup {:?}", key);
             let lookup_test =
-                |trie: Rc<Trie>| -> Result<_, StorageError> { trie.get(&state_root, key) };
-            test_incomplete_storage(Rc::clone(&trie), lookup_test);
+                |trie: Arc<Trie>| -> Result<_, StorageError> { trie.get(&state_root, key) };
+            test_incomplete_storage(Arc::clone(&trie), lookup_test);
         }
         {
             println!("Testing TrieIterator over whole trie");
-            let trie_records = |trie: Rc<Trie>| -> Result<_, StorageError> {
+            let trie_records = |trie: Arc<Trie>| -> Result<_, StorageError> {
                 let iterator = trie.iter(&state_root)?;
                 iterator.collect::<Result<Vec<_>, _>>()
             };
-            test_incomplete_storage(Rc::clone(&trie), trie_records);
+            test_incomplete_storage(Arc::clone(&trie), trie_records);
         }
         {
             let (key, _) = trie_changes.choose(&mut rng).unwrap();
             let key_prefix = &key[0..rng.gen_range(0, key.len() + 1)];
             println!("Testing TrieUpdateIterator over prefix {:?}", key_prefix);
-            let trie_update_keys = |trie: Rc<Trie>| -> Result<_, StorageError> {
+            let trie_update_keys = |trie: Arc<Trie>| -> Result<_, StorageError> {
                 let trie_update = TrieUpdate::new(trie, state_root);
                 let keys = trie_update.iter(key_prefix)?.collect::<Result<Vec<_>, _>>()?;
                 Ok(keys)
             };
-            test_incomplete_storage(Rc::clone(&trie), trie_update_keys);
+            test_incomplete_storage(Arc::clone(&trie), trie_update_keys);
         }
     }
 }
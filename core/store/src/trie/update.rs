@@ -11,9 +11,10 @@ use crate::StorageError;
 
 use super::{Trie, TrieIterator};
 use near_primitives::trie_key::TrieKey;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Key-value update. Contains a TrieKey and a value.
+#[derive(Clone)]
 pub struct TrieKeyValueUpdate {
     pub trie_key: TrieKey,
     pub value: Option<Vec<u8>>,
@@ -22,9 +23,12 @@ pub struct TrieKeyValueUpdate {
 /// key that was updated -> the update.
 pub type TrieUpdates = BTreeMap<Vec<u8>, TrieKeyValueUpdate>;
 
+/// Uncommitted changes of a `TrieUpdate` at some point, see `TrieUpdate::checkpoint`.
+pub struct TrieUpdateCheckpoint(TrieUpdates);
+
 /// Provides a way to access Storage and record changes with future commit.
 pub struct TrieUpdate {
-    pub trie: Rc<Trie>,
+    pub trie: Arc<Trie>,
     root: CryptoHash,
     committed: RawStateChanges,
     prospective: TrieUpdates,
@@ -52,7 +56,7 @@ impl<'a> TrieUpdateValuePtr<'a> {
 }
 
 impl TrieUpdate {
-    pub fn new(trie: Rc<Trie>, root: CryptoHash) -> Self {
+    pub fn new(trie: Arc<Trie>, root: CryptoHash) -> Self {
         TrieUpdate { trie, root, committed: Default::default(), prospective: Default::default() }
     }
 
@@ -114,6 +118,16 @@ impl TrieUpdate {
         self.prospective.clear();
     }
 
+    /// Saves the uncommitted changes, so the changes made after it can be reverted with
+    /// `rollback_to_checkpoint` without reverting the earlier ones.
+    pub fn checkpoint(&self) -> TrieUpdateCheckpoint {
+        TrieUpdateCheckpoint(self.prospective.clone())
+    }
+
+    pub fn rollback_to_checkpoint(&mut self, checkpoint: TrieUpdateCheckpoint) {
+        self.prospective = checkpoint.0;
+    }
+
     pub fn finalize(self) -> Result<(TrieChanges, Vec<RawStateChangesWithTrieKey>), StorageError> {
         assert!(self.prospective.is_empty(), "Finalize cannot be called with uncommitted changes.");
         let TrieUpdate { trie, root, committed, .. } = self;
@@ -421,6 +435,19 @@ mod tests {
         assert_eq!(new_root, CryptoHash::default());
     }
 
+    #[test]
+    fn trie_rollback_to_checkpoint() {
+        let tries = create_tries();
+        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+        let checkpoint = trie_update.checkpoint();
+        trie_update.set(test_key(b"dog".to_vec()), b"dog".to_vec());
+        trie_update.set(test_key(b"cat".to_vec()), b"kitten".to_vec());
+        trie_update.rollback_to_checkpoint(checkpoint);
+        assert_eq!(trie_update.get(&test_key(b"dog".to_vec())), Ok(Some(b"puppy".to_vec())));
+        assert_eq!(trie_update.get(&test_key(b"cat".to_vec())), Ok(None));
+    }
+
     #[test]
     fn trie_iter() {
         let tries = create_tries();
@@ -503,4 +530,39 @@ mod tests {
             ]
         );
     }
+
+    /// A view of the state is read on another thread while a newer state is committed.
+    #[test]
+    fn trie_update_view_is_isolated_from_commits() {
+        let tries = create_tries();
+        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+        trie_update
+            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+        let trie_changes = trie_update.finalize().unwrap().0;
+        let (store_update, old_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let view = tries.new_trie_update_view(0, old_root);
+        let (committed_sender, committed_receiver) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            committed_receiver.recv().unwrap();
+            view.get(&test_key(b"dog".to_vec()))
+        });
+
+        let mut trie_update = tries.new_trie_update(0, old_root);
+        trie_update.set(test_key(b"dog".to_vec()), b"dog".to_vec());
+        trie_update
+            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+        let trie_changes = trie_update.finalize().unwrap().0;
+        // Like the chain, keep the nodes of the old state until they are garbage collected.
+        let mut store_update = tries.get_store().store_update();
+        tries.apply_insertions(&trie_changes, 0, &mut store_update).unwrap();
+        store_update.commit().unwrap();
+        committed_sender.send(()).unwrap();
+
+        assert_eq!(handle.join().unwrap(), Ok(Some(b"puppy".to_vec())));
+        let view = tries.new_trie_update_view(0, trie_changes.new_root);
+        assert_eq!(view.get(&test_key(b"dog".to_vec())), Ok(Some(b"dog".to_vec())));
+    }
 }
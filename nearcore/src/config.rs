@@ -12,21 +12,25 @@ use num_rational::Rational;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use near_chain_configs::{ClientConfig, Genesis, GenesisConfig, LogSummaryStyle};
+use near_chain_configs::{ClientConfig, Genesis, GenesisConfig, LogSummaryStyle, ShardTracking};
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
+use near_epoch_manager::ValidatorSetOverride;
 #[cfg(feature = "json_rpc")]
 use near_jsonrpc::RpcConfig;
+use near_network::noise::ConnectionEncryption;
+use near_network::send_queue::SendQueueConfig;
 use near_network::test_utils::open_port;
 use near_network::types::ROUTED_MESSAGE_TTL;
 use near_network::utils::blacklist_from_iter;
 use near_network::NetworkConfig;
 use near_primitives::account::{AccessKey, Account};
+use near_primitives::epoch_manager::EpochConfig;
 use near_primitives::hash::CryptoHash;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
-    AccountId, AccountInfo, Balance, BlockHeightDelta, EpochHeight, Gas, NumBlocks, NumSeats,
-    NumShards, ShardId,
+    AccountId, AccountInfo, Balance, BlockHeightDelta, EpochHeight, Gas, Nonce, NumBlocks,
+    NumSeats, NumShards, ShardId, StateChangesFilter,
 };
 use near_primitives::utils::{generate_random_string, get_num_seats_per_shard};
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
@@ -135,6 +139,8 @@ pub const CONFIG_FILENAME: &str = "config.json";
 pub const GENESIS_CONFIG_FILENAME: &str = "genesis.json";
 pub const NODE_KEY_FILE: &str = "node_key.json";
 pub const VALIDATOR_KEY_FILE: &str = "validator_key.json";
+/// Validator set to force on the network, only meant to recover a stalled network.
+pub const VALIDATOR_SET_OVERRIDE_FILE: &str = "validator_set_override.json";
 
 pub const MAINNET_TELEMETRY_URL: &str = "https://explorer.mainnet.near.org/api/nodes";
 pub const NETWORK_TELEMETRY_URL: &str = "https://explorer.{}.near.org/api/nodes";
@@ -192,6 +198,22 @@ fn default_peer_stats_period() -> Duration {
     Duration::from_secs(5)
 }
 
+fn default_reputation_ban_threshold() -> u32 {
+    100
+}
+
+fn default_reputation_half_life() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn default_max_relayed_peers() -> u32 {
+    8
+}
+
+fn default_max_mismatching_peers() -> u32 {
+    3
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Network {
     /// Address to listen for incoming connections.
@@ -232,6 +254,16 @@ pub struct Network {
     pub skip_sync_wait: bool,
     /// Ban window for peers who misbehave.
     pub ban_window: Duration,
+    /// Peers whose reputation score reaches this threshold are banned for `ban_window`.
+    #[serde(default = "default_reputation_ban_threshold")]
+    pub reputation_ban_threshold: u32,
+    /// Time after which the penalty for a misbehavior of a peer counts half.
+    #[serde(default = "default_reputation_half_life")]
+    pub reputation_half_life: Duration,
+    /// Bandwidth budgets and queue limits of the consensus, normal and bulk messages sent to a
+    /// peer.
+    #[serde(default)]
+    pub send_queue: SendQueueConfig,
     /// List of addresses that will not be accepted as valid neighbors.
     /// It can be IP:Port or IP (to blacklist all connections coming from this address).
     #[serde(default)]
@@ -242,6 +274,22 @@ pub struct Network {
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// Set if the node can't accept inbound connections, e.g. because it is behind NAT.
+    /// The node then registers at a relay peer and connects to the peers asking it to.
+    #[serde(default)]
+    pub use_relay: bool,
+    /// Maximum number of peers behind NAT this node relays for.
+    #[serde(default = "default_max_relayed_peers")]
+    pub max_relayed_peers: u32,
+    /// Encryption of the connections to peers. Plaintext connections are only dropped once set
+    /// to `Required`, which should wait until all the peers support encryption.
+    #[serde(default)]
+    pub encryption: ConnectionEncryption,
+    /// Number of peers rejecting the handshake because they are on another network (different
+    /// chain id, genesis or protocol version) after which the node stops with a report of the
+    /// differences, unless it has been connected to some peer. 0 to keep retrying forever.
+    #[serde(default = "default_max_mismatching_peers")]
+    pub max_mismatching_peers: u32,
 }
 
 impl Default for Network {
@@ -261,9 +309,16 @@ impl Default for Network {
             reconnect_delay: Duration::from_secs(60),
             skip_sync_wait: false,
             ban_window: Duration::from_secs(3 * 60 * 60),
+            reputation_ban_threshold: default_reputation_ban_threshold(),
+            reputation_half_life: default_reputation_half_life(),
+            send_queue: SendQueueConfig::default(),
             blacklist: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            use_relay: false,
+            max_relayed_peers: default_max_relayed_peers(),
+            encryption: ConnectionEncryption::default(),
+            max_mismatching_peers: default_max_mismatching_peers(),
         }
     }
 }
@@ -313,6 +368,10 @@ fn default_doomslug_step_period() -> Duration {
     Duration::from_millis(100)
 }
 
+fn default_head_stall_timeout() -> Duration {
+    Duration::from_secs(300)
+}
+
 fn default_view_client_throttle_period() -> Duration {
     Duration::from_secs(30)
 }
@@ -321,6 +380,18 @@ fn default_trie_viewer_state_size_limit() -> Option<u64> {
     Some(50_000)
 }
 
+fn default_min_free_disk_space_for_upgrade() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_max_chunk_transactions_size() -> u64 {
+    4 * 1024 * 1024
+}
+
+fn default_tx_nonce_window() -> Nonce {
+    10_000
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -372,6 +443,10 @@ pub struct Consensus {
     /// Time between running doomslug timer.
     #[serde(default = "default_doomslug_step_period")]
     pub doomslug_step_period: Duration,
+    /// How long the head or the final head may not advance before reconnecting peers and
+    /// restarting the sync
+    #[serde(default = "default_head_stall_timeout")]
+    pub head_stall_timeout: Duration,
 }
 
 impl Default for Consensus {
@@ -398,6 +473,7 @@ impl Default for Consensus {
             sync_check_period: default_sync_check_period(),
             sync_step_period: default_sync_step_period(),
             doomslug_step_period: default_doomslug_step_period(),
+            head_stall_timeout: default_head_stall_timeout(),
         }
     }
 }
@@ -420,6 +496,8 @@ pub struct Config {
     pub consensus: Consensus,
     pub tracked_accounts: Vec<AccountId>,
     pub tracked_shards: Vec<ShardId>,
+    /// Set to `all` to track all shards or to `own` to track only the assigned ones.
+    pub shard_tracking: ShardTracking,
     pub archive: bool,
     pub log_summary_style: LogSummaryStyle,
     #[serde(default = "default_gc_blocks_limit")]
@@ -434,6 +512,43 @@ pub struct Config {
     /// If set, overrides value in genesis configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_gas_burnt_view: Option<Gas>,
+    /// If set, view method calls running longer than this are stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_view_call_time: Option<Duration>,
+    /// State changes matching these filters are stored per block and can be queried with
+    /// `EXPERIMENTAL_filtered_changes`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub state_changes_filters: Vec<StateChangesFilter>,
+    /// Path of the database archival nodes move the data older than the GC horizon to,
+    /// relative to the home directory. The data stays in the main database if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cold_store_path: Option<String>,
+    /// Commit the store updates of processed blocks on a dedicated writer thread, so block
+    /// processing doesn't wait for the database to persist them.
+    pub async_store_commits: bool,
+    /// Minimum free disk space of the database, in bytes, to vote for a newer protocol version.
+    #[serde(default = "default_min_free_disk_space_for_upgrade")]
+    pub min_free_disk_space_for_upgrade: u64,
+    /// Check at the end of every epoch that the total supply matches the states of the shards.
+    /// The check reads the whole state, so it's meant for debugging nodes tracking all shards.
+    pub audit_total_supply: bool,
+    /// Maximum total size of the transactions in a produced chunk, in bytes, keeping the chunk
+    /// well within the size of a network message. Larger transactions are rejected.
+    #[serde(default = "default_max_chunk_transactions_size")]
+    pub max_chunk_transactions_size: u64,
+    /// Transactions whose nonce is more than this ahead of the nonce of their access key are
+    /// rejected, to limit the transactions kept in the pool for nonces far in the future.
+    /// 0 disables the limit.
+    #[serde(default = "default_tx_nonce_window")]
+    pub tx_nonce_window: Nonce,
+    /// Whether a transaction replaces a pending transaction with the same access key and nonce
+    /// if it attaches more gas, allowing to speed up or fix a transaction stuck in the pool.
+    #[serde(default)]
+    pub tx_replace_by_nonce: bool,
+    /// Sync only the block headers and the epoch data, serving the light client RPC with minimal
+    /// disk and CPU usage, e.g. for monitoring or bridge relayers. Such a node can't validate.
+    #[serde(default)]
+    pub header_only: bool,
 }
 
 impl Default for Config {
@@ -452,6 +567,7 @@ impl Default for Config {
             consensus: Consensus::default(),
             tracked_accounts: vec![],
             tracked_shards: vec![],
+            shard_tracking: ShardTracking::default(),
             archive: false,
             log_summary_style: LogSummaryStyle::Colored,
             gc_blocks_limit: default_gc_blocks_limit(),
@@ -460,6 +576,16 @@ impl Default for Config {
             view_client_throttle_period: default_view_client_throttle_period(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
             max_gas_burnt_view: None,
+            max_view_call_time: None,
+            state_changes_filters: vec![],
+            cold_store_path: None,
+            async_store_commits: false,
+            min_free_disk_space_for_upgrade: default_min_free_disk_space_for_upgrade(),
+            audit_total_supply: false,
+            max_chunk_transactions_size: default_max_chunk_transactions_size(),
+            tx_nonce_window: default_tx_nonce_window(),
+            tx_replace_by_nonce: false,
+            header_only: false,
         }
     }
 }
@@ -592,6 +718,8 @@ pub struct NearConfig {
     pub telemetry_config: TelemetryConfig,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
+    /// Validator set to force on the network, see `load_validator_set_override`.
+    pub validator_set_override: Option<ValidatorSetOverride>,
 }
 
 impl NearConfig {
@@ -639,6 +767,7 @@ impl NearConfig {
                 doosmslug_step_period: config.consensus.doomslug_step_period,
                 tracked_accounts: config.tracked_accounts,
                 tracked_shards: config.tracked_shards,
+                shard_tracking: config.shard_tracking,
                 archive: config.archive,
                 log_summary_style: config.log_summary_style,
                 gc_blocks_limit: config.gc_blocks_limit,
@@ -647,6 +776,17 @@ impl NearConfig {
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
+                max_view_call_time: config.max_view_call_time,
+                state_changes_filters: config.state_changes_filters,
+                // Set once the home directory is known.
+                store_path: None,
+                min_free_disk_space_for_upgrade: config.min_free_disk_space_for_upgrade,
+                head_stall_timeout: config.consensus.head_stall_timeout,
+                audit_total_supply: config.audit_total_supply,
+                max_chunk_transactions_size: config.max_chunk_transactions_size,
+                tx_nonce_window: config.tx_nonce_window,
+                tx_replace_by_nonce: config.tx_replace_by_nonce,
+                header_only: config.header_only,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -680,6 +820,9 @@ impl NearConfig {
                     .network
                     .archival_peer_connections_lower_bound,
                 ban_window: config.network.ban_window,
+                reputation_ban_threshold: config.network.reputation_ban_threshold,
+                reputation_half_life: config.network.reputation_half_life,
+                send_queue: config.network.send_queue.clone(),
                 max_send_peers: 512,
                 peer_expiration_duration: Duration::from_secs(7 * 24 * 60 * 60),
                 peer_stats_period: Duration::from_secs(5),
@@ -691,6 +834,10 @@ impl NearConfig {
                 blacklist: blacklist_from_iter(config.network.blacklist),
                 outbound_disabled: false,
                 archive: config.archive,
+                use_relay: config.network.use_relay,
+                max_relayed_peers: config.network.max_relayed_peers,
+                encryption: config.network.encryption,
+                max_mismatching_peers: config.network.max_mismatching_peers,
             },
             telemetry_config: config.telemetry,
             #[cfg(feature = "json_rpc")]
@@ -699,6 +846,7 @@ impl NearConfig {
             rosetta_rpc_config: config.rosetta_rpc,
             genesis,
             validator_signer,
+            validator_set_override: None,
         }
     }
 
@@ -712,6 +860,14 @@ impl NearConfig {
 }
 
 impl NearConfig {
+    /// Path of the cold database, if the node is archival and has one configured.
+    pub fn cold_store_path(&self, home_dir: &Path) -> Option<PathBuf> {
+        if !self.config.archive {
+            return None;
+        }
+        self.config.cold_store_path.as_ref().map(|path| home_dir.join(path))
+    }
+
     /// Test tool to save configs back to the folder.
     /// Useful for dynamic creating testnet configs and then saving them in different folders.
     pub fn save_to_dir(&self, dir: &Path) {
@@ -1151,6 +1307,19 @@ pub fn load_config(dir: &Path) -> NearConfig {
     near_config
 }
 
+/// Reads the validator set override from `VALIDATOR_SET_OVERRIDE_FILE` in the home directory, if
+/// there is one, and checks it against the genesis config.
+pub fn load_validator_set_override(
+    dir: &Path,
+    genesis_config: &GenesisConfig,
+) -> Result<Option<ValidatorSetOverride>, String> {
+    let path = dir.join(VALIDATOR_SET_OVERRIDE_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    ValidatorSetOverride::from_file(&path, &EpochConfig::from(genesis_config)).map(Some)
+}
+
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
     let mut config = Config::default();
     config.network.addr = format!("0.0.0.0:{}", port);
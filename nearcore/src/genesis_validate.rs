@@ -1,6 +1,8 @@
 use near_chain_configs::{Genesis, GenesisConfig};
 use near_crypto::key_conversion::is_valid_staking_key;
+use near_primitives::epoch_manager::StakeDenomination;
 use near_primitives::state_record::StateRecord;
+use near_primitives::types::AccountId;
 use num_rational::Rational;
 use std::collections::{HashMap, HashSet};
 
@@ -127,6 +129,75 @@ impl<'a> GenesisValidator<'a> {
             self.genesis_config.gas_price_adjustment_rate < Rational::from_integer(1),
             "Gas price adjustment rate must be less than 1"
         );
+        self.validate_protocol_treasury_split();
+        self.validate_stake_denomination();
+    }
+
+    fn validate_stake_denomination(&self) {
+        let ratios: Vec<(&AccountId, &Rational)> = match &self.genesis_config.stake_denomination {
+            StakeDenomination::Native => vec![],
+            StakeDenomination::Multipliers(multipliers) => multipliers.iter().collect(),
+            StakeDenomination::SecondaryToken { stakes, rate } => {
+                stakes.iter().map(|stake| (&stake.account_id, rate)).collect()
+            }
+        };
+        for (account_id, ratio) in ratios {
+            assert!(
+                *ratio.numer() >= 0 && *ratio.denom() > 0,
+                "Stake denomination ratio of {} must not be negative",
+                account_id
+            );
+            assert!(
+                *ratio.numer() < 10_000_000 && *ratio.denom() < 10_000_000,
+                "Stake denomination ratio of {} is too precise, may lead to overflow.",
+                account_id
+            );
+        }
+    }
+
+    fn validate_protocol_treasury_split(&self) {
+        let treasury_accounts = self.genesis_config.protocol_treasury_accounts();
+        assert_eq!(
+            treasury_accounts.iter().collect::<HashSet<_>>().len(),
+            treasury_accounts.len(),
+            "Duplicate account in protocol treasury split"
+        );
+        for account_id in treasury_accounts.iter().skip(1) {
+            assert!(
+                self.account_ids.contains(account_id),
+                "protocol treasury split account {} does not exist",
+                account_id
+            );
+        }
+        let mut total_share = Rational::from_integer(0);
+        for share in self.genesis_config.protocol_treasury_split.iter() {
+            assert!(
+                *share.share.numer() > 0 && *share.share.denom() > 0,
+                "Protocol treasury share of {} must be positive",
+                share.account_id
+            );
+            assert!(
+                *share.share.numer() < 10_000_000 && *share.share.denom() < 10_000_000,
+                "Protocol treasury share of {} is too precise, may lead to overflow.",
+                share.account_id
+            );
+            total_share += share.share;
+        }
+        if let Some(share) = self.genesis_config.developer_reward_share {
+            assert!(
+                *share.numer() > 0 && *share.denom() > 0,
+                "Developer reward share must be positive"
+            );
+            assert!(
+                *share.numer() < 10_000_000 && *share.denom() < 10_000_000,
+                "Developer reward share is too precise, may lead to overflow."
+            );
+            total_share += share;
+        }
+        assert!(
+            total_share <= Rational::from_integer(1),
+            "Protocol treasury shares must add up to at most 1"
+        );
     }
 }
 
@@ -134,7 +205,7 @@ impl<'a> GenesisValidator<'a> {
 mod test {
     use super::*;
 
-    use near_chain_configs::GenesisRecords;
+    use near_chain_configs::{GenesisRecords, ProtocolTreasuryShare};
     use near_crypto::{KeyType, PublicKey};
     use near_primitives::account::{AccessKey, Account};
     use near_primitives::types::AccountInfo;
@@ -243,4 +314,59 @@ mod test {
         ]);
         validate_genesis(&genesis);
     }
+
+    fn genesis_with_treasury_split(shares: Vec<(&str, Rational)>) -> Genesis {
+        let mut genesis = Genesis::default();
+        genesis.config.validators = vec![AccountInfo {
+            account_id: "test".to_string(),
+            public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+            amount: 10,
+        }];
+        genesis.config.total_supply = 330;
+        genesis.config.protocol_treasury_account = "near".to_string();
+        genesis.config.protocol_treasury_split = shares
+            .into_iter()
+            .map(|(account_id, share)| ProtocolTreasuryShare {
+                account_id: account_id.to_string(),
+                share,
+            })
+            .collect();
+        genesis.records = GenesisRecords(vec![
+            StateRecord::Account { account_id: "test".to_string(), account: create_account() },
+            StateRecord::Account {
+                account_id: "near".to_string(),
+                account: Account::new(110, 0, Default::default(), 0),
+            },
+            StateRecord::Account {
+                account_id: "grants".to_string(),
+                account: Account::new(110, 0, Default::default(), 0),
+            },
+        ]);
+        genesis
+    }
+
+    #[test]
+    fn test_protocol_treasury_split() {
+        validate_genesis(&genesis_with_treasury_split(vec![("grants", Rational::new(1, 3))]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Protocol treasury shares must add up to at most 1")]
+    fn test_protocol_treasury_split_exceeds_reward() {
+        validate_genesis(&genesis_with_treasury_split(vec![("grants", Rational::new(4, 3))]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate account in protocol treasury split")]
+    fn test_protocol_treasury_split_duplicate_account() {
+        validate_genesis(&genesis_with_treasury_split(vec![("near", Rational::new(1, 3))]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Protocol treasury shares must add up to at most 1")]
+    fn test_developer_reward_share_exceeds_reward() {
+        let mut genesis = genesis_with_treasury_split(vec![("grants", Rational::new(2, 3))]);
+        genesis.config.developer_reward_share = Some(Rational::new(1, 2));
+        validate_genesis(&genesis);
+    }
 }
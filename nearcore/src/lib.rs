@@ -4,18 +4,19 @@ use std::sync::Arc;
 
 use actix::{Actor, Addr, Arbiter};
 use actix_rt::ArbiterHandle;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 use near_chain::ChainGenesis;
 #[cfg(feature = "adversarial")]
 use near_client::AdversarialControls;
 use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
+use near_network::event_log::NetworkEventLog;
 use near_network::{NetworkRecipient, PeerManagerActor};
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::start_rosetta_rpc;
 #[cfg(feature = "performance_stats")]
 use near_rust_allocator_proxy::allocator::reset_memory_usage_max;
-use near_store::{create_store, Store};
+use near_store::{create_split_store, create_store, with_async_commits, Store};
 use near_telemetry::TelemetryActor;
 
 pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
@@ -27,7 +28,8 @@ pub use crate::runtime::NightshadeRuntime;
 use near_store::migrations::{
     fill_col_outcomes_by_hash, fill_col_transaction_refcount, get_store_version, migrate_10_to_11,
     migrate_11_to_12, migrate_13_to_14, migrate_14_to_15, migrate_17_to_18, migrate_21_to_22,
-    migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10, set_store_version,
+    migrate_25_to_26, migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10,
+    set_store_version,
 };
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -226,6 +228,10 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 24 to 25");
         migrate_24_to_25(&path);
     }
+    if db_version <= 25 {
+        info!(target: "near", "Migrate DB from version 25 to 26");
+        migrate_25_to_26(&path);
+    }
     #[cfg(feature = "nightly_protocol")]
     {
         let store = create_store(&path);
@@ -252,34 +258,57 @@ pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Arc<
     if store_exists {
         apply_store_migrations(&path, near_config);
     }
-    let store = create_store(&path);
+    let store = match near_config.cold_store_path(home_dir) {
+        Some(cold_path) => {
+            info!(target: "near", "Opening cold store database at {:?}", cold_path);
+            create_split_store(&path, cold_path.to_str().unwrap())
+        }
+        None => create_store(&path),
+    };
     if !store_exists {
         set_store_version(&store, near_primitives::version::DB_VERSION);
     }
+    if near_config.config.async_store_commits {
+        info!(target: "near", "Committing store updates on a dedicated writer thread");
+        return with_async_commits(store);
+    }
     store
 }
 
 pub fn start_with_config(
     home_dir: &Path,
-    config: NearConfig,
+    mut config: NearConfig,
 ) -> (Addr<ClientActor>, Addr<ViewClientActor>, Vec<ArbiterHandle>) {
     let store = init_and_migrate_store(home_dir, &config);
+    config.client_config.store_path = Some(home_dir.join(STORE_PATH));
+    if config.client_config.header_only && config.validator_signer.is_some() {
+        warn!(target: "near", "The node syncs only the block headers, ignoring the validator key");
+        config.validator_signer = None;
+        config.network_config.account_id = None;
+    }
 
-    let runtime = Arc::new(NightshadeRuntime::new(
-        home_dir,
-        Arc::clone(&store),
-        &config.genesis,
-        config.client_config.tracked_accounts.clone(),
-        config.client_config.tracked_shards.clone(),
-        config.client_config.trie_viewer_state_size_limit,
-        config.client_config.max_gas_burnt_view,
-    ));
+    let runtime = Arc::new(
+        NightshadeRuntime::new(
+            home_dir,
+            Arc::clone(&store),
+            &config.genesis,
+            config.client_config.tracked_accounts.clone(),
+            config.client_config.tracked_shards.clone(),
+            config.client_config.trie_viewer_state_size_limit,
+            config.client_config.max_gas_burnt_view,
+        )
+        .with_max_view_call_time(config.client_config.max_view_call_time)
+        .with_shard_tracking(config.client_config.shard_tracking)
+        .with_validator_set_override(config.validator_set_override.clone()),
+    );
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
 
     let node_id = config.network_config.public_key.clone().into();
     let network_adapter = Arc::new(NetworkRecipient::new());
+    let network_event_log = NetworkEventLog::default();
+    network_event_log.dump_on_panic();
     #[cfg(feature = "adversarial")]
     let adv = Arc::new(std::sync::RwLock::new(AdversarialControls::default()));
 
@@ -310,6 +339,7 @@ pub fn start_with_config(
             config.genesis.config.clone(),
             client_actor.clone(),
             view_client.clone(),
+            network_event_log.clone(),
         );
     }
     #[cfg(feature = "rosetta_rpc")]
@@ -331,7 +361,9 @@ pub fn start_with_config(
     let network_config = config.network_config;
 
     let network_actor = PeerManagerActor::start_in_arbiter(&arbiter.handle(), move |_ctx| {
-        PeerManagerActor::new(store, network_config, client_actor1, view_client1).unwrap()
+        PeerManagerActor::new(store, network_config, client_actor1, view_client1)
+            .unwrap()
+            .with_event_log(network_event_log)
     });
 
     network_adapter.set_recipient(network_actor.recipient());
@@ -492,6 +492,9 @@ pub fn load_migration_data(chain_id: &String) -> MigrationData {
         } else {
             ReceiptResult::default()
         },
+        // No maintenance calls are scheduled yet.
+        #[cfg(feature = "protocol_feature_system_calls")]
+        system_calls: Default::default(),
     }
 }
 
@@ -20,6 +20,22 @@ impl QueryError {
             node_runtime::state_viewer::errors::CallFunctionError::VMError { error_message } => {
                 Self::ContractExecutionError { error_message, block_height, block_hash }
             }
+            node_runtime::state_viewer::errors::CallFunctionError::GasLimitExceeded {
+                gas_burnt,
+                ref logs,
+                ..
+            }
+            | node_runtime::state_viewer::errors::CallFunctionError::TimeLimitExceeded {
+                gas_burnt,
+                ref logs,
+                ..
+            } => Self::ViewLimitExceeded {
+                error_message: error.to_string(),
+                gas_burnt,
+                logs: logs.clone(),
+                block_height,
+                block_hash,
+            },
         }
     }
 
@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use borsh::ser::BorshSerialize;
 use borsh::BorshDeserialize;
@@ -13,11 +13,13 @@ use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo, ValidatorInfoId
 use near_chain::{BlockHeader, Error, ErrorKind, RuntimeAdapter};
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_chain::{Doomslug, DoomslugThresholdMode};
-use near_chain_configs::{Genesis, GenesisConfig, ProtocolConfig};
+use near_chain_configs::{Genesis, GenesisConfig, ProtocolConfig, ShardTracking};
 #[cfg(feature = "protocol_feature_evm")]
 use near_chain_configs::{BETANET_EVM_CHAIN_ID, MAINNET_EVM_CHAIN_ID, TESTNET_EVM_CHAIN_ID};
 use near_crypto::{PublicKey, Signature};
-use near_epoch_manager::{EpochManager, RewardCalculator};
+#[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+use near_epoch_manager::NUM_SEED_BLOCKS;
+use near_epoch_manager::{EpochManager, RewardCalculator, ValidatorSetOverride};
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{Approval, ApprovalInner};
@@ -26,39 +28,51 @@ use near_primitives::contract::ContractCode;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::EpochConfig;
-use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError};
+#[cfg(feature = "protocol_feature_slashing_escrow")]
+use near_primitives::epoch_manager::SlashState;
+use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError, StorageError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::{self, ShardLayout};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::{state_record_to_account_id, StateRecord};
+use near_primitives::syncing::compute_epoch_sync_data_hash;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
-    AccountId, ApprovalStake, Balance, BlockHeight, CompiledContractCache, EpochHeight, EpochId,
-    EpochInfoProvider, Gas, MerkleHash, NumShards, ShardId, StateChangeCause, StateRoot,
-    StateRootNode,
+    AccountId, ApprovalStake, Balance, BlockHeight, BurnLedger, CompiledContractCache, EpochHeight,
+    EpochId, EpochInfoProvider, Gas, MerkleHash, Nonce, NumShards, ShardId, StateChangeCause,
+    StateRoot, StateRootNode,
 };
+use near_primitives::utils::system_account;
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
-    AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewApplyState, ViewStateResult,
+    AccessKeyInfoView, CallResult, EpochValidatorDiffView, EpochValidatorInfo, QueryRequest,
+    QueryResponse, QueryResponseKind, SeatPriceView, ViewApplyState, ViewStateResult,
 };
 use near_vm_runner::precompile_contract;
 
 use near_store::{
     get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots,
-    ApplyStatePartResult, ColState, PartialStorage, ShardTries, Store, StoreCompiledContractCache,
-    StoreUpdate, Trie, WrappedTrieChanges,
+    ApplyStatePartResult, ColBlockHeader, ColContractExecutionStats, ColState, PartialStorage,
+    ShardTries, Store, StoreCompiledContractCache, StoreUpdate, Trie, WrappedTrieChanges,
 };
 use node_runtime::adapter::ViewRuntimeAdapter;
+use node_runtime::config::{receipts_cost, safe_add_balance};
+use node_runtime::state_viewer::errors::ViewAccessKeyError;
 use node_runtime::state_viewer::TrieViewer;
+use node_runtime::supply::compute_total_supply;
 use node_runtime::{
     validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
     ValidatorAccountsUpdate,
 };
 
 use crate::shard_tracker::{account_id_to_shard_id, ShardTracker};
+use near_primitives::runtime::apply_state::ApplyObserver;
 use near_primitives::runtime::config::ActualRuntimeConfig;
+use near_primitives::runtime::contract_stats::{
+    merge_contract_stats, ContractExecutionStats, ContractStats,
+};
 
 use crate::migrations::load_migration_data;
 use errors::FromStateViewerErrors;
@@ -70,6 +84,9 @@ pub mod errors;
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 const STATE_DUMP_FILE: &str = "state_dump";
 const GENESIS_ROOTS_FILE: &str = "genesis_roots";
+/// Number of applied chunks after which the execution statistics of contracts are written to
+/// the store.
+const CONTRACT_STATS_FLUSH_PERIOD: u64 = 100;
 
 /// Wrapper type for epoch manager to get avoid implementing trait for foreign types.
 pub struct SafeEpochManager(pub Arc<RwLock<EpochManager>>);
@@ -139,6 +156,19 @@ pub struct NightshadeRuntime {
     shard_tracker: ShardTracker,
     genesis_state_roots: Vec<StateRoot>,
     migration_data: Arc<MigrationData>,
+    /// Protocol version used to apply the chunks instead of the version of their epoch, to replay
+    /// the chain with a candidate protocol version.
+    protocol_version_override: Option<ProtocolVersion>,
+    apply_observer: Option<Arc<dyn ApplyObserver>>,
+    pending_contract_stats: Mutex<PendingContractStats>,
+}
+
+/// Execution statistics of contracts in the chunks applied since they were last written to the
+/// store.
+#[derive(Default)]
+struct PendingContractStats {
+    stats: ContractStats,
+    num_applied_chunks: u64,
 }
 
 impl NightshadeRuntime {
@@ -193,9 +223,93 @@ impl NightshadeRuntime {
             shard_tracker,
             genesis_state_roots: state_roots,
             migration_data: Arc::new(load_migration_data(&genesis.config.chain_id)),
+            protocol_version_override: None,
+            apply_observer: None,
+            pending_contract_stats: Mutex::new(PendingContractStats::default()),
         }
     }
 
+    /// Limits the wall-clock time of view method calls.
+    pub fn with_max_view_call_time(mut self, max_view_call_time: Option<Duration>) -> Self {
+        self.trie_viewer =
+            std::mem::take(&mut self.trie_viewer).with_max_view_call_time(max_view_call_time);
+        self
+    }
+
+    /// Applies the chunks with the given protocol version, whatever the version of their epoch.
+    /// Only meant for replaying the chain, the results don't match the canonical chain.
+    pub fn with_protocol_version_override(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.protocol_version_override = Some(protocol_version);
+        self
+    }
+
+    /// Tracks all shards, only the assigned ones, or the ones the runtime was created with.
+    pub fn with_shard_tracking(mut self, shard_tracking: ShardTracking) -> Self {
+        self.shard_tracker.set_shard_tracking(shard_tracking);
+        self
+    }
+
+    /// Forces the validator set override on the network, see `ValidatorSetOverride`.
+    pub fn with_validator_set_override(
+        self,
+        validator_set_override: Option<ValidatorSetOverride>,
+    ) -> Self {
+        if let Some(validator_set_override) = validator_set_override {
+            self.epoch_manager
+                .as_ref()
+                .write()
+                .expect(POISONED_LOCK_ERR)
+                .set_validator_set_override(validator_set_override);
+        }
+        self
+    }
+
+    /// Streams the execution events of all applied chunks to the observer.
+    pub fn with_apply_observer(mut self, apply_observer: Arc<dyn ApplyObserver>) -> Self {
+        self.apply_observer = Some(apply_observer);
+        self
+    }
+
+    /// Returns the layout of the shards in the given epoch, which assigns accounts to the shards
+    /// of the chunks of the epoch.
+    pub fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager.get_shard_layout(epoch_id).map_err(Error::from)
+    }
+
+    /// Adds the execution statistics of contracts in an applied chunk to the aggregated ones,
+    /// which are written to the store periodically.
+    fn record_contract_stats(&self, contract_stats: &ContractStats) -> Result<(), Error> {
+        let mut pending = self.pending_contract_stats.lock().expect(POISONED_LOCK_ERR);
+        merge_contract_stats(&mut pending.stats, contract_stats);
+        pending.num_applied_chunks += 1;
+        if pending.num_applied_chunks >= CONTRACT_STATS_FLUSH_PERIOD {
+            self.write_contract_stats(&mut pending)?;
+        }
+        Ok(())
+    }
+
+    fn write_contract_stats(&self, pending: &mut PendingContractStats) -> Result<(), Error> {
+        let mut store_update = self.store.store_update();
+        for (code_hash, stats) in pending.stats.iter() {
+            let mut stored_stats: ContractExecutionStats = self
+                .store
+                .get_ser(ColContractExecutionStats, code_hash.as_ref())?
+                .unwrap_or_default();
+            stored_stats.merge(stats);
+            store_update.set_ser(ColContractExecutionStats, code_hash.as_ref(), &stored_stats)?;
+        }
+        store_update.commit()?;
+        *pending = PendingContractStats::default();
+        Ok(())
+    }
+
+    /// Writes the execution statistics of contracts aggregated so far to the store.
+    pub fn flush_contract_stats(&self) -> Result<(), Error> {
+        let mut pending = self.pending_contract_stats.lock().expect(POISONED_LOCK_ERR);
+        self.write_contract_stats(&mut pending)
+    }
+
     fn get_epoch_height_from_prev_block(
         &self,
         prev_block_hash: &CryptoHash,
@@ -210,6 +324,52 @@ impl NightshadeRuntime {
         epoch_manager.get_epoch_id(hash).map_err(Error::from)
     }
 
+    /// Random seed of the epoch of the block after the given one: the random value of the last
+    /// block of the previous epoch, which is known before any block of the epoch is produced.
+    fn get_epoch_random_seed_from_prev_block(
+        &self,
+        prev_block_hash: &CryptoHash,
+    ) -> Result<CryptoHash, Error> {
+        let last_block_hash = {
+            let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+            if epoch_manager.is_next_block_epoch_start(prev_block_hash)? {
+                *prev_block_hash
+            } else {
+                let epoch_first_block =
+                    *epoch_manager.get_block_info(prev_block_hash)?.epoch_first_block();
+                *epoch_manager.get_block_info(&epoch_first_block)?.prev_hash()
+            }
+        };
+        match self.store.get_ser::<BlockHeader>(ColBlockHeader, last_block_hash.as_ref())? {
+            Some(header) => Ok(*header.random_value()),
+            None => {
+                Err(ErrorKind::DBNotFoundErr(format!("BLOCK HEADER: {}", last_block_hash)).into())
+            }
+        }
+    }
+
+    /// Returns the random values of up to `NUM_SEED_BLOCKS` last blocks ending with the given one,
+    /// oldest first. The epoch manager derives the seed of the validator shuffling from them when
+    /// the block is the last one of its epoch.
+    #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+    fn get_epoch_seed_inputs(
+        &self,
+        block_header_info: &BlockHeaderInfo,
+    ) -> Result<Vec<CryptoHash>, Error> {
+        let mut random_values = vec![block_header_info.random_value];
+        let mut prev_hash = block_header_info.prev_hash;
+        while random_values.len() < NUM_SEED_BLOCKS && prev_hash != CryptoHash::default() {
+            let header = self
+                .store
+                .get_ser::<BlockHeader>(ColBlockHeader, prev_hash.as_ref())?
+                .ok_or_else(|| ErrorKind::DBNotFoundErr(format!("BLOCK HEADER: {}", prev_hash)))?;
+            random_values.push(*header.random_value());
+            prev_hash = *header.prev_hash();
+        }
+        random_values.reverse();
+        Ok(random_values)
+    }
+
     fn genesis_state_from_dump(store: Arc<Store>, home_dir: &Path) -> Vec<StateRoot> {
         error!(target: "near", "Loading genesis from a state dump file. Do not use this outside of genesis-tools");
         let mut state_file = home_dir.to_path_buf();
@@ -233,17 +393,20 @@ impl NightshadeRuntime {
         let num_shards = genesis.config.num_block_producer_seats_per_shard.len() as NumShards;
         let mut shard_account_ids: Vec<HashSet<AccountId>> =
             (0..num_shards).map(|_| HashSet::new()).collect();
-        let mut has_protocol_account = false;
+        let mut missing_protocol_accounts: HashSet<AccountId> =
+            genesis.config.protocol_treasury_accounts().into_iter().collect();
         genesis.for_each_record(|record: &StateRecord| {
             shard_account_ids[state_record_to_shard_id(record, num_shards) as usize]
                 .insert(state_record_to_account_id(record).clone());
             if let StateRecord::Account { account_id, .. } = record {
-                if account_id == &genesis.config.protocol_treasury_account {
-                    has_protocol_account = true;
-                }
+                missing_protocol_accounts.remove(account_id);
             }
         });
-        assert!(has_protocol_account, "Genesis spec doesn't have protocol treasury account");
+        assert!(
+            missing_protocol_accounts.is_empty(),
+            "Genesis spec doesn't have protocol treasury accounts {:?}",
+            missing_protocol_accounts
+        );
         let tries = ShardTries::new(store.clone(), num_shards);
         let runtime = Runtime::new();
         for shard_id in 0..num_shards {
@@ -365,8 +528,19 @@ impl NightshadeRuntime {
                 .collect();
 
             if epoch_manager.is_next_block_epoch_start(prev_block_hash)? {
-                let (stake_info, validator_reward, double_sign_slashing_info) =
+                let (stake_info, mut validator_reward, double_sign_slashing_info) =
                     epoch_manager.compute_stake_return_info(prev_block_hash)?;
+                // The developer reward is kept under the system account. It is split evenly
+                // between the shards and the first shard gets the rounding.
+                let developer_reward =
+                    validator_reward.remove(&system_account()).map(|developer_reward| {
+                        let num_shards = self.num_shards() as Balance;
+                        let mut shard_reward = developer_reward / num_shards;
+                        if shard_id == 0 {
+                            shard_reward += developer_reward % num_shards;
+                        }
+                        shard_reward
+                    });
                 let stake_info = stake_info
                     .into_iter()
                     .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
@@ -388,23 +562,39 @@ impl NightshadeRuntime {
                     .map(|(account_id, stake)| (account_id, Some(stake)))
                     .collect();
                 slashing_info.extend(double_sign_slashing_info);
+                #[cfg(feature = "protocol_feature_slashing_escrow")]
+                let slash_appeals = epoch_manager
+                    .compute_slash_appeals(prev_block_hash)?
+                    .into_iter()
+                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .collect();
+                #[cfg(not(feature = "protocol_feature_slashing_escrow"))]
+                let slash_appeals = HashMap::default();
                 Some(ValidatorAccountsUpdate {
                     stake_info,
                     validator_rewards,
                     last_proposals,
-                    protocol_treasury_account_id: Some(
-                        self.genesis_config.protocol_treasury_account.clone(),
-                    )
-                    .filter(|account_id| self.account_id_to_shard_id(account_id) == shard_id),
+                    protocol_treasury_account_ids: self
+                        .genesis_config
+                        .protocol_treasury_accounts()
+                        .into_iter()
+                        .filter(|account_id| self.account_id_to_shard_id(account_id) == shard_id)
+                        .collect(),
                     slashing_info,
+                    slash_appeals,
+                    slashing_escrow_epochs: self.genesis_config.slashing_escrow_epochs,
+                    developer_reward,
                 })
             } else if !challenges_result.is_empty() {
                 Some(ValidatorAccountsUpdate {
                     stake_info: Default::default(),
                     validator_rewards: Default::default(),
                     last_proposals: Default::default(),
-                    protocol_treasury_account_id: None,
+                    protocol_treasury_account_ids: vec![],
                     slashing_info,
+                    slash_appeals: Default::default(),
+                    slashing_escrow_epochs: self.genesis_config.slashing_escrow_epochs,
+                    developer_reward: None,
                 })
             } else {
                 None
@@ -413,10 +603,46 @@ impl NightshadeRuntime {
 
         let epoch_height = self.get_epoch_height_from_prev_block(prev_block_hash)?;
         let epoch_id = self.get_epoch_id_from_prev_block(prev_block_hash)?;
+        let epoch_random_seed = self.get_epoch_random_seed_from_prev_block(prev_block_hash)?;
         let prev_block_epoch_id = self.get_epoch_id(prev_block_hash)?;
-        let current_protocol_version = self.get_epoch_protocol_version(&epoch_id)?;
+        let epoch_protocol_version = self.get_epoch_protocol_version(&epoch_id)?;
         let prev_block_protocol_version = self.get_epoch_protocol_version(&prev_block_epoch_id)?;
-        let is_first_block_of_version = current_protocol_version != prev_block_protocol_version;
+        let is_first_block_of_version = epoch_protocol_version != prev_block_protocol_version;
+        let current_protocol_version =
+            self.protocol_version_override.unwrap_or(epoch_protocol_version);
+        // Outgoing receipts are routed to the shards of the epoch of the chunk.
+        let layout = self.get_shard_layout(&epoch_id)?;
+        // The maintenance calls scheduled at this height for the accounts of the shard go ahead of
+        // the incoming receipts. They are skipped if the chunk of the shard is missing.
+        #[cfg(feature = "protocol_feature_system_calls")]
+        let mut system_call_receipts = match self.migration_data.system_calls.get(&block_height) {
+            Some(system_calls)
+                if is_new_chunk
+                    && near_primitives::checked_feature!(
+                        "protocol_feature_system_calls",
+                        SystemCalls,
+                        current_protocol_version
+                    ) =>
+            {
+                system_calls
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, system_call)| {
+                        shard_layout::account_id_to_shard_id(&system_call.receiver_id, &layout)
+                            == shard_id
+                    })
+                    .map(|(index, system_call)| system_call.to_receipt(block_height, index))
+                    .collect::<Vec<_>>()
+            }
+            _ => vec![],
+        };
+        #[cfg(feature = "protocol_feature_system_calls")]
+        let receipts = if system_call_receipts.is_empty() {
+            receipts
+        } else {
+            system_call_receipts.extend_from_slice(receipts);
+            system_call_receipts.as_slice()
+        };
 
         let apply_state = ApplyState {
             block_index: block_height,
@@ -428,6 +654,7 @@ impl NightshadeRuntime {
             block_timestamp,
             gas_limit: Some(gas_limit),
             random_seed,
+            epoch_random_seed,
             current_protocol_version,
             config: self.runtime_config.for_protocol_version(current_protocol_version).clone(),
             cache: Some(Arc::new(StoreCompiledContractCache { store: self.store.clone() })),
@@ -440,6 +667,7 @@ impl NightshadeRuntime {
                 is_first_block_of_version,
                 is_first_block_with_chunk_of_version,
             },
+            observer: self.apply_observer.clone(),
         };
 
         let apply_result = self
@@ -468,6 +696,8 @@ impl NightshadeRuntime {
                 RuntimeError::ValidatorError(e) => e.into(),
             })?;
 
+        self.record_contract_stats(&apply_result.stats.contract_stats)?;
+
         let total_gas_burnt =
             apply_result.outcomes.iter().map(|tx_result| tx_result.outcome.gas_burnt).sum();
         let total_balance_burnt = apply_result
@@ -483,7 +713,7 @@ impl NightshadeRuntime {
         let mut receipt_result = HashMap::default();
         for receipt in apply_result.outgoing_receipts {
             receipt_result
-                .entry(self.account_id_to_shard_id(&receipt.receiver_id))
+                .entry(shard_layout::account_id_to_shard_id(&receipt.receiver_id, &layout))
                 .or_insert_with(|| vec![])
                 .push(receipt);
         }
@@ -636,16 +866,34 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn get_access_key_nonce(
+        &self,
+        shard_id: ShardId,
+        state_root: StateRoot,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<Option<Nonce>, Error> {
+        match self.view_access_key(shard_id, state_root, account_id, public_key) {
+            Ok(access_key) => Ok(Some(access_key.nonce)),
+            Err(ViewAccessKeyError::InternalError { error_message }) => {
+                Err(ErrorKind::Other(error_message).into())
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
     fn prepare_transactions(
         &self,
         gas_price: Balance,
         gas_limit: Gas,
+        transactions_size_limit: u64,
         shard_id: ShardId,
         state_root: StateRoot,
         next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
-        chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         current_protocol_version: ProtocolVersion,
+        dropped_transactions: &mut Vec<(SignedTransaction, InvalidTxError)>,
     ) -> Result<Vec<SignedTransaction>, Error> {
         let mut state_update = self.get_tries().new_trie_update(shard_id, state_root);
 
@@ -653,17 +901,30 @@ impl RuntimeAdapter for NightshadeRuntime {
         let mut total_gas_burnt = 0;
         // TODO: Update gas limit for transactions
         let transactions_gas_limit = gas_limit / 2;
+        // Total size of the transactions added to the chunk.
+        let mut total_size = 0;
         let mut transactions = vec![];
         let mut num_checked_transactions = 0;
 
         let runtime_config = self.runtime_config.for_protocol_version(current_protocol_version);
 
-        while total_gas_burnt < transactions_gas_limit {
+        'pool: while total_gas_burnt < transactions_gas_limit {
             if let Some(iter) = pool_iterator.next() {
-                while let Some(tx) = iter.next() {
+                loop {
+                    match iter.peek() {
+                        Some(tx) if total_size + tx.get_size() > transactions_size_limit => {
+                            // Keep the transaction in the pool for the next chunks.
+                            break 'pool;
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                    let tx = iter.next().expect("just peeked");
                     num_checked_transactions += 1;
                     // Verifying the transaction is on the same chain and hasn't expired yet.
-                    if chain_validate(&tx) {
+                    if let Err(err) = chain_validate(&tx) {
+                        dropped_transactions.push((tx, err));
+                    } else {
                         // Verifying the validity of the transaction based on the current state.
                         match verify_and_charge_transaction(
                             runtime_config,
@@ -676,12 +937,14 @@ impl RuntimeAdapter for NightshadeRuntime {
                         ) {
                             Ok(verification_result) => {
                                 state_update.commit(StateChangeCause::NotWritableToDisk);
+                                total_size += tx.get_size();
                                 transactions.push(tx);
                                 total_gas_burnt += verification_result.gas_burnt;
                                 break;
                             }
-                            Err(RuntimeError::InvalidTxError(_err)) => {
+                            Err(RuntimeError::InvalidTxError(err)) => {
                                 state_update.rollback();
+                                dropped_transactions.push((tx, err));
                             }
                             Err(RuntimeError::StorageError(err)) => {
                                 return Err(Error::from(ErrorKind::StorageError(err)))
@@ -1060,13 +1323,14 @@ impl RuntimeAdapter for NightshadeRuntime {
             cur_epoch_info,
             next_epoch_info,
         ) = self.get_epoch_sync_data(prev_epoch_last_block_hash, epoch_id, next_epoch_id)?;
-        let mut data = prev_epoch_first_block_info.try_to_vec().unwrap();
-        data.extend(prev_epoch_prev_last_block_info.try_to_vec().unwrap());
-        data.extend(prev_epoch_last_block_info.try_to_vec().unwrap());
-        data.extend(prev_epoch_info.try_to_vec().unwrap());
-        data.extend(cur_epoch_info.try_to_vec().unwrap());
-        data.extend(next_epoch_info.try_to_vec().unwrap());
-        Ok(hash(data.as_slice()))
+        Ok(compute_epoch_sync_data_hash(
+            &prev_epoch_first_block_info,
+            &prev_epoch_prev_last_block_info,
+            &prev_epoch_last_block_info,
+            &prev_epoch_info,
+            &cur_epoch_info,
+            &next_epoch_info,
+        ))
     }
 
     // TODO #3488 this likely to be updated
@@ -1134,9 +1398,14 @@ impl RuntimeAdapter for NightshadeRuntime {
                     && block_header_info.slashed_validators.is_empty())
         );
         debug!(target: "runtime", "add validator proposals at block height {} {:?}", block_header_info.height, block_header_info.proposals);
+        #[cfg(feature = "protocol_feature_epoch_seed_from_vrf")]
+        let random_values = self.get_epoch_seed_inputs(&block_header_info)?;
+        #[cfg(not(feature = "protocol_feature_epoch_seed_from_vrf"))]
+        let random_values = vec![block_header_info.random_value];
         // Deal with validator proposals and epoch finishing.
         let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
-        let block_info = BlockInfo::new(
+        #[allow(unused_mut)]
+        let mut block_info = BlockInfo::new(
             block_header_info.hash,
             block_header_info.height,
             block_header_info.last_finalized_height,
@@ -1149,8 +1418,11 @@ impl RuntimeAdapter for NightshadeRuntime {
             block_header_info.latest_protocol_version,
             block_header_info.timestamp_nanosec,
         );
-        let rng_seed = block_header_info.random_value.0;
-        epoch_manager.record_block_info(block_info, rng_seed).map_err(|err| err.into())
+        #[cfg(feature = "protocol_feature_slashing_escrow")]
+        for (account_id, challenge_block_hash) in block_header_info.appealed_validators {
+            block_info.slashed_mut().insert(account_id, SlashState::Appealed(challenge_block_hash));
+        }
+        epoch_manager.record_block_info(block_info, &random_values).map_err(|err| err.into())
     }
 
     fn apply_transactions_with_optional_storage_proof(
@@ -1321,9 +1593,17 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
-            QueryRequest::ViewState { account_id, prefix } => {
+            QueryRequest::ViewState { account_id, prefix, start_key, limit, include_proof } => {
                 let view_state_result = self
-                    .view_state(shard_id, *state_root, account_id, prefix.as_ref())
+                    .view_state(
+                        shard_id,
+                        *state_root,
+                        account_id,
+                        prefix.as_ref(),
+                        start_key.as_deref(),
+                        *limit,
+                        *include_proof,
+                    )
                     .map_err(|err| {
                         near_chain::near_chain_primitives::error::QueryError::from_view_state_error(
                             err,
@@ -1337,6 +1617,20 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
+            QueryRequest::ViewBurnLedger { .. } => {
+                let burn_ledger = self.view_burn_ledger(shard_id, *state_root).map_err(|err| {
+                    near_chain::near_chain_primitives::error::QueryError::InternalError {
+                        error_message: err.to_string(),
+                        block_height,
+                        block_hash: *block_hash,
+                    }
+                })?;
+                Ok(QueryResponse {
+                    kind: QueryResponseKind::BurnLedger(burn_ledger.into()),
+                    block_height,
+                    block_hash: *block_hash,
+                })
+            }
             QueryRequest::ViewAccessKeyList { account_id } => {
                 let access_key_list =
                     self.view_access_keys(shard_id, *state_root, account_id).map_err(|err| {
@@ -1387,6 +1681,24 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_validator_info(epoch_id).map_err(|e| e.into())
     }
 
+    fn get_validator_diff(&self, epoch_id: &EpochId) -> Result<EpochValidatorDiffView, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager.get_validator_diff(epoch_id).map_err(|e| e.into())
+    }
+
+    fn get_seat_prices(&self, block_hash: &CryptoHash) -> Result<SeatPriceView, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        let epoch_id = epoch_manager.get_epoch_id(block_hash)?;
+        let next_epoch_id = epoch_manager.get_next_epoch_id(block_hash)?;
+        Ok(SeatPriceView {
+            seat_price: epoch_manager.get_seat_price(&epoch_id)?,
+            next_seat_price: epoch_manager.get_seat_price(&next_epoch_id)?,
+            minimum_stake_to_join: epoch_manager.minimum_stake_to_join(block_hash)?,
+            epoch_id,
+            next_epoch_id,
+        })
+    }
+
     /// Returns StorageError when storage is inconsistent.
     /// This is possible with the used isolation level + running ViewClient in a separate thread
     fn obtain_state_part(
@@ -1559,6 +1871,54 @@ impl RuntimeAdapter for NightshadeRuntime {
             epoch_manager.get_prev_epoch_id(prev_block_hash).map_err(Error::from)
         }
     }
+
+    fn get_contract_execution_stats(
+        &self,
+        code_hash: &CryptoHash,
+    ) -> Result<Option<ContractExecutionStats>, Error> {
+        let pending = self.pending_contract_stats.lock().expect(POISONED_LOCK_ERR);
+        let stored_stats: Option<ContractExecutionStats> =
+            self.store.get_ser(ColContractExecutionStats, code_hash.as_ref())?;
+        Ok(match (stored_stats, pending.stats.get(code_hash)) {
+            (Some(mut stats), Some(pending_stats)) => {
+                stats.merge(pending_stats);
+                Some(stats)
+            }
+            (stats, pending_stats) => stats.or_else(|| pending_stats.cloned()),
+        })
+    }
+
+    fn compute_total_supply(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        receipts: &[Receipt],
+    ) -> Result<Balance, Error> {
+        let protocol_version = self.get_epoch_protocol_version(epoch_id)?;
+        let transaction_costs =
+            &self.runtime_config.for_protocol_version(protocol_version).transaction_costs;
+        let supply = compute_total_supply(
+            &self.tries,
+            shard_id,
+            state_root,
+            transaction_costs,
+            protocol_version,
+        )
+        .map_err(|e| ErrorKind::Other(format!("Failed to compute the total supply: {:?}", e)))?;
+        supply
+            .total()
+            .and_then(|supply| {
+                safe_add_balance(
+                    supply,
+                    receipts_cost(transaction_costs, receipts, protocol_version)?,
+                )
+            })
+            .map_err(|_| {
+                ErrorKind::Other("Integer overflow during total supply summation".to_string())
+                    .into()
+            })
+    }
 }
 
 impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
@@ -1652,9 +2012,28 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         state_root: MerkleHash,
         account_id: &AccountId,
         prefix: &[u8],
+        start_key: Option<&[u8]>,
+        limit: Option<u64>,
+        include_proof: bool,
     ) -> Result<ViewStateResult, node_runtime::state_viewer::errors::ViewStateError> {
         let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
-        self.trie_viewer.view_state(&state_update, account_id, prefix)
+        self.trie_viewer.view_state(
+            &state_update,
+            account_id,
+            prefix,
+            start_key,
+            limit,
+            include_proof,
+        )
+    }
+
+    fn view_burn_ledger(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+    ) -> Result<BurnLedger, StorageError> {
+        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        self.trie_viewer.view_burn_ledger(&state_update)
     }
 }
 
@@ -1808,6 +2187,7 @@ mod test {
                     last_finalized_block_hash: CryptoHash::default(),
                     proposals: vec![],
                     slashed_validators: vec![],
+                    appealed_validators: vec![],
                     chunk_mask: vec![],
                     total_supply: genesis_total_supply,
                     latest_protocol_version: genesis_protocol_version,
@@ -1880,6 +2260,7 @@ mod test {
                     last_finalized_block_hash: self.head.last_block_hash,
                     proposals: self.last_proposals.clone(),
                     slashed_validators: challenges_result,
+                    appealed_validators: vec![],
                     chunk_mask,
                     total_supply: self.runtime.genesis_config.total_supply,
                     latest_protocol_version: self.runtime.genesis_config.protocol_version,
@@ -2298,6 +2679,7 @@ mod test {
                     last_finalized_block_hash: prev_hash,
                     proposals: new_env.last_proposals,
                     slashed_validators: vec![],
+                    appealed_validators: vec![],
                     chunk_mask: vec![true],
                     total_supply: new_env.runtime.genesis_config.total_supply,
                     latest_protocol_version: new_env.runtime.genesis_config.protocol_version,
@@ -3063,4 +3445,46 @@ mod test {
         assert_eq!(env.last_proposals.len(), 1);
         assert_eq!(env.last_proposals[0].stake(), 0);
     }
+
+    #[test]
+    fn test_contract_execution_stats() {
+        let env = TestEnv::new(
+            "test_contract_execution_stats",
+            vec![vec!["test1".to_string()]],
+            4,
+            vec![],
+            vec![],
+            false,
+        );
+        let code_hash = hash(&[1]);
+        let mut contract_stats = ContractStats::default();
+        contract_stats.entry(code_hash).or_default().record_call(
+            100,
+            Duration::from_millis(1),
+            Some("WasmTrap"),
+        );
+        let stored_stats = |runtime: &NightshadeRuntime| {
+            runtime
+                .store
+                .get_ser::<ContractExecutionStats>(ColContractExecutionStats, code_hash.as_ref())
+                .unwrap()
+        };
+
+        // The statistics are written to the store periodically, but can be queried right away.
+        env.runtime.record_contract_stats(&contract_stats).unwrap();
+        assert_eq!(stored_stats(&env.runtime), None);
+        let stats = env.runtime.get_contract_execution_stats(&code_hash).unwrap().unwrap();
+        assert_eq!(stats.calls, 1);
+        for _ in 1..CONTRACT_STATS_FLUSH_PERIOD {
+            env.runtime.record_contract_stats(&contract_stats).unwrap();
+        }
+        assert_eq!(stored_stats(&env.runtime).unwrap().calls, CONTRACT_STATS_FLUSH_PERIOD);
+
+        env.runtime.record_contract_stats(&contract_stats).unwrap();
+        let stats = env.runtime.get_contract_execution_stats(&code_hash).unwrap().unwrap();
+        assert_eq!(stats.calls, CONTRACT_STATS_FLUSH_PERIOD + 1);
+        assert_eq!(stats.failures["WasmTrap"], CONTRACT_STATS_FLUSH_PERIOD + 1);
+        assert_eq!(stats.average_gas_burnt(), 100);
+        assert!(env.runtime.get_contract_execution_stats(&hash(&[2])).unwrap().is_none());
+    }
 }
@@ -1,25 +1,25 @@
 use std::collections::{HashMap, HashSet};
-use std::io::Cursor;
 use std::sync::{Arc, RwLock};
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use tracing::info;
 
+use near_chain_configs::ShardTracking;
 use near_epoch_manager::EpochManager;
 use near_primitives::errors::EpochError;
-use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::{self, ShardLayout};
 use near_primitives::types::{AccountId, EpochId, NumShards, ShardId};
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
 pub fn account_id_to_shard_id(account_id: &AccountId, num_shards: NumShards) -> ShardId {
-    let mut cursor = Cursor::new(hash(&account_id.clone().into_bytes()).0);
-    cursor.read_u64::<LittleEndian>().expect("Must not happened") % (num_shards)
+    shard_layout::account_id_to_shard_id(account_id, &ShardLayout::v0(num_shards, 0))
 }
 
 /// Tracker that tracks shard ids and accounts. It maintains two items: `tracked_accounts` and
 /// `tracked_shards`. The shards that are actually tracked are the union of shards that `tracked_accounts`
-/// are in and `tracked_shards`.
+/// are in and `tracked_shards`, unless `shard_tracking` is set to track all or only the shards
+/// assigned by the epoch manager.
 #[derive(Clone)]
 pub struct ShardTracker {
     /// Tracked accounts by shard id. For each shard id, the corresponding set of accounts should be
@@ -39,6 +39,8 @@ pub struct ShardTracker {
     epoch_manager: Arc<RwLock<EpochManager>>,
     /// Number of shards in the system.
     num_shards: NumShards,
+    /// Whether all shards, the tracked ones or none of them are tracked besides the assigned ones.
+    shard_tracking: ShardTracking,
 }
 
 impl ShardTracker {
@@ -69,6 +71,27 @@ impl ShardTracker {
             current_epoch_id: epoch_id,
             epoch_manager,
             num_shards,
+            shard_tracking: ShardTracking::Subset,
+        }
+    }
+
+    pub fn set_shard_tracking(&mut self, shard_tracking: ShardTracking) {
+        match shard_tracking {
+            ShardTracking::All => info!(target: "runtime", "Tracking all shards"),
+            ShardTracking::Subset => {
+                info!(target: "runtime", "Tracking shards: {:?}", self.actual_tracked_shards)
+            }
+            ShardTracking::Own => info!(target: "runtime", "Tracking only the assigned shards"),
+        }
+        self.shard_tracking = shard_tracking;
+    }
+
+    /// Whether the shard is tracked regardless of the shards assigned by the epoch manager.
+    fn tracks_shard(&self, shard_id: ShardId) -> bool {
+        match self.shard_tracking {
+            ShardTracking::All => shard_id < self.num_shards,
+            ShardTracking::Subset => self.actual_tracked_shards.contains(&shard_id),
+            ShardTracking::Own => false,
         }
     }
 
@@ -193,9 +216,9 @@ impl ShardTracker {
             if !is_me {
                 return account_cares_about_shard;
             }
-            account_cares_about_shard || self.actual_tracked_shards.contains(&shard_id)
+            account_cares_about_shard || self.tracks_shard(shard_id)
         } else {
-            self.actual_tracked_shards.contains(&shard_id)
+            self.tracks_shard(shard_id)
         }
     }
 
@@ -221,7 +244,7 @@ impl ShardTracker {
         }
         let mut tracker = self.clone();
         tracker.flush_pending();
-        tracker.actual_tracked_shards.contains(&shard_id)
+        tracker.tracks_shard(shard_id)
     }
 }
 
@@ -233,13 +256,13 @@ mod tests {
     use near_crypto::{KeyType, PublicKey};
     use near_epoch_manager::{EpochManager, RewardCalculator};
     use near_primitives::epoch_manager::block_info::BlockInfo;
-    use near_primitives::epoch_manager::EpochConfig;
+    use near_primitives::epoch_manager::{EpochConfig, StakeDenomination};
     use near_primitives::hash::{hash, CryptoHash};
     use near_primitives::types::validator_stake::ValidatorStake;
     use near_primitives::types::{BlockHeight, EpochId, NumShards};
     use near_store::test_utils::create_test_store;
 
-    use super::{account_id_to_shard_id, ShardTracker, POISONED_LOCK_ERR};
+    use super::{account_id_to_shard_id, ShardTracker, ShardTracking, POISONED_LOCK_ERR};
     use near_primitives::version::PROTOCOL_VERSION;
     use num_rational::Rational;
 
@@ -261,6 +284,9 @@ mod tests {
             minimum_stake_divisor: 1,
             protocol_upgrade_stake_threshold: Rational::new(80, 100),
             protocol_upgrade_num_epochs: 2,
+            min_epoch_duration_nanosec: 0,
+            resharding_shard_layout: None,
+            stake_denomination: StakeDenomination::Native,
         };
         let reward_calculator = RewardCalculator {
             max_inflation_rate: Rational::from_integer(0),
@@ -268,6 +294,8 @@ mod tests {
             epoch_length: 1,
             protocol_reward_rate: Rational::from_integer(0),
             protocol_treasury_account: "".to_string(),
+            protocol_treasury_split: vec![],
+            developer_reward_share: None,
             online_max_threshold: initial_epoch_config.online_max_threshold,
             online_min_threshold: initial_epoch_config.online_min_threshold,
             num_seconds_per_year: 1000000,
@@ -310,7 +338,7 @@ mod tests {
                     PROTOCOL_VERSION,
                     height * 10u64.pow(9),
                 ),
-                [0; 32],
+                &[],
             )
             .unwrap()
             .commit()
@@ -395,4 +423,26 @@ mod tests {
 
         assert_eq!(tracker.actual_tracked_shards, total_tracked_shards);
     }
+
+    #[test]
+    fn test_shard_tracking() {
+        let num_shards = 4;
+        let epoch_manager = get_epoch_manager(num_shards);
+        let mut tracker =
+            ShardTracker::new(vec![], vec![2], EpochId::default(), epoch_manager, num_shards);
+        let parent_hash = CryptoHash::default();
+        let tracked_shards = |tracker: &ShardTracker| {
+            (0..num_shards)
+                .filter(|shard_id| {
+                    tracker.care_about_shard(None, &parent_hash, *shard_id, true)
+                        && tracker.will_care_about_shard(None, &parent_hash, *shard_id, true)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tracked_shards(&tracker), vec![2]);
+        tracker.set_shard_tracking(ShardTracking::All);
+        assert_eq!(tracked_shards(&tracker), vec![0, 1, 2, 3]);
+        tracker.set_shard_tracking(ShardTracking::Own);
+        assert_eq!(tracked_shards(&tracker), Vec::<u64>::new());
+    }
 }
@@ -49,6 +49,7 @@ fn add_blocks(
             *blocks[(((prev.header().height()) / epoch_length) * epoch_length) as usize].hash(),
         );
         let block = Block::produce(
+            PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             &prev.header(),
             prev.header().height() + 1,
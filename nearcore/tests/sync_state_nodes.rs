@@ -9,6 +9,8 @@ use near_chain_configs::Genesis;
 use near_client::GetBlock;
 use near_logger_utils::init_integration_logger;
 use near_network::test_utils::{convert_boot_nodes, open_port, WaitOrTimeout};
+#[cfg(feature = "protocol_feature_block_header_v3")]
+use near_primitives::types::{BlockId, BlockReference};
 use nearcore::{config::GenesisExt, load_test_config, start_with_config};
 use testlib::test_helpers::heavy_test;
 
@@ -99,6 +101,111 @@ fn sync_state_nodes() {
     });
 }
 
+/// A fresh node epoch syncs to a running chain several epochs ahead, then syncs the state at the
+/// first block of the current epoch, without downloading the blocks before it.
+#[cfg(feature = "protocol_feature_block_header_v3")]
+#[test]
+fn sync_state_nodes_after_epoch_sync() {
+    heavy_test(|| {
+        init_integration_logger();
+
+        let mut genesis = Genesis::test(vec!["test1"], 1);
+        genesis.config.epoch_length = 20;
+
+        let (port1, port2) = (open_port(), open_port());
+        let mut near1 = load_test_config("test1", port1, genesis.clone());
+        near1.network_config.boot_nodes = convert_boot_nodes(vec![]);
+        near1.client_config.min_num_peers = 0;
+        near1.client_config.min_block_production_delay = Duration::from_millis(200);
+        near1.client_config.max_block_production_delay = Duration::from_millis(400);
+        run_actix(async move {
+            let dir1 = tempfile::Builder::new().prefix("epoch_sync_nodes_1").tempdir().unwrap();
+            let (_, view_client1, _) = start_with_config(dir1.path(), near1);
+
+            let view_client2_holder = Arc::new(RwLock::new(None));
+            let arbiters_holder = Arc::new(RwLock::new(vec![]));
+            let arbiters_holder2 = arbiters_holder.clone();
+
+            WaitOrTimeout::new(
+                Box::new(move |_ctx| {
+                    if view_client2_holder.read().unwrap().is_none() {
+                        let view_client2_holder2 = view_client2_holder.clone();
+                        let arbiters_holder2 = arbiters_holder2.clone();
+                        let genesis2 = genesis.clone();
+
+                        actix::spawn(view_client1.send(GetBlock::latest()).then(move |res| {
+                            match &res {
+                                Ok(Ok(b)) if b.header.height >= 101 => {
+                                    let mut view_client2_holder2 =
+                                        view_client2_holder2.write().unwrap();
+                                    let mut arbiters_holder2 = arbiters_holder2.write().unwrap();
+
+                                    if view_client2_holder2.is_none() {
+                                        let mut near2 =
+                                            load_test_config("test2", port2, genesis2.clone());
+                                        near2.client_config.skip_sync_wait = false;
+                                        near2.client_config.min_num_peers = 1;
+                                        near2.network_config.boot_nodes =
+                                            convert_boot_nodes(vec![("test1", port1)]);
+                                        near2.client_config.epoch_sync_enabled = true;
+
+                                        let dir2 = tempfile::Builder::new()
+                                            .prefix("epoch_sync_nodes_2")
+                                            .tempdir()
+                                            .unwrap();
+                                        let (_, view_client2, arbiters) =
+                                            start_with_config(dir2.path(), near2);
+                                        *view_client2_holder2 = Some(view_client2);
+                                        *arbiters_holder2 = arbiters;
+                                    }
+                                }
+                                Ok(Ok(b)) if b.header.height < 101 => {
+                                    println!("FIRST STAGE {}", b.header.height)
+                                }
+                                Err(_) => return future::ready(()),
+                                _ => {}
+                            };
+                            future::ready(())
+                        }));
+                    }
+
+                    if let Some(view_client2) = &*view_client2_holder.write().unwrap() {
+                        let view_client2 = view_client2.clone();
+                        actix::spawn(view_client2.send(GetBlock::latest()).then(move |res| {
+                            match &res {
+                                Ok(Ok(b)) if b.header.height >= 101 => {
+                                    // The blocks of the epochs skipped by epoch sync are not
+                                    // available.
+                                    actix::spawn(
+                                        view_client2
+                                            .send(GetBlock(BlockReference::BlockId(
+                                                BlockId::Height(1),
+                                            )))
+                                            .then(|res| {
+                                                assert!(matches!(res, Ok(Err(_))));
+                                                System::current().stop();
+                                                future::ready(())
+                                            }),
+                                    );
+                                }
+                                Ok(Ok(b)) if b.header.height < 101 => {
+                                    println!("SECOND STAGE {}", b.header.height)
+                                }
+                                Err(_) => return future::ready(()),
+                                _ => {}
+                            };
+                            future::ready(())
+                        }));
+                    }
+                }),
+                100,
+                60000,
+            )
+            .start();
+        });
+    });
+}
+
 /// One client is in front, another must sync to it using state (fast) sync.
 #[test]
 fn sync_state_nodes_multishard() {
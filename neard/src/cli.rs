@@ -2,14 +2,14 @@ use super::{DEFAULT_HOME, NEARD_VERSION, NEARD_VERSION_STRING, PROTOCOL_VERSION}
 use clap::{AppSettings, Clap};
 use futures::future::FutureExt;
 use near_primitives::types::{Gas, NumSeats, NumShards};
+use near_store::db::{DBCol, RocksDB};
 use nearcore::get_store_path;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::{env, fs, io};
-#[cfg(feature = "adversarial")]
-use tracing::error;
-use tracing::info;
+use strum::IntoEnumIterator;
 use tracing::metadata::LevelFilter;
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 /// NEAR Protocol Node
@@ -46,6 +46,12 @@ impl NeardCmd {
             NeardSubCommand::Init(cmd) => cmd.run(&home_dir),
             NeardSubCommand::Testnet(cmd) => cmd.run(&home_dir),
             NeardSubCommand::Run(cmd) => cmd.run(&home_dir),
+            NeardSubCommand::Database(cmd) => {
+                if let Err(err) = cmd.run(&home_dir) {
+                    error!(target: "neard", "{}", err);
+                    std::process::exit(1);
+                }
+            }
 
             NeardSubCommand::UnsafeResetData => {
                 let store_path = get_store_path(&home_dir);
@@ -96,6 +102,10 @@ pub(super) enum NeardSubCommand {
     /// config)
     #[clap(name = "unsafe_reset_data")]
     UnsafeResetData,
+    /// Inspects and maintains the node database. The node must be stopped while running any of
+    /// the subcommands except `analyze`.
+    #[clap(name = "database")]
+    Database(DatabaseCmd),
 }
 
 #[derive(Clap)]
@@ -260,6 +270,18 @@ impl RunCmd {
             near_config.client_config.max_gas_burnt_view = self.max_gas_burnt_view;
         }
 
+        match nearcore::config::load_validator_set_override(home_dir, &near_config.genesis.config) {
+            Ok(Some(validator_set_override)) => {
+                warn!(target: "neard", "The validator set is overridden at height {}", validator_set_override.height);
+                near_config.validator_set_override = Some(validator_set_override);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                error!(target: "neard", "{}", err);
+                std::process::exit(1);
+            }
+        }
+
         #[cfg(feature = "sandbox")]
         {
             if near_config.client_config.chain_id == "mainnet"
@@ -292,7 +314,12 @@ impl RunCmd {
             info!(target: "neard", "Got {}, stopping", sig);
             actix::System::current().stop();
         });
-        sys.run().unwrap();
+        // The node stops with a non-zero code when it can't join the network, e.g. because of a
+        // different genesis, after the reason has been logged.
+        if let Err(err) = sys.run() {
+            error!(target: "neard", "{}", err);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -325,6 +352,176 @@ impl TestnetCmd {
     }
 }
 
+#[derive(Clap)]
+pub(super) struct DatabaseCmd {
+    #[clap(subcommand)]
+    subcmd: DatabaseSubCommand,
+}
+
+#[derive(Clap)]
+enum DatabaseSubCommand {
+    /// Prints estimated number of keys and SST file sizes for each column
+    #[clap(name = "analyze")]
+    Analyze,
+    /// Runs manual compaction of one column or of all columns
+    #[clap(name = "compact")]
+    Compact(CompactCmd),
+    /// Removes all data from a deprecated column
+    #[clap(name = "drop-column")]
+    DropColumn(DropColumnCmd),
+    /// Creates a point-in-time checkpoint of the database in the given directory
+    #[clap(name = "snapshot")]
+    Snapshot(SnapshotCmd),
+    /// Replaces the node database with a previously created checkpoint
+    #[clap(name = "restore-snapshot")]
+    RestoreSnapshot(RestoreSnapshotCmd),
+}
+
+#[derive(Clap)]
+struct CompactCmd {
+    /// Column to compact, given by its index (e.g. `5` or `col5`). All columns are compacted if
+    /// omitted.
+    #[clap(long, parse(try_from_str = parse_column))]
+    column: Option<DBCol>,
+}
+
+#[derive(Clap)]
+struct DropColumnCmd {
+    /// Column to drop, given by its index (e.g. `43` or `col43`).
+    #[clap(parse(try_from_str = parse_column))]
+    column: DBCol,
+}
+
+#[derive(Clap)]
+struct SnapshotCmd {
+    /// Directory to create the checkpoint in. Must not exist.
+    #[clap(parse(from_os_str))]
+    destination: PathBuf,
+}
+
+#[derive(Clap)]
+struct RestoreSnapshotCmd {
+    /// Directory with a checkpoint created by `snapshot`.
+    #[clap(parse(from_os_str))]
+    source: PathBuf,
+}
+
+fn parse_column(s: &str) -> Result<DBCol, String> {
+    let index: usize = s
+        .trim_start_matches("col")
+        .parse()
+        .map_err(|_| format!("expected column index, got `{}`", s))?;
+    DBCol::iter()
+        .find(|col| *col as usize == index)
+        .ok_or_else(|| format!("unknown column index {}", index))
+}
+
+impl DatabaseCmd {
+    pub(super) fn run(self, home_dir: &Path) -> Result<(), String> {
+        let store_path = get_store_path(home_dir);
+        let requires_compaction = matches!(
+            self.subcmd,
+            DatabaseSubCommand::Compact(_) | DatabaseSubCommand::DropColumn(_)
+        );
+        if requires_compaction && !RocksDB::can_compact() {
+            return Err("Manual compaction is not supported by this build".to_string());
+        }
+        let is_restore = matches!(self.subcmd, DatabaseSubCommand::RestoreSnapshot(_));
+        if !is_restore && !Path::new(&store_path).exists() {
+            return Err(format!("No database at {}", store_path));
+        }
+        match self.subcmd {
+            DatabaseSubCommand::Analyze => {
+                let db = RocksDB::new_read_only_all_columns(&store_path)
+                    .map_err(|err| format!("Failed to open the database: {}", err))?;
+                println!(
+                    "{:>6} {:>48} {:>14} {:>16} {:>16} {:>8}",
+                    "column", "description", "est. keys", "sst size", "live data", "L0 files"
+                );
+                for col in DBCol::iter() {
+                    let stats = db
+                        .column_stats(col)
+                        .map_err(|err| format!("Failed to read column statistics: {}", err))?;
+                    println!(
+                        "{:>6} {:>48} {:>14} {:>16} {:>16} {:>8}",
+                        format!("col{}", col as usize),
+                        col.to_string(),
+                        stats.estimate_num_keys,
+                        bytesize::ByteSize::b(stats.total_sst_files_size).to_string(),
+                        bytesize::ByteSize::b(stats.estimate_live_data_size).to_string(),
+                        stats.num_files_at_level0
+                    );
+                }
+            }
+            DatabaseSubCommand::Compact(cmd) => {
+                let db = RocksDB::new(&store_path)
+                    .map_err(|err| format!("Failed to open the database: {}", err))?;
+                let columns: Vec<DBCol> = match cmd.column {
+                    Some(col) => vec![col],
+                    None => DBCol::iter().collect(),
+                };
+                for col in columns {
+                    info!(target: "neard", "Compacting {} ({})", col, col as usize);
+                    db.compact(col);
+                }
+            }
+            DatabaseSubCommand::DropColumn(cmd) => {
+                if !cmd.column.is_deprecated() {
+                    return Err(format!(
+                        "Column {} ({}) is in use and cannot be dropped",
+                        cmd.column, cmd.column as usize
+                    ));
+                }
+                let store = near_store::create_store(&store_path);
+                let mut store_update = store.store_update();
+                store_update.delete_all(cmd.column);
+                store_update
+                    .commit()
+                    .map_err(|err| format!("Failed to drop the column: {}", err))?;
+                store.get_rocksdb().unwrap().compact(cmd.column);
+                info!(target: "neard", "Dropped {} ({})", cmd.column, cmd.column as usize);
+            }
+            DatabaseSubCommand::Snapshot(cmd) => {
+                let db = RocksDB::new(&store_path)
+                    .map_err(|err| format!("Failed to open the database: {}", err))?;
+                db.create_checkpoint(&cmd.destination)
+                    .map_err(|err| format!("Failed to create the checkpoint: {}", err))?;
+                info!(target: "neard", "Created checkpoint in {}", cmd.destination.display());
+            }
+            DatabaseSubCommand::RestoreSnapshot(cmd) => {
+                // Make sure the source is a valid database before touching the current one.
+                RocksDB::get_version(&cmd.source).map_err(|err| {
+                    format!("Failed to open the checkpoint {}: {}", cmd.source.display(), err)
+                })?;
+                let store_path = Path::new(&store_path);
+                if store_path.exists() {
+                    return Err(format!(
+                        "{} already exists, move it away or run `unsafe_reset_data` first",
+                        store_path.display()
+                    ));
+                }
+                copy_dir(&cmd.source, store_path)
+                    .map_err(|err| format!("Failed to copy the checkpoint: {}", err))?;
+                info!(target: "neard", "Restored {} from {}", store_path.display(), cmd.source.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), destination.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
 fn init_logging(verbose: Option<&str>) {
     let mut env_filter = EnvFilter::new(
         "tokio_reactor=info,near=info,stats=info,telemetry=info,delay_detector=info,\
@@ -383,6 +580,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn database_column_argument() {
+        let cmd = NeardCmd::parse_from(&["test", "database", "compact", "--column=col5"]);
+        if let NeardSubCommand::Database(DatabaseCmd {
+            subcmd: DatabaseSubCommand::Compact(scmd),
+        }) = cmd.subcmd
+        {
+            assert_eq!(scmd.column, Some(DBCol::ColState));
+        } else {
+            panic!("incorrect subcommand");
+        }
+        assert!(NeardCmd::try_parse_from(&["test", "database", "drop-column", "col1000"]).is_err());
+    }
+
     #[test]
     fn equal_no_value_syntax() {
         assert!(NeardCmd::try_parse_from(&[
@@ -53,6 +53,9 @@ pub struct VMContext {
     #[serde(with = "crate::serde_with::bytes_as_base58")]
     /// Initial seed for randomness
     pub random_seed: Vec<u8>,
+    #[serde(default, with = "crate::serde_with::bytes_as_base58")]
+    /// Seed for randomness which stays the same for the whole epoch.
+    pub epoch_random_seed: Vec<u8>,
     /// Whether the execution should not charge any costs.
     pub is_view: bool,
     /// How many `DataReceipt`'s should receive this execution result. This should be empty if
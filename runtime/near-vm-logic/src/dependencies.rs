@@ -47,6 +47,34 @@ pub trait ValuePtr {
     fn deref(&self) -> Result<Vec<u8>>;
 }
 
+/// Completion of a storage read which the embedder may still be fulfilling.
+pub trait StorageReadCompletion<'a> {
+    /// Parks the contract execution until the read completes.
+    /// # Errors
+    /// StorageError if reading from storage fails
+    fn wait(self: Box<Self>) -> Result<Option<Box<dyn ValuePtr + 'a>>>;
+}
+
+/// A storage read started by the contract. The wasm side stays synchronous: the execution is
+/// parked in `wait` until the embedder fulfils the read, so that it can overlap the storage IO
+/// with other work.
+pub enum StorageRead<'a> {
+    /// The value is already read.
+    Ready(Option<Box<dyn ValuePtr + 'a>>),
+    /// The read is still in flight.
+    Pending(Box<dyn StorageReadCompletion<'a> + 'a>),
+}
+
+impl<'a> StorageRead<'a> {
+    /// Returns the read value, parking the contract execution until a pending read completes.
+    pub fn wait(self) -> Result<Option<Box<dyn ValuePtr + 'a>>> {
+        match self {
+            StorageRead::Ready(value_ptr) => Ok(value_ptr),
+            StorageRead::Pending(completion) => completion.wait(),
+        }
+    }
+}
+
 /// An external blockchain interface for the Runtime logic
 pub trait External {
     /// Write to the storage trie of the current account
@@ -95,6 +123,32 @@ pub trait External {
     /// ```
     fn storage_get<'a>(&'a self, key: &[u8]) -> Result<Option<Box<dyn ValuePtr + 'a>>>;
 
+    /// Starts reading from the storage trie of the current account. The embedder may fulfil the
+    /// read asynchronously, the value is available once `StorageRead::wait` returns.
+    /// By default the value is read synchronously with `storage_get`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key to read
+    ///
+    /// # Errors
+    ///
+    /// This function could return HostErrorOrStorageError::StorageError on underlying DB failure
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::{MockedExternal};
+    /// # use near_vm_logic::{External, ValuePtr};
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// external.storage_set(b"key42", b"value1337").unwrap();
+    /// let read = external.storage_read_start(b"key42").unwrap();
+    /// assert_eq!(read.wait().unwrap().map(|ptr| ptr.deref().unwrap()), Some(b"value1337".to_vec()));
+    /// ```
+    fn storage_read_start<'a>(&'a self, key: &[u8]) -> Result<StorageRead<'a>> {
+        Ok(StorageRead::Ready(self.storage_get(key)?))
+    }
+
     /// Removes the key from the storage
     ///
     /// # Arguments
@@ -167,6 +221,51 @@ pub trait External {
     /// ```
     fn storage_has_key(&mut self, key: &[u8]) -> Result<bool>;
 
+    /// Attaches an extension record to the current account, replacing the previous one.
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::MockedExternal;
+    /// # use near_vm_logic::External;
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// assert_eq!(external.account_extension_set(b"recovery", b"alice.near"), Ok(()));
+    /// ```
+    fn account_extension_set(&mut self, extension_id: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Reads an extension record of the current account.
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::MockedExternal;
+    /// # use near_vm_logic::{External, ValuePtr};
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// external.account_extension_set(b"recovery", b"alice.near").unwrap();
+    /// assert_eq!(
+    ///     external.account_extension_get(b"recovery").unwrap().map(|ptr| ptr.deref().unwrap()),
+    ///     Some(b"alice.near".to_vec())
+    /// );
+    /// ```
+    fn account_extension_get<'a>(
+        &'a self,
+        extension_id: &[u8],
+    ) -> Result<Option<Box<dyn ValuePtr + 'a>>>;
+
+    /// Removes an extension record of the current account, if it exists.
+    ///
+    /// # Example
+    /// ```
+    /// # use near_vm_logic::mocks::mock_external::MockedExternal;
+    /// # use near_vm_logic::External;
+    ///
+    /// # let mut external = MockedExternal::new();
+    /// external.account_extension_set(b"recovery", b"alice.near").unwrap();
+    /// assert_eq!(external.account_extension_remove(b"recovery"), Ok(()));
+    /// assert_eq!(external.account_extension_remove(b"recovery"), Ok(()));
+    /// ```
+    fn account_extension_remove(&mut self, extension_id: &[u8]) -> Result<()>;
+
     /// Creates a receipt which will be executed after `receipt_indices`
     ///
     /// # Arguments
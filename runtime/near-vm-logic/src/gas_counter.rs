@@ -7,8 +7,10 @@ use near_primitives_core::{
     profile::ProfileData,
     types::Gas,
 };
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fmt;
+use std::time::Instant;
 
 #[cfg(feature = "protocol_feature_evm")]
 #[inline]
@@ -45,6 +47,25 @@ pub fn with_ext_cost_counter(f: impl FnOnce(&mut HashMap<ExtCosts, u64>)) {
     let _ = f;
 }
 
+thread_local! {
+    static VIEW_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// Runs `f` with a wall-clock deadline for view calls executed on the current thread.
+/// Once the deadline passes, view calls fail with `GasLimitExceeded` on the next gas charge.
+/// Function calls that are not views are never affected.
+pub fn with_view_deadline<T>(deadline: Option<Instant>, f: impl FnOnce() -> T) -> T {
+    let previous = VIEW_DEADLINE.with(|cell| cell.replace(deadline));
+    let result = f();
+    VIEW_DEADLINE.with(|cell| cell.set(previous));
+    result
+}
+
+#[inline]
+fn view_deadline_passed() -> bool {
+    VIEW_DEADLINE.with(|cell| cell.get()).map_or(false, |deadline| Instant::now() >= deadline)
+}
+
 type Result<T> = ::std::result::Result<T, VMLogicError>;
 
 /// Gas counter (a part of VMlogic)
@@ -92,6 +113,9 @@ impl GasCounter {
         let new_burnt_gas =
             self.burnt_gas.checked_add(burn_gas).ok_or(HostError::IntegerOverflow)?;
         let new_used_gas = self.used_gas.checked_add(use_gas).ok_or(HostError::IntegerOverflow)?;
+        if self.is_view && view_deadline_passed() {
+            return Err(HostError::GasLimitExceeded.into());
+        }
         if new_burnt_gas <= self.max_gas_burnt && (self.is_view || new_used_gas <= self.prepaid_gas)
         {
             self.burnt_gas = new_burnt_gas;
@@ -253,4 +277,21 @@ mod tests {
             GasCounter::new(ExtCostsConfig::default(), 100, 10, false, ProfileData::new());
         counter.deduct_gas(10, 5).unwrap();
     }
+
+    #[test]
+    fn test_view_deadline() {
+        let deadline = Some(Instant::now());
+        let mut view_counter =
+            GasCounter::new(ExtCostsConfig::default(), 100, 0, true, ProfileData::new());
+        let mut counter =
+            GasCounter::new(ExtCostsConfig::default(), 100, 100, false, ProfileData::new());
+        with_view_deadline(deadline, || {
+            assert_eq!(
+                view_counter.deduct_gas(1, 1),
+                Err(VMLogicError::HostError(HostError::GasLimitExceeded))
+            );
+            counter.deduct_gas(1, 1).expect("only views have a deadline");
+        });
+        view_counter.deduct_gas(1, 1).expect("deadline is reset after the call");
+    }
 }
@@ -10,7 +10,7 @@ pub mod types;
 mod utils;
 
 pub use context::VMContext;
-pub use dependencies::{External, MemoryLike, ValuePtr};
+pub use dependencies::{External, MemoryLike, StorageRead, StorageReadCompletion, ValuePtr};
 pub use logic::{VMLogic, VMOutcome};
 pub use near_primitives_core::config::*;
 pub use near_primitives_core::profile;
@@ -18,4 +18,4 @@ pub use near_primitives_core::types::ProtocolVersion;
 pub use near_vm_errors::{HostError, VMLogicError};
 pub use types::ReturnData;
 
-pub use gas_counter::with_ext_cost_counter;
+pub use gas_counter::{with_ext_cost_counter, with_view_deadline};
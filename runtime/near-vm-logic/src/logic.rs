@@ -1,5 +1,5 @@
 use crate::context::VMContext;
-use crate::dependencies::{External, MemoryLike};
+use crate::dependencies::{External, MemoryLike, StorageRead};
 use crate::gas_counter::GasCounter;
 use crate::types::{PromiseIndex, PromiseResult, ReceiptIndex, ReturnData};
 use crate::utils::split_method_names;
@@ -70,6 +70,10 @@ pub struct VMLogic<'a> {
 
     /// Current protocol version that is used for the function call.
     current_protocol_version: ProtocolVersion,
+
+    /// Number of values already returned by `next_random_seed`.
+    #[cfg(feature = "protocol_feature_next_random_seed")]
+    random_seed_nonce: u64,
 }
 
 /// Promises API allows to create a DAG-structure that defines dependencies between smart contract
@@ -148,6 +152,8 @@ impl<'a> VMLogic<'a> {
             receipt_to_account: HashMap::new(),
             total_log_length: 0,
             current_protocol_version,
+            #[cfg(feature = "protocol_feature_next_random_seed")]
+            random_seed_nonce: 0,
         }
     }
 
@@ -874,7 +880,8 @@ impl<'a> VMLogic<'a> {
         Ok(crate::alt_bn128::alt_bn128_pairing_check(&value_buf)? as u64)
     }
 
-    /// Writes random seed into the register.
+    /// Writes random seed into the register. It's derived from the hash of the action and the
+    /// random value of the block, so every call within a function call returns the same value.
     ///
     /// # Errors
     ///
@@ -888,6 +895,51 @@ impl<'a> VMLogic<'a> {
         self.internal_write_register(register_id, self.context.random_seed.clone())
     }
 
+    /// Writes random seed of the epoch into the register. Unlike `random_seed`, it's the same for
+    /// all the blocks of the epoch, so the producer of the current block can't influence it.
+    ///
+    /// # Errors
+    ///
+    /// If the size of the registers exceed the set limit `MemoryAccessViolation`.
+    ///
+    /// # Cost
+    ///
+    /// `base + write_register_base + write_register_byte * num_bytes`.
+    #[cfg(feature = "protocol_feature_epoch_random_seed")]
+    pub fn epoch_random_seed(&mut self, register_id: u64) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        self.internal_write_register(register_id, self.context.epoch_random_seed.clone())
+    }
+
+    /// Writes a new random value into the register on every call. The `n`-th call of the function
+    /// call, counting from 0, returns `sha256(random_seed || n)` with `n` as a little endian u64,
+    /// where `random_seed` is the value returned by `random_seed`, itself derived from the hash
+    /// of the action and the random value of the block. So the values differ between calls, and
+    /// between the actions and receipts of the block, while anyone can recompute them.
+    ///
+    /// # Errors
+    ///
+    /// If the size of the registers exceed the set limit `MemoryAccessViolation`.
+    ///
+    /// # Cost
+    ///
+    /// `base + sha256_base + sha256_byte * (num_seed_bytes + 8) + write_register_base +
+    /// write_register_byte * 32`.
+    #[cfg(feature = "protocol_feature_next_random_seed")]
+    pub fn next_random_seed(&mut self, register_id: u64) -> Result<()> {
+        self.gas_counter.pay_base(base)?;
+        self.gas_counter.pay_base(sha256_base)?;
+        let mut value = self.context.random_seed.clone();
+        value.extend_from_slice(&self.random_seed_nonce.to_le_bytes());
+        self.gas_counter.pay_per(sha256_byte, value.len() as u64)?;
+        self.random_seed_nonce += 1;
+
+        use sha2::Digest;
+
+        let value_hash = sha2::Sha256::digest(&value);
+        self.internal_write_register(register_id, value_hash.as_slice().to_vec())
+    }
+
     /// Hashes the given value using sha256 and returns it into `register_id`.
     ///
     /// # Errors
@@ -1418,6 +1470,15 @@ impl<'a> VMLogic<'a> {
         Ok((receipt_idx, sir))
     }
 
+    /// Pays the overhead of appending an action to a receipt, on top of the fees of the action.
+    fn pay_action_overhead(&mut self, sir: bool, action: ActionCosts) -> Result<()> {
+        self.gas_counter.pay_action_base(
+            &self.fees_config.action_creation_config.action_overhead_cost,
+            sir,
+            action,
+        )
+    }
+
     /// Appends `CreateAccount` action to the batch of actions for the given promise pointed by
     /// `promise_idx`.
     ///
@@ -1447,6 +1508,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::create_account,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::create_account)?;
 
         self.ext.append_action_create_account(receipt_idx)?;
         Ok(())
@@ -1499,6 +1561,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::deploy_contract,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::deploy_contract)?;
         self.gas_counter.pay_action_per_byte(
             &self.fees_config.action_creation_config.deploy_contract_cost_per_byte,
             num_bytes,
@@ -1561,6 +1624,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::function_call,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::function_call)?;
         self.gas_counter.pay_action_per_byte(
             &self.fees_config.action_creation_config.function_call_cost_per_byte,
             num_bytes,
@@ -1619,6 +1683,7 @@ impl<'a> VMLogic<'a> {
         let burn_gas = send_fee;
         let use_gas = burn_gas.checked_add(exec_fee).ok_or(HostError::IntegerOverflow)?;
         self.gas_counter.pay_action_accumulated(burn_gas, use_gas, ActionCosts::transfer)?;
+        self.pay_action_overhead(sir, ActionCosts::transfer)?;
 
         self.deduct_balance(amount)?;
 
@@ -1667,6 +1732,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::stake,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::stake)?;
 
         self.ext.append_action_stake(receipt_idx, amount, public_key)?;
         Ok(())
@@ -1712,6 +1778,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::add_key,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::add_key)?;
 
         self.ext.append_action_add_key_with_full_access(receipt_idx, public_key, nonce)?;
         Ok(())
@@ -1772,6 +1839,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::function_call,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::function_call)?;
         self.gas_counter.pay_action_per_byte(
             &self.fees_config.action_creation_config.add_key_cost.function_call_cost_per_byte,
             num_bytes,
@@ -1829,6 +1897,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::delete_key,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::delete_key)?;
 
         self.ext.append_action_delete_key(receipt_idx, public_key)?;
         Ok(())
@@ -1872,6 +1941,7 @@ impl<'a> VMLogic<'a> {
             sir,
             ActionCosts::delete_account,
         )?;
+        self.pay_action_overhead(sir, ActionCosts::delete_account)?;
 
         self.ext.append_action_delete_account(receipt_idx, beneficiary_id)?;
         Ok(())
@@ -2221,7 +2291,7 @@ impl<'a> VMLogic<'a> {
         self.gas_counter.pay_per(storage_write_key_byte, key.len() as u64)?;
         self.gas_counter.pay_per(storage_write_value_byte, value.len() as u64)?;
         let nodes_before = self.ext.get_touched_nodes_count();
-        let evicted_ptr = self.ext.storage_get(&key)?;
+        let evicted_ptr = self.ext.storage_read_start(&key)?.wait()?;
         let evicted =
             Self::deref_value(&mut self.gas_counter, storage_write_evicted_byte, evicted_ptr)?;
         self.gas_counter
@@ -2302,7 +2372,7 @@ impl<'a> VMLogic<'a> {
         }
         self.gas_counter.pay_per(storage_read_key_byte, key.len() as u64)?;
         let nodes_before = self.ext.get_touched_nodes_count();
-        let read = self.ext.storage_get(&key);
+        let read = self.ext.storage_read_start(&key).and_then(StorageRead::wait);
         self.gas_counter
             .pay_per(touching_trie_node, self.ext.get_touched_nodes_count() - nodes_before)?;
         let read = Self::deref_value(&mut self.gas_counter, storage_read_value_byte, read?)?;
@@ -2352,7 +2422,7 @@ impl<'a> VMLogic<'a> {
         }
         self.gas_counter.pay_per(storage_remove_key_byte, key.len() as u64)?;
         let nodes_before = self.ext.get_touched_nodes_count();
-        let removed_ptr = self.ext.storage_get(&key)?;
+        let removed_ptr = self.ext.storage_read_start(&key)?.wait()?;
         let removed =
             Self::deref_value(&mut self.gas_counter, storage_remove_ret_value_byte, removed_ptr)?;
 
@@ -2409,6 +2479,225 @@ impl<'a> VMLogic<'a> {
         Ok(res? as u64)
     }
 
+    /// Reads the id of an account extension record from memory or register and checks its
+    /// length.
+    ///
+    /// # Errors
+    ///
+    /// * If `extension_id_len + extension_id_ptr` exceeds the memory container or points to an
+    ///   unused register it returns `MemoryAccessViolation`;
+    /// * If the length of the id exceeds `max_length_account_extension_id` returns
+    ///   `KeyLengthExceeded`.
+    #[cfg(feature = "protocol_feature_account_extensions")]
+    fn read_account_extension_id(
+        &mut self,
+        extension_id_ptr: u64,
+        extension_id_len: u64,
+    ) -> Result<Vec<u8>> {
+        let extension_id =
+            self.get_vec_from_memory_or_register(extension_id_ptr, extension_id_len)?;
+        if extension_id.len() as u64 > self.config.limit_config.max_length_account_extension_id {
+            return Err(HostError::KeyLengthExceeded {
+                length: extension_id.len() as u64,
+                limit: self.config.limit_config.max_length_account_extension_id,
+            }
+            .into());
+        }
+        Ok(extension_id)
+    }
+
+    /// Writes an extension record of the current account. Extension records are small records
+    /// kept next to the account, outside of the contract storage, and are staked for like the
+    /// contract storage.
+    /// * If the record doesn't exist it inserts it and does not modify the register. Returns `0`;
+    /// * If the record exists it overwrites it and copies the old value into the `register_id`.
+    ///   Returns `1`.
+    ///
+    /// # Errors
+    ///
+    /// * If `extension_id_len + extension_id_ptr` or `value_len + value_ptr` exceeds the memory
+    ///   container or points to an unused register it returns `MemoryAccessViolation`;
+    /// * If returning the preempted value into the registers exceed the memory container it returns
+    ///   `MemoryAccessViolation`.
+    /// * If the length of the id exceeds `max_length_account_extension_id` returns
+    ///   `KeyLengthExceeded`.
+    /// * If the length of the value exceeds `max_length_account_extension_value` returns
+    ///   `ValueLengthExceeded`.
+    /// * If called as view function returns `ProhibitedInView``.
+    ///
+    /// # Cost
+    ///
+    /// Same as `storage_write`.
+    #[cfg(feature = "protocol_feature_account_extensions")]
+    pub fn account_extension_write(
+        &mut self,
+        extension_id_len: u64,
+        extension_id_ptr: u64,
+        value_len: u64,
+        value_ptr: u64,
+        register_id: u64,
+    ) -> Result<u64> {
+        self.gas_counter.pay_base(base)?;
+        if self.context.is_view {
+            return Err(HostError::ProhibitedInView {
+                method_name: "account_extension_write".to_string(),
+            }
+            .into());
+        }
+        self.gas_counter.pay_base(storage_write_base)?;
+        let extension_id = self.read_account_extension_id(extension_id_ptr, extension_id_len)?;
+        let value = self.get_vec_from_memory_or_register(value_ptr, value_len)?;
+        if value.len() as u64 > self.config.limit_config.max_length_account_extension_value {
+            return Err(HostError::ValueLengthExceeded {
+                length: value.len() as u64,
+                limit: self.config.limit_config.max_length_account_extension_value,
+            }
+            .into());
+        }
+        self.gas_counter.pay_per(storage_write_key_byte, extension_id.len() as u64)?;
+        self.gas_counter.pay_per(storage_write_value_byte, value.len() as u64)?;
+        let nodes_before = self.ext.get_touched_nodes_count();
+        let evicted_ptr = self.ext.account_extension_get(&extension_id)?;
+        let evicted =
+            Self::deref_value(&mut self.gas_counter, storage_write_evicted_byte, evicted_ptr)?;
+        self.gas_counter
+            .pay_per(touching_trie_node, self.ext.get_touched_nodes_count() - nodes_before)?;
+        self.ext.account_extension_set(&extension_id, &value)?;
+        let storage_config = &self.fees_config.storage_usage_config;
+        match evicted {
+            Some(old_value) => {
+                // Inner value can't overflow, because the value length is limited.
+                self.current_storage_usage = self
+                    .current_storage_usage
+                    .checked_sub(old_value.len() as u64)
+                    .ok_or(InconsistentStateError::IntegerOverflow)?;
+                // Inner value can't overflow, because the value length is limited.
+                self.current_storage_usage = self
+                    .current_storage_usage
+                    .checked_add(value.len() as u64)
+                    .ok_or(InconsistentStateError::IntegerOverflow)?;
+                self.internal_write_register(register_id, old_value)?;
+                Ok(1)
+            }
+            None => {
+                // Inner value can't overflow, because the id/value length is limited.
+                self.current_storage_usage = self
+                    .current_storage_usage
+                    .checked_add(
+                        value.len() as u64
+                            + extension_id.len() as u64
+                            + storage_config.num_extra_bytes_record,
+                    )
+                    .ok_or(InconsistentStateError::IntegerOverflow)?;
+                Ok(0)
+            }
+        }
+    }
+
+    /// Reads an extension record of the current account.
+    /// * If the record exists copies its value into the `register_id`, even if the value is zero
+    ///   bytes. Returns `1`;
+    /// * If the record doesn't exist then does not modify the register. Returns `0`.
+    ///
+    /// # Errors
+    ///
+    /// * If `extension_id_len + extension_id_ptr` exceeds the memory container or points to an
+    ///   unused register it returns `MemoryAccessViolation`;
+    /// * If returning the value into the registers exceed the memory container it returns
+    ///   `MemoryAccessViolation`.
+    /// * If the length of the id exceeds `max_length_account_extension_id` returns
+    ///   `KeyLengthExceeded`.
+    ///
+    /// # Cost
+    ///
+    /// Same as `storage_read`.
+    #[cfg(feature = "protocol_feature_account_extensions")]
+    pub fn account_extension_read(
+        &mut self,
+        extension_id_len: u64,
+        extension_id_ptr: u64,
+        register_id: u64,
+    ) -> Result<u64> {
+        self.gas_counter.pay_base(base)?;
+        self.gas_counter.pay_base(storage_read_base)?;
+        let extension_id = self.read_account_extension_id(extension_id_ptr, extension_id_len)?;
+        self.gas_counter.pay_per(storage_read_key_byte, extension_id.len() as u64)?;
+        let nodes_before = self.ext.get_touched_nodes_count();
+        let read = self.ext.account_extension_get(&extension_id);
+        self.gas_counter
+            .pay_per(touching_trie_node, self.ext.get_touched_nodes_count() - nodes_before)?;
+        let read = Self::deref_value(&mut self.gas_counter, storage_read_value_byte, read?)?;
+        match read {
+            Some(value) => {
+                self.internal_write_register(register_id, value)?;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Removes an extension record of the current account.
+    /// * If the record exists, removes it and copies its value into the `register_id`, even if the
+    ///   value is zero bytes. Returns `1`;
+    /// * If the record doesn't exist then does not modify the register. Returns `0`.
+    ///
+    /// # Errors
+    ///
+    /// * If `extension_id_len + extension_id_ptr` exceeds the memory container or points to an
+    ///   unused register it returns `MemoryAccessViolation`;
+    /// * If returning the removed value into the registers exceed the memory container it returns
+    ///   `MemoryAccessViolation`.
+    /// * If the length of the id exceeds `max_length_account_extension_id` returns
+    ///   `KeyLengthExceeded`.
+    /// * If called as view function returns `ProhibitedInView``.
+    ///
+    /// # Cost
+    ///
+    /// Same as `storage_remove`.
+    #[cfg(feature = "protocol_feature_account_extensions")]
+    pub fn account_extension_remove(
+        &mut self,
+        extension_id_len: u64,
+        extension_id_ptr: u64,
+        register_id: u64,
+    ) -> Result<u64> {
+        self.gas_counter.pay_base(base)?;
+        if self.context.is_view {
+            return Err(HostError::ProhibitedInView {
+                method_name: "account_extension_remove".to_string(),
+            }
+            .into());
+        }
+        self.gas_counter.pay_base(storage_remove_base)?;
+        let extension_id = self.read_account_extension_id(extension_id_ptr, extension_id_len)?;
+        self.gas_counter.pay_per(storage_remove_key_byte, extension_id.len() as u64)?;
+        let nodes_before = self.ext.get_touched_nodes_count();
+        let removed_ptr = self.ext.account_extension_get(&extension_id)?;
+        let removed =
+            Self::deref_value(&mut self.gas_counter, storage_remove_ret_value_byte, removed_ptr)?;
+
+        self.ext.account_extension_remove(&extension_id)?;
+        self.gas_counter
+            .pay_per(touching_trie_node, self.ext.get_touched_nodes_count() - nodes_before)?;
+        let storage_config = &self.fees_config.storage_usage_config;
+        match removed {
+            Some(value) => {
+                // Inner value can't overflow, because the id/value length is limited.
+                self.current_storage_usage = self
+                    .current_storage_usage
+                    .checked_sub(
+                        value.len() as u64
+                            + extension_id.len() as u64
+                            + storage_config.num_extra_bytes_record,
+                    )
+                    .ok_or(InconsistentStateError::IntegerOverflow)?;
+                self.internal_write_register(register_id, value)?;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
     /// DEPRECATED
     /// Creates an iterator object inside the host. Returns the identifier that uniquely
     /// differentiates the given iterator from other iterators that can be simultaneously created.
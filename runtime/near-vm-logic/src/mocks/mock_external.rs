@@ -1,4 +1,4 @@
-use crate::{External, ValuePtr};
+use crate::{External, StorageRead, StorageReadCompletion, ValuePtr};
 use near_primitives_core::types::{AccountId, Balance, Gas};
 use near_vm_errors::HostError;
 use serde::{Deserialize, Serialize};
@@ -8,8 +8,11 @@ use std::collections::HashMap;
 /// Emulates the trie and the mock handling code.
 pub struct MockedExternal {
     pub fake_trie: HashMap<Vec<u8>, Vec<u8>>,
+    pub account_extensions: HashMap<Vec<u8>, Vec<u8>>,
     receipts: Vec<Receipt>,
     pub validators: HashMap<AccountId, Balance>,
+    /// Whether the storage reads are left in flight until the contract waits for them.
+    pub pending_reads: bool,
 }
 
 pub struct MockedValuePtr {
@@ -35,6 +38,17 @@ impl ValuePtr for MockedValuePtr {
     }
 }
 
+/// A storage read in flight, which completes as soon as it's waited for.
+pub struct MockedStorageReadCompletion {
+    value: Option<Vec<u8>>,
+}
+
+impl<'a> StorageReadCompletion<'a> for MockedStorageReadCompletion {
+    fn wait(self: Box<Self>) -> crate::dependencies::Result<Option<Box<dyn ValuePtr + 'a>>> {
+        Ok(self.value.map(|value| Box::new(MockedValuePtr { value }) as Box<_>))
+    }
+}
+
 impl MockedExternal {
     pub fn new() -> Self {
         Self::default()
@@ -62,6 +76,15 @@ impl External for MockedExternal {
             .map(|value| Box::new(MockedValuePtr { value: value.clone() }) as Box<_>))
     }
 
+    fn storage_read_start<'a>(&'a self, key: &[u8]) -> Result<StorageRead<'a>> {
+        let value = self.fake_trie.get(key).cloned();
+        if self.pending_reads {
+            Ok(StorageRead::Pending(Box::new(MockedStorageReadCompletion { value })))
+        } else {
+            Ok(StorageRead::Ready(value.map(|value| Box::new(MockedValuePtr { value }) as Box<_>)))
+        }
+    }
+
     fn storage_remove(&mut self, key: &[u8]) -> Result<()> {
         self.fake_trie.remove(key);
         Ok(())
@@ -76,6 +99,23 @@ impl External for MockedExternal {
         Ok(self.fake_trie.contains_key(key))
     }
 
+    fn account_extension_set(&mut self, extension_id: &[u8], value: &[u8]) -> Result<()> {
+        self.account_extensions.insert(extension_id.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn account_extension_get(&self, extension_id: &[u8]) -> Result<Option<Box<dyn ValuePtr>>> {
+        Ok(self
+            .account_extensions
+            .get(extension_id)
+            .map(|value| Box::new(MockedValuePtr { value: value.clone() }) as Box<_>))
+    }
+
+    fn account_extension_remove(&mut self, extension_id: &[u8]) -> Result<()> {
+        self.account_extensions.remove(extension_id);
+        Ok(())
+    }
+
     fn create_receipt(&mut self, receipt_indices: Vec<u64>, receiver_id: String) -> Result<u64> {
         if let Some(index) = receipt_indices.iter().find(|&&el| el >= self.receipts.len() as u64) {
             return Err(HostError::InvalidReceiptIndex { receipt_index: *index }.into());
@@ -17,6 +17,7 @@ pub fn get_context(input: Vec<u8>, is_view: bool) -> VMContext {
         attached_deposit: 10,
         prepaid_gas: 10_u64.pow(14),
         random_seed: vec![],
+        epoch_random_seed: vec![],
         is_view,
         output_data_receivers: vec![],
     }
@@ -20,6 +20,7 @@ pub fn create_context() -> VMContext {
         attached_deposit: 2u128,
         prepaid_gas: 10_u64.pow(14),
         random_seed: vec![0, 1, 2],
+        epoch_random_seed: vec![3, 4, 5],
         is_view: false,
         output_data_receivers: vec![],
     }
@@ -88,6 +89,12 @@ decl_test_bytes!(
 );
 
 decl_test_bytes!(test_random_seed, random_seed, create_context().random_seed.as_slice());
+#[cfg(feature = "protocol_feature_epoch_random_seed")]
+decl_test_bytes!(
+    test_epoch_random_seed,
+    epoch_random_seed,
+    create_context().epoch_random_seed.as_slice()
+);
 
 decl_test_bytes!(test_input, input, create_context().input.as_slice());
 
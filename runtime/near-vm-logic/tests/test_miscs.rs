@@ -431,6 +431,40 @@ fn test_valid_log_utf16_null_terminated_fail() {
     });
 }
 
+#[cfg(feature = "protocol_feature_next_random_seed")]
+#[test]
+fn test_next_random_seed() {
+    use sha2::Digest;
+
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut context = get_context(vec![], false);
+    context.random_seed = vec![7; 32];
+    let mut logic = logic_builder.build(context);
+
+    let mut values = vec![];
+    for nonce in 0u64..2 {
+        logic.next_random_seed(0).unwrap();
+        let res = vec![0u8; 32];
+        logic.read_register(0, res.as_ptr() as _).expect("OK");
+        let mut seed = vec![7; 32];
+        seed.extend_from_slice(&nonce.to_le_bytes());
+        assert_eq!(res.as_slice(), sha2::Sha256::digest(&seed).as_slice());
+        values.push(res);
+    }
+    assert_ne!(values[0], values[1]);
+    assert_costs(map! {
+        ExtCosts::base: 2,
+        ExtCosts::write_memory_base: 2,
+        ExtCosts::write_memory_byte: 64,
+        ExtCosts::read_register_base: 2,
+        ExtCosts::read_register_byte: 64,
+        ExtCosts::write_register_base: 2,
+        ExtCosts::write_register_byte: 64,
+        ExtCosts::sha256_base: 2,
+        ExtCosts::sha256_byte: 80,
+    });
+}
+
 #[test]
 fn test_sha256() {
     let mut logic_builder = VMLogicBuilder::default();
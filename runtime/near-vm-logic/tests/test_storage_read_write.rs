@@ -40,6 +40,34 @@ fn test_storage_read_with_register() {
     assert_eq!(&res, b"bar");
 }
 
+#[test]
+fn test_storage_read_write_remove_pending() {
+    let mut logic_builder = VMLogicBuilder::default();
+    logic_builder.ext.pending_reads = true;
+    let mut logic = logic_builder.build(get_context(vec![], false));
+
+    let key: &[u8] = b"foo";
+    let val: &[u8] = b"bar";
+
+    assert_eq!(logic.storage_read(key.len() as _, key.as_ptr() as _, 0), Ok(0));
+    assert_eq!(
+        logic.storage_write(
+            key.len() as _,
+            key.as_ptr() as _,
+            val.len() as _,
+            val.as_ptr() as _,
+            0
+        ),
+        Ok(0)
+    );
+    assert_eq!(logic.storage_read(key.len() as _, key.as_ptr() as _, 0), Ok(1));
+    let res = [0u8; 3];
+    logic.read_register(0, res.as_ptr() as _).unwrap();
+    assert_eq!(&res, b"bar");
+    assert_eq!(logic.storage_remove(key.len() as _, key.as_ptr() as _, 1), Ok(1));
+    assert_eq!(logic.storage_read(key.len() as _, key.as_ptr() as _, 0), Ok(0));
+}
+
 #[test]
 fn test_storage_remove_with_register() {
     let mut logic_builder = VMLogicBuilder::default();
@@ -74,3 +102,67 @@ fn test_storage_has_key_with_register() {
 
     assert_eq!(logic.storage_has_key(std::u64::MAX, 1 as _), Ok(1));
 }
+
+#[cfg(feature = "protocol_feature_account_extensions")]
+#[test]
+fn test_account_extension_write_read_remove() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let mut logic = logic_builder.build(get_context(vec![], false));
+
+    let extension_id: &[u8] = b"metadata";
+    let val: &[u8] = b"bar";
+    let storage_usage_before = logic.storage_usage().unwrap();
+
+    assert_eq!(
+        logic.account_extension_write(
+            extension_id.len() as _,
+            extension_id.as_ptr() as _,
+            val.len() as _,
+            val.as_ptr() as _,
+            0,
+        ),
+        Ok(0)
+    );
+    assert!(logic.storage_usage().unwrap() > storage_usage_before);
+    // Extension records don't share the key space with the contract storage.
+    assert_eq!(logic.storage_has_key(extension_id.len() as _, extension_id.as_ptr() as _), Ok(0));
+
+    assert_eq!(
+        logic.account_extension_read(extension_id.len() as _, extension_id.as_ptr() as _, 0),
+        Ok(1)
+    );
+    let res = [0u8; 3];
+    logic.read_register(0, res.as_ptr() as _).unwrap();
+    assert_eq!(&res, b"bar");
+
+    assert_eq!(
+        logic.account_extension_remove(extension_id.len() as _, extension_id.as_ptr() as _, 1),
+        Ok(1)
+    );
+    assert_eq!(logic.storage_usage().unwrap(), storage_usage_before);
+    assert_eq!(
+        logic.account_extension_read(extension_id.len() as _, extension_id.as_ptr() as _, 2),
+        Ok(0)
+    );
+}
+
+#[cfg(feature = "protocol_feature_account_extensions")]
+#[test]
+fn test_account_extension_value_length_exceeded() {
+    let mut logic_builder = VMLogicBuilder::default();
+    let limit = logic_builder.config.limit_config.max_length_account_extension_value;
+    let mut logic = logic_builder.build(get_context(vec![], false));
+
+    let extension_id: &[u8] = b"metadata";
+    let val = vec![0u8; limit as usize + 1];
+    assert_eq!(
+        logic.account_extension_write(
+            extension_id.len() as _,
+            extension_id.as_ptr() as _,
+            val.len() as _,
+            val.as_ptr() as _,
+            0,
+        ),
+        Err(near_vm_errors::HostError::ValueLengthExceeded { length: limit + 1, limit }.into())
+    );
+}
@@ -38,6 +38,7 @@ fn setup(
         attached_deposit: 0,
         prepaid_gas: 10u64.pow(15),
         random_seed: vec![0, 1, 2],
+        epoch_random_seed: vec![3, 4, 5],
         is_view: false,
         output_data_receivers: vec![],
     };
@@ -1,4 +1,5 @@
 use crate::errors::{ContractPrecompilatonError, ContractPrecompilatonResult, IntoVMError};
+use crate::metrics;
 use crate::prepare;
 use crate::wasmer1_runner::{default_wasmer1_store, wasmer1_vm_hash};
 use crate::wasmer_runner::wasmer0_vm_hash;
@@ -11,10 +12,12 @@ use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::CompiledContractCache;
 use near_vm_errors::CacheError::{DeserializationError, ReadError, SerializationError, WriteError};
-use near_vm_errors::{CacheError, VMError};
+use near_vm_errors::{CacheError, PrepareError, VMError};
 use near_vm_logic::VMConfig;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, BorshDeserialize, BorshSerialize)]
@@ -104,6 +107,115 @@ impl fmt::Debug for MockCompiledContractCache {
 #[cfg(not(feature = "no_cache"))]
 const CACHE_SIZE: usize = 128;
 
+/// Memory limit of the prepared code cache.
+#[cfg(not(feature = "no_cache"))]
+const PREPARED_CODE_CACHE_SIZE_BYTES: usize = 64 * 1024 * 1024;
+#[cfg(feature = "no_cache")]
+const PREPARED_CODE_CACHE_SIZE_BYTES: usize = 0;
+
+/// Key of the prepared code: the code hash and the hash of the parts of the config the
+/// preparation depends on, so that config changes which don't affect it keep the entries valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PreparedCodeKey {
+    code_hash: CryptoHash,
+    prepare_config_hash: u64,
+}
+
+impl PreparedCodeKey {
+    fn new(code: &ContractCode, config: &VMConfig) -> Self {
+        let mut s = DefaultHasher::new();
+        config.limit_config.hash(&mut s);
+        config.regular_op_cost.hash(&mut s);
+        config.grow_mem_cost.hash(&mut s);
+        PreparedCodeKey { code_hash: code.hash, prepare_config_hash: s.finish() }
+    }
+}
+
+struct PreparedCodeEntry {
+    code: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+/// In-memory cache of the prepared contract code, see `prepare::prepare_contract`.
+/// The total size of the cached code is bounded, the least recently used entries are evicted
+/// first.
+struct PreparedCodeCache {
+    entries: HashMap<PreparedCodeKey, PreparedCodeEntry>,
+    /// Keys of the entries by the time they were last used at.
+    lru: BTreeMap<u64, PreparedCodeKey>,
+    /// Logical time, incremented on every access.
+    tick: u64,
+    size: usize,
+    capacity: usize,
+}
+
+impl PreparedCodeCache {
+    fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), lru: BTreeMap::new(), tick: 0, size: 0, capacity }
+    }
+
+    fn get(&mut self, key: &PreparedCodeKey) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(self.tick, *key);
+        entry.last_used = self.tick;
+        Some(entry.code.clone())
+    }
+
+    /// Inserts the prepared code, evicting the least recently used entries to stay within the
+    /// capacity. Code larger than the capacity is not cached.
+    fn put(&mut self, key: PreparedCodeKey, code: Arc<Vec<u8>>) {
+        if code.len() > self.capacity || self.entries.contains_key(&key) {
+            return;
+        }
+        while self.size + code.len() > self.capacity {
+            let (_, evicted_key) = match self.lru.iter().next() {
+                Some((tick, key)) => (*tick, *key),
+                None => break,
+            };
+            self.remove(&evicted_key);
+            near_metrics::inc_counter(&metrics::PREPARED_CODE_CACHE_EVICTIONS_TOTAL);
+        }
+        self.tick += 1;
+        self.size += code.len();
+        self.lru.insert(self.tick, key);
+        self.entries.insert(key, PreparedCodeEntry { code, last_used: self.tick });
+        near_metrics::set_gauge(&metrics::PREPARED_CODE_CACHE_SIZE_BYTES, self.size as i64);
+    }
+
+    fn remove(&mut self, key: &PreparedCodeKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+            self.size -= entry.code.len();
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PREPARED_CODE_CACHE: Mutex<PreparedCodeCache> =
+        Mutex::new(PreparedCodeCache::new(PREPARED_CODE_CACHE_SIZE_BYTES));
+}
+
+/// Prepares the contract code, reusing the result of earlier preparations of the same code with
+/// the same config. Preparation errors are not cached.
+pub(crate) fn prepare_contract_cached(
+    code: &ContractCode,
+    config: &VMConfig,
+) -> Result<Arc<Vec<u8>>, PrepareError> {
+    let key = PreparedCodeKey::new(code, config);
+    if let Some(prepared_code) = PREPARED_CODE_CACHE.lock().unwrap().get(&key) {
+        near_metrics::inc_counter(&metrics::PREPARED_CODE_CACHE_HITS_TOTAL);
+        return Ok(prepared_code);
+    }
+    near_metrics::inc_counter(&metrics::PREPARED_CODE_CACHE_MISSES_TOTAL);
+    // The lock is not held during the preparation, so that different contracts can be prepared
+    // in parallel.
+    let prepared_code = Arc::new(prepare::prepare_contract(&code.code, config)?);
+    PREPARED_CODE_CACHE.lock().unwrap().put(key, prepared_code.clone());
+    Ok(prepared_code)
+}
+
 #[cfg(feature = "wasmer0_vm")]
 pub mod wasmer0_cache {
     use super::*;
@@ -112,22 +224,22 @@ pub mod wasmer0_cache {
     use wasmer_runtime_core::load_cache_with;
 
     pub(crate) fn compile_module(
-        code: &[u8],
+        code: &ContractCode,
         config: &VMConfig,
     ) -> Result<wasmer_runtime::Module, VMError> {
-        let prepared_code = prepare::prepare_contract(code, config)?;
+        let prepared_code = prepare_contract_cached(code, config)?;
         wasmer_runtime::compile(&prepared_code).map_err(|err| err.into_vm_error())
     }
 
     pub(crate) fn compile_and_serialize_wasmer(
-        wasm_code: &[u8],
+        code: &ContractCode,
         config: &VMConfig,
         key: &CryptoHash,
         cache: &dyn CompiledContractCache,
     ) -> Result<wasmer_runtime::Module, VMError> {
         let _span = tracing::debug_span!(target: "vm", "compile_and_serialize_wasmer").entered();
 
-        let module = compile_module(wasm_code, config).map_err(|e| cache_error(e, &key, cache))?;
+        let module = compile_module(code, config).map_err(|e| cache_error(e, &key, cache))?;
         let artifact =
             module.cache().map_err(|_e| VMError::CacheError(SerializationError { hash: key.0 }))?;
         let code = artifact
@@ -164,12 +276,12 @@ pub mod wasmer0_cache {
 
     fn compile_module_cached_wasmer_impl(
         key: CryptoHash,
-        wasm_code: &[u8],
+        code: &ContractCode,
         config: &VMConfig,
         cache: Option<&dyn CompiledContractCache>,
     ) -> Result<wasmer_runtime::Module, VMError> {
         if cache.is_none() {
-            return compile_module(wasm_code, config);
+            return compile_module(code, config);
         }
 
         let cache = cache.unwrap();
@@ -178,7 +290,7 @@ pub mod wasmer0_cache {
                 Some(serialized) => {
                     deserialize_wasmer(serialized.as_slice()).map_err(VMError::CacheError)?
                 }
-                None => compile_and_serialize_wasmer(wasm_code, config, &key, cache),
+                None => compile_and_serialize_wasmer(code, config, &key, cache),
             },
             Err(_) => Err(VMError::CacheError(ReadError)),
         }
@@ -194,10 +306,10 @@ pub mod wasmer0_cache {
 
         fn memcache_compile_module_cached_wasmer(
             key: CryptoHash,
-            wasm_code: &[u8],
+            code: &ContractCode,
             config: &VMConfig,
             cache: Option<&dyn CompiledContractCache>) -> Result<wasmer_runtime::Module, VMError> = {
-            compile_module_cached_wasmer_impl(key, wasm_code, config, cache)
+            compile_module_cached_wasmer_impl(key, code, config, cache)
         }
     }
 
@@ -208,9 +320,9 @@ pub mod wasmer0_cache {
     ) -> Result<wasmer_runtime::Module, VMError> {
         let key = get_contract_cache_key(code, VMKind::Wasmer0, config);
         #[cfg(not(feature = "no_cache"))]
-        return memcache_compile_module_cached_wasmer(key, &code.code, config, cache);
+        return memcache_compile_module_cached_wasmer(key, code, config, cache);
         #[cfg(feature = "no_cache")]
-        return compile_module_cached_wasmer_impl(key, &code.code, config, cache);
+        return compile_module_cached_wasmer_impl(key, code, config, cache);
     }
 }
 
@@ -221,16 +333,16 @@ pub mod wasmer1_cache {
     use super::*;
 
     fn compile_module_wasmer1(
-        code: &[u8],
+        code: &ContractCode,
         config: &VMConfig,
         store: &wasmer::Store,
     ) -> Result<wasmer::Module, VMError> {
-        let prepared_code = prepare::prepare_contract(code, config)?;
-        wasmer::Module::new(&store, prepared_code).map_err(|err| err.into_vm_error())
+        let prepared_code = prepare_contract_cached(code, config)?;
+        wasmer::Module::new(&store, prepared_code.as_slice()).map_err(|err| err.into_vm_error())
     }
 
     pub(crate) fn compile_and_serialize_wasmer1(
-        wasm_code: &[u8],
+        code: &ContractCode,
         key: &CryptoHash,
         config: &VMConfig,
         cache: &dyn CompiledContractCache,
@@ -238,8 +350,8 @@ pub mod wasmer1_cache {
     ) -> Result<wasmer::Module, VMError> {
         let _span = tracing::debug_span!(target: "vm", "compile_and_serialize_wasmer1").entered();
 
-        let module = compile_module_wasmer1(wasm_code, config, store)
-            .map_err(|e| cache_error(e, &key, cache))?;
+        let module =
+            compile_module_wasmer1(code, config, store).map_err(|e| cache_error(e, &key, cache))?;
         let code = module
             .serialize()
             .map_err(|_e| VMError::CacheError(SerializationError { hash: key.0 }))?;
@@ -267,13 +379,13 @@ pub mod wasmer1_cache {
 
     fn compile_module_cached_wasmer1_impl(
         key: CryptoHash,
-        wasm_code: &[u8],
+        code: &ContractCode,
         config: &VMConfig,
         cache: Option<&dyn CompiledContractCache>,
         store: &wasmer::Store,
     ) -> Result<wasmer::Module, VMError> {
         if cache.is_none() {
-            return compile_module_wasmer1(wasm_code, config, store);
+            return compile_module_wasmer1(code, config, store);
         }
 
         let cache = cache.unwrap();
@@ -281,7 +393,7 @@ pub mod wasmer1_cache {
             Ok(serialized) => match serialized {
                 Some(serialized) => deserialize_wasmer1(serialized.as_slice(), store)
                     .map_err(VMError::CacheError)?,
-                None => compile_and_serialize_wasmer1(wasm_code, &key, config, cache, store),
+                None => compile_and_serialize_wasmer1(code, &key, config, cache, store),
             },
             Err(_) => Err(VMError::CacheError(ReadError)),
         }
@@ -297,11 +409,11 @@ pub mod wasmer1_cache {
 
         fn memcache_compile_module_cached_wasmer1(
             key: CryptoHash,
-            wasm_code: &[u8],
+            code: &ContractCode,
             config: &VMConfig,
             cache: Option<&dyn CompiledContractCache>,
             store: &wasmer::Store) -> Result<wasmer::Module, VMError> = {
-            compile_module_cached_wasmer1_impl(key, wasm_code, config, cache, store)
+            compile_module_cached_wasmer1_impl(key, code, config, cache, store)
         }
     }
 
@@ -313,9 +425,9 @@ pub mod wasmer1_cache {
     ) -> Result<wasmer::Module, VMError> {
         let key = get_contract_cache_key(code, VMKind::Wasmer1, config);
         #[cfg(not(feature = "no_cache"))]
-        return memcache_compile_module_cached_wasmer1(key, &code.code, config, cache, store);
+        return memcache_compile_module_cached_wasmer1(key, code, config, cache, store);
         #[cfg(feature = "no_cache")]
-        return compile_module_cached_wasmer1_impl(key, &code.code, config, cache, store);
+        return compile_module_cached_wasmer1_impl(key, code, config, cache, store);
     }
 }
 
@@ -337,23 +449,16 @@ pub fn precompile_contract_vm(
         Ok(None) | Err(_) => {}
     };
     match vm_kind {
-        VMKind::Wasmer0 => match wasmer0_cache::compile_and_serialize_wasmer(
-            wasm_code.code.as_slice(),
-            config,
-            &key,
-            cache,
-        ) {
-            Ok(_) => Ok(ContractPrecompilatonResult::ContractCompiled),
-            Err(err) => Err(ContractPrecompilatonError::new(err)),
-        },
+        VMKind::Wasmer0 => {
+            match wasmer0_cache::compile_and_serialize_wasmer(wasm_code, config, &key, cache) {
+                Ok(_) => Ok(ContractPrecompilatonResult::ContractCompiled),
+                Err(err) => Err(ContractPrecompilatonError::new(err)),
+            }
+        }
         VMKind::Wasmer1 => {
             let store = default_wasmer1_store();
             match wasmer1_cache::compile_and_serialize_wasmer1(
-                wasm_code.code.as_slice(),
-                &key,
-                config,
-                cache,
-                &store,
+                wasm_code, &key, config, cache, &store,
             ) {
                 Ok(_) => Ok(ContractPrecompilatonResult::ContractCompiled),
                 Err(err) => Err(ContractPrecompilatonError::new(err)),
@@ -375,3 +480,55 @@ pub fn precompile_contract(
 ) -> Result<ContractPrecompilatonResult, ContractPrecompilatonError> {
     precompile_contract_vm(VMKind::default(), wasm_code, config, cache)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: &[u8], config: &VMConfig) -> PreparedCodeKey {
+        PreparedCodeKey::new(&ContractCode::new(code.to_vec(), None), config)
+    }
+
+    #[test]
+    fn test_prepared_code_key() {
+        let config = VMConfig::default();
+        let mut other_fees_config = config.clone();
+        other_fees_config.ext_costs.base += 1;
+        let mut other_limit_config = config.clone();
+        other_limit_config.limit_config.max_stack_height += 1;
+        // Only the parts of the config used by the preparation change the key.
+        assert_eq!(key(&[1], &config), key(&[1], &other_fees_config));
+        assert_ne!(key(&[1], &config), key(&[1], &other_limit_config));
+        assert_ne!(key(&[1], &config), key(&[2], &config));
+    }
+
+    #[test]
+    fn test_prepared_code_cache_eviction() {
+        let config = VMConfig::default();
+        let mut cache = PreparedCodeCache::new(10);
+        cache.put(key(&[1], &config), Arc::new(vec![0; 4]));
+        cache.put(key(&[2], &config), Arc::new(vec![0; 4]));
+        // Using the first entry makes the second one the least recently used.
+        assert!(cache.get(&key(&[1], &config)).is_some());
+        cache.put(key(&[3], &config), Arc::new(vec![0; 4]));
+        assert!(cache.get(&key(&[1], &config)).is_some());
+        assert!(cache.get(&key(&[2], &config)).is_none());
+        assert!(cache.get(&key(&[3], &config)).is_some());
+        assert_eq!(cache.size, 8);
+
+        // Code larger than the capacity is not cached.
+        cache.put(key(&[4], &config), Arc::new(vec![0; 11]));
+        assert!(cache.get(&key(&[4], &config)).is_none());
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_prepare_contract_cached() {
+        let code = ContractCode::new(near_test_contracts::rs_contract().to_vec(), None);
+        let config = VMConfig::default();
+        let prepared_code = prepare_contract_cached(&code, &config).unwrap();
+        assert_eq!(*prepared_code, prepare::prepare_contract(&code.code, &config).unwrap());
+        let invalid_code = ContractCode::new(vec![1, 2, 3], None);
+        assert!(prepare_contract_cached(&invalid_code, &config).is_err());
+    }
+}
@@ -177,6 +177,19 @@ macro_rules! wrapped_imports {
                 })*
             }
 
+            /// The first protocol version providing the host function `name` to the contracts,
+            /// `None` if this build doesn't provide it at any protocol version.
+            pub(crate) fn import_allowed_since(name: &str) -> Option<ProtocolVersion> {
+                $({
+                    $(#[cfg(feature = $feature_name)])*
+                    if name == stringify!($func) {
+                        let versions = [0 $(, near_primitives::version::ProtocolFeature::$feature.protocol_version())*];
+                        return versions.iter().copied().max();
+                    }
+                })*
+                None
+            }
+
             #[cfg(feature = "wasmtime_vm")]
             pub(crate) fn last_wasmtime_error() -> Option<near_vm_logic::VMLogicError> {
                 wasmtime_ext::EMBEDDER_ERROR.with(|embedder_error| {
@@ -218,6 +231,8 @@ wrapped_imports! {
     // # Math API #
     // ############
     random_seed<[register_id: u64] -> []>,
+    #["protocol_feature_epoch_random_seed", EpochRandomSeed] epoch_random_seed<[register_id: u64] -> []>,
+    #["protocol_feature_next_random_seed", NextRandomSeed] next_random_seed<[register_id: u64] -> []>,
     sha256<[value_len: u64, value_ptr: u64, register_id: u64] -> []>,
     keccak256<[value_len: u64, value_ptr: u64, register_id: u64] -> []>,
     keccak512<[value_len: u64, value_ptr: u64, register_id: u64] -> []>,
@@ -320,6 +335,9 @@ wrapped_imports! {
     storage_read<[key_len: u64, key_ptr: u64, register_id: u64] -> [u64]>,
     storage_remove<[key_len: u64, key_ptr: u64, register_id: u64] -> [u64]>,
     storage_has_key<[key_len: u64, key_ptr: u64] -> [u64]>,
+    #["protocol_feature_account_extensions", AccountExtensions] account_extension_write<[extension_id_len: u64, extension_id_ptr: u64, value_len: u64, value_ptr: u64, register_id: u64] -> [u64]>,
+    #["protocol_feature_account_extensions", AccountExtensions] account_extension_read<[extension_id_len: u64, extension_id_ptr: u64, register_id: u64] -> [u64]>,
+    #["protocol_feature_account_extensions", AccountExtensions] account_extension_remove<[extension_id_len: u64, extension_id_ptr: u64, register_id: u64] -> [u64]>,
     storage_iter_prefix<[prefix_len: u64, prefix_ptr: u64] -> [u64]>,
     storage_iter_range<[start_len: u64, start_ptr: u64, end_len: u64, end_ptr: u64] -> [u64]>,
     storage_iter_next<[iterator_id: u64, key_register_id: u64, value_register_id: u64] -> [u64]>,
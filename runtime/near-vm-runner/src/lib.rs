@@ -6,6 +6,7 @@ mod errors;
 mod imports;
 #[cfg(feature = "wasmer0_vm")]
 mod memory;
+mod metrics;
 mod vm_kind;
 
 mod preload;
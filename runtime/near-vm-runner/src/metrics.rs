@@ -0,0 +1,24 @@
+use near_metrics::{try_create_int_counter, try_create_int_gauge, IntCounter, IntGauge};
+
+lazy_static::lazy_static! {
+    pub static ref PREPARED_CODE_CACHE_HITS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_vm_prepared_code_cache_hits_total",
+            "Number of contract preparations served from the prepared code cache"
+        );
+    pub static ref PREPARED_CODE_CACHE_MISSES_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_vm_prepared_code_cache_misses_total",
+            "Number of contract preparations missing the prepared code cache"
+        );
+    pub static ref PREPARED_CODE_CACHE_EVICTIONS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_vm_prepared_code_cache_evictions_total",
+            "Number of prepared contracts evicted from the prepared code cache"
+        );
+    pub static ref PREPARED_CODE_CACHE_SIZE_BYTES: near_metrics::Result<IntGauge> =
+        try_create_int_gauge(
+            "near_vm_prepared_code_cache_size_bytes",
+            "Total size of the prepared contracts in the prepared code cache"
+        );
+}
@@ -5,9 +5,12 @@ use parity_wasm::builder;
 use parity_wasm::elements::{self, External, MemorySection, Type};
 use pwasm_utils::{self, rules};
 
+use near_primitives::version::ProtocolVersion;
 use near_vm_errors::PrepareError;
 use near_vm_logic::VMConfig;
 
+use crate::imports::import_allowed_since;
+
 struct ContractModule<'a> {
     module: elements::Module,
     config: &'a VMConfig,
@@ -162,6 +165,39 @@ pub fn prepare_contract(original_code: &[u8], config: &VMConfig) -> Result<Vec<u
         .into_wasm_code()
 }
 
+/// A host function imported by a contract which isn't provided at the protocol version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisallowedImport {
+    pub name: String,
+    /// The first protocol version providing the function, `None` if no protocol version does.
+    pub allowed_since: Option<ProtocolVersion>,
+}
+
+/// Finds the first function the contract imports from the external environment which isn't
+/// provided at `protocol_version`. Such a contract would fail to link on every call.
+///
+/// Code which can't be deserialized is left to `prepare_contract` to reject.
+pub fn find_disallowed_import(
+    original_code: &[u8],
+    protocol_version: ProtocolVersion,
+) -> Option<DisallowedImport> {
+    let module = elements::deserialize_buffer::<elements::Module>(original_code).ok()?;
+    module.import_section()?.entries().iter().find_map(|import| {
+        if !matches!(import.external(), External::Function(_)) {
+            return None;
+        }
+        let (name, allowed_since) = if import.module() == "env" {
+            (import.field().to_string(), import_allowed_since(import.field()))
+        } else {
+            (format!("{}.{}", import.module(), import.field()), None)
+        };
+        match allowed_since {
+            Some(version) if version <= protocol_version => None,
+            _ => Some(DisallowedImport { name, allowed_since }),
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use assert_matches::assert_matches;
@@ -226,4 +262,47 @@ mod tests {
         assert_matches!(r, Err(Error::Instantiate));
         */
     }
+
+    #[test]
+    fn disallowed_imports() {
+        let find = |wat: &str| find_disallowed_import(&wat::parse_str(wat).unwrap(), 0);
+
+        let r = find(
+            r#"(module (import "env" "memory" (memory 1 1)) (import "env" "gas" (func (param i32))))"#,
+        );
+        assert_eq!(r, None);
+
+        let r = find(r#"(module (import "env" "unknown_func" (func)))"#);
+        assert_eq!(
+            r,
+            Some(DisallowedImport { name: "unknown_func".to_string(), allowed_since: None })
+        );
+
+        let r = find(r#"(module (import "another_module" "gas" (func (param i32))))"#);
+        assert_eq!(
+            r,
+            Some(DisallowedImport { name: "another_module.gas".to_string(), allowed_since: None })
+        );
+
+        // Invalid code is rejected by `prepare_contract`.
+        assert_eq!(find_disallowed_import(&[1, 2, 3], 0), None);
+    }
+
+    #[cfg(feature = "protocol_feature_alt_bn128")]
+    #[test]
+    fn import_allowed_since_protocol_version() {
+        use near_primitives::version::ProtocolFeature;
+
+        let version = ProtocolFeature::AltBn128.protocol_version();
+        let wat = r#"(module (import "env" "alt_bn128_g1_sum" (func (param i64 i64 i64))))"#;
+        let code = wat::parse_str(wat).unwrap();
+        assert_eq!(
+            find_disallowed_import(&code, version - 1),
+            Some(DisallowedImport {
+                name: "alt_bn128_g1_sum".to_string(),
+                allowed_since: Some(version)
+            })
+        );
+        assert_eq!(find_disallowed_import(&code, version), None);
+    }
 }
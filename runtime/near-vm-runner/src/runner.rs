@@ -140,13 +140,14 @@ pub fn precompile<'a>(
     cache: &'a dyn CompiledContractCache,
     vm_kind: VMKind,
 ) -> Option<VMError> {
+    let code = ContractCode::new(code.to_vec(), None);
     match vm_kind {
         #[cfg(not(feature = "wasmer0_vm"))]
         VMKind::Wasmer0 => panic!("Wasmer0 is not supported, compile with '--features wasmer0_vm'"),
         #[cfg(feature = "wasmer0_vm")]
         VMKind::Wasmer0 => {
             let result = crate::cache::wasmer0_cache::compile_and_serialize_wasmer(
-                code,
+                &code,
                 wasm_config,
                 code_hash,
                 cache,
@@ -159,7 +160,7 @@ pub fn precompile<'a>(
                 wasmer::JIT::new(wasmer_compiler_singlepass::Singlepass::default()).engine();
             let store = wasmer::Store::new(&engine);
             let result = crate::cache::wasmer1_cache::compile_and_serialize_wasmer1(
-                code,
+                &code,
                 code_hash,
                 wasm_config,
                 cache,
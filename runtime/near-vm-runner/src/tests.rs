@@ -49,6 +49,7 @@ fn create_context(input: Vec<u8>) -> VMContext {
         attached_deposit: 2u128,
         prepaid_gas: 10_u64.pow(14),
         random_seed: vec![0, 1, 2],
+        epoch_random_seed: vec![3, 4, 5],
         is_view: false,
         output_data_receivers: vec![],
     }
@@ -29,6 +29,7 @@ fn default_vm_context() -> VMContext {
         attached_deposit: 0,
         prepaid_gas: 10u64.pow(18),
         random_seed: vec![0, 1, 2],
+        epoch_random_seed: vec![3, 4, 5],
         is_view: false,
         output_data_receivers: vec![],
         epoch_height: 1,
@@ -3,8 +3,9 @@ use wasmtime::Module;
 // mod only to apply feature to it. Is it possible to avoid it?
 #[cfg(feature = "wasmtime_vm")]
 pub mod wasmtime_runner {
+    use crate::cache::prepare_contract_cached;
     use crate::errors::IntoVMError;
-    use crate::{imports, prepare};
+    use crate::imports;
     use near_primitives::contract::ContractCode;
     use near_primitives::runtime::fees::RuntimeFeesConfig;
     use near_primitives::{
@@ -164,11 +165,11 @@ pub mod wasmtime_runner {
             wasm_config.limit_config.max_memory_pages,
         )
         .unwrap();
-        let prepared_code = match prepare::prepare_contract(&code.code, wasm_config) {
+        let prepared_code = match prepare_contract_cached(code, wasm_config) {
             Ok(code) => code,
             Err(err) => return (None, Some(VMError::from(err))),
         };
-        let module = match Module::new(&engine, prepared_code) {
+        let module = match Module::new(&engine, prepared_code.as_slice()) {
             Ok(module) => module,
             Err(err) => return (None, Some(err.into_vm_error())),
         };
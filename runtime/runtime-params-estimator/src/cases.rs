@@ -709,6 +709,8 @@ fn get_runtime_fees_config(measurement: &Measurements) -> RuntimeFeesConfig {
             },
             delete_key_cost: measured_to_fee(metric, measured[&ActionDeleteKey]),
             delete_account_cost: measured_to_fee(metric, measured[&ActionDeleteAccount]),
+            // The overhead of an action is included in the measured base cost of every action.
+            action_overhead_cost: Fee::free(),
         },
         ..Default::default()
     }
@@ -65,6 +65,7 @@ impl RuntimeTestbed {
             max_number_logs: u64::max_value(),
 
             max_actions_per_receipt: u64::max_value(),
+            max_actions_per_transaction: u64::max_value(),
             max_promises_per_function_call_action: u64::max_value(),
             max_number_input_data_dependencies: u64::max_value(),
 
@@ -89,6 +90,7 @@ impl RuntimeTestbed {
             block_timestamp: 0,
             gas_limit: None,
             random_seed: Default::default(),
+            epoch_random_seed: Default::default(),
             current_protocol_version: PROTOCOL_VERSION,
             config: Arc::new(runtime_config),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
@@ -98,6 +100,7 @@ impl RuntimeTestbed {
             profile: Default::default(),
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
+            observer: None,
         };
         Self {
             workdir,
@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use near_crypto::PublicKey;
@@ -10,11 +12,14 @@ use near_primitives::errors::{
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{ActionReceipt, Receipt};
 use near_primitives::runtime::config::AccountCreationConfig;
+use near_primitives::runtime::contract_stats::ContractStats;
 use near_primitives::runtime::fees::RuntimeFeesConfig;
 use near_primitives::transaction::{
     Action, AddKeyAction, DeleteAccountAction, DeleteKeyAction, DeployContractAction,
-    FunctionCallAction, StakeAction, TransferAction,
+    DeployGlobalContractAction, FunctionCallAction, StakeAction, TransferAction,
+    UseGlobalContractAction,
 };
+use near_primitives::trie_key::TrieKey;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, EpochInfoProvider};
 use near_primitives::utils::create_random_seed;
@@ -27,8 +32,8 @@ use near_runtime_utils::{
     is_valid_top_level_account_id,
 };
 use near_store::{
-    get_access_key, get_code, remove_access_key, remove_account, set_access_key, set_code,
-    StorageError, TrieUpdate,
+    get_access_key, get_code, get_global_code, remove_access_key, remove_account, set_access_key,
+    set_code, set_global_code, StorageError, TrieUpdate,
 };
 use near_vm_errors::{
     CacheError, CompilationError, FunctionCallError, InconsistentStateError, VMError,
@@ -40,6 +45,7 @@ use crate::config::{safe_add_gas, RuntimeConfig};
 use crate::ext::RuntimeExt;
 use crate::{ActionResult, ApplyState};
 use near_vm_runner::precompile_contract;
+use near_vm_runner::prepare::{find_disallowed_import, DisallowedImport};
 
 /// Runs given function call with given context / apply state.
 /// Precompiles:
@@ -128,6 +134,7 @@ pub(crate) fn execute_function_call(
             attached_deposit: function_call.deposit,
             prepaid_gas: function_call.gas,
             random_seed,
+            epoch_random_seed: apply_state.epoch_random_seed.as_ref().to_vec(),
             is_view,
             output_data_receivers,
         };
@@ -161,6 +168,7 @@ pub(crate) fn action_function_call(
     config: &RuntimeConfig,
     is_last_action: bool,
     epoch_info_provider: &dyn EpochInfoProvider,
+    contract_stats: &mut ContractStats,
 ) -> Result<(), RuntimeError> {
     if account.amount().checked_add(function_call.deposit).is_none() {
         return Err(StorageError::StorageInconsistentState(
@@ -181,6 +189,8 @@ pub(crate) fn action_function_call(
         epoch_info_provider,
         apply_state.current_protocol_version,
     );
+    let code_hash = account.code_hash();
+    let started = Instant::now();
     let (outcome, err) = execute_function_call(
         apply_state,
         &mut runtime_ext,
@@ -194,6 +204,11 @@ pub(crate) fn action_function_call(
         is_last_action,
         false,
     );
+    let wall_time = started.elapsed();
+    let failure = match &err {
+        Some(VMError::FunctionCallError(err)) => Some(function_call_failure_kind(err)),
+        _ => None,
+    };
     let execution_succeeded = match err {
         Some(VMError::FunctionCallError(err)) => match err {
             FunctionCallError::Nondeterministic(msg) => {
@@ -256,6 +271,11 @@ pub(crate) fn action_function_call(
         }
         None => true,
     };
+    contract_stats.entry(code_hash).or_default().record_call(
+        outcome.as_ref().map_or(0, |outcome| outcome.burnt_gas),
+        wall_time,
+        failure,
+    );
     if let Some(outcome) = outcome {
         result.gas_burnt = safe_add_gas(result.gas_burnt, outcome.burnt_gas)?;
         result.gas_burnt_for_function_call =
@@ -278,6 +298,20 @@ pub(crate) fn action_function_call(
     Ok(())
 }
 
+/// Kind of the failure of a function call in the execution statistics of contracts.
+fn function_call_failure_kind(err: &FunctionCallError) -> &'static str {
+    match err {
+        FunctionCallError::CompilationError(_) => "CompilationError",
+        FunctionCallError::LinkError { .. } => "LinkError",
+        FunctionCallError::MethodResolveError(_) => "MethodResolveError",
+        FunctionCallError::WasmTrap(_) => "WasmTrap",
+        FunctionCallError::WasmUnknownError { .. } => "WasmUnknownError",
+        FunctionCallError::HostError(_) => "HostError",
+        FunctionCallError::EvmError(_) => "EvmError",
+        FunctionCallError::Nondeterministic(_) => "Nondeterministic",
+    }
+}
+
 pub(crate) fn action_stake(
     account: &mut Account,
     result: &mut ActionResult,
@@ -482,6 +516,54 @@ pub(crate) fn action_deploy_contract(
     Ok(())
 }
 
+pub(crate) fn action_deploy_global_contract(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    account_id: &AccountId,
+    deploy_global_contract: &DeployGlobalContractAction,
+    apply_state: &ApplyState,
+) -> Result<(), StorageError> {
+    let code = ContractCode::new(deploy_global_contract.code.clone(), None);
+    if get_global_code(state_update, &code.get_hash())?.is_some() {
+        return Ok(());
+    }
+    // The account publishing the code pays for its storage for good.
+    account.set_storage_usage(
+        account.storage_usage().checked_add(code.code.len() as u64).ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Storage usage integer overflow for account {}",
+                account_id
+            ))
+        })?,
+    );
+    set_global_code(state_update, &code);
+    // The compiled code is cached by the hash of the code, so it's shared by all the accounts
+    // using it.
+    precompile_contract(&code, &apply_state.config.wasm_config, apply_state.cache.as_deref()).ok();
+    Ok(())
+}
+
+pub(crate) fn action_use_global_contract(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    account_id: &AccountId,
+    use_global_contract: &UseGlobalContractAction,
+    result: &mut ActionResult,
+) -> Result<(), StorageError> {
+    let code_hash = use_global_contract.code_hash;
+    if get_global_code(state_update, &code_hash)?.is_none() {
+        result.result = Err(ActionErrorKind::GlobalContractDoesNotExist { code_hash }.into());
+        return Ok(());
+    }
+    if let Some(prev_code) = get_code(state_update, account_id, Some(account.code_hash()))? {
+        account
+            .set_storage_usage(account.storage_usage().saturating_sub(prev_code.code.len() as u64));
+        state_update.remove(TrieKey::ContractCode { account_id: account_id.clone() });
+    }
+    account.set_code_hash(code_hash);
+    Ok(())
+}
+
 pub(crate) fn action_delete_account(
     state_update: &mut TrieUpdate,
     account: &mut Option<Account>,
@@ -613,7 +695,12 @@ pub(crate) fn check_actor_permissions(
     account_id: &AccountId,
 ) -> Result<(), ActionError> {
     match action {
-        Action::DeployContract(_) | Action::Stake(_) | Action::AddKey(_) | Action::DeleteKey(_) => {
+        Action::DeployContract(_)
+        | Action::DeployGlobalContract(_)
+        | Action::UseGlobalContract(_)
+        | Action::Stake(_)
+        | Action::AddKey(_)
+        | Action::DeleteKey(_) => {
             if actor_id != account_id {
                 return Err(ActionErrorKind::ActorNoPermission {
                     account_id: account_id.clone(),
@@ -638,11 +725,48 @@ pub(crate) fn check_actor_permissions(
                 .into());
             }
         }
-        Action::CreateAccount(_) | Action::FunctionCall(_) | Action::Transfer(_) => (),
+        Action::CreateAccount(_)
+        | Action::FunctionCall(_)
+        | Action::OptionalFunctionCall(_)
+        | Action::Transfer(_)
+        | Action::MaxStorageDelta(_)
+        | Action::ValidUntil(_) => (),
     };
     Ok(())
 }
 
+/// Rejects deploying code importing host functions which the current protocol version doesn't
+/// provide, such a contract would fail to link on every call.
+pub(crate) fn check_contract_imports(
+    action: &Action,
+    account_id: &AccountId,
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), ActionError> {
+    if !checked_feature!(
+        "protocol_feature_contract_import_versions",
+        ContractImportVersions,
+        current_protocol_version
+    ) {
+        return Ok(());
+    }
+    let code = match action {
+        Action::DeployContract(DeployContractAction { code })
+        | Action::DeployGlobalContract(DeployGlobalContractAction { code }) => code,
+        _ => return Ok(()),
+    };
+    match find_disallowed_import(code, current_protocol_version) {
+        Some(DisallowedImport { name, allowed_since }) => {
+            Err(ActionErrorKind::ContractImportNotAllowed {
+                account_id: account_id.clone(),
+                import: name,
+                allowed_since,
+            }
+            .into())
+        }
+        None => Ok(()),
+    }
+}
+
 pub(crate) fn check_account_existence(
     action: &Action,
     account: &mut Option<Account>,
@@ -700,8 +824,13 @@ pub(crate) fn check_account_existence(
                 };
             }
         }
+        // The account may be created by the following actions.
+        Action::MaxStorageDelta(_) | Action::ValidUntil(_) => (),
         Action::DeployContract(_)
+        | Action::DeployGlobalContract(_)
+        | Action::UseGlobalContract(_)
         | Action::FunctionCall(_)
+        | Action::OptionalFunctionCall(_)
         | Action::Stake(_)
         | Action::AddKey(_)
         | Action::DeleteKey(_)
@@ -901,4 +1030,33 @@ mod tests {
             })
         );
     }
+
+    #[cfg(feature = "protocol_feature_contract_import_versions")]
+    #[test]
+    fn test_check_contract_imports() {
+        let account_id = "alice".to_string();
+        let version = ProtocolFeature::ContractImportVersions.protocol_version();
+        let deploy =
+            |code: &[u8]| Action::DeployContract(DeployContractAction { code: code.to_vec() });
+
+        let valid = deploy(near_test_contracts::rs_contract());
+        assert_eq!(check_contract_imports(&valid, &account_id, version), Ok(()));
+
+        // A module importing `env.unknown_func` with the `() -> ()` signature.
+        let mut code =
+            b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x02\x14\x01\x03env\x0cunknown_func".to_vec();
+        code.extend_from_slice(&[0, 0]);
+        let invalid = deploy(&code);
+        assert_eq!(
+            check_contract_imports(&invalid, &account_id, version),
+            Err(ActionErrorKind::ContractImportNotAllowed {
+                account_id: account_id.clone(),
+                import: "unknown_func".to_string(),
+                allowed_since: None,
+            }
+            .into())
+        );
+        // Deploys are only checked from the protocol version of the feature.
+        assert_eq!(check_contract_imports(&invalid, &account_id, version - 1), Ok(()));
+    }
 }
@@ -1,9 +1,11 @@
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::contract::ContractCode;
+use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, MerkleHash, ShardId,
+    AccountId, BlockHeight, BurnLedger, EpochHeight, EpochId, EpochInfoProvider, MerkleHash,
+    ShardId,
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::ViewStateResult;
@@ -64,5 +66,14 @@ pub trait ViewRuntimeAdapter {
         state_root: MerkleHash,
         account_id: &AccountId,
         prefix: &[u8],
+        start_key: Option<&[u8]>,
+        limit: Option<u64>,
+        include_proof: bool,
     ) -> Result<ViewStateResult, crate::state_viewer::errors::ViewStateError>;
+
+    fn view_burn_ledger(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+    ) -> Result<BurnLedger, StorageError>;
 }
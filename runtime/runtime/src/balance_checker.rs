@@ -1,21 +1,22 @@
 use crate::safe_add_balance_apply;
 
-use crate::config::{
-    safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit, total_prepaid_exec_fees,
-    total_prepaid_gas,
-};
+use crate::config::{receipt_cost, receipts_cost, safe_add_balance};
 use crate::{ApplyStats, DelayedReceiptIndices, ValidatorAccountsUpdate};
+#[cfg(feature = "balance_checker_debug")]
+use near_primitives::errors::AccountBalanceDiff;
 use near_primitives::errors::{
     BalanceMismatchError, IntegerOverflowError, RuntimeError, StorageError,
 };
-use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::receipt::{PostponedReceiptExpiry, Receipt, ReceiptEnum};
 use near_primitives::runtime::fees::RuntimeFeesConfig;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{AccountId, Balance};
-use near_primitives::utils::system_account;
 use near_primitives::version::ProtocolVersion;
-use near_store::{get, get_account, get_postponed_receipt, TrieUpdate};
+use near_store::{
+    get, get_account, get_all_contract_gas_burnt, get_postponed_receipt, get_slash_escrow,
+    get_slash_escrows, TrieUpdate,
+};
 use std::collections::HashSet;
 
 pub(crate) fn check_balance(
@@ -30,14 +31,10 @@ pub(crate) fn check_balance(
     current_protocol_version: ProtocolVersion,
 ) -> Result<(), RuntimeError> {
     // Delayed receipts
-    let initial_delayed_receipt_indices: DelayedReceiptIndices =
-        get(&initial_state, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
-    let final_delayed_receipt_indices: DelayedReceiptIndices =
-        get(&final_state, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
-    let get_delayed_receipts = |from_index, to_index, state| {
+    let get_delayed_receipts = |from_index, to_index, state, receipt_key: fn(u64) -> TrieKey| {
         (from_index..to_index)
             .map(|index| {
-                get(state, &TrieKey::DelayedReceipt { index })?.ok_or_else(|| {
+                get(state, &receipt_key(index))?.ok_or_else(|| {
                     StorageError::StorageInconsistentState(format!(
                         "Delayed receipt #{} should be in the state",
                         index
@@ -46,18 +43,33 @@ pub(crate) fn check_balance(
             })
             .collect::<Result<Vec<Receipt>, StorageError>>()
     };
-    // Previously delayed receipts that were processed this time.
-    let processed_delayed_receipts = get_delayed_receipts(
-        initial_delayed_receipt_indices.first_index,
-        final_delayed_receipt_indices.first_index,
-        &initial_state,
-    )?;
-    // Receipts that were not processed this time and are delayed now.
-    let new_delayed_receipts = get_delayed_receipts(
-        initial_delayed_receipt_indices.next_available_index,
-        final_delayed_receipt_indices.next_available_index,
-        &final_state,
-    )?;
+    let mut processed_delayed_receipts = vec![];
+    let mut new_delayed_receipts = vec![];
+    for (indices_key, receipt_key) in [
+        (TrieKey::DelayedReceiptIndices, (|index| TrieKey::DelayedReceipt { index }) as fn(_) -> _),
+        (TrieKey::DelayedPriorityReceiptIndices, |index| TrieKey::DelayedPriorityReceipt { index }),
+    ]
+    .iter()
+    {
+        let initial_indices: DelayedReceiptIndices =
+            get(&initial_state, indices_key)?.unwrap_or_default();
+        let final_indices: DelayedReceiptIndices =
+            get(&final_state, indices_key)?.unwrap_or_default();
+        // Previously delayed receipts that were processed this time.
+        processed_delayed_receipts.extend(get_delayed_receipts(
+            initial_indices.first_index,
+            final_indices.first_index,
+            &initial_state,
+            *receipt_key,
+        )?);
+        // Receipts that were not processed this time and are delayed now.
+        new_delayed_receipts.extend(get_delayed_receipts(
+            initial_indices.next_available_index,
+            final_indices.next_available_index,
+            &final_state,
+            *receipt_key,
+        )?);
+    }
 
     // Accounts
     let mut all_accounts_ids: HashSet<AccountId> = transactions
@@ -72,63 +84,54 @@ pub(crate) fn check_balance(
             all_accounts_ids.extend(validator_accounts_update.validator_rewards.keys().cloned());
             all_accounts_ids.extend(validator_accounts_update.last_proposals.keys().cloned());
             all_accounts_ids.extend(validator_accounts_update.slashing_info.keys().cloned());
-            if let Some(account_id) = &validator_accounts_update.protocol_treasury_account_id {
-                all_accounts_ids.insert(account_id.clone());
+            all_accounts_ids.extend(validator_accounts_update.slash_appeals.keys().cloned());
+            // Escrowed slashed stake is accounted to the slashed account until it is burnt.
+            all_accounts_ids.extend(
+                get_slash_escrows(initial_state)?.into_iter().map(|(account_id, _)| account_id),
+            );
+            all_accounts_ids
+                .extend(validator_accounts_update.protocol_treasury_account_ids.iter().cloned());
+            // The developer reward is paid to the contracts which burnt gas in the epoch.
+            if validator_accounts_update.developer_reward.is_some() {
+                all_accounts_ids.extend(
+                    get_all_contract_gas_burnt(initial_state)?
+                        .into_iter()
+                        .map(|(account_id, _)| account_id),
+                );
             }
-            validator_accounts_update
-                .validator_rewards
-                .values()
-                .try_fold(0u128, |res, balance| safe_add_balance(res, *balance))?
+            validator_accounts_update.validator_rewards.values().try_fold(
+                validator_accounts_update.developer_reward.unwrap_or(0),
+                |res, balance| safe_add_balance(res, *balance),
+            )?
         } else {
             0
         };
-    let total_accounts_balance = |state| -> Result<Balance, RuntimeError> {
+    let account_balance =
+        |state: &TrieUpdate, account_id: &AccountId| -> Result<Balance, RuntimeError> {
+            let account_balance = get_account(state, account_id)?.map_or(Ok(0), |a| {
+                safe_add_balance(a.amount(), a.locked())
+                    .map_err(|_| RuntimeError::UnexpectedIntegerOverflow)
+            })?;
+            let escrow_balance = get_slash_escrow(state, account_id)?.map_or(0, |e| e.amount);
+            safe_add_balance(account_balance, escrow_balance)
+                .map_err(|_| RuntimeError::UnexpectedIntegerOverflow)
+        };
+    let total_accounts_balance = |state: &TrieUpdate| -> Result<Balance, RuntimeError> {
         Ok(all_accounts_ids
             .iter()
-            .map(|account_id| {
-                get_account(state, account_id)?.map_or(Ok(0), |a| {
-                    safe_add_balance(a.amount(), a.locked())
-                        .map_err(|_| RuntimeError::UnexpectedIntegerOverflow)
-                })
-            })
+            .map(|account_id| account_balance(state, account_id))
             .collect::<Result<Vec<Balance>, RuntimeError>>()?
             .into_iter()
             .try_fold(0u128, |res, balance| safe_add_balance(res, balance))?)
     };
-    let initial_accounts_balance = total_accounts_balance(&initial_state)?;
-    let final_accounts_balance = total_accounts_balance(&final_state)?;
+    let initial_accounts_balance = total_accounts_balance(initial_state)?;
+    let final_accounts_balance = total_accounts_balance(final_state)?;
     // Receipts
     let receipt_cost = |receipt: &Receipt| -> Result<Balance, IntegerOverflowError> {
-        Ok(match &receipt.receipt {
-            ReceiptEnum::Action(action_receipt) => {
-                let mut total_cost = total_deposit(&action_receipt.actions)?;
-                if receipt.predecessor_id != system_account() {
-                    let mut total_gas = safe_add_gas(
-                        transaction_costs.action_receipt_creation_config.exec_fee(),
-                        total_prepaid_exec_fees(
-                            transaction_costs,
-                            &action_receipt.actions,
-                            &receipt.receiver_id,
-                            current_protocol_version,
-                        )?,
-                    )?;
-                    total_gas =
-                        safe_add_gas(total_gas, total_prepaid_gas(&action_receipt.actions)?)?;
-                    let total_gas_cost = safe_gas_to_balance(action_receipt.gas_price, total_gas)?;
-                    total_cost = safe_add_balance(total_cost, total_gas_cost)?;
-                }
-                total_cost
-            }
-            ReceiptEnum::Data(_) => 0,
-        })
+        receipt_cost(transaction_costs, receipt, current_protocol_version)
     };
     let receipts_cost = |receipts: &[Receipt]| -> Result<Balance, IntegerOverflowError> {
-        receipts
-            .iter()
-            .map(receipt_cost)
-            .collect::<Result<Vec<Balance>, IntegerOverflowError>>()?
-            .into_iter()
-            .try_fold(0u128, |res, balance| safe_add_balance(res, balance))
+        receipts_cost(transaction_costs, receipts, current_protocol_version)
     };
     let incoming_receipts_balance = receipts_cost(incoming_receipts)?;
     let outgoing_receipts_balance = receipts_cost(outgoing_receipts)?;
@@ -138,7 +141,7 @@ pub(crate) fn check_balance(
     // account ID when the input data is not received yet.
     // We calculate all potential receipts IDs that might be postponed initially or after the
     // execution.
-    let all_potential_postponed_receipt_ids = incoming_receipts
+    let mut all_potential_postponed_receipt_ids = incoming_receipts
         .iter()
         .chain(processed_delayed_receipts.iter())
         .map(|receipt| {
@@ -164,6 +167,21 @@ pub(crate) fn check_balance(
         .into_iter()
         .filter_map(|x| x)
         .collect::<HashSet<_>>();
+    // The receipts postponed for too long are failed by popping them from the expiry queue.
+    let initial_expiry_indices: DelayedReceiptIndices =
+        get(&initial_state, &TrieKey::PostponedReceiptExpiryIndices)?.unwrap_or_default();
+    let final_expiry_indices: DelayedReceiptIndices =
+        get(&final_state, &TrieKey::PostponedReceiptExpiryIndices)?.unwrap_or_default();
+    for index in initial_expiry_indices.first_index..final_expiry_indices.first_index {
+        let expiry: PostponedReceiptExpiry =
+            get(initial_state, &TrieKey::PostponedReceiptExpiry { index })?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Postponed receipt expiry #{} should be in the state",
+                    index
+                ))
+            })?;
+        all_potential_postponed_receipt_ids.insert((expiry.receiver_id, expiry.receipt_id));
+    }
 
     let total_postponed_receipts_cost = |state| -> Result<Balance, RuntimeError> {
         Ok(all_potential_postponed_receipt_ids
@@ -197,6 +215,26 @@ pub(crate) fn check_balance(
         stats.other_burnt_amount
     );
     if initial_balance != final_balance {
+        // The balances of all the accounts touched by the chunk make the error too large to be
+        // reported by default.
+        #[cfg(feature = "balance_checker_debug")]
+        let account_diffs = {
+            let mut account_diffs = all_accounts_ids
+                .iter()
+                .map(|account_id| {
+                    Ok(AccountBalanceDiff {
+                        account_id: account_id.clone(),
+                        initial_balance: account_balance(initial_state, account_id)?,
+                        final_balance: account_balance(final_state, account_id)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, RuntimeError>>()?;
+            account_diffs.retain(|diff| diff.initial_balance != diff.final_balance);
+            account_diffs.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+            account_diffs
+        };
+        #[cfg(not(feature = "balance_checker_debug"))]
+        let account_diffs = vec![];
         Err(BalanceMismatchError {
             // Inputs
             incoming_validator_rewards,
@@ -212,6 +250,7 @@ pub(crate) fn check_balance(
             tx_burnt_amount: stats.tx_burnt_amount,
             slashed_burnt_amount: stats.slashed_burnt_amount,
             other_burnt_amount: stats.other_burnt_amount,
+            account_diffs,
         }
         .into())
     } else {
@@ -286,6 +325,59 @@ mod tests {
         assert_matches!(err, RuntimeError::BalanceMismatchError(_));
     }
 
+    #[test]
+    fn test_check_balance_mismatch_report() {
+        let tries = create_tries();
+        let root = MerkleHash::default();
+        let account_id = alice_account();
+
+        let initial_balance = TESTING_INIT_BALANCE;
+        let refund_balance = 1000;
+
+        let mut initial_state = tries.new_trie_update(0, root);
+        let initial_account = account_new(initial_balance, hash(&[]));
+        set_account(&mut initial_state, account_id.clone(), &initial_account);
+        initial_state.commit(StateChangeCause::NotWritableToDisk);
+
+        // The refund is credited twice.
+        let mut final_state = tries.new_trie_update(0, root);
+        let final_account = account_new(initial_balance + 2 * refund_balance, hash(&[]));
+        set_account(&mut final_state, account_id.clone(), &final_account);
+        final_state.commit(StateChangeCause::NotWritableToDisk);
+
+        let err = match check_balance(
+            &RuntimeFeesConfig::default(),
+            &initial_state,
+            &final_state,
+            &None,
+            &[Receipt::new_balance_refund(&account_id, refund_balance)],
+            &[],
+            &[],
+            &ApplyStats::default(),
+            PROTOCOL_VERSION,
+        ) {
+            Err(RuntimeError::BalanceMismatchError(err)) => err,
+            res => panic!("expected a balance mismatch, got {:?}", res),
+        };
+        assert_eq!(err.incoming_receipts_balance, refund_balance);
+        assert_eq!(err.initial_accounts_balance, initial_balance);
+        assert_eq!(err.final_accounts_balance, initial_balance + 2 * refund_balance);
+        assert!(err
+            .to_string()
+            .contains(&format!("Accounts balance delta: +{}", 2 * refund_balance)));
+        #[cfg(feature = "balance_checker_debug")]
+        assert_eq!(
+            err.account_diffs,
+            vec![AccountBalanceDiff {
+                account_id,
+                initial_balance,
+                final_balance: initial_balance + 2 * refund_balance,
+            }]
+        );
+        #[cfg(not(feature = "balance_checker_debug"))]
+        assert!(err.account_diffs.is_empty());
+    }
+
     #[test]
     fn test_check_balance_refund() {
         let tries = create_tries();
@@ -387,6 +479,7 @@ mod tests {
                 gas_deficit_amount: 0,
                 other_burnt_amount: 0,
                 slashed_burnt_amount: 0,
+                ..Default::default()
             },
             PROTOCOL_VERSION,
         )
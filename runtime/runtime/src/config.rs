@@ -7,14 +7,17 @@ use num_traits::pow::Pow;
 
 use near_primitives::account::AccessKeyPermission;
 use near_primitives::errors::IntegerOverflowError;
+use near_primitives::receipt::{Receipt, ReceiptEnum};
 // Just re-exporting RuntimeConfig for backwards compatibility.
 pub use near_primitives::num_rational::Rational;
 pub use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::fees::{transfer_exec_fee, transfer_send_fee, RuntimeFeesConfig};
 use near_primitives::transaction::{
-    Action, AddKeyAction, DeployContractAction, FunctionCallAction, Transaction,
+    Action, AddKeyAction, DeployContractAction, DeployGlobalContractAction, FunctionCallAction,
+    Transaction,
 };
-use near_primitives::types::{AccountId, Balance, Gas};
+use near_primitives::types::{AccountId, Balance, Gas, StorageUsage};
+use near_primitives::utils::system_account;
 use near_primitives::version::{is_implicit_account_creation_enabled, ProtocolVersion};
 use near_runtime_utils::is_account_id_64_len_hex;
 
@@ -83,12 +86,14 @@ pub fn total_send_fees(
         use Action::*;
         let delta = match action {
             CreateAccount(_) => cfg.create_account_cost.send_fee(sender_is_receiver),
-            DeployContract(DeployContractAction { code }) => {
+            DeployContract(DeployContractAction { code })
+            | DeployGlobalContract(DeployGlobalContractAction { code }) => {
                 let num_bytes = code.len() as u64;
                 cfg.deploy_contract_cost.send_fee(sender_is_receiver)
                     + cfg.deploy_contract_cost_per_byte.send_fee(sender_is_receiver) * num_bytes
             }
-            FunctionCall(FunctionCallAction { method_name, args, .. }) => {
+            FunctionCall(FunctionCallAction { method_name, args, .. })
+            | OptionalFunctionCall(FunctionCallAction { method_name, args, .. }) => {
                 let num_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
                 cfg.function_call_cost.send_fee(sender_is_receiver)
                     + cfg.function_call_cost_per_byte.send_fee(sender_is_receiver) * num_bytes
@@ -122,8 +127,15 @@ pub fn total_send_fees(
             },
             DeleteKey(_) => cfg.delete_key_cost.send_fee(sender_is_receiver),
             DeleteAccount(_) => cfg.delete_account_cost.send_fee(sender_is_receiver),
+            // Only compares storage usages, which the action overhead covers.
+            MaxStorageDelta(_) => 0,
+            // Only compares heights, which the action overhead covers.
+            ValidUntil(_) => 0,
+            // Doesn't copy any code.
+            UseGlobalContract(_) => cfg.deploy_contract_cost.send_fee(sender_is_receiver),
         };
         result = safe_add_gas(result, delta)?;
+        result = safe_add_gas(result, cfg.action_overhead_cost.send_fee(sender_is_receiver))?;
     }
     Ok(result)
 }
@@ -133,18 +145,20 @@ pub fn exec_fee(
     action: &Action,
     receiver_id: &AccountId,
     current_protocol_version: ProtocolVersion,
-) -> Gas {
+) -> Result<Gas, IntegerOverflowError> {
     let cfg = &config.action_creation_config;
     use Action::*;
 
-    match action {
+    let fee = match action {
         CreateAccount(_) => cfg.create_account_cost.exec_fee(),
-        DeployContract(DeployContractAction { code }) => {
+        DeployContract(DeployContractAction { code })
+        | DeployGlobalContract(DeployGlobalContractAction { code }) => {
             let num_bytes = code.len() as u64;
             cfg.deploy_contract_cost.exec_fee()
                 + cfg.deploy_contract_cost_per_byte.exec_fee() * num_bytes
         }
-        FunctionCall(FunctionCallAction { method_name, args, .. }) => {
+        FunctionCall(FunctionCallAction { method_name, args, .. })
+        | OptionalFunctionCall(FunctionCallAction { method_name, args, .. }) => {
             let num_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
             cfg.function_call_cost.exec_fee()
                 + cfg.function_call_cost_per_byte.exec_fee() * num_bytes
@@ -172,7 +186,10 @@ pub fn exec_fee(
         },
         DeleteKey(_) => cfg.delete_key_cost.exec_fee(),
         DeleteAccount(_) => cfg.delete_account_cost.exec_fee(),
-    }
+        MaxStorageDelta(_) | ValidUntil(_) => 0,
+        UseGlobalContract(_) => cfg.deploy_contract_cost.exec_fee(),
+    };
+    safe_add_gas(fee, cfg.action_overhead_cost.exec_fee())
 }
 
 /// Returns transaction costs for a given transaction.
@@ -241,7 +258,7 @@ pub fn total_prepaid_exec_fees(
 ) -> Result<Gas, IntegerOverflowError> {
     let mut result = 0;
     for action in actions {
-        let delta = exec_fee(&config, action, receiver_id, current_protocol_version);
+        let delta = exec_fee(&config, action, receiver_id, current_protocol_version)?;
         result = safe_add_gas(result, delta)?;
     }
     Ok(result)
@@ -260,6 +277,56 @@ pub fn total_prepaid_gas(actions: &[Action]) -> Result<Gas, IntegerOverflowError
     actions.iter().try_fold(0, |acc, action| safe_add_gas(acc, action.get_prepaid_gas()))
 }
 
+/// Tokens held by the receipt: the deposits and, unless it's a refund, the prepaid gas and
+/// execution fees at the price at which the gas was purchased.
+pub fn receipt_cost(
+    config: &RuntimeFeesConfig,
+    receipt: &Receipt,
+    current_protocol_version: ProtocolVersion,
+) -> Result<Balance, IntegerOverflowError> {
+    Ok(match &receipt.receipt {
+        ReceiptEnum::Action(action_receipt) => {
+            let mut total_cost = total_deposit(&action_receipt.actions)?;
+            if receipt.predecessor_id != system_account() {
+                let mut total_gas = safe_add_gas(
+                    config.action_receipt_creation_config.exec_fee(),
+                    total_prepaid_exec_fees(
+                        config,
+                        &action_receipt.actions,
+                        &receipt.receiver_id,
+                        current_protocol_version,
+                    )?,
+                )?;
+                total_gas = safe_add_gas(total_gas, total_prepaid_gas(&action_receipt.actions)?)?;
+                let total_gas_cost = safe_gas_to_balance(action_receipt.gas_price, total_gas)?;
+                total_cost = safe_add_balance(total_cost, total_gas_cost)?;
+            }
+            total_cost
+        }
+        ReceiptEnum::Data(_) => 0,
+    })
+}
+
+/// Total tokens held by the receipts, see `receipt_cost`.
+pub fn receipts_cost(
+    config: &RuntimeFeesConfig,
+    receipts: &[Receipt],
+    current_protocol_version: ProtocolVersion,
+) -> Result<Balance, IntegerOverflowError> {
+    receipts.iter().try_fold(0, |acc, receipt| {
+        safe_add_balance(acc, receipt_cost(config, receipt, current_protocol_version)?)
+    })
+}
+
+/// Get the rebate for freeing `freed_bytes` of storage, which can't exceed `burnt_amount`.
+pub fn storage_rebate(
+    config: &RuntimeFeesConfig,
+    freed_bytes: StorageUsage,
+    burnt_amount: Balance,
+) -> Balance {
+    config.storage_rebate_per_byte.saturating_mul(Balance::from(freed_bytes)).min(burnt_amount)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +339,31 @@ mod tests {
         assert_eq!(safe_gas_price_inflated(10000, Rational::new(101, 100), 3).unwrap(), 10304);
         assert_eq!(safe_gas_price_inflated(10000, Rational::new(101, 100), 32).unwrap(), 13750);
     }
+
+    #[test]
+    fn test_storage_rebate() {
+        let mut config = RuntimeFeesConfig::default();
+        assert_eq!(storage_rebate(&config, 100, 1000), 0);
+        config.storage_rebate_per_byte = 5;
+        assert_eq!(storage_rebate(&config, 100, 1000), 500);
+        // Capped at the burnt amount.
+        assert_eq!(storage_rebate(&config, 300, 1000), 1000);
+        config.storage_rebate_per_byte = Balance::MAX;
+        assert_eq!(storage_rebate(&config, 2, 1000), 1000);
+    }
+
+    #[test]
+    fn test_action_overhead_fees() {
+        use near_primitives::runtime::fees::Fee;
+        use near_primitives::transaction::TransferAction;
+
+        let mut config = RuntimeFeesConfig::free();
+        config.action_creation_config.action_overhead_cost =
+            Fee { send_sir: 1, send_not_sir: 2, execution: 3 };
+        let actions = vec![Action::Transfer(TransferAction { deposit: 1 }); 10];
+        let receiver_id = "bob".to_string();
+        assert_eq!(total_send_fees(&config, true, &actions, &receiver_id, 0).unwrap(), 10);
+        assert_eq!(total_send_fees(&config, false, &actions, &receiver_id, 0).unwrap(), 20);
+        assert_eq!(total_prepaid_exec_fees(&config, &actions, &receiver_id, 0).unwrap(), 30);
+    }
 }
@@ -5,6 +5,7 @@ use log::debug;
 
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
+use near_primitives::checked_feature;
 use near_primitives::contract::ContractCode;
 use near_primitives::errors::{ExternalError, StorageError};
 use near_primitives::hash::CryptoHash;
@@ -17,7 +18,7 @@ use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{AccountId, Balance, EpochId, EpochInfoProvider};
 use near_primitives::utils::create_data_id;
 use near_primitives::version::ProtocolVersion;
-use near_store::{get_code, TrieUpdate, TrieUpdateValuePtr};
+use near_store::{get_code, get_global_code, TrieUpdate, TrieUpdateValuePtr};
 use near_vm_errors::{HostError, InconsistentStateError, VMLogicError};
 use near_vm_logic::{External, ValuePtr};
 
@@ -90,7 +91,21 @@ impl<'a> RuntimeExt<'a> {
         code_hash: CryptoHash,
     ) -> Result<Option<Arc<ContractCode>>, StorageError> {
         debug!(target:"runtime", "Calling the contract at account {}", self.account_id);
-        let code = || get_code(self.trie_update, self.account_id, Some(code_hash));
+        let code = || {
+            let code = get_code(self.trie_update, self.account_id, Some(code_hash))?;
+            // Accounts using a global contract don't have a code of their own.
+            if code.is_none()
+                && code_hash != CryptoHash::default()
+                && checked_feature!(
+                    "protocol_feature_global_contracts",
+                    GlobalContracts,
+                    self.current_protocol_version
+                )
+            {
+                return get_global_code(self.trie_update, &code_hash);
+            }
+            Ok(code)
+        };
         crate::cache::get_code(code_hash, code)
     }
 
@@ -98,6 +113,13 @@ impl<'a> RuntimeExt<'a> {
         TrieKey::ContractData { account_id: self.account_id.clone(), key: key.to_vec() }
     }
 
+    fn create_account_extension_key(&self, extension_id: &[u8]) -> TrieKey {
+        TrieKey::AccountExtension {
+            account_id: self.account_id.clone(),
+            extension_id: extension_id.to_vec(),
+        }
+    }
+
     fn new_data_id(&mut self) -> CryptoHash {
         let data_id = create_data_id(
             self.current_protocol_version,
@@ -205,6 +227,29 @@ impl<'a> External for RuntimeExt<'a> {
         Ok(())
     }
 
+    fn account_extension_set(&mut self, extension_id: &[u8], value: &[u8]) -> ExtResult<()> {
+        let extension_key = self.create_account_extension_key(extension_id);
+        self.trie_update.set(extension_key, Vec::from(value));
+        Ok(())
+    }
+
+    fn account_extension_get<'b>(
+        &'b self,
+        extension_id: &[u8],
+    ) -> ExtResult<Option<Box<dyn ValuePtr + 'b>>> {
+        let extension_key = self.create_account_extension_key(extension_id);
+        self.trie_update
+            .get_ref(&extension_key)
+            .map_err(wrap_storage_error)
+            .map(|option| option.map(|ptr| Box::new(RuntimeExtValuePtr(ptr)) as Box<_>))
+    }
+
+    fn account_extension_remove(&mut self, extension_id: &[u8]) -> ExtResult<()> {
+        let extension_key = self.create_account_extension_key(extension_id);
+        self.trie_update.remove(extension_key);
+        Ok(())
+    }
+
     fn create_receipt(&mut self, receipt_indices: Vec<u64>, receiver_id: String) -> ExtResult<u64> {
         let mut input_data_ids = vec![];
         for receipt_index in receipt_indices {
@@ -2,6 +2,9 @@ use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 
 use log::debug;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use near_chain_configs::Genesis;
 pub use near_crypto;
@@ -10,21 +13,25 @@ pub use near_primitives;
 use near_primitives::runtime::get_insufficient_storage_stake;
 use near_primitives::{
     account::Account,
+    challenge::{EscrowedSlash, SlashEscrow},
     checked_feature,
     errors::{ActionError, ActionErrorKind, RuntimeError, TxExecutionError},
     hash::CryptoHash,
+    profile::ProfileData,
     receipt::{
-        ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData,
+        ActionReceipt, DataReceipt, DelayedReceiptIndices, PostponedReceiptExpiry, Receipt,
+        ReceiptEnum, ReceivedData,
     },
     state_record::StateRecord,
     transaction::{
         Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, LogEntry,
-        SignedTransaction,
+        SignedTransaction, TransferAction,
     },
     trie_key::TrieKey,
     types::{
-        validator_stake::ValidatorStake, AccountId, Balance, EpochInfoProvider, Gas,
-        RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+        validator_stake::ValidatorStake, AccountId, Balance, BurnLedger, EpochHeight,
+        EpochInfoProvider, Gas, RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+        StorageUsage,
     },
     utils::{
         create_action_hash, create_receipt_id_from_receipt, create_receipt_id_from_transaction,
@@ -32,8 +39,11 @@ use near_primitives::{
     },
 };
 use near_store::{
-    get, get_account, get_postponed_receipt, get_received_data, remove_postponed_receipt, set,
-    set_account, set_postponed_receipt, set_received_data, PartialStorage, ShardTries,
+    get, get_account, get_all_contract_gas_burnt, get_burn_ledger, get_contract_gas_burnt,
+    get_delayed_receipts_count, get_postponed_receipt, get_received_data, get_slash_escrow,
+    get_slash_escrows, remove_contract_gas_burnt, remove_postponed_receipt, remove_slash_escrow,
+    set, set_account, set_burn_ledger, set_contract_gas_burnt, set_delayed_receipts_count,
+    set_postponed_receipt, set_received_data, set_slash_escrow, PartialStorage, ShardTries,
     StorageError, Trie, TrieChanges, TrieUpdate,
 };
 #[cfg(feature = "sandbox")]
@@ -45,7 +55,7 @@ pub use near_vm_runner::with_ext_cost_counter;
 use crate::actions::*;
 use crate::balance_checker::check_balance;
 use crate::config::{
-    exec_fee, safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit,
+    exec_fee, safe_add_balance, safe_add_gas, safe_gas_to_balance, storage_rebate, total_deposit,
     total_prepaid_exec_fees, total_prepaid_gas, RuntimeConfig,
 };
 use crate::genesis::{GenesisStateApplier, StorageComputer};
@@ -53,14 +63,14 @@ use crate::verifier::validate_receipt;
 pub use crate::verifier::{validate_transaction, verify_and_charge_transaction};
 #[cfg(feature = "sandbox")]
 use near_primitives::contract::ContractCode;
-pub use near_primitives::runtime::apply_state::ApplyState;
+pub use near_primitives::runtime::apply_state::{ApplyObserver, ApplyState};
+use near_primitives::runtime::contract_stats::ContractStats;
 use near_primitives::runtime::fees::RuntimeFeesConfig;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
 use near_primitives::transaction::ExecutionMetadata;
 use near_primitives::version::{
     is_implicit_account_creation_enabled, ProtocolFeature, ProtocolVersion,
 };
-use std::rc::Rc;
 use std::sync::Arc;
 
 mod actions;
@@ -72,10 +82,18 @@ pub mod ext;
 mod genesis;
 mod metrics;
 pub mod state_viewer;
+pub mod supply;
 mod verifier;
 
 const EXPECT_ACCOUNT_EXISTS: &str = "account exists, checked above";
 
+/// Maximum number of entries of the postponed receipts expiry queue processed per chunk.
+const MAX_EXPIRED_POSTPONED_RECEIPTS: u64 = 100;
+
+/// The priority receipts of a chunk can burn at most the chunk gas limit divided by this, the rest
+/// is left to the user receipts.
+const PRIORITY_RECEIPTS_GAS_LIMIT_DIVISOR: Gas = 2;
+
 /// Contains information to update validators accounts at the first block of a new epoch.
 #[derive(Debug)]
 pub struct ValidatorAccountsUpdate {
@@ -85,10 +103,18 @@ pub struct ValidatorAccountsUpdate {
     pub validator_rewards: HashMap<AccountId, Balance>,
     /// Stake proposals from the last chunk.
     pub last_proposals: HashMap<AccountId, Balance>,
-    /// The ID of the protocol treasure account if it belongs to the current shard.
-    pub protocol_treasury_account_id: Option<AccountId>,
+    /// The IDs of the accounts receiving the protocol treasury reward which belong to the current
+    /// shard.
+    pub protocol_treasury_account_ids: Vec<AccountId>,
     /// Accounts to slash and the slashed amount (None means everything)
     pub slashing_info: HashMap<AccountId, Option<Balance>>,
+    /// Accounts whose escrowed slashed stake should be returned because of counter-evidence, with
+    /// the hashes of the blocks which caused the appealed slashes.
+    pub slash_appeals: HashMap<AccountId, CryptoHash>,
+    /// Number of epochs slashed stake is kept in escrow before it is burnt.
+    pub slashing_escrow_epochs: EpochHeight,
+    /// Share of the developer reward to distribute between the contracts of the current shard.
+    pub developer_reward: Option<Balance>,
 }
 
 #[derive(Debug)]
@@ -111,6 +137,33 @@ pub struct ApplyStats {
     /// This is a negative amount. This amount was not charged from the account that issued
     /// the transaction. It's likely due to the delayed queue of the receipts.
     pub gas_deficit_amount: Balance,
+    /// Changes of the storage usage of the accounts. Accounts whose storage usage ends up
+    /// unchanged are omitted.
+    pub storage_usage_deltas: HashMap<AccountId, i64>,
+    /// Tokens refunded for freeing storage instead of being burnt. They are part of the gas
+    /// refunds, so they don't affect the balance check.
+    pub storage_rebate_amount: Balance,
+    /// Execution statistics of the called contracts.
+    pub contract_stats: ContractStats,
+}
+
+impl ApplyStats {
+    fn add_storage_usage_delta(
+        &mut self,
+        account_id: &AccountId,
+        before: StorageUsage,
+        after: StorageUsage,
+    ) {
+        if before == after {
+            return;
+        }
+        let delta = self.storage_usage_deltas.entry(account_id.clone()).or_insert(0);
+        // Storage usage is saved in state, hence it is nowhere close to max value of i64.
+        *delta += after as i64 - before as i64;
+        if *delta == 0 {
+            self.storage_usage_deltas.remove(account_id);
+        }
+    }
 }
 
 pub struct ApplyResult {
@@ -124,6 +177,52 @@ pub struct ApplyResult {
     pub proof: Option<PartialStorage>,
 }
 
+/// An action executed while replaying a receipt with `Runtime::replay_receipt`.
+#[derive(Debug)]
+pub struct ActionTrace {
+    pub action_index: usize,
+    pub action: Action,
+    pub gas_burnt: Gas,
+    pub error: Option<ActionError>,
+}
+
+/// Result of replaying a single receipt with `Runtime::replay_receipt`.
+#[derive(Debug)]
+pub struct ReplayReceiptResult {
+    /// Outcome of the receipt, `None` if the receipt wasn't executed, e.g. if it still waits for
+    /// input data.
+    pub outcome: Option<ExecutionOutcomeWithId>,
+    /// Executed actions of the receipt. Actions after the first failed one are not executed.
+    pub actions: Vec<ActionTrace>,
+    pub outgoing_receipts: Vec<Receipt>,
+    /// Changes of the state made by the receipt. They are never committed to the store.
+    pub state_changes: Vec<RawStateChangesWithTrieKey>,
+    /// Gas profile of the receipt execution.
+    pub profile: ProfileData,
+}
+
+/// Records the actions executed while replaying a receipt.
+#[derive(Default)]
+struct ActionTracer(std::sync::Mutex<Vec<ActionTrace>>);
+
+impl ApplyObserver for ActionTracer {
+    fn on_action_executed(
+        &self,
+        _receipt: &Receipt,
+        action_index: usize,
+        action: &Action,
+        gas_burnt: Gas,
+        error: Option<&ActionError>,
+    ) {
+        self.0.lock().unwrap().push(ActionTrace {
+            action_index,
+            action: action.clone(),
+            gas_burnt,
+            error: error.cloned(),
+        });
+    }
+}
+
 #[derive(Debug)]
 pub struct ActionResult {
     pub gas_burnt: Gas,
@@ -133,6 +232,8 @@ pub struct ActionResult {
     pub logs: Vec<LogEntry>,
     pub new_receipts: Vec<Receipt>,
     pub validator_proposals: Vec<ValidatorStake>,
+    /// Deposits of the failed optional function calls, refunded to the predecessor.
+    pub optional_deposit_refund: Balance,
 }
 
 impl ActionResult {
@@ -165,6 +266,29 @@ impl ActionResult {
         }
         Ok(())
     }
+
+    /// Merges the result of a failed optional action, whose state changes were reverted.
+    /// The result of the previous actions is kept, the receipts and the validator proposals of
+    /// the failed action are dropped and its `deposit` is refunded. Only the burnt gas counts as
+    /// used, since nothing is attached to the dropped receipts.
+    pub fn merge_failed_optional(
+        &mut self,
+        mut next_result: ActionResult,
+        deposit: Balance,
+    ) -> Result<(), RuntimeError> {
+        assert!(self.result.is_ok(), "Actions after a failure must not be executed");
+        assert!(next_result.result.is_err(), "Only a failed action can be merged as optional");
+        assert!(next_result.gas_burnt_for_function_call <= next_result.gas_burnt);
+        self.gas_burnt = safe_add_gas(self.gas_burnt, next_result.gas_burnt)?;
+        self.gas_burnt_for_function_call = safe_add_gas(
+            self.gas_burnt_for_function_call,
+            next_result.gas_burnt_for_function_call,
+        )?;
+        self.gas_used = safe_add_gas(self.gas_used, next_result.gas_burnt)?;
+        self.logs.append(&mut next_result.logs);
+        self.optional_deposit_refund = safe_add_balance(self.optional_deposit_refund, deposit)?;
+        Ok(())
+    }
 }
 
 impl Default for ActionResult {
@@ -177,6 +301,7 @@ impl Default for ActionResult {
             logs: vec![],
             new_receipts: vec![],
             validator_proposals: vec![],
+            optional_deposit_refund: 0,
         }
     }
 }
@@ -211,11 +336,13 @@ impl Runtime {
     /// `ExecutionOutcomeWithId` for the transaction.
     /// In case of an error, returns either `InvalidTxError` if the transaction verification failed
     /// or a `StorageError` wrapped into `RuntimeError`.
+    /// The signature is skipped unless `verify_signature`, when it was already verified.
     fn process_transaction(
         &self,
         state_update: &mut TrieUpdate,
         apply_state: &ApplyState,
         signed_transaction: &SignedTransaction,
+        verify_signature: bool,
         stats: &mut ApplyStats,
     ) -> Result<(Receipt, ExecutionOutcomeWithId), RuntimeError> {
         let _span =
@@ -227,7 +354,7 @@ impl Runtime {
             state_update,
             apply_state.gas_price,
             signed_transaction,
-            true,
+            verify_signature,
             Some(apply_state.block_index),
             apply_state.current_protocol_version,
         ) {
@@ -270,6 +397,9 @@ impl Runtime {
                         metadata: ExecutionMetadata::ExecutionMetadataV1,
                     },
                 };
+                if let Some(observer) = &apply_state.observer {
+                    observer.on_transaction_processed(signed_transaction, &outcome);
+                }
                 Ok((receipt, outcome))
             }
             Err(e) => {
@@ -294,6 +424,7 @@ impl Runtime {
         action_index: usize,
         actions: &[Action],
         epoch_info_provider: &dyn EpochInfoProvider,
+        stats: &mut ApplyStats,
     ) -> Result<ActionResult, RuntimeError> {
         // println!("enter apply_action");
         let mut result = ActionResult::default();
@@ -302,7 +433,7 @@ impl Runtime {
             action,
             &receipt.receiver_id,
             apply_state.current_protocol_version,
-        );
+        )?;
         result.gas_burnt += exec_fees;
         result.gas_used += exec_fees;
         let account_id = &receipt.receiver_id;
@@ -325,6 +456,13 @@ impl Runtime {
             result.result = Err(e);
             return Ok(result);
         }
+        // Deployed code validation
+        if let Err(e) =
+            check_contract_imports(action, account_id, apply_state.current_protocol_version)
+        {
+            result.result = Err(e);
+            return Ok(result);
+        }
         match action {
             Action::CreateAccount(_) => {
                 near_metrics::inc_counter(&metrics::ACTION_CREATE_ACCOUNT_TOTAL);
@@ -348,7 +486,7 @@ impl Runtime {
                     &apply_state,
                 )?;
             }
-            Action::FunctionCall(function_call) => {
+            Action::FunctionCall(function_call) | Action::OptionalFunctionCall(function_call) => {
                 near_metrics::inc_counter(&metrics::ACTION_FUNCTION_CALL_TOTAL);
                 action_function_call(
                     state_update,
@@ -364,6 +502,7 @@ impl Runtime {
                     &apply_state.config,
                     action_index + 1 == actions.len(),
                     epoch_info_provider,
+                    &mut stats.contract_stats,
                 )?;
             }
             Action::Transfer(transfer) => {
@@ -442,6 +581,28 @@ impl Runtime {
                     apply_state.current_protocol_version,
                 )?;
             }
+            // Enforced by `apply_action_receipt` after every action.
+            Action::MaxStorageDelta(_) => {}
+            // Enforced when the transaction is verified.
+            Action::ValidUntil(_) => {}
+            Action::DeployGlobalContract(deploy_global_contract) => {
+                action_deploy_global_contract(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &account_id,
+                    deploy_global_contract,
+                    &apply_state,
+                )?;
+            }
+            Action::UseGlobalContract(use_global_contract) => {
+                action_use_global_contract(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &account_id,
+                    use_global_contract,
+                    &mut result,
+                )?;
+            }
         };
         Ok(result)
     }
@@ -461,6 +622,9 @@ impl Runtime {
             ReceiptEnum::Action(action_receipt) => action_receipt,
             _ => unreachable!("given receipt should be an action receipt"),
         };
+        if let Some(observer) = &apply_state.observer {
+            observer.on_receipt_started(receipt);
+        }
         let account_id = &receipt.receiver_id;
         // Collecting input data and removing it from the state
         let promise_results = action_receipt
@@ -491,14 +655,26 @@ impl Runtime {
         });
 
         let mut account = get_account(state_update, account_id)?;
+        let initial_storage_usage = account.as_ref().map_or(0, |account| account.storage_usage());
+        // Validation only allows the cap as the first action.
+        let max_storage_delta = match action_receipt.actions.first() {
+            Some(Action::MaxStorageDelta(action)) => Some(action.max_storage_delta),
+            _ => None,
+        };
         let mut actor_id = receipt.predecessor_id.clone();
         let mut result = ActionResult::default();
         let exec_fee =
             apply_state.config.transaction_costs.action_receipt_creation_config.exec_fee();
         result.gas_used = exec_fee;
         result.gas_burnt = exec_fee;
-        // Executing actions one by one
+        // Executing actions one by one. Each action observes the effects of the previous ones and
+        // the first failing action fails the whole receipt, reverting the effects of all actions.
+        // The exception is an optional function call which isn't the last action: when it fails
+        // only its own effects are reverted and the following actions are executed.
         for (action_index, action) in action_receipt.actions.iter().enumerate() {
+            let is_optional = matches!(action, Action::OptionalFunctionCall(_))
+                && action_index + 1 < action_receipt.actions.len();
+            let checkpoint = if is_optional { Some(state_update.checkpoint()) } else { None };
             let action_hash = create_action_hash(
                 apply_state.current_protocol_version,
                 &receipt,
@@ -519,15 +695,49 @@ impl Runtime {
                 action_index,
                 &action_receipt.actions,
                 epoch_info_provider,
+                stats,
             )?;
             if new_result.result.is_ok() {
                 if let Err(e) = new_result.new_receipts.iter().try_for_each(|receipt| {
-                    validate_receipt(&apply_state.config.wasm_config.limit_config, receipt)
+                    validate_receipt(
+                        &apply_state.config.wasm_config.limit_config,
+                        receipt,
+                        apply_state.current_protocol_version,
+                    )
                 }) {
                     new_result.result = Err(ActionErrorKind::NewReceiptValidationError(e).into());
                 }
             }
+            if let Some(observer) = &apply_state.observer {
+                observer.on_action_executed(
+                    receipt,
+                    action_index,
+                    action,
+                    new_result.gas_burnt,
+                    new_result.result.as_ref().err(),
+                );
+            }
+            if new_result.result.is_err() {
+                if let Some(checkpoint) = checkpoint {
+                    state_update.rollback_to_checkpoint(checkpoint);
+                    result.merge_failed_optional(new_result, action.get_deposit_balance())?;
+                    continue;
+                }
+            }
             result.merge(new_result)?;
+            if let (Ok(_), Some(max_storage_delta), Some(account)) =
+                (&result.result, max_storage_delta, &account)
+            {
+                let storage_delta = account.storage_usage().saturating_sub(initial_storage_usage);
+                if storage_delta > max_storage_delta {
+                    result.result = Err(ActionErrorKind::MaxStorageDeltaExceeded {
+                        account_id: account_id.clone(),
+                        storage_delta,
+                        max_storage_delta,
+                    }
+                    .into());
+                }
+            }
             // TODO storage error
             if let Err(ref mut res) = result.result {
                 res.index = Some(action_index as u64);
@@ -557,7 +767,43 @@ impl Runtime {
             }
         }
 
-        let gas_deficit_amount = if receipt.predecessor_id == system_account() {
+        // Part of the burnt gas rewarded to the account for function call execution. Nobody pays
+        // for the gas of the maintenance calls issued by the system, so they aren't rewarded.
+        let is_system_receipt = receipt.predecessor_id == system_account();
+        let receiver_gas_reward = if is_system_receipt {
+            0
+        } else {
+            result.gas_burnt_for_function_call
+                * *apply_state.config.transaction_costs.burnt_gas_reward.numer() as u64
+                / *apply_state.config.transaction_costs.burnt_gas_reward.denom() as u64
+        };
+
+        // Receipts which free storage of the account get part of the burnt fees back. It doesn't
+        // exceed the fees burnt at the lower of the purchased and current gas prices, minus the
+        // reward of the account, so it's always covered by the burnt amount below.
+        let storage_rebate_amount = if result.result.is_ok()
+            && receipt.predecessor_id != system_account()
+            && checked_feature!(
+                "protocol_feature_storage_rebate",
+                StorageRebate,
+                apply_state.current_protocol_version
+            ) {
+            let final_storage_usage = account.as_ref().map_or(0, |account| account.storage_usage());
+            storage_rebate(
+                &apply_state.config.transaction_costs,
+                initial_storage_usage.saturating_sub(final_storage_usage),
+                safe_gas_to_balance(
+                    std::cmp::min(apply_state.gas_price, action_receipt.gas_price),
+                    result.gas_burnt.saturating_sub(receiver_gas_reward),
+                )?,
+            )
+        } else {
+            0
+        };
+        stats.storage_rebate_amount =
+            safe_add_balance(stats.storage_rebate_amount, storage_rebate_amount)?;
+
+        let (gas_deficit_amount, local_gas_refund) = if receipt.predecessor_id == system_account() {
             // We will set gas_burnt for refund receipts to be 0 when we calculate tx_burnt_amount
             // Here we don't set result.gas_burnt to be zero if CountRefundReceiptsInGasLimit is
             // enabled because we want it to be counted in gas limit calculation later
@@ -576,7 +822,7 @@ impl Runtime {
                     total_deposit(&action_receipt.actions)?,
                 )?
             }
-            0
+            (0, 0)
         } else {
             // Calculating and generating refunds
             self.generate_refund_receipts(
@@ -584,6 +830,7 @@ impl Runtime {
                 receipt,
                 action_receipt,
                 &mut result,
+                storage_rebate_amount,
                 apply_state.current_protocol_version,
                 &apply_state.config.transaction_costs,
             )?
@@ -596,6 +843,11 @@ impl Runtime {
         // Committing or rolling back state.
         match &result.result {
             Ok(_) => {
+                stats.add_storage_usage_delta(
+                    account_id,
+                    initial_storage_usage,
+                    account.as_ref().map_or(0, |account| account.storage_usage()),
+                );
                 state_update.commit(StateChangeCause::ReceiptProcessing {
                     receipt_hash: receipt.get_hash(),
                 });
@@ -605,20 +857,43 @@ impl Runtime {
             }
         };
 
+        // The gas refund of a receipt executed by the signer account itself is applied directly,
+        // unless the account was deleted by the receipt. Then the refund receipt fails as before.
+        if local_gas_refund > 0 {
+            if let Some(mut signer_account) = get_account(state_update, &action_receipt.signer_id)?
+            {
+                signer_account
+                    .set_amount(safe_add_balance(signer_account.amount(), local_gas_refund)?);
+                set_account(state_update, action_receipt.signer_id.clone(), &signer_account);
+                try_refund_allowance(
+                    state_update,
+                    &action_receipt.signer_id,
+                    &action_receipt.signer_public_key,
+                    &TransferAction { deposit: local_gas_refund },
+                )?;
+                state_update.commit(StateChangeCause::ActionReceiptGasRefund {
+                    receipt_hash: receipt.get_hash(),
+                });
+            } else {
+                result.new_receipts.push(Receipt::new_gas_refund(
+                    &action_receipt.signer_id,
+                    local_gas_refund,
+                    action_receipt.signer_public_key.clone(),
+                ));
+            }
+        }
+
         // If the receipt is a refund, then we consider it free without burnt gas.
         let gas_burnt: Gas =
             if receipt.predecessor_id == system_account() { 0 } else { result.gas_burnt };
         // `gas_deficit_amount` is strictly less than `gas_price * gas_burnt`.
-        let mut tx_burnt_amount =
-            safe_gas_to_balance(apply_state.gas_price, gas_burnt)? - gas_deficit_amount;
+        let mut tx_burnt_amount = safe_gas_to_balance(apply_state.gas_price, gas_burnt)?
+            - gas_deficit_amount
+            - storage_rebate_amount;
         // The amount of tokens burnt for the execution of this receipt. It's used in the execution
         // outcome.
         let tokens_burnt = tx_burnt_amount;
 
-        // Adding burnt gas reward for function calls if the account exists.
-        let receiver_gas_reward = result.gas_burnt_for_function_call
-            * *apply_state.config.transaction_costs.burnt_gas_reward.numer() as u64
-            / *apply_state.config.transaction_costs.burnt_gas_reward.denom() as u64;
         // The balance that the current account should receive as a reward for function call
         // execution.
         let receiver_reward = safe_gas_to_balance(apply_state.gas_price, receiver_gas_reward)?
@@ -638,6 +913,26 @@ impl Runtime {
             }
         }
 
+        // The gas burnt by the function calls of the contract is recorded to pay the developer
+        // reward at the end of the epoch.
+        if result.gas_burnt_for_function_call > 0
+            && !is_system_receipt
+            && checked_feature!(
+                "protocol_feature_developer_reward",
+                DeveloperReward,
+                apply_state.current_protocol_version
+            )
+        {
+            let gas_burnt = get_contract_gas_burnt(state_update, account_id)?.unwrap_or(0);
+            set_contract_gas_burnt(
+                state_update,
+                account_id.clone(),
+                safe_add_gas(gas_burnt, result.gas_burnt_for_function_call)?,
+            );
+            state_update
+                .commit(StateChangeCause::ReceiptProcessing { receipt_hash: receipt.get_hash() });
+        }
+
         stats.tx_burnt_amount = safe_add_balance(stats.tx_burnt_amount, tx_burnt_amount)?;
 
         // Generating outgoing data
@@ -725,7 +1020,7 @@ impl Runtime {
 
         Self::print_log(&result.logs);
 
-        Ok(ExecutionOutcomeWithId {
+        let outcome = ExecutionOutcomeWithId {
             id: receipt.receipt_id,
             outcome: ExecutionOutcome {
                 status,
@@ -736,7 +1031,11 @@ impl Runtime {
                 executor_id: account_id.clone(),
                 metadata: ExecutionMetadata::ExecutionMetadataV1,
             },
-        })
+        };
+        if let Some(observer) = &apply_state.observer {
+            observer.on_receipt_finished(receipt, &outcome);
+        }
+        Ok(outcome)
     }
 
     fn generate_refund_receipts(
@@ -745,9 +1044,10 @@ impl Runtime {
         receipt: &Receipt,
         action_receipt: &ActionReceipt,
         result: &mut ActionResult,
+        storage_rebate_amount: Balance,
         current_protocol_version: ProtocolVersion,
         transaction_costs: &RuntimeFeesConfig,
-    ) -> Result<Balance, RuntimeError> {
+    ) -> Result<(Balance, Balance), RuntimeError> {
         let total_deposit = total_deposit(&action_receipt.actions)?;
         let prepaid_gas = total_prepaid_gas(&action_receipt.actions)?;
         let prepaid_exec_gas = safe_add_gas(
@@ -759,7 +1059,8 @@ impl Runtime {
             )?,
             transaction_costs.action_receipt_creation_config.exec_fee(),
         )?;
-        let deposit_refund = if result.result.is_err() { total_deposit } else { 0 };
+        let deposit_refund =
+            if result.result.is_err() { total_deposit } else { result.optional_deposit_refund };
         let gas_refund = if result.result.is_err() {
             safe_add_gas(prepaid_gas, prepaid_exec_gas)? - result.gas_burnt
         } else {
@@ -794,11 +1095,25 @@ impl Runtime {
                 )?,
             )?;
         }
+        // The storage rebate is refunded along with the gas.
+        gas_balance_refund = safe_add_balance(gas_balance_refund, storage_rebate_amount)?;
         if deposit_refund > 0 {
             result
                 .new_receipts
                 .push(Receipt::new_balance_refund(&receipt.predecessor_id, deposit_refund));
         }
+        if receipt.receiver_id == action_receipt.signer_id
+            && checked_feature!(
+                "protocol_feature_local_gas_refund",
+                LocalGasRefund,
+                current_protocol_version
+            )
+        {
+            // The signer account is on this shard, so instead of a refund receipt the gas refund
+            // is returned to be applied directly once the state changes of the receipt are
+            // committed.
+            return Ok((gas_deficit_amount, gas_balance_refund));
+        }
         if gas_balance_refund > 0 {
             // Gas refunds refund the allowance of the access key, so if the key exists on the
             // account it will increase the allowance by the refund amount.
@@ -808,7 +1123,7 @@ impl Runtime {
                 action_receipt.signer_public_key.clone(),
             ));
         }
-        Ok(gas_deficit_amount)
+        Ok((gas_deficit_amount, 0))
     }
 
     fn process_receipt(
@@ -959,6 +1274,13 @@ impl Runtime {
                     );
                     // Save the receipt itself into the state.
                     set_postponed_receipt(state_update, &receipt);
+                    if checked_feature!(
+                        "protocol_feature_postponed_receipt_timeout",
+                        PostponedReceiptTimeout,
+                        apply_state.current_protocol_version
+                    ) {
+                        Self::enqueue_postponed_receipt_expiry(state_update, apply_state, receipt)?;
+                    }
                 }
             }
         };
@@ -968,6 +1290,265 @@ impl Runtime {
         Ok(None)
     }
 
+    /// Adds the postponed receipt to the end of the expiry queue in the state.
+    fn enqueue_postponed_receipt_expiry(
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        receipt: &Receipt,
+    ) -> Result<(), StorageError> {
+        let mut indices: DelayedReceiptIndices =
+            get(state_update, &TrieKey::PostponedReceiptExpiryIndices)?.unwrap_or_default();
+        set(
+            state_update,
+            TrieKey::PostponedReceiptExpiry { index: indices.next_available_index },
+            &PostponedReceiptExpiry {
+                receiver_id: receipt.receiver_id.clone(),
+                receipt_id: receipt.receipt_id,
+                postponed_height: apply_state.block_index,
+                postponed_epoch_height: apply_state.epoch_height,
+            },
+        );
+        indices.next_available_index =
+            indices.next_available_index.checked_add(1).ok_or_else(|| {
+                StorageError::StorageInconsistentState(
+                    "Next available index for postponed receipt expiry exceeded the integer limit"
+                        .to_string(),
+                )
+            })?;
+        set(state_update, TrieKey::PostponedReceiptExpiryIndices, &indices);
+        Ok(())
+    }
+
+    /// Fails the postponed receipts which have been waiting for their input data for
+    /// `postponed_receipt_timeout_epochs` or longer. The entries of the expiry queue whose
+    /// receipts were executed in the meantime are dropped. At most
+    /// `MAX_EXPIRED_POSTPONED_RECEIPTS` entries are processed per chunk.
+    fn expire_postponed_receipts(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        outgoing_receipts: &mut Vec<Receipt>,
+        outcomes: &mut Vec<ExecutionOutcomeWithId>,
+    ) -> Result<(), RuntimeError> {
+        let timeout_epochs = apply_state.config.postponed_receipt_timeout_epochs;
+        if timeout_epochs == 0 {
+            return Ok(());
+        }
+        let mut indices: DelayedReceiptIndices =
+            get(state_update, &TrieKey::PostponedReceiptExpiryIndices)?.unwrap_or_default();
+        let initial_first_index = indices.first_index;
+        while indices.first_index < indices.next_available_index
+            && indices.first_index - initial_first_index < MAX_EXPIRED_POSTPONED_RECEIPTS
+        {
+            let key = TrieKey::PostponedReceiptExpiry { index: indices.first_index };
+            let expiry: PostponedReceiptExpiry = get(state_update, &key)?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Postponed receipt expiry #{} should be in the state",
+                    indices.first_index
+                ))
+            })?;
+            if expiry.postponed_epoch_height.saturating_add(timeout_epochs)
+                > apply_state.epoch_height
+            {
+                break;
+            }
+            state_update.remove(key);
+            // Math checked above: first_index is less than next_available_index
+            indices.first_index += 1;
+            if let Some(receipt) =
+                get_postponed_receipt(state_update, &expiry.receiver_id, expiry.receipt_id)?
+            {
+                outcomes.push(self.fail_postponed_receipt(
+                    state_update,
+                    apply_state,
+                    &receipt,
+                    outgoing_receipts,
+                )?);
+            }
+        }
+        if indices.first_index != initial_first_index {
+            set(state_update, TrieKey::PostponedReceiptExpiryIndices, &indices);
+        }
+        Ok(())
+    }
+
+    /// Fails the postponed receipt without executing it. Its input data is removed from the state,
+    /// its deposit and all its prepaid gas are refunded, and the receivers of its output data get
+    /// a failed result, as for any failed receipt.
+    fn fail_postponed_receipt(
+        &self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        receipt: &Receipt,
+        outgoing_receipts: &mut Vec<Receipt>,
+    ) -> Result<ExecutionOutcomeWithId, RuntimeError> {
+        let action_receipt = match &receipt.receipt {
+            ReceiptEnum::Action(action_receipt) => action_receipt,
+            _ => unreachable!("postponed receipt should be an action receipt"),
+        };
+        let account_id = &receipt.receiver_id;
+        for data_id in action_receipt.input_data_ids.iter() {
+            if get_received_data(state_update, account_id, *data_id)?.is_some() {
+                state_update.remove(TrieKey::ReceivedData {
+                    receiver_id: account_id.clone(),
+                    data_id: *data_id,
+                });
+            } else {
+                state_update.remove(TrieKey::PostponedReceiptId {
+                    receiver_id: account_id.clone(),
+                    data_id: *data_id,
+                });
+            }
+        }
+        state_update.remove(TrieKey::PendingDataCount {
+            receiver_id: account_id.clone(),
+            receipt_id: receipt.receipt_id,
+        });
+        remove_postponed_receipt(state_update, account_id, receipt.receipt_id);
+        state_update
+            .commit(StateChangeCause::PostponedReceiptExpired { receipt_hash: receipt.get_hash() });
+
+        let error =
+            ActionErrorKind::PostponedReceiptExpired { account_id: account_id.clone() }.into();
+        self.fail_action_receipt(apply_state, receipt, action_receipt, error, outgoing_receipts)
+    }
+
+    /// Fails the action receipt with the given error without executing it. Its deposit and all
+    /// its prepaid gas are refunded, and the receivers of its output data get a failed result.
+    fn fail_action_receipt(
+        &self,
+        apply_state: &ApplyState,
+        receipt: &Receipt,
+        action_receipt: &ActionReceipt,
+        error: ActionError,
+        outgoing_receipts: &mut Vec<Receipt>,
+    ) -> Result<ExecutionOutcomeWithId, RuntimeError> {
+        let account_id = &receipt.receiver_id;
+        let mut result = ActionResult { result: Err(error.clone()), ..Default::default() };
+        // No gas is burnt, so the whole receipt cost is refunded.
+        let (_, local_gas_refund) = self.generate_refund_receipts(
+            apply_state.gas_price,
+            receipt,
+            action_receipt,
+            &mut result,
+            0,
+            apply_state.current_protocol_version,
+            &apply_state.config.transaction_costs,
+        )?;
+        if local_gas_refund > 0 {
+            result.new_receipts.push(Receipt::new_gas_refund(
+                &action_receipt.signer_id,
+                local_gas_refund,
+                action_receipt.signer_public_key.clone(),
+            ));
+        }
+        result.new_receipts.extend(action_receipt.output_data_receivers.iter().map(
+            |data_receiver| Receipt {
+                predecessor_id: account_id.clone(),
+                receiver_id: data_receiver.receiver_id.clone(),
+                receipt_id: CryptoHash::default(),
+                receipt: ReceiptEnum::Data(DataReceipt {
+                    data_id: data_receiver.data_id,
+                    data: None,
+                }),
+            },
+        ));
+
+        let mut receipt_ids = vec![];
+        for (receipt_index, mut new_receipt) in result.new_receipts.into_iter().enumerate() {
+            new_receipt.receipt_id = create_receipt_id_from_receipt(
+                apply_state.current_protocol_version,
+                receipt,
+                &apply_state.prev_block_hash,
+                &apply_state.block_hash,
+                receipt_index,
+            );
+            if let ReceiptEnum::Action(_) = new_receipt.receipt {
+                receipt_ids.push(new_receipt.receipt_id);
+            }
+            outgoing_receipts.push(new_receipt);
+        }
+
+        Ok(ExecutionOutcomeWithId {
+            id: receipt.receipt_id,
+            outcome: ExecutionOutcome {
+                status: ExecutionStatus::Failure(TxExecutionError::ActionError(error)),
+                logs: vec![],
+                receipt_ids,
+                gas_burnt: 0,
+                tokens_burnt: 0,
+                executor_id: account_id.clone(),
+                metadata: ExecutionMetadata::ExecutionMetadataV1,
+            },
+        })
+    }
+
+    /// Fails the receipts of the transactions to receivers with `max_delayed_receipts_per_account`
+    /// or more delayed receipts and returns the other receipts. The delayed receipts of a receiver
+    /// are only counted in its shard, so the limit is enforced once the receipts reach it, before
+    /// they are executed or delayed. The receipts of transactions are the ones whose predecessor
+    /// is the signer. Stake receipts are never rejected.
+    fn reject_congested_receipts<'a>(
+        &self,
+        state_update: &TrieUpdate,
+        apply_state: &ApplyState,
+        receipts: Vec<&'a Receipt>,
+        outgoing_receipts: &mut Vec<Receipt>,
+        outcomes: &mut Vec<ExecutionOutcomeWithId>,
+    ) -> Result<Vec<&'a Receipt>, RuntimeError> {
+        let limit = apply_state.config.max_delayed_receipts_per_account;
+        if limit == 0
+            || !checked_feature!(
+                "protocol_feature_receipt_congestion_control",
+                ReceiptCongestionControl,
+                apply_state.current_protocol_version
+            )
+        {
+            return Ok(receipts);
+        }
+        let mut accepted_receipts = vec![];
+        for receipt in receipts {
+            let action_receipt = match &receipt.receipt {
+                ReceiptEnum::Action(action_receipt)
+                    if receipt.predecessor_id == action_receipt.signer_id
+                        && !receipt.is_priority() =>
+                {
+                    action_receipt
+                }
+                _ => {
+                    accepted_receipts.push(receipt);
+                    continue;
+                }
+            };
+            let delayed_receipts = get_delayed_receipts_count(state_update, &receipt.receiver_id)?;
+            if delayed_receipts < limit {
+                accepted_receipts.push(receipt);
+                continue;
+            }
+            // Invalid receipts fail the chunk whether their receiver is congested or not.
+            validate_receipt(
+                &apply_state.config.wasm_config.limit_config,
+                receipt,
+                apply_state.current_protocol_version,
+            )
+            .map_err(RuntimeError::ReceiptValidationError)?;
+            let error = ActionErrorKind::ReceiverCongested {
+                receiver_id: receipt.receiver_id.clone(),
+                delayed_receipts,
+                limit,
+            }
+            .into();
+            outcomes.push(self.fail_action_receipt(
+                apply_state,
+                receipt,
+                action_receipt,
+                error,
+                outgoing_receipts,
+            )?);
+        }
+        Ok(accepted_receipts)
+    }
+
     /// Iterates over the validators in the current shard and updates their accounts to return stake
     /// and allocate rewards. Also updates protocol treasure account if it belongs to the current
     /// shard.
@@ -975,8 +1556,14 @@ impl Runtime {
         &self,
         state_update: &mut TrieUpdate,
         validator_accounts_update: &ValidatorAccountsUpdate,
+        apply_state: &ApplyState,
         stats: &mut ApplyStats,
     ) -> Result<(), RuntimeError> {
+        let is_slashing_escrow_enabled = checked_feature!(
+            "protocol_feature_slashing_escrow",
+            SlashingEscrow,
+            apply_state.current_protocol_version
+        );
         for (account_id, max_of_stakes) in &validator_accounts_update.stake_info {
             if let Some(mut account) = get_account(state_update, account_id)? {
                 if let Some(reward) = validator_accounts_update.validator_rewards.get(account_id) {
@@ -1041,10 +1628,29 @@ impl Runtime {
                         "FATAL: staking invariant does not hold. Account locked {} is less than slashed {}",
                         account.locked(), amount_to_slash)).into());
                 }
-                stats.slashed_burnt_amount = stats
-                    .slashed_burnt_amount
-                    .checked_add(amount_to_slash)
-                    .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?;
+                if is_slashing_escrow_enabled {
+                    let mut slash_escrow =
+                        get_slash_escrow(state_update, account_id)?.unwrap_or_default();
+                    slash_escrow.amount = slash_escrow
+                        .amount
+                        .checked_add(amount_to_slash)
+                        .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?;
+                    // Every slash has an appeal window of its own.
+                    slash_escrow.slashes.push(EscrowedSlash {
+                        block_hash: apply_state.block_hash,
+                        amount: amount_to_slash,
+                        release_epoch_height: apply_state
+                            .epoch_height
+                            .checked_add(validator_accounts_update.slashing_escrow_epochs)
+                            .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?,
+                    });
+                    set_slash_escrow(state_update, account_id.clone(), &slash_escrow);
+                } else {
+                    stats.slashed_burnt_amount = stats
+                        .slashed_burnt_amount
+                        .checked_add(amount_to_slash)
+                        .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?;
+                }
                 account.set_locked(
                     account
                         .locked()
@@ -1061,7 +1667,16 @@ impl Runtime {
             }
         }
 
-        if let Some(account_id) = &validator_accounts_update.protocol_treasury_account_id {
+        if is_slashing_escrow_enabled {
+            self.resolve_slash_escrows(
+                state_update,
+                validator_accounts_update,
+                apply_state.epoch_height,
+                stats,
+            )?;
+        }
+
+        for account_id in validator_accounts_update.protocol_treasury_account_ids.iter() {
             // If protocol treasury stakes, then the rewards was already distributed above.
             if !validator_accounts_update.stake_info.contains_key(account_id) {
                 let mut account = get_account(state_update, account_id)?.ok_or_else(|| {
@@ -1088,19 +1703,118 @@ impl Runtime {
                 set_account(state_update, account_id.clone(), &account);
             }
         }
+
+        if let Some(developer_reward) = validator_accounts_update.developer_reward {
+            self.distribute_developer_reward(state_update, developer_reward, stats)?;
+        }
         state_update.commit(StateChangeCause::ValidatorAccountsUpdate);
 
         Ok(())
     }
 
-    pub fn apply_migrations(
+    /// Pays the developer reward to the contracts by their share of the gas burnt by function
+    /// calls in the epoch and resets the recorded gas. The rounding and the shares of the deleted
+    /// accounts are burnt.
+    fn distribute_developer_reward(
         &self,
         state_update: &mut TrieUpdate,
-        migration_data: &Arc<MigrationData>,
-        migration_flags: &MigrationFlags,
-        protocol_version: ProtocolVersion,
-    ) -> Result<(Gas, Vec<Receipt>), StorageError> {
-        let mut gas_used: Gas = 0;
+        developer_reward: Balance,
+        stats: &mut ApplyStats,
+    ) -> Result<(), RuntimeError> {
+        let contract_gas_burnt = get_all_contract_gas_burnt(state_update)?;
+        let total_gas_burnt: u128 =
+            contract_gas_burnt.iter().map(|(_, gas_burnt)| *gas_burnt as u128).sum();
+        let mut burnt_reward = developer_reward;
+        for (account_id, gas_burnt) in contract_gas_burnt {
+            if let Some(mut account) = get_account(state_update, &account_id)? {
+                let reward = (BigUint::from(developer_reward) * gas_burnt
+                    / BigUint::from(total_gas_burnt))
+                .to_u128()
+                .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?;
+                debug!(target: "runtime", "account {} adding developer reward {}", account_id, reward);
+                account.set_amount(safe_add_balance(account.amount(), reward)?);
+                set_account(state_update, account_id.clone(), &account);
+                burnt_reward -= reward;
+            }
+            remove_contract_gas_burnt(state_update, account_id);
+        }
+        stats.other_burnt_amount = safe_add_balance(stats.other_burnt_amount, burnt_reward)?;
+        Ok(())
+    }
+
+    /// Returns the escrowed stake of the appealed slashes and burns the escrowed stake of the
+    /// slashes for which the appeal window is over. The other slashes stay in escrow.
+    fn resolve_slash_escrows(
+        &self,
+        state_update: &mut TrieUpdate,
+        validator_accounts_update: &ValidatorAccountsUpdate,
+        epoch_height: EpochHeight,
+        stats: &mut ApplyStats,
+    ) -> Result<(), RuntimeError> {
+        for (account_id, slash_escrow) in get_slash_escrows(state_update)? {
+            let appealed_block_hash = validator_accounts_update.slash_appeals.get(&account_id);
+            let (resolved, pending): (Vec<_>, Vec<_>) =
+                slash_escrow.slashes.into_iter().partition(|slash| {
+                    Some(&slash.block_hash) == appealed_block_hash
+                        || slash.release_epoch_height <= epoch_height
+                });
+            if resolved.is_empty() {
+                continue;
+            }
+            let mut account = get_account(state_update, &account_id)?;
+            let mut remaining_amount = slash_escrow.amount;
+            for slash in resolved {
+                remaining_amount = remaining_amount.checked_sub(slash.amount).ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Escrowed slashes of {} exceed the escrowed amount",
+                        account_id
+                    ))
+                })?;
+                match account.as_mut() {
+                    Some(account) if Some(&slash.block_hash) == appealed_block_hash => {
+                        debug!(target: "runtime", "returning escrowed slash {} to {}", slash.amount, account_id);
+                        account.set_amount(
+                            account
+                                .amount()
+                                .checked_add(slash.amount)
+                                .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?,
+                        );
+                    }
+                    _ => {
+                        // The appeal window is over or the appealed account doesn't exist anymore.
+                        debug!(target: "runtime", "burning escrowed slash {} of {}", slash.amount, account_id);
+                        stats.slashed_burnt_amount = stats
+                            .slashed_burnt_amount
+                            .checked_add(slash.amount)
+                            .ok_or_else(|| RuntimeError::UnexpectedIntegerOverflow)?;
+                    }
+                }
+            }
+            if let Some(account) = account {
+                set_account(state_update, account_id.clone(), &account);
+            }
+            if pending.is_empty() {
+                remove_slash_escrow(state_update, account_id);
+            } else {
+                set_slash_escrow(
+                    state_update,
+                    account_id,
+                    &SlashEscrow { amount: remaining_amount, slashes: pending },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_migrations(
+        &self,
+        state_update: &mut TrieUpdate,
+        migration_data: &Arc<MigrationData>,
+        migration_flags: &MigrationFlags,
+        protocol_version: ProtocolVersion,
+        stats: &mut ApplyStats,
+    ) -> Result<(Gas, Vec<Receipt>), StorageError> {
+        let mut gas_used: Gas = 0;
         if ProtocolFeature::FixStorageUsage.protocol_version() == protocol_version
             && migration_flags.is_first_block_of_version
         {
@@ -1110,7 +1824,13 @@ impl Runtime {
                         // Storage usage is saved in state, hence it is nowhere close to max value
                         // of u64, and maximal delta is 4196, se we can add here without checking
                         // for overflow
-                        account.set_storage_usage(account.storage_usage() + delta);
+                        let storage_usage = account.storage_usage() + delta;
+                        stats.add_storage_usage_delta(
+                            account_id,
+                            account.storage_usage(),
+                            storage_usage,
+                        );
+                        account.set_storage_usage(storage_usage);
                         set_account(state_update, account_id.clone(), &account);
                     }
                     // Account could have been deleted in the meantime
@@ -1142,6 +1862,45 @@ impl Runtime {
         Ok((gas_used, receipts_to_restore))
     }
 
+    /// Records the amounts burnt and the gas deficit of the chunk in the burn ledger of the shard.
+    /// Chunks which burnt nothing and had no deficit leave the ledger as is.
+    fn update_burn_ledger(
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        stats: &ApplyStats,
+    ) -> Result<(), RuntimeError> {
+        if !checked_feature!(
+            "protocol_feature_burn_ledger",
+            BurnLedger,
+            apply_state.current_protocol_version
+        ) {
+            return Ok(());
+        }
+        let burnt_amount = safe_add_balance(
+            safe_add_balance(stats.tx_burnt_amount, stats.slashed_burnt_amount)?,
+            stats.other_burnt_amount,
+        )?;
+        if burnt_amount == 0 && stats.gas_deficit_amount == 0 {
+            return Ok(());
+        }
+        let burn_ledger = get_burn_ledger(state_update)?;
+        let burn_ledger = BurnLedger {
+            block_height: apply_state.block_index,
+            tx_burnt_amount: stats.tx_burnt_amount,
+            slashed_burnt_amount: stats.slashed_burnt_amount,
+            other_burnt_amount: stats.other_burnt_amount,
+            gas_deficit_amount: stats.gas_deficit_amount,
+            total_burnt_amount: safe_add_balance(burn_ledger.total_burnt_amount, burnt_amount)?,
+            total_gas_deficit_amount: safe_add_balance(
+                burn_ledger.total_gas_deficit_amount,
+                stats.gas_deficit_amount,
+            )?,
+        };
+        set_burn_ledger(state_update, &burn_ledger);
+        state_update.commit(StateChangeCause::UpdatedBurnLedger);
+        Ok(())
+    }
+
     /// Applies new singed transactions and incoming receipts for some chunk/shard on top of
     /// given trie and the given state root.
     /// If the validator accounts update is provided, updates validators accounts.
@@ -1168,7 +1927,7 @@ impl Runtime {
             panic!("Can only patch state in sandbox mode");
         }
 
-        let trie = Rc::new(trie);
+        let trie = Arc::new(trie);
         let initial_state = TrieUpdate::new(trie.clone(), root);
         let mut state_update = TrieUpdate::new(trie.clone(), root);
 
@@ -1178,6 +1937,7 @@ impl Runtime {
             self.update_validator_accounts(
                 &mut state_update,
                 validator_accounts_update,
+                apply_state,
                 &mut stats,
             )?;
         }
@@ -1188,6 +1948,7 @@ impl Runtime {
                 &apply_state.migration_data,
                 &apply_state.migration_flags,
                 apply_state.current_protocol_version,
+                &mut stats,
             )
             .map_err(|e| RuntimeError::StorageError(e))?;
         // If we have receipts that need to be restored, prepend them to the list of incoming receipts
@@ -1202,6 +1963,7 @@ impl Runtime {
             && apply_state.current_protocol_version
                 >= ProtocolFeature::FixApplyChunks.protocol_version()
         {
+            Self::update_burn_ledger(&mut state_update, apply_state, &stats)?;
             let (trie_changes, state_changes) = state_update.finalize()?;
             let proof = trie.recorded_storage();
             return Ok(ApplyResult {
@@ -1225,11 +1987,24 @@ impl Runtime {
         // limit
         let mut total_gas_burnt = gas_used_for_migrations;
 
-        for signed_transaction in transactions {
+        // Signatures don't depend on the state, so they are verified in parallel ahead of the
+        // serial processing. Transactions with invalid signatures are verified again in order, so
+        // they fail at the same point with the same error.
+        let signatures_valid = transactions
+            .par_iter()
+            .map(|signed_transaction| {
+                signed_transaction.signature.verify(
+                    signed_transaction.get_hash().as_ref(),
+                    &signed_transaction.transaction.public_key,
+                )
+            })
+            .collect::<Vec<_>>();
+        for (signed_transaction, signature_valid) in transactions.iter().zip(signatures_valid) {
             let (receipt, outcome_with_id) = self.process_transaction(
                 &mut state_update,
                 apply_state,
                 signed_transaction,
+                !signature_valid,
                 &mut stats,
             )?;
             if receipt.receiver_id == signed_transaction.transaction.signer_id {
@@ -1246,6 +2021,24 @@ impl Runtime {
         let mut delayed_receipts_indices: DelayedReceiptIndices =
             get(&state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
         let initial_delayed_receipt_indices = delayed_receipts_indices.clone();
+        let mut delayed_priority_receipts_indices: DelayedReceiptIndices =
+            get(&state_update, &TrieKey::DelayedPriorityReceiptIndices)?.unwrap_or_default();
+        let initial_delayed_priority_receipt_indices = delayed_priority_receipts_indices.clone();
+
+        let local_receipts = self.reject_congested_receipts(
+            &state_update,
+            apply_state,
+            local_receipts.iter().collect(),
+            &mut outgoing_receipts,
+            &mut outcomes,
+        )?;
+        let new_incoming_receipts = self.reject_congested_receipts(
+            &state_update,
+            apply_state,
+            incoming_receipts.iter().collect(),
+            &mut outgoing_receipts,
+            &mut outcomes,
+        )?;
 
         let mut process_receipt = |receipt: &Receipt,
                                    state_update: &mut TrieUpdate,
@@ -1274,14 +2067,92 @@ impl Runtime {
 
         let gas_limit = apply_state.gas_limit.unwrap_or(Gas::max_value());
 
+        // Refunds and stake receipts are issued on behalf of the protocol. Once the feature is
+        // enabled they are processed before any user receipt, delayed or not, so that congestion
+        // caused by users can't postpone them. They can only use a share of the gas limit though,
+        // the rest of them is delayed to a queue of their own, which is processed first in the
+        // next chunks. The relative order of receipts within each group is preserved.
+        let (priority_incoming_receipts, other_incoming_receipts): (Vec<&Receipt>, Vec<&Receipt>) =
+            if checked_feature!(
+                "protocol_feature_priority_receipts",
+                PriorityReceipts,
+                apply_state.current_protocol_version
+            ) {
+                new_incoming_receipts.into_iter().partition(|receipt| receipt.is_priority())
+            } else {
+                (vec![], new_incoming_receipts)
+            };
+        let priority_gas_limit = gas_limit / PRIORITY_RECEIPTS_GAS_LIMIT_DIVISOR;
+        let mut priority_gas_burnt: Gas = 0;
+
+        while delayed_priority_receipts_indices.first_index
+            < delayed_priority_receipts_indices.next_available_index
+        {
+            if priority_gas_burnt >= priority_gas_limit {
+                break;
+            }
+            let index = delayed_priority_receipts_indices.first_index;
+            let key = TrieKey::DelayedPriorityReceipt { index };
+            let receipt: Receipt = get(&state_update, &key)?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed priority receipt #{} should be in the state",
+                    index
+                ))
+            })?;
+            validate_receipt(
+                &apply_state.config.wasm_config.limit_config,
+                &receipt,
+                apply_state.current_protocol_version,
+            )
+            .map_err(|e| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed priority receipt #{} in the state is invalid: {}",
+                    index, e
+                ))
+            })?;
+            state_update.remove(key);
+            // Math checked above: first_index is less than next_available_index
+            delayed_priority_receipts_indices.first_index += 1;
+            let gas_burnt_before = total_gas_burnt;
+            process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+            priority_gas_burnt =
+                safe_add_gas(priority_gas_burnt, total_gas_burnt - gas_burnt_before)?;
+        }
+
+        for receipt in priority_incoming_receipts {
+            validate_receipt(
+                &apply_state.config.wasm_config.limit_config,
+                receipt,
+                apply_state.current_protocol_version,
+            )
+            .map_err(RuntimeError::ReceiptValidationError)?;
+            if priority_gas_burnt < priority_gas_limit {
+                let gas_burnt_before = total_gas_burnt;
+                process_receipt(receipt, &mut state_update, &mut total_gas_burnt)?;
+                priority_gas_burnt =
+                    safe_add_gas(priority_gas_burnt, total_gas_burnt - gas_burnt_before)?;
+            } else {
+                Self::delay_priority_receipt(
+                    &mut state_update,
+                    &mut delayed_priority_receipts_indices,
+                    receipt,
+                )?;
+            }
+        }
+
         // We first process local receipts. They contain staking, local contract calls, etc.
-        for receipt in local_receipts.iter() {
+        for receipt in local_receipts {
             if total_gas_burnt < gas_limit {
                 // NOTE: We don't need to validate the local receipt, because it's just validated in
                 // the `verify_and_charge_transaction`.
-                process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+                process_receipt(receipt, &mut state_update, &mut total_gas_burnt)?;
             } else {
-                Self::delay_receipt(&mut state_update, &mut delayed_receipts_indices, receipt)?;
+                Self::delay_receipt(
+                    &mut state_update,
+                    &mut delayed_receipts_indices,
+                    receipt,
+                    apply_state.current_protocol_version,
+                )?;
             }
         }
 
@@ -1299,37 +2170,83 @@ impl Runtime {
             })?;
 
             // Validating the delayed receipt. If it fails, it's likely the state is inconsistent.
-            validate_receipt(&apply_state.config.wasm_config.limit_config, &receipt).map_err(
-                |e| {
-                    StorageError::StorageInconsistentState(format!(
-                        "Delayed receipt #{} in the state is invalid: {}",
-                        delayed_receipts_indices.first_index, e
-                    ))
-                },
-            )?;
+            validate_receipt(
+                &apply_state.config.wasm_config.limit_config,
+                &receipt,
+                apply_state.current_protocol_version,
+            )
+            .map_err(|e| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed receipt #{} in the state is invalid: {}",
+                    delayed_receipts_indices.first_index, e
+                ))
+            })?;
 
             state_update.remove(key);
             // Math checked above: first_index is less than next_available_index
             delayed_receipts_indices.first_index += 1;
+            if checked_feature!(
+                "protocol_feature_receipt_congestion_control",
+                ReceiptCongestionControl,
+                apply_state.current_protocol_version
+            ) {
+                // The receipts delayed before the protocol upgrade weren't counted.
+                let count = get_delayed_receipts_count(&state_update, &receipt.receiver_id)?;
+                set_delayed_receipts_count(
+                    &mut state_update,
+                    receipt.receiver_id.clone(),
+                    count.saturating_sub(1),
+                );
+            }
             process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
         }
 
         // And then we process the new incoming receipts. These are receipts from other shards.
-        for receipt in incoming_receipts.iter() {
+        for receipt in other_incoming_receipts {
             // Validating new incoming no matter whether we have available gas or not. We don't
             // want to store invalid receipts in state as delayed.
-            validate_receipt(&apply_state.config.wasm_config.limit_config, &receipt)
-                .map_err(RuntimeError::ReceiptValidationError)?;
+            validate_receipt(
+                &apply_state.config.wasm_config.limit_config,
+                &receipt,
+                apply_state.current_protocol_version,
+            )
+            .map_err(RuntimeError::ReceiptValidationError)?;
             if total_gas_burnt < gas_limit {
                 process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
             } else {
-                Self::delay_receipt(&mut state_update, &mut delayed_receipts_indices, receipt)?;
+                Self::delay_receipt(
+                    &mut state_update,
+                    &mut delayed_receipts_indices,
+                    receipt,
+                    apply_state.current_protocol_version,
+                )?;
             }
         }
 
+        // Finally we fail the postponed receipts which didn't receive their input data in time.
+        if checked_feature!(
+            "protocol_feature_postponed_receipt_timeout",
+            PostponedReceiptTimeout,
+            apply_state.current_protocol_version
+        ) {
+            self.expire_postponed_receipts(
+                &mut state_update,
+                apply_state,
+                &mut outgoing_receipts,
+                &mut outcomes,
+            )?;
+        }
+
         if delayed_receipts_indices != initial_delayed_receipt_indices {
             set(&mut state_update, TrieKey::DelayedReceiptIndices, &delayed_receipts_indices);
         }
+        if delayed_priority_receipts_indices != initial_delayed_priority_receipt_indices {
+            set(
+                &mut state_update,
+                TrieKey::DelayedPriorityReceiptIndices,
+                &delayed_priority_receipts_indices,
+            );
+        }
 
         check_balance(
             &apply_state.config.transaction_costs,
@@ -1344,6 +2261,7 @@ impl Runtime {
         )?;
 
         state_update.commit(StateChangeCause::UpdatedDelayedReceipts);
+        Self::update_burn_ledger(&mut state_update, apply_state, &stats)?;
 
         #[cfg(feature = "sandbox")]
         if let Some(patch) = states_to_patch {
@@ -1379,16 +2297,73 @@ impl Runtime {
     }
 
     // Adds the given receipt into the end of the delayed receipt queue in the state.
+    /// Executes exactly one receipt on top of `state_root`, to find out why it failed. The
+    /// executed actions are traced and the gas is profiled from scratch. Neither the delayed
+    /// receipts queue nor the validator accounts are touched, and migrations are not applied.
+    /// The state changes are returned instead of being committed.
+    pub fn replay_receipt(
+        &self,
+        trie: Trie,
+        state_root: CryptoHash,
+        apply_state: &ApplyState,
+        receipt: &Receipt,
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<ReplayReceiptResult, RuntimeError> {
+        let _span = tracing::debug_span!(target: "runtime", "Runtime::replay_receipt").entered();
+
+        let tracer = Arc::new(ActionTracer::default());
+        let apply_state = ApplyState {
+            profile: ProfileData::new(),
+            observer: Some(tracer.clone()),
+            ..apply_state.clone()
+        };
+        let mut state_update = TrieUpdate::new(Arc::new(trie), state_root);
+        let mut outgoing_receipts = vec![];
+        let mut validator_proposals = vec![];
+        let mut stats = ApplyStats::default();
+        let outcome = self.process_receipt(
+            &mut state_update,
+            &apply_state,
+            receipt,
+            &mut outgoing_receipts,
+            &mut validator_proposals,
+            &mut stats,
+            epoch_info_provider,
+        )?;
+        let (_, state_changes) = state_update.finalize()?;
+        let actions = std::mem::take(&mut *tracer.0.lock().unwrap());
+        Ok(ReplayReceiptResult {
+            outcome,
+            actions,
+            outgoing_receipts,
+            state_changes,
+            profile: apply_state.profile,
+        })
+    }
+
     fn delay_receipt(
         state_update: &mut TrieUpdate,
         delayed_receipts_indices: &mut DelayedReceiptIndices,
         receipt: &Receipt,
+        current_protocol_version: ProtocolVersion,
     ) -> Result<(), StorageError> {
         set(
             state_update,
             TrieKey::DelayedReceipt { index: delayed_receipts_indices.next_available_index },
             receipt,
         );
+        if checked_feature!(
+            "protocol_feature_receipt_congestion_control",
+            ReceiptCongestionControl,
+            current_protocol_version
+        ) {
+            let count = get_delayed_receipts_count(state_update, &receipt.receiver_id)?;
+            set_delayed_receipts_count(
+                state_update,
+                receipt.receiver_id.clone(),
+                count.saturating_add(1),
+            );
+        }
         delayed_receipts_indices.next_available_index =
             delayed_receipts_indices.next_available_index.checked_add(1).ok_or_else(|| {
                 StorageError::StorageInconsistentState(
@@ -1399,6 +2374,32 @@ impl Runtime {
         Ok(())
     }
 
+    /// Appends a priority receipt to the delayed priority receipts queue. These receipts aren't
+    /// counted towards the congestion of their receivers.
+    fn delay_priority_receipt(
+        state_update: &mut TrieUpdate,
+        delayed_priority_receipts_indices: &mut DelayedReceiptIndices,
+        receipt: &Receipt,
+    ) -> Result<(), StorageError> {
+        set(
+            state_update,
+            TrieKey::DelayedPriorityReceipt {
+                index: delayed_priority_receipts_indices.next_available_index,
+            },
+            receipt,
+        );
+        delayed_priority_receipts_indices.next_available_index = delayed_priority_receipts_indices
+            .next_available_index
+            .checked_add(1)
+            .ok_or_else(|| {
+                StorageError::StorageInconsistentState(
+                    "Next available index for delayed priority receipt exceeded the integer limit"
+                        .to_string(),
+                )
+            })?;
+        Ok(())
+    }
+
     #[cfg(feature = "sandbox")]
     fn apply_state_patches(
         &self,
@@ -1462,7 +2463,7 @@ mod tests {
     use near_crypto::{InMemorySigner, KeyType, Signer};
     use near_primitives::account::AccessKey;
     use near_primitives::contract::ContractCode;
-    use near_primitives::errors::ReceiptValidationError;
+    use near_primitives::errors::{InvalidTxError, ReceiptValidationError};
     use near_primitives::hash::hash;
     use near_primitives::profile::ProfileData;
     use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
@@ -1576,6 +2577,7 @@ mod tests {
             block_timestamp: 100,
             gas_limit: Some(gas_limit),
             random_seed: Default::default(),
+            epoch_random_seed: Default::default(),
             current_protocol_version: PROTOCOL_VERSION,
             config: Arc::new(RuntimeConfig::default()),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
@@ -1585,6 +2587,7 @@ mod tests {
             profile: ProfileData::new(),
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
+            observer: None,
         };
 
         (runtime, tries, root, apply_state, signer, MockEpochInfoProvider::default())
@@ -1620,8 +2623,11 @@ mod tests {
             stake_info: vec![(alice_account(), initial_locked)].into_iter().collect(),
             validator_rewards: vec![(alice_account(), reward)].into_iter().collect(),
             last_proposals: Default::default(),
-            protocol_treasury_account_id: None,
+            protocol_treasury_account_ids: vec![],
             slashing_info: HashMap::default(),
+            slash_appeals: HashMap::default(),
+            slashing_escrow_epochs: 0,
+            developer_reward: None,
         };
 
         runtime
@@ -1722,36 +2728,82 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "protocol_feature_priority_receipts")]
     #[test]
-    fn test_apply_delayed_receipts_add_more_using_chunks() {
+    fn test_apply_priority_receipts_before_delayed() {
         let initial_balance = to_yocto(1_000_000);
         let initial_locked = to_yocto(500_000);
-        let small_transfer = to_yocto(10_000);
-        let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
+        let small_transfer = to_yocto(1);
+        let refund = to_yocto(10_000);
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
             setup_runtime(initial_balance, initial_locked, 1);
+        let receipt_gas_cost = apply_state
+            .config
+            .transaction_costs
+            .action_receipt_creation_config
+            .exec_fee()
+            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
+        apply_state.gas_limit = Some(receipt_gas_cost * 2);
+
+        let mut receipts = generate_receipts(small_transfer, 3);
+        let mut refund_receipt = Receipt::new_balance_refund(&alice_account(), refund);
+        refund_receipt.receipt_id = hash(b"refund");
+        receipts.push(refund_receipt);
+
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state = tries.new_trie_update(0, root);
+        let account = get_account(&state, &alice_account()).unwrap().unwrap();
+        // The refund is the last receipt, but it's not delayed despite the gas limit.
+        assert!(account.amount() >= initial_balance + refund);
+        assert_eq!(apply_result.outcomes[0].id, hash(b"refund"));
+    }
 
+    #[cfg(feature = "protocol_feature_priority_receipts")]
+    #[test]
+    fn test_apply_delayed_priority_receipts_first() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(1);
+        let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 1);
         let receipt_gas_cost = apply_state
             .config
             .transaction_costs
             .action_receipt_creation_config
             .exec_fee()
             + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
-        apply_state.gas_limit = Some(receipt_gas_cost * 3);
+        // Two receipts per chunk, only one of them can be a priority receipt.
+        apply_state.gas_limit = Some(receipt_gas_cost * 2);
 
-        let n = 40;
-        let receipts = generate_receipts(small_transfer, n);
-        let mut receipt_chunks = receipts.chunks_exact(4);
+        let mut refunds = generate_refund_receipts(small_transfer, 3);
+        for (i, refund) in refunds.iter_mut().enumerate() {
+            refund.receipt_id = hash(format!("refund{}", i).as_bytes());
+        }
+        let transfers = generate_receipts(small_transfer, 3);
+        let receipts: Vec<_> = transfers.iter().chain(refunds.iter()).cloned().collect();
 
-        // Every time we'll process 3 receipts, so we need n / 3 rounded up. Then we do 3 extra.
-        for i in 1..=n / 3 + 3 {
-            let prev_receipts: &[Receipt] = receipt_chunks.next().unwrap_or_default();
+        let mut processed = vec![];
+        for incoming_receipts in [receipts, vec![], vec![]].iter() {
             let apply_result = runtime
                 .apply(
                     tries.get_trie_for_shard(0),
                     root,
                     &None,
                     &apply_state,
-                    prev_receipts,
+                    incoming_receipts,
                     &[],
                     &epoch_info_provider,
                     None,
@@ -1760,66 +2812,439 @@ mod tests {
             let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
             root = new_root;
             store_update.commit().unwrap();
-            let state = tries.new_trie_update(0, root);
-            let account = get_account(&state, &alice_account()).unwrap().unwrap();
-            let capped_i = std::cmp::min(i * 3, n);
-            assert_eq!(
-                account.amount(),
-                initial_balance
-                    + small_transfer * Balance::from(capped_i)
-                    + Balance::from(capped_i * (capped_i - 1) / 2)
-            );
+            processed
+                .push(apply_result.outcomes.iter().map(|outcome| outcome.id).collect::<Vec<_>>());
         }
+        // The refunds over the gas available to the priority receipts are delayed, but processed
+        // before the transfers delayed earlier.
+        let refund_ids: Vec<_> = refunds.iter().map(|receipt| receipt.receipt_id).collect();
+        let transfer_ids: Vec<_> = transfers.iter().map(|receipt| receipt.receipt_id).collect();
+        assert_eq!(processed[0], vec![refund_ids[0], transfer_ids[0]]);
+        assert_eq!(processed[1], vec![refund_ids[1], transfer_ids[1]]);
+        assert_eq!(processed[2], vec![refund_ids[2], transfer_ids[2]]);
     }
 
+    #[cfg(feature = "protocol_feature_slashing_escrow")]
     #[test]
-    fn test_apply_delayed_receipts_adjustable_gas_limit() {
+    fn test_apply_slashing_escrow() {
         let initial_balance = to_yocto(1_000_000);
         let initial_locked = to_yocto(500_000);
-        let small_transfer = to_yocto(10_000);
+        let slashed = to_yocto(100_000);
         let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
-            setup_runtime(initial_balance, initial_locked, 1);
-
-        let receipt_gas_cost = apply_state
-            .config
-            .transaction_costs
-            .action_receipt_creation_config
-            .exec_fee()
-            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
-
-        let n = 120;
-        let receipts = generate_receipts(small_transfer, n);
-        let mut receipt_chunks = receipts.chunks_exact(4);
+            setup_runtime(initial_balance, initial_locked, 10u64.pow(15));
+        let validator_accounts_update =
+            |slashing_info: HashMap<AccountId, Option<Balance>>,
+             slash_appeals: HashMap<AccountId, CryptoHash>| ValidatorAccountsUpdate {
+                stake_info: Default::default(),
+                validator_rewards: Default::default(),
+                last_proposals: Default::default(),
+                protocol_treasury_account_ids: vec![],
+                slashing_info,
+                slash_appeals,
+                slashing_escrow_epochs: 2,
+                developer_reward: None,
+            };
 
-        let mut num_receipts_given = 0;
-        let mut num_receipts_processed = 0;
-        let mut num_receipts_per_block = 1;
-        // Test adjusts gas limit based on the number of receipt given and number of receipts processed.
-        while num_receipts_processed < n {
-            if num_receipts_given > num_receipts_processed {
-                num_receipts_per_block += 1;
-            } else if num_receipts_per_block > 1 {
-                num_receipts_per_block -= 1;
-            }
-            apply_state.gas_limit = Some(num_receipts_per_block * receipt_gas_cost);
-            let prev_receipts: &[Receipt] = receipt_chunks.next().unwrap_or_default();
-            num_receipts_given += prev_receipts.len() as u64;
+        let mut apply = |apply_state: &ApplyState, update: ValidatorAccountsUpdate| {
             let apply_result = runtime
                 .apply(
                     tries.get_trie_for_shard(0),
                     root,
-                    &None,
-                    &apply_state,
-                    prev_receipts,
+                    &Some(update),
+                    apply_state,
+                    &[],
                     &[],
                     &epoch_info_provider,
                     None,
                 )
                 .unwrap();
             let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
-            root = new_root;
             store_update.commit().unwrap();
-            let state = tries.new_trie_update(0, root);
+            root = new_root;
+            (tries.new_trie_update(0, root), apply_result.stats.slashed_burnt_amount)
+        };
+
+        // The slashed stake is moved to escrow instead of being burnt.
+        let first_slash_block = hash(b"first slash");
+        apply_state.block_hash = first_slash_block;
+        let slashing_info = vec![(alice_account(), Some(slashed))].into_iter().collect();
+        let (state, burnt) =
+            apply(&apply_state, validator_accounts_update(slashing_info, HashMap::default()));
+        assert_eq!(burnt, 0);
+        let account = get_account(&state, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.locked(), initial_locked - slashed);
+        let first_slash = EscrowedSlash {
+            block_hash: first_slash_block,
+            amount: slashed,
+            release_epoch_height: 2,
+        };
+        assert_eq!(
+            get_slash_escrow(&state, &alice_account()).unwrap(),
+            Some(SlashEscrow { amount: slashed, slashes: vec![first_slash.clone()] })
+        );
+
+        // A later slash gets an appeal window of its own.
+        apply_state.epoch_height = 1;
+        let second_slash_block = hash(b"second slash");
+        apply_state.block_hash = second_slash_block;
+        let slashing_info = vec![(alice_account(), Some(slashed))].into_iter().collect();
+        let (state, burnt) =
+            apply(&apply_state, validator_accounts_update(slashing_info, HashMap::default()));
+        assert_eq!(burnt, 0);
+        let second_slash = EscrowedSlash {
+            block_hash: second_slash_block,
+            amount: slashed,
+            release_epoch_height: 3,
+        };
+        assert_eq!(
+            get_slash_escrow(&state, &alice_account()).unwrap(),
+            Some(SlashEscrow {
+                amount: 2 * slashed,
+                slashes: vec![first_slash, second_slash.clone()]
+            })
+        );
+
+        // The appeal only returns the stake escrowed by the appealed slash.
+        let slash_appeals = vec![(alice_account(), first_slash_block)].into_iter().collect();
+        let (state, burnt) =
+            apply(&apply_state, validator_accounts_update(HashMap::default(), slash_appeals));
+        assert_eq!(burnt, 0);
+        let account = get_account(&state, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.amount(), initial_balance + slashed);
+        assert_eq!(
+            get_slash_escrow(&state, &alice_account()).unwrap(),
+            Some(SlashEscrow { amount: slashed, slashes: vec![second_slash] })
+        );
+
+        // Without an appeal the escrowed stake is burnt at expiry.
+        apply_state.epoch_height = 3;
+        let (state, burnt) =
+            apply(&apply_state, validator_accounts_update(HashMap::default(), HashMap::default()));
+        assert_eq!(burnt, slashed);
+        assert_eq!(get_slash_escrow(&state, &alice_account()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_distribute_developer_reward() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let developer_reward = 3_001;
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 10u64.pow(15));
+
+        // Bob's account doesn't exist, so his share is burnt together with the rounding.
+        let mut state = tries.new_trie_update(0, root);
+        set_contract_gas_burnt(&mut state, alice_account(), 1);
+        set_contract_gas_burnt(&mut state, bob_account(), 2);
+        state.commit(StateChangeCause::InitialState);
+        let trie_changes = state.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let validator_accounts_update = ValidatorAccountsUpdate {
+            stake_info: Default::default(),
+            validator_rewards: Default::default(),
+            last_proposals: Default::default(),
+            protocol_treasury_account_ids: vec![],
+            slashing_info: HashMap::default(),
+            slash_appeals: HashMap::default(),
+            slashing_escrow_epochs: 0,
+            developer_reward: Some(developer_reward),
+        };
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &Some(validator_accounts_update),
+                &apply_state,
+                &[],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state = tries.new_trie_update(0, root);
+        let account = get_account(&state, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.amount(), initial_balance + 1_000);
+        assert_eq!(apply_result.stats.other_burnt_amount, 2_001);
+        assert!(get_all_contract_gas_burnt(&state).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "protocol_feature_burn_ledger")]
+    #[test]
+    fn test_burn_ledger() {
+        let (runtime, tries, mut root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let mut apply = |block_index: u64, receipts: &[Receipt]| {
+            let apply_state = ApplyState { block_index, ..apply_state.clone() };
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+            (apply_result.stats, get_burn_ledger(&tries.new_trie_update(0, root)).unwrap())
+        };
+        let transfer = create_receipts_with_actions(
+            alice_account(),
+            signer,
+            vec![Action::Transfer(TransferAction { deposit: 1 })],
+        );
+
+        let (stats, first) = apply(1, &transfer);
+        assert!(stats.tx_burnt_amount > 0);
+        assert_eq!(first.block_height, 1);
+        assert_eq!(first.tx_burnt_amount, stats.tx_burnt_amount);
+        assert_eq!(first.total_burnt_amount, stats.tx_burnt_amount);
+        // Chunks burning nothing leave the ledger as is.
+        assert_eq!(apply(2, &[]).1, first);
+
+        let (stats, second) = apply(3, &transfer);
+        assert_eq!(second.block_height, 3);
+        assert_eq!(second.total_burnt_amount, first.total_burnt_amount + stats.tx_burnt_amount);
+        assert_eq!(second.total_gas_deficit_amount, 0);
+    }
+
+    #[test]
+    fn test_apply_delayed_receipts_add_more_using_chunks() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(10_000);
+        let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 1);
+
+        let receipt_gas_cost = apply_state
+            .config
+            .transaction_costs
+            .action_receipt_creation_config
+            .exec_fee()
+            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
+        apply_state.gas_limit = Some(receipt_gas_cost * 3);
+
+        let n = 40;
+        let receipts = generate_receipts(small_transfer, n);
+        let mut receipt_chunks = receipts.chunks_exact(4);
+
+        // Every time we'll process 3 receipts, so we need n / 3 rounded up. Then we do 3 extra.
+        for i in 1..=n / 3 + 3 {
+            let prev_receipts: &[Receipt] = receipt_chunks.next().unwrap_or_default();
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    prev_receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            root = new_root;
+            store_update.commit().unwrap();
+            let state = tries.new_trie_update(0, root);
+            let account = get_account(&state, &alice_account()).unwrap().unwrap();
+            let capped_i = std::cmp::min(i * 3, n);
+            assert_eq!(
+                account.amount(),
+                initial_balance
+                    + small_transfer * Balance::from(capped_i)
+                    + Balance::from(capped_i * (capped_i - 1) / 2)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_receipt_congestion_control")]
+    fn test_apply_delayed_receipts_count() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(10_000);
+        let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 1);
+
+        let receipt_gas_cost = apply_state
+            .config
+            .transaction_costs
+            .action_receipt_creation_config
+            .exec_fee()
+            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
+        apply_state.gas_limit = Some(receipt_gas_cost * 3);
+
+        // Every time 3 receipts are processed and the rest stay delayed.
+        let receipts = generate_receipts(small_transfer, 10);
+        let mut prev_receipts: &[Receipt] = &receipts;
+        for expected_count in vec![7, 4, 1, 0] {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    prev_receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            root = new_root;
+            store_update.commit().unwrap();
+            prev_receipts = &[];
+            let state = tries.new_trie_update(0, root);
+            assert_eq!(
+                get_delayed_receipts_count(&state, &alice_account()).unwrap(),
+                expected_count
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_receipt_congestion_control")]
+    fn test_apply_rejects_congested_receipts() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(10_000);
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 1);
+
+        let mut config = (*apply_state.config).clone();
+        config.max_delayed_receipts_per_account = 2;
+        apply_state.config = Arc::new(config);
+        let receipt_gas_cost = apply_state
+            .config
+            .transaction_costs
+            .action_receipt_creation_config
+            .exec_fee()
+            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
+        apply_state.gas_limit = Some(receipt_gas_cost);
+
+        // One receipt is processed and the rest are delayed, as the receiver isn't congested yet.
+        let receipts = generate_receipts(small_transfer, 8);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts[..5],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outcomes.len(), 1);
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state = tries.new_trie_update(0, root);
+        assert_eq!(get_delayed_receipts_count(&state, &alice_account()).unwrap(), 4);
+
+        // The new receipts to the congested receiver are failed and refunded instead of delayed.
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts[5..],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let rejected: Vec<_> = apply_result
+            .outcomes
+            .iter()
+            .filter(|outcome| {
+                matches!(
+                    &outcome.outcome.status,
+                    ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                        kind: ActionErrorKind::ReceiverCongested {
+                            delayed_receipts: 4,
+                            limit: 2,
+                            ..
+                        },
+                        ..
+                    }))
+                )
+            })
+            .map(|outcome| outcome.id)
+            .collect();
+        assert_eq!(
+            rejected,
+            receipts[5..].iter().map(|receipt| receipt.receipt_id).collect::<Vec<_>>()
+        );
+        assert!(apply_result
+            .outgoing_receipts
+            .iter()
+            .any(|receipt| receipt.receiver_id == bob_account()
+                && receipt.predecessor_id == system_account()));
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state = tries.new_trie_update(0, root);
+        assert_eq!(get_delayed_receipts_count(&state, &alice_account()).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_apply_delayed_receipts_adjustable_gas_limit() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(10_000);
+        let (runtime, tries, mut root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, 1);
+
+        let receipt_gas_cost = apply_state
+            .config
+            .transaction_costs
+            .action_receipt_creation_config
+            .exec_fee()
+            + apply_state.config.transaction_costs.action_creation_config.transfer_cost.exec_fee();
+
+        let n = 120;
+        let receipts = generate_receipts(small_transfer, n);
+        let mut receipt_chunks = receipts.chunks_exact(4);
+
+        let mut num_receipts_given = 0;
+        let mut num_receipts_processed = 0;
+        let mut num_receipts_per_block = 1;
+        // Test adjusts gas limit based on the number of receipt given and number of receipts processed.
+        while num_receipts_processed < n {
+            if num_receipts_given > num_receipts_processed {
+                num_receipts_per_block += 1;
+            } else if num_receipts_per_block > 1 {
+                num_receipts_per_block -= 1;
+            }
+            apply_state.gas_limit = Some(num_receipts_per_block * receipt_gas_cost);
+            let prev_receipts: &[Receipt] = receipt_chunks.next().unwrap_or_default();
+            num_receipts_given += prev_receipts.len() as u64;
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    prev_receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            root = new_root;
+            store_update.commit().unwrap();
+            let state = tries.new_trie_update(0, root);
             num_receipts_processed += apply_result.outcomes.len() as u64;
             let account = get_account(&state, &alice_account()).unwrap().unwrap();
             assert_eq!(
@@ -2100,14 +3525,183 @@ mod tests {
     }
 
     #[test]
-    fn test_apply_invalid_incoming_receipts() {
-        let initial_balance = to_yocto(1_000_000);
-        let initial_locked = to_yocto(500_000);
-        let small_transfer = to_yocto(10_000);
-        let gas_limit = 1;
-        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
-            setup_runtime(initial_balance, initial_locked, gas_limit);
-
+    fn test_apply_observer() {
+        #[derive(Default)]
+        struct EventRecorder(std::sync::Mutex<Vec<String>>);
+
+        impl ApplyObserver for EventRecorder {
+            fn on_transaction_processed(
+                &self,
+                transaction: &SignedTransaction,
+                _outcome: &ExecutionOutcomeWithId,
+            ) {
+                self.0.lock().unwrap().push(format!("tx {}", transaction.get_hash()));
+            }
+
+            fn on_receipt_started(&self, receipt: &Receipt) {
+                self.0.lock().unwrap().push(format!("start {}", receipt.receipt_id));
+            }
+
+            fn on_action_executed(
+                &self,
+                receipt: &Receipt,
+                action_index: usize,
+                _action: &Action,
+                _gas_burnt: Gas,
+                error: Option<&ActionError>,
+            ) {
+                self.0.lock().unwrap().push(format!(
+                    "action {} {} {}",
+                    receipt.receipt_id,
+                    action_index,
+                    error.is_none()
+                ));
+            }
+
+            fn on_receipt_finished(&self, receipt: &Receipt, outcome: &ExecutionOutcomeWithId) {
+                assert_eq!(receipt.receipt_id, outcome.id);
+                self.0.lock().unwrap().push(format!("finish {}", receipt.receipt_id));
+            }
+        }
+
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let recorder = Arc::new(EventRecorder::default());
+        apply_state.observer = Some(recorder.clone());
+
+        let transaction = SignedTransaction::send_money(
+            1,
+            alice_account(),
+            alice_account(),
+            &*signer,
+            to_yocto(1),
+            CryptoHash::default(),
+        );
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                &[transaction.clone()],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let receipt_id = apply_result.outcomes[1].id;
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                format!("tx {}", transaction.get_hash()),
+                format!("start {}", receipt_id),
+                format!("action {} 0 true", receipt_id),
+                format!("finish {}", receipt_id),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_receipt() {
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let missing_key = PublicKey::empty(KeyType::ED25519);
+        let actions = vec![
+            Action::Transfer(TransferAction { deposit: 100 }),
+            Action::DeleteKey(DeleteKeyAction { public_key: missing_key }),
+        ];
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+
+        let result = runtime
+            .replay_receipt(
+                tries.get_trie_for_shard(0),
+                root,
+                &apply_state,
+                &receipts[0],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        let outcome = result.outcome.unwrap();
+        assert!(matches!(outcome.outcome.status, ExecutionStatus::Failure(_)));
+        assert_eq!(result.actions.len(), 2);
+        assert!(result.actions[0].error.is_none());
+        assert_eq!(result.actions[1].action_index, 1);
+        assert!(result.actions[1].error.is_some());
+        // The deposit of the failed receipt is refunded.
+        assert!(!result.outgoing_receipts.is_empty());
+        assert!(!result.state_changes.is_empty());
+        assert_eq!(apply_state.profile.all_gas(), 0);
+
+        // Nothing is committed, so the receipt replays the same way again.
+        let replayed = runtime
+            .replay_receipt(
+                tries.get_trie_for_shard(0),
+                root,
+                &apply_state,
+                &receipts[0],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        assert_eq!(replayed.outcome.unwrap(), outcome);
+        assert_eq!(replayed.state_changes.len(), result.state_changes.len());
+    }
+
+    #[test]
+    fn test_apply_invalid_transaction_signature() {
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+
+        let mut transactions = (0..3)
+            .map(|i| {
+                SignedTransaction::send_money(
+                    i + 1,
+                    alice_account(),
+                    bob_account(),
+                    &*signer,
+                    to_yocto(1),
+                    CryptoHash::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                &transactions,
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+
+        transactions[1].signature = transactions[0].signature.clone();
+        let err = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                &transactions,
+                &epoch_info_provider,
+                None,
+            )
+            .err()
+            .unwrap();
+        assert_eq!(err, RuntimeError::InvalidTxError(InvalidTxError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_apply_invalid_incoming_receipts() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let small_transfer = to_yocto(10_000);
+        let gas_limit = 1;
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
         let n = 1;
         let mut receipts = generate_receipts(small_transfer, n);
         let invalid_account_id = "Invalid".to_string();
@@ -2151,8 +3745,13 @@ mod tests {
         // Saving invalid receipt to the delayed receipts.
         let mut state_update = tries.new_trie_update(0, root);
         let mut delayed_receipts_indices = DelayedReceiptIndices::default();
-        Runtime::delay_receipt(&mut state_update, &mut delayed_receipts_indices, &invalid_receipt)
-            .unwrap();
+        Runtime::delay_receipt(
+            &mut state_update,
+            &mut delayed_receipts_indices,
+            &invalid_receipt,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
         set(&mut state_update, TrieKey::DelayedReceiptIndices, &delayed_receipts_indices);
         state_update.commit(StateChangeCause::UpdatedDelayedReceipts);
         let trie_changes = state_update.finalize().unwrap().0;
@@ -2270,6 +3869,10 @@ mod tests {
             .unwrap();
         // We used part of the prepaid gas to paying extra fees.
         assert_eq!(result.stats.gas_deficit_amount, 0);
+        // Alice doesn't have a contract, so the call fails.
+        let contract_stats = &result.stats.contract_stats[&hash(&[])];
+        assert_eq!(contract_stats.calls, 1);
+        assert_eq!(contract_stats.failures.get("CompilationError"), Some(&1));
         // The refund is less than the received amount.
         match &result.outgoing_receipts[0].receipt {
             ReceiptEnum::Action(ActionReceipt { actions, .. }) => {
@@ -2281,6 +3884,159 @@ mod tests {
         };
     }
 
+    #[cfg(feature = "protocol_feature_local_gas_refund")]
+    #[test]
+    fn test_apply_local_gas_refund() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(15);
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        // The gas was purchased at a higher price, so the difference is refunded.
+        let gas_price = GAS_PRICE * 2;
+        let actions = vec![Action::Transfer(TransferAction { deposit: 0 })];
+        let expected_gas_burnt = safe_add_gas(
+            apply_state.config.transaction_costs.action_receipt_creation_config.exec_fee(),
+            total_prepaid_exec_fees(
+                &apply_state.config.transaction_costs,
+                &actions,
+                &alice_account(),
+                PROTOCOL_VERSION,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let expected_refund = Balance::from(expected_gas_burnt) * (gas_price - GAS_PRICE);
+        let receipt_to = |receiver_id: AccountId| Receipt {
+            predecessor_id: alice_account(),
+            receiver_id,
+            receipt_id: hash(b"receipt"),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: actions.clone(),
+            }),
+        };
+
+        // The receipt executed by the signer refunds the gas without a refund receipt.
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[receipt_to(alice_account())],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert!(result.outgoing_receipts.is_empty());
+        let state = tries.new_trie_update(0, result.state_root);
+        let account = get_account(&state, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.amount(), initial_balance + expected_refund);
+
+        // Receipts executed by other accounts still send refund receipts to the signer.
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[receipt_to(bob_account())],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result.outgoing_receipts.len(), 1);
+        assert_eq!(result.outgoing_receipts[0].receiver_id, alice_account());
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_postponed_receipt_timeout")]
+    fn test_expire_postponed_receipt() {
+        use near_primitives::receipt::DataReceiver;
+
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(15);
+        let (runtime, tries, root, mut apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        let receipt = Receipt {
+            predecessor_id: bob_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(b"receipt"),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: bob_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![DataReceiver {
+                    data_id: hash(b"output"),
+                    receiver_id: bob_account(),
+                }],
+                input_data_ids: vec![hash(b"input")],
+                actions: vec![Action::Transfer(TransferAction { deposit: to_yocto(1) })],
+            }),
+        };
+
+        // The receipt is postponed until its input data arrives.
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[receipt.clone()],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert!(result.outcomes.is_empty());
+        let (store_update, root) = tries.apply_all(&result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        // The data never arrives, so the receipt is failed once the timeout passes.
+        apply_state.epoch_height += apply_state.config.postponed_receipt_timeout_epochs;
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result.outcomes.len(), 1);
+        assert_eq!(result.outcomes[0].id, receipt.receipt_id);
+        assert!(matches!(
+            &result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                kind: ActionErrorKind::PostponedReceiptExpired { .. },
+                ..
+            }))
+        ));
+        // The deposit and the gas are refunded and the output data receiver gets no data.
+        assert_eq!(result.outgoing_receipts.len(), 3);
+        assert!(result.outgoing_receipts.iter().any(|receipt| matches!(
+            &receipt.receipt,
+            ReceiptEnum::Data(DataReceipt { data: None, .. })
+        )));
+        let state = tries.new_trie_update(0, result.state_root);
+        assert!(get_postponed_receipt(&state, &alice_account(), receipt.receipt_id)
+            .unwrap()
+            .is_none());
+    }
+
     #[test]
     fn test_apply_deficit_gas_for_function_call_partial() {
         let initial_balance = to_yocto(1_000_000);
@@ -2344,6 +4100,259 @@ mod tests {
         assert_eq!(result.stats.tx_burnt_amount, total_receipt_cost);
     }
 
+    #[cfg(feature = "protocol_feature_optional_function_calls")]
+    #[test]
+    fn test_apply_optional_function_call() {
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+
+        // Alice has no contract, so the function calls fail.
+        let function_call = FunctionCallAction {
+            method_name: "hello".to_string(),
+            args: vec![],
+            gas: 10u64.pow(12),
+            deposit: 10,
+        };
+        let transfer = Action::Transfer(TransferAction { deposit: 100 });
+        let receipts = vec![
+            vec![Action::OptionalFunctionCall(function_call.clone()), transfer.clone()],
+            vec![transfer, Action::OptionalFunctionCall(function_call)],
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, actions)| Receipt {
+            predecessor_id: bob_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(&[i as u8]),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: bob_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions,
+            }),
+        })
+        .collect::<Vec<_>>();
+
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        // The failed call doesn't fail the receipt unless it's the last action.
+        assert!(matches!(result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(_)));
+        assert!(matches!(result.outcomes[1].outcome.status, ExecutionStatus::Failure(_)));
+        // Only the deposit of the failed call is refunded, while the transfer goes through.
+        let balance_refunds = result
+            .outgoing_receipts
+            .iter()
+            .filter_map(|receipt| match &receipt.receipt {
+                ReceiptEnum::Action(action_receipt)
+                    if action_receipt.signer_id == system_account() =>
+                {
+                    Some(action_receipt.actions[0].get_deposit_balance())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(balance_refunds, vec![10, 110]);
+    }
+
+    #[cfg(feature = "protocol_feature_max_storage_delta")]
+    #[test]
+    fn test_apply_max_storage_delta() {
+        use near_primitives::transaction::MaxStorageDeltaAction;
+
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+
+        let new_key =
+            InMemorySigner::from_seed("new_key", KeyType::ED25519, "new_key").public_key();
+        let add_key = Action::AddKey(AddKeyAction {
+            public_key: new_key,
+            access_key: AccessKey::full_access(),
+        });
+        // Adding a key takes way more than 10 bytes, so only the second receipt goes through.
+        let receipts = [10, 1_000]
+            .iter()
+            .enumerate()
+            .map(|(i, &max_storage_delta)| Receipt {
+                receipt_id: hash(&[i as u8]),
+                ..create_receipts_with_actions(
+                    alice_account(),
+                    signer.clone(),
+                    vec![
+                        Action::MaxStorageDelta(MaxStorageDeltaAction { max_storage_delta }),
+                        add_key.clone(),
+                    ],
+                )
+                .remove(0)
+            })
+            .collect::<Vec<_>>();
+
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert!(matches!(
+            &result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(1),
+                kind: ActionErrorKind::MaxStorageDeltaExceeded { max_storage_delta: 10, .. },
+            }))
+        ));
+        assert!(matches!(result.outcomes[1].outcome.status, ExecutionStatus::SuccessValue(_)));
+    }
+
+    #[cfg(feature = "protocol_feature_system_calls")]
+    #[test]
+    fn test_apply_system_call() {
+        use near_primitives::runtime::migration_data::SystemCall;
+
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let actions = vec![Action::DeployContract(DeployContractAction {
+            code: near_test_contracts::rs_contract().to_vec(),
+        })];
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let initial_amount = get_account(&tries.new_trie_update(0, root), &alice_account())
+            .unwrap()
+            .unwrap()
+            .amount();
+
+        let system_call = SystemCall {
+            receiver_id: alice_account(),
+            method_name: "log_something".to_string(),
+            args: vec![],
+            gas: 10u64.pow(13),
+        };
+        let receipt = system_call.to_receipt(apply_state.block_index, 0);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &[receipt.clone()],
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let outcome = &apply_result.outcomes[0];
+        assert_eq!(outcome.id, receipt.receipt_id);
+        assert!(matches!(outcome.outcome.status, ExecutionStatus::SuccessValue(_)));
+        assert_eq!(outcome.outcome.logs, vec!["hello".to_string()]);
+        // The gas is spent but nobody pays for it, and it isn't rewarded or refunded either.
+        assert!(outcome.outcome.gas_burnt > 0);
+        assert_eq!(outcome.outcome.tokens_burnt, 0);
+        assert_eq!(apply_result.stats.tx_burnt_amount, 0);
+        assert!(apply_result.outgoing_receipts.is_empty());
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let final_amount = get_account(&tries.new_trie_update(0, root), &alice_account())
+            .unwrap()
+            .unwrap()
+            .amount();
+        assert_eq!(final_amount, initial_amount);
+    }
+
+    #[cfg(feature = "protocol_feature_global_contracts")]
+    #[test]
+    fn test_apply_global_contract() {
+        use near_primitives::transaction::{DeployGlobalContractAction, UseGlobalContractAction};
+
+        let (runtime, tries, mut root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let code = near_test_contracts::rs_contract().to_vec();
+        let code_hash = hash(&code);
+        let initial_storage_usage = get_account(&tries.new_trie_update(0, root), &alice_account())
+            .unwrap()
+            .unwrap()
+            .storage_usage();
+
+        let mut apply = |actions: Vec<Action>| {
+            let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            root = new_root;
+            apply_result.outcomes[0].outcome.status.clone()
+        };
+
+        let use_global_contract = Action::UseGlobalContract(UseGlobalContractAction { code_hash });
+        assert!(matches!(
+            apply(vec![use_global_contract.clone()]),
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                kind: ActionErrorKind::GlobalContractDoesNotExist { .. },
+                ..
+            }))
+        ));
+        // Publishing the same code twice only stores it once.
+        let deploy_global_contract =
+            Action::DeployGlobalContract(DeployGlobalContractAction { code: code.clone() });
+        for _ in 0..2 {
+            assert!(matches!(
+                apply(vec![deploy_global_contract.clone(), use_global_contract.clone()]),
+                ExecutionStatus::SuccessValue(_)
+            ));
+        }
+        let function_call = Action::FunctionCall(FunctionCallAction {
+            method_name: "log_something".to_string(),
+            args: vec![],
+            gas: 10u64.pow(13),
+            deposit: 0,
+        });
+        assert!(matches!(apply(vec![function_call]), ExecutionStatus::SuccessValue(_)));
+
+        let state_update = tries.new_trie_update(0, root);
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.code_hash(), code_hash);
+        assert!(near_store::get_code(&state_update, &alice_account(), None).unwrap().is_none());
+        assert_eq!(account.storage_usage(), initial_storage_usage + code.len() as u64);
+    }
+
     #[test]
     fn test_delete_key_add_key() {
         let initial_locked = to_yocto(500_000);
@@ -2382,6 +4391,89 @@ mod tests {
         let final_account_state = get_account(&state_update, &alice_account()).unwrap().unwrap();
 
         assert_eq!(initial_account_state.storage_usage(), final_account_state.storage_usage());
+        assert!(apply_result.stats.storage_usage_deltas.is_empty());
+    }
+
+    #[test]
+    fn test_storage_usage_deltas() {
+        let initial_locked = to_yocto(500_000);
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), initial_locked, 10u64.pow(15));
+
+        let state_update = tries.new_trie_update(0, root);
+        let initial_account_state = get_account(&state_update, &alice_account()).unwrap().unwrap();
+
+        let new_key =
+            InMemorySigner::from_seed("new_key", KeyType::ED25519, "new_key").public_key();
+        let actions = vec![Action::AddKey(AddKeyAction {
+            public_key: new_key,
+            access_key: AccessKey::full_access(),
+        })];
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, root);
+        let final_account_state = get_account(&state_update, &alice_account()).unwrap().unwrap();
+
+        let expected_delta = final_account_state.storage_usage() as i64
+            - initial_account_state.storage_usage() as i64;
+        assert!(expected_delta > 0);
+        assert_eq!(
+            apply_result.stats.storage_usage_deltas,
+            vec![(alice_account(), expected_delta)].into_iter().collect::<HashMap<_, _>>()
+        );
+    }
+
+    #[cfg(feature = "protocol_feature_storage_rebate")]
+    #[test]
+    fn test_storage_rebate() {
+        let initial_locked = to_yocto(500_000);
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), initial_locked, 10u64.pow(15));
+        let mut config = RuntimeConfig::default();
+        config.transaction_costs.storage_rebate_per_byte = 1000;
+        apply_state.config = Arc::new(config);
+
+        let actions = vec![Action::DeleteKey(DeleteKeyAction { public_key: signer.public_key() })];
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+
+        // The balance checker runs as part of applying the receipt.
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+
+        let freed_bytes = -apply_result.stats.storage_usage_deltas[&alice_account()];
+        assert!(freed_bytes > 0);
+        let rebate = freed_bytes as Balance * 1000;
+        assert_eq!(apply_result.stats.storage_rebate_amount, rebate);
+        let outcome = &apply_result.outcomes[0].outcome;
+        assert_eq!(
+            outcome.tokens_burnt,
+            safe_gas_to_balance(GAS_PRICE, outcome.gas_burnt).unwrap() - rebate
+        );
     }
 
     #[test]
@@ -52,6 +52,20 @@ pub enum CallFunctionError {
     InternalError { error_message: String },
     #[error("VM error occurred: #{error_message}")]
     VMError { error_message: String },
+    #[error("View call exceeded the gas limit of {gas_limit} after burning {gas_burnt} gas")]
+    GasLimitExceeded {
+        gas_limit: near_primitives::types::Gas,
+        gas_burnt: near_primitives::types::Gas,
+        logs: Vec<String>,
+    },
+    #[error(
+        "View call exceeded the time limit of {time_limit_ms}ms after burning {gas_burnt} gas"
+    )]
+    TimeLimitExceeded {
+        time_limit_ms: u64,
+        gas_burnt: near_primitives::types::Gas,
+        logs: Vec<String>,
+    },
 }
 
 impl From<ViewAccountError> for ViewContractCodeError {
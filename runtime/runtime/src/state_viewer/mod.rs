@@ -16,13 +16,21 @@ use near_primitives::{
     serialize::to_base64,
     transaction::FunctionCallAction,
     trie_key::trie_key_parsers,
-    types::{AccountId, EpochInfoProvider, Gas},
+    types::{AccountId, BurnLedger, EpochInfoProvider, Gas},
     views::{StateItem, ViewApplyState, ViewStateResult},
 };
 use near_runtime_utils::is_valid_account_id;
-use near_store::{get_access_key, get_account, get_code, TrieUpdate};
-use near_vm_logic::ReturnData;
-use std::{str, sync::Arc, time::Instant};
+use near_store::{
+    get_access_key, get_account, get_burn_ledger, get_code, get_global_code, StorageError,
+    TrieUpdate,
+};
+use near_vm_errors::{FunctionCallError, HostError, VMError};
+use near_vm_logic::{with_view_deadline, ReturnData};
+use std::{
+    str,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub mod errors;
 
@@ -31,6 +39,8 @@ pub struct TrieViewer {
     state_size_limit: Option<u64>,
     /// Gas limit used when when handling call_function queries.
     max_gas_burnt_view: Gas,
+    /// Wall-clock time limit of a single call_function query. None is no limit
+    max_view_call_time: Option<Duration>,
 }
 
 impl Default for TrieViewer {
@@ -38,6 +48,7 @@ impl Default for TrieViewer {
         Self {
             state_size_limit: None,
             max_gas_burnt_view: VMLimitConfig::default().max_gas_burnt_view,
+            max_view_call_time: None,
         }
     }
 }
@@ -46,7 +57,12 @@ impl TrieViewer {
     pub fn new(state_size_limit: Option<u64>, max_gas_burnt_view: Option<Gas>) -> Self {
         let max_gas_burnt_view =
             max_gas_burnt_view.unwrap_or_else(|| TrieViewer::default().max_gas_burnt_view);
-        Self { state_size_limit, max_gas_burnt_view }
+        Self { state_size_limit, max_gas_burnt_view, max_view_call_time: None }
+    }
+
+    pub fn with_max_view_call_time(mut self, max_view_call_time: Option<Duration>) -> Self {
+        self.max_view_call_time = max_view_call_time;
+        self
     }
 
     pub fn view_account(
@@ -73,13 +89,22 @@ impl TrieViewer {
         account_id: &AccountId,
     ) -> Result<ContractCode, errors::ViewContractCodeError> {
         let account = self.view_account(state_update, account_id)?;
-        get_code(state_update, account_id, Some(account.code_hash()))?.ok_or_else(|| {
-            errors::ViewContractCodeError::NoContractCode {
-                contract_account_id: account_id.clone(),
+        let code = match get_code(state_update, account_id, Some(account.code_hash()))? {
+            // Accounts using a global contract don't have a code of their own.
+            None if account.code_hash() != CryptoHash::default() => {
+                get_global_code(state_update, &account.code_hash())?
             }
+            code => code,
+        };
+        code.ok_or_else(|| errors::ViewContractCodeError::NoContractCode {
+            contract_account_id: account_id.clone(),
         })
     }
 
+    pub fn view_burn_ledger(&self, state_update: &TrieUpdate) -> Result<BurnLedger, StorageError> {
+        get_burn_ledger(state_update)
+    }
+
     pub fn view_access_key(
         &self,
         state_update: &TrieUpdate,
@@ -133,11 +158,20 @@ impl TrieViewer {
         access_keys
     }
 
+    /// Returns the contract data of the account whose keys start with `prefix`, in key order.
+    ///
+    /// With a `limit`, at most `limit` values starting at `start_key` are returned, along with the
+    /// key to start the next page at. Such a page is also cut once its size exceeds the state
+    /// size limit, instead of refusing accounts with a large state. With `include_proof`, the
+    /// trie nodes read to produce the values are returned as a proof against the state root.
     pub fn view_state(
         &self,
         state_update: &TrieUpdate,
         account_id: &AccountId,
         prefix: &[u8],
+        start_key: Option<&[u8]>,
+        limit: Option<u64>,
+        include_proof: bool,
     ) -> Result<ViewStateResult, errors::ViewStateError> {
         if !is_valid_account_id(account_id) {
             return Err(errors::ViewStateError::InvalidAccountId {
@@ -149,8 +183,8 @@ impl TrieViewer {
                 let code_len = get_code(state_update, account_id, Some(account.code_hash()))?
                     .map(|c| c.code.len() as u64)
                     .unwrap_or_default();
-                if let Some(limit) = self.state_size_limit {
-                    if account.storage_usage().saturating_sub(code_len) > limit {
+                if let (Some(state_size_limit), None) = (self.state_size_limit, limit) {
+                    if account.storage_usage().saturating_sub(code_len) > state_size_limit {
                         return Err(errors::ViewStateError::AccountStateTooLarge {
                             requested_account_id: account_id.clone(),
                         });
@@ -164,24 +198,51 @@ impl TrieViewer {
             }
         };
 
+        let recording_trie;
+        let trie = if include_proof {
+            recording_trie = state_update.trie.recording_reads();
+            &recording_trie
+        } else {
+            state_update.trie.as_ref()
+        };
         let mut values = vec![];
+        let mut next_key = None;
+        let mut page_size = 0u64;
         let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, prefix);
         let acc_sep_len = query.len() - prefix.len();
-        let mut iter = state_update.trie.iter(&state_update.get_root())?;
-        iter.seek(&query)?;
+        let mut iter = trie.iter(&state_update.get_root())?;
+        match start_key {
+            Some(start_key) => {
+                let start =
+                    trie_key_parsers::get_raw_prefix_for_contract_data(account_id, start_key);
+                iter.seek(std::cmp::max(&query, &start))?;
+            }
+            None => iter.seek(&query)?,
+        }
         for item in iter {
             let (key, value) = item?;
             if !key.starts_with(&query.as_ref()) {
                 break;
             }
+            if let Some(limit) = limit {
+                let page_too_large = !values.is_empty()
+                    && self.state_size_limit.map_or(false, |size_limit| page_size > size_limit);
+                if values.len() as u64 >= limit || page_too_large {
+                    next_key = Some(to_base64(&key[acc_sep_len..]));
+                    break;
+                }
+            }
+            page_size += (key.len() - acc_sep_len + value.len()) as u64;
             values.push(StateItem {
                 key: to_base64(&key[acc_sep_len..]),
                 value: to_base64(&value),
                 proof: vec![],
             });
         }
-        // TODO(2076): Add proofs for the storage items.
-        Ok(ViewStateResult { values, proof: vec![] })
+        let proof = trie
+            .recorded_storage()
+            .map_or_else(Vec::new, |storage| storage.nodes.0.iter().map(to_base64).collect());
+        Ok(ViewStateResult { values, proof, next_key })
     }
 
     pub fn call_function(
@@ -239,6 +300,7 @@ impl TrieViewer {
             block_timestamp: view_state.block_timestamp,
             gas_limit: None,
             random_seed: root,
+            epoch_random_seed: root,
             current_protocol_version: view_state.current_protocol_version,
             config: config.clone(),
             cache: view_state.cache,
@@ -248,6 +310,7 @@ impl TrieViewer {
             profile: Default::default(),
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
+            observer: None,
         };
         let action_receipt = ActionReceipt {
             signer_id: originator_id.clone(),
@@ -263,34 +326,56 @@ impl TrieViewer {
             gas: config.wasm_config.limit_config.max_gas_burnt_view,
             deposit: 0,
         };
-        let (outcome, err) = execute_function_call(
-            &apply_state,
-            &mut runtime_ext,
-            &mut account,
-            &originator_id,
-            &action_receipt,
-            &[],
-            &function_call,
-            &empty_hash,
-            &config,
-            true,
-            true,
-        );
+        let deadline = self.max_view_call_time.map(|limit| now + limit);
+        let (outcome, err) = with_view_deadline(deadline, || {
+            execute_function_call(
+                &apply_state,
+                &mut runtime_ext,
+                &mut account,
+                &originator_id,
+                &action_receipt,
+                &[],
+                &function_call,
+                &empty_hash,
+                &config,
+                true,
+                true,
+            )
+        });
         let elapsed = now.elapsed();
         let time_ms =
             (elapsed.as_secs() as f64 / 1_000.0) + f64::from(elapsed.subsec_nanos()) / 1_000_000.0;
         let time_str = format!("{:.*}ms", 2, time_ms);
 
         if let Some(err) = err {
+            let gas_burnt = outcome.as_ref().map_or(0, |outcome| outcome.burnt_gas);
             if let Some(outcome) = outcome {
                 logs.extend(outcome.logs);
             }
             let message = format!("wasm execution failed with error: {:?}", err);
-            debug!(target: "runtime", "(exec time {}) {}", time_str, message);
+            debug!(target: "runtime", "(exec time {}, gas burnt {}) {}", time_str, gas_burnt, message);
+            if let VMError::FunctionCallError(FunctionCallError::HostError(
+                HostError::GasLimitExceeded,
+            )) = err
+            {
+                // The deadline is reported as exceeded gas limit by the VM.
+                if let Some(limit) = self.max_view_call_time.filter(|limit| elapsed >= *limit) {
+                    return Err(errors::CallFunctionError::TimeLimitExceeded {
+                        time_limit_ms: limit.as_millis() as u64,
+                        gas_burnt,
+                        logs: logs.clone(),
+                    });
+                }
+                return Err(errors::CallFunctionError::GasLimitExceeded {
+                    gas_limit: self.max_gas_burnt_view,
+                    gas_burnt,
+                    logs: logs.clone(),
+                });
+            }
             Err(errors::CallFunctionError::VMError { error_message: message })
         } else {
             let outcome = outcome.unwrap();
-            debug!(target: "runtime", "(exec time {}) result of execution: {:?}", time_str, outcome);
+            debug!(target: "runtime", "(exec time {}, gas burnt {}) result of execution: {:?}", time_str, outcome.burnt_gas, outcome);
             logs.extend(outcome.logs);
             let result = match outcome.return_data {
                 ReturnData::Value(buf) => buf,
@@ -306,6 +391,8 @@ mod tests {
     #[cfg(feature = "protocol_feature_evm")]
     use near_chain_configs::TESTNET_EVM_CHAIN_ID;
     use near_primitives::{
+        challenge::PartialState,
+        serialize::from_base64,
         test_utils::MockEpochInfoProvider,
         trie_key::TrieKey,
         types::{EpochId, StateChangeCause},
@@ -316,7 +403,7 @@ mod tests {
     };
 
     use super::*;
-    use near_store::set_account;
+    use near_store::{set_account, PartialStorage, Trie};
 
     #[test]
     fn test_view_call() {
@@ -348,6 +435,73 @@ mod tests {
         assert_eq!(result.unwrap(), encode_int(10));
     }
 
+    fn view_state() -> ViewApplyState {
+        ViewApplyState {
+            block_height: 1,
+            prev_block_hash: CryptoHash::default(),
+            block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            epoch_height: 0,
+            block_timestamp: 1,
+            current_protocol_version: PROTOCOL_VERSION,
+            cache: None,
+            #[cfg(feature = "protocol_feature_evm")]
+            evm_chain_id: TESTNET_EVM_CHAIN_ID,
+        }
+    }
+
+    #[test]
+    fn test_view_call_gas_limit_exceeded() {
+        let (_, root) = get_test_trie_viewer();
+        let viewer = TrieViewer::new(None, Some(1_000_000_000));
+
+        let mut logs = vec![];
+        let result = viewer.call_function(
+            root,
+            view_state(),
+            &AccountId::from("test.contract"),
+            "loop_forever",
+            &[],
+            &mut logs,
+            &MockEpochInfoProvider::default(),
+        );
+
+        match result {
+            Err(errors::CallFunctionError::GasLimitExceeded { gas_limit, gas_burnt, .. }) => {
+                assert_eq!(gas_limit, 1_000_000_000);
+                assert_eq!(gas_burnt, 1_000_000_000);
+            }
+            other => panic!("Expected GasLimitExceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_view_call_time_limit_exceeded() {
+        let (viewer, root) = get_test_trie_viewer();
+        let viewer = viewer.with_max_view_call_time(Some(Duration::from_millis(10)));
+
+        let mut logs = vec![];
+        let result = viewer.call_function(
+            root,
+            view_state(),
+            &AccountId::from("test.contract"),
+            "loop_forever",
+            &[],
+            &mut logs,
+            &MockEpochInfoProvider::default(),
+        );
+
+        match result {
+            Err(errors::CallFunctionError::TimeLimitExceeded {
+                time_limit_ms, gas_burnt, ..
+            }) => {
+                assert_eq!(time_limit_ms, 10);
+                assert!(gas_burnt > 0);
+            }
+            other => panic!("Expected TimeLimitExceeded, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_view_call_bad_contract_id() {
         let (viewer, root) = get_test_trie_viewer();
@@ -473,7 +627,9 @@ mod tests {
 
         let state_update = tries.new_trie_update(0, new_root);
         let trie_viewer = TrieViewer::default();
-        let result = trie_viewer.view_state(&state_update, &alice_account(), b"").unwrap();
+        let result = trie_viewer
+            .view_state(&state_update, &alice_account(), b"", None, None, false)
+            .unwrap();
         assert_eq!(result.proof, Vec::<String>::new());
         assert_eq!(
             result.values,
@@ -490,9 +646,13 @@ mod tests {
                 }
             ]
         );
-        let result = trie_viewer.view_state(&state_update, &alice_account(), b"xyz").unwrap();
+        let result = trie_viewer
+            .view_state(&state_update, &alice_account(), b"xyz", None, None, false)
+            .unwrap();
         assert_eq!(result.values, []);
-        let result = trie_viewer.view_state(&state_update, &alice_account(), b"test123").unwrap();
+        let result = trie_viewer
+            .view_state(&state_update, &alice_account(), b"test123", None, None, false)
+            .unwrap();
         assert_eq!(
             result.values,
             [StateItem {
@@ -513,8 +673,71 @@ mod tests {
             &Account::new(0, 0, CryptoHash::default(), 50_001),
         );
         let trie_viewer = TrieViewer::new(Some(50_000), None);
-        let result = trie_viewer.view_state(&state_update, &alice_account(), b"");
+        let result =
+            trie_viewer.view_state(&state_update, &alice_account(), b"", None, None, false);
         assert!(matches!(result, Err(errors::ViewStateError::AccountStateTooLarge { .. })));
+        // Pages are bounded by the limit instead.
+        let result =
+            trie_viewer.view_state(&state_update, &alice_account(), b"", None, Some(10), false);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_view_state_pages_with_proof() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let mut state_update = tries.new_trie_update(0, root);
+        for i in 0..5u8 {
+            state_update.set(
+                TrieKey::ContractData { account_id: alice_account(), key: vec![b'k', i] },
+                vec![i],
+            );
+        }
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: b"other".to_vec() },
+            b"other".to_vec(),
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (db_changes, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        db_changes.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, new_root);
+        let trie_viewer = TrieViewer::default();
+        let mut start_key: Option<Vec<u8>> = None;
+        let mut page_sizes = vec![];
+        loop {
+            let result = trie_viewer
+                .view_state(
+                    &state_update,
+                    &alice_account(),
+                    b"k",
+                    start_key.as_deref(),
+                    Some(2),
+                    true,
+                )
+                .unwrap();
+            page_sizes.push(result.values.len());
+
+            // The same values are found in the trie built from the proof.
+            let nodes = result.proof.iter().map(|node| from_base64(node).unwrap()).collect();
+            let trie = Trie::from_recorded_storage(PartialStorage { nodes: PartialState(nodes) });
+            let start = trie_key_parsers::get_raw_prefix_for_contract_data(
+                &alice_account(),
+                start_key.as_deref().unwrap_or(&b"k"[..]),
+            );
+            let mut iter = trie.iter(&new_root).unwrap();
+            iter.seek(&start).unwrap();
+            for item in result.values.iter() {
+                let (_, value) = iter.next().unwrap().unwrap();
+                assert_eq!(to_base64(&value), item.value);
+            }
+
+            match result.next_key {
+                Some(next_key) => start_key = Some(from_base64(&next_key).unwrap()),
+                None => break,
+            }
+        }
+        assert_eq!(page_sizes, vec![2, 2, 1]);
     }
 
     #[test]
@@ -531,7 +754,8 @@ mod tests {
             [0; Account::MAX_ACCOUNT_DELETION_STORAGE_USAGE as usize].to_vec(),
         );
         let trie_viewer = TrieViewer::new(Some(50_000), None);
-        let result = trie_viewer.view_state(&state_update, &alice_account(), b"");
+        let result =
+            trie_viewer.view_state(&state_update, &alice_account(), b"", None, None, false);
         assert!(result.is_ok());
     }
 
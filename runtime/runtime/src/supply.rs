@@ -0,0 +1,196 @@
+//! Audit of the tokens held by the state of a shard.
+//!
+//! The total supply of the chain is the sum of the tokens held by the states of all the shards,
+//! the receipts in flight between the shards and the tokens burnt by the chunks which weren't
+//! included into the blocks yet.
+use borsh::BorshDeserialize;
+
+use near_primitives::errors::{IntegerOverflowError, RuntimeError, StorageError};
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt};
+use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::types::{Balance, ShardId, StateRoot};
+use near_primitives::version::ProtocolVersion;
+use near_store::{get, get_slash_escrows, ShardTries};
+
+use crate::config::{receipt_cost, receipts_cost, safe_add_balance};
+
+/// Number of accounts read from the trie at once.
+const ACCOUNTS_BATCH_SIZE: usize = 1000;
+
+/// Tokens held by the state of a shard.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShardSupply {
+    /// Sum of the amounts and the locked balances of the accounts.
+    pub accounts: Balance,
+    /// Slashed stake held in escrow.
+    pub slash_escrows: Balance,
+    /// Deposits and prepaid gas of the delayed receipts.
+    pub delayed_receipts: Balance,
+    /// Deposits and prepaid gas of the receipts waiting for their input data.
+    pub postponed_receipts: Balance,
+}
+
+impl ShardSupply {
+    pub fn total(&self) -> Result<Balance, IntegerOverflowError> {
+        safe_add_balance(
+            safe_add_balance(self.accounts, self.slash_escrows)?,
+            safe_add_balance(self.delayed_receipts, self.postponed_receipts)?,
+        )
+    }
+}
+
+/// Computes the tokens held by the state of the shard at the given state root. The accounts are
+/// read in batches, so the whole state is never loaded into memory.
+pub fn compute_total_supply(
+    tries: &ShardTries,
+    shard_id: ShardId,
+    state_root: &StateRoot,
+    transaction_costs: &RuntimeFeesConfig,
+    current_protocol_version: ProtocolVersion,
+) -> Result<ShardSupply, RuntimeError> {
+    let mut supply = ShardSupply::default();
+
+    let mut from_account = None;
+    loop {
+        let accounts = tries.iterate_accounts(
+            shard_id,
+            state_root,
+            from_account.as_ref(),
+            ACCOUNTS_BATCH_SIZE,
+        )?;
+        for (_, account) in accounts.iter() {
+            supply.accounts = safe_add_balance(
+                supply.accounts,
+                safe_add_balance(account.amount(), account.locked())?,
+            )?;
+        }
+        if accounts.len() < ACCOUNTS_BATCH_SIZE {
+            break;
+        }
+        // The next account id is at least the last one followed by the zero byte.
+        let mut next_account = accounts.last().unwrap().0.clone();
+        next_account.push('\0');
+        from_account = Some(next_account);
+    }
+
+    let state_update = tries.new_trie_update_view(shard_id, *state_root);
+    for (_, slash_escrow) in get_slash_escrows(&state_update)? {
+        supply.slash_escrows = safe_add_balance(supply.slash_escrows, slash_escrow.amount)?;
+    }
+
+    for (indices_key, receipt_key) in [
+        (TrieKey::DelayedReceiptIndices, (|index| TrieKey::DelayedReceipt { index }) as fn(_) -> _),
+        (TrieKey::DelayedPriorityReceiptIndices, |index| TrieKey::DelayedPriorityReceipt { index }),
+    ]
+    .iter()
+    {
+        let delayed_receipt_indices: DelayedReceiptIndices =
+            get(&state_update, indices_key)?.unwrap_or_default();
+        for index in
+            delayed_receipt_indices.first_index..delayed_receipt_indices.next_available_index
+        {
+            let receipt: Receipt = get(&state_update, &receipt_key(index))?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed receipt #{} should be in the state",
+                    index
+                ))
+            })?;
+            supply.delayed_receipts = safe_add_balance(
+                supply.delayed_receipts,
+                receipt_cost(transaction_costs, &receipt, current_protocol_version)?,
+            )?;
+        }
+    }
+
+    let prefix = trie_key_parsers::get_raw_prefix_for_postponed_receipts();
+    let trie = tries.get_view_trie_for_shard(shard_id);
+    let mut iter = trie.iter(state_root)?;
+    iter.seek(&prefix)?;
+    let mut postponed_receipts = vec![];
+    for item in iter {
+        let (key, value) = item?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        postponed_receipts.push(Receipt::try_from_slice(&value).map_err(|_| {
+            StorageError::StorageInconsistentState(
+                "Can't deserialize a postponed receipt".to_string(),
+            )
+        })?);
+    }
+    supply.postponed_receipts =
+        receipts_cost(transaction_costs, &postponed_receipts, current_protocol_version)?;
+
+    Ok(supply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::receipt::{ActionReceipt, ReceiptEnum};
+    use near_primitives::test_utils::account_new;
+    use near_primitives::transaction::{Action, TransferAction};
+    use near_primitives::types::{MerkleHash, StateChangeCause};
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_store::test_utils::create_tries;
+    use near_store::{set, set_account, set_postponed_receipt};
+    use testlib::runtime_utils::{alice_account, bob_account};
+
+    #[test]
+    fn test_compute_total_supply() {
+        let tries = create_tries();
+        let transaction_costs = RuntimeFeesConfig::default();
+        let receipt = |deposit: Balance| Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: bob_account(),
+            receipt_id: hash(&deposit.to_le_bytes()),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 100,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::Transfer(TransferAction { deposit })],
+            }),
+        };
+        let delayed_receipt = receipt(1000);
+        let postponed_receipt = receipt(2000);
+
+        let mut state_update = tries.new_trie_update(0, MerkleHash::default());
+        set_account(&mut state_update, alice_account(), &account_new(100, CryptoHash::default()));
+        set_account(&mut state_update, bob_account(), &account_new(200, CryptoHash::default()));
+        set(&mut state_update, TrieKey::DelayedReceipt { index: 0 }, &delayed_receipt);
+        set(
+            &mut state_update,
+            TrieKey::DelayedReceiptIndices,
+            &DelayedReceiptIndices { first_index: 0, next_available_index: 1 },
+        );
+        set_postponed_receipt(&mut state_update, &postponed_receipt);
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let supply =
+            compute_total_supply(&tries, 0, &root, &transaction_costs, PROTOCOL_VERSION).unwrap();
+        assert_eq!(supply.accounts, 300);
+        assert_eq!(supply.slash_escrows, 0);
+        assert_eq!(
+            supply.delayed_receipts,
+            receipt_cost(&transaction_costs, &delayed_receipt, PROTOCOL_VERSION).unwrap()
+        );
+        assert_eq!(
+            supply.postponed_receipts,
+            receipt_cost(&transaction_costs, &postponed_receipt, PROTOCOL_VERSION).unwrap()
+        );
+        assert!(supply.postponed_receipts > 2000);
+        assert_eq!(
+            supply.total().unwrap(),
+            300 + supply.delayed_receipts + supply.postponed_receipts
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use near_crypto::key_conversion::is_valid_staking_key;
 use near_primitives::runtime::get_insufficient_storage_stake;
 use near_primitives::{
@@ -17,7 +19,8 @@ use near_primitives::{
 };
 use near_runtime_utils::is_valid_account_id;
 use near_store::{
-    get_access_key, get_account, set_access_key, set_account, StorageError, TrieUpdate,
+    get_access_key, get_account, get_delayed_receipts_count, set_access_key, set_account,
+    StorageError, TrieUpdate,
 };
 
 use crate::config::{total_prepaid_gas, tx_cost, TransactionCost};
@@ -26,6 +29,10 @@ use near_primitives::checked_feature;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::types::BlockHeight;
 
+/// Method names of function call access keys ending with this character allow all the methods
+/// starting with the rest of the name.
+const METHOD_NAME_WILDCARD: char = '*';
+
 /// Validates the transaction without using the state. It allows any node to validate a
 /// transaction before forwarding it to the node that tracks the `signer_id` account.
 pub fn validate_transaction(
@@ -65,8 +72,21 @@ pub fn validate_transaction(
         .into());
     }
 
-    validate_actions(&config.wasm_config.limit_config, &transaction.actions)
-        .map_err(|e| InvalidTxError::ActionsValidation(e))?;
+    let max_actions_per_transaction = config.wasm_config.limit_config.max_actions_per_transaction;
+    if transaction.actions.len() as u64 > max_actions_per_transaction {
+        return Err(InvalidTxError::NumberOfActionsExceeded {
+            number_of_actions: transaction.actions.len() as u64,
+            limit: max_actions_per_transaction,
+        }
+        .into());
+    }
+
+    validate_actions(
+        &config.wasm_config.limit_config,
+        &transaction.actions,
+        current_protocol_version,
+    )
+    .map_err(|e| InvalidTxError::ActionsValidation(e))?;
 
     let sender_is_receiver = &transaction.receiver_id == signer_id;
 
@@ -142,6 +162,35 @@ pub fn verify_and_charge_transaction(
         }
     };
 
+    if let (Some(valid_until), Some(block_height)) = (transaction.valid_until(), block_height) {
+        if block_height > valid_until {
+            return Err(InvalidTxError::ValidUntilExceeded { valid_until, block_height }.into());
+        }
+    }
+
+    if checked_feature!(
+        "protocol_feature_receipt_congestion_control",
+        ReceiptCongestionControl,
+        current_protocol_version
+    ) {
+        // The delayed receipts of a receiver are only counted in its shard, so only the receivers
+        // in the shard of the signer are checked here. The receipts to the other receivers are
+        // failed once they reach their shard.
+        let limit = config.max_delayed_receipts_per_account;
+        if limit > 0 {
+            let delayed_receipts =
+                get_delayed_receipts_count(state_update, &transaction.receiver_id)?;
+            if delayed_receipts >= limit {
+                return Err(InvalidTxError::ReceiverCongested {
+                    receiver_id: transaction.receiver_id.clone(),
+                    delayed_receipts,
+                    limit,
+                }
+                .into());
+            }
+        }
+    }
+
     access_key.nonce = transaction.nonce;
 
     signer.set_amount(signer.amount().checked_sub(total_cost).ok_or_else(|| {
@@ -182,13 +231,20 @@ pub fn verify_and_charge_transaction(
     };
 
     if let AccessKeyPermission::FunctionCall(ref function_call_permission) = access_key.permission {
-        if transaction.actions.len() != 1 {
+        // MaxStorageDelta and ValidUntil actions only restrict the function call, so they're
+        // allowed.
+        let actions: Vec<_> = transaction
+            .actions
+            .iter()
+            .filter(|action| !matches!(action, Action::MaxStorageDelta(_) | Action::ValidUntil(_)))
+            .collect();
+        if actions.len() != 1 {
             return Err(InvalidTxError::InvalidAccessKeyError(
                 InvalidAccessKeyError::RequiresFullAccess,
             )
             .into());
         }
-        if let Some(Action::FunctionCall(ref function_call)) = transaction.actions.get(0) {
+        if let Some(Action::FunctionCall(ref function_call)) = actions.get(0) {
             if function_call.deposit > 0 {
                 return Err(InvalidTxError::InvalidAccessKeyError(
                     InvalidAccessKeyError::DepositWithFunctionCall,
@@ -204,12 +260,16 @@ pub fn verify_and_charge_transaction(
                 )
                 .into());
             }
-            if !function_call_permission.method_names.is_empty()
-                && function_call_permission
-                    .method_names
-                    .iter()
-                    .all(|method_name| &function_call.method_name != method_name)
-            {
+            let patterns_enabled = checked_feature!(
+                "protocol_feature_method_name_patterns",
+                MethodNamePatterns,
+                current_protocol_version
+            );
+            if !is_method_name_allowed(
+                &function_call_permission.method_names,
+                &function_call.method_name,
+                patterns_enabled,
+            ) {
                 return Err(InvalidTxError::InvalidAccessKeyError(
                     InvalidAccessKeyError::MethodNameMismatch {
                         method_name: function_call.method_name.clone(),
@@ -231,11 +291,31 @@ pub fn verify_and_charge_transaction(
     Ok(VerificationResult { gas_burnt, gas_remaining, receipt_gas_price, burnt_amount })
 }
 
+/// Checks that a function call access key with the given method names allows calling
+/// `method_name`. An empty list allows any method. With `patterns_enabled`, a method name ending
+/// with `*` allows all the methods starting with the rest of it.
+fn is_method_name_allowed(
+    method_names: &[String],
+    method_name: &str,
+    patterns_enabled: bool,
+) -> bool {
+    method_names.is_empty()
+        || method_names.iter().any(|allowed| {
+            if patterns_enabled {
+                if let Some(prefix) = allowed.strip_suffix(METHOD_NAME_WILDCARD) {
+                    return method_name.starts_with(prefix);
+                }
+            }
+            allowed == method_name
+        })
+}
+
 /// Validates a given receipt. Checks validity of the predecessor and receiver account IDs and
 /// the validity of the Action or Data receipt.
 pub(crate) fn validate_receipt(
     limit_config: &VMLimitConfig,
     receipt: &Receipt,
+    current_protocol_version: ProtocolVersion,
 ) -> Result<(), ReceiptValidationError> {
     if !is_valid_account_id(&receipt.predecessor_id) {
         return Err(ReceiptValidationError::InvalidPredecessorId {
@@ -249,7 +329,7 @@ pub(crate) fn validate_receipt(
     }
     match &receipt.receipt {
         ReceiptEnum::Action(action_receipt) => {
-            validate_action_receipt(limit_config, action_receipt)
+            validate_action_receipt(limit_config, action_receipt, current_protocol_version)
         }
         ReceiptEnum::Data(data_receipt) => validate_data_receipt(limit_config, data_receipt),
     }
@@ -260,6 +340,7 @@ pub(crate) fn validate_receipt(
 fn validate_action_receipt(
     limit_config: &VMLimitConfig,
     receipt: &ActionReceipt,
+    current_protocol_version: ProtocolVersion,
 ) -> Result<(), ReceiptValidationError> {
     if !is_valid_account_id(&receipt.signer_id) {
         return Err(ReceiptValidationError::InvalidSignerId {
@@ -279,7 +360,7 @@ fn validate_action_receipt(
             limit: limit_config.max_number_input_data_dependencies,
         });
     }
-    validate_actions(limit_config, &receipt.actions)
+    validate_actions(limit_config, &receipt.actions, current_protocol_version)
         .map_err(|e| ReceiptValidationError::ActionsValidation(e))
 }
 
@@ -302,9 +383,16 @@ fn validate_data_receipt(
 /// Checks that the total number of actions doesn't exceed the limit.
 /// Validates each individual action.
 /// Checks that the total prepaid gas doesn't exceed the limit.
+/// Checks that no contract is deployed and no access key is added or deleted more than once.
+/// Checks that optional function calls are supported by the protocol version.
+/// Checks that a MaxStorageDelta action is supported and comes first.
+/// Checks that global contracts are supported by the protocol version.
+/// Checks that ValidUntil actions are supported by the protocol version.
+/// Checks that method name patterns of added keys are well formed, once they are supported.
 pub(crate) fn validate_actions(
     limit_config: &VMLimitConfig,
     actions: &[Action],
+    current_protocol_version: ProtocolVersion,
 ) -> Result<(), ActionsValidationError> {
     if actions.len() as u64 > limit_config.max_actions_per_receipt {
         return Err(ActionsValidationError::TotalNumberOfActionsExceeded {
@@ -313,16 +401,86 @@ pub(crate) fn validate_actions(
         });
     }
 
-    let mut iter = actions.iter().peekable();
-    while let Some(action) = iter.next() {
+    let mut iter = actions.iter().enumerate().peekable();
+    while let Some((index, action)) = iter.next() {
         if let Action::DeleteAccount(_) = action {
             if iter.peek().is_some() {
                 return Err(ActionsValidationError::DeleteActionMustBeFinal);
             }
         }
+        if let Action::OptionalFunctionCall(_) = action {
+            if !checked_feature!(
+                "protocol_feature_optional_function_calls",
+                OptionalFunctionCalls,
+                current_protocol_version
+            ) {
+                return Err(ActionsValidationError::UnsupportedProtocolFeature {
+                    protocol_feature: "OptionalFunctionCalls".to_string(),
+                    version: current_protocol_version,
+                });
+            }
+        }
+        if let Action::MaxStorageDelta(_) = action {
+            if !checked_feature!(
+                "protocol_feature_max_storage_delta",
+                MaxStorageDelta,
+                current_protocol_version
+            ) {
+                return Err(ActionsValidationError::UnsupportedProtocolFeature {
+                    protocol_feature: "MaxStorageDelta".to_string(),
+                    version: current_protocol_version,
+                });
+            }
+            if index != 0 {
+                return Err(ActionsValidationError::MaxStorageDeltaMustBeFirst {
+                    index: index as u64,
+                });
+            }
+        }
+        if matches!(action, Action::DeployGlobalContract(_) | Action::UseGlobalContract(_))
+            && !checked_feature!(
+                "protocol_feature_global_contracts",
+                GlobalContracts,
+                current_protocol_version
+            )
+        {
+            return Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "GlobalContracts".to_string(),
+                version: current_protocol_version,
+            });
+        }
+        if let Action::ValidUntil(_) = action {
+            if !checked_feature!(
+                "protocol_feature_transaction_valid_until",
+                TransactionValidUntil,
+                current_protocol_version
+            ) {
+                return Err(ActionsValidationError::UnsupportedProtocolFeature {
+                    protocol_feature: "TransactionValidUntil".to_string(),
+                    version: current_protocol_version,
+                });
+            }
+        }
+        if let Action::AddKey(add_key) = action {
+            if checked_feature!(
+                "protocol_feature_method_name_patterns",
+                MethodNamePatterns,
+                current_protocol_version
+            ) {
+                validate_method_name_patterns(add_key)?;
+            }
+        }
         validate_action(limit_config, action)?;
     }
 
+    if checked_feature!(
+        "protocol_feature_duplicate_actions_validation",
+        DuplicateActionsValidation,
+        current_protocol_version
+    ) {
+        validate_no_duplicate_actions(actions)?;
+    }
+
     let total_prepaid_gas =
         total_prepaid_gas(actions).map_err(|_| ActionsValidationError::IntegerOverflow)?;
     if total_prepaid_gas > limit_config.max_total_prepaid_gas {
@@ -335,6 +493,46 @@ pub(crate) fn validate_actions(
     Ok(())
 }
 
+/// Actions of a receipt are applied one by one in the given order, each one observing the effects
+/// of the previous ones, and the first failing action fails the whole receipt. With these
+/// semantics deploying a contract twice only wastes gas, while adding or deleting the same key
+/// twice always fails on the second action, so such action lists are rejected upfront.
+/// Deleting a key and adding it back, e.g. to change its permission, is still allowed.
+fn validate_no_duplicate_actions(actions: &[Action]) -> Result<(), ActionsValidationError> {
+    let mut deployed_contract = false;
+    let mut added_keys = HashSet::new();
+    let mut deleted_keys = HashSet::new();
+    for (index, action) in actions.iter().enumerate() {
+        let index = index as u64;
+        match action {
+            Action::DeployContract(_) => {
+                if deployed_contract {
+                    return Err(ActionsValidationError::DuplicateDeployContract { index });
+                }
+                deployed_contract = true;
+            }
+            Action::AddKey(add_key) => {
+                if !added_keys.insert(&add_key.public_key) {
+                    return Err(ActionsValidationError::DuplicateAddKey {
+                        public_key: add_key.public_key.clone(),
+                        index,
+                    });
+                }
+            }
+            Action::DeleteKey(delete_key) => {
+                if !deleted_keys.insert(&delete_key.public_key) {
+                    return Err(ActionsValidationError::DuplicateDeleteKey {
+                        public_key: delete_key.public_key.clone(),
+                        index,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 /// Validates a single given action. Checks limits and validates `account_id` if applicable.
 pub fn validate_action(
     limit_config: &VMLimitConfig,
@@ -343,12 +541,20 @@ pub fn validate_action(
     match action {
         Action::CreateAccount(_) => Ok(()),
         Action::DeployContract(a) => validate_deploy_contract_action(limit_config, a),
-        Action::FunctionCall(a) => validate_function_call_action(limit_config, a),
+        Action::FunctionCall(a) | Action::OptionalFunctionCall(a) => {
+            validate_function_call_action(limit_config, a)
+        }
         Action::Transfer(_) => Ok(()),
         Action::Stake(a) => validate_stake_action(a),
         Action::AddKey(a) => validate_add_key_action(limit_config, a),
         Action::DeleteKey(_) => Ok(()),
         Action::DeleteAccount(a) => validate_delete_account_action(a),
+        Action::MaxStorageDelta(_) => Ok(()),
+        Action::DeployGlobalContract(a) => {
+            validate_contract_size(limit_config, a.code.len() as u64)
+        }
+        Action::UseGlobalContract(_) => Ok(()),
+        Action::ValidUntil(_) => Ok(()),
     }
 }
 
@@ -357,9 +563,17 @@ fn validate_deploy_contract_action(
     limit_config: &VMLimitConfig,
     action: &DeployContractAction,
 ) -> Result<(), ActionsValidationError> {
-    if action.code.len() as u64 > limit_config.max_contract_size {
+    validate_contract_size(limit_config, action.code.len() as u64)
+}
+
+/// Checks that the size of a deployed code doesn't exceed the limit.
+fn validate_contract_size(
+    limit_config: &VMLimitConfig,
+    size: u64,
+) -> Result<(), ActionsValidationError> {
+    if size > limit_config.max_contract_size {
         return Err(ActionsValidationError::ContractSizeExceeded {
-            size: action.code.len() as u64,
+            size,
             limit: limit_config.max_contract_size,
         });
     }
@@ -442,6 +656,23 @@ fn validate_add_key_action(
     Ok(())
 }
 
+/// Checks that the method names of a function call `AddKeyAction` have no `*`, except as their
+/// last character.
+fn validate_method_name_patterns(action: &AddKeyAction) -> Result<(), ActionsValidationError> {
+    if let AccessKeyPermission::FunctionCall(fc) = &action.access_key.permission {
+        for method_name in &fc.method_names {
+            let name = method_name.strip_suffix(METHOD_NAME_WILDCARD).unwrap_or(method_name);
+            if name.contains(METHOD_NAME_WILDCARD) {
+                return Err(ActionsValidationError::AddKeyInvalidMethodNamePattern {
+                    method_name: method_name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Validates `DeleteAccountAction`. Checks that the `beneficiary_id` is a valid account ID.
 fn validate_delete_account_action(
     action: &DeleteAccountAction,
@@ -1231,13 +1462,190 @@ mod tests {
         .expect("valid transaction");
     }
 
+    #[test]
+    fn test_validate_transaction_exceeding_number_of_actions_limit() {
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+
+        let transaction = SignedTransaction::from_actions(
+            1,
+            alice_account(),
+            bob_account(),
+            &*signer,
+            vec![Action::Transfer(TransferAction { deposit: 1 }); 3],
+            CryptoHash::default(),
+        );
+
+        let mut config = RuntimeConfig::default();
+        config.wasm_config.limit_config.max_actions_per_transaction = 2;
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &transaction,
+                false,
+                None,
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::NumberOfActionsExceeded {
+                number_of_actions: 3,
+                limit: 2
+            }),
+        );
+
+        config.wasm_config.limit_config.max_actions_per_transaction = 3;
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &transaction,
+            false,
+            None,
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_receipt_congestion_control")]
+    fn test_validate_transaction_congested_receiver() {
+        use near_store::set_delayed_receipts_count;
+
+        let (signer, mut state_update, gas_price) =
+            setup_common(TESTING_INIT_BALANCE, 0, Some(AccessKey::full_access()));
+        let transaction = SignedTransaction::send_money(
+            1,
+            alice_account(),
+            bob_account(),
+            &*signer,
+            100,
+            CryptoHash::default(),
+        );
+
+        let mut config = RuntimeConfig::default();
+        config.max_delayed_receipts_per_account = 2;
+        set_delayed_receipts_count(&mut state_update, bob_account(), 2);
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &transaction,
+                false,
+                None,
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::ReceiverCongested {
+                receiver_id: bob_account(),
+                delayed_receipts: 2,
+                limit: 2
+            }),
+        );
+
+        set_delayed_receipts_count(&mut state_update, bob_account(), 1);
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &transaction,
+            false,
+            None,
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_transaction_valid_until")]
+    fn test_validate_transaction_valid_until() {
+        use near_primitives::transaction::ValidUntilAction;
+        use near_primitives::version::ProtocolFeature;
+
+        let config = RuntimeConfig::default();
+        let (signer, mut state_update, gas_price) = setup_common(
+            TESTING_INIT_BALANCE,
+            0,
+            Some(AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                    allowance: None,
+                    receiver_id: bob_account(),
+                    method_names: vec![],
+                }),
+            }),
+        );
+        // Function call access keys accept the action in front of their function call.
+        let transaction = SignedTransaction::from_actions(
+            1,
+            alice_account(),
+            bob_account(),
+            &*signer,
+            vec![
+                Action::ValidUntil(ValidUntilAction { valid_until: 10 }),
+                Action::FunctionCall(FunctionCallAction {
+                    method_name: "hello".to_string(),
+                    args: b"abc".to_vec(),
+                    gas: 100,
+                    deposit: 0,
+                }),
+            ],
+            CryptoHash::default(),
+        );
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &transaction,
+                true,
+                Some(11),
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::ValidUntilExceeded {
+                valid_until: 10,
+                block_height: 11,
+            }),
+        );
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &transaction,
+            true,
+            Some(10),
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+
+        let protocol_version = ProtocolFeature::TransactionValidUntil.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(
+                &config.wasm_config.limit_config,
+                &transaction.transaction.actions,
+                protocol_version
+            ),
+            Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "TransactionValidUntil".to_string(),
+                version: protocol_version,
+            })
+        );
+    }
+
     // Receipts
 
     #[test]
     fn test_validate_receipt_valid() {
         let limit_config = VMLimitConfig::default();
-        validate_receipt(&limit_config, &Receipt::new_balance_refund(&alice_account(), 10))
-            .expect("valid receipt");
+        validate_receipt(
+            &limit_config,
+            &Receipt::new_balance_refund(&alice_account(), 10),
+            PROTOCOL_VERSION,
+        )
+        .expect("valid receipt");
     }
 
     #[test]
@@ -1247,7 +1655,8 @@ mod tests {
         let mut receipt = Receipt::new_balance_refund(&alice_account(), 10);
         receipt.predecessor_id = invalid_account_id.clone();
         assert_eq!(
-            validate_receipt(&limit_config, &receipt).expect_err("expected an error"),
+            validate_receipt(&limit_config, &receipt, PROTOCOL_VERSION)
+                .expect_err("expected an error"),
             ReceiptValidationError::InvalidPredecessorId { account_id: invalid_account_id }
         );
     }
@@ -1257,8 +1666,12 @@ mod tests {
         let limit_config = VMLimitConfig::default();
         let invalid_account_id = "WHAT?".to_string();
         assert_eq!(
-            validate_receipt(&limit_config, &Receipt::new_balance_refund(&invalid_account_id, 10))
-                .expect_err("expected an error"),
+            validate_receipt(
+                &limit_config,
+                &Receipt::new_balance_refund(&invalid_account_id, 10),
+                PROTOCOL_VERSION
+            )
+            .expect_err("expected an error"),
             ReceiptValidationError::InvalidReceiverId { account_id: invalid_account_id }
         );
     }
@@ -1279,7 +1692,8 @@ mod tests {
                     output_data_receivers: vec![],
                     input_data_ids: vec![],
                     actions: vec![]
-                }
+                },
+                PROTOCOL_VERSION
             )
             .expect_err("expected an error"),
             ReceiptValidationError::InvalidSignerId { account_id: invalid_account_id }
@@ -1303,7 +1717,8 @@ mod tests {
                     }],
                     input_data_ids: vec![],
                     actions: vec![]
-                }
+                },
+                PROTOCOL_VERSION
             )
             .expect_err("expected an error"),
             ReceiptValidationError::InvalidDataReceiverId { account_id: invalid_account_id }
@@ -1324,7 +1739,8 @@ mod tests {
                     output_data_receivers: vec![],
                     input_data_ids: vec![CryptoHash::default(), CryptoHash::default()],
                     actions: vec![]
-                }
+                },
+                PROTOCOL_VERSION
             )
             .expect_err("expected an error"),
             ReceiptValidationError::NumberInputDataDependenciesExceeded {
@@ -1375,7 +1791,7 @@ mod tests {
     #[test]
     fn test_validate_actions_empty() {
         let limit_config = VMLimitConfig::default();
-        validate_actions(&limit_config, &[]).expect("empty actions");
+        validate_actions(&limit_config, &[], PROTOCOL_VERSION).expect("empty actions");
     }
 
     #[test]
@@ -1389,6 +1805,7 @@ mod tests {
                 gas: 100,
                 deposit: 0,
             })],
+            PROTOCOL_VERSION,
         )
         .expect("valid function call action");
     }
@@ -1413,7 +1830,8 @@ mod tests {
                         gas: 150,
                         deposit: 0,
                     })
-                ]
+                ],
+                PROTOCOL_VERSION
             )
             .expect_err("expected an error"),
             ActionsValidationError::TotalPrepaidGasExceeded { total_prepaid_gas: 250, limit: 220 }
@@ -1440,7 +1858,8 @@ mod tests {
                         gas: u64::max_value() / 2 + 1,
                         deposit: 0,
                     })
-                ]
+                ],
+                PROTOCOL_VERSION
             )
             .expect_err("Expected an error"),
             ActionsValidationError::IntegerOverflow,
@@ -1457,7 +1876,8 @@ mod tests {
                 &vec![
                     Action::CreateAccount(CreateAccountAction {}),
                     Action::CreateAccount(CreateAccountAction {}),
-                ]
+                ],
+                PROTOCOL_VERSION
             )
             .expect_err("Expected an error"),
             ActionsValidationError::TotalNumberOfActionsExceeded {
@@ -1477,7 +1897,8 @@ mod tests {
                 &vec![
                     Action::DeleteAccount(DeleteAccountAction { beneficiary_id: "bob".into() }),
                     Action::CreateAccount(CreateAccountAction {}),
-                ]
+                ],
+                PROTOCOL_VERSION
             )
             .expect_err("Expected an error"),
             ActionsValidationError::DeleteActionMustBeFinal,
@@ -1494,12 +1915,212 @@ mod tests {
                 &vec![
                     Action::CreateAccount(CreateAccountAction {}),
                     Action::DeleteAccount(DeleteAccountAction { beneficiary_id: "bob".into() }),
-                ]
+                ],
+                PROTOCOL_VERSION
             ),
             Ok(()),
         );
     }
 
+    #[test]
+    #[cfg(feature = "protocol_feature_duplicate_actions_validation")]
+    fn test_validate_duplicate_deploy_contract() {
+        let limit_config = VMLimitConfig::default();
+        assert_eq!(
+            validate_actions(
+                &limit_config,
+                &vec![
+                    Action::DeployContract(DeployContractAction { code: vec![1] }),
+                    Action::CreateAccount(CreateAccountAction {}),
+                    Action::DeployContract(DeployContractAction { code: vec![2] }),
+                ],
+                PROTOCOL_VERSION
+            )
+            .expect_err("Expected an error"),
+            ActionsValidationError::DuplicateDeployContract { index: 2 },
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_duplicate_actions_validation")]
+    fn test_validate_duplicate_keys() {
+        let limit_config = VMLimitConfig::default();
+        let public_key = PublicKey::empty(KeyType::ED25519);
+        let add_key = Action::AddKey(AddKeyAction {
+            public_key: public_key.clone(),
+            access_key: AccessKey::full_access(),
+        });
+        let delete_key = Action::DeleteKey(DeleteKeyAction { public_key: public_key.clone() });
+        assert_eq!(
+            validate_actions(
+                &limit_config,
+                &vec![add_key.clone(), add_key.clone()],
+                PROTOCOL_VERSION
+            )
+            .expect_err("Expected an error"),
+            ActionsValidationError::DuplicateAddKey { public_key: public_key.clone(), index: 1 },
+        );
+        assert_eq!(
+            validate_actions(
+                &limit_config,
+                &vec![delete_key.clone(), add_key.clone(), delete_key.clone()],
+                PROTOCOL_VERSION
+            )
+            .expect_err("Expected an error"),
+            ActionsValidationError::DuplicateDeleteKey { public_key, index: 2 },
+        );
+        // Replacing a key is allowed.
+        assert_eq!(
+            validate_actions(&limit_config, &vec![delete_key, add_key], PROTOCOL_VERSION),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_duplicate_actions_validation")]
+    fn test_validate_duplicate_actions_before_feature() {
+        use near_primitives::version::ProtocolFeature;
+        let limit_config = VMLimitConfig::default();
+        let add_key = Action::AddKey(AddKeyAction {
+            public_key: PublicKey::empty(KeyType::ED25519),
+            access_key: AccessKey::full_access(),
+        });
+        let protocol_version = ProtocolFeature::DuplicateActionsValidation.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(&limit_config, &vec![add_key.clone(), add_key], protocol_version),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_optional_function_calls")]
+    fn test_validate_optional_function_call() {
+        use near_primitives::version::ProtocolFeature;
+        let limit_config = VMLimitConfig::default();
+        let actions = vec![Action::OptionalFunctionCall(FunctionCallAction {
+            method_name: "hello".to_string(),
+            args: vec![],
+            gas: 100,
+            deposit: 0,
+        })];
+        assert_eq!(validate_actions(&limit_config, &actions, PROTOCOL_VERSION), Ok(()));
+        let protocol_version = ProtocolFeature::OptionalFunctionCalls.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(&limit_config, &actions, protocol_version),
+            Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "OptionalFunctionCalls".to_string(),
+                version: protocol_version,
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_max_storage_delta")]
+    fn test_validate_max_storage_delta() {
+        use near_primitives::transaction::MaxStorageDeltaAction;
+        use near_primitives::version::ProtocolFeature;
+        let limit_config = VMLimitConfig::default();
+        let max_storage_delta =
+            Action::MaxStorageDelta(MaxStorageDeltaAction { max_storage_delta: 1000 });
+        let create_account = Action::CreateAccount(CreateAccountAction {});
+        let actions = vec![max_storage_delta.clone(), create_account.clone()];
+        assert_eq!(validate_actions(&limit_config, &actions, PROTOCOL_VERSION), Ok(()));
+        assert_eq!(
+            validate_actions(&limit_config, &[create_account, max_storage_delta], PROTOCOL_VERSION),
+            Err(ActionsValidationError::MaxStorageDeltaMustBeFirst { index: 1 })
+        );
+        let protocol_version = ProtocolFeature::MaxStorageDelta.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(&limit_config, &actions, protocol_version),
+            Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "MaxStorageDelta".to_string(),
+                version: protocol_version,
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_global_contracts")]
+    fn test_validate_global_contract() {
+        use near_primitives::transaction::{DeployGlobalContractAction, UseGlobalContractAction};
+        use near_primitives::version::ProtocolFeature;
+        let limit_config = VMLimitConfig::default();
+        let actions = vec![
+            Action::DeployGlobalContract(DeployGlobalContractAction { code: vec![1; 10] }),
+            Action::UseGlobalContract(UseGlobalContractAction { code_hash: CryptoHash::default() }),
+        ];
+        assert_eq!(validate_actions(&limit_config, &actions, PROTOCOL_VERSION), Ok(()));
+        let protocol_version = ProtocolFeature::GlobalContracts.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(&limit_config, &actions, protocol_version),
+            Err(ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "GlobalContracts".to_string(),
+                version: protocol_version,
+            })
+        );
+        let limit_config = VMLimitConfig { max_contract_size: 5, ..VMLimitConfig::default() };
+        assert_eq!(
+            validate_actions(&limit_config, &actions, PROTOCOL_VERSION),
+            Err(ActionsValidationError::ContractSizeExceeded { size: 10, limit: 5 })
+        );
+    }
+
+    #[test]
+    fn test_is_method_name_allowed() {
+        let method_names = vec!["nft_*".to_string(), "ping".to_string()];
+        assert!(is_method_name_allowed(&[], "anything", false));
+        assert!(is_method_name_allowed(&method_names, "ping", false));
+        assert!(!is_method_name_allowed(&method_names, "nft_transfer", false));
+        assert!(is_method_name_allowed(&method_names, "nft_*", false));
+
+        assert!(is_method_name_allowed(&method_names, "ping", true));
+        assert!(is_method_name_allowed(&method_names, "nft_transfer", true));
+        assert!(is_method_name_allowed(&method_names, "nft_", true));
+        assert!(!is_method_name_allowed(&method_names, "nft", true));
+        assert!(!is_method_name_allowed(&method_names, "pong", true));
+        assert!(is_method_name_allowed(&["*".to_string()], "pong", true));
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_method_name_patterns")]
+    fn test_validate_method_name_patterns() {
+        use near_primitives::version::ProtocolFeature;
+        let limit_config = VMLimitConfig::default();
+        let add_key = |method_name: &str| {
+            Action::AddKey(AddKeyAction {
+                public_key: PublicKey::empty(KeyType::ED25519),
+                access_key: AccessKey {
+                    nonce: 0,
+                    permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                        allowance: None,
+                        receiver_id: bob_account(),
+                        method_names: vec!["ping".to_string(), method_name.to_string()],
+                    }),
+                },
+            })
+        };
+        for method_name in &["nft_*", "*"] {
+            assert_eq!(
+                validate_actions(&limit_config, &[add_key(method_name)], PROTOCOL_VERSION),
+                Ok(())
+            );
+        }
+        for method_name in &["nft_**", "*_transfer", "nft_*_transfer"] {
+            assert_eq!(
+                validate_actions(&limit_config, &[add_key(method_name)], PROTOCOL_VERSION),
+                Err(ActionsValidationError::AddKeyInvalidMethodNamePattern {
+                    method_name: method_name.to_string(),
+                })
+            );
+        }
+        // Before the patterns, `*` was an ordinary character of the method names.
+        let protocol_version = ProtocolFeature::MethodNamePatterns.protocol_version() - 1;
+        assert_eq!(
+            validate_actions(&limit_config, &[add_key("*_transfer")], protocol_version),
+            Ok(())
+        );
+    }
+
     // Individual actions
 
     #[test]
@@ -3,17 +3,20 @@ use near_crypto::{InMemorySigner, KeyType};
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::Receipt;
+use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
 use near_primitives::state_record::{state_record_to_account_id, StateRecord};
 use near_primitives::test_utils::MockEpochInfoProvider;
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
-use near_primitives::types::{AccountId, Balance};
+use near_primitives::types::{AccountId, Balance, BlockHeight, Gas, ShardId};
 use near_primitives::version::PROTOCOL_VERSION;
-use near_store::test_utils::create_tries;
-use near_store::ShardTries;
+use near_store::test_utils::{create_test_store, create_tries};
+use near_store::{get_account, ShardTries};
 use node_runtime::{ApplyState, Runtime};
 use random_config::random_config;
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
@@ -29,6 +32,52 @@ pub const TESTING_INIT_STAKE: Balance = 50_000_000 * NEAR_BASE;
 /// One NEAR, divisible by 10^24.
 pub const NEAR_BASE: Balance = 1_000_000_000_000_000_000_000_000;
 
+/// Time between the blocks processed by a standalone runtime, in nanoseconds.
+pub const BLOCK_DURATION_NS: u64 = 1_000_000_000;
+
+fn test_runtime_config() -> RuntimeConfig {
+    let mut runtime_config = random_config();
+    // Bumping costs to avoid inflation overflows.
+    runtime_config.wasm_config.limit_config.max_total_prepaid_gas = 10u64.pow(15);
+    runtime_config.transaction_costs.action_receipt_creation_config.execution =
+        runtime_config.wasm_config.limit_config.max_total_prepaid_gas / 64;
+    runtime_config.transaction_costs.data_receipt_creation_config.base_cost.execution =
+        runtime_config.wasm_config.limit_config.max_total_prepaid_gas / 64;
+    runtime_config
+}
+
+fn test_apply_state(runtime_config: RuntimeConfig) -> ApplyState {
+    ApplyState {
+        block_index: 1,
+        prev_block_hash: Default::default(),
+        block_hash: Default::default(),
+        epoch_id: Default::default(),
+        epoch_height: 0,
+        gas_price: 100,
+        block_timestamp: 0,
+        gas_limit: None,
+        random_seed: Default::default(),
+        epoch_random_seed: Default::default(),
+        current_protocol_version: PROTOCOL_VERSION,
+        config: Arc::new(runtime_config),
+        cache: None,
+        is_new_chunk: true,
+        #[cfg(feature = "protocol_feature_evm")]
+        evm_chain_id: near_chain_configs::TESTNET_EVM_CHAIN_ID,
+        profile: Default::default(),
+        migration_data: Arc::new(MigrationData::default()),
+        migration_flags: MigrationFlags::default(),
+        observer: None,
+    }
+}
+
+/// State of a standalone runtime as of some block, see `StandaloneRuntime::snapshot`.
+#[derive(Clone)]
+pub struct StandaloneSnapshot {
+    apply_state: ApplyState,
+    root: CryptoHash,
+}
+
 pub struct StandaloneRuntime {
     pub apply_state: ApplyState,
     pub runtime: Runtime,
@@ -44,14 +93,7 @@ impl StandaloneRuntime {
     }
 
     pub fn new(signer: InMemorySigner, state_records: &[StateRecord], tries: ShardTries) -> Self {
-        let mut runtime_config = random_config();
-        // Bumping costs to avoid inflation overflows.
-        runtime_config.wasm_config.limit_config.max_total_prepaid_gas = 10u64.pow(15);
-        runtime_config.transaction_costs.action_receipt_creation_config.execution =
-            runtime_config.wasm_config.limit_config.max_total_prepaid_gas / 64;
-        runtime_config.transaction_costs.data_receipt_creation_config.base_cost.execution =
-            runtime_config.wasm_config.limit_config.max_total_prepaid_gas / 64;
-
+        let runtime_config = test_runtime_config();
         let runtime = Runtime::new();
         let genesis =
             Genesis::new(GenesisConfig::default(), GenesisRecords(state_records.to_vec()));
@@ -69,29 +111,8 @@ impl StandaloneRuntime {
             account_ids,
         );
 
-        let apply_state = ApplyState {
-            block_index: 1,
-            prev_block_hash: Default::default(),
-            block_hash: Default::default(),
-            epoch_id: Default::default(),
-            epoch_height: 0,
-            gas_price: 100,
-            block_timestamp: 0,
-            gas_limit: None,
-            random_seed: Default::default(),
-            current_protocol_version: PROTOCOL_VERSION,
-            config: Arc::new(runtime_config),
-            cache: None,
-            is_new_chunk: true,
-            #[cfg(feature = "protocol_feature_evm")]
-            evm_chain_id: near_chain_configs::TESTNET_EVM_CHAIN_ID,
-            profile: Default::default(),
-            migration_data: Arc::new(MigrationData::default()),
-            migration_flags: MigrationFlags::default(),
-        };
-
         Self {
-            apply_state,
+            apply_state: test_apply_state(runtime_config),
             runtime,
             tries,
             signer,
@@ -100,6 +121,21 @@ impl StandaloneRuntime {
         }
     }
 
+    /// Creates a runtime forked from the state of a live network, dumped by the `dump_state`
+    /// command of the state viewer. The key of the signer is added as a full access key of its
+    /// account, so transactions of the account can be signed without knowing its actual keys.
+    pub fn from_state_dump(signer: InMemorySigner, genesis_path: &Path, tries: ShardTries) -> Self {
+        let genesis = Genesis::from_file(genesis_path);
+        let mut state_records = vec![];
+        genesis.for_each_record(|record: &StateRecord| state_records.push(record.clone()));
+        state_records.push(StateRecord::AccessKey {
+            account_id: signer.account_id.clone(),
+            public_key: signer.public_key.clone(),
+            access_key: AccessKey::full_access().into(),
+        });
+        Self::new(signer, &state_records, tries)
+    }
+
     pub fn process_block(
         &mut self,
         receipts: &[Receipt],
@@ -119,13 +155,192 @@ impl StandaloneRuntime {
             )
             .unwrap();
 
-        let (store_update, root) = self.tries.apply_all(&apply_result.trie_changes, 0).unwrap();
-        self.root = root;
+        // The nodes of the previous states are kept, like on the chain before they are garbage
+        // collected, so snapshots can be restored.
+        let mut store_update = self.tries.get_store().store_update();
+        self.tries.apply_insertions(&apply_result.trie_changes, 0, &mut store_update).unwrap();
         store_update.commit().unwrap();
+        self.root = apply_result.trie_changes.new_root;
         self.apply_state.block_index += 1;
+        self.apply_state.block_timestamp += BLOCK_DURATION_NS;
 
         (apply_result.outgoing_receipts, apply_result.outcomes)
     }
+
+    /// Processes the block at the given height and timestamp, the following blocks continue from
+    /// there. Lets the tests exercise time-dependent contract logic, e.g. vesting or auctions.
+    pub fn produce_block_at(
+        &mut self,
+        height: BlockHeight,
+        timestamp: u64,
+        receipts: &[Receipt],
+        transactions: &[SignedTransaction],
+    ) -> (Vec<Receipt>, Vec<ExecutionOutcomeWithId>) {
+        assert!(
+            height >= self.apply_state.block_index,
+            "Block height {} is below the next block height {}",
+            height,
+            self.apply_state.block_index
+        );
+        self.apply_state.block_index = height;
+        self.apply_state.block_timestamp = timestamp;
+        self.process_block(receipts, transactions)
+    }
+
+    /// Processes `n_blocks` empty blocks, so the delayed receipts get processed and the time
+    /// passes. Returns the outgoing receipts and the outcomes of all these blocks.
+    pub fn fast_forward(&mut self, n_blocks: u64) -> (Vec<Receipt>, Vec<ExecutionOutcomeWithId>) {
+        let mut outgoing_receipts = vec![];
+        let mut outcomes = vec![];
+        for _ in 0..n_blocks {
+            let (receipts, block_outcomes) = self.process_block(&[], &[]);
+            outgoing_receipts.extend(receipts);
+            outcomes.extend(block_outcomes);
+        }
+        (outgoing_receipts, outcomes)
+    }
+
+    /// Limits the gas burnt by the following blocks, receipts over the limit are delayed.
+    pub fn set_block_gas_limit(&mut self, gas_limit: Gas) {
+        self.apply_state.gas_limit = Some(gas_limit);
+    }
+
+    /// Snapshot of the current state, to run what-if transactions and go back to it.
+    pub fn snapshot(&self) -> StandaloneSnapshot {
+        StandaloneSnapshot { apply_state: self.apply_state.clone(), root: self.root }
+    }
+
+    /// Goes back to the state of the snapshot, the following blocks continue from there.
+    pub fn restore(&mut self, snapshot: &StandaloneSnapshot) {
+        self.apply_state = snapshot.apply_state.clone();
+        self.root = snapshot.root;
+    }
+
+    pub fn view_account(&self, account_id: &AccountId) -> Option<Account> {
+        let state_update = self.tries.new_trie_update(0, self.root);
+        get_account(&state_update, account_id).unwrap()
+    }
+}
+
+/// Standalone runtime simulating several shards, to test the assumptions of the contracts about
+/// the latency between the shards. The accounts are assigned to the shards by the shard layout.
+/// Every block applies each shard to its own state root, and the receipts sent by a shard are
+/// applied by their receiving shard in the next block, like on the chain.
+pub struct ShardedStandaloneRuntime {
+    pub apply_state: ApplyState,
+    pub runtime: Runtime,
+    pub tries: ShardTries,
+    pub shard_layout: ShardLayout,
+    /// State root of every shard.
+    pub roots: Vec<CryptoHash>,
+    /// Receipts every shard applies in the next block.
+    pub incoming_receipts: Vec<Vec<Receipt>>,
+    pub epoch_info_provider: MockEpochInfoProvider,
+}
+
+impl ShardedStandaloneRuntime {
+    pub fn new(shard_layout: ShardLayout, state_records: &[StateRecord]) -> Self {
+        let runtime_config = test_runtime_config();
+        let runtime = Runtime::new();
+        let num_shards = shard_layout.num_shards();
+        let tries = ShardTries::new(create_test_store(), num_shards);
+        let genesis =
+            Genesis::new(GenesisConfig::default(), GenesisRecords(state_records.to_vec()));
+
+        let mut shard_account_ids = vec![HashSet::new(); num_shards as usize];
+        genesis.for_each_record(|record: &StateRecord| {
+            let account_id = state_record_to_account_id(record);
+            let shard_id = account_id_to_shard_id(account_id, &shard_layout);
+            shard_account_ids[shard_id as usize].insert(account_id.clone());
+        });
+        let roots = shard_account_ids
+            .into_iter()
+            .enumerate()
+            .map(|(shard_id, account_ids)| {
+                runtime.apply_genesis_state(
+                    tries.clone(),
+                    shard_id as ShardId,
+                    &[],
+                    &genesis,
+                    &runtime_config,
+                    account_ids,
+                )
+            })
+            .collect();
+
+        Self {
+            apply_state: test_apply_state(runtime_config),
+            runtime,
+            tries,
+            shard_layout,
+            roots,
+            incoming_receipts: vec![vec![]; num_shards as usize],
+            epoch_info_provider: MockEpochInfoProvider::default(),
+        }
+    }
+
+    pub fn shard_id(&self, account_id: &AccountId) -> ShardId {
+        account_id_to_shard_id(account_id, &self.shard_layout)
+    }
+
+    /// Applies every shard, the transactions are applied by the shards of their signers. Returns
+    /// the outcomes of all the shards.
+    pub fn process_block(
+        &mut self,
+        transactions: &[SignedTransaction],
+    ) -> Vec<ExecutionOutcomeWithId> {
+        let num_shards = self.shard_layout.num_shards() as usize;
+        let mut shard_transactions = vec![vec![]; num_shards];
+        for transaction in transactions {
+            let shard_id = self.shard_id(&transaction.transaction.signer_id);
+            shard_transactions[shard_id as usize].push(transaction.clone());
+        }
+        let incoming_receipts =
+            std::mem::replace(&mut self.incoming_receipts, vec![vec![]; num_shards]);
+
+        let mut outcomes = vec![];
+        for (shard_id, (receipts, transactions)) in
+            incoming_receipts.iter().zip(shard_transactions.iter()).enumerate()
+        {
+            let apply_result = self
+                .runtime
+                .apply(
+                    self.tries.get_trie_for_shard(shard_id as ShardId),
+                    self.roots[shard_id],
+                    &None,
+                    &self.apply_state,
+                    receipts,
+                    transactions,
+                    &self.epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+
+            let (store_update, root) =
+                self.tries.apply_all(&apply_result.trie_changes, shard_id as ShardId).unwrap();
+            self.roots[shard_id] = root;
+            store_update.commit().unwrap();
+            for receipt in apply_result.outgoing_receipts {
+                let receiver_shard_id = self.shard_id(&receipt.receiver_id);
+                self.incoming_receipts[receiver_shard_id as usize].push(receipt);
+            }
+            outcomes.extend(apply_result.outcomes);
+        }
+        self.apply_state.block_index += 1;
+        self.apply_state.block_timestamp += BLOCK_DURATION_NS;
+        outcomes
+    }
+
+    /// Whether some receipts are waiting to be applied in the next block.
+    pub fn has_pending_receipts(&self) -> bool {
+        self.incoming_receipts.iter().any(|receipts| !receipts.is_empty())
+    }
+
+    pub fn view_account(&self, account_id: &AccountId) -> Option<Account> {
+        let shard_id = self.shard_id(account_id);
+        let state_update = self.tries.new_trie_update(shard_id, self.roots[shard_id as usize]);
+        get_account(&state_update, account_id).unwrap()
+    }
 }
 
 #[derive(Default)]
@@ -179,7 +394,7 @@ impl RuntimeGroup {
     }
 
     /// Get state records and signers for standalone runtimes.
-    fn state_records_signers(
+    pub fn state_records_signers(
         account_ids: Vec<AccountId>,
         num_existing_accounts: u64,
         contract_code: &[u8],
@@ -41,6 +41,7 @@ pub fn random_config() -> RuntimeConfig {
                 },
                 delete_key_cost: random_fee(),
                 delete_account_cost: random_fee(),
+                action_overhead_cost: random_fee(),
             },
             storage_usage_config: StorageUsageConfig {
                 num_bytes_account: rng.next_u64() % 10000,
@@ -51,6 +52,7 @@ pub fn random_config() -> RuntimeConfig {
                 (101 + rng.next_u32() % 10).try_into().unwrap(),
                 100,
             ),
+            storage_rebate_per_byte: (rng.next_u64() % 1000).into(),
             #[cfg(feature = "protocol_feature_evm")]
             evm_config: EvmCostConfig {
                 bootstrap_cost: rng.next_u64() % 1000,
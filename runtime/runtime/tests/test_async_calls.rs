@@ -1,4 +1,4 @@
-use crate::runtime_group_tools::RuntimeGroup;
+use crate::runtime_group_tools::{RuntimeGroup, ShardedStandaloneRuntime};
 use borsh::ser::BorshSerialize;
 use near_crypto::{InMemorySigner, KeyType};
 use near_primitives::account::{AccessKeyPermission, FunctionCallPermission};
@@ -1076,3 +1076,159 @@ fn test_create_transfer_64len_hex_fail() {
     assert_refund!(group, ref1 @ "near_1");
     assert_refund!(group, ref2 @ "near_0");
 }
+
+#[test]
+fn test_cross_shard_transfer_takes_a_block() {
+    use near_primitives::shard_layout::ShardLayout;
+    use near_primitives::transaction::SignedTransaction;
+
+    let account_ids = vec!["near_0".to_string(), "near_1".to_string()];
+    let (state_records, signers) = RuntimeGroup::state_records_signers(
+        account_ids.clone(),
+        2,
+        near_test_contracts::rs_contract(),
+    );
+    let mut runtime = ShardedStandaloneRuntime::new(
+        ShardLayout::v1(vec!["near_1".to_string()], 1),
+        &state_records,
+    );
+    assert_eq!(runtime.shard_id(&account_ids[0]), 0);
+    assert_eq!(runtime.shard_id(&account_ids[1]), 1);
+
+    let transaction = SignedTransaction::send_money(
+        1,
+        account_ids[0].clone(),
+        account_ids[1].clone(),
+        &signers[0],
+        NEAR_BASE,
+        CryptoHash::default(),
+    );
+    let initial_balance = runtime.view_account(&account_ids[1]).unwrap().amount();
+    // The transaction is converted to a receipt sent to the shard of the receiver.
+    let outcomes = runtime.process_block(&[transaction]);
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(runtime.incoming_receipts[1].len(), 1);
+    assert_eq!(runtime.view_account(&account_ids[1]).unwrap().amount(), initial_balance);
+
+    // The receipt is applied in the next block.
+    runtime.process_block(&[]);
+    assert_eq!(
+        runtime.view_account(&account_ids[1]).unwrap().amount(),
+        initial_balance + NEAR_BASE
+    );
+}
+
+#[test]
+fn test_fork_state_dump_and_restore_snapshot() {
+    use crate::runtime_group_tools::StandaloneRuntime;
+    use near_chain_configs::{Genesis, GenesisConfig, GenesisRecords};
+    use near_primitives::transaction::SignedTransaction;
+    use near_store::test_utils::create_tries;
+
+    let account_ids = vec!["near_0".to_string(), "near_1".to_string()];
+    let (state_records, _) = RuntimeGroup::state_records_signers(
+        account_ids.clone(),
+        2,
+        near_test_contracts::rs_contract(),
+    );
+    let dir = tempfile::Builder::new().prefix("state_dump").tempdir().unwrap();
+    let genesis_path = dir.path().join("genesis.json");
+    Genesis::new(GenesisConfig::default(), GenesisRecords(state_records)).to_file(&genesis_path);
+
+    // The forked state doesn't know this key, the importer adds it to the account.
+    let signer = InMemorySigner::from_seed(&account_ids[0], KeyType::ED25519, "fork");
+    let mut runtime =
+        StandaloneRuntime::from_state_dump(signer.clone(), &genesis_path, create_tries());
+    let initial_balance = runtime.view_account(&account_ids[1]).unwrap().amount();
+    let snapshot = runtime.snapshot();
+
+    let transaction = SignedTransaction::send_money(
+        1,
+        account_ids[0].clone(),
+        account_ids[1].clone(),
+        &signer,
+        NEAR_BASE,
+        CryptoHash::default(),
+    );
+    let (receipts, _) = runtime.process_block(&[], &[transaction]);
+    runtime.process_block(&receipts, &[]);
+    assert_eq!(
+        runtime.view_account(&account_ids[1]).unwrap().amount(),
+        initial_balance + NEAR_BASE
+    );
+
+    runtime.restore(&snapshot);
+    assert_eq!(runtime.view_account(&account_ids[1]).unwrap().amount(), initial_balance);
+    assert_eq!(runtime.apply_state.block_index, 1);
+}
+
+#[test]
+fn test_produce_block_at_and_fast_forward() {
+    use crate::runtime_group_tools::{StandaloneRuntime, BLOCK_DURATION_NS};
+    use near_store::test_utils::create_tries;
+
+    let account_ids = vec!["near_0".to_string()];
+    let (state_records, signers) =
+        RuntimeGroup::state_records_signers(account_ids, 1, near_test_contracts::rs_contract());
+    let mut runtime = StandaloneRuntime::new(signers[0].clone(), &state_records, create_tries());
+
+    runtime.produce_block_at(10, 100 * BLOCK_DURATION_NS, &[], &[]);
+    assert_eq!(runtime.apply_state.block_index, 11);
+    assert_eq!(runtime.apply_state.block_timestamp, 101 * BLOCK_DURATION_NS);
+
+    // The following blocks continue from the given height and timestamp.
+    runtime.fast_forward(5);
+    assert_eq!(runtime.apply_state.block_index, 16);
+    assert_eq!(runtime.apply_state.block_timestamp, 106 * BLOCK_DURATION_NS);
+}
+
+#[test]
+fn test_block_gas_limit_delays_receipts() {
+    use crate::runtime_group_tools::StandaloneRuntime;
+    use near_primitives::transaction::SignedTransaction;
+    use near_store::test_utils::create_tries;
+
+    let account_ids = vec!["near_0".to_string(), "near_1".to_string()];
+    let (state_records, signers) = RuntimeGroup::state_records_signers(
+        account_ids.clone(),
+        2,
+        near_test_contracts::rs_contract(),
+    );
+    let mut runtime = StandaloneRuntime::new(signers[0].clone(), &state_records, create_tries());
+    let initial_balance = runtime.view_account(&account_ids[1]).unwrap().amount();
+
+    let transactions: Vec<_> = (1..=3)
+        .map(|nonce| {
+            SignedTransaction::send_money(
+                nonce,
+                account_ids[0].clone(),
+                account_ids[1].clone(),
+                &signers[0],
+                NEAR_BASE,
+                CryptoHash::default(),
+            )
+        })
+        .collect();
+    let (receipts, _) = runtime.process_block(&[], &transactions);
+    assert_eq!(receipts.len(), 3);
+
+    // Only the first receipt fits in the gas limit, the others are delayed.
+    runtime.set_block_gas_limit(1);
+    runtime.process_block(&receipts, &[]);
+    assert_eq!(
+        runtime.view_account(&account_ids[1]).unwrap().amount(),
+        initial_balance + NEAR_BASE
+    );
+
+    // A delayed receipt is processed per block.
+    runtime.fast_forward(1);
+    assert_eq!(
+        runtime.view_account(&account_ids[1]).unwrap().amount(),
+        initial_balance + 2 * NEAR_BASE
+    );
+    runtime.fast_forward(1);
+    assert_eq!(
+        runtime.view_account(&account_ids[1]).unwrap().amount(),
+        initial_balance + 3 * NEAR_BASE
+    );
+}
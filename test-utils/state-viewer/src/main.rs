@@ -6,31 +6,39 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use ansi_term::Color::Red;
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 use borsh::BorshSerialize;
-use near_chain::chain::collect_receipts_from_response;
-use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
 use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
-use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
+use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
 use near_logger_utils::init_integration_logger;
 use near_network::peer_store::PeerStore;
 use near_primitives::block::BlockHeader;
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
+use near_primitives::runtime::config::ActualRuntimeConfig;
 use near_primitives::serialize::to_base;
 use near_primitives::state_record::StateRecord;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_primitives::types::{AccountId, BlockHeight, BlockId, ShardId, StateRoot};
+use near_primitives::views::{QueryRequest, QueryResponse};
+use near_store::export::{export_column_csv, export_state_records_csv};
 use near_store::test_utils::create_test_store;
-use near_store::{create_store, Store, TrieIterator};
+use near_store::{create_store, DBCol, Store, TrieIterator};
 use nearcore::{get_default_home, get_store_path, load_config, NearConfig, NightshadeRuntime};
 use node_runtime::adapter::ViewRuntimeAdapter;
+use node_runtime::supply::compute_total_supply;
+use shadow_apply::{apply_chunk, shadow_apply};
 use state_dump::state_dump;
+use strum::IntoEnumIterator;
 
+mod shadow_apply;
 mod state_dump;
 
+/// Number of accounts read from the trie at once by the `accounts` command.
+const ACCOUNTS_BATCH_SIZE: usize = 1000;
+
 #[allow(unused)]
 enum LoadTrieMode {
     /// Load latest state
@@ -227,75 +235,8 @@ fn apply_block_at_height(
         None,
         near_config.client_config.max_gas_burnt_view,
     ));
+    let apply_result = apply_chunk(&mut chain_store, runtime_adapter.as_ref(), height, shard_id);
     let block_hash = chain_store.get_block_hash_by_height(height).unwrap();
-    let block = chain_store.get_block(&block_hash).unwrap().clone();
-    let apply_result = if block.chunks()[shard_id as usize].height_included() == height {
-        let chunk =
-            chain_store.get_chunk(&block.chunks()[shard_id as usize].chunk_hash()).unwrap().clone();
-        let prev_block = chain_store.get_block(&block.header().prev_hash()).unwrap().clone();
-        let mut chain_store_update = ChainStoreUpdate::new(&mut chain_store);
-        let receipt_proof_response = chain_store_update
-            .get_incoming_receipts_for_shard(
-                shard_id,
-                block_hash,
-                prev_block.chunks()[shard_id as usize].height_included(),
-            )
-            .unwrap();
-        let receipts = collect_receipts_from_response(&receipt_proof_response);
-
-        let chunk_inner = chunk.cloned_header().take_inner();
-        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
-            &mut chain_store,
-            runtime_adapter.as_ref(),
-            block.header().prev_hash(),
-            shard_id,
-        )
-        .unwrap();
-        runtime_adapter
-            .apply_transactions(
-                shard_id,
-                chunk_inner.prev_state_root(),
-                height,
-                block.header().raw_timestamp(),
-                block.header().prev_hash(),
-                block.hash(),
-                &receipts,
-                chunk.transactions(),
-                chunk_inner.validator_proposals(),
-                prev_block.header().gas_price(),
-                chunk_inner.gas_limit(),
-                &block.header().challenges_result(),
-                *block.header().random_value(),
-                true,
-                is_first_block_with_chunk_of_version,
-                None,
-            )
-            .unwrap()
-    } else {
-        let chunk_extra =
-            chain_store.get_chunk_extra(block.header().prev_hash(), shard_id).unwrap().clone();
-
-        runtime_adapter
-            .apply_transactions(
-                shard_id,
-                chunk_extra.state_root(),
-                block.header().height(),
-                block.header().raw_timestamp(),
-                block.header().prev_hash(),
-                &block.hash(),
-                &[],
-                &[],
-                chunk_extra.validator_proposals(),
-                block.header().gas_price(),
-                chunk_extra.gas_limit(),
-                &block.header().challenges_result(),
-                *block.header().random_value(),
-                false,
-                false,
-                None,
-            )
-            .unwrap()
-    };
     let (outcome_root, _) = ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
     let chunk_extra = ChunkExtra::new(
         &apply_result.new_root,
@@ -415,6 +356,104 @@ fn dump_code(account: &str, contract_code: ContractCode, output: &str) {
     println!("Dump contract of account {} into file {}", account, output);
 }
 
+/// Runs a view query against the state of the shard of the account right after the given block,
+/// or after the head if no block is given. Fails if the state was garbage collected or if the
+/// node doesn't track the shard.
+fn query_at_block(
+    store: Arc<Store>,
+    home_dir: &Path,
+    near_config: &NearConfig,
+    block_id: Option<BlockId>,
+    account_id: &AccountId,
+    request: QueryRequest,
+) -> Result<QueryResponse, String> {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let runtime = NightshadeRuntime::new(
+        &home_dir,
+        store,
+        &near_config.genesis,
+        near_config.client_config.tracked_accounts.clone(),
+        near_config.client_config.tracked_shards.clone(),
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    // Blocks up to the tail are garbage collected, except maybe a single one.
+    let tail = chain_store.tail().map_err(|err| err.to_string())?;
+    let garbage_collected = |height: BlockHeight| {
+        format!(
+            "State at height {} is garbage collected, the node only keeps the blocks above height {}",
+            height, tail
+        )
+    };
+    let block_hash = match block_id {
+        None => chain_store.head().map_err(|err| err.to_string())?.last_block_hash,
+        Some(BlockId::Hash(block_hash)) => block_hash,
+        Some(BlockId::Height(height)) => {
+            chain_store.get_block_hash_by_height(height).map_err(|_| {
+                if height <= tail {
+                    garbage_collected(height)
+                } else {
+                    format!("No block at height {} on the canonical chain", height)
+                }
+            })?
+        }
+    };
+    let header = chain_store
+        .get_block_header(&block_hash)
+        .map_err(|_| format!("Block {} is unknown or garbage collected", block_hash))?
+        .clone();
+    let shard_id = runtime.account_id_to_shard_id(account_id);
+    let state_root = *chain_store
+        .get_chunk_extra(header.hash(), shard_id)
+        .map_err(|_| {
+            if header.height() <= tail {
+                garbage_collected(header.height())
+            } else {
+                format!(
+                    "State of shard {} at block {} is not tracked by the node",
+                    shard_id, block_hash
+                )
+            }
+        })?
+        .state_root();
+    runtime
+        .query(
+            shard_id,
+            &state_root,
+            header.height(),
+            header.raw_timestamp(),
+            header.prev_hash(),
+            header.hash(),
+            header.epoch_id(),
+            &request,
+        )
+        .map_err(|err| err.to_string())
+}
+
+fn block_id_args<'a, 'b>() -> [Arg<'a, 'b>; 2] {
+    [
+        Arg::with_name("block_height")
+            .long("block_height")
+            .help("height of the block to view the state after, the head if not given")
+            .takes_value(true)
+            .conflicts_with("block_hash"),
+        Arg::with_name("block_hash")
+            .long("block_hash")
+            .help("hash of the block to view the state after, the head if not given")
+            .takes_value(true),
+    ]
+}
+
+fn parse_block_id(args: &ArgMatches) -> Option<BlockId> {
+    if let Some(height) = args.value_of("block_height") {
+        Some(BlockId::Height(height.parse().unwrap()))
+    } else if let Some(hash) = args.value_of("block_hash") {
+        Some(BlockId::Hash(hash.parse().unwrap()))
+    } else {
+        None
+    }
+}
+
 fn main() {
     init_integration_logger();
 
@@ -491,6 +530,31 @@ fn main() {
                 )
                 .help("apply block at some height for shard"),
         )
+        .subcommand(
+            SubCommand::with_name("shadow_apply")
+                .arg(
+                    Arg::with_name("start_index")
+                        .long("start_index")
+                        .required(true)
+                        .help("Start index of query")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("end_index")
+                        .long("end_index")
+                        .required(true)
+                        .help("End index of query")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("protocol_version")
+                        .long("protocol_version")
+                        .required(true)
+                        .help("Candidate protocol version to apply the blocks with")
+                        .takes_value(true),
+                )
+                .help("apply blocks with a candidate protocol version and report divergences"),
+        )
         .subcommand(
             SubCommand::with_name("view_chain")
                 .arg(
@@ -566,6 +630,93 @@ fn main() {
                 )
                 .help("dump contract data in storage of given account to binary file"),
         )
+        .subcommand(
+            SubCommand::with_name("accounts")
+                .arg(
+                    Arg::with_name("from_account")
+                        .long("from_account")
+                        .help("first account id to print in every shard")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .help("max number of accounts to print per shard")
+                        .takes_value(true),
+                )
+                .help("print the latest accounts of every shard and their total balances"),
+        )
+        .subcommand(
+            SubCommand::with_name("view_account")
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .help("account id")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .args(&block_id_args())
+                .help("print an account as of a block which isn't garbage collected"),
+        )
+        .subcommand(
+            SubCommand::with_name("view_access_keys")
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .help("account id")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .args(&block_id_args())
+                .help("print the access keys of an account as of a block which isn't garbage collected"),
+        )
+        .subcommand(
+            SubCommand::with_name("call_function")
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .help("account id of the contract")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("method_name")
+                        .long("method_name")
+                        .help("view method to call")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .long("args")
+                        .help("arguments of the call, e.g. JSON")
+                        .takes_value(true)
+                        .default_value(""),
+                )
+                .args(&block_id_args())
+                .help("call a view method against the state as of a block which isn't garbage collected"),
+        )
+        .subcommand(
+            SubCommand::with_name("total_supply")
+                .help("print the tokens held by the latest states of the shards"),
+        )
+        .subcommand(
+            SubCommand::with_name("export_csv")
+                .arg(
+                    Arg::with_name("column")
+                        .long("column")
+                        .help("database column to export, e.g. ColBlockHeader; the latest state records are exported if not given")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("output csv file, shard id is appended for state records")
+                        .takes_value(true)
+                        .default_value("output.csv"),
+                )
+                .help("export a database column or the state records to csv files"),
+        )
         .get_matches();
 
     let home_dir = matches.value_of("home").map(|dir| Path::new(dir)).unwrap();
@@ -635,6 +786,14 @@ fn main() {
                 args.value_of("shard_id").map(|s| s.parse::<u64>().unwrap()).unwrap_or_default();
             apply_block_at_height(store, home_dir, &near_config, height, shard_id);
         }
+        ("shadow_apply", Some(args)) => {
+            let start_index =
+                args.value_of("start_index").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let end_index = args.value_of("end_index").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let protocol_version =
+                args.value_of("protocol_version").map(|s| s.parse::<u32>().unwrap()).unwrap();
+            shadow_apply(store, home_dir, &near_config, start_index, end_index, protocol_version);
+        }
         ("view_chain", Some(args)) => {
             let height = args.value_of("height").map(|s| s.parse::<u64>().unwrap());
             let view_block = args.is_present("block");
@@ -704,6 +863,149 @@ fn main() {
             println!("Storage under key {} of account {} not found", storage_key, account_id);
             std::process::exit(1);
         }
+        ("accounts", Some(args)) => {
+            let from_account = args.value_of("from_account").map(|s| s.to_string());
+            let limit = args.value_of("limit").map(|s| s.parse::<usize>().unwrap());
+            let (runtime, state_roots, header) = load_trie(store, &home_dir, &near_config);
+            let tries = runtime.get_tries();
+            for (shard_id, state_root) in state_roots.iter().enumerate() {
+                let (mut num_accounts, mut total_amount, mut total_locked) = (0, 0, 0);
+                let mut from_account = from_account.clone();
+                loop {
+                    let batch_size = match limit {
+                        Some(limit) => std::cmp::min(ACCOUNTS_BATCH_SIZE, limit - num_accounts),
+                        None => ACCOUNTS_BATCH_SIZE,
+                    };
+                    if batch_size == 0 {
+                        break;
+                    }
+                    let accounts = tries
+                        .iterate_accounts(
+                            shard_id as ShardId,
+                            state_root,
+                            from_account.as_ref(),
+                            batch_size,
+                        )
+                        .unwrap();
+                    for (account_id, account) in accounts.iter() {
+                        println!("{} {:?}", account_id, account);
+                        total_amount += account.amount();
+                        total_locked += account.locked();
+                    }
+                    num_accounts += accounts.len();
+                    if accounts.len() < batch_size {
+                        break;
+                    }
+                    // Account ids are ordered by bytes, the next one is at least the last one
+                    // followed by the zero byte.
+                    let mut next_account = accounts.last().unwrap().0.clone();
+                    next_account.push('\0');
+                    from_account = Some(next_account);
+                }
+                println!(
+                    "Shard {} at height {}: {} accounts, total amount {}, total locked {}",
+                    shard_id,
+                    header.height(),
+                    num_accounts,
+                    total_amount,
+                    total_locked
+                );
+            }
+        }
+        ("view_account", Some(args))
+        | ("view_access_keys", Some(args))
+        | ("call_function", Some(args)) => {
+            let account_id = args.value_of("account").unwrap().to_string();
+            let request = match matches.subcommand_name() {
+                Some("view_account") => {
+                    QueryRequest::ViewAccount { account_id: account_id.clone() }
+                }
+                Some("view_access_keys") => {
+                    QueryRequest::ViewAccessKeyList { account_id: account_id.clone() }
+                }
+                _ => QueryRequest::CallFunction {
+                    account_id: account_id.clone(),
+                    method_name: args.value_of("method_name").unwrap().to_string(),
+                    args: args.value_of("args").unwrap().as_bytes().to_vec().into(),
+                },
+            };
+            match query_at_block(
+                store,
+                home_dir,
+                &near_config,
+                parse_block_id(args),
+                &account_id,
+                request,
+            ) {
+                Ok(response) => {
+                    println!(
+                        "At block #{} {}:\n{:#?}",
+                        response.block_height, response.block_hash, response.kind
+                    );
+                }
+                Err(err) => {
+                    println!("{}", Red.paint(err));
+                    std::process::exit(1);
+                }
+            }
+        }
+        ("total_supply", Some(_args)) => {
+            let (runtime, state_roots, header) = load_trie(store, &home_dir, &near_config);
+            let tries = runtime.get_tries();
+            let protocol_version = runtime.get_epoch_protocol_version(header.epoch_id()).unwrap();
+            let runtime_config =
+                ActualRuntimeConfig::new(near_config.genesis.config.runtime_config.clone(), None);
+            let transaction_costs =
+                &runtime_config.for_protocol_version(protocol_version).transaction_costs;
+            let mut total_supply = 0;
+            for (shard_id, state_root) in state_roots.iter().enumerate() {
+                let supply = compute_total_supply(
+                    &tries,
+                    shard_id as ShardId,
+                    state_root,
+                    transaction_costs,
+                    protocol_version,
+                )
+                .unwrap();
+                println!("Shard {}: {:?}", shard_id, supply);
+                total_supply += supply.total().unwrap();
+            }
+            // The receipts in flight between the shards and the tokens burnt by the last chunks
+            // are accounted in the header but not in the states.
+            println!(
+                "Total supply of the states at height {}: {}, total supply in the header: {}",
+                header.height(),
+                total_supply,
+                header.total_supply()
+            );
+        }
+        ("export_csv", Some(args)) => {
+            let output = args.value_of("output").unwrap();
+            if let Some(column) = args.value_of("column") {
+                let column = DBCol::iter()
+                    .find(|col| format!("{:?}", col) == column)
+                    .unwrap_or_else(|| panic!("unknown column {}", column));
+                let rows =
+                    export_column_csv(&store, column, File::create(output).unwrap()).unwrap();
+                println!("Exported {} rows of column {:?} into {}", rows, column, output);
+            } else {
+                let (runtime, state_roots, header) = load_trie(store, &home_dir, &near_config);
+                for (shard_id, state_root) in state_roots.iter().enumerate() {
+                    let trie = runtime.get_trie_for_shard(shard_id as u64);
+                    let output = format!("{}.{}", output, shard_id);
+                    let rows =
+                        export_state_records_csv(&trie, state_root, File::create(&output).unwrap())
+                            .unwrap();
+                    println!(
+                        "Exported {} state records of shard {} at height {} into {}",
+                        rows,
+                        shard_id,
+                        header.height(),
+                        output
+                    );
+                }
+            }
+        }
         (_, _) => unreachable!(),
     }
 }
@@ -0,0 +1,228 @@
+//! Replay of the chain with a candidate protocol version, to validate protocol changes against
+//! real traffic before they are released.
+//!
+//! Every chunk is applied twice from its canonical state root, once with the protocol version of
+//! its epoch and once with the candidate version, and the state roots, outcomes and gas of both
+//! are compared. Since every chunk starts over from the canonical state, a divergence is reported
+//! for the chunk causing it only. Nothing is written to the store.
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use near_chain::chain::collect_receipts_from_response;
+use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
+use near_chain::types::ApplyTransactionResult;
+use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
+use near_primitives::transaction::ExecutionOutcomeWithId;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::version::ProtocolVersion;
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+
+/// Applies the chunk of the shard in the block at the given height, or the block without a chunk
+/// if the shard has no new chunk in it.
+pub(crate) fn apply_chunk(
+    chain_store: &mut ChainStore,
+    runtime_adapter: &dyn RuntimeAdapter,
+    height: BlockHeight,
+    shard_id: ShardId,
+) -> ApplyTransactionResult {
+    let block_hash = chain_store.get_block_hash_by_height(height).unwrap();
+    let block = chain_store.get_block(&block_hash).unwrap().clone();
+    if block.chunks()[shard_id as usize].height_included() == height {
+        let chunk =
+            chain_store.get_chunk(&block.chunks()[shard_id as usize].chunk_hash()).unwrap().clone();
+        let prev_block = chain_store.get_block(&block.header().prev_hash()).unwrap().clone();
+        let mut chain_store_update = ChainStoreUpdate::new(chain_store);
+        let receipt_proof_response = chain_store_update
+            .get_incoming_receipts_for_shard(
+                shard_id,
+                block_hash,
+                prev_block.chunks()[shard_id as usize].height_included(),
+            )
+            .unwrap();
+        let receipts = collect_receipts_from_response(&receipt_proof_response);
+
+        let chunk_inner = chunk.cloned_header().take_inner();
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            chain_store,
+            runtime_adapter,
+            block.header().prev_hash(),
+            shard_id,
+        )
+        .unwrap();
+        runtime_adapter
+            .apply_transactions(
+                shard_id,
+                chunk_inner.prev_state_root(),
+                height,
+                block.header().raw_timestamp(),
+                block.header().prev_hash(),
+                block.hash(),
+                &receipts,
+                chunk.transactions(),
+                chunk_inner.validator_proposals(),
+                prev_block.header().gas_price(),
+                chunk_inner.gas_limit(),
+                &block.header().challenges_result(),
+                *block.header().random_value(),
+                true,
+                is_first_block_with_chunk_of_version,
+                None,
+            )
+            .unwrap()
+    } else {
+        let chunk_extra =
+            chain_store.get_chunk_extra(block.header().prev_hash(), shard_id).unwrap().clone();
+
+        runtime_adapter
+            .apply_transactions(
+                shard_id,
+                chunk_extra.state_root(),
+                block.header().height(),
+                block.header().raw_timestamp(),
+                block.header().prev_hash(),
+                &block.hash(),
+                &[],
+                &[],
+                chunk_extra.validator_proposals(),
+                block.header().gas_price(),
+                chunk_extra.gas_limit(),
+                &block.header().challenges_result(),
+                *block.header().random_value(),
+                false,
+                false,
+                None,
+            )
+            .unwrap()
+    }
+}
+
+/// Replays the blocks from `start_height` to `end_height` with the candidate protocol version
+/// and prints the divergences from the canonical results.
+pub(crate) fn shadow_apply(
+    store: Arc<Store>,
+    home_dir: &Path,
+    near_config: &NearConfig,
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+    protocol_version: ProtocolVersion,
+) {
+    let mut chain_store = ChainStore::new(store.clone(), near_config.genesis.config.genesis_height);
+    let new_runtime = || {
+        NightshadeRuntime::new(
+            home_dir,
+            store.clone(),
+            &near_config.genesis,
+            near_config.client_config.tracked_accounts.clone(),
+            near_config.client_config.tracked_shards.clone(),
+            None,
+            near_config.client_config.max_gas_burnt_view,
+        )
+    };
+    let canonical_runtime = new_runtime();
+    let candidate_runtime = new_runtime().with_protocol_version_override(protocol_version);
+
+    let mut num_chunks = 0;
+    let mut num_divergences = 0;
+    for height in start_height..=end_height {
+        if chain_store.get_block_hash_by_height(height).is_err() {
+            continue;
+        }
+        for shard_id in 0..canonical_runtime.num_shards() {
+            let expected = apply_chunk(&mut chain_store, &canonical_runtime, height, shard_id);
+            let actual = apply_chunk(&mut chain_store, &candidate_runtime, height, shard_id);
+            let divergences = compare_results(&expected, &actual);
+            for divergence in divergences.iter() {
+                println!("height {} shard {}: {}", height, shard_id, divergence);
+            }
+            num_chunks += 1;
+            num_divergences += divergences.len();
+        }
+    }
+    println!(
+        "Applied {} chunks from height {} to {} with protocol version {}, {} divergences",
+        num_chunks, start_height, end_height, protocol_version, num_divergences
+    );
+}
+
+fn compare_results(
+    expected: &ApplyTransactionResult,
+    actual: &ApplyTransactionResult,
+) -> Vec<String> {
+    let mut divergences = vec![];
+    if expected.new_root != actual.new_root {
+        divergences
+            .push(format!("state root {} instead of {}", actual.new_root, expected.new_root));
+    }
+    if expected.total_gas_burnt != actual.total_gas_burnt {
+        divergences.push(format!(
+            "gas burnt {} instead of {}",
+            actual.total_gas_burnt, expected.total_gas_burnt
+        ));
+    }
+    divergences.extend(compare_outcomes(&expected.outcomes, &actual.outcomes));
+    divergences
+}
+
+fn compare_outcomes(
+    expected: &[ExecutionOutcomeWithId],
+    actual: &[ExecutionOutcomeWithId],
+) -> Vec<String> {
+    let mut divergences = vec![];
+    let mut actual: HashMap<_, _> =
+        actual.iter().map(|outcome| (outcome.id, &outcome.outcome)).collect();
+    for ExecutionOutcomeWithId { id, outcome: expected } in expected.iter() {
+        match actual.remove(id) {
+            None => divergences.push(format!("no outcome for {}", id)),
+            Some(actual) => {
+                if actual.status != expected.status {
+                    divergences.push(format!(
+                        "status of {} is {:?} instead of {:?}",
+                        id, actual.status, expected.status
+                    ));
+                }
+                if actual.gas_burnt != expected.gas_burnt {
+                    divergences.push(format!(
+                        "gas burnt by {} is {} instead of {}",
+                        id, actual.gas_burnt, expected.gas_burnt
+                    ));
+                }
+            }
+        }
+    }
+    for id in actual.keys() {
+        divergences.push(format!("unexpected outcome for {}", id));
+    }
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+    use near_primitives::transaction::ExecutionStatus;
+
+    use super::*;
+
+    fn outcome(seed: &[u8], gas_burnt: u64) -> ExecutionOutcomeWithId {
+        let mut outcome = ExecutionOutcomeWithId { id: hash(seed), ..Default::default() };
+        outcome.outcome.gas_burnt = gas_burnt;
+        outcome.outcome.status = ExecutionStatus::SuccessValue(vec![]);
+        outcome
+    }
+
+    #[test]
+    fn test_compare_outcomes() {
+        let expected = vec![outcome(b"a", 10), outcome(b"b", 20), outcome(b"c", 30)];
+        assert!(compare_outcomes(&expected, &expected).is_empty());
+
+        let mut actual = vec![outcome(b"a", 10), outcome(b"b", 25), outcome(b"d", 30)];
+        actual[0].outcome.status = ExecutionStatus::Unknown;
+        let divergences = compare_outcomes(&expected, &actual);
+        assert_eq!(divergences.len(), 4);
+        assert!(divergences[0].starts_with("status of"));
+        assert!(divergences[1].starts_with("gas burnt by"));
+        assert_eq!(divergences[2], format!("no outcome for {}", hash(b"c")));
+        assert_eq!(divergences[3], format!("unexpected outcome for {}", hash(b"d")));
+    }
+}
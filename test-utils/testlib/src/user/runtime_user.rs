@@ -137,6 +137,7 @@ impl RuntimeUser {
             gas_price: MIN_GAS_PRICE,
             gas_limit: None,
             random_seed: Default::default(),
+            epoch_random_seed: Default::default(),
             epoch_id: Default::default(),
             current_protocol_version: PROTOCOL_VERSION,
             config: self.runtime_config.clone(),
@@ -147,6 +148,7 @@ impl RuntimeUser {
             profile: Default::default(),
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
+            observer: None,
         }
     }
 
@@ -229,7 +231,7 @@ impl User for RuntimeUser {
     fn view_state(&self, account_id: &AccountId, prefix: &[u8]) -> Result<ViewStateResult, String> {
         let state_update = self.client.read().expect(POISONED_LOCK_ERR).get_state_update();
         self.trie_viewer
-            .view_state(&state_update, account_id, prefix)
+            .view_state(&state_update, account_id, prefix, None, None, false)
             .map_err(|err| err.to_string())
     }
 
@@ -3,6 +3,10 @@ use syn::{Data, DataEnum, DataStruct, DeriveInput, Fields, FieldsNamed, FieldsUn
 
 use serde::{Deserialize, Serialize};
 
+/// Version of the generated errors schema. Must be bumped whenever an error is renamed or
+/// removed, or its fields change.
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct ErrorType {
     /// A type name of the error
@@ -7,15 +7,22 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use syn::{parse_macro_input, DeriveInput};
 
-use near_rpc_error_core::{parse_error_type, ErrorType};
+use near_rpc_error_core::{parse_error_type, ErrorType, SCHEMA_VERSION};
 
 thread_local!(static SCHEMA: RefCell<Schema> = RefCell::new(Schema::default()));
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct Schema {
+    pub version: u32,
     pub schema: BTreeMap<String, ErrorType>,
 }
 
+impl Default for Schema {
+    fn default() -> Self {
+        Self { version: SCHEMA_VERSION, schema: BTreeMap::default() }
+    }
+}
+
 #[cfg(feature = "dump_errors_schema")]
 fn merge(a: &mut Value, b: &Value) {
     match (a, b) {
@@ -366,6 +366,11 @@ impl<I, T: AsyncWrite + Unpin, U: Encoder<I>, K: 'static + EncoderCallBack>
         inner.high = high;
     }
 
+    /// Number of bytes waiting in the write buffer.
+    pub fn buffer_len(&self) -> usize {
+        self.inner.0.borrow().buffer.len()
+    }
+
     /// Writes an item to the sink.
     pub fn write(&mut self, item: I) -> bool {
         let mut inner = self.inner.0.borrow_mut();